@@ -36,7 +36,7 @@ use futures::StreamExt;
 use lazy_static::lazy_static;
 use log::error;
 use os_socketaddr::OsSocketAddr;
-use srt_protocol::options::SrtVersion;
+use srt_protocol::{options::SrtVersion, settings::KeyMaterialState};
 
 use crate::epoll::SrtEpoll;
 
@@ -945,6 +945,20 @@ pub enum SRT_KM_STATE {
     SRT_KM_S_BADSECRET = 4, //Stream encrypted and wrong secret, cannot decrypt Keying Material
 }
 
+impl From<KeyMaterialState> for SRT_KM_STATE {
+    fn from(state: KeyMaterialState) -> Self {
+        use KeyMaterialState::*;
+        use SRT_KM_STATE::*;
+        match state {
+            Unsecured => SRT_KM_S_UNSECURED,
+            Securing => SRT_KM_S_SECURING,
+            Secured => SRT_KM_S_SECURED,
+            NoSecret => SRT_KM_S_NOSECRET,
+            BadSecret => SRT_KM_S_BADSECRET,
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn srt_getsockstate(sock: SRTSOCKET) -> SRT_SOCKSTATUS {
     use SRT_SOCKSTATUS::*;
@@ -12,6 +12,8 @@ use tokio::io::Interest;
 use tokio::pin;
 use tokio::time::sleep;
 
+use srt_tokio::SendMessage;
+
 use crate::c_api::{get_sock, SrtError, SYSSOCKET, TOKIO_RUNTIME};
 use crate::errors::SRT_ERRNO::*;
 use crate::socket::{CSrtSocket, SocketData};
@@ -238,7 +240,10 @@ impl SrtEpoll {
                                         ReadyPendingError::Pending
                                     };
                                     let ready_send = if flags.contains(EpollFlags::OUT) {
-                                        poll!(poll_fn(|cx| sink.poll_ready_unpin(cx))).into()
+                                        poll!(poll_fn(|cx| {
+                                            SinkExt::<SendMessage>::poll_ready_unpin(&mut sink, cx)
+                                        }))
+                                        .into()
                                     } else {
                                         ReadyPendingError::Pending
                                     };
@@ -26,14 +26,14 @@ use srt_protocol::{
         DataRate, ListenerOptions, LiveBandwidthMode, PacketSize, Percent, Sender, SocketOptions,
         StreamId, Validation,
     },
-    settings::{KeySettings, KeySize, Passphrase},
+    settings::{KeySettings, KeySize, KeySource, Passphrase},
 };
 use srt_tokio::{SrtListener, SrtSocket};
 use tokio::{net::UdpSocket, sync::oneshot, task::JoinHandle};
 
 use crate::c_api::{
-    get_sock, insert_socket, srt_close, srt_listen_callback_fn, SrtError, SRTSOCKET, SRT_SOCKOPT,
-    TOKIO_RUNTIME,
+    get_sock, insert_socket, srt_close, srt_listen_callback_fn, SrtError, SRTSOCKET, SRT_KM_STATE,
+    SRT_SOCKOPT, TOKIO_RUNTIME,
 };
 use crate::errors::SRT_ERRNO::{self, *};
 
@@ -163,6 +163,16 @@ impl SocketData {
         }
     }
 
+    fn km_state(&self, opt: SRT_SOCKOPT) -> SRT_KM_STATE {
+        match self {
+            SocketData::Established(sock, _) if opt == SRT_SOCKOPT::SRTO_SNDKMSTATE => {
+                sock.sender_key_material_state().into()
+            }
+            SocketData::Established(sock, _) => sock.receiver_key_material_state().into(),
+            _ => SRT_KM_STATE::SRT_KM_S_UNSECURED,
+        }
+    }
+
     fn opts_mut(&mut self) -> (Option<&mut ApiOptions>, Option<&mut SocketOptions>) {
         use SocketData::*;
         match self {
@@ -191,8 +201,10 @@ impl SocketData {
             match opt {
                 SRTO_PASSPHRASE => {
                     *params = Some(KeySettings {
-                        passphrase: Passphrase::try_from(extract_str(optval, optlen)?)
-                            .map_err(|_| SRT_EINVPARAM)?,
+                        key_source: KeySource::Passphrase(
+                            Passphrase::try_from(extract_str(optval, optlen)?)
+                                .map_err(|_| SRT_EINVPARAM)?,
+                        ),
                         key_size: params
                             .as_ref()
                             .map(|p| p.key_size)
@@ -242,6 +254,12 @@ impl SocketData {
                     )); // tsbpd=false is not implemented
                 }
             }
+            (SRTO_TLPKTDROP, (_, Some(o))) => {
+                o.receiver.too_late_packet_drop = extract_bool(optval, optlen)?;
+            }
+            (SRTO_NAKREPORT, (_, Some(o))) => {
+                o.receiver.nak_report = extract_bool(optval, optlen)?;
+            }
             (SRTO_PASSPHRASE, (_, Some(o))) => {
                 let pwd = extract_str(optval, optlen)?;
                 if pwd.is_empty() {
@@ -333,6 +351,10 @@ impl SocketData {
                 (SRTO_RCVLATENCY, _, _, Some(cs)) => {
                     Int(cs.recv_tsbpd_latency.as_millis() as c_int)
                 }
+                (SRTO_TLPKTDROP, _, Some(opts), _) => Bool(opts.receiver.too_late_packet_drop),
+                (SRTO_TLPKTDROP, _, _, Some(cs)) => Bool(cs.receiver_drops_too_late()),
+                (SRTO_NAKREPORT, _, Some(opts), _) => Bool(opts.receiver.nak_report),
+                (SRTO_NAKREPORT, _, _, Some(cs)) => Bool(cs.sends_periodic_nak()),
                 (SRTO_PEERLATENCY, _, Some(opts), _) => {
                     Int(opts.sender.peer_latency.as_millis() as c_int)
                 }
@@ -356,6 +378,9 @@ impl SocketData {
                         Unlimited => 0,
                     })
                 }
+                (SRTO_KMSTATE | SRTO_SNDKMSTATE | SRTO_RCVKMSTATE, _, _, _) => {
+                    Int(self.km_state(opt) as c_int)
+                }
                 _ => unimplemented!("{:?} {:?}", opt, self),
             }
         };
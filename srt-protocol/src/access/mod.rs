@@ -5,8 +5,11 @@ use std::{
     str::FromStr,
 };
 
+mod token;
+
 pub use crate::packet::{RejectReason, ServerRejectReason};
-pub use crate::settings::{AcceptParameters, StreamAcceptor};
+pub use crate::settings::{AcceptParameters, ConnectionSettingsOverride, StreamAcceptor};
+pub use token::TokenAuthenticator;
 
 // See https://datatracker.ietf.org/doc/html/draft-sharabayko-srt-00#appendix-B
 #[derive(Debug, PartialEq, Eq)]
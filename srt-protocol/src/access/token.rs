@@ -0,0 +1,128 @@
+use std::{net::SocketAddr, time::Duration};
+
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+use crate::{
+    packet::CoreRejectReason,
+    protocol::time::{SystemTime, UNIX_EPOCH},
+    settings::{AcceptParameters, StreamAcceptor},
+};
+
+use super::{AccessControlEntry, AccessControlList, RejectReason};
+
+/// Accepts callers whose stream ID carries an unexpired token signed with a secret only the
+/// server and whoever hands out the tokens know, and rejects everyone else with
+/// [`CoreRejectReason::BadSecret`].
+///
+/// A token is an access control list with an expiry and a signature appended,
+/// `#!::r=live,u=alice,exp=<unix seconds>,sig=<hex>`, where the signature is the HMAC-SHA1 of
+/// everything before `,sig=`. [`sign`](Self::sign) creates them.
+#[derive(Clone)]
+pub struct TokenAuthenticator {
+    mac: Hmac<Sha1>,
+}
+
+impl TokenAuthenticator {
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        TokenAuthenticator {
+            // HMAC accepts keys of any length
+            mac: Hmac::new_from_slice(secret.as_ref()).unwrap(),
+        }
+    }
+
+    /// The stream ID for `acl`, valid until `expires`
+    pub fn sign(&self, mut acl: AccessControlList, expires: SystemTime) -> String {
+        let expires = expires.duration_since(UNIX_EPOCH).unwrap_or_default();
+        acl.0.push(AccessControlEntry::new(
+            "exp",
+            expires.as_secs().to_string(),
+        ));
+        let signed = acl.to_string();
+
+        let mut mac = self.mac.clone();
+        mac.update(signed.as_bytes());
+        let signature = hex::encode(mac.finalize().into_bytes());
+        format!("{signed},sig={signature}")
+    }
+
+    /// Checks the token in `stream_id` at `now`, returning the access control list it was issued
+    /// for, without the expiry and signature.
+    pub fn validate(
+        &self,
+        stream_id: Option<&str>,
+        now: SystemTime,
+    ) -> Result<AccessControlList, RejectReason> {
+        let bad_secret = RejectReason::Core(CoreRejectReason::BadSecret);
+        let (signed, signature) = stream_id
+            .and_then(|stream_id| stream_id.rsplit_once(",sig="))
+            .ok_or(bad_secret)?;
+        let signature = hex::decode(signature).map_err(|_| bad_secret)?;
+        let mut mac = self.mac.clone();
+        mac.update(signed.as_bytes());
+        mac.verify_slice(&signature).map_err(|_| bad_secret)?;
+
+        // signed by us, so the rest is well formed unless the secret leaked
+        let mut acl: AccessControlList = signed.parse().map_err(|_| bad_secret)?;
+        let expires = match acl.0.pop() {
+            Some(entry) if entry.key == "exp" => entry.value.parse().map_err(|_| bad_secret)?,
+            _ => return Err(bad_secret),
+        };
+        if now > UNIX_EPOCH + Duration::from_secs(expires) {
+            return Err(bad_secret);
+        }
+        Ok(acl)
+    }
+}
+
+impl StreamAcceptor for TokenAuthenticator {
+    fn accept(
+        &mut self,
+        streamid: Option<&str>,
+        _ip: SocketAddr,
+    ) -> Result<AcceptParameters, RejectReason> {
+        self.validate(streamid, SystemTime::now())?;
+        Ok(AcceptParameters::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access::StandardAccessControlEntry;
+
+    #[test]
+    fn token() {
+        let authenticator = TokenAuthenticator::new("secret");
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let acl = || {
+            AccessControlList(vec![
+                StandardAccessControlEntry::ResourceName("live".into()).into(),
+                StandardAccessControlEntry::UserName("alice".into()).into(),
+            ])
+        };
+        let token = authenticator.sign(acl(), now + Duration::from_secs(60));
+        assert!(
+            token.starts_with("#!::r=live,u=alice,exp=1700000060,sig="),
+            "{token}"
+        );
+
+        assert_eq!(authenticator.validate(Some(&token), now), Ok(acl()));
+        // still valid at the second it names
+        assert_eq!(
+            authenticator.validate(Some(&token), now + Duration::from_secs(60)),
+            Ok(acl())
+        );
+
+        let bad_secret = Err(RejectReason::Core(CoreRejectReason::BadSecret));
+        let expired = now + Duration::from_secs(61);
+        assert_eq!(authenticator.validate(Some(&token), expired), bad_secret);
+        assert_eq!(authenticator.validate(None, now), bad_secret);
+        assert_eq!(authenticator.validate(Some("#!::r=live"), now), bad_secret);
+
+        let tampered = token.replace("alice", "mallory");
+        assert_eq!(authenticator.validate(Some(&tampered), now), bad_secret);
+        let other_secret = TokenAuthenticator::new("other secret");
+        assert_eq!(other_secret.validate(Some(&token), now), bad_secret);
+    }
+}
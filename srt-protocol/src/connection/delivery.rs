@@ -0,0 +1,59 @@
+use std::{collections::VecDeque, mem::size_of, ops::Range};
+
+use crate::packet::SeqNumber;
+
+/// What became of a message sent with
+/// [`handle_tracked_data_input`](super::DuplexConnection::handle_tracked_data_input)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Delivery {
+    /// The peer acknowledged every packet of it
+    Acknowledged,
+    /// Some of it was dropped before the peer acknowledged it, because it was too late, its time
+    /// to live ran out or the send buffer was full
+    Dropped,
+}
+
+// the tracked messages that are still in the send buffer, oldest first
+#[derive(Debug, Default)]
+pub(crate) struct Deliveries {
+    pending: VecDeque<Range<SeqNumber>>,
+    reports: VecDeque<(Range<SeqNumber>, Delivery)>,
+}
+
+impl Deliveries {
+    pub fn track(&mut self, packets: Range<SeqNumber>) {
+        self.pending.push_back(packets);
+    }
+
+    pub fn report(&mut self, packets: Range<SeqNumber>, delivery: Delivery) {
+        self.reports.push_back((packets, delivery));
+    }
+
+    pub fn on_ack(&mut self, ack_number: SeqNumber) {
+        while let Some(packets) = self.pending.front().filter(|p| p.end <= ack_number) {
+            let packets = packets.clone();
+            let _ = self.pending.pop_front();
+            self.report(packets, Delivery::Acknowledged);
+        }
+    }
+
+    pub fn on_drop(&mut self, dropped: Range<SeqNumber>) {
+        let reports = &mut self.reports;
+        self.pending.retain(|packets| {
+            let hit = packets.start < dropped.end && dropped.start < packets.end;
+            if hit {
+                reports.push_back((packets.clone(), Delivery::Dropped));
+            }
+            !hit
+        });
+    }
+
+    pub fn pop_report(&mut self) -> Option<(Range<SeqNumber>, Delivery)> {
+        self.reports.pop_front()
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.pending.capacity() * size_of::<Range<SeqNumber>>()
+            + self.reports.capacity() * size_of::<(Range<SeqNumber>, Delivery)>()
+    }
+}
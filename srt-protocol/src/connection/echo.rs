@@ -0,0 +1,227 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::Duration,
+};
+
+use crate::{
+    packet::{TimeSpan, TimeStamp},
+    protocol::time::{Instant, TimeBase},
+};
+
+/// The answer to an echo request, see [`DuplexConnection::send_echo_request`](super::DuplexConnection::send_echo_request)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EchoSample {
+    pub id: u32,
+    pub sent: Instant,
+    pub rtt: Duration,
+    /// The clock of the peer minus this one, halfway through the round trip. Only differences
+    /// between samples mean something, the clocks started at different times.
+    pub offset: TimeSpan,
+    /// How many echo requests the peer received in total, so far
+    pub requests_received: u32,
+}
+
+impl EchoSample {
+    /// How many of the requests up to this one were lost on the way to the peer. The ids count
+    /// the requests sent on the connection, so this holds unless requests were reordered.
+    pub fn requests_lost(&self) -> u32 {
+        self.id.saturating_sub(self.requests_received)
+    }
+}
+
+/// Sums up the answers to a series of echo requests, like ping does
+///
+/// The one way delays split each round trip relative to the fastest one, which is assumed to
+/// have been symmetric. The clocks of the two ends aren't synchronized, so they show how the
+/// delay in each direction varies rather than what it is, and drift apart over long series.
+#[derive(Debug, Default)]
+pub struct EchoStatistics {
+    sent: u32,
+    samples: Vec<EchoSample>,
+}
+
+/// The minimum, average, maximum and mean deviation of a delay
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DelaySummary {
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+    pub mdev: Duration,
+}
+
+impl EchoStatistics {
+    pub fn on_request(&mut self) {
+        self.sent += 1;
+    }
+
+    pub fn on_reply(&mut self, sample: EchoSample) {
+        self.samples.push(sample);
+    }
+
+    pub fn sent(&self) -> u32 {
+        self.sent
+    }
+
+    pub fn received(&self) -> u32 {
+        self.samples.len() as u32
+    }
+
+    pub fn lost(&self) -> u32 {
+        self.sent.saturating_sub(self.received())
+    }
+
+    /// The requests lost on the way to the peer, over the whole connection, as far as the last
+    /// answer tells. The rest of the losses were answers lost on the way back, or requests that
+    /// weren't answered by then.
+    pub fn forward_lost(&self) -> u32 {
+        self.samples
+            .iter()
+            .max_by_key(|s| s.id)
+            .map_or(0, EchoSample::requests_lost)
+    }
+
+    pub fn rtt(&self) -> Option<DelaySummary> {
+        summarize(self.samples.iter().map(|s| s.rtt))
+    }
+
+    pub fn forward_delay(&self) -> Option<DelaySummary> {
+        summarize(self.samples.iter().map(|s| self.one_way_delays(s).0))
+    }
+
+    pub fn backward_delay(&self) -> Option<DelaySummary> {
+        summarize(self.samples.iter().map(|s| self.one_way_delays(s).1))
+    }
+
+    /// The estimated delays of the request and of the reply of `sample`
+    pub fn one_way_delays(&self, sample: &EchoSample) -> (Duration, Duration) {
+        let fastest = self.samples.iter().min_by_key(|s| s.rtt).unwrap_or(sample);
+        let half = sample.rtt.as_micros() as i64 / 2;
+        let skew = i64::from((sample.offset - fastest.offset).as_micros());
+        let micros =
+            |d: i64| Duration::from_micros(d.clamp(0, sample.rtt.as_micros() as i64) as u64);
+        (micros(half + skew), micros(half - skew))
+    }
+}
+
+fn summarize(delays: impl Iterator<Item = Duration> + Clone) -> Option<DelaySummary> {
+    let count = delays.clone().count() as u32;
+    let min = delays.clone().min()?;
+    let max = delays.clone().max()?;
+    let avg = delays.clone().sum::<Duration>() / count;
+    let mdev = delays.map(|d| d.abs_diff(avg)).sum::<Duration>() / count;
+    Some(DelaySummary {
+        min,
+        avg,
+        max,
+        mdev,
+    })
+}
+
+/// The requests in flight and the answers to them, along with how many requests the peer sent us
+#[derive(Debug)]
+pub(crate) struct Echoes {
+    time_base: TimeBase,
+    next_id: u32,
+    in_flight: BTreeMap<u32, Instant>,
+    replies: VecDeque<EchoSample>,
+    requests_received: u32,
+}
+
+impl Echoes {
+    // forget requests that weren't answered after this many newer ones
+    const MAX_IN_FLIGHT: usize = 1024;
+
+    pub fn new(socket_start_time: Instant) -> Self {
+        Self {
+            time_base: TimeBase::new(socket_start_time),
+            next_id: 1,
+            in_flight: BTreeMap::new(),
+            replies: VecDeque::new(),
+            requests_received: 0,
+        }
+    }
+
+    pub fn on_send_request(&mut self, now: Instant) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        if self.in_flight.len() == Self::MAX_IN_FLIGHT {
+            self.in_flight.pop_first();
+        }
+        self.in_flight.insert(id, now);
+        id
+    }
+
+    /// The time to put in the reply and the number of requests received, including this one
+    pub fn on_request(&mut self, now: Instant) -> (TimeStamp, u32) {
+        self.requests_received = self.requests_received.wrapping_add(1);
+        (self.time_base.timestamp_from(now), self.requests_received)
+    }
+
+    pub fn on_reply(
+        &mut self,
+        now: Instant,
+        id: u32,
+        timestamp: TimeStamp,
+        requests_received: u32,
+    ) {
+        let Some(sent) = self.in_flight.remove(&id) else {
+            return;
+        };
+        let rtt = now.saturating_duration_since(sent);
+        let midpoint = self.time_base.timestamp_from(sent + rtt / 2);
+        self.replies.push_back(EchoSample {
+            id,
+            sent,
+            rtt,
+            offset: timestamp - midpoint,
+            requests_received,
+        });
+    }
+
+    pub fn next_reply(&mut self) -> Option<EchoSample> {
+        self.replies.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn one_way_delays() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let sample = |id, rtt, offset_ms| EchoSample {
+            id,
+            sent: start,
+            rtt: ms(rtt),
+            offset: TimeSpan::from_millis(offset_ms),
+            requests_received: id,
+        };
+
+        let mut statistics = EchoStatistics::default();
+        for _ in 0..3 {
+            statistics.on_request();
+        }
+        // 10ms there and back, then 20ms there and 10ms back
+        statistics.on_reply(sample(1, 20, 500));
+        statistics.on_reply(sample(3, 30, 505));
+
+        assert_eq!(statistics.lost(), 1);
+        assert_eq!(statistics.forward_lost(), 0);
+        assert_eq!(
+            statistics.one_way_delays(&sample(3, 30, 505)),
+            (ms(20), ms(10))
+        );
+        assert_eq!(
+            statistics.rtt(),
+            Some(DelaySummary {
+                min: ms(20),
+                avg: ms(25),
+                max: ms(30),
+                mdev: ms(5),
+            })
+        );
+        assert_eq!(statistics.backward_delay().map(|d| d.max), Some(ms(10)));
+    }
+}
@@ -0,0 +1,37 @@
+use std::fmt;
+
+use bytes::Bytes;
+
+use crate::protocol::time::Instant;
+
+/// Called with the type and payload of every SRT control extension packet this crate doesn't
+/// know about
+pub type ExtensionHandler = Box<dyn FnMut(Instant, u16, Bytes) + Send>;
+
+#[derive(Default)]
+pub(crate) struct ControlExtensions(Option<ExtensionHandler>);
+
+impl ControlExtensions {
+    pub fn set_handler(&mut self, handler: ExtensionHandler) {
+        self.0 = Some(handler);
+    }
+
+    /// Returns false if there was no handler to pass the extension to
+    pub fn on_extension(&mut self, now: Instant, ty: u16, payload: Bytes) -> bool {
+        match &mut self.0 {
+            Some(handler) => {
+                handler(now, ty, payload);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl fmt::Debug for ControlExtensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ControlExtensions")
+            .field(&self.0.as_ref().map(|_| "handler"))
+            .finish()
+    }
+}
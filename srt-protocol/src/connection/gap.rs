@@ -0,0 +1,30 @@
+use std::{fmt, ops::Range};
+
+use crate::{packet::SeqNumber, protocol::time::Instant};
+
+/// Called with the packets the receiver gave up on, before the message that follows them is
+/// released
+pub type GapHandler = Box<dyn FnMut(Instant, Range<SeqNumber>) + Send>;
+
+#[derive(Default)]
+pub(crate) struct Gaps(Option<GapHandler>);
+
+impl Gaps {
+    pub fn set_handler(&mut self, handler: GapHandler) {
+        self.0 = Some(handler);
+    }
+
+    pub fn on_gap(&mut self, now: Instant, packets: Range<SeqNumber>) {
+        if let Some(handler) = &mut self.0 {
+            handler(now, packets);
+        }
+    }
+}
+
+impl fmt::Debug for Gaps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Gaps")
+            .field(&self.0.as_ref().map(|_| "handler"))
+            .finish()
+    }
+}
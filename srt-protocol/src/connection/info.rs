@@ -0,0 +1,89 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    net::SocketAddr,
+    time::Duration,
+};
+
+use crate::{
+    options::{KeySize, PacketCount, PacketSize, SrtVersion},
+    packet::{SocketId, SrtShakeFlags},
+};
+
+use super::ConnectionSettings;
+
+/// What the handshake settled on, to log or show, taken from the [`ConnectionSettings`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConnectionInfo {
+    pub remote: SocketAddr,
+    pub local_sockid: SocketId,
+    pub remote_sockid: SocketId,
+    pub stream_id: Option<String>,
+    /// The latency the peer holds the packets sent from here for
+    pub send_latency: Duration,
+    /// The latency the packets from the peer are held for
+    pub recv_latency: Duration,
+    pub peer_version: SrtVersion,
+    /// The size of the AES-CTR keys the stream is encrypted with, [`KeySize::Unspecified`] if
+    /// it isn't
+    pub key_size: KeySize,
+    /// Whether the control packets about the stream are encrypted too
+    pub encrypt_control: bool,
+    /// The largest payload of a packet
+    pub max_payload_size: PacketSize,
+    pub flow_window: PacketCount,
+    /// The flags this side sent in its handshake
+    pub local_flags: SrtShakeFlags,
+    /// The flags the peer sent in its handshake
+    pub peer_flags: SrtShakeFlags,
+}
+
+impl From<&ConnectionSettings> for ConnectionInfo {
+    fn from(settings: &ConnectionSettings) -> Self {
+        Self {
+            remote: settings.remote,
+            local_sockid: settings.local_sockid,
+            remote_sockid: settings.remote_sockid,
+            stream_id: settings.stream_id.clone(),
+            send_latency: settings.send_tsbpd_latency,
+            recv_latency: settings.recv_tsbpd_latency,
+            peer_version: settings.peer_version,
+            key_size: settings
+                .cipher
+                .as_ref()
+                .map(|cipher| cipher.stream_keys.key_size())
+                .unwrap_or_default(),
+            encrypt_control: settings.encrypt_control,
+            max_payload_size: settings.max_packet_size,
+            flow_window: settings.max_flow_size,
+            local_flags: settings.local_flags,
+            peer_flags: settings.peer_flags,
+        }
+    }
+}
+
+impl Display for ConnectionInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:?} -> {:?}",
+            self.remote, self.local_sockid, self.remote_sockid
+        )?;
+        if let Some(stream_id) = &self.stream_id {
+            write!(f, ", stream id {stream_id:?}")?;
+        }
+        write!(
+            f,
+            ", latency {:?} sending and {:?} receiving, peer version {}",
+            self.send_latency, self.recv_latency, self.peer_version
+        )?;
+        match self.key_size {
+            KeySize::Unspecified => write!(f, ", unencrypted")?,
+            key_size => write!(f, ", AES-{}", key_size.as_usize() * 8)?,
+        }
+        write!(
+            f,
+            ", payload up to {}, flow window {}",
+            self.max_payload_size, self.flow_window
+        )
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt;
+
+use log::{Level, LevelFilter, Record};
+
+/// Where the log records of a connection go, and how verbose they are
+#[derive(Debug, Clone)]
+pub(crate) struct Logging {
+    target: String,
+    level: Option<LevelFilter>,
+}
+
+impl Default for Logging {
+    fn default() -> Self {
+        Self {
+            target: TARGET.to_string(),
+            level: None,
+        }
+    }
+}
+
+// that of the rest of the connection module, so the records without a context stay where they were
+const TARGET: &str = "srt_protocol::connection";
+
+impl Logging {
+    pub fn set_context(&mut self, context: &str) {
+        self.target = if context.is_empty() {
+            TARGET.to_string()
+        } else {
+            format!("{TARGET}::{context}")
+        };
+    }
+
+    pub fn set_level(&mut self, level: Option<LevelFilter>) {
+        self.level = level;
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    pub fn enabled(&self, level: Level) -> bool {
+        level <= log::STATIC_MAX_LEVEL && level <= self.level.unwrap_or_else(log::max_level)
+    }
+
+    pub fn log(&self, level: Level, args: fmt::Arguments) {
+        if !self.enabled(level) {
+            return;
+        }
+        // not log::log!, which would filter on the global max level again
+        log::logger().log(
+            &Record::builder()
+                .level(level)
+                .target(&self.target)
+                .module_path_static(Some(TARGET))
+                .file_static(Some(file!()))
+                .args(args)
+                .build(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn context_and_level() {
+        let mut logging = Logging::default();
+        assert_eq!(logging.target(), "srt_protocol::connection");
+        assert_eq!(
+            logging.enabled(Level::Error),
+            log::max_level() >= Level::Error
+        );
+
+        logging.set_context("stream-42");
+        assert_eq!(logging.target(), "srt_protocol::connection::stream-42");
+        logging.set_context("");
+        assert_eq!(logging.target(), "srt_protocol::connection");
+
+        logging.set_level(Some(LevelFilter::Trace));
+        assert!(logging.enabled(Level::Debug));
+        logging.set_level(Some(LevelFilter::Off));
+        assert!(!logging.enabled(Level::Error));
+    }
+}
@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use thiserror::Error;
+
+use crate::{options::PacketSize, protocol::time::Instant};
+
+/// A message to send, with the per message options of libsrt's `SRT_MSGCTRL`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SendMessage {
+    pub data: Bytes,
+    /// The origin time of the message, the peer releases it one latency later
+    pub src_time: Instant,
+    /// How long after `src_time` the message is still worth sending. Once it has passed, packets
+    /// of the message that weren't sent yet or have to be retransmitted aren't, the peer is told
+    /// to drop the message instead.
+    ///
+    /// Default: unlimited, only the messages that are too late to be released are dropped
+    pub ttl: Option<Duration>,
+    /// Set the in order delivery flag on the packets of the message
+    ///
+    /// Default: false
+    pub inorder: bool,
+}
+
+impl SendMessage {
+    pub fn new(src_time: Instant, data: Bytes) -> Self {
+        Self {
+            data,
+            src_time,
+            ttl: None,
+            inorder: false,
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn with_inorder(mut self, inorder: bool) -> Self {
+        self.inorder = inorder;
+        self
+    }
+
+    // the instant the ttl runs out, if it is before the end of time
+    pub(crate) fn expires(&self) -> Option<Instant> {
+        self.src_time.checked_add(self.ttl?)
+    }
+}
+
+/// A message that doesn't fit in a single packet of the payload size the connection negotiated,
+/// which the sender doesn't split when [`max_payload_size`](crate::options::Sender::max_payload_size)
+/// is set
+#[derive(Debug, Clone, Copy, Error, Eq, PartialEq)]
+#[error("The message of {size} bytes is larger than the maximum payload size of {max} bytes")]
+pub struct PayloadTooLarge {
+    pub size: usize,
+    pub max: PacketSize,
+}
+
+impl From<(Instant, Bytes)> for SendMessage {
+    fn from((src_time, data): (Instant, Bytes)) -> Self {
+        Self::new(src_time, data)
+    }
+}
+
+impl From<SendMessage> for (Instant, Bytes) {
+    fn from(message: SendMessage) -> Self {
+        (message.src_time, message.data)
+    }
+}
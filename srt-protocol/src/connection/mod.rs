@@ -1,28 +1,48 @@
+pub mod delivery;
+pub mod echo;
+pub mod extension;
+pub mod gap;
+pub mod info;
+mod logging;
+pub mod message;
+pub mod rebind;
+pub mod snapshot;
+pub mod stall;
 pub mod status;
+pub mod tap;
+#[cfg(feature = "packet_telemetry")]
+pub mod telemetry;
+
+pub use delivery::Delivery;
+pub use echo::{DelaySummary, EchoSample, EchoStatistics};
+pub use info::ConnectionInfo;
+pub use message::{PayloadTooLarge, SendMessage};
+pub use snapshot::ConnectionSnapshot;
+pub use stall::StallEvent;
 pub use status::*;
 
 use std::{
-    convert::TryFrom,
-    fmt::Debug,
-    io,
-    net::SocketAddr,
-    time::{Duration, Instant},
+    cmp::max, convert::TryFrom, fmt::Debug, io, mem::size_of, net::SocketAddr, ops::Range,
+    time::Duration,
 };
 
-use bytes::Bytes;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 
 use crate::{
     options::*,
     packet::*,
     protocol::{
         handshake::Handshake,
-        output::Output,
+        output::{DeliveryRate, Output},
         receiver::{Receiver, ReceiverContext},
         sender::{Sender, SenderContext},
+        time::Instant,
         time::Timers,
     },
     settings::CipherSettings,
-    statistics::SocketStatistics,
+    statistics::{
+        CongestionAlarm, CongestionEvent, CongestionThresholds, MemoryUsage, SocketStatistics,
+    },
 };
 
 #[derive(Debug, Eq, PartialEq)]
@@ -68,10 +88,125 @@ pub struct ConnectionSettings {
     pub recv_buffer_size: PacketCount,
     /// Size of the send buffer, in packets
     pub send_buffer_size: PacketCount,
+    /// The most the send buffer may hold, in bytes on the wire
+    pub send_buffer_bytes: Option<ByteCount>,
+    /// The most the send buffer may hold, between its oldest and newest packets
+    pub send_buffer_time: Option<Duration>,
+    pub send_buffer_policy: SendBufferPolicy,
     pub cipher: Option<CipherSettings>,
     pub stream_id: Option<String>,
     pub bandwidth: LiveBandwidthMode,
     pub statistics_interval: Duration,
+    pub ack2_mode: Ack2Mode,
+    pub lite_ack_rtt_sampling: bool,
+    pub max_burst: PacketCount,
+    pub duplicate_interval: Option<Duration>,
+    pub fast_retransmit: Option<u32>,
+    pub sequence_restart_window: PacketCount,
+    pub skip_gaps: bool,
+    pub immediate_nak: bool,
+    /// Send a light ACK every this many received packets
+    pub light_ack_interval: PacketCount,
+    /// Space out the messages released to the application to stay within this rate
+    pub max_delivery_rate: Option<DataRate>,
+    pub half_close: bool,
+    /// Refuse messages that don't fit in a single packet, rather than split them
+    pub limit_payload_size: bool,
+    /// Send a keepalive when nothing else was sent for this long
+    pub keepalive_interval: Duration,
+    /// Send keepalives more often while the peer is silent
+    pub adaptive_keepalive: bool,
+    pub message_tags: bool,
+    /// Both sides offered to report Congestion Experienced marks
+    pub ecn: bool,
+    pub peer_address_policy: PeerAddressPolicy,
+    /// Both sides agreed to encrypt the control packets about the stream
+    pub encrypt_control: bool,
+
+    /// The SRT version and flags the peer sent in its handshake
+    pub peer_version: SrtVersion,
+    pub peer_flags: SrtShakeFlags,
+    /// The flags this side sent in its handshake
+    pub local_flags: SrtShakeFlags,
+}
+
+impl ConnectionSettings {
+    /// Whether the peer takes the retransmitted bit of a data packet as such, rather than as part
+    /// of the message number
+    pub fn peer_supports_retransmit_flag(&self) -> bool {
+        self.peer_flags.contains(SrtShakeFlags::REXMITFLG)
+    }
+
+    /// Whether the peer can be sent periodic NAK reports, i.e. lost packets reported again until
+    /// they are recovered. Senders before 1.2.0 retransmit on their own timer and take repeated
+    /// reports as fresh losses.
+    ///
+    /// Packet filters, socket groups and version 2 key material, which later versions added, are
+    /// not implemented here, so there's nothing else to degrade.
+    pub fn peer_supports_periodic_nak(&self) -> bool {
+        self.peer_version >= SrtVersion::new(1, 2, 0)
+    }
+
+    /// The largest message data that fits in a single packet, with the room its tag takes, if
+    /// messages aren't split. A byte stream has no messages to keep whole, so it has no limit.
+    pub fn max_message_size(&self) -> Option<usize> {
+        if self.limit_payload_size && !self.stream_mode() {
+            let tag = if self.message_tags { 4 } else { 0 };
+            Some(usize::from(self.max_packet_size).saturating_sub(tag))
+        } else {
+            None
+        }
+    }
+
+    /// Whether `message` fits in a single packet, if messages aren't split
+    pub fn check_payload_size(&self, message: &SendMessage) -> Result<(), PayloadTooLarge> {
+        let size = message.data.len() + if self.message_tags { 4 } else { 0 };
+        match self.max_message_size() {
+            Some(max_size) if message.data.len() > max_size => Err(PayloadTooLarge {
+                size,
+                max: self.max_packet_size,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether received messages are released at their TSBPD time, rather than as soon as they
+    /// are complete and in order, which needs the peer to send with TSBPD timestamps
+    pub fn receives_with_tsbpd(&self) -> bool {
+        self.local_flags.contains(SrtShakeFlags::TSBPDRCV)
+            && self.peer_flags.contains(SrtShakeFlags::TSBPDSND)
+    }
+
+    /// Whether the receiver skips missing packets once the messages after them are due
+    /// (`SRTO_TLPKTDROP`), which only a TSBPD receiver can tell
+    pub fn receiver_drops_too_late(&self) -> bool {
+        self.receives_with_tsbpd() && self.local_flags.contains(SrtShakeFlags::TLPKTDROP)
+    }
+
+    /// Whether the data is a byte stream rather than messages (`SRTO_MESSAGEAPI` off), as both
+    /// sides must have agreed in the handshake
+    pub fn stream_mode(&self) -> bool {
+        self.local_flags.contains(SrtShakeFlags::STREAM)
+    }
+
+    /// Whether the sender drops packets the peer would drop as too late anyway, rather than
+    /// sending them until they're acknowledged
+    pub fn sender_drops_too_late(&self) -> bool {
+        self.local_flags.contains(SrtShakeFlags::TSBPDSND)
+            && self.peer_flags.contains(SrtShakeFlags::TSBPDRCV)
+            && self.peer_flags.contains(SrtShakeFlags::TLPKTDROP)
+    }
+
+    /// What was agreed on with the peer, see [`ConnectionInfo`]
+    pub fn info(&self) -> ConnectionInfo {
+        ConnectionInfo::from(self)
+    }
+
+    /// Whether the receiver repeats its loss reports (`SRTO_NAKREPORT`), see
+    /// [`peer_supports_periodic_nak`](Self::peer_supports_periodic_nak)
+    pub fn sends_periodic_nak(&self) -> bool {
+        self.local_flags.contains(SrtShakeFlags::NAKREPORT) && self.peer_supports_periodic_nak()
+    }
 }
 
 #[derive(Debug)]
@@ -80,10 +215,21 @@ pub struct DuplexConnection {
     timers: Timers,
     handshake: Handshake,
     output: Output,
+    delivery_rate: Option<DeliveryRate>,
     sender: Sender,
     receiver: Receiver,
     stats: SocketStatistics,
     status: ConnectionStatus,
+    extensions: extension::ControlExtensions,
+    echoes: echo::Echoes,
+    gaps: gap::Gaps,
+    tap: tap::Tap,
+    rebinds: rebind::Rebinds,
+    stall: stall::StallWatch,
+    congestion: Option<CongestionAlarm>,
+    logging: logging::Logging,
+    #[cfg(feature = "packet_telemetry")]
+    telemetry: telemetry::PacketTelemetry,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -115,14 +261,142 @@ impl DuplexConnection {
             settings: settings.clone(),
             handshake: connection.handshake,
             output: Output::new(&settings),
-            status: ConnectionStatus::new(settings.send_tsbpd_latency * 2), // the timeout should be larger than latency as otherwise packets that have just arrived definitely have a change to flush
-            timers: Timers::new(settings.socket_start_time, settings.statistics_interval, settings.peer_idle_timeout),
+            delivery_rate: settings.max_delivery_rate.map(DeliveryRate::new),
+            status: ConnectionStatus::new(settings.send_tsbpd_latency * 2, settings.half_close), // the timeout should be larger than latency as otherwise packets that have just arrived definitely have a change to flush
+            timers: Timers::new(
+                settings.socket_start_time,
+                settings.statistics_interval,
+                settings.peer_idle_timeout,
+            ),
             stats: SocketStatistics::new(),
+            echoes: echo::Echoes::new(settings.socket_start_time),
+            stall: stall::StallWatch::new(settings.socket_start_time),
             receiver: Receiver::new(settings.clone()),
             sender: Sender::new(settings),
+            extensions: Default::default(),
+            gaps: Default::default(),
+            tap: Default::default(),
+            rebinds: Default::default(),
+            congestion: None,
+            logging: Default::default(),
+            #[cfg(feature = "packet_telemetry")]
+            telemetry: Default::default(),
         }
     }
 
+    /// Install a handler for SRT control extension packets of unknown types, which are
+    /// otherwise discarded.
+    pub fn set_extension_handler(
+        &mut self,
+        handler: impl FnMut(Instant, u16, Bytes) + Send + 'static,
+    ) {
+        self.extensions.set_handler(Box::new(handler));
+    }
+
+    /// Install a handler that is told about every gap in the received data, i.e. the packets that
+    /// weren't recovered in time and were skipped over.
+    pub fn set_gap_handler(
+        &mut self,
+        handler: impl FnMut(Instant, Range<SeqNumber>) + Send + 'static,
+    ) {
+        self.gaps.set_handler(Box::new(handler));
+    }
+
+    /// Install a handler that gets every data packet as soon as it's in order, i.e. it and all the
+    /// packets before it were received or given up on, without waiting for the TSBPD time of its
+    /// message, e.g. to record the stream while the application still gets it with the latency.
+    /// It starts with the packets that weren't in order when it's installed.
+    pub fn set_tap_handler(&mut self, handler: impl FnMut(Instant, &DataPacket) + Send + 'static) {
+        let next = self.receiver.arq.next_in_order();
+        self.tap.set_handler(Box::new(handler), next);
+    }
+
+    /// Install a handler that is told whenever the connection follows the peer to another address,
+    /// with the old and the new one, e.g. to notice a NAT rebinding or a peer that roamed.
+    pub fn set_rebind_handler(
+        &mut self,
+        handler: impl FnMut(Instant, SocketAddr, SocketAddr) + Send + 'static,
+    ) {
+        self.rebinds.set_handler(Box::new(handler));
+    }
+
+    /// Install a handler that is told when no new data packet, retransmissions aside, arrived from
+    /// the peer for `threshold` while the connection is up, and when they arrive again, e.g. for
+    /// a player to show that the signal is lost long before the peer idle timeout. It counts from
+    /// the last data packet, or from the start of the connection. It replaces the previous one.
+    pub fn set_stall_handler(
+        &mut self,
+        threshold: Duration,
+        handler: impl FnMut(Instant, &StallEvent) + Send + 'static,
+    ) {
+        self.stall.set_handler(threshold, Box::new(handler));
+    }
+
+    /// Install a handler that is told when the retransmit ratio or the rate of NAKs from the peer
+    /// cross `thresholds`, and when they're back below them, checked each time the statistics
+    /// are updated. It replaces the previous one.
+    pub fn set_congestion_alarm(
+        &mut self,
+        thresholds: CongestionThresholds,
+        handler: impl FnMut(&CongestionEvent) + Send + 'static,
+    ) {
+        self.congestion = Some(CongestionAlarm::new(thresholds, Box::new(handler)));
+    }
+
+    /// Log this connection's records to the `srt_protocol::connection::<context>` target instead
+    /// of `srt_protocol::connection`, so a logger filtering on targets (e.g. `RUST_LOG` with
+    /// env_logger) can pick out a single connection. An empty context goes back to the default.
+    pub fn set_log_context(&mut self, context: &str) {
+        self.logging.set_context(context);
+    }
+
+    /// Override the maximum level this connection logs at, which is otherwise the global one from
+    /// [`log::max_level`]. The logger still has the final say on what it keeps.
+    pub fn set_log_level(&mut self, level: Option<log::LevelFilter>) {
+        self.logging.set_level(level);
+    }
+
+    /// The target this connection logs to, see [`set_log_context`](Self::set_log_context)
+    pub fn log_target(&self) -> &str {
+        self.logging.target()
+    }
+
+    /// Queue a user-defined SRT control extension packet for the peer.
+    ///
+    /// # Panics
+    /// If `ty` is one of the extension types used by SRT itself or by this implementation, see
+    /// [`SrtControlPacket::is_reserved_type`]
+    pub fn send_control_extension(&mut self, now: Instant, ty: u16, payload: Bytes) {
+        assert!(
+            !SrtControlPacket::is_reserved_type(ty),
+            "SRT control extension type {ty} is reserved"
+        );
+        let control = ControlTypes::Srt(SrtControlPacket::Extension { ty, payload });
+        self.output.send_control(now, control);
+    }
+
+    /// Queue a latency probe for the peer, which answers right away if it's this implementation
+    /// too. Returns the id of the [`EchoSample`] the answer turns into, see
+    /// [`next_echo_reply`](Self::next_echo_reply).
+    pub fn send_echo_request(&mut self, now: Instant) -> u32 {
+        let id = self.echoes.on_send_request(now);
+        let control = ControlTypes::Srt(SrtControlPacket::EchoRequest { id });
+        self.output.send_control(now, control);
+        id
+    }
+
+    /// The answers to [`send_echo_request`](Self::send_echo_request), in the order they arrived
+    pub fn next_echo_reply(&mut self) -> Option<EchoSample> {
+        self.echoes.next_reply()
+    }
+
+    /// Install a hook that is called with every packet sent to or received from the peer, e.g.
+    /// to feed a custom analyzer or record traffic for replay.
+    #[cfg(feature = "packet_telemetry")]
+    pub fn set_packet_hook(&mut self, hook: impl FnMut(&telemetry::PacketEvent) + Send + 'static) {
+        self.telemetry.set_hook(Box::new(hook));
+    }
+
     pub fn handle_input(&mut self, now: Instant, input: Input) -> Action {
         self.debug(now, "input", &input);
 
@@ -153,23 +427,85 @@ impl DuplexConnection {
         self.status.is_open()
     }
 
+    /// Whether the peer has finished sending and all of its data has been released. Without
+    /// half close this only happens as the whole connection closes.
+    pub fn is_receiver_closed(&self) -> bool {
+        self.status.is_receiver_closed()
+    }
+
+    /// Whether the send buffer has no room for another full sized packet, when the
+    /// [`buffer_full_policy`](crate::options::Sender::buffer_full_policy) kicks in
+    pub fn is_send_buffer_full(&self) -> bool {
+        !self.sender.has_buffer_room()
+    }
+
+    /// The peer's clock minus this one, as the receiver tracks it to release data at its TSBPD
+    /// time: set by the first packet from the peer and corrected for drift since. It includes
+    /// the delay on the way here, so whatever the peer stamped `t` arrived around `t - offset`
+    /// on this clock, which lines up the feeds of several peers without synchronizing clocks.
+    ///
+    /// `None` until the peer sent a packet.
+    pub fn peer_clock_offset(&self) -> Option<TimeSpan> {
+        self.receiver.arq.peer_clock_offset()
+    }
+
     pub fn settings(&self) -> &ConnectionSettings {
         &self.settings
     }
 
+    /// Roughly how much memory the connection takes, by what it is for. It's in the statistics
+    /// too, as of their last update.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage {
+            state: size_of::<Self>(),
+            send_buffer: self.output.memory_usage(),
+            ..Default::default()
+        };
+        self.sender.memory_usage(&mut usage);
+        self.receiver.memory_usage(&mut usage);
+        usage
+    }
+
+    /// The state another process needs to carry on with this connection, see
+    /// [`ConnectionSnapshot`]. This connection should be dropped without closing it afterwards,
+    /// the peer must not notice the hand-over.
+    pub fn snapshot(&self) -> ConnectionSnapshot {
+        ConnectionSnapshot {
+            settings: self.settings.clone(),
+            handshake: self.handshake.clone(),
+            sender: self.sender.snapshot(),
+            receiver: self.receiver.snapshot(),
+        }
+    }
+
+    /// Carry on with the connection a snapshot was taken of
+    pub fn restore(snapshot: ConnectionSnapshot, now: Instant) -> DuplexConnection {
+        let settings = snapshot.settings;
+        let mut connection = DuplexConnection::new(Connection {
+            settings: settings.clone(),
+            handshake: snapshot.handshake,
+        });
+        connection.receiver = Receiver::restore(settings.clone(), now, snapshot.receiver);
+        connection.sender = Sender::restore(settings, now, snapshot.sender);
+        connection
+    }
+
     pub fn update_statistics(&mut self, now: Instant) {
         self.stats.elapsed_time = now - self.settings.socket_start_time;
-        self.stats.tx_buffered_time = self.sender.tx_buffered_time();
-        self.stats.tx_buffered_data = self.sender.tx_buffered_packets();
-        self.stats.tx_buffered_bytes = self.sender.tx_buffered_bytes();
+        self.stats.tx_buffer_time = self.sender.tx_buffer_time(now);
+        self.stats.tx_unacknowledged_age = self.sender.tx_unacknowledged_age(now);
+
+        self.stats.tx_km_state = self.sender.key_material_state();
+        self.stats.rx_km_state = self.receiver.key_material_state();
+        self.stats.memory = self.memory_usage();
 
-        self.stats.rx_acknowledged_time = self.receiver.rx_acknowledged_time();
+        if let Some(alarm) = &mut self.congestion {
+            alarm.update(&self.stats);
+        }
     }
 
     pub fn next_packet(&mut self, now: Instant) -> Option<(Packet, SocketAddr)> {
         let p = self.output.pop_packet()?;
-        self.stats.tx_all_packets += 1;
-        self.stats.tx_all_bytes += u64::try_from(p.wire_size()).unwrap();
 
         // payload length + (20 bytes IPv4 + 8 bytes UDP + 16 bytes SRT)
         match &p {
@@ -194,31 +530,74 @@ impl DuplexConnection {
             },
         }
         self.debug(now, "send", &p);
+
+        let p = self.encrypt_control(p);
+        #[cfg(feature = "packet_telemetry")]
+        self.telemetry
+            .on_packet(telemetry::PacketDirection::Sent, now, &p);
+        self.stats.tx_all_packets += 1;
+        self.stats.tx_all_bytes += u64::try_from(p.wire_size()).unwrap();
         Some((p, self.settings.remote))
     }
 
     pub fn next_data(&mut self, now: Instant) -> Option<(Instant, Bytes)> {
-        match self.receiver.arq.pop_next_message(now) {
-            Ok(Some(data)) => {
-                self.debug(now, "output", &data);
-                Some(data)
-            }
-            Err(error) => {
-                self.warn(now, "output", &error);
-                let dropped = error.too_late_packets.end - error.too_late_packets.start;
-                self.stats.rx_dropped_data += dropped as u64;
-                None
-            }
-            _ => None,
+        self.next_tagged_data(now)
+            .map(|(time, _, data)| (time, data))
+    }
+
+    /// Like [`next_data`](Self::next_data), but with the tag the peer sent the message with, 0
+    /// unless [`message_tags`](crate::options::Session::message_tags) is enabled.
+    pub fn next_tagged_data(&mut self, now: Instant) -> Option<(Instant, u32, Bytes)> {
+        if !self
+            .delivery_rate
+            .as_ref()
+            .is_none_or(|rate| rate.is_ready(now))
+        {
+            return None;
         }
+        let data = loop {
+            match self.receiver.arq.pop_next_message(now) {
+                Ok(Some(data)) => {
+                    self.debug(now, "output", &data);
+                    if let Some(rate) = &mut self.delivery_rate {
+                        rate.on_release(now, data.1.len());
+                    }
+                    break Some(data);
+                }
+                // the message after the gap is due by now, it's released right away
+                Err(error) => {
+                    self.warn(now, "output", &error);
+                    let dropped = error.too_late_packets.end - error.too_late_packets.start;
+                    self.stats.rx_dropped_data += dropped as u64;
+                    self.gaps.on_gap(now, error.too_late_packets);
+                    self.tap(now);
+                }
+                Ok(None) => break None,
+            }
+        };
+        self.receiver().update_gauges();
+        data.map(|(time, data)| {
+            let (tag, data) = self.untag(data);
+            (time, tag, data)
+        })
     }
 
     pub fn next_timer(&self, now: Instant) -> Instant {
         let has_packets_to_send = self.sender.has_packets_to_send();
-        let next_message = self.receiver.arq.next_message_release_time();
+        let next_message = self.receiver.arq.next_progress_time();
+        // a message that is due waits for the delivery rate
+        let next_message = match self.delivery_rate.as_ref().and_then(|r| r.next_release()) {
+            Some(release) => next_message.map(|message| max(message, release)),
+            None => next_message,
+        };
         let unacked_packets = self.receiver.arq.unacked_packet_count();
-        self.timers
-            .next_timer(now, has_packets_to_send, next_message, unacked_packets)
+        let next_timer =
+            self.timers
+                .next_timer(now, has_packets_to_send, next_message, unacked_packets);
+        match self.stall.deadline() {
+            Some(deadline) => next_timer.min(deadline),
+            None => next_timer,
+        }
     }
 
     pub fn should_close(&mut self, now: Instant) -> bool {
@@ -231,7 +610,14 @@ impl DuplexConnection {
     }
 
     pub fn should_update_statistics(&mut self, now: Instant) -> bool {
-        self.timers.check_statistics(now).is_some()
+        // key material state changes are reported immediately, so applications can detect
+        // undecryptable content without waiting for the next statistics interval
+        self.timers.check_statistics(now).is_some() || self.key_material_state_changed()
+    }
+
+    fn key_material_state_changed(&self) -> bool {
+        self.sender.key_material_state() != self.stats.tx_km_state
+            || self.receiver.key_material_state() != self.stats.rx_km_state
     }
 
     pub fn statistics(&self) -> &SocketStatistics {
@@ -248,6 +634,9 @@ impl DuplexConnection {
         if self.timers.check_peer_idle_timeout(now).is_some() {
             self.on_peer_idle_timeout(now);
         }
+        if let Some(event) = self.stall.check(now) {
+            self.warn(now, "stream stalled", &event);
+        }
         if let Some(elapsed_periods) = self.timers.check_snd(now) {
             self.sender().on_snd_event(now, elapsed_periods)
         }
@@ -274,17 +663,67 @@ impl DuplexConnection {
     }
 
     pub fn handle_data_input(&mut self, now: Instant, data: Option<(Instant, Bytes)>) {
-        self.debug(now, "input", &data);
         match data {
-            Some(item) => {
-                self.sender().handle_data(now, item);
-            }
+            Some(item) => self.handle_message_input(now, item.into()),
             None => {
+                self.debug(now, "input", &data);
                 self.handle_data_stream_close(now);
             }
         }
     }
 
+    /// Like [`handle_data_input`](Self::handle_data_input), with the per message options of
+    /// [`SendMessage`]
+    pub fn handle_message_input(&mut self, now: Instant, message: SendMessage) {
+        self.debug(now, "input", &message);
+        if !self.fits(now, &message) {
+            return;
+        }
+        let message = self.tag(0, message);
+        self.sender().handle_data(now, message);
+    }
+
+    /// Like [`handle_message_input`](Self::handle_message_input), but reports what became of the
+    /// message through [`next_delivery`](Self::next_delivery), under the sequence numbers
+    /// returned here.
+    pub fn handle_tracked_data_input(
+        &mut self,
+        now: Instant,
+        item: impl Into<SendMessage>,
+    ) -> Option<Range<SeqNumber>> {
+        let message = item.into();
+        self.debug(now, "input", &message);
+        if !self.fits(now, &message) {
+            return None;
+        }
+        let message = self.tag(0, message);
+        self.sender().handle_tracked_data(now, message)
+    }
+
+    /// Like [`handle_message_input`](Self::handle_message_input), with a tag the receiver gets
+    /// the message with from [`next_tagged_data`](Self::next_tagged_data), e.g. a frame number.
+    /// The tag is dropped unless [`message_tags`](crate::options::Session::message_tags) is
+    /// enabled.
+    pub fn handle_tagged_data_input(
+        &mut self,
+        now: Instant,
+        item: impl Into<SendMessage>,
+        tag: u32,
+    ) {
+        let message = item.into();
+        self.debug(now, "input", &message);
+        if !self.fits(now, &message) {
+            return;
+        }
+        let message = self.tag(tag, message);
+        self.sender().handle_data(now, message);
+    }
+
+    /// The next tracked message that was acknowledged or dropped
+    pub fn next_delivery(&mut self) -> Option<(Range<SeqNumber>, Delivery)> {
+        self.sender.next_delivery()
+    }
+
     pub fn handle_packet_input(&mut self, now: Instant, packet: ReceivePacketResult) {
         self.debug(now, "packet", &packet);
         use ReceivePacketError::*;
@@ -295,6 +734,49 @@ impl DuplexConnection {
         }
     }
 
+    /// Report data packets that arrived with an ECN Congestion Experienced mark, which the
+    /// socket reads from the IP header, the packets themselves go through
+    /// [`handle_packet_input`](Self::handle_packet_input) as usual
+    pub fn handle_congestion_experienced(&mut self, packets: u32) {
+        // only a peer that offered ECN in its handshake knows of the report
+        if self.settings.ecn {
+            self.receiver().handle_congestion_experienced(packets);
+        }
+    }
+
+    // the application is told of oversized messages when it hands them over, see
+    // ConnectionSettings::check_payload_size, those that get this far are dropped
+    fn fits(&self, now: Instant, message: &SendMessage) -> bool {
+        match self.settings.check_payload_size(message) {
+            Ok(()) => true,
+            Err(e) => {
+                self.warn(now, "input", &e);
+                false
+            }
+        }
+    }
+
+    fn tag(&self, tag: u32, message: SendMessage) -> SendMessage {
+        if !self.settings.message_tags {
+            return message;
+        }
+        let mut tagged = BytesMut::with_capacity(4 + message.data.len());
+        tagged.put_u32(tag);
+        tagged.put(message.data);
+        SendMessage {
+            data: tagged.freeze(),
+            ..message
+        }
+    }
+
+    fn untag(&self, mut data: Bytes) -> (u32, Bytes) {
+        // both sides enabled tags in the handshake, a shorter message is from a misbehaving peer
+        if !self.settings.message_tags || data.len() < 4 {
+            return (0, data);
+        }
+        (data.get_u32(), data)
+    }
+
     fn handle_data_stream_close(&mut self, now: Instant) {
         self.info(now, "closed data", &());
         self.status.on_data_stream_closed(now);
@@ -305,39 +787,95 @@ impl DuplexConnection {
         self.status.on_socket_closed(now);
     }
 
+    /// Close the connection right away, without flushing the send buffer or waiting for the
+    /// peer. The Shutdown packet telling the peer is left for [`next_packet`](Self::next_packet).
+    pub fn abort(&mut self, now: Instant) {
+        self.info(now, "abort", &());
+        self.output.send_control(now, ControlTypes::Shutdown);
+        self.status.on_abort();
+    }
+
     pub fn on_peer_idle_timeout(&mut self, now: Instant) {
         self.output.send_control(now, ControlTypes::Shutdown);
         self.status.on_peer_idle_timeout(now);
     }
 
     fn handle_packet(&mut self, now: Instant, (packet, from): (Packet, SocketAddr)) {
-        // TODO: record/report packets from invalid hosts?
-        // We don't care about packets from elsewhere
-        if from != self.settings.remote {
-            self.info(now, "invalid address", &(packet, from));
-            return;
-        }
-
         if self.settings.local_sockid != packet.dest_sockid() {
             self.info(now, "invalid socket id", &(packet, from));
             return;
         }
 
+        // We don't care about packets from elsewhere, unless the peer's NAT picked a new port
+        // for it, as far as the policy trusts it to. Anybody can put the socket id in a packet,
+        // so the peer is only followed on packets it would send right now, which takes knowing
+        // the sequence numbers
+        if from != self.settings.remote {
+            let policy = self.settings.peer_address_policy;
+            if !policy.allows(self.settings.remote, from) || !self.is_in_window(&packet) {
+                self.info(now, "invalid address", &(packet, from));
+                self.stats.rx_foreign_packets += 1;
+                return;
+            }
+            self.info(now, "peer address changed", &(self.settings.remote, from));
+            self.rebinds.on_rebind(now, self.settings.remote, from);
+            self.settings.remote = from;
+        }
+
         self.timers.reset_exp(now);
+        self.output.on_peer_packet(now);
+
+        #[cfg(feature = "packet_telemetry")]
+        self.telemetry
+            .on_packet(telemetry::PacketDirection::Received, now, &packet);
 
         self.stats.rx_all_packets += 1;
         self.stats.rx_all_bytes += u64::try_from(packet.wire_size()).unwrap();
         match packet {
-            Packet::Data(data) => self.receiver().handle_data_packet(now, data),
+            Packet::Data(data) => {
+                if !data.retransmitted {
+                    if let Some(event) = self.stall.on_data(now) {
+                        self.info(now, "stream resumed", &event);
+                    }
+                }
+                self.receiver().handle_data_packet(now, data);
+                self.tap(now);
+            }
             Packet::Control(control) => self.handle_control_packet(now, control),
         }
     }
 
+    // data the receive buffer has room for, or an ACK of what was sent
+    fn is_in_window(&self, packet: &Packet) -> bool {
+        match packet {
+            Packet::Data(data) => self.receiver.arq.is_in_window(data.seq_number),
+            Packet::Control(ControlPacket {
+                control_type: ControlTypes::Ack(ack),
+                ..
+            }) => self.sender.is_ack_in_window(ack.ack_number()),
+            Packet::Control(_) => false,
+        }
+    }
+
+    // the packets that are in order since the last time, a packet filling a gap puts those after
+    // it in order too, as does giving up on the gap
+    fn tap(&mut self, now: Instant) {
+        let Some(next) = self.tap.next() else {
+            return;
+        };
+        for packet in self.receiver.arq.in_order_packets_from(next) {
+            self.tap.on_packet(now, packet);
+        }
+    }
+
     fn handle_control_packet(&mut self, now: Instant, control: ControlPacket) {
         self.receiver().synchronize_clock(now, control.timestamp);
+        self.handle_control_type(now, control.control_type);
+    }
 
+    fn handle_control_type(&mut self, now: Instant, control_type: ControlTypes) {
         use ControlTypes::*;
-        match control.control_type {
+        match control_type {
             // sender-responsible packets
             Ack(ack) => self.sender().handle_ack_packet(now, ack),
             DropRequest { range, .. } => self.receiver().handle_drop_request(now, range),
@@ -378,7 +916,93 @@ impl DuplexConnection {
             KeyRefreshResponse(keying_material) => {
                 self.sender().handle_key_refresh_response(keying_material)
             }
-            _ => unimplemented!("{:?}", pack),
+            Extension { ty, payload } => {
+                if !self.extensions.on_extension(now, ty, payload) {
+                    self.debug(now, "extension", &ty)
+                }
+            }
+            CongestionExperienced(packets) => self.sender().handle_congestion_experienced(packets),
+            EchoRequest { id } => {
+                let (timestamp, requests_received) = self.echoes.on_request(now);
+                let reply = EchoReply {
+                    id,
+                    timestamp,
+                    requests_received,
+                };
+                self.output.send_control(now, ControlTypes::Srt(reply));
+            }
+            EchoReply {
+                id,
+                timestamp,
+                requests_received,
+            } => self.echoes.on_reply(now, id, timestamp, requests_received),
+            EncryptedControl {
+                key,
+                nonce,
+                payload,
+            } => self.handle_encrypted_control_packet(now, key, nonce, payload),
+            StreamId(_) | Congestion(_) | Filter(_) | Group { .. } | Reject => {
+                self.warn(now, "unexpected srt control", &pack)
+            }
+        }
+    }
+
+    // the control packets about the stream, which the peers agreed to encrypt
+    fn is_encrypted_control(control_type: &ControlTypes) -> bool {
+        use ControlTypes::*;
+        matches!(control_type, Ack(_) | Ack2(_) | Nak(_) | DropRequest { .. })
+    }
+
+    fn encrypt_control(&mut self, packet: Packet) -> Packet {
+        let control = match &packet {
+            Packet::Control(control)
+                if self.settings.encrypt_control
+                    && Self::is_encrypted_control(&control.control_type) =>
+            {
+                control
+            }
+            _ => return packet,
+        };
+        let mut data = BytesMut::new();
+        control.serialize(&mut data);
+        match self.sender.encrypt_control(control.dest_sockid, &mut data) {
+            Some((key, nonce)) => Packet::Control(ControlPacket {
+                timestamp: control.timestamp,
+                dest_sockid: control.dest_sockid,
+                control_type: ControlTypes::Srt(SrtControlPacket::EncryptedControl {
+                    key,
+                    nonce,
+                    payload: data.freeze(),
+                }),
+            }),
+            None => packet,
+        }
+    }
+
+    fn handle_encrypted_control_packet(
+        &mut self,
+        now: Instant,
+        key: DataEncryption,
+        nonce: u64,
+        payload: Bytes,
+    ) {
+        if !self.settings.encrypt_control {
+            self.warn(now, "unexpected encrypted control", &(key, nonce));
+            return;
+        }
+        let is_ipv6 = self.settings.remote.is_ipv6();
+        let control = self
+            .receiver
+            .decryption
+            .decrypt_control(key, self.settings.local_sockid, nonce, &payload)
+            .ok()
+            .and_then(|mut data| ControlPacket::parse(&mut data, is_ipv6).ok());
+        match control {
+            Some(control) if Self::is_encrypted_control(&control.control_type) => {
+                self.debug(now, "decrypted control", &control);
+                self.handle_control_type(now, control.control_type);
+            }
+            _ => self.warn(now, "undecryptable control", &(key, nonce)),
         }
     }
 
@@ -401,46 +1025,46 @@ impl DuplexConnection {
         )
     }
 
-    fn debug(&self, now: Instant, tag: &str, debug: &impl Debug) {
-        log::debug!(
-            "{:?}|{:?}|{} - {:?}",
-            TimeSpan::from_interval(self.settings.socket_start_time, now),
-            self.settings.local_sockid,
-            tag,
-            debug
+    fn log(&self, level: log::Level, now: Instant, tag: &str, debug: &impl Debug) {
+        self.logging.log(
+            level,
+            format_args!(
+                "{:?}|{:?}|{} - {:?}",
+                TimeSpan::from_interval(self.settings.socket_start_time, now),
+                self.settings.local_sockid,
+                tag,
+                debug
+            ),
         );
     }
 
+    fn debug(&self, now: Instant, tag: &str, debug: &impl Debug) {
+        self.log(log::Level::Debug, now, tag, debug);
+    }
+
     fn info(&self, now: Instant, tag: &str, debug: &impl Debug) {
-        log::info!(
-            "{:?}|{:?}|{} - {:?}",
-            TimeSpan::from_interval(self.settings.socket_start_time, now),
-            self.settings.local_sockid,
-            tag,
-            debug
-        );
+        self.log(log::Level::Info, now, tag, debug);
     }
 
     fn warn(&self, now: Instant, tag: &str, debug: &impl Debug) {
-        log::warn!(
-            "{:?}|{:?}|{} - {:?}",
-            TimeSpan::from_interval(self.settings.socket_start_time, now),
-            self.settings.local_sockid,
-            tag,
-            debug
-        );
+        self.log(log::Level::Warn, now, tag, debug);
     }
 }
 
 #[cfg(test)]
 mod duplex_connection {
+    use std::sync::{Arc, Mutex};
+
     use assert_matches::assert_matches;
 
     use Action::*;
     use ControlTypes::*;
     use Packet::*;
 
-    use crate::protocol::time::Rtt;
+    use crate::{
+        protocol::time::Rtt,
+        settings::{KeySettings, KeySource, Salt, SharedKeyProvider},
+    };
 
     use super::*;
 
@@ -475,11 +1099,35 @@ mod duplex_connection {
                 recv_tsbpd_latency: TSBPD,
                 recv_buffer_size: PacketCount(1024),
                 send_buffer_size: PacketCount(1024),
+                send_buffer_bytes: None,
+                send_buffer_time: None,
+                send_buffer_policy: SendBufferPolicy::DropOldest,
                 cipher: None,
                 stream_id: None,
                 bandwidth: LiveBandwidthMode::Unlimited,
                 statistics_interval: Duration::from_secs(10),
                 peer_idle_timeout: Duration::from_secs(5),
+                ack2_mode: Ack2Mode::EveryFullAck,
+                lite_ack_rtt_sampling: false,
+                max_burst: PacketCount(0),
+                duplicate_interval: None,
+                fast_retransmit: None,
+                sequence_restart_window: PacketCount(0),
+                skip_gaps: false,
+                immediate_nak: false,
+                light_ack_interval: PacketCount(64),
+                max_delivery_rate: None,
+                half_close: false,
+                limit_payload_size: false,
+                keepalive_interval: Duration::from_secs(1),
+                adaptive_keepalive: false,
+                message_tags: false,
+                ecn: false,
+                peer_address_policy: PeerAddressPolicy::Rebind,
+                encrypt_control: false,
+                peer_version: SrtVersion::CURRENT,
+                peer_flags: SrtShakeFlags::SUPPORTED,
+                local_flags: SrtShakeFlags::SUPPORTED,
             },
             handshake: crate::protocol::handshake::Handshake::Connector,
         }
@@ -566,6 +1214,55 @@ mod duplex_connection {
         assert_eq!(connection.handle_input(now, Input::Timer), Close);
     }
 
+    #[test]
+    fn abort() {
+        let start = Instant::now();
+        let mut connection = DuplexConnection::new(new_connection(start));
+
+        connection.handle_data_input(start, Some((start, Bytes::new())));
+        connection.abort(start);
+        assert!(!connection.is_open());
+        assert_matches!(
+            connection.next_packet(start),
+            Some((
+                Control(ControlPacket {
+                    control_type: Shutdown,
+                    ..
+                }),
+                _
+            ))
+        );
+        assert_eq!(connection.next_packet(start), None);
+    }
+
+    #[test]
+    fn old_peer() {
+        let start = Instant::now();
+        let mut old = new_connection(start);
+        old.settings.peer_version = SrtVersion::new(1, 1, 0);
+        old.settings.peer_flags = SrtShakeFlags::TSBPDSND | SrtShakeFlags::TSBPDRCV;
+        assert!(!old.settings.peer_supports_retransmit_flag());
+        assert!(!old.settings.peer_supports_periodic_nak());
+        let mut connection = DuplexConnection::new(old);
+
+        let mut now = start;
+        connection.handle_input(now, Input::Data(Some((start, Bytes::new()))));
+        connection.handle_input(now, Input::Data(Some((start, Bytes::new()))));
+        now += SND;
+        assert_matches!(
+            connection.handle_input(now, Input::Timer),
+            SendPacket((Data(DataPacket { seq_number, .. }), _)) if seq_number.0 == 0
+        );
+
+        // the retransmission isn't flagged, the peer would take the bit as part of the message
+        // number
+        now += TSBPD;
+        assert_matches!(
+            connection.handle_input(now, Input::Timer),
+            SendPacket((Data(DataPacket {seq_number, retransmitted: false, ..}), _)) if seq_number.0 == 0
+        );
+    }
+
     #[test]
     fn too_late_packet_drop() {
         let start = Instant::now();
@@ -643,11 +1340,1248 @@ mod duplex_connection {
                     dest_sockid: remote_sockid(),
                     control_type: DropRequest {
                         msg_to_drop: MsgNumber(0),
-                        range: SeqNumber(0)..=SeqNumber(1)
+                        range: SeqNumber(0)..=SeqNumber(0)
+                    }
+                }),
+                remote_addr()
+            ))
+        );
+        // one drop request per message, with its message number
+        assert_eq!(
+            connection.handle_input(now, Input::Timer),
+            SendPacket((
+                Control(ControlPacket {
+                    timestamp: TimeStamp::MIN + SND + TSBPD + TSBPD / 4,
+                    dest_sockid: remote_sockid(),
+                    control_type: DropRequest {
+                        msg_to_drop: MsgNumber(1),
+                        range: SeqNumber(1)..=SeqNumber(1)
                     }
                 }),
                 remote_addr()
             ))
         );
     }
+
+    #[test]
+    fn peer_without_too_late_drop() {
+        let start = Instant::now();
+        let mut peer = new_connection(start);
+        peer.settings.peer_flags.remove(SrtShakeFlags::TLPKTDROP);
+        assert!(!peer.settings.sender_drops_too_late());
+        let mut connection = DuplexConnection::new(peer);
+
+        let mut now = start;
+        connection.handle_input(now, Input::Data(Some((start, Bytes::new()))));
+        now += SND;
+        assert_matches!(
+            connection.handle_input(now, Input::Timer),
+            SendPacket((Data(DataPacket { seq_number, .. }), _)) if seq_number.0 == 0
+        );
+
+        // long after the packet would have been dropped, the peer still waits for it
+        now += TSBPD * 2;
+        assert_matches!(
+            connection.handle_input(
+                now,
+                Input::Packet(Ok((
+                    Control(ControlPacket {
+                        timestamp: TimeStamp::MIN + SND + TSBPD * 2,
+                        dest_sockid: remote_sockid(),
+                        control_type: Nak((SeqNumber(0)..SeqNumber(1)).into()),
+                    }),
+                    remote_addr()
+                )))
+            ),
+            SendPacket((Data(DataPacket { seq_number, retransmitted: true, .. }), _)) if seq_number.0 == 0
+        );
+    }
+
+    #[test]
+    fn gap_handler() {
+        let start = Instant::now();
+        let mut connection = new_connection(start);
+        connection.settings.skip_gaps = true;
+        let mut connection = DuplexConnection::new(connection);
+
+        let gaps = Arc::new(Mutex::new(vec![]));
+        let recorded = gaps.clone();
+        connection.set_gap_handler(move |_, packets| recorded.lock().unwrap().push(packets));
+
+        // the second packet is lost, the ones around it were sent 20ms apart
+        let packet = |n: u32, timestamp: TimeStamp, payload: &'static [u8]| {
+            Data(DataPacket {
+                seq_number: SeqNumber(n),
+                message_loc: PacketLocation::ONLY,
+                in_order_delivery: false,
+                encryption: DataEncryption::None,
+                retransmitted: false,
+                message_number: MsgNumber(n),
+                timestamp,
+                dest_sockid: local_sockid(),
+                payload: Bytes::from_static(payload),
+            })
+        };
+        let later = TimeSpan::from_millis(20);
+        connection.handle_packet_input(
+            start,
+            Ok((packet(0, TimeStamp::MIN, b"before"), remote_addr())),
+        );
+        connection.handle_packet_input(
+            start,
+            Ok((packet(2, TimeStamp::MIN + later, b"after"), remote_addr())),
+        );
+
+        let now = start + TSBPD;
+        assert_eq!(
+            connection.next_data(now),
+            Some((start, b"before"[..].into()))
+        );
+        assert_eq!(connection.next_data(now), None);
+
+        // the packet after the gap isn't due before its own TSBPD time
+        let now = start + TSBPD + later / 2;
+        assert_eq!(connection.next_data(now), None);
+        assert!(gaps.lock().unwrap().is_empty());
+
+        // then it's released right away, without waiting for the lost packet any longer
+        let now = start + TSBPD + later;
+        assert_eq!(
+            connection.next_data(now),
+            Some((start + later, b"after"[..].into()))
+        );
+        assert_eq!(*gaps.lock().unwrap(), [SeqNumber(1)..SeqNumber(2)]);
+    }
+
+    #[test]
+    fn delivery_rate() {
+        let start = Instant::now();
+        let mut connection = new_connection(start);
+        // 1000 bytes take 1 ms
+        connection.settings.max_delivery_rate = Some(DataRate(1_000_000));
+        let mut connection = DuplexConnection::new(connection);
+
+        for n in 0..10 {
+            let data = DataPacket {
+                seq_number: SeqNumber(n),
+                message_loc: PacketLocation::ONLY,
+                in_order_delivery: false,
+                encryption: DataEncryption::None,
+                retransmitted: false,
+                message_number: MsgNumber(n),
+                timestamp: TimeStamp::MIN,
+                dest_sockid: local_sockid(),
+                payload: Bytes::from(vec![0; 1000]),
+            };
+            connection.handle_packet_input(start, Ok((Data(data), remote_addr())));
+        }
+
+        // the burst the rate allows for goes out at once, then the rest is spaced out
+        let mut now = start + TSBPD;
+        let mut released = 0;
+        while connection.next_data(now).is_some() {
+            released += 1;
+        }
+        assert_eq!(released, 6);
+        for _ in 0..4 {
+            assert_eq!(connection.check_timers(now), now + Duration::from_millis(1));
+            assert_eq!(connection.next_data(now), None);
+            now += Duration::from_millis(1);
+            assert!(connection.next_data(now).is_some());
+            assert_eq!(connection.next_data(now), None);
+        }
+    }
+
+    #[test]
+    fn tap_handler() {
+        let start = Instant::now();
+        let mut connection = DuplexConnection::new(new_connection(start));
+
+        let tapped = Arc::new(Mutex::new(vec![]));
+        connection.set_tap_handler({
+            let tapped = tapped.clone();
+            move |_, packet| tapped.lock().unwrap().push(packet.seq_number)
+        });
+
+        let mut receive = |seq_number| {
+            let data = DataPacket {
+                seq_number,
+                message_loc: PacketLocation::ONLY,
+                in_order_delivery: false,
+                encryption: DataEncryption::None,
+                retransmitted: false,
+                message_number: MsgNumber(seq_number.0),
+                timestamp: TimeStamp::MIN,
+                dest_sockid: local_sockid(),
+                payload: Bytes::new(),
+            };
+            connection.handle_packet_input(start, Ok((Data(data), remote_addr())));
+        };
+
+        // 1 is lost, 2 waits for it
+        receive(SeqNumber(0));
+        receive(SeqNumber(2));
+        assert_eq!(*tapped.lock().unwrap(), [SeqNumber(0)]);
+
+        // recovered, long before any of them are released
+        receive(SeqNumber(1));
+        assert_eq!(
+            *tapped.lock().unwrap(),
+            [SeqNumber(0), SeqNumber(1), SeqNumber(2)]
+        );
+        assert_eq!(connection.next_data(start), None);
+    }
+
+    #[test]
+    fn coalesced_loss_reports() {
+        let start = Instant::now();
+        let mut connection = DuplexConnection::new(new_connection(start));
+
+        // receive packets 0, 2 and 4 before the next flush, leaving gaps at 1 and 3
+        for seq_number in [SeqNumber(0), SeqNumber(2), SeqNumber(4)] {
+            let data = DataPacket {
+                seq_number,
+                message_loc: PacketLocation::ONLY,
+                in_order_delivery: false,
+                encryption: DataEncryption::None,
+                retransmitted: false,
+                message_number: MsgNumber(0),
+                timestamp: TimeStamp::MIN,
+                dest_sockid: local_sockid(),
+                payload: Bytes::new(),
+            };
+            connection.handle_packet_input(start, Ok((Data(data), remote_addr())));
+        }
+
+        let mut naks = vec![];
+        while let Some((packet, _)) = connection.next_packet(start) {
+            if let Control(ControlPacket {
+                control_type: Nak(loss_list),
+                ..
+            }) = packet
+            {
+                naks.push(loss_list.iter_ranges().collect::<Vec<_>>());
+            }
+        }
+        assert_eq!(
+            naks,
+            [vec![SeqNumber(1)..SeqNumber(2), SeqNumber(3)..SeqNumber(4)]]
+        );
+    }
+
+    #[test]
+    fn arq_gauges() {
+        let start = Instant::now();
+        let mut connection = DuplexConnection::new(new_connection(start));
+
+        let mut now = start;
+        for _ in 0..3 {
+            connection.handle_input(now, Input::Data(Some((start, Bytes::new()))));
+        }
+        assert_eq!(connection.statistics().tx_buffered_data, 3);
+
+        for _ in 0..3 {
+            now += SND;
+            assert_matches!(
+                connection.handle_input(now, Input::Timer),
+                SendPacket((Data(_), _))
+            );
+        }
+        assert_eq!(connection.statistics().tx_unacknowledged_data, 3);
+        connection.update_statistics(now);
+        assert_eq!(connection.statistics().tx_unacknowledged_age, SND * 2);
+        connection.handle_input(
+            now,
+            Input::Packet(Ok((
+                Control(ControlPacket {
+                    timestamp: TimeStamp::MIN,
+                    dest_sockid: local_sockid(),
+                    control_type: Nak((SeqNumber(0)..SeqNumber(2)).into()),
+                }),
+                remote_addr(),
+            ))),
+        );
+        assert_eq!(connection.statistics().tx_loss_list_length, 2);
+
+        // receive packets 0 and 2, leaving a gap at 1
+        for seq_number in [SeqNumber(0), SeqNumber(2)] {
+            let data = DataPacket {
+                seq_number,
+                message_loc: PacketLocation::ONLY,
+                in_order_delivery: false,
+                encryption: DataEncryption::None,
+                retransmitted: false,
+                message_number: MsgNumber(0),
+                timestamp: TimeStamp::MIN,
+                dest_sockid: local_sockid(),
+                payload: Bytes::new(),
+            };
+            connection.handle_input(now, Input::Packet(Ok((Data(data), remote_addr()))));
+        }
+        assert_eq!(connection.statistics().rx_buffered_data, 3);
+        assert_eq!(connection.statistics().rx_acknowledged_data, 1);
+    }
+
+    #[test]
+    fn congestion_experienced() {
+        let start = Instant::now();
+        let mut connection = new_connection(start);
+        connection.settings.ecn = true;
+        let mut connection = DuplexConnection::new(connection);
+
+        let data = DataPacket {
+            seq_number: SeqNumber(0),
+            message_loc: PacketLocation::ONLY,
+            in_order_delivery: false,
+            encryption: DataEncryption::None,
+            retransmitted: false,
+            message_number: MsgNumber(0),
+            timestamp: TimeStamp::MIN,
+            dest_sockid: local_sockid(),
+            payload: Bytes::new(),
+        };
+        connection.handle_input(start, Input::Packet(Ok((Data(data), remote_addr()))));
+        connection.handle_congestion_experienced(1);
+        assert_eq!(connection.statistics().rx_congestion_experienced, 1);
+
+        // the marks are echoed along with the next full ACK
+        let now = start + Timers::SYN;
+        connection.check_timers(now);
+        let mut sent = Vec::new();
+        while let Some((Control(control), _)) = connection.next_packet(now) {
+            sent.push(control.control_type);
+        }
+        assert_matches!(
+            sent[..],
+            [Ack(_), Srt(SrtControlPacket::CongestionExperienced(1))]
+        );
+
+        connection.handle_input(
+            now,
+            Input::Packet(Ok((
+                Control(ControlPacket {
+                    timestamp: TimeStamp::MIN,
+                    dest_sockid: local_sockid(),
+                    control_type: Srt(SrtControlPacket::CongestionExperienced(2)),
+                }),
+                remote_addr(),
+            ))),
+        );
+        assert_eq!(connection.statistics().tx_congestion_experienced, 2);
+    }
+
+    #[test]
+    fn congestion_experienced_not_offered() {
+        let start = Instant::now();
+        let mut connection = DuplexConnection::new(new_connection(start));
+
+        let data = DataPacket {
+            seq_number: SeqNumber(0),
+            message_loc: PacketLocation::ONLY,
+            in_order_delivery: false,
+            encryption: DataEncryption::None,
+            retransmitted: false,
+            message_number: MsgNumber(0),
+            timestamp: TimeStamp::MIN,
+            dest_sockid: local_sockid(),
+            payload: Bytes::new(),
+        };
+        connection.handle_input(start, Input::Packet(Ok((Data(data), remote_addr()))));
+        connection.handle_congestion_experienced(1);
+
+        // a peer that didn't offer ECN, such as libsrt, doesn't know of the report
+        let now = start + Timers::SYN;
+        connection.check_timers(now);
+        let mut sent = Vec::new();
+        while let Some((Control(control), _)) = connection.next_packet(now) {
+            sent.push(control.control_type);
+        }
+        assert_matches!(sent[..], [Ack(_)]);
+    }
+
+    #[test]
+    fn tracked_delivery() {
+        let start = Instant::now();
+        let mut connection = DuplexConnection::new(new_connection(start));
+
+        let first = connection.handle_tracked_data_input(start, (start, Bytes::from("first")));
+        let second = connection.handle_tracked_data_input(start, (start, Bytes::from("second")));
+        assert_eq!(first, Some(SeqNumber(0)..SeqNumber(1)));
+        assert_eq!(second, Some(SeqNumber(1)..SeqNumber(2)));
+        assert_eq!(connection.next_delivery(), None);
+
+        let mut now = start;
+        for _ in 0..2 {
+            now += SND;
+            assert_matches!(
+                connection.handle_input(now, Input::Timer),
+                SendPacket((Data(_), _))
+            );
+        }
+        let ack = Control(ControlPacket {
+            timestamp: TimeStamp::MIN,
+            dest_sockid: local_sockid(),
+            control_type: Ack(Acknowledgement::Lite(SeqNumber(1))),
+        });
+        connection.handle_input(now, Input::Packet(Ok((ack, remote_addr()))));
+        assert_eq!(
+            connection.next_delivery(),
+            Some((SeqNumber(0)..SeqNumber(1), Delivery::Acknowledged))
+        );
+        assert_eq!(connection.next_delivery(), None);
+
+        // a full send buffer drops the oldest message to make room
+        let mut full = new_connection(start);
+        full.settings.send_buffer_size = PacketCount(1);
+        let mut connection = DuplexConnection::new(full);
+        connection.handle_tracked_data_input(start, (start, Bytes::from("first")));
+        connection.handle_tracked_data_input(start, (start, Bytes::from("second")));
+        assert_eq!(
+            connection.next_delivery(),
+            Some((SeqNumber(0)..SeqNumber(1), Delivery::Dropped))
+        );
+        assert_eq!(connection.next_delivery(), None);
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let start = Instant::now();
+        let mut settings = new_connection(start);
+        settings.settings.cipher = Some(CipherSettings::new_random(
+            &KeySettings {
+                key_size: KeySize::AES128,
+                key_source: KeySource::Passphrase("password123".into()),
+            },
+            &Default::default(),
+        ));
+        settings.settings.stream_id = Some("live/stream".into());
+        let mut connection = DuplexConnection::new(settings);
+
+        // two packets sent, one queued
+        for _ in 0..3 {
+            connection.handle_input(start, Input::Data(Some((start, Bytes::from("data")))));
+        }
+        let mut now = start;
+        let mut sent = vec![];
+        for _ in 0..2 {
+            now += SND;
+            match connection.handle_input(now, Input::Timer) {
+                SendPacket((Data(packet), _)) => sent.push(packet),
+                action => panic!("expected data, got {action:?}"),
+            }
+        }
+        // received packet 0, but not 1
+        let received = DataPacket {
+            dest_sockid: local_sockid(),
+            ..sent[0].clone()
+        };
+        connection.handle_input(now, Input::Packet(Ok((Data(received), remote_addr()))));
+
+        let snapshot = connection.snapshot();
+        let mut serialized = BytesMut::new();
+        snapshot.serialize(now, &mut serialized);
+        let parsed = ConnectionSnapshot::parse(now, &mut serialized.freeze()).unwrap();
+        assert_eq!(parsed, snapshot);
+
+        let next_data_packet = |connection: &mut DuplexConnection, now| loop {
+            match connection.handle_input(now, Input::Timer) {
+                SendPacket((Data(packet), _)) => break packet,
+                SendPacket(_) | UpdateStatistics(_) => continue,
+                action => panic!("expected data, got {action:?}"),
+            }
+        };
+
+        let mut restored = DuplexConnection::restore(parsed, now);
+        // the queued packet goes out next
+        now += SND;
+        let packet = next_data_packet(&mut restored, now);
+        assert_eq!(packet.seq_number, SeqNumber(2));
+        assert_eq!(packet.message_number, MsgNumber(2));
+        // and the sent ones are still there to be retransmitted
+        restored.handle_input(
+            now,
+            Input::Packet(Ok((
+                Control(ControlPacket {
+                    timestamp: TimeStamp::MIN,
+                    dest_sockid: local_sockid(),
+                    control_type: Nak((SeqNumber(1)..SeqNumber(2)).into()),
+                }),
+                remote_addr(),
+            ))),
+        );
+        now += SND;
+        let retransmitted = next_data_packet(&mut restored, now);
+        assert_eq!(retransmitted.seq_number, SeqNumber(1));
+        assert_eq!(retransmitted.payload, sent[1].payload);
+
+        // the received packet is released, the remote clock is synchronized again from scratch
+        assert_eq!(
+            restored.next_data(now + TSBPD).map(|(_, data)| data),
+            Some(Bytes::from("data"))
+        );
+    }
+
+    #[test]
+    fn snapshot_key_provider() {
+        let start = Instant::now();
+        let provider = SharedKeyProvider::new(Passphrase::from("not in the snapshot"));
+        let mut settings = new_connection(start);
+        settings.settings.cipher = Some(CipherSettings::new_random(
+            &KeySettings {
+                key_size: KeySize::AES128,
+                key_source: KeySource::Provider(provider.clone()),
+            },
+            &Default::default(),
+        ));
+        let connection = DuplexConnection::new(settings);
+
+        let snapshot = connection.snapshot();
+        let mut serialized = BytesMut::new();
+        snapshot.serialize(start, &mut serialized);
+        let serialized = serialized.freeze();
+        assert!(!serialized
+            .windows(b"not in the snapshot".len())
+            .any(|w| w == b"not in the snapshot"));
+
+        assert_eq!(
+            ConnectionSnapshot::parse(start, &mut serialized.clone()),
+            Err(snapshot::SnapshotError::KeyProviderRequired)
+        );
+        let parsed = ConnectionSnapshot::parse_with_key_provider(
+            start,
+            &mut serialized.clone(),
+            Some(provider),
+        )
+        .unwrap();
+        assert_eq!(parsed, snapshot);
+    }
+
+    #[test]
+    fn snapshot_key_derivation() {
+        let start = Instant::now();
+        let mut settings = new_connection(start);
+        settings.settings.cipher = Some(CipherSettings::new_random(
+            &KeySettings {
+                key_size: KeySize::AES256,
+                key_source: KeySource::DerivedPassphrase(
+                    "password123".into(),
+                    KeyDerivation {
+                        iterations: 4096,
+                        salt_length: 16,
+                        initial_salt: Some(Salt::new_random()),
+                    },
+                ),
+            },
+            &Default::default(),
+        ));
+        let connection = DuplexConnection::new(settings);
+
+        let snapshot = connection.snapshot();
+        let mut serialized = BytesMut::new();
+        snapshot.serialize(start, &mut serialized);
+        let parsed = ConnectionSnapshot::parse(start, &mut serialized.freeze()).unwrap();
+        assert_eq!(parsed, snapshot);
+    }
+
+    #[test]
+    fn message_tags() {
+        let start = Instant::now();
+        let tagged = || {
+            let mut connection = new_connection(start);
+            connection.settings.message_tags = true;
+            connection
+        };
+
+        let mut sender = DuplexConnection::new(tagged());
+        sender.handle_tagged_data_input(start, (start, Bytes::from("frame")), 1234);
+        let now = start + SND;
+        let packet = match sender.handle_input(now, Input::Timer) {
+            SendPacket((Data(packet), _)) => packet,
+            action => panic!("expected data, got {action:?}"),
+        };
+        assert_eq!(&packet.payload[..], b"\0\0\x04\xd2frame");
+
+        let mut receiver = DuplexConnection::new(tagged());
+        let data = DataPacket {
+            dest_sockid: local_sockid(),
+            ..packet
+        };
+        receiver.handle_input(now, Input::Packet(Ok((Data(data), remote_addr()))));
+        let (_, tag, data) = receiver.next_tagged_data(now + TSBPD).unwrap();
+        assert_eq!(tag, 1234);
+        assert_eq!(data, "frame");
+    }
+
+    #[test]
+    fn lost_packet_release_time() {
+        let start = Instant::now();
+        let mut connection = DuplexConnection::new(new_connection(start));
+
+        // packet 0 is lost and nothing else arrives, packet 1 should still be released as soon
+        // as packet 0 is too late, not whenever the next unrelated timer fires
+        let data = DataPacket {
+            seq_number: SeqNumber(1),
+            message_loc: PacketLocation::ONLY,
+            in_order_delivery: false,
+            encryption: DataEncryption::None,
+            retransmitted: false,
+            message_number: MsgNumber(1),
+            timestamp: TimeStamp::MIN,
+            dest_sockid: local_sockid(),
+            payload: Bytes::from("late"),
+        };
+        connection.handle_input(start, Input::Packet(Ok((Data(data), remote_addr()))));
+
+        let too_late = start + TSBPD + Duration::from_millis(5);
+        let mut now = start;
+        let released_at = loop {
+            match connection.handle_input(now, Input::Timer) {
+                ReleaseData(_) => break now,
+                WaitForData(wait) => now += wait,
+                Close => panic!("connection closed"),
+                _ => {}
+            }
+            assert!(now <= too_late, "packet was not released on time");
+        };
+        assert_eq!(released_at, too_late);
+
+        connection.update_statistics(now);
+        assert_eq!(
+            connection.statistics().rx_delivery_jitter,
+            Duration::from_millis(5) / 16
+        );
+    }
+
+    #[test]
+    fn duplicate_statistics() {
+        let start = Instant::now();
+        let mut connection = new_connection(start);
+        connection.settings.duplicate_interval = Some(5 * MILLIS);
+        let mut connection = DuplexConnection::new(connection);
+
+        connection.handle_input(start, Input::Data(Some((start, Bytes::from("copied")))));
+        let mut sent = 0;
+        let mut now = start;
+        while now < start + 8 * MILLIS {
+            match connection.handle_input(now, Input::Timer) {
+                SendPacket((Data(_), _)) => sent += 1,
+                WaitForData(wait) => now += wait,
+                _ => {}
+            }
+        }
+        assert_eq!(sent, 2);
+
+        // the copy isn't a retransmission
+        connection.update_statistics(now);
+        let stats = connection.statistics();
+        assert_eq!((stats.tx_data, stats.tx_unique_data), (2, 1));
+        assert_eq!(stats.tx_duplicate_data, 1);
+        assert_eq!(stats.tx_duplicate_bytes, stats.tx_unique_bytes);
+        assert_eq!(stats.tx_retransmit_data, 0);
+    }
+
+    fn rebinding_data(seq_number: SeqNumber) -> Packet {
+        Data(DataPacket {
+            seq_number,
+            message_loc: PacketLocation::ONLY,
+            in_order_delivery: false,
+            encryption: DataEncryption::None,
+            retransmitted: false,
+            message_number: MsgNumber(0),
+            timestamp: TimeStamp::MIN,
+            dest_sockid: local_sockid(),
+            payload: Bytes::from_static(b"hello"),
+        })
+    }
+
+    fn rebinding_control(control_type: ControlTypes) -> Packet {
+        Control(ControlPacket {
+            timestamp: TimeStamp::MIN,
+            dest_sockid: local_sockid(),
+            control_type,
+        })
+    }
+
+    #[test]
+    fn peer_rebinding() {
+        let start = Instant::now();
+        let mut connection = DuplexConnection::new(new_connection(start));
+        let rebound: SocketAddr = ([127, 0, 0, 1], 3334).into();
+
+        // a handshake doesn't move the connection
+        let handshake = rebinding_control(Handshake(HandshakeControlInfo {
+            init_seq_num: SeqNumber(0),
+            max_packet_size: PacketSize(1316),
+            max_flow_size: PacketCount(8192),
+            shake_type: ShakeType::Conclusion,
+            socket_id: remote_sockid(),
+            syn_cookie: 0,
+            peer_addr: [127, 0, 0, 1].into(),
+            info: HandshakeVsInfo::V4(SocketType::Datagram),
+        }));
+        connection.handle_input(start, Input::Packet(Ok((handshake, rebound))));
+        assert_eq!(connection.settings().remote, remote_addr());
+
+        // nor does a packet that takes no more than the socket id to make up
+        let keepalive = rebinding_control(KeepAlive);
+        connection.handle_input(start, Input::Packet(Ok((keepalive, rebound))));
+        assert_eq!(connection.settings().remote, remote_addr());
+        assert_eq!(connection.statistics().rx_all_packets, 0);
+
+        // data the peer would send right now does
+        let data = rebinding_data(SeqNumber(0));
+        connection.handle_input(start, Input::Packet(Ok((data, rebound))));
+        assert_eq!(connection.settings().remote, rebound);
+        assert_eq!(connection.statistics().rx_all_packets, 1);
+
+        connection.handle_input(start, Input::Data(Some((start, Bytes::from("hello")))));
+        assert_matches!(
+            connection.handle_input(start + SND, Input::Timer),
+            SendPacket((Data(_), addr)) if addr == rebound
+        );
+    }
+
+    #[test]
+    fn spoofed_peer_rebinding() {
+        let start = Instant::now();
+        let mut connection = DuplexConnection::new(new_connection(start));
+        let spoofed: SocketAddr = ([10, 0, 0, 1], 2223).into();
+
+        // data far outside of the receive window, and an ACK of packets that were never sent
+        let packets = [
+            rebinding_data(SeqNumber(1_000_000)),
+            rebinding_data(SeqNumber(0) - 1),
+            rebinding_control(Ack(Acknowledgement::Lite(SeqNumber(10)))),
+            rebinding_control(Shutdown),
+        ];
+        for packet in packets {
+            connection.handle_input(start, Input::Packet(Ok((packet, spoofed))));
+            assert_eq!(connection.settings().remote, remote_addr());
+        }
+        assert_eq!(connection.statistics().rx_all_packets, 0);
+        assert_eq!(connection.statistics().rx_foreign_packets, 4);
+        assert!(connection.is_open());
+
+        connection.handle_input(start, Input::Data(Some((start, Bytes::from("hello")))));
+        assert_matches!(
+            connection.handle_input(start + SND, Input::Timer),
+            SendPacket((Data(_), addr)) if addr == remote_addr()
+        );
+    }
+
+    #[test]
+    fn peer_address_policy() {
+        let start = Instant::now();
+        let mut connection = new_connection(start);
+        connection.settings.peer_address_policy = PeerAddressPolicy::RebindPort;
+        let mut connection = DuplexConnection::new(connection);
+        let rebinds = Arc::new(Mutex::new(Vec::new()));
+        connection.set_rebind_handler({
+            let rebinds = rebinds.clone();
+            move |_, old, new| rebinds.lock().unwrap().push((old, new))
+        });
+
+        // another host is turned away, even with a packet the peer could have sent
+        let spoofed: SocketAddr = ([10, 0, 0, 1], 2223).into();
+        let data = rebinding_data(SeqNumber(0));
+        connection.handle_input(start, Input::Packet(Ok((data, spoofed))));
+        assert_eq!(connection.settings().remote, remote_addr());
+        assert_eq!(connection.statistics().rx_all_packets, 0);
+        assert_eq!(connection.statistics().rx_foreign_packets, 1);
+
+        // while the peer can move to another port
+        let rebound: SocketAddr = ([127, 0, 0, 1], 3334).into();
+        let data = rebinding_data(SeqNumber(0));
+        connection.handle_input(start, Input::Packet(Ok((data, rebound))));
+        assert_eq!(connection.settings().remote, rebound);
+        assert_eq!(connection.statistics().rx_all_packets, 1);
+        assert_eq!(*rebinds.lock().unwrap(), [(remote_addr(), rebound)]);
+    }
+
+    #[test]
+    fn stall_handler() {
+        let start = Instant::now();
+        let mut connection = DuplexConnection::new(new_connection(start));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        connection.set_stall_handler(Duration::from_millis(500), {
+            let events = events.clone();
+            move |now, event| events.lock().unwrap().push((now, *event))
+        });
+        let data = |seq, retransmitted| {
+            Input::Packet(Ok((
+                Data(DataPacket {
+                    seq_number: SeqNumber(seq),
+                    message_loc: PacketLocation::ONLY,
+                    in_order_delivery: false,
+                    encryption: DataEncryption::None,
+                    retransmitted,
+                    message_number: MsgNumber(seq + 1),
+                    timestamp: TimeStamp::MIN,
+                    dest_sockid: local_sockid(),
+                    payload: Bytes::from_static(b"data"),
+                }),
+                remote_addr(),
+            )))
+        };
+        let ms = |ms| start + Duration::from_millis(ms);
+
+        connection.handle_input(ms(100), data(0, false));
+        assert!(connection.next_timer(ms(100)) <= ms(600));
+        connection.handle_input(ms(599), Input::Timer);
+        assert!(events.lock().unwrap().is_empty());
+        connection.handle_input(ms(600), Input::Timer);
+        let stalled = StallEvent::Stalled { since: ms(100) };
+        assert_eq!(*events.lock().unwrap(), [(ms(600), stalled)]);
+
+        // a retransmission doesn't bring the stream back, new data does
+        connection.handle_input(ms(700), data(0, true));
+        connection.handle_input(ms(900), data(1, false));
+        let resumed = StallEvent::Resumed {
+            stalled_for: Duration::from_millis(800),
+        };
+        assert_eq!(
+            *events.lock().unwrap(),
+            [(ms(600), stalled), (ms(900), resumed)]
+        );
+    }
+
+    #[test]
+    fn adaptive_keepalive() {
+        let start = Instant::now();
+        let mut connection = new_connection(start);
+        connection.settings.adaptive_keepalive = true;
+        let mut connection = DuplexConnection::new(connection);
+
+        let mut keepalives = Vec::new();
+        for step in 0..=70 {
+            let now = start + step * 50 * MILLIS;
+            let mut input = Input::Timer;
+            loop {
+                input = match connection.handle_input(now, input) {
+                    SendPacket((packet, _)) => {
+                        if matches!(
+                            packet,
+                            Control(ControlPacket {
+                                control_type: KeepAlive,
+                                ..
+                            })
+                        ) {
+                            keepalives.push(now - start);
+                        }
+                        Input::PacketSent
+                    }
+                    UpdateStatistics(_) => Input::StatisticsUpdated,
+                    WaitForData(_) => break,
+                    action => panic!("{action:?}"),
+                }
+            }
+            // the peer is heard from again
+            if step == 40 {
+                let keepalive = Control(ControlPacket {
+                    timestamp: TimeStamp::MIN,
+                    dest_sockid: local_sockid(),
+                    control_type: KeepAlive,
+                });
+                connection.handle_input(now, Input::Packet(Ok((keepalive, remote_addr()))));
+            }
+        }
+
+        // every second, four times as often while the peer is silent, back to every second once
+        // it's heard from, until it's been silent for too long again
+        assert_eq!(
+            keepalives,
+            [1000, 1250, 1500, 1750, 2000, 3000, 3250, 3500]
+                .map(|ms| ms * MILLIS)
+                .to_vec()
+        );
+    }
+
+    #[test]
+    fn payload_size_limit() {
+        let start = Instant::now();
+        let mut connection = new_connection(start);
+        connection.settings.limit_payload_size = true;
+        let mut connection = DuplexConnection::new(connection);
+        let message = |size| SendMessage::new(start, Bytes::from(vec![0; size]));
+
+        assert_eq!(connection.settings().max_message_size(), Some(1316));
+        assert_eq!(
+            connection.settings().check_payload_size(&message(1317)),
+            Err(PayloadTooLarge {
+                size: 1317,
+                max: PacketSize(1316)
+            })
+        );
+        // refused rather than split
+        assert_eq!(
+            connection.handle_tracked_data_input(start, message(1317)),
+            None
+        );
+        assert_eq!(
+            connection.handle_tracked_data_input(start, message(1316)),
+            Some(SeqNumber(0)..SeqNumber(1))
+        );
+
+        // a byte stream is split whatever the size of the write
+        let mut connection = new_connection(start);
+        connection.settings.limit_payload_size = true;
+        connection.settings.local_flags |= SrtShakeFlags::STREAM;
+        let mut connection = DuplexConnection::new(connection);
+        assert_eq!(connection.settings().max_message_size(), None);
+        assert_eq!(
+            connection.handle_tracked_data_input(start, message(1317)),
+            Some(SeqNumber(0)..SeqNumber(2))
+        );
+    }
+
+    #[test]
+    fn control_extension() {
+        use std::sync::{Arc, Mutex};
+
+        let start = Instant::now();
+        let mut connection = DuplexConnection::new(new_connection(start));
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        connection.set_extension_handler({
+            let received = received.clone();
+            move |_, ty, payload| received.lock().unwrap().push((ty, payload))
+        });
+
+        connection.send_control_extension(start, 0x4000, Bytes::from_static(b"ping"));
+        let extension = ControlTypes::Srt(SrtControlPacket::Extension {
+            ty: 0x4000,
+            payload: Bytes::from_static(b"ping"),
+        });
+        assert_matches!(
+            connection.next_packet(start),
+            Some((Control(ControlPacket { control_type, .. }), _)) if control_type == extension
+        );
+
+        let pong = Control(ControlPacket {
+            timestamp: TimeStamp::MIN,
+            dest_sockid: local_sockid(),
+            control_type: ControlTypes::Srt(SrtControlPacket::Extension {
+                ty: 0x4001,
+                payload: Bytes::from_static(b"pong"),
+            }),
+        });
+        connection.handle_input(start, Input::Packet(Ok((pong, remote_addr()))));
+
+        assert_eq!(
+            received.lock().unwrap()[..],
+            [(0x4001, Bytes::from_static(b"pong"))]
+        );
+
+        // the handshake's extensions on their own are ignored, not the connection's end
+        let stream_id = Control(ControlPacket {
+            timestamp: TimeStamp::MIN,
+            dest_sockid: local_sockid(),
+            control_type: ControlTypes::Srt(SrtControlPacket::StreamId("id".into())),
+        });
+        connection.handle_input(start, Input::Packet(Ok((stream_id, remote_addr()))));
+        assert!(connection.is_open());
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn echo() {
+        let start = Instant::now();
+        let mut connection = DuplexConnection::new(new_connection(start));
+
+        let echo = |control_type| {
+            Input::Packet(Ok((
+                Control(ControlPacket {
+                    timestamp: TimeStamp::MIN,
+                    dest_sockid: local_sockid(),
+                    control_type: Srt(control_type),
+                }),
+                remote_addr(),
+            )))
+        };
+
+        // answered right away
+        assert_matches!(
+            connection.handle_input(start, echo(SrtControlPacket::EchoRequest { id: 7 })),
+            SendPacket((
+                Control(ControlPacket {
+                    control_type: Srt(SrtControlPacket::EchoReply {
+                        id: 7,
+                        requests_received: 1,
+                        ..
+                    }),
+                    ..
+                }),
+                _
+            ))
+        );
+
+        let id = connection.send_echo_request(start);
+        assert_matches!(
+            connection.next_packet(start),
+            Some((Control(ControlPacket {
+                control_type: Srt(SrtControlPacket::EchoRequest { id: sent }),
+                ..
+            }), _)) if sent == id
+        );
+        let now = start + 10 * MILLIS;
+        connection.handle_input(
+            now,
+            echo(SrtControlPacket::EchoReply {
+                id,
+                timestamp: TimeStamp::MIN + 5 * MILLIS,
+                requests_received: 1,
+            }),
+        );
+        let sample = connection.next_echo_reply().unwrap();
+        assert_eq!((sample.id, sample.rtt), (id, 10 * MILLIS));
+        assert_eq!(sample.offset, TimeSpan::ZERO);
+        assert_eq!(connection.next_echo_reply(), None);
+
+        // unsolicited
+        connection.handle_input(
+            now,
+            echo(SrtControlPacket::EchoReply {
+                id: 1234,
+                timestamp: TimeStamp::MIN,
+                requests_received: 1,
+            }),
+        );
+        assert_eq!(connection.next_echo_reply(), None);
+    }
+
+    #[test]
+    fn encrypted_control() {
+        let start = Instant::now();
+        let mut settings = new_connection(start);
+        settings.settings.cipher = Some(CipherSettings::new_random(
+            &KeySettings {
+                key_size: KeySize::AES128,
+                key_source: KeySource::Passphrase("password123".into()),
+            },
+            &Default::default(),
+        ));
+        settings.settings.encrypt_control = true;
+        // it talks to itself, the socket ids of both ends are the same
+        let mut connection = DuplexConnection::new(settings);
+
+        let next_encrypted = |connection: &mut DuplexConnection, now: &mut Instant| loop {
+            match connection.handle_input(*now, Input::Timer) {
+                SendPacket((
+                    packet @ Control(ControlPacket {
+                        control_type: Srt(SrtControlPacket::EncryptedControl { .. }),
+                        ..
+                    }),
+                    _,
+                )) => break packet,
+                SendPacket((Control(ControlPacket { control_type, .. }), _))
+                    if DuplexConnection::is_encrypted_control(&control_type) =>
+                {
+                    panic!("{control_type:?} wasn't encrypted")
+                }
+                SendPacket(_) | UpdateStatistics(_) => continue,
+                WaitForData(wait) => *now += wait,
+                action => panic!("expected a control packet, got {action:?}"),
+            }
+        };
+
+        connection.handle_input(start, Input::Data(Some((start, Bytes::from("data")))));
+        let mut now = start + SND;
+        let data = connection.handle_input(now, Input::Timer);
+        let SendPacket((data, _)) = data else {
+            panic!("expected data, got {data:?}")
+        };
+        connection.handle_input(now, Input::Packet(Ok((data, remote_addr()))));
+
+        let ack = next_encrypted(&mut connection, &mut now);
+        connection.handle_input(now, Input::Packet(Ok((ack.clone(), remote_addr()))));
+        assert_eq!(connection.stats.rx_ack, 1);
+
+        // without the agreement they're dropped
+        connection.settings.encrypt_control = false;
+        connection.handle_input(now, Input::Packet(Ok((ack, remote_addr()))));
+        assert_eq!(connection.stats.rx_ack, 1);
+    }
+
+    #[test]
+    fn memory_usage() {
+        let start = Instant::now();
+        let mut connection = DuplexConnection::new(new_connection(start));
+
+        let idle = connection.memory_usage();
+        assert!(idle.state >= size_of::<DuplexConnection>());
+        // the receive buffer is allocated up front
+        assert!(idle.receive_buffer >= 1024 * size_of::<DataPacket>());
+
+        for _ in 0..100 {
+            connection.handle_input(
+                start,
+                Input::Data(Some((start, Bytes::from(vec![0; 1000])))),
+            );
+        }
+        let busy = connection.memory_usage();
+        assert!(busy.send_buffer >= idle.send_buffer + 100 * 1000);
+        assert_eq!(busy.receive_buffer, idle.receive_buffer);
+        assert_eq!(
+            busy.total(),
+            busy.state + busy.send_buffer + busy.receive_buffer + busy.loss_lists + busy.history
+        );
+
+        connection.update_statistics(start);
+        assert_eq!(connection.stats.memory, busy);
+    }
+
+    #[test]
+    fn rogue_ack_and_nak() {
+        let start = Instant::now();
+        let mut connection = DuplexConnection::new(new_connection(start));
+
+        connection.handle_input(start, Input::Data(Some((start, Bytes::from("hello")))));
+        let now = start + SND;
+        assert_matches!(
+            connection.handle_input(now, Input::Timer),
+            SendPacket((Data(_), _))
+        );
+
+        let control = |control_type| {
+            Control(ControlPacket {
+                timestamp: TimeStamp::MIN,
+                dest_sockid: local_sockid(),
+                control_type,
+            })
+        };
+        let statistics = |rtt| AckStatistics {
+            rtt,
+            buffer_available: 10000,
+            packet_receive_rate: None,
+            estimated_link_capacity: None,
+            data_receive_rate: None,
+        };
+        let rogue = [
+            // for a packet that wasn't sent
+            Ack(Acknowledgement::Full(
+                SeqNumber(2),
+                statistics(Rtt::default()),
+                FullAckSeqNumber::INITIAL,
+            )),
+            // with a negative round trip
+            Ack(Acknowledgement::Full(
+                SeqNumber(1),
+                statistics(Rtt::new(TimeSpan::from_micros(-10), TimeSpan::ZERO)),
+                FullAckSeqNumber::INITIAL,
+            )),
+            Nak((SeqNumber(0)..SeqNumber(100_000)).into()),
+        ];
+        for control_type in rogue {
+            assert_matches!(
+                connection.handle_input(
+                    now,
+                    Input::Packet(Ok((control(control_type), remote_addr())))
+                ),
+                WaitForData(_)
+            );
+        }
+        assert_eq!(connection.stats.rx_ack_ignored, 2);
+        assert_eq!(connection.stats.rx_nak_ignored, 1);
+        assert_eq!(connection.stats.tx_loss_data, 0);
+
+        // the connection carries on as if they never came
+        let ack = Ack(Acknowledgement::Full(
+            SeqNumber(1),
+            statistics(Rtt::default()),
+            FullAckSeqNumber::INITIAL,
+        ));
+        assert_matches!(
+            connection.handle_input(now, Input::Packet(Ok((control(ack), remote_addr())))),
+            SendPacket((
+                Control(ControlPacket {
+                    control_type: Ack2(_),
+                    ..
+                }),
+                _
+            ))
+        );
+        assert_eq!(connection.stats.rx_ack_ignored, 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn reserved_control_extension() {
+        let start = Instant::now();
+        let mut connection = DuplexConnection::new(new_connection(start));
+        connection.send_control_extension(start, 3, Bytes::new());
+    }
+
+    #[cfg(feature = "packet_telemetry")]
+    #[test]
+    fn packet_hook() {
+        use std::sync::{Arc, Mutex};
+
+        use telemetry::{PacketDirection, PacketKind};
+
+        let start = Instant::now();
+        let mut connection = DuplexConnection::new(new_connection(start));
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        connection.set_packet_hook({
+            let events = events.clone();
+            move |event| {
+                events.lock().unwrap().push((
+                    event.direction,
+                    event.kind(),
+                    event.seq_number(),
+                    event.size(),
+                ))
+            }
+        });
+
+        let mut now = start;
+        connection.handle_input(now, Input::Data(Some((start, Bytes::from("hello")))));
+        now += SND;
+        assert_matches!(
+            connection.handle_input(now, Input::Timer),
+            SendPacket((Data(_), _))
+        );
+
+        let ack2 = Control(ControlPacket {
+            timestamp: TimeStamp::MIN,
+            dest_sockid: local_sockid(),
+            control_type: Ack2(FullAckSeqNumber::INITIAL),
+        });
+        let ack2_size = ack2.wire_size();
+        connection.handle_input(now, Input::Packet(Ok((ack2, remote_addr()))));
+
+        assert_eq!(
+            events.lock().unwrap()[..],
+            [
+                (
+                    PacketDirection::Sent,
+                    PacketKind::Data,
+                    Some(SeqNumber(0)),
+                    5 + Packet::HEADER_SIZE.0 as usize
+                ),
+                (PacketDirection::Received, PacketKind::Ack2, None, ack2_size),
+            ]
+        );
+    }
+
+    #[test]
+    fn info() {
+        let mut settings = new_connection(Instant::now()).settings;
+        settings.recv_tsbpd_latency = Duration::from_millis(200);
+        settings.stream_id = Some("live/a".into());
+
+        let info = settings.info();
+        assert_eq!(info.send_latency, TSBPD);
+        assert_eq!(info.recv_latency, Duration::from_millis(200));
+        assert_eq!(info.key_size, KeySize::Unspecified);
+        assert_eq!(info.max_payload_size, PacketSize(1316));
+        assert_eq!(info.stream_id.as_deref(), Some("live/a"));
+        assert_eq!(
+            info.to_string(),
+            format!(
+                "127.0.0.1:2223 SRT#00000002 -> SRT#00000002, stream id \"live/a\", latency {TSBPD:?} sending and 200ms receiving, peer version {}, unencrypted, payload up to 1316 bytes, flow window 8192 packets",
+                SrtVersion::CURRENT
+            )
+        );
+    }
 }
@@ -0,0 +1,30 @@
+use std::{fmt, net::SocketAddr};
+
+use crate::protocol::time::Instant;
+
+/// Called with the peer's old and new address whenever the connection follows it to another
+/// one, see [`peer_address_policy`](crate::options::Session::peer_address_policy)
+pub type RebindHandler = Box<dyn FnMut(Instant, SocketAddr, SocketAddr) + Send>;
+
+#[derive(Default)]
+pub(crate) struct Rebinds(Option<RebindHandler>);
+
+impl Rebinds {
+    pub fn set_handler(&mut self, handler: RebindHandler) {
+        self.0 = Some(handler);
+    }
+
+    pub fn on_rebind(&mut self, now: Instant, old: SocketAddr, new: SocketAddr) {
+        if let Some(handler) = &mut self.0 {
+            handler(now, old, new);
+        }
+    }
+}
+
+impl fmt::Debug for Rebinds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Rebinds")
+            .field(&self.0.as_ref().map(|_| "handler"))
+            .finish()
+    }
+}
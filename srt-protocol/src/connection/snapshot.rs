@@ -0,0 +1,689 @@
+use std::{
+    convert::TryFrom,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use thiserror::Error;
+
+use crate::{
+    options::*,
+    packet::*,
+    protocol::{encryption::EncryptionSnapshot, handshake::Handshake, time::Instant},
+    settings::{
+        CipherSettings, KeyMaterialError, KeyMaterialRefreshSettings, KeySettings, KeySource, Salt,
+        SharedKeyProvider, StreamEncryptionKeys,
+    },
+};
+
+use super::ConnectionSettings;
+
+/// The state a connection needs to carry on in another process, so a live connection can be
+/// handed over during an upgrade without the peer noticing. Take it with
+/// [`DuplexConnection::snapshot`](super::DuplexConnection::snapshot), pass it on with
+/// [`serialize`](Self::serialize) and [`parse`](Self::parse), and pick the connection up again
+/// with [`DuplexConnection::restore`](super::DuplexConnection::restore).
+///
+/// It holds the negotiated settings, where both directions are at in their sequence and message
+/// numbers, the packets in the send and receive buffers and the stream keys in use. Estimates
+/// like the RTT and the bandwidth start over, as do the statistics.
+///
+/// The serialized snapshot contains the passphrase and the stream keys, so it has to be passed on
+/// as carefully as they are. With a [`KeyProvider`](crate::settings::KeyProvider) it only holds
+/// the stream keys, wrapped with the provider's key, and the provider has to be passed to
+/// [`parse_with_key_provider`](Self::parse_with_key_provider) again.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConnectionSnapshot {
+    pub settings: ConnectionSettings,
+    pub handshake: Handshake,
+    pub(crate) sender: SenderSnapshot,
+    pub(crate) receiver: ReceiverSnapshot,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct SenderSnapshot {
+    pub next_sequence_number: SeqNumber,
+    pub next_message_number: MsgNumber,
+    // encrypted, oldest first
+    pub buffer: Vec<DataPacket>,
+    // the first packet of the buffer that wasn't sent yet
+    pub next_send: SeqNumber,
+    pub keys: Option<EncryptionSnapshot>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct ReceiverSnapshot {
+    pub first_sequence_number: SeqNumber,
+    // decrypted, received but not released yet
+    pub buffer: Vec<DataPacket>,
+    pub keys: Option<StreamEncryptionKeys>,
+}
+
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum SnapshotError {
+    #[error("Snapshot is truncated")]
+    NotEnoughData,
+    #[error("Unsupported snapshot version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Invalid {0} in snapshot")]
+    InvalidValue(&'static str),
+    #[error("Invalid packet in snapshot: {0}")]
+    InvalidPacket(PacketParseError),
+    #[error("Invalid stream keys in snapshot: {0:?}")]
+    InvalidKeys(KeyMaterialError),
+    #[error("The snapshot's keys come from a key provider, which has to be passed to parse it")]
+    KeyProviderRequired,
+}
+
+impl ConnectionSnapshot {
+    const VERSION: u32 = 16;
+
+    /// The socket start time is written as the age of the socket at `now`
+    pub fn serialize(&self, now: Instant, into: &mut impl BufMut) {
+        into.put_u32(Self::VERSION);
+        self.serialize_settings(now, into);
+
+        match &self.handshake {
+            Handshake::Connector => into.put_u8(0),
+            Handshake::Listener(control) => {
+                into.put_u8(1);
+                put_control(control, into);
+            }
+            Handshake::Rendezvous(None) => into.put_u8(2),
+            Handshake::Rendezvous(Some(control)) => {
+                into.put_u8(3);
+                put_control(control, into);
+            }
+        }
+
+        let key_settings = self.settings.cipher.as_ref().map(|c| &c.key_settings);
+        let sender = &self.sender;
+        into.put_u32(sender.next_sequence_number.as_raw());
+        into.put_u32(sender.next_message_number.as_raw());
+        into.put_u32(sender.next_send.as_raw());
+        put_packets(&sender.buffer, into);
+        match (&sender.keys, key_settings) {
+            (Some(keys), Some(key_settings)) => {
+                into.put_u8(1);
+                put_keys(&keys.stream_keys, key_settings, into);
+                into.put_u8(keys.active_sek as u8);
+                into.put_u64(keys.packets_until_key_switch as u64);
+                into.put_u64(keys.packets_with_active_sek as u64);
+                put_bool(keys.refreshing, into);
+                into.put_u64(keys.control_nonce);
+            }
+            _ => into.put_u8(0),
+        }
+
+        let receiver = &self.receiver;
+        into.put_u32(receiver.first_sequence_number.as_raw());
+        put_packets(&receiver.buffer, into);
+        match (&receiver.keys, key_settings) {
+            (Some(keys), Some(key_settings)) => {
+                into.put_u8(1);
+                put_keys(keys, key_settings, into);
+            }
+            _ => into.put_u8(0),
+        }
+    }
+
+    /// The socket start time is the age of the socket before `now`
+    pub fn parse(now: Instant, buf: &mut impl Buf) -> Result<Self, SnapshotError> {
+        Self::parse_with_key_provider(now, buf, None)
+    }
+
+    /// Parse the snapshot of a connection whose keys come from `key_provider`
+    pub fn parse_with_key_provider(
+        now: Instant,
+        buf: &mut impl Buf,
+        key_provider: Option<SharedKeyProvider>,
+    ) -> Result<Self, SnapshotError> {
+        let version = get_u32(buf)?;
+        if version != Self::VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let settings = Self::parse_settings(now, buf, key_provider)?;
+
+        let is_ipv6 = settings.remote.is_ipv6();
+        let handshake = match get_u8(buf)? {
+            0 => Handshake::Connector,
+            1 => Handshake::Listener(get_control(is_ipv6, buf)?),
+            2 => Handshake::Rendezvous(None),
+            3 => Handshake::Rendezvous(Some(get_control(is_ipv6, buf)?)),
+            _ => return Err(SnapshotError::InvalidValue("handshake")),
+        };
+
+        let key_settings = settings.cipher.as_ref().map(|c| &c.key_settings);
+        let next_sequence_number = get_seq_number(buf)?;
+        let next_message_number = MsgNumber::new_truncate(get_u32(buf)?);
+        let next_send = get_seq_number(buf)?;
+        let buffer = get_packets(buf)?;
+        let keys = match (get_u8(buf)?, key_settings) {
+            (0, _) => None,
+            (1, Some(key_settings)) => Some(EncryptionSnapshot {
+                stream_keys: get_keys(key_settings, buf)?,
+                active_sek: DataEncryption::try_from(get_u8(buf)?)
+                    .map_err(SnapshotError::InvalidPacket)?,
+                packets_until_key_switch: usize::try_from(get_u64(buf)?)
+                    .map_err(|_| SnapshotError::InvalidValue("key switch"))?,
+                packets_with_active_sek: usize::try_from(get_u64(buf)?)
+                    .map_err(|_| SnapshotError::InvalidValue("key usage"))?,
+                refreshing: get_bool(buf)?,
+                control_nonce: get_u64(buf)?,
+            }),
+            _ => return Err(SnapshotError::InvalidValue("sender keys")),
+        };
+        let sender = SenderSnapshot {
+            next_sequence_number,
+            next_message_number,
+            buffer,
+            next_send,
+            keys,
+        };
+
+        let first_sequence_number = get_seq_number(buf)?;
+        let buffer = get_packets(buf)?;
+        let keys = match (get_u8(buf)?, key_settings) {
+            (0, _) => None,
+            (1, Some(key_settings)) => Some(get_keys(key_settings, buf)?),
+            _ => return Err(SnapshotError::InvalidValue("receiver keys")),
+        };
+        let receiver = ReceiverSnapshot {
+            first_sequence_number,
+            buffer,
+            keys,
+        };
+
+        Ok(Self {
+            settings,
+            handshake,
+            sender,
+            receiver,
+        })
+    }
+
+    fn serialize_settings(&self, now: Instant, into: &mut impl BufMut) {
+        let settings = &self.settings;
+        match settings.remote.ip() {
+            IpAddr::V4(ip) => {
+                into.put_u8(4);
+                into.put_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                into.put_u8(6);
+                into.put_slice(&ip.octets());
+            }
+        }
+        into.put_u16(settings.remote.port());
+        into.put_u32(settings.remote_sockid.0);
+        into.put_u32(settings.local_sockid.0);
+        put_duration(
+            now.saturating_duration_since(settings.socket_start_time),
+            into,
+        );
+        put_duration(settings.rtt, into);
+        into.put_u32(settings.init_seq_num.as_raw());
+        into.put_u64(settings.max_packet_size.0);
+        into.put_u64(settings.max_flow_size.0);
+        put_duration(settings.send_tsbpd_latency, into);
+        put_duration(settings.recv_tsbpd_latency, into);
+        put_duration(settings.peer_idle_timeout, into);
+        into.put_u64(settings.recv_buffer_size.0);
+        into.put_u64(settings.send_buffer_size.0);
+        match settings.send_buffer_bytes {
+            Some(bytes) => {
+                into.put_u8(1);
+                into.put_u64(bytes.0);
+            }
+            None => into.put_u8(0),
+        }
+        match settings.send_buffer_time {
+            Some(time) => {
+                into.put_u8(1);
+                put_duration(time, into);
+            }
+            None => into.put_u8(0),
+        }
+        into.put_u8(match settings.send_buffer_policy {
+            SendBufferPolicy::DropOldest => 0,
+            SendBufferPolicy::Block => 1,
+            SendBufferPolicy::Error => 2,
+        });
+        match &settings.cipher {
+            Some(cipher) => {
+                into.put_u8(1);
+                into.put_u8(cipher.key_settings.key_size.as_raw());
+                match &cipher.key_settings.key_source {
+                    KeySource::Passphrase(passphrase) => {
+                        into.put_u8(0);
+                        put_slice(passphrase.as_bytes(), into);
+                    }
+                    KeySource::Provider(_) => into.put_u8(1),
+                    KeySource::DerivedPassphrase(passphrase, derivation) => {
+                        into.put_u8(2);
+                        put_slice(passphrase.as_bytes(), into);
+                        into.put_u32(derivation.iterations);
+                        into.put_u8(derivation.salt_length as u8);
+                        match &derivation.initial_salt {
+                            Some(salt) => {
+                                into.put_u8(1);
+                                into.put_slice(salt.as_slice());
+                            }
+                            None => into.put_u8(0),
+                        }
+                    }
+                }
+                into.put_u64(cipher.key_refresh.period() as u64);
+                into.put_u64(cipher.key_refresh.pre_announcement_period() as u64);
+                put_keys(&cipher.stream_keys, &cipher.key_settings, into);
+            }
+            None => into.put_u8(0),
+        }
+        match &settings.stream_id {
+            Some(stream_id) => {
+                into.put_u8(1);
+                put_slice(stream_id.as_bytes(), into);
+            }
+            None => into.put_u8(0),
+        }
+        match &settings.bandwidth {
+            LiveBandwidthMode::Max(rate) => {
+                into.put_u8(0);
+                into.put_u64(rate.0);
+            }
+            LiveBandwidthMode::Input { rate, overhead } => {
+                into.put_u8(1);
+                into.put_u64(rate.0);
+                into.put_u64(overhead.0);
+            }
+            LiveBandwidthMode::Estimated { overhead, expected } => {
+                into.put_u8(2);
+                into.put_u64(overhead.0);
+                into.put_u64(expected.0);
+            }
+            LiveBandwidthMode::Unlimited => into.put_u8(3),
+        }
+        put_duration(settings.statistics_interval, into);
+        into.put_u8(match settings.ack2_mode {
+            Ack2Mode::EveryFullAck => 0,
+            Ack2Mode::Throttled => 1,
+        });
+        put_bool(settings.lite_ack_rtt_sampling, into);
+        into.put_u64(settings.max_burst.0);
+        match settings.duplicate_interval {
+            Some(interval) => {
+                into.put_u8(1);
+                put_duration(interval, into);
+            }
+            None => into.put_u8(0),
+        }
+        match settings.fast_retransmit {
+            Some(acks) => {
+                into.put_u8(1);
+                into.put_u32(acks);
+            }
+            None => into.put_u8(0),
+        }
+        into.put_u64(settings.sequence_restart_window.0);
+        put_bool(settings.skip_gaps, into);
+        put_bool(settings.immediate_nak, into);
+        into.put_u64(settings.light_ack_interval.0);
+        match settings.max_delivery_rate {
+            Some(rate) => {
+                into.put_u8(1);
+                into.put_u64(rate.0);
+            }
+            None => into.put_u8(0),
+        }
+        put_bool(settings.half_close, into);
+        put_bool(settings.limit_payload_size, into);
+        put_duration(settings.keepalive_interval, into);
+        put_bool(settings.adaptive_keepalive, into);
+        put_bool(settings.message_tags, into);
+        put_bool(settings.ecn, into);
+        into.put_u8(match settings.peer_address_policy {
+            PeerAddressPolicy::Rebind => 0,
+            PeerAddressPolicy::RebindPort => 1,
+            PeerAddressPolicy::Reject => 2,
+        });
+        put_bool(settings.encrypt_control, into);
+        into.put_u32(settings.peer_version.to_u32());
+        into.put_u32(settings.peer_flags.bits());
+        into.put_u32(settings.local_flags.bits());
+    }
+
+    fn parse_settings(
+        now: Instant,
+        buf: &mut impl Buf,
+        key_provider: Option<SharedKeyProvider>,
+    ) -> Result<ConnectionSettings, SnapshotError> {
+        let ip = match get_u8(buf)? {
+            4 => IpAddr::V4(Ipv4Addr::from(
+                <[u8; 4]>::try_from(&get_bytes(4, buf)?[..]).unwrap(),
+            )),
+            6 => IpAddr::V6(Ipv6Addr::from(
+                <[u8; 16]>::try_from(&get_bytes(16, buf)?[..]).unwrap(),
+            )),
+            _ => return Err(SnapshotError::InvalidValue("remote address")),
+        };
+        let remote = SocketAddr::new(ip, get_u16(buf)?);
+        let remote_sockid = SocketId(get_u32(buf)?);
+        let local_sockid = SocketId(get_u32(buf)?);
+        let age = get_duration(buf)?;
+        let socket_start_time = now
+            .checked_sub(age)
+            .ok_or(SnapshotError::InvalidValue("socket age"))?;
+        let rtt = get_duration(buf)?;
+        let init_seq_num = get_seq_number(buf)?;
+        let max_packet_size = PacketSize(get_u64(buf)?);
+        let max_flow_size = PacketCount(get_u64(buf)?);
+        let send_tsbpd_latency = get_duration(buf)?;
+        let recv_tsbpd_latency = get_duration(buf)?;
+        let peer_idle_timeout = get_duration(buf)?;
+        let recv_buffer_size = PacketCount(get_u64(buf)?);
+        let send_buffer_size = PacketCount(get_u64(buf)?);
+        let send_buffer_bytes = match get_u8(buf)? {
+            0 => None,
+            1 => Some(ByteCount(get_u64(buf)?)),
+            _ => return Err(SnapshotError::InvalidValue("send buffer bytes")),
+        };
+        let send_buffer_time = match get_u8(buf)? {
+            0 => None,
+            1 => Some(get_duration(buf)?),
+            _ => return Err(SnapshotError::InvalidValue("send buffer time")),
+        };
+        let send_buffer_policy = match get_u8(buf)? {
+            0 => SendBufferPolicy::DropOldest,
+            1 => SendBufferPolicy::Block,
+            2 => SendBufferPolicy::Error,
+            _ => return Err(SnapshotError::InvalidValue("send buffer policy")),
+        };
+        let cipher = match get_u8(buf)? {
+            0 => None,
+            1 => {
+                let key_size = KeySize::from_raw(get_u8(buf)?.into())
+                    .ok_or(SnapshotError::InvalidValue("key size"))?;
+                let key_source = match get_u8(buf)? {
+                    0 => KeySource::Passphrase(get_passphrase(buf)?),
+                    1 => {
+                        KeySource::Provider(key_provider.ok_or(SnapshotError::KeyProviderRequired)?)
+                    }
+                    2 => {
+                        let passphrase = get_passphrase(buf)?;
+                        let iterations = get_u32(buf)?;
+                        let salt_length = get_u8(buf)?.into();
+                        let initial_salt = match get_u8(buf)? {
+                            0 => None,
+                            1 => Some(
+                                Salt::try_from(&get_bytes(16, buf)?)
+                                    .map_err(|_| SnapshotError::InvalidValue("salt"))?,
+                            ),
+                            _ => return Err(SnapshotError::InvalidValue("initial salt")),
+                        };
+                        KeySource::DerivedPassphrase(
+                            passphrase,
+                            KeyDerivation {
+                                iterations,
+                                salt_length,
+                                initial_salt,
+                            },
+                        )
+                    }
+                    _ => return Err(SnapshotError::InvalidValue("key source")),
+                };
+                let key_settings = KeySettings {
+                    key_size,
+                    key_source,
+                };
+                let period = get_u64(buf)? as usize;
+                let pre_announcement_period = get_u64(buf)? as usize;
+                let key_refresh = KeyMaterialRefreshSettings::new(period, pre_announcement_period)
+                    .map_err(|_| SnapshotError::InvalidValue("key refresh"))?;
+                let stream_keys = get_keys(&key_settings, buf)?;
+                Some(CipherSettings {
+                    key_settings,
+                    key_refresh,
+                    stream_keys,
+                })
+            }
+            _ => return Err(SnapshotError::InvalidValue("cipher")),
+        };
+        let stream_id = match get_u8(buf)? {
+            0 => None,
+            1 => Some(
+                String::from_utf8(get_slice(buf)?.to_vec())
+                    .map_err(|_| SnapshotError::InvalidValue("stream id"))?,
+            ),
+            _ => return Err(SnapshotError::InvalidValue("stream id")),
+        };
+        let bandwidth = match get_u8(buf)? {
+            0 => LiveBandwidthMode::Max(DataRate(get_u64(buf)?)),
+            1 => LiveBandwidthMode::Input {
+                rate: DataRate(get_u64(buf)?),
+                overhead: Percent(get_u64(buf)?),
+            },
+            2 => LiveBandwidthMode::Estimated {
+                overhead: Percent(get_u64(buf)?),
+                expected: DataRate(get_u64(buf)?),
+            },
+            3 => LiveBandwidthMode::Unlimited,
+            _ => return Err(SnapshotError::InvalidValue("bandwidth")),
+        };
+        let statistics_interval = get_duration(buf)?;
+        let ack2_mode = match get_u8(buf)? {
+            0 => Ack2Mode::EveryFullAck,
+            1 => Ack2Mode::Throttled,
+            _ => return Err(SnapshotError::InvalidValue("ACK2 mode")),
+        };
+        let lite_ack_rtt_sampling = get_bool(buf)?;
+        let max_burst = PacketCount(get_u64(buf)?);
+        let duplicate_interval = match get_u8(buf)? {
+            0 => None,
+            1 => Some(get_duration(buf)?),
+            _ => return Err(SnapshotError::InvalidValue("duplicate interval")),
+        };
+        let fast_retransmit = match get_u8(buf)? {
+            0 => None,
+            1 => Some(get_u32(buf)?),
+            _ => return Err(SnapshotError::InvalidValue("fast retransmit")),
+        };
+        let sequence_restart_window = PacketCount(get_u64(buf)?);
+        let skip_gaps = get_bool(buf)?;
+        let immediate_nak = get_bool(buf)?;
+        let light_ack_interval = PacketCount(get_u64(buf)?);
+        let max_delivery_rate = match get_u8(buf)? {
+            0 => None,
+            1 => Some(DataRate(get_u64(buf)?)),
+            _ => return Err(SnapshotError::InvalidValue("delivery rate")),
+        };
+        let half_close = get_bool(buf)?;
+        let limit_payload_size = get_bool(buf)?;
+        let keepalive_interval = get_duration(buf)?;
+        let adaptive_keepalive = get_bool(buf)?;
+        let message_tags = get_bool(buf)?;
+        let ecn = get_bool(buf)?;
+        let peer_address_policy = match get_u8(buf)? {
+            0 => PeerAddressPolicy::Rebind,
+            1 => PeerAddressPolicy::RebindPort,
+            2 => PeerAddressPolicy::Reject,
+            _ => return Err(SnapshotError::InvalidValue("peer address policy")),
+        };
+        let encrypt_control = get_bool(buf)?;
+        let peer_version = SrtVersion::parse(get_u32(buf)?);
+        let peer_flags = SrtShakeFlags::from_bits_truncate(get_u32(buf)?);
+        let local_flags = SrtShakeFlags::from_bits_truncate(get_u32(buf)?);
+
+        Ok(ConnectionSettings {
+            remote,
+            remote_sockid,
+            local_sockid,
+            socket_start_time,
+            rtt,
+            init_seq_num,
+            max_packet_size,
+            max_flow_size,
+            send_tsbpd_latency,
+            recv_tsbpd_latency,
+            peer_idle_timeout,
+            recv_buffer_size,
+            send_buffer_size,
+            send_buffer_bytes,
+            send_buffer_time,
+            send_buffer_policy,
+            cipher,
+            stream_id,
+            bandwidth,
+            statistics_interval,
+            ack2_mode,
+            lite_ack_rtt_sampling,
+            max_burst,
+            duplicate_interval,
+            fast_retransmit,
+            sequence_restart_window,
+            skip_gaps,
+            immediate_nak,
+            light_ack_interval,
+            max_delivery_rate,
+            half_close,
+            limit_payload_size,
+            keepalive_interval,
+            adaptive_keepalive,
+            message_tags,
+            ecn,
+            peer_address_policy,
+            encrypt_control,
+            peer_version,
+            peer_flags,
+            local_flags,
+        })
+    }
+}
+
+fn put_bool(value: bool, into: &mut impl BufMut) {
+    into.put_u8(value.into());
+}
+
+fn put_duration(duration: Duration, into: &mut impl BufMut) {
+    into.put_u64(u64::try_from(duration.as_micros()).unwrap_or(u64::MAX));
+}
+
+fn put_slice(slice: &[u8], into: &mut impl BufMut) {
+    into.put_u32(u32::try_from(slice.len()).unwrap());
+    into.put_slice(slice);
+}
+
+// the wire formats, with the length in front
+fn put_packets(packets: &[DataPacket], into: &mut impl BufMut) {
+    into.put_u32(u32::try_from(packets.len()).unwrap());
+    let mut serialized = BytesMut::new();
+    for packet in packets {
+        serialized.clear();
+        packet.serialize(&mut serialized);
+        put_slice(&serialized, into);
+    }
+}
+
+fn put_control(control: &ControlTypes, into: &mut impl BufMut) {
+    let mut serialized = BytesMut::new();
+    ControlPacket {
+        timestamp: TimeStamp::MIN,
+        dest_sockid: SocketId(0),
+        control_type: control.clone(),
+    }
+    .serialize(&mut serialized);
+    put_slice(&serialized, into);
+}
+
+// wrapped with the key encrypting key, like in a key material exchange
+fn put_keys(keys: &StreamEncryptionKeys, key_settings: &KeySettings, into: &mut impl BufMut) {
+    let mut serialized = BytesMut::new();
+    if let Some(key_material) = keys.wrap_with(key_settings) {
+        key_material.serialize(&mut serialized);
+    }
+    put_slice(&serialized, into);
+}
+
+fn get_u8(buf: &mut impl Buf) -> Result<u8, SnapshotError> {
+    ensure(1, buf)?;
+    Ok(buf.get_u8())
+}
+
+fn get_u16(buf: &mut impl Buf) -> Result<u16, SnapshotError> {
+    ensure(2, buf)?;
+    Ok(buf.get_u16())
+}
+
+fn get_u32(buf: &mut impl Buf) -> Result<u32, SnapshotError> {
+    ensure(4, buf)?;
+    Ok(buf.get_u32())
+}
+
+fn get_u64(buf: &mut impl Buf) -> Result<u64, SnapshotError> {
+    ensure(8, buf)?;
+    Ok(buf.get_u64())
+}
+
+fn get_bool(buf: &mut impl Buf) -> Result<bool, SnapshotError> {
+    match get_u8(buf)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(SnapshotError::InvalidValue("flag")),
+    }
+}
+
+fn get_duration(buf: &mut impl Buf) -> Result<Duration, SnapshotError> {
+    Ok(Duration::from_micros(get_u64(buf)?))
+}
+
+fn get_seq_number(buf: &mut impl Buf) -> Result<SeqNumber, SnapshotError> {
+    SeqNumber::new(get_u32(buf)?).map_err(|_| SnapshotError::InvalidValue("sequence number"))
+}
+
+fn get_bytes(len: usize, buf: &mut impl Buf) -> Result<Bytes, SnapshotError> {
+    ensure(len, buf)?;
+    Ok(buf.copy_to_bytes(len))
+}
+
+fn get_slice(buf: &mut impl Buf) -> Result<Bytes, SnapshotError> {
+    let len = get_u32(buf)? as usize;
+    get_bytes(len, buf)
+}
+
+fn get_passphrase(buf: &mut impl Buf) -> Result<Passphrase, SnapshotError> {
+    String::from_utf8(get_slice(buf)?.to_vec())
+        .ok()
+        .and_then(|p| Passphrase::try_from(p).ok())
+        .ok_or(SnapshotError::InvalidValue("passphrase"))
+}
+
+fn get_packets(buf: &mut impl Buf) -> Result<Vec<DataPacket>, SnapshotError> {
+    let count = get_u32(buf)?;
+    (0..count)
+        .map(|_| DataPacket::parse(&mut get_slice(buf)?).map_err(SnapshotError::InvalidPacket))
+        .collect()
+}
+
+fn get_control(is_ipv6: bool, buf: &mut impl Buf) -> Result<ControlTypes, SnapshotError> {
+    ControlPacket::parse(&mut get_slice(buf)?, is_ipv6)
+        .map(|packet| packet.control_type)
+        .map_err(SnapshotError::InvalidPacket)
+}
+
+fn get_keys(
+    key_settings: &KeySettings,
+    buf: &mut impl Buf,
+) -> Result<StreamEncryptionKeys, SnapshotError> {
+    let key_material =
+        KeyingMaterialMessage::parse(&mut get_slice(buf)?).map_err(SnapshotError::InvalidPacket)?;
+    StreamEncryptionKeys::unwrap_from(key_settings, &key_material)
+        .map_err(SnapshotError::InvalidKeys)
+}
+
+fn ensure(len: usize, buf: &impl Buf) -> Result<(), SnapshotError> {
+    if buf.remaining() < len {
+        Err(SnapshotError::NotEnoughData)
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use std::{fmt, time::Duration};
+
+use crate::protocol::time::Instant;
+
+/// A change of the state of the stream from the peer, see
+/// [`set_stall_handler`](super::DuplexConnection::set_stall_handler)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StallEvent {
+    /// No new DATA packet arrived for the threshold, the last one arrived at `since`, or the
+    /// connection started then if none did
+    Stalled { since: Instant },
+
+    /// A new DATA packet arrived after a stall, this long after the one before it
+    Resumed { stalled_for: Duration },
+}
+
+pub type StallHandler = Box<dyn FnMut(Instant, &StallEvent) + Send>;
+
+// retransmissions don't count, they only fill the gaps of a stream that has already moved on
+pub(crate) struct StallWatch {
+    last_data: Instant,
+    stalled: bool,
+    threshold: Duration,
+    handler: Option<StallHandler>,
+}
+
+impl StallWatch {
+    pub fn new(start: Instant) -> Self {
+        Self {
+            last_data: start,
+            stalled: false,
+            threshold: Duration::MAX,
+            handler: None,
+        }
+    }
+
+    pub fn set_handler(&mut self, threshold: Duration, handler: StallHandler) {
+        self.threshold = threshold;
+        self.handler = Some(handler);
+    }
+
+    pub fn on_data(&mut self, now: Instant) -> Option<StallEvent> {
+        let stalled_for = now.saturating_duration_since(self.last_data);
+        self.last_data = now;
+        if !self.stalled {
+            return None;
+        }
+        self.stalled = false;
+        self.notify(now, StallEvent::Resumed { stalled_for })
+    }
+
+    pub fn check(&mut self, now: Instant) -> Option<StallEvent> {
+        if self.stalled || now < self.deadline()? {
+            return None;
+        }
+        self.stalled = true;
+        self.notify(
+            now,
+            StallEvent::Stalled {
+                since: self.last_data,
+            },
+        )
+    }
+
+    // when the stream stalls unless more data arrives, None while it's stalled already
+    pub fn deadline(&self) -> Option<Instant> {
+        if self.handler.is_none() || self.stalled {
+            return None;
+        }
+        self.last_data.checked_add(self.threshold)
+    }
+
+    fn notify(&mut self, now: Instant, event: StallEvent) -> Option<StallEvent> {
+        let handler = self.handler.as_mut()?;
+        handler(now, &event);
+        Some(event)
+    }
+}
+
+impl fmt::Debug for StallWatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StallWatch")
+            .field("last_data", &self.last_data)
+            .field("stalled", &self.stalled)
+            .field("threshold", &self.threshold)
+            .field("handler", &self.handler.as_ref().map(|_| "handler"))
+            .finish()
+    }
+}
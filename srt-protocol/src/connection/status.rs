@@ -1,8 +1,8 @@
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use log::info;
 
-use crate::settings::SocketId;
+use crate::{protocol::time::Instant, settings::SocketId};
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 enum Status {
@@ -17,14 +17,16 @@ pub struct ConnectionStatus {
     connection: Status,
     sender: Status,
     receiver: Status,
+    half_close: bool,
 }
 
 impl ConnectionStatus {
-    pub fn new(flush_timeout: Duration) -> Self {
+    pub fn new(flush_timeout: Duration, half_close: bool) -> Self {
         Self {
             connection: Status::Open(flush_timeout),
             receiver: Status::Open(flush_timeout),
             sender: Status::Open(flush_timeout),
+            half_close,
         }
     }
 
@@ -36,6 +38,10 @@ impl ConnectionStatus {
         matches!(self.connection, Status::Closed)
     }
 
+    pub fn is_receiver_closed(&self) -> bool {
+        matches!(self.receiver, Status::Closed)
+    }
+
     pub fn should_drain_send_buffer(&self) -> bool {
         use Status::*;
         matches!(self.sender, Shutdown(_) | Drain(_))
@@ -51,20 +57,45 @@ impl ConnectionStatus {
 
     pub fn on_socket_closed(&mut self, now: Instant) {
         use Status::*;
-        if let Open(timeout) = self.receiver {
-            info!("socket closed, receiver is draining");
-            self.receiver = Drain(now + timeout);
+        // the peer is gone, so a half closed connection can't keep sending either
+        self.half_close = false;
+        match self.receiver {
+            Open(timeout) => {
+                info!("socket closed, receiver is draining");
+                self.receiver = Drain(now + timeout);
+            }
+            Closed => {
+                info!("socket closed, connection is closed");
+                self.connection = Closed;
+            }
+            _ => {}
         }
     }
 
     pub fn on_peer_idle_timeout(&mut self, now: Instant) {
         use Status::*;
-        if let Open(timeout) = self.receiver {
-            info!("peer idle timeout, receiver is draining");
-            self.receiver = Drain(now + timeout);
+        self.half_close = false;
+        match self.receiver {
+            Open(timeout) => {
+                info!("peer idle timeout, receiver is draining");
+                self.receiver = Drain(now + timeout);
+            }
+            Closed => {
+                info!("peer idle timeout, connection is closed");
+                self.connection = Closed;
+            }
+            _ => {}
         }
     }
 
+    pub fn on_abort(&mut self) {
+        use Status::*;
+        info!("connection aborted");
+        self.sender = Closed;
+        self.receiver = Closed;
+        self.connection = Closed;
+    }
+
     pub fn handle_shutdown_packet(&mut self, now: Instant, log_sockid: SocketId) {
         use Status::*;
         if let Open(timeout) = self.receiver {
@@ -94,7 +125,12 @@ impl ConnectionStatus {
             }
             _ => false,
         };
-        if matches!(self.sender, Closed) && receive_buffer_flushed && output_empty {
+        let receiver_done = if self.half_close {
+            matches!(self.receiver, Closed)
+        } else {
+            receive_buffer_flushed
+        };
+        if matches!(self.sender, Closed) && receiver_done && output_empty {
             info!("sender closed and receiver flushed, socket is closed");
             self.connection = Closed;
         }
@@ -111,14 +147,22 @@ impl ConnectionStatus {
         match self.receiver {
             Shutdown(_) | Drain(_) if receive_buffer_flushed => {
                 self.receiver = Closed;
-                self.connection = Closed;
-                info!("{log_sockid:?} reciever closed and flushed, connection is closed");
+                if self.half_close && !matches!(self.sender, Closed) {
+                    info!("{log_sockid:?} reciever closed and flushed, sender is still open");
+                } else {
+                    self.connection = Closed;
+                    info!("{log_sockid:?} reciever closed and flushed, connection is closed");
+                }
                 false
             }
             Shutdown(timeout) | Drain(timeout) if now > timeout => {
                 self.receiver = Closed;
-                self.connection = Closed;
-                info!("{log_sockid:?} reciever timed out flushing ({:?} too late), connection is closed", now - timeout);
+                if self.half_close && !matches!(self.sender, Closed) {
+                    info!("{log_sockid:?} reciever timed out flushing ({:?} too late), sender is still open", now - timeout);
+                } else {
+                    self.connection = Closed;
+                    info!("{log_sockid:?} reciever timed out flushing ({:?} too late), connection is closed", now - timeout);
+                }
                 true
             }
             _ => false,
@@ -133,7 +177,7 @@ mod tests {
     #[test]
     fn open_close() {
         let timeout = Duration::from_secs(10);
-        let mut status = ConnectionStatus::new(timeout);
+        let mut status = ConnectionStatus::new(timeout, false);
 
         assert!(status.is_open());
         assert!(!status.is_closed());
@@ -146,4 +190,73 @@ mod tests {
         assert!(!status.is_closed());
         assert!(!status.should_drain_send_buffer());
     }
+
+    #[test]
+    fn half_close() {
+        let timeout = Duration::from_secs(10);
+        let sockid = SocketId(1);
+        let mut status = ConnectionStatus::new(timeout, true);
+
+        // the peer finished sending, but this side can still send
+        let now = Instant::now();
+        status.handle_shutdown_packet(now, sockid);
+        assert!(!status.check_receive_close_timeout(now, true, sockid));
+        assert!(status.is_receiver_closed());
+        assert!(status.is_open());
+        assert!(!status.check_sender_shutdown(now, true, true, true));
+        assert!(status.is_open());
+
+        // once this side finishes sending too, the connection closes
+        status.on_data_stream_closed(now);
+        assert!(status.should_drain_send_buffer());
+        assert!(status.check_sender_shutdown(now, true, true, true));
+        assert!(status.is_open());
+        assert!(!status.check_sender_shutdown(now, true, true, true));
+        assert!(status.is_closed());
+    }
+
+    #[test]
+    fn half_close_sender_first() {
+        let timeout = Duration::from_secs(10);
+        let sockid = SocketId(1);
+        let mut status = ConnectionStatus::new(timeout, true);
+
+        // this side finished sending, but keeps receiving
+        let now = Instant::now();
+        status.on_data_stream_closed(now);
+        assert!(status.check_sender_shutdown(now, true, true, true));
+        assert!(!status.check_sender_shutdown(now, true, true, true));
+        assert!(status.is_open());
+        assert!(!status.is_receiver_closed());
+
+        status.handle_shutdown_packet(now, sockid);
+        assert!(!status.check_receive_close_timeout(now, true, sockid));
+        assert!(status.is_closed());
+    }
+
+    #[test]
+    fn half_close_peer_idle_timeout() {
+        let timeout = Duration::from_secs(10);
+        let sockid = SocketId(1);
+        let mut status = ConnectionStatus::new(timeout, true);
+
+        let now = Instant::now();
+        status.handle_shutdown_packet(now, sockid);
+        status.check_receive_close_timeout(now, true, sockid);
+        assert!(status.is_open());
+
+        // a sender with nobody left to send to is closed too
+        status.on_peer_idle_timeout(now);
+        assert!(status.is_closed());
+    }
+
+    #[test]
+    fn abort() {
+        let mut status = ConnectionStatus::new(Duration::from_secs(10), true);
+
+        status.on_data_stream_closed(Instant::now());
+        status.on_abort();
+        assert!(status.is_closed());
+        assert!(status.is_receiver_closed());
+    }
 }
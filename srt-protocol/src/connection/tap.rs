@@ -0,0 +1,41 @@
+use std::fmt;
+
+use crate::{
+    packet::{DataPacket, SeqNumber},
+    protocol::time::Instant,
+};
+
+/// Called with every data packet as soon as it and all the packets before it were received,
+/// recovered or given up on, rather than when its message is released at its TSBPD time, e.g. to
+/// record the stream as it arrives
+pub type TapHandler = Box<dyn FnMut(Instant, &DataPacket) + Send>;
+
+// the handler, and the first packet it wasn't called with yet
+#[derive(Default)]
+pub(crate) struct Tap(Option<(TapHandler, SeqNumber)>);
+
+impl Tap {
+    pub fn set_handler(&mut self, handler: TapHandler, next: SeqNumber) {
+        self.0 = Some((handler, next));
+    }
+
+    /// The first packet the handler wasn't called with yet, if there is a handler
+    pub fn next(&self) -> Option<SeqNumber> {
+        self.0.as_ref().map(|(_, next)| *next)
+    }
+
+    pub fn on_packet(&mut self, now: Instant, packet: &DataPacket) {
+        if let Some((handler, next)) = &mut self.0 {
+            handler(now, packet);
+            *next = packet.seq_number + 1;
+        }
+    }
+}
+
+impl fmt::Debug for Tap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Tap")
+            .field(&self.next().map(|next| ("handler", next)))
+            .finish()
+    }
+}
@@ -0,0 +1,102 @@
+use std::fmt;
+
+use crate::{packet::*, protocol::time::Instant};
+
+/// Whether a packet was sent to or received from the peer
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PacketDirection {
+    Sent,
+    Received,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PacketKind {
+    Data,
+    Handshake,
+    KeepAlive,
+    Ack,
+    Nak,
+    CongestionWarning,
+    Shutdown,
+    Ack2,
+    DropRequest,
+    PeerError,
+    Srt,
+}
+
+/// A packet passing through a [`DuplexConnection`](super::DuplexConnection), as reported to the
+/// hook installed with [`set_packet_hook`](super::DuplexConnection::set_packet_hook).
+///
+/// The packet is borrowed, so the hook only pays for what it copies out of it.
+#[derive(Debug, Clone, Copy)]
+pub struct PacketEvent<'a> {
+    pub direction: PacketDirection,
+    pub time: Instant,
+    pub packet: &'a Packet,
+}
+
+impl PacketEvent<'_> {
+    pub fn kind(&self) -> PacketKind {
+        use ControlTypes::*;
+        let control = match self.packet {
+            Packet::Data(_) => return PacketKind::Data,
+            Packet::Control(control) => &control.control_type,
+        };
+        match control {
+            Handshake(_) => PacketKind::Handshake,
+            KeepAlive => PacketKind::KeepAlive,
+            Ack(_) => PacketKind::Ack,
+            Nak(_) => PacketKind::Nak,
+            CongestionWarning => PacketKind::CongestionWarning,
+            Shutdown => PacketKind::Shutdown,
+            Ack2(_) => PacketKind::Ack2,
+            DropRequest { .. } => PacketKind::DropRequest,
+            PeerError(_) => PacketKind::PeerError,
+            Srt(_) => PacketKind::Srt,
+        }
+    }
+
+    /// Wire size of the packet, including IP+UDP+SRT headers
+    pub fn size(&self) -> usize {
+        self.packet.wire_size()
+    }
+
+    /// The sequence number of a data packet
+    pub fn seq_number(&self) -> Option<SeqNumber> {
+        self.packet.data().map(|data| data.seq_number)
+    }
+
+    /// The timestamp in the packet header
+    pub fn timestamp(&self) -> TimeStamp {
+        self.packet.timestamp()
+    }
+}
+
+pub type PacketHook = Box<dyn FnMut(&PacketEvent) + Send>;
+
+#[derive(Default)]
+pub(crate) struct PacketTelemetry(Option<PacketHook>);
+
+impl PacketTelemetry {
+    pub fn set_hook(&mut self, hook: PacketHook) {
+        self.0 = Some(hook);
+    }
+
+    pub fn on_packet(&mut self, direction: PacketDirection, time: Instant, packet: &Packet) {
+        if let Some(hook) = &mut self.0 {
+            hook(&PacketEvent {
+                direction,
+                time,
+                packet,
+            });
+        }
+    }
+}
+
+impl fmt::Debug for PacketTelemetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PacketTelemetry")
+            .field(&self.0.as_ref().map(|_| "hook"))
+            .finish()
+    }
+}
@@ -1,8 +1,26 @@
-pub mod access;
-pub mod connection;
-pub mod listener;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+// items that need std. Without it only the packet format is usable, options and protocol just
+// keep the few types it's built on (key sizes, versions, units and timestamps)
+macro_rules! cfg_std {
+    ($($item:item)*) => {
+        $(
+            #[cfg(feature = "std")]
+            $item
+        )*
+    };
+}
+
 pub mod options;
 pub mod packet;
 pub mod protocol;
-pub mod settings;
-pub mod statistics;
+
+cfg_std! {
+    pub mod access;
+    pub mod connection;
+    pub mod listener;
+    pub mod settings;
+    pub mod statistics;
+}
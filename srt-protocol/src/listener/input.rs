@@ -7,7 +7,9 @@ use crate::{
 
 use super::*;
 
-pub use crate::protocol::pending_connection::{AccessControlRequest, AccessControlResponse};
+pub use crate::protocol::pending_connection::{
+    AccessControlRequest, AccessControlResponse, HandshakeInfo,
+};
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub struct SessionId(pub SocketAddr);
@@ -19,6 +21,9 @@ pub enum Input {
     AccessResponse(Option<(SessionId, AccessControlResponse)>),
     Success(ResultOf),
     Failure(ResultOf),
+    /// The action was given up on instead of holding up the others, e.g. the packet for a
+    /// connection that isn't keeping up
+    Overflow(ResultOf),
     Timer,
 }
 
@@ -71,4 +76,8 @@ impl NextInputContext {
             Err(_) => Input::Failure(result_of),
         }
     }
+
+    pub fn overflow(self) -> Input {
+        Input::Overflow(self.0.unwrap())
+    }
 }
@@ -2,9 +2,13 @@ mod input;
 mod session;
 mod statistics;
 
-use std::{collections::HashMap, fmt::Debug, net::SocketAddr, time::Duration, time::Instant};
+use std::{collections::HashMap, fmt::Debug, net::SocketAddr, time::Duration};
 
-use crate::{packet::*, protocol::time::Timer, settings::ConnInitSettings};
+use crate::{
+    packet::*,
+    protocol::time::{Instant, Timer},
+    settings::{ConnInitSettings, SocketIdLease},
+};
 
 use session::*;
 
@@ -14,12 +18,21 @@ pub use statistics::*;
 #[derive(Clone, Debug)]
 pub struct ListenerSettings {}
 
+// callers retransmit a handshake every 100-250ms until they hear back, copies arriving faster than
+// this are from a misbehaving peer, answering each of them would send as much as it likes
+const HANDSHAKE_RESPONSE_INTERVAL: Duration = Duration::from_millis(100);
+
 #[derive(Debug)]
 pub struct MultiplexListener {
     start_time: Instant,
     local_address: SocketAddr,
     settings: ConnInitSettings,
     sessions: HashMap<SessionId, SessionState>,
+    // the address each session connected from
+    routes: HashMap<SocketAddr, SessionId>,
+    socket_ids: HashMap<SocketId, (SessionId, SocketIdLease)>,
+    // the last handshake of each session, with its cookie, and when it was last answered
+    handshakes: HashMap<SessionId, ((ShakeType, i32), Instant)>,
     stats: ListenerStatistics,
     stats_timer: Timer,
 }
@@ -31,6 +44,9 @@ impl MultiplexListener {
             local_address,
             settings,
             sessions: Default::default(),
+            routes: Default::default(),
+            socket_ids: Default::default(),
+            handshakes: Default::default(),
             stats: Default::default(),
             stats_timer: Timer::new(now, Duration::from_secs(1)),
         }
@@ -43,6 +59,7 @@ impl MultiplexListener {
             Input::Timer => self.handle_timer(now),
             Input::Success(result_of) => self.handle_success(now, result_of),
             Input::Failure(result_of) => self.handle_failure(now, result_of),
+            Input::Overflow(result_of) => self.handle_overflow(result_of),
         }
     }
 
@@ -69,14 +86,95 @@ impl MultiplexListener {
     fn handle_packet(&mut self, now: Instant, packet: (Packet, SocketAddr)) -> Action {
         self.stats.rx_packets += 1;
         //self.stats.rx_bytes += packet
-        let session_id = SessionId(packet.1);
-        let settings = &self.settings;
+        let from = packet.1;
+        let session_id = match self.routes.get(&from) {
+            Some(session_id) => *session_id,
+            // the connection decides whether it's the peer and follows it, or drops it and counts
+            // it as a foreign packet, the address isn't routed on the strength of the socket id
+            None => match self.rebound_session(&packet.0) {
+                Some(session_id) => {
+                    self.stats.cx_rebound += 1;
+                    session_id
+                }
+                None => self.new_session(from),
+            },
+        };
+        if self.is_duplicate_handshake(now, session_id, &packet.0) {
+            return Action::WaitForInput;
+        }
         self.sessions
-            .entry(session_id)
-            .or_insert_with(|| SessionState::new_pending(settings.clone()))
+            .get_mut(&session_id)
+            .expect("routes only point to existing sessions")
             .handle_packet(now, session_id, packet)
     }
 
+    // a peer behind a NAT that picked a new port, the socket id routes it back to its session
+    fn rebound_session(&self, packet: &Packet) -> Option<SessionId> {
+        if packet.is_handshake() {
+            return None;
+        }
+        let (session_id, _) = self.socket_ids.get(&packet.dest_sockid())?;
+        self.sessions
+            .get(session_id)
+            .filter(|session| session.is_open())
+            .map(|_| *session_id)
+    }
+
+    fn is_duplicate_handshake(
+        &mut self,
+        now: Instant,
+        session_id: SessionId,
+        packet: &Packet,
+    ) -> bool {
+        let shake = match packet {
+            Packet::Control(ControlPacket {
+                control_type: ControlTypes::Handshake(shake),
+                ..
+            }) => shake,
+            _ => return false,
+        };
+        let handshake = (shake.shake_type, shake.syn_cookie);
+        match self.handshakes.get_mut(&session_id) {
+            Some((last, answered)) if *last == handshake => {
+                self.stats.rx_duplicate_handshakes += 1;
+                if now < *answered + HANDSHAKE_RESPONSE_INTERVAL {
+                    self.stats.rx_dropped_handshakes += 1;
+                    return true;
+                }
+                *answered = now;
+                false
+            }
+            _ => {
+                self.handshakes.insert(session_id, (handshake, now));
+                false
+            }
+        }
+    }
+
+    fn new_session(&mut self, from: SocketAddr) -> SessionId {
+        let session_id = SessionId(from);
+        // sessions share the listening socket, so each one needs its own socket id, which no other
+        // connection of the process uses either
+        let lease = SocketIdLease::allocate();
+        let local_sockid = lease.socket_id();
+        let settings = ConnInitSettings {
+            local_sockid,
+            ..self.settings.clone()
+        };
+        self.routes.insert(from, session_id);
+        self.socket_ids.insert(local_sockid, (session_id, lease));
+        self.sessions
+            .insert(session_id, SessionState::new_pending(settings));
+        session_id
+    }
+
+    fn remove_session(&mut self, session_id: SessionId) {
+        self.sessions.remove(&session_id);
+        self.routes.retain(|_, id| *id != session_id);
+        self.socket_ids.retain(|_, (id, _)| *id != session_id);
+        self.handshakes.remove(&session_id);
+    }
+
     fn handle_packet_receive_error(&mut self, now: Instant, error: ReceivePacketError) -> Action {
         self.warn(now, "packet", &error);
 
@@ -122,7 +220,7 @@ impl MultiplexListener {
             }
             RejectConnection(session_id) => {
                 self.stats.cx_rejected += 1;
-                self.sessions.remove(&session_id);
+                self.remove_session(session_id);
             }
             OpenConnection(_) => {
                 self.stats.cx_opened += 1;
@@ -132,7 +230,7 @@ impl MultiplexListener {
             }
             DropConnection(session_id) => {
                 self.stats.cx_dropped += 1;
-                self.sessions.remove(&session_id);
+                self.remove_session(session_id);
             }
             UpdateStatistics => {}
         }
@@ -150,6 +248,14 @@ impl MultiplexListener {
         }
     }
 
+    fn handle_overflow(&mut self, result_of: ResultOf) -> Action {
+        // the connection recovers what it missed, as if it was lost on the way
+        if let ResultOf::DelegatePacket(_) = result_of {
+            self.stats.delegate_dropped_packets += 1;
+        }
+        Action::WaitForInput
+    }
+
     fn handle_close(&mut self) -> Action {
         Action::Close
     }
@@ -175,7 +281,7 @@ mod test {
 
     use rand::random;
 
-    use crate::options::{KeySize, PacketCount, PacketSize, SrtVersion};
+    use crate::options::{KeySize, PacketCount, PacketSize, PeerAddressPolicy, SrtVersion};
 
     use super::*;
 
@@ -215,6 +321,9 @@ mod test {
                 })),
                 ext_km: None,
                 ext_group: None,
+                encrypted_control: false,
+                message_tags: false,
+                congestion_experienced: false,
                 sid: None,
             }),
         }
@@ -254,7 +363,10 @@ mod test {
 
         let action = listener.handle_input(
             Instant::now(),
-            Input::AccessResponse(Some((session_id(), AccessControlResponse::Accepted(None)))),
+            Input::AccessResponse(Some((
+                session_id(),
+                AccessControlResponse::Accepted(Default::default()),
+            ))),
         );
         assert_matches!(action, Action::OpenConnection(_, _));
 
@@ -264,12 +376,198 @@ mod test {
             listener.handle_input(Instant::now(), Input::Success(OpenConnection(session_id())));
         assert_matches!(action, Action::WaitForInput);
 
+        // the caller retransmitting its conclusion
         let packet = build_hs_pack(test_conclusion());
-        let action =
-            listener.handle_input(Instant::now(), Input::Packet(Ok((packet, conn_addr()))));
+        let action = listener.handle_input(
+            Instant::now() + HANDSHAKE_RESPONSE_INTERVAL,
+            Input::Packet(Ok((packet, conn_addr()))),
+        );
         assert_matches!(action, Action::DelegatePacket(_, _));
     }
 
+    fn open_session(listener: &mut MultiplexListener, from: SocketAddr) -> SocketId {
+        let packet = build_hs_pack(test_induction());
+        let action = listener.handle_input(Instant::now(), Input::Packet(Ok((packet, from))));
+        assert_matches!(action, Action::SendPacket(_));
+
+        let conclusion = HandshakeControlInfo {
+            syn_cookie: crate::protocol::pending_connection::cookie::gen_cookie(&from),
+            ..test_conclusion()
+        };
+        let packet = build_hs_pack(conclusion);
+        let action = listener.handle_input(Instant::now(), Input::Packet(Ok((packet, from))));
+        assert_matches!(action, Action::RequestAccess(_, _));
+
+        let action = listener.handle_input(
+            Instant::now(),
+            Input::AccessResponse(Some((
+                SessionId(from),
+                AccessControlResponse::Accepted(Default::default()),
+            ))),
+        );
+        let local_sockid = match action {
+            Action::OpenConnection(_, connection) => connection.1.settings.local_sockid,
+            action => panic!("expected connection, got {action:?}"),
+        };
+
+        let action = listener.handle_input(
+            Instant::now(),
+            Input::Success(ResultOf::OpenConnection(SessionId(from))),
+        );
+        assert_matches!(action, Action::WaitForInput);
+
+        local_sockid
+    }
+
+    fn keepalive(dest_sockid: SocketId) -> Packet {
+        Packet::Control(ControlPacket {
+            timestamp: TimeStamp::from_micros(0),
+            dest_sockid,
+            control_type: ControlTypes::KeepAlive,
+        })
+    }
+
+    #[test]
+    fn socket_id_per_session() {
+        let settings = ConnInitSettings::default();
+        let local = "0.0.0.0:2000".parse().unwrap();
+        let mut listener = MultiplexListener::new(Instant::now(), local, settings);
+
+        let first = open_session(&mut listener, conn_addr());
+        let second = open_session(&mut listener, "127.0.0.1:8766".parse().unwrap());
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn rebinding() {
+        let settings = ConnInitSettings::default();
+        let local = "0.0.0.0:2000".parse().unwrap();
+        let mut listener = MultiplexListener::new(Instant::now(), local, settings);
+        let local_sockid = open_session(&mut listener, conn_addr());
+
+        // the same peer from a new port
+        let rebound: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let action = listener.handle_input(
+            Instant::now(),
+            Input::Packet(Ok((keepalive(local_sockid), rebound))),
+        );
+        assert_matches!(action, Action::DelegatePacket(id, (_, from)) if id == session_id() && from == rebound);
+        assert_eq!(listener.stats.cx_rebound, 1);
+
+        // both addresses reach the session, which follows the peer or not
+        for from in [conn_addr(), rebound] {
+            let action = listener.handle_input(
+                Instant::now(),
+                Input::Packet(Ok((keepalive(local_sockid), from))),
+            );
+            assert_matches!(action, Action::DelegatePacket(id, _) if id == session_id());
+        }
+        assert!(!listener.routes.contains_key(&rebound));
+
+        // somebody else
+        let other: SocketAddr = "127.0.0.1:9877".parse().unwrap();
+        let action = listener.handle_input(
+            Instant::now(),
+            Input::Packet(Ok((keepalive(SocketId(local_sockid.0 + 1)), other))),
+        );
+        assert_matches!(action, Action::WaitForInput);
+
+        // and the routes go away with the session
+        let action = listener.handle_input(
+            Instant::now(),
+            Input::Success(ResultOf::DropConnection(session_id())),
+        );
+        assert_matches!(action, Action::WaitForInput);
+        let action = listener.handle_input(
+            Instant::now(),
+            Input::Packet(Ok((keepalive(local_sockid), rebound))),
+        );
+        assert_matches!(action, Action::WaitForInput);
+        assert_eq!(listener.stats.cx_rebound, 2);
+    }
+
+    #[test]
+    fn rebinding_spoofed() {
+        let settings = ConnInitSettings {
+            peer_address_policy: PeerAddressPolicy::RebindPort,
+            ..Default::default()
+        };
+        let local = "0.0.0.0:2000".parse().unwrap();
+        let mut listener = MultiplexListener::new(Instant::now(), local, settings);
+        let local_sockid = open_session(&mut listener, conn_addr());
+
+        // another host with the socket id reaches the connection, which drops it, but isn't routed
+        let spoofed: SocketAddr = "10.0.0.1:9876".parse().unwrap();
+        for _ in 0..2 {
+            let action = listener.handle_input(
+                Instant::now(),
+                Input::Packet(Ok((keepalive(local_sockid), spoofed))),
+            );
+            assert_matches!(action, Action::DelegatePacket(id, _) if id == session_id());
+        }
+        assert_eq!(listener.stats.cx_rebound, 2);
+        assert!(!listener.routes.contains_key(&spoofed));
+    }
+
+    #[test]
+    fn overflow() {
+        let settings = ConnInitSettings::default();
+        let local = "0.0.0.0:2000".parse().unwrap();
+        let mut listener = MultiplexListener::new(Instant::now(), local, settings);
+        let local_sockid = open_session(&mut listener, conn_addr());
+
+        let action = listener.handle_input(
+            Instant::now(),
+            Input::Packet(Ok((keepalive(local_sockid), conn_addr()))),
+        );
+        let next = NextInputContext::for_action(&action);
+        assert_matches!(action, Action::DelegatePacket(_, _));
+
+        // the session stays, only the packet is gone
+        let action = listener.handle_input(Instant::now(), next.overflow());
+        assert_matches!(action, Action::WaitForInput);
+        assert_eq!(listener.stats.delegate_dropped_packets, 1);
+        assert_eq!(listener.stats.delegated_packets, 0);
+        let action = listener.handle_input(
+            Instant::now(),
+            Input::Packet(Ok((keepalive(local_sockid), conn_addr()))),
+        );
+        assert_matches!(action, Action::DelegatePacket(id, _) if id == session_id());
+    }
+
+    #[test]
+    fn duplicate_handshakes() {
+        let settings = ConnInitSettings::default();
+        let local = "0.0.0.0:2000".parse().unwrap();
+        let mut listener = MultiplexListener::new(Instant::now(), local, settings);
+        open_session(&mut listener, conn_addr());
+
+        // a storm of conclusion retransmissions reaches the connection only once an interval
+        let start = Instant::now();
+        for _ in 0..10 {
+            let packet = build_hs_pack(test_conclusion());
+            let action = listener.handle_input(start, Input::Packet(Ok((packet, conn_addr()))));
+            assert_matches!(action, Action::WaitForInput);
+        }
+        assert_eq!(listener.stats.rx_duplicate_handshakes, 10);
+        assert_eq!(listener.stats.rx_dropped_handshakes, 10);
+
+        let packet = build_hs_pack(test_conclusion());
+        let action = listener.handle_input(
+            start + HANDSHAKE_RESPONSE_INTERVAL,
+            Input::Packet(Ok((packet, conn_addr()))),
+        );
+        assert_matches!(action, Action::DelegatePacket(id, _) if id == session_id());
+        assert_eq!(listener.stats.rx_duplicate_handshakes, 11);
+        assert_eq!(listener.stats.rx_dropped_handshakes, 10);
+
+        // other peers aren't held up by it
+        let other: SocketAddr = "127.0.0.1:8766".parse().unwrap();
+        let packet = build_hs_pack(test_induction());
+        let action = listener.handle_input(start, Input::Packet(Ok((packet, other))));
+        assert_matches!(action, Action::SendPacket(_));
+    }
+
     #[test]
     fn reject() {
         let settings = ConnInitSettings::default();
@@ -299,8 +597,10 @@ mod test {
         );
 
         let packet = build_hs_pack(test_conclusion());
-        let action =
-            listener.handle_input(Instant::now(), Input::Packet(Ok((packet, conn_addr()))));
+        let action = listener.handle_input(
+            Instant::now() + HANDSHAKE_RESPONSE_INTERVAL,
+            Input::Packet(Ok((packet, conn_addr()))),
+        );
         assert_matches!(
             action,
             Action::RejectConnection(_, Some((Packet::Control(_), _)))
@@ -1,9 +1,10 @@
-use std::{net::SocketAddr, time::Instant};
+use std::net::SocketAddr;
 
 use crate::{
     connection::Connection,
     packet::Packet,
     protocol::pending_connection::{listen::Listen, ConnectionResult},
+    protocol::time::Instant,
     settings::ConnInitSettings,
 };
 
@@ -23,6 +24,10 @@ impl SessionState {
         SessionState::Pending(Listen::new(settings, true))
     }
 
+    pub fn is_open(&self) -> bool {
+        matches!(self, SessionState::Open)
+    }
+
     pub fn handle_packet(
         &mut self,
         now: Instant,
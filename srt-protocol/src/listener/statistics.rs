@@ -4,16 +4,26 @@ pub struct ListenerStatistics {
     pub rx_bytes: u64,
     pub rx_parse_errors: u64,
     pub rx_io_errors: u64,
+    /// Handshakes repeating the last one from the same peer, with the same cookie
+    pub rx_duplicate_handshakes: u64,
+    /// Duplicate handshakes left unanswered, as they came in quicker than a caller retransmits
+    pub rx_dropped_handshakes: u64,
 
     pub tx_packets: u64,
     pub tx_bytes: u64,
 
     pub delegated_packets: u64,
     pub delegated_bytes: u64,
+    /// Packets dropped because the connection they were for had fallen a full queue behind,
+    /// rather than have the other connections wait for it
+    pub delegate_dropped_packets: u64,
 
     pub cx_inbound: u64,
     pub cx_opened: u64,
     pub cx_dropped: u64,
     pub cx_rejected: u64,
     pub cx_accepted: u64,
+    /// Packets routed to an open session by their socket id, from an address other than the one
+    /// it connected from, the session's peer after a NAT rebinding or somebody else
+    pub cx_rebound: u64,
 }
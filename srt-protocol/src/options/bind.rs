@@ -72,4 +72,15 @@ mod tests {
         let _: BindOptions = listener.into();
         let _: BindOptions = rendezvous.into();
     }
+
+    #[test]
+    fn rendezvous_local_port() {
+        let rendezvous = RendezvousOptions::new("127.0.0.1:42").unwrap();
+        assert_eq!(rendezvous.socket.connect.local.port(), 42);
+
+        let mut socket = SocketOptions::default();
+        socket.connect.local.set_port(43);
+        let rendezvous = RendezvousOptions::with("127.0.0.1:42", socket).unwrap();
+        assert_eq!(rendezvous.socket.connect.local.port(), 43);
+    }
 }
@@ -29,14 +29,14 @@ pub struct Connect {
     /// UDP Socket Receive Buffer Size. Configured in bytes, maintained in packets based on MSS value.
     /// Receive buffer must not be greater than FC size.
     ///
-    /// Default is 64k
+    /// Default is 64k, 1M on Windows
     pub udp_recv_buffer_size: ByteCount,
 
     /// SRT_UDP_SNDBUF
     ///
     /// UDP Socket Send Buffer Size. Configured in bytes, maintained in packets based on SRTO_MSS value.
     ///
-    /// Default is 64k
+    /// Default is 64k, 1M on Windows
     pub udp_send_buffer_size: ByteCount,
 
     /// SRTO_IPTTL
@@ -52,8 +52,27 @@ pub struct Connect {
     ///
     /// Default is 180s
     pub linger: Option<Duration>,
+
+    /// Mark outgoing datagrams as ECN capable and read the ECN bits of received ones, where the
+    /// platform supports it (Linux). Data packets that arrive with a Congestion Experienced mark
+    /// are reported back to the sender, which slows down as it would for a loss report, before
+    /// any packet is actually lost.
+    ///
+    /// The reports are an extension of this implementation, so they are agreed on in the handshake
+    /// and only sent when both sides enable this, a peer that doesn't offer them (libsrt) gets
+    /// none.
+    ///
+    /// Default is false
+    pub ecn: bool,
 }
 
+// Windows wakes up less reliably, so it needs more room to absorb the packets that arrive in
+// between
+#[cfg(not(windows))]
+const DEFAULT_UDP_BUFFER_SIZE: u64 = 65536;
+#[cfg(windows)]
+const DEFAULT_UDP_BUFFER_SIZE: u64 = 1024 * 1024;
+
 impl Connect {}
 impl Default for Connect {
     fn default() -> Self {
@@ -61,10 +80,11 @@ impl Default for Connect {
             local: SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
             timeout: Duration::from_secs(3),
             min_version: SrtVersion::new(1, 0, 0),
-            udp_recv_buffer_size: ByteCount(65536),
-            udp_send_buffer_size: ByteCount(65536),
+            udp_recv_buffer_size: ByteCount(DEFAULT_UDP_BUFFER_SIZE),
+            udp_send_buffer_size: ByteCount(DEFAULT_UDP_BUFFER_SIZE),
             ip_ttl: 64,
             linger: Some(Duration::from_secs(180)),
+            ecn: false,
         }
     }
 }
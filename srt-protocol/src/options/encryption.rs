@@ -1,6 +1,17 @@
 use std::{
     convert::TryFrom,
     fmt::{self, Debug, Display, Formatter},
+    sync::Arc,
+};
+
+use zeroize::Zeroize;
+
+use crate::{
+    protocol::encryption::{backend::crypto_backend, provider::DerivedKeys},
+    settings::{
+        KeyMaterialRefreshSettings, KeyProvider, KeyProviderError, KeySource, Salt,
+        SharedKeyProvider,
+    },
 };
 
 use super::*;
@@ -74,7 +85,28 @@ pub struct Encryption {
     /// passphrase.
     pub passphrase: Option<Passphrase>,
 
+    /// How the key encrypting key is derived from the passphrase
+    pub key_derivation: KeyDerivation,
+
+    /// Takes the place of the passphrase, the key encrypting key comes from the provider instead
+    /// of being derived from a string in the configuration, e.g. from a KMS or an HSM. The peer
+    /// can use either as long as both come up with the same key.
+    ///
+    /// It can't be set along with a passphrase.
+    pub key_provider: Option<SharedKeyProvider>,
+
     pub km_refresh: KeyMaterialRefresh,
+
+    /// Encrypt the control packets about the stream too, i.e. ACKs, ACKACKs, loss reports and drop
+    /// requests, with the stream encryption key, for when the loss and acknowledgement patterns
+    /// are sensitive. They're not authenticated, just hidden.
+    ///
+    /// Experimental, this is an extension of this implementation. It's offered in the handshake
+    /// and only used when the peer offers it too, so it stays off with libsrt. It needs a
+    /// passphrase.
+    ///
+    /// Default value: false
+    pub encrypt_control: bool,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -96,6 +128,9 @@ pub struct KeyMaterialRefresh {
     /// active after switchover in order to decrypt packets that might still be in flight, or
     /// packets that have to be retransmitted.
     ///
+    /// It has to be less than 2^31, the number of sequence numbers, otherwise the keystream of a
+    /// key would repeat.
+    ///
     /// Default value: 0 - corresponds to 16777216 packets (2^24 or 0x1000000).
     pub period: PacketCount,
 
@@ -134,6 +169,49 @@ pub struct KeyMaterialRefresh {
     pub pre_announcement_period: PacketCount,
 }
 
+/// The PBKDF2-HMAC-SHA1 parameters that derive the key encrypting key from the passphrase and
+/// the salt of the keying material
+///
+/// libsrt has no options for these and always uses the defaults, only two peers of this
+/// implementation can agree on other iterations or salt length. The initial salt doesn't have to
+/// be agreed on, the peer takes it from the keying material.
+///
+/// Derived keys are kept with the [`Passphrase`] for the next connections with the same
+/// parameters and salt, up to 64 of them, so a handshake that's repeated or a connection that's
+/// restarted doesn't run PBKDF2 again. A caller with an initial salt reconnects with the key of
+/// the first connection, and so does its listener. The keys stay in memory as long as the
+/// options the passphrase is in, their clones, or a connection made with them do, and are zeroed
+/// along with the passphrase once the last of them is dropped. Options built anew for each
+/// connection don't keep any keys around, but derive them every time.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct KeyDerivation {
+    /// The number of PBKDF2 iterations
+    ///
+    /// Default value: 2048, like libsrt
+    pub iterations: u32,
+
+    /// How many of the last bytes of the 16 byte salt are the salt of PBKDF2
+    ///
+    /// Default value: 8, like libsrt
+    pub salt_length: usize,
+
+    /// The salt to start connections with as the initiator, instead of a random one for each of
+    /// them, so they all use the same key encrypting key. The stream keys stay random.
+    ///
+    /// Default value: None
+    pub initial_salt: Option<Salt>,
+}
+
+impl Default for KeyDerivation {
+    fn default() -> Self {
+        Self {
+            iterations: 2048,
+            salt_length: 8,
+            initial_salt: None,
+        }
+    }
+}
+
 impl Default for KeyMaterialRefresh {
     fn default() -> Self {
         Self {
@@ -143,6 +221,34 @@ impl Default for KeyMaterialRefresh {
     }
 }
 
+impl Encryption {
+    /// Where the key encrypting key comes from, if the connection is encrypted
+    pub fn key_source(&self) -> Option<KeySource> {
+        match (&self.passphrase, &self.key_provider) {
+            (Some(passphrase), _) if self.key_derivation == KeyDerivation::default() => {
+                Some(KeySource::Passphrase(passphrase.clone()))
+            }
+            (Some(passphrase), _) => Some(KeySource::DerivedPassphrase(
+                passphrase.clone(),
+                self.key_derivation.clone(),
+            )),
+            (None, Some(provider)) => Some(KeySource::Provider(provider.clone())),
+            (None, None) => None,
+        }
+    }
+
+    /// Derive the key encrypting key for the initial salt of the key derivation ahead of time, so
+    /// the first connection doesn't wait for it either
+    pub fn pre_derive(&self) -> Result<(), KeyProviderError> {
+        let (Some(key_source), Some(salt)) = (self.key_source(), &self.key_derivation.initial_salt)
+        else {
+            return Ok(());
+        };
+        key_source.key_encrypting_key(self.key_size, salt)?;
+        Ok(())
+    }
+}
+
 impl Validation for Encryption {
     type Error = OptionsError;
 
@@ -150,11 +256,31 @@ impl Validation for Encryption {
         let period: u64 = self.km_refresh.period.into();
         let pre_announcement_period: u64 = self.km_refresh.pre_announcement_period.into();
 
+        if self.passphrase.is_some() && self.key_provider.is_some() {
+            return Err(OptionsError::PassphraseAndKeyProvider);
+        }
+
+        // rather than failing the connection, or sending in the clear
+        if self.key_source().is_some() && crypto_backend().is_err() {
+            return Err(OptionsError::NoCryptoBackend);
+        }
+
+        let KeyDerivation {
+            iterations,
+            salt_length,
+            ..
+        } = self.key_derivation;
+        if iterations == 0 || !(1..=16).contains(&salt_length) {
+            return Err(OptionsError::KeyDerivation(iterations, salt_length));
+        }
+
         if period == 0 || pre_announcement_period > period.saturating_sub(1) / 2 {
             Err(OptionsError::KeyMaterialRefresh(
                 PacketCount(period),
                 PacketCount(pre_announcement_period),
             ))
+        } else if period > KeyMaterialRefreshSettings::MAX_PERIOD as u64 {
+            Err(OptionsError::KeyMaterialRefreshPeriod(PacketCount(period)))
         } else {
             Ok(())
         }
@@ -162,8 +288,20 @@ impl Validation for Encryption {
 }
 
 // https://github.com/Haivision/srt/blob/master/docs/API/API-socket-options.md#srto_passphrase
-#[derive(Clone, Eq, PartialEq)]
-pub struct Passphrase(String);
+#[derive(Clone)]
+pub struct Passphrase(Arc<Secret>);
+
+// shared by the clones of a passphrase, see KeyDerivation
+struct Secret {
+    passphrase: String,
+    derived: DerivedKeys,
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.passphrase.zeroize();
+    }
+}
 
 impl<'a> From<&'a str> for Passphrase {
     fn from(value: &'a str) -> Self {
@@ -178,72 +316,40 @@ impl TryFrom<String> for Passphrase {
         if !(10..=79).contains(&value.len()) {
             return Err(OptionsError::PassphraseLength(value.len()));
         }
-        Ok(Passphrase(value))
+        Ok(Passphrase(Arc::new(Secret {
+            passphrase: value,
+            derived: DerivedKeys::default(),
+        })))
     }
 }
 
 impl Passphrase {
     pub fn as_bytes(&self) -> &[u8] {
-        self.0.as_bytes()
+        self.0.passphrase.as_bytes()
     }
-}
 
-impl Display for Passphrase {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        Debug::fmt(self, f)
+    pub(crate) fn derived_keys(&self) -> &DerivedKeys {
+        &self.0.derived
     }
 }
 
-impl Debug for Passphrase {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Passphrase").finish()
+impl PartialEq for Passphrase {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.passphrase == other.0.passphrase
     }
 }
 
-// https://github.com/Haivision/srt/blob/master/docs/API/API-socket-options.md#srto_pbkeylen
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
-pub enum KeySize {
-    #[default]
-    Unspecified,
-    AES128,
-    AES192,
-    AES256,
-}
-
-impl KeySize {
-    pub fn as_raw(self) -> u8 {
-        use KeySize::*;
-        match self {
-            Unspecified => 0,
-            AES128 => 16,
-            AES192 => 24,
-            AES256 => 32,
-        }
-    }
+impl Eq for Passphrase {}
 
-    pub fn as_usize(self) -> usize {
-        use KeySize::*;
-        match self {
-            Unspecified => 16,
-            AES128 => 16,
-            AES192 => 24,
-            AES256 => 32,
-        }
+impl Display for Passphrase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self, f)
     }
 }
 
-impl TryFrom<u16> for KeySize {
-    type Error = OptionsError;
-
-    fn try_from(value: u16) -> Result<Self, OptionsError> {
-        use KeySize::*;
-        match value {
-            0 => Ok(Unspecified),
-            16 => Ok(AES128),
-            24 => Ok(AES192),
-            32 => Ok(AES256),
-            value => Err(OptionsError::InvalidKeySize(value)),
-        }
+impl Debug for Passphrase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Passphrase").finish()
     }
 }
 
@@ -271,4 +377,105 @@ mod tests {
             Err(PassphraseLength(80))
         );
     }
+
+    #[test]
+    fn km_refresh_period() {
+        let encryption = |period| Encryption {
+            km_refresh: KeyMaterialRefresh {
+                period: PacketCount(period),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(encryption((1 << 31) - 1).is_valid(), Ok(()));
+        assert_eq!(
+            encryption(1 << 31).is_valid(),
+            Err(OptionsError::KeyMaterialRefreshPeriod(PacketCount(1 << 31)))
+        );
+    }
+
+    #[test]
+    fn key_provider() {
+        let provider = SharedKeyProvider::new(Passphrase::from("kept somewhere else"));
+        let encryption = Encryption {
+            key_provider: Some(provider.clone()),
+            ..Default::default()
+        };
+        assert_eq!(encryption.is_valid(), Ok(()));
+        assert_eq!(encryption.key_source(), Some(KeySource::Provider(provider)));
+        assert_eq!(
+            Encryption {
+                passphrase: Some("1234567890".into()),
+                ..encryption
+            }
+            .is_valid(),
+            Err(OptionsError::PassphraseAndKeyProvider)
+        );
+    }
+
+    #[cfg(not(any(feature = "rust-crypto", feature = "openssl")))]
+    #[test]
+    fn no_crypto_backend() {
+        assert_eq!(Encryption::default().is_valid(), Ok(()));
+        assert_eq!(
+            Encryption {
+                passphrase: Some("1234567890".into()),
+                ..Default::default()
+            }
+            .is_valid(),
+            Err(OptionsError::NoCryptoBackend)
+        );
+    }
+
+    #[test]
+    fn key_derivation() {
+        let passphrase = Passphrase::from("1234567890");
+        let encryption = |key_derivation| Encryption {
+            passphrase: Some(passphrase.clone()),
+            key_derivation,
+            ..Default::default()
+        };
+
+        // libsrt's parameters
+        let default = encryption(KeyDerivation::default());
+        assert_eq!(default.is_valid(), Ok(()));
+        assert_eq!(
+            default.key_source(),
+            Some(KeySource::Passphrase(passphrase.clone()))
+        );
+
+        let derivation = KeyDerivation {
+            iterations: 10_000,
+            initial_salt: Some(Salt::new_random()),
+            ..Default::default()
+        };
+        let derived = encryption(derivation.clone());
+        assert_eq!(derived.is_valid(), Ok(()));
+        assert_eq!(
+            derived.key_source(),
+            Some(KeySource::DerivedPassphrase(
+                passphrase.clone(),
+                derivation.clone()
+            ))
+        );
+        assert_eq!(
+            derived.key_source().unwrap().new_salt(),
+            derivation.initial_salt.unwrap()
+        );
+        assert_eq!(derived.pre_derive(), Ok(()));
+
+        use OptionsError::KeyDerivation as Invalid;
+        let invalid = |iterations, salt_length| {
+            encryption(KeyDerivation {
+                iterations,
+                salt_length,
+                initial_salt: None,
+            })
+            .is_valid()
+        };
+        assert_eq!(invalid(0, 8), Err(Invalid(0, 8)));
+        assert_eq!(invalid(2048, 0), Err(Invalid(2048, 0)));
+        assert_eq!(invalid(2048, 17), Err(Invalid(2048, 17)));
+        assert_eq!(invalid(1, 16), Ok(()));
+    }
 }
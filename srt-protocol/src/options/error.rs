@@ -9,8 +9,16 @@ use crate::options::*;
 pub enum OptionsError {
     #[error("KM Refresh Period ({0}) must be non-zero and greater than 1/2 the KM Pre Announce Period ({1}).")]
     KeyMaterialRefresh(PacketCount, PacketCount),
+    #[error("KM Refresh Period ({0}) must be less than 2^31, the keystream of a key would repeat after that many packets.")]
+    KeyMaterialRefreshPeriod(PacketCount),
     #[error("Invalid password length: {0}. The password must be minimum 10 and maximum 79 characters long.")]
     PassphraseLength(usize),
+    #[error("A passphrase and a key provider can't be set both, the key encrypting key comes from either.")]
+    PassphraseAndKeyProvider,
+    #[error("Encryption needs a crypto backend, enable the rust-crypto or openssl feature or call install_crypto_backend.")]
+    NoCryptoBackend,
+    #[error("Invalid key derivation: {0} iterations with a salt length of {1}. There has to be at least one iteration and the salt length must be 1 to 16 bytes.")]
+    KeyDerivation(u32, usize),
     #[error("Invalid encryption key size: {0}. Valid sizes are 16, 24, or 32 bytes.")]
     InvalidKeySize(u16),
 
@@ -41,9 +49,26 @@ pub enum OptionsError {
         flow_control_window: PacketCount,
     },
 
+    #[error(
+        "Payload size {payload} doesn't fit in a packet of the maximum segment size {max_segment}"
+    )]
+    PayloadSizeTooLarge {
+        payload: PacketSize,
+        max_segment: PacketSize,
+    },
+
     #[error("Sender flow_control_window_size {0} is less than the minimum 32 packets")]
     FlowControlWindowMin(PacketCount),
 
+    #[error("Send buffer byte limit {0} can't hold a single full sized packet of 1500 bytes")]
+    SendBufferBytesMin(ByteCount),
+
+    #[error("Send buffer time limit can't be zero")]
+    SendBufferTimeZero,
+
+    #[error("The fast retransmit threshold must be at least 1 duplicate ACK")]
+    FastRetransmitZero,
+
     #[error("A specific local port is required to listen for incoming callers.")]
     LocalPortRequiredToListen,
 
@@ -62,8 +87,26 @@ pub enum OptionsError {
     #[error("IP TTL is invalid, must be > 0")]
     InvalidIpTtl,
 
+    #[error("The light ACK interval must be at least 1 packet")]
+    LightAckIntervalZero,
+
+    #[error("The delivery rate limit must be greater than zero")]
+    DeliveryRateZero,
+
     #[error("Statistics interval is out of range: {0:?}. The minimum interval is 200ms.")]
     StatisticsIntervalOutOfRange(Duration),
+
+    #[error("Minimum latency {0:?} is greater than the maximum latency {1:?}")]
+    LatencyRange(Duration, Duration),
+
+    #[error("The keepalive interval must be greater than zero")]
+    KeepaliveIntervalZero,
+
+    #[error("Message tags need the message API, there are no messages to tag in stream mode")]
+    MessageTagsInStreamMode,
+
+    #[error("Socket id 0 is reserved for handshakes with a listener")]
+    InvalidSocketId,
 }
 
 impl From<OptionsError> for io::Error {
@@ -85,6 +85,51 @@ pub struct Receiver {
     /// SRTO_DRIFTTRACER - Enable/disable drift tracer - unit: bool, default: true, range: t|f
     /// Enables or disables time drift tracer (receiver).
     pub drift_tracer: bool,
+
+    /// How far, in packets, the sequence number of an incoming DATA packet may be from the next
+    /// expected sequence number before it is treated as the sender having restarted with a new
+    /// initial sequence number, rather than as a duplicate, retransmission or loss.
+    ///
+    /// When a restart is detected the receive buffer is reset to continue from the new sequence
+    /// number without reconnecting. This should be well above the number of packets that can be in
+    /// flight over the latency window, otherwise stale retransmissions will be mistaken for a
+    /// restart. By default this value is set to 0, which means that this mechanism is off.
+    pub sequence_restart_window: PacketCount,
+
+    /// Release each message at its TSBPD time even when packets before it are still missing,
+    /// rather than giving them a few more milliseconds to be recovered. Anything released past the
+    /// latency is useless for live playback, so this keeps the output on schedule at the cost of
+    /// dropping retransmissions that would have only just made it.
+    ///
+    /// The dropped packets are counted in the statistics either way, see
+    /// DuplexConnection::set_gap_handler to be told where the gaps are.
+    pub skip_gaps: bool,
+
+    /// Report a loss again as soon as the retransmission is overdue, i.e. one RTT + 4 * RTTVar
+    /// after the last report, checked whenever a DATA packet arrives, rather than on the NAK
+    /// timer. Gaps are always reported as soon as they are detected; the repeats wait for the NAK
+    /// period, 20 ms at least, and back off with every report. On low RTT links this cuts the
+    /// recovery time of a lost retransmission to about one round trip, at the cost of more NAK
+    /// packets. Each lost packet is still reported at most once per window.
+    ///
+    /// Needs a peer that accepts repeated loss reports, see SRTO_NAKREPORT.
+    pub immediate_nak: bool,
+
+    /// Acknowledge every this many received packets with a light ACK, on top of the full ACK
+    /// every 10 ms. The sender releases its buffer as the ACKs come in, so at high bitrates over
+    /// short round trips, where the full ACK cadence dominates how long packets stay in the send
+    /// buffer, a small interval, down to 1 for an ACK on every packet, frees it sooner, at the
+    /// cost of a control packet per N data packets.
+    ///
+    /// Default value: 64, as libsrt
+    pub light_ack_interval: PacketCount,
+
+    /// The most the received messages are released to the application at, in payload bytes per
+    /// second, or no limit if `None`. Once a loss is recovered, the messages held back by it are
+    /// released all at once, which a downstream device fed over UDP, e.g. a set-top box, may not
+    /// absorb; with a limit they are spaced out instead, taking their time out of the latency.
+    /// It should be well above the bitrate of the stream for it to catch up after a burst.
+    pub max_delivery_rate: Option<DataRate>,
 }
 
 impl Default for Receiver {
@@ -96,6 +141,11 @@ impl Default for Receiver {
             nak_report: true,
             too_late_packet_drop: true,
             drift_tracer: false,
+            sequence_restart_window: PacketCount(0),
+            skip_gaps: false,
+            immediate_nak: false,
+            light_ack_interval: PacketCount(64),
+            max_delivery_rate: None,
         }
     }
 }
@@ -107,6 +157,10 @@ impl Validation for Receiver {
         use OptionsError::*;
         if self.buffer_size < ByteCount(46592) {
             Err(ReceiveBufferMin(self.buffer_size))
+        } else if self.light_ack_interval == PacketCount(0) {
+            Err(LightAckIntervalZero)
+        } else if self.max_delivery_rate == Some(DataRate(0)) {
+            Err(DeliveryRateZero)
         } else {
             Ok(())
         }
@@ -130,5 +184,19 @@ mod tests {
             result.try_validate(),
             Err(ReceiveBufferMin(ByteCount(46591)))
         );
+
+        let result = Receiver {
+            light_ack_interval: PacketCount(0),
+            ..Default::default()
+        };
+
+        assert_eq!(result.try_validate(), Err(LightAckIntervalZero));
+
+        let result = Receiver {
+            max_delivery_rate: Some(DataRate(0)),
+            ..Default::default()
+        };
+
+        assert_eq!(result.try_validate(), Err(DeliveryRateZero));
     }
 }
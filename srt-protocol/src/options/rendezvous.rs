@@ -14,13 +14,18 @@ impl RendezvousOptions {
         Self::with(remote, socket)
     }
 
+    /// Both peers send from the port the other one sends to, unless the local port is set it's
+    /// the same as the remote one, like srt-live-transmit does it
     pub fn with(
         remote: impl TryInto<SocketAddress>,
-        socket: SocketOptions,
+        mut socket: SocketOptions,
     ) -> Result<Valid<RendezvousOptions>, OptionsError> {
-        let remote = remote
+        let remote: SocketAddress = remote
             .try_into()
             .map_err(|_| OptionsError::InvalidRemoteAddress)?;
+        if socket.connect.local.port() == 0 {
+            socket.connect.local.set_port(remote.port);
+        }
         Self { remote, socket }.try_validate()
     }
 }
@@ -88,8 +88,20 @@ pub struct Sender {
     /// For Live mode: Default value is 1316, but can be increased up to 1456. Note that with the
     /// SRTO_PACKETFILTER option additional header space is usually required, which decreases the
     /// maximum possible value for SRTO_PAYLOADSIZE.
+    ///
+    /// Both sides agree on the smaller of their payload sizes during the handshake. Larger
+    /// messages are split into packets of that size and put back together by the receiver, unless
+    /// [`limit_payload_size`](Self::limit_payload_size) is set. With 0, the packets are as large as
+    /// the MSS leaves room for.
     pub max_payload_size: PacketSize,
 
+    /// Refuse messages that don't fit in a single packet of the payload size with
+    /// [`PayloadTooLarge`](crate::connection::PayloadTooLarge), as libsrt does in live mode, rather
+    /// than split them.
+    ///
+    /// Default is false
+    pub limit_payload_size: bool,
+
     /// SRTO_RETRANSMITALGO - prioritize this
     ///
     /// An SRT sender option to choose between two retransmission algorithms:
@@ -116,6 +128,115 @@ pub struct Sender {
     /// NOTE: The efficient retransmission algorithm can only be used when a receiver sends Periodic
     /// NAK reports. See SRTO_NAKREPORT.
     pub intensive_retransmission: bool,
+
+    /// How the sender acknowledges full ACK packets with ACK2 (ACKACK) packets.
+    ///
+    /// The receiver calculates the RTT from the time between sending a full ACK and receiving the
+    /// corresponding ACK2, so every ACK2 sent is an RTT sample for the peer. Throttling ACK2 to one
+    /// per SYN interval (10 ms), as the reference implementation does, reduces control traffic
+    /// when the peer sends full ACKs more often than that.
+    ///
+    /// Default: Ack2Mode::EveryFullAck
+    pub ack2_mode: Ack2Mode,
+
+    /// Take additional RTT samples from light ACK packets.
+    ///
+    /// Light ACKs are sent by the receiver every 64 packets, which at high packet rates is much
+    /// more often than full ACKs. When enabled, the sender measures the time between first sending
+    /// a packet and receiving a light ACK for it, and blends that into its RTT estimate if a full
+    /// ACK has not updated it within the last SYN interval. Retransmitted packets are never sampled.
+    ///
+    /// Default: false
+    pub lite_ack_rtt_sampling: bool,
+
+    /// The maximum number of packets the sender may send back to back in one send tick.
+    ///
+    /// Sending is paced by a token bucket that gains one token per send period (derived from the
+    /// configured bandwidth) and holds at most this many tokens. Retransmissions and fresh packets
+    /// share the same bucket, so a late send tick, or a tick following a burst of NAKs, can't push
+    /// the output rate above the configured bandwidth by more than this burst. Smaller values are
+    /// friendlier to policed links. 0 means no limit: every elapsed send period is spent at once.
+    ///
+    /// Default: 0
+    pub max_burst: PacketCount,
+
+    /// Send every packet a second time, this long after the first, whether or not the receiver
+    /// reports it lost.
+    ///
+    /// On links with heavy or bursty loss a NAK round trip may not fit into the latency, the copy
+    /// gets there without one, at the cost of doubling the bandwidth. The interval should be longer
+    /// than the loss bursts, so both copies aren't lost together, and well below the latency.
+    /// Copies of packets that have been acknowledged by then aren't sent, the ones that are sent
+    /// are counted in `tx_duplicate_data`, apart from retransmissions.
+    ///
+    /// Default: None
+    pub duplicate_interval: Option<Duration>,
+
+    /// The most the send buffer may hold, in bytes on the wire, on top of its limit in packets.
+    ///
+    /// The send buffer holds the data handed to the socket until the peer acknowledges it. When
+    /// the network stalls it fills up with data that can't be sent, and small packets fit many
+    /// more messages into the same number of packets. Once full, the oldest messages are dropped,
+    /// sent or not, to make room for new ones, and counted in `tx_dropped_data` and
+    /// `tx_buffer_overflow_data`. It must hold at least one full sized packet (1500 bytes).
+    ///
+    /// Default: None, only [`buffer_size`](Self::buffer_size) limits the number of packets
+    pub max_buffer_bytes: Option<ByteCount>,
+
+    /// The most the send buffer may hold, as the time between the source times of its oldest and
+    /// newest packets, on top of its limits in packets and bytes.
+    ///
+    /// A buffer bounded by time holds the same span of the stream whatever its bitrate, e.g. to
+    /// never queue more than the latency.
+    ///
+    /// Default: None
+    pub max_buffer_time: Option<Duration>,
+
+    /// What happens to new messages once the send buffer is full.
+    ///
+    /// By default the oldest messages make room for them, which suits live streams best. An
+    /// application that would rather slow its source down can have the socket stop taking
+    /// messages instead, its `Sink` stays pending until the peer acknowledged enough to make room.
+    ///
+    /// Default: SendBufferPolicy::DropOldest
+    pub buffer_full_policy: SendBufferPolicy,
+
+    /// Retransmit the oldest unacknowledged packet once this many ACKs in a row carry the same
+    /// ACK number while it's still outstanding, like TCP does on duplicate ACKs.
+    ///
+    /// The receiver keeps acknowledging up to the first packet it is missing, so repeated ACK
+    /// numbers hint that the packet was lost, even when the NAK reporting it was lost on the way
+    /// back. Each ACK number triggers at most one such retransmission, NAKs and the
+    /// retransmission timeout take care of the rest. This receiver mostly repeats ACK numbers in
+    /// its light ACKs, every 64 packets, so the hint comes sooner at high packet rates. The ones
+    /// sent count in `tx_fast_retransmit_data`, and as retransmissions.
+    ///
+    /// Default: None
+    pub fast_retransmit: Option<u32>,
+}
+
+/// See [`Sender::buffer_full_policy`]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SendBufferPolicy {
+    /// Drop the oldest messages, sent or not, to make room for new ones
+    #[default]
+    DropOldest,
+    /// Don't take new messages until there is room for them, the socket's `Sink` is pending
+    Block,
+    /// Like `Block`, but the socket's `Sink` fails with [`std::io::ErrorKind::WouldBlock`]
+    /// rather than wait
+    Error,
+}
+
+/// See [`Sender::ack2_mode`]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Ack2Mode {
+    /// Respond to every full ACK with an ACK2
+    #[default]
+    EveryFullAck,
+    /// Respond to at most one full ACK per SYN interval, unless the ACK is a repeat of the last
+    /// one acknowledged
+    Throttled,
 }
 
 impl Default for Sender {
@@ -127,7 +248,16 @@ impl Default for Sender {
             bandwidth: Default::default(),
             flow_control_window_size: PacketCount(25600),
             max_payload_size: PacketSize(1316),
+            limit_payload_size: false,
             intensive_retransmission: false,
+            ack2_mode: Ack2Mode::EveryFullAck,
+            lite_ack_rtt_sampling: false,
+            max_burst: PacketCount(0),
+            duplicate_interval: None,
+            max_buffer_bytes: None,
+            max_buffer_time: None,
+            buffer_full_policy: SendBufferPolicy::DropOldest,
+            fast_retransmit: None,
         }
     }
 }
@@ -139,6 +269,12 @@ impl Validation for Sender {
         use OptionsError::*;
         if self.flow_control_window_size < PacketCount(32) {
             Err(FlowControlWindowMin(self.flow_control_window_size))
+        } else if let Some(bytes) = self.max_buffer_bytes.filter(|b| *b < ByteCount(1500)) {
+            Err(SendBufferBytesMin(bytes))
+        } else if self.max_buffer_time == Some(Duration::ZERO) {
+            Err(SendBufferTimeZero)
+        } else if self.fast_retransmit == Some(0) {
+            Err(FastRetransmitZero)
         } else {
             Ok(())
         }
@@ -162,5 +298,29 @@ mod tests {
             result.try_validate(),
             Err(FlowControlWindowMin(PacketCount(31)))
         );
+
+        let result = Sender {
+            max_buffer_bytes: Some(ByteCount(1499)),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            result.try_validate(),
+            Err(SendBufferBytesMin(ByteCount(1499)))
+        );
+
+        let result = Sender {
+            max_buffer_time: Some(Duration::ZERO),
+            ..Default::default()
+        };
+
+        assert_eq!(result.try_validate(), Err(SendBufferTimeZero));
+
+        let result = Sender {
+            fast_retransmit: Some(0),
+            ..Default::default()
+        };
+
+        assert_eq!(result.try_validate(), Err(FastRetransmitZero));
     }
 }
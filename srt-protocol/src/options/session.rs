@@ -1,4 +1,9 @@
-use std::time::Duration;
+use std::{net::SocketAddr, time::Duration};
+
+use crate::{
+    packet::{SeqNumber, SocketId},
+    protocol::time::Instant,
+};
 
 use super::*;
 
@@ -26,6 +31,150 @@ pub struct Session {
     pub max_segment_size: PacketSize,
 
     pub statistics_interval: Duration,
+
+    /// Close each direction of the connection independently.
+    ///
+    /// Normally closing the data stream of one peer closes the whole connection: once the
+    /// sender has flushed it sends a Shutdown packet, and both peers close as soon as their
+    /// receive buffers are flushed. With half close enabled a Shutdown packet only means that the
+    /// peer has finished sending. The receive direction ends once everything the peer sent has
+    /// been released, while this side can carry on sending until its own data stream is closed,
+    /// which suits request/response exchanges. Both peers need to enable it; libsrt closes the
+    /// whole connection when it receives a Shutdown packet.
+    ///
+    /// Default: false
+    pub half_close: bool,
+
+    /// How long this side may go without sending the peer anything before it sends a keepalive,
+    /// which keeps the mappings of NATs and stateful firewalls on the way open on a sparse or
+    /// idle stream. Any packet sent counts, so a stream that's flowing doesn't send any.
+    ///
+    /// Default: 1 s, as libsrt
+    pub keepalive_interval: Duration,
+
+    /// Send keepalives four times as often while nothing has been heard from the peer for longer
+    /// than [`keepalive_interval`](Self::keepalive_interval), until it's heard from again. When
+    /// a NAT dropped the mapping of the connection, the peer's packets stop arriving, and the
+    /// next keepalive that gets out sets it up again, so the stream recovers sooner.
+    ///
+    /// Default: false
+    pub adaptive_keepalive: bool,
+
+    /// Carry a 32-bit tag of the application's with every message, e.g. its frame number, to
+    /// correlate messages on either side of the connection when debugging.
+    ///
+    /// The tag goes in front of the message's payload, so a message takes 4 bytes more of the
+    /// payload of its packets. Both peers need to enable it, which they agree on in the handshake,
+    /// a connection where only one side does is refused, and so is one to a peer of another
+    /// implementation that doesn't know of tags.
+    ///
+    /// Default: false
+    pub message_tags: bool,
+
+    /// SRTO_MESSAGEAPI
+    ///
+    /// Whether the data is a sequence of messages, each released to the receiving application as
+    /// a whole, or a stream of bytes (stream mode), released as it's received, in order, however
+    /// the sender split it, e.g. to tunnel TCP over SRT. Both peers must agree, otherwise the
+    /// connection is rejected.
+    ///
+    /// In stream mode this side doesn't ask the peer to drop packets that are too late, a gap
+    /// would corrupt the stream, and [`message_tags`](Self::message_tags) can't be used.
+    ///
+    /// Default: true
+    pub message_api: bool,
+
+    /// Whether an established connection follows its peer to another address. Packets from
+    /// elsewhere that carry the connection's socket id are taken for the peer's, e.g. after its
+    /// NAT picked a new port, and the connection sends to where they came from from then on.
+    /// Following the peer to any host, with [`PeerAddressPolicy::Rebind`], also lets anyone who
+    /// learns the socket id inject packets, or take the stream over, so it has to be asked for.
+    ///
+    /// Packets that aren't taken are dropped and counted in
+    /// [`rx_foreign_packets`](crate::statistics::SocketStatistics::rx_foreign_packets).
+    ///
+    /// Default: [`PeerAddressPolicy::RebindPort`]
+    pub peer_address_policy: PeerAddressPolicy,
+
+    /// The lowest TSBPD latency, in either direction, a connection accepted by this side will
+    /// use. A lower proposal from the peer is raised to it.
+    ///
+    /// It only applies to the responding side of the handshake, i.e. listeners, and rendezvous
+    /// connections this side responds to.
+    ///
+    /// Default: 0
+    pub min_latency: Duration,
+
+    /// The highest TSBPD latency, in either direction, a connection accepted by this side will
+    /// use. What happens to a peer proposing more is up to [`latency_policy`](Self::latency_policy).
+    ///
+    /// Normally the latency of each direction is the larger of the two peers' proposals, so an
+    /// off-the-wall value from the caller would hold back every packet for that long.
+    ///
+    /// Default: unlimited
+    pub max_latency: Duration,
+
+    /// What to do with a peer proposing more than [`max_latency`](Self::max_latency)
+    ///
+    /// Default: [`LatencyPolicy::Clamp`]
+    pub latency_policy: LatencyPolicy,
+
+    /// Identify this side of the connection with this socket id instead of a random one, e.g. to
+    /// reproduce a test run or a peer bug that only shows with specific values. The connections
+    /// of a multiplexing listener share its UDP socket, so each of them still gets a random one.
+    /// Binding fails when another connection of the process uses the id already.
+    ///
+    /// Default: random, and unique among the connections of the process
+    pub socket_id: Option<SocketId>,
+
+    /// The sequence number of the first data packet this side sends. Like libsrt, the initiating
+    /// side of the handshake picks it for both directions, i.e. callers, and the side of a
+    /// rendezvous connection that wins the cookie contest; it's ignored otherwise.
+    ///
+    /// Default: random
+    pub initial_sequence_number: Option<SeqNumber>,
+
+    /// The instant the timestamps of the connection count from, instead of when the handshake
+    /// started. It has to be on the clock the connection runs on and no later than the
+    /// connection is established.
+    ///
+    /// Default: when the handshake started
+    pub start_time: Option<Instant>,
+}
+
+/// See [`Session::latency_policy`]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum LatencyPolicy {
+    /// Accept the connection with the latency lowered to [`Session::max_latency`]. The peer
+    /// learns the latency from the handshake response.
+    #[default]
+    Clamp,
+    /// Reject the connection with [`ServerRejectReason::Unacceptable`](crate::packet::ServerRejectReason::Unacceptable)
+    Reject,
+}
+
+/// See [`Session::peer_address_policy`]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum PeerAddressPolicy {
+    /// Follow the peer to any address
+    Rebind,
+    /// Follow the peer to another port of the same IP address, which is what a NAT rebinding
+    /// looks like
+    #[default]
+    RebindPort,
+    /// Only take packets from the address the connection was established with
+    Reject,
+}
+
+impl PeerAddressPolicy {
+    /// Whether a peer at `current` may move to `new`
+    pub fn allows(self, current: SocketAddr, new: SocketAddr) -> bool {
+        match self {
+            PeerAddressPolicy::Rebind => true,
+            PeerAddressPolicy::RebindPort => current.ip() == new.ip(),
+            PeerAddressPolicy::Reject => current == new,
+        }
+    }
 }
 
 impl Default for Session {
@@ -34,6 +183,18 @@ impl Default for Session {
             peer_idle_timeout: Duration::from_secs(5),
             max_segment_size: PacketSize(1500),
             statistics_interval: Duration::from_secs(1),
+            half_close: false,
+            keepalive_interval: Duration::from_secs(1),
+            adaptive_keepalive: false,
+            message_tags: false,
+            message_api: true,
+            peer_address_policy: PeerAddressPolicy::RebindPort,
+            min_latency: Duration::ZERO,
+            max_latency: Duration::MAX,
+            latency_policy: LatencyPolicy::Clamp,
+            socket_id: None,
+            initial_sequence_number: None,
+            start_time: None,
         }
     }
 }
@@ -47,6 +208,14 @@ impl Validation for Session {
             Err(MaxSegmentSizeOutOfRange(self.max_segment_size))
         } else if self.statistics_interval < Duration::from_millis(200) {
             Err(StatisticsIntervalOutOfRange(self.statistics_interval))
+        } else if self.min_latency > self.max_latency {
+            Err(LatencyRange(self.min_latency, self.max_latency))
+        } else if self.socket_id == Some(SocketId(0)) {
+            Err(InvalidSocketId)
+        } else if self.keepalive_interval.is_zero() {
+            Err(KeepaliveIntervalZero)
+        } else if self.message_tags && !self.message_api {
+            Err(MessageTagsInStreamMode)
         } else {
             Ok(())
         }
@@ -63,6 +63,15 @@ impl CompositeValidation for SocketOptions {
             });
         }
 
+        // what's left of the MSS after the IP, UDP and SRT headers
+        let max_payload = self.session.max_segment_size - crate::packet::Packet::HEADER_SIZE;
+        if self.sender.max_payload_size > max_payload {
+            return Err(OptionsError::PayloadSizeTooLarge {
+                payload: self.sender.max_payload_size,
+                max_segment: self.session.max_segment_size,
+            });
+        }
+
         Ok(())
     }
 }
@@ -198,6 +207,21 @@ mod test {
             Ok(_)
         );
 
+        assert_eq!(
+            SocketOptions::new().set(|op| {
+                op.session.max_segment_size = PacketSize(1500);
+                op.sender.max_payload_size = PacketSize(1457);
+            }),
+            Err(OptionsError::PayloadSizeTooLarge {
+                payload: PacketSize(1457),
+                max_segment: PacketSize(1500),
+            })
+        );
+        assert_matches!(
+            SocketOptions::new().set(|op| op.sender.max_payload_size = PacketSize(1456)),
+            Ok(_)
+        );
+
         Ok(())
     }
 }
@@ -1,4 +1,4 @@
-use std::{cmp::Ordering, fmt};
+use core::{cmp::Ordering, fmt};
 
 /// Serialied, it looks like:
 /// major * 0x10000 + minor * 0x100 + patch
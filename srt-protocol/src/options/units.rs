@@ -1,4 +1,4 @@
-use std::{
+use core::{
     ops::{Div, Mul},
     time::Duration,
 };
@@ -1,4 +1,4 @@
-use std::iter::Iterator;
+use core::iter::Iterator;
 
 use super::SeqNumber;
 
@@ -3,7 +3,8 @@ mod srt;
 
 pub use srt::*;
 
-use std::{
+use alloc::{string::String, vec, vec::Vec};
+use core::{
     convert::TryFrom,
     convert::TryInto,
     fmt::{self, Debug, Display, Formatter},
@@ -14,7 +15,7 @@ use std::{
 };
 
 use bitflags::bitflags;
-use bytes::{Buf, BufMut};
+use bytes::{Buf, BufMut, Bytes};
 use log::warn;
 
 use crate::{
@@ -133,10 +134,23 @@ pub struct HsV5Info {
     /// The extension KMREQ/KMRESP
     pub ext_km: Option<SrtControlPacket>,
 
+    /// The extension GROUP, the bonding group of a libsrt peer
     pub ext_group: Option<SrtControlPacket>,
 
     /// The SID
     pub sid: Option<String>,
+
+    /// Offers to encrypt control packets, an extension of this implementation, see
+    /// [`SrtControlPacket::EncryptedControl`]
+    pub encrypted_control: bool,
+
+    /// Tags every message, an extension of this implementation, both sides have to, see
+    /// [`message_tags`](crate::options::Session::message_tags)
+    pub message_tags: bool,
+
+    /// Offers to report Congestion Experienced marks, an extension of this implementation, see
+    /// [`SrtControlPacket::CongestionExperienced`]
+    pub congestion_experienced: bool,
 }
 
 /// HS-version dependenent data
@@ -340,7 +354,13 @@ impl HandshakeVsInfo {
             HandshakeVsInfo::V4(ty) => *ty as u32,
             HandshakeVsInfo::V5(hs) => {
                 if shake_type == ShakeType::Induction
-                    && (hs.ext_hs.is_some() || hs.ext_km.is_some() || hs.sid.is_some())
+                    && (hs.ext_hs.is_some()
+                        || hs.ext_km.is_some()
+                        || hs.ext_group.is_some()
+                        || hs.sid.is_some()
+                        || hs.encrypted_control
+                        || hs.message_tags
+                        || hs.congestion_experienced)
                 {
                     // induction does not include any extensions, and instead has the
                     // magic code. this is an incompatialbe place to be.
@@ -355,7 +375,12 @@ impl HandshakeVsInfo {
                 if hs.ext_km.is_some() {
                     flags |= ExtFlags::KM;
                 }
-                if hs.sid.is_some() {
+                if hs.ext_group.is_some()
+                    || hs.sid.is_some()
+                    || hs.encrypted_control
+                    || hs.message_tags
+                    || hs.congestion_experienced
+                {
                     flags |= ExtFlags::CONFIG;
                 }
                 // take the crypto size, get rid of the frist three (guaranteed zero) bits, then shift it into the
@@ -392,7 +417,13 @@ impl SocketType {
 }
 
 impl ControlPacket {
+    /// Parses a control packet from the whole of `buf`, starting with its 16 byte header, see
+    /// [`Packet::parse`](super::Packet::parse)
     pub fn parse(buf: &mut impl Buf, is_ipv6: bool) -> Result<ControlPacket, PacketParseError> {
+        if buf.remaining() < 16 {
+            return Err(PacketParseError::NotEnoughData);
+        }
+
         let control_type = buf.get_u16() << 1 >> 1; // clear first bit
 
         // get reserved data, which is the last two bytes of the first four bytes
@@ -553,9 +584,9 @@ impl ControlTypes {
                     }),
                     5 => {
                         // make sure crypto size is of a valid variant
-                        let crypto_size = match KeySize::try_from(crypto_size) {
-                            Ok(size) => size,
-                            Err(_) => {
+                        let crypto_size = match KeySize::from_raw(crypto_size) {
+                            Some(size) => size,
+                            None => {
                                 warn!(
                                     "Unrecognized crypto key length: {}, disabling encryption. Should be 0, 16, 24, or 32 bytes.",
                                    crypto_size
@@ -590,8 +621,13 @@ impl ControlTypes {
                             let mut sid = None;
                             let mut ext_hs = None;
                             let mut ext_km = None;
+                            let mut ext_group = None;
+                            let mut encrypted_control = false;
+                            let mut message_tags = false;
+                            let mut congestion_experienced = false;
 
-                            while buf.remaining() > 4 {
+                            // an extension may be just its type and size
+                            while buf.remaining() >= 4 {
                                 let pack_type = buf.get_u16();
 
                                 let pack_size_words = buf.get_u16();
@@ -632,7 +668,26 @@ impl ControlTypes {
                                             SrtControlPacket::StreamId(stream_id) => {
                                                 sid = Some(stream_id)
                                             }
-                                            _ => unimplemented!("Implement other kinds"),
+                                            pack @ SrtControlPacket::Group { .. } => {
+                                                ext_group = Some(pack)
+                                            }
+                                            SrtControlPacket::Extension {
+                                                ty: SrtControlPacket::ENCRYPTED_CONTROL_TYPE_ID,
+                                                ..
+                                            } => encrypted_control = true,
+                                            SrtControlPacket::Extension {
+                                                ty: SrtControlPacket::MESSAGE_TAGS_TYPE_ID,
+                                                ..
+                                            } => message_tags = true,
+                                            SrtControlPacket::CongestionExperienced(_) => {
+                                                congestion_experienced = true
+                                            }
+                                            // e.g. the congestion control or packet filter of
+                                            // a libsrt peer, which this side doesn't have
+                                            pack => warn!(
+                                                "Ignoring unsupported handshake extension type {}",
+                                                pack.type_id()
+                                            ),
                                         }
                                     }
                                 }
@@ -653,8 +708,11 @@ impl ControlTypes {
                                 key_size: crypto_size,
                                 ext_hs,
                                 ext_km,
-                                ext_group: None,
+                                ext_group,
                                 sid,
+                                encrypted_control,
+                                message_tags,
+                                congestion_experienced,
                             })
                         }
                     }
@@ -728,6 +786,13 @@ impl ControlTypes {
                 while buf.remaining() >= 4 {
                     loss_info.push(buf.get_u32());
                 }
+                // a range is its first and last sequence number, the first one flagged
+                let mut words = loss_info.iter();
+                while let Some(word) = words.next() {
+                    if word & 0x8000_0000 != 0 && words.next().is_none() {
+                        return Err(PacketParseError::NotEnoughData);
+                    }
+                }
 
                 Ok(ControlTypes::Nak(CompressedLossList(loss_info)))
             }
@@ -915,6 +980,50 @@ impl CompressedLossList {
     pub fn into_iter_decompressed(self) -> impl Iterator<Item = SeqNumber> {
         decompress_loss_list(self.0.into_iter())
     }
+
+    /// The lost packets as ranges, in the order they are listed
+    pub fn iter_ranges(&self) -> impl Iterator<Item = Range<SeqNumber>> + '_ {
+        let mut compressed = self.iter_compressed();
+        core::iter::from_fn(move || {
+            let first = compressed.next()?;
+            let start = SeqNumber::new_truncate(first & !(1 << 31));
+            let last = if first & (1 << 31) != 0 {
+                SeqNumber::new_truncate(compressed.next()?)
+            } else {
+                start
+            };
+            Some(start..last + 1)
+        })
+    }
+
+    /// One list with the losses of both, overlapping and adjacent ranges joined, unless it takes
+    /// more than `max_len` 32-bit words
+    pub fn try_merge(&self, other: &CompressedLossList, max_len: usize) -> Option<Self> {
+        let mut ranges = self
+            .iter_ranges()
+            .chain(other.iter_ranges())
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|range| range.start);
+
+        let mut merged: Vec<Range<SeqNumber>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+
+        let mut loss_list = Vec::with_capacity(2 * merged.len());
+        for range in merged {
+            if range.start + 1 == range.end {
+                loss_list.push(range.start.as_raw());
+            } else {
+                loss_list.push((1 << 31) | range.start.as_raw());
+                loss_list.push((range.end - 1).as_raw());
+            }
+        }
+        (loss_list.len() <= max_len).then_some(CompressedLossList(loss_list))
+    }
 }
 
 impl FromIterator<SeqNumber> for CompressedLossList {
@@ -1069,9 +1178,21 @@ impl Debug for HandshakeVsInfo {
                 if let Some(pack) = &hs.ext_km {
                     write!(f, " km={pack:?}")?;
                 }
+                if let Some(pack) = &hs.ext_group {
+                    write!(f, " {pack:?}")?;
+                }
                 if let Some(sid) = &hs.sid {
                     write!(f, " sid={sid:?}")?;
                 }
+                if hs.encrypted_control {
+                    write!(f, " encrypted_control")?;
+                }
+                if hs.message_tags {
+                    write!(f, " message_tags")?;
+                }
+                if hs.congestion_experienced {
+                    write!(f, " congestion_experienced")?;
+                }
                 Ok(())
             }
         }
@@ -1295,7 +1416,15 @@ impl HandshakeControlInfo {
                 +
                 info.ext_km.as_ref().map(|hs| 2 * size_of::<u16>() + usize::from(hs.size_words()) * size_of::<u32>()).unwrap_or(0)
                 +
+                info.ext_group.as_ref().map(|hs| 2 * size_of::<u16>() + usize::from(hs.size_words()) * size_of::<u32>()).unwrap_or(0)
+                +
                 info.sid.as_ref().map(|sid| 2 * size_of::<u16>() + ((sid.len() + 3) / 4 * 4)).unwrap_or(0)
+                +
+                if info.encrypted_control { 2 * size_of::<u16>() } else { 0 }
+                +
+                if info.message_tags { 2 * size_of::<u16>() } else { 0 }
+                +
+                if info.congestion_experienced { 2 * size_of::<u16>() + size_of::<u32>() } else { 0 }
             }
         }
     }
@@ -1330,7 +1459,18 @@ impl HandshakeControlInfo {
             for ext in [
                 &hs.ext_hs,
                 &hs.ext_km,
+                &hs.ext_group,
                 &hs.sid.clone().map(SrtControlPacket::StreamId),
+                &hs.encrypted_control.then(|| SrtControlPacket::Extension {
+                    ty: SrtControlPacket::ENCRYPTED_CONTROL_TYPE_ID,
+                    payload: Bytes::new(),
+                }),
+                &hs.message_tags.then(|| SrtControlPacket::Extension {
+                    ty: SrtControlPacket::MESSAGE_TAGS_TYPE_ID,
+                    payload: Bytes::new(),
+                }),
+                &hs.congestion_experienced
+                    .then_some(SrtControlPacket::CongestionExperienced(0)),
             ]
             .into_iter()
             .filter_map(|s| s.as_ref())
@@ -1371,6 +1511,8 @@ mod test {
 
     use std::{convert::TryInto, io::Cursor, time::Duration};
 
+    use bytes::Bytes;
+
     use crate::options::*;
 
     fn ser_des_test(pack: ControlPacket) -> Vec<u8> {
@@ -1422,6 +1564,9 @@ mod test {
                     })),
                     ext_km: None,
                     ext_group: None,
+                    encrypted_control: false,
+                    message_tags: false,
+                    congestion_experienced: false,
                     sid: None,
                 }),
             }),
@@ -1447,6 +1592,41 @@ mod test {
         });
     }
 
+    #[test]
+    fn raw_full_ack() {
+        // a full ACK laid out the way the reference implementation sends it: packets/s received,
+        // the link capacity estimate in packets/s, then bytes/s received
+        let packet_data = hex::decode(concat!(
+            "80020000", "00000007", "000186A0", "2BFFEFF2", // header, ACK number 7
+            "00012345", "00002710", "00001388", "00001FEF", // seq, RTT, RTT var, buffer
+            "000003E8", "000061A8", "001414A0", // 1000 pkts/s, 25000 pkts/s, 1316000 B/s
+        ))
+        .unwrap();
+        let packet = ControlPacket::parse(&mut Cursor::new(&packet_data[..]), false).unwrap();
+        assert_eq!(
+            packet,
+            ControlPacket {
+                timestamp: TimeStamp::from_micros(100_000),
+                dest_sockid: SocketId(0x2BFF_EFF2),
+                control_type: ControlTypes::Ack(Acknowledgement::Full(
+                    SeqNumber(0x12345),
+                    AckStatistics {
+                        rtt: Rtt::new(TimeSpan::from_micros(10_000), TimeSpan::from_micros(5_000)),
+                        buffer_available: 8175,
+                        packet_receive_rate: Some(1000),
+                        estimated_link_capacity: Some(25_000),
+                        data_receive_rate: Some(1_316_000),
+                    },
+                    FullAckSeqNumber::new(7).unwrap(),
+                )),
+            }
+        );
+
+        let mut buf = Vec::new();
+        packet.serialize(&mut buf);
+        assert_eq!(buf, packet_data);
+    }
+
     #[test]
     fn ack2_ser_des_test() {
         let buf = ser_des_test(ControlPacket {
@@ -1477,6 +1657,9 @@ mod test {
                     ext_km: None,
                     ext_hs: None,
                     ext_group: None,
+                    encrypted_control: false,
+                    message_tags: false,
+                    congestion_experienced: false,
                     sid: None,
                 }),
             }),
@@ -1501,12 +1684,150 @@ mod test {
                     ext_km: None,
                     ext_hs: None,
                     ext_group: None,
+                    encrypted_control: false,
+                    message_tags: false,
+                    congestion_experienced: false,
+                    sid: Some("Hello hello".into()),
+                }),
+            }),
+        });
+    }
+
+    #[test]
+    fn group_handshake_test() {
+        let packet = ControlPacket {
+            timestamp: TimeStamp::from_micros(0),
+            dest_sockid: SocketId(0),
+            control_type: ControlTypes::Handshake(HandshakeControlInfo {
+                init_seq_num: SeqNumber(0),
+                max_packet_size: PacketSize(1816),
+                max_flow_size: PacketCount(0),
+                shake_type: ShakeType::Conclusion,
+                socket_id: SocketId(0),
+                syn_cookie: 0,
+                peer_addr: [127, 0, 0, 1].into(),
+                info: HandshakeVsInfo::V5(HsV5Info {
+                    key_size: KeySize::Unspecified,
+                    ext_km: None,
+                    ext_hs: None,
+                    ext_group: Some(SrtControlPacket::Group {
+                        id: SocketId(0x4000_0001),
+                        ty: GroupType::MainBackup,
+                        flags: GroupFlags::MSG_SYNC,
+                        weight: 10,
+                    }),
+                    encrypted_control: false,
+                    message_tags: false,
+                    congestion_experienced: false,
+                    sid: None,
+                }),
+            }),
+        };
+
+        // as libsrt has it: the group id, then type, flags and weight in a word
+        let mut buf = Vec::new();
+        packet.serialize(&mut buf);
+        assert!(
+            buf.ends_with(&[0, 8, 0, 2, 0x40, 0, 0, 1, 2, 1, 0, 10]),
+            "{buf:02x?}"
+        );
+        ser_des_test(packet);
+    }
+
+    #[test]
+    fn encrypted_control_ser_des_test() {
+        ser_des_test(ControlPacket {
+            timestamp: TimeStamp::from_micros(0),
+            dest_sockid: SocketId(0),
+            control_type: ControlTypes::Handshake(HandshakeControlInfo {
+                init_seq_num: SeqNumber(0),
+                max_packet_size: PacketSize(1816),
+                max_flow_size: PacketCount(0),
+                shake_type: ShakeType::Conclusion,
+                socket_id: SocketId(0),
+                syn_cookie: 0,
+                peer_addr: [127, 0, 0, 1].into(),
+                info: HandshakeVsInfo::V5(HsV5Info {
+                    key_size: KeySize::AES128,
+                    ext_km: None,
+                    ext_hs: None,
+                    ext_group: None,
+                    encrypted_control: true,
+                    message_tags: false,
+                    congestion_experienced: false,
                     sid: Some("Hello hello".into()),
                 }),
             }),
         });
     }
 
+    #[test]
+    fn message_tags_ser_des_test() {
+        ser_des_test(ControlPacket {
+            timestamp: TimeStamp::from_micros(0),
+            dest_sockid: SocketId(0),
+            control_type: ControlTypes::Handshake(HandshakeControlInfo {
+                init_seq_num: SeqNumber(0),
+                max_packet_size: PacketSize(1816),
+                max_flow_size: PacketCount(0),
+                shake_type: ShakeType::Conclusion,
+                socket_id: SocketId(0),
+                syn_cookie: 0,
+                peer_addr: [127, 0, 0, 1].into(),
+                info: HandshakeVsInfo::V5(HsV5Info {
+                    key_size: KeySize::Unspecified,
+                    ext_km: None,
+                    ext_hs: None,
+                    ext_group: None,
+                    encrypted_control: false,
+                    message_tags: true,
+                    congestion_experienced: false,
+                    sid: None,
+                }),
+            }),
+        });
+    }
+
+    #[test]
+    fn congestion_experienced_handshake_ser_des_test() {
+        ser_des_test(ControlPacket {
+            timestamp: TimeStamp::from_micros(0),
+            dest_sockid: SocketId(0),
+            control_type: ControlTypes::Handshake(HandshakeControlInfo {
+                init_seq_num: SeqNumber(0),
+                max_packet_size: PacketSize(1816),
+                max_flow_size: PacketCount(0),
+                shake_type: ShakeType::Conclusion,
+                socket_id: SocketId(0),
+                syn_cookie: 0,
+                peer_addr: [127, 0, 0, 1].into(),
+                info: HandshakeVsInfo::V5(HsV5Info {
+                    key_size: KeySize::Unspecified,
+                    ext_km: None,
+                    ext_hs: None,
+                    ext_group: None,
+                    encrypted_control: false,
+                    message_tags: false,
+                    congestion_experienced: true,
+                    sid: None,
+                }),
+            }),
+        });
+    }
+
+    #[test]
+    fn unterminated_loss_range() {
+        let mut packet_data = Vec::new();
+        packet_data.put_u32(0x8003_0000);
+        packet_data.put_bytes(0, 12);
+        packet_data.put_u32(5);
+        packet_data.put_u32(0x8000_0007);
+        assert_eq!(
+            ControlPacket::parse(&mut &packet_data[..], false),
+            Err(PacketParseError::NotEnoughData)
+        );
+    }
+
     #[test]
     fn keepalive_ser_des_test() {
         ser_des_test(ControlPacket {
@@ -1549,6 +1870,7 @@ mod test {
             timestamp: TimeStamp::from_micros(100),
             dest_sockid: rand::random(),
             control_type: ControlTypes::Srt(SrtControlPacket::Group {
+                id: SocketId(0x4000_0001),
                 ty: GroupType::MainBackup,
                 flags: GroupFlags::MSG_SYNC,
                 weight: 123,
@@ -1556,6 +1878,18 @@ mod test {
         });
     }
 
+    #[test]
+    fn extension_ser_des_test() {
+        ser_des_test(ControlPacket {
+            timestamp: TimeStamp::from_micros(100),
+            dest_sockid: rand::random(),
+            control_type: ControlTypes::Srt(SrtControlPacket::Extension {
+                ty: 0x1234,
+                payload: Bytes::from_static(b"research"),
+            }),
+        });
+    }
+
     #[test]
     fn drop_request_ser_des_test() {
         ser_des_test(ControlPacket {
@@ -1661,6 +1995,9 @@ mod test {
                         })),
                         ext_km: None,
                         ext_group: None,
+                        encrypted_control: false,
+                        message_tags: false,
+                        congestion_experienced: false,
                         sid: None,
                     })
                 })
@@ -1709,6 +2046,9 @@ mod test {
                         })),
                         ext_km: None,
                         ext_group: None,
+                        encrypted_control: false,
+                        message_tags: false,
+                        congestion_experienced: false,
                         sid: Some(String::from("abcdefghij")),
                     })
                 })
@@ -1766,6 +2106,9 @@ mod test {
                             .unwrap()
                         })),
                         ext_group: None,
+                        encrypted_control: false,
+                        message_tags: false,
+                        congestion_experienced: false,
                         sid: None,
                     })
                 })
@@ -1862,6 +2205,9 @@ mod test {
                             .unwrap()
                     })),
                     ext_group: None,
+                    encrypted_control: false,
+                    message_tags: false,
+                    congestion_experienced: false,
                     sid: Some("#!::u=hex".into()),
                 }),
             }),
@@ -1872,4 +2218,37 @@ mod test {
 
     #[test]
     fn drop_request() {}
+
+    #[test]
+    fn merge_loss_lists() {
+        let list = |seq_numbers: &[u32]| {
+            seq_numbers
+                .iter()
+                .map(|n| SeqNumber::new_truncate(*n))
+                .collect::<CompressedLossList>()
+        };
+
+        // overlapping, adjacent and out of order ranges
+        let merged = list(&[5, 6, 7, 20]).try_merge(&list(&[1, 7, 8, 9, 21]), 10);
+        assert_eq!(merged, Some(list(&[1, 5, 6, 7, 8, 9, 20, 21])));
+        assert_eq!(
+            merged.unwrap().iter_ranges().collect::<Vec<_>>(),
+            vec![
+                SeqNumber(1)..SeqNumber(2),
+                SeqNumber(5)..SeqNumber(10),
+                SeqNumber(20)..SeqNumber(22)
+            ]
+        );
+
+        // the list has to fit into a packet
+        assert_eq!(list(&[1]).try_merge(&list(&[3]), 2), Some(list(&[1, 3])));
+        assert_eq!(list(&[1]).try_merge(&list(&[3, 5]), 2), None);
+
+        // across the wrap around of the sequence numbers
+        let last = SeqNumber::MAX - 1;
+        assert_eq!(
+            list(&[last - 1, last]).try_merge(&list(&[0, 1]), 2),
+            Some(list(&[last - 1, last, 0, 1]))
+        );
+    }
 }
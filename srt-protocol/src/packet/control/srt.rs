@@ -1,13 +1,23 @@
-use std::{
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{
     fmt::{self, Display, Formatter},
-    {collections::BTreeMap, convert::TryFrom, time::Duration},
+    {convert::TryFrom, time::Duration},
 };
 
 use bitflags::bitflags;
-use bytes::{Buf, BufMut};
+use bytes::{Buf, BufMut, Bytes};
 use log::warn;
 
-use crate::{options::SrtVersion, packet::PacketParseError};
+use crate::{
+    options::SrtVersion,
+    packet::{DataEncryption, PacketParseError, SocketId, TimeStamp},
+};
 
 /// The SRT-specific control packets
 /// These are `Packet::Custom` types
@@ -47,12 +57,52 @@ pub enum SrtControlPacket {
     /// a:b,c:d
     Filter(FilterSpec),
 
-    // ID = 8
+    /// The bonding group a libsrt peer's socket is a member of, in the handshake. The responder
+    /// answers with the id of its own group for the member.
+    /// ID = 8
     Group {
+        id: SocketId,
         ty: GroupType,
         flags: GroupFlags,
         weight: u16,
     },
+
+    /// The number of data packets that arrived with the ECN Congestion Experienced mark since the
+    /// last report. This is an extension of this implementation, not part of SRT, and is only sent
+    /// to a peer that offered it in its handshake.
+    /// ID = 0x7ece
+    CongestionExperienced(u32),
+
+    /// A latency probe, which the peer answers right away with an
+    /// [`EchoReply`](Self::EchoReply). This is an extension of this implementation, not part of
+    /// SRT.
+    /// ID = 0x7ec0
+    EchoRequest { id: u32 },
+
+    /// The answer to an [`EchoRequest`](Self::EchoRequest), with the time it was received on the
+    /// clock of the peer and how many requests the peer received so far. This is an extension of
+    /// this implementation, not part of SRT.
+    /// ID = 0x7ec1
+    EchoReply {
+        id: u32,
+        timestamp: TimeStamp,
+        requests_received: u32,
+    },
+
+    /// A control packet encrypted with the stream encryption key `key`, for peers that agreed to
+    /// it in the handshake, where an empty extension of this type offers it. The nonce is part of
+    /// the IV, so it must not repeat for a key. This is an extension of this implementation, not
+    /// part of SRT.
+    /// ID = 0x7ec2
+    EncryptedControl {
+        key: DataEncryption,
+        nonce: u64,
+        payload: Bytes,
+    },
+
+    /// Any other extension type, e.g. to prototype protocol extensions
+    /// The payload is padded to 32-bit words on the wire
+    Extension { ty: u16, payload: Bytes },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -71,7 +121,8 @@ pub enum GroupType {
 bitflags! {
     #[derive(Clone, Copy, Eq, PartialEq, Debug)]
     pub struct GroupFlags: u8 {
-        const MSG_SYNC = 1 << 6;
+        /// The members are synchronized on the message number, SRT_GFLAG_SYNCONMSG
+        const MSG_SYNC = 1;
     }
 }
 
@@ -260,14 +311,21 @@ bitflags! {
         /// One bit in payload packet msgno is "retransmitted" flag
         const REXMITFLG = 0x20;
 
-        /// This entity supports stream ID packets
+        /// The stream (buffer) API rather than the message API, i.e. file mode. Set when
+        /// [`message_api`](crate::options::Session::message_api) is off, both peers have to agree
+        /// on it, otherwise the connection is rejected
         const STREAM = 0x40;
 
-        /// Again not sure... TODO:
+        /// A packet filter (e.g. FEC) is configured, which isn't implemented
         const PACKET_FILTER = 0x80;
 
-        // currently implemented flags
-        const SUPPORTED = Self::TSBPDSND.bits() | Self::TSBPDRCV.bits() | Self::HAICRYPT.bits() | Self::REXMITFLG.bits();
+        // the flags sent with the default options
+        const SUPPORTED = Self::TSBPDSND.bits()
+            | Self::TSBPDRCV.bits()
+            | Self::HAICRYPT.bits()
+            | Self::TLPKTDROP.bits()
+            | Self::NAKREPORT.bits()
+            | Self::REXMITFLG.bits();
     }
 }
 
@@ -275,6 +333,9 @@ fn le_bytes_to_string(le_bytes: &mut impl Buf) -> Result<String, PacketParseErro
     if le_bytes.remaining() % 4 != 0 {
         return Err(PacketParseError::NotEnoughData);
     }
+    if !le_bytes.has_remaining() {
+        return Ok(String::new());
+    }
 
     let mut str_bytes = Vec::with_capacity(le_bytes.remaining());
 
@@ -323,6 +384,37 @@ impl Display for FilterSpec {
 }
 
 impl SrtControlPacket {
+    /// The highest extension type used by SRT itself
+    pub const MAX_TYPE_ID: u16 = 8;
+
+    /// The extension type of [`CongestionExperienced`](Self::CongestionExperienced) reports
+    pub const CONGESTION_EXPERIENCED_TYPE_ID: u16 = 0x7ece;
+
+    /// The extension type of [`EchoRequest`](Self::EchoRequest) probes
+    pub const ECHO_REQUEST_TYPE_ID: u16 = 0x7ec0;
+
+    /// The extension type of [`EchoReply`](Self::EchoReply) answers
+    pub const ECHO_REPLY_TYPE_ID: u16 = 0x7ec1;
+
+    /// The extension type of [`EncryptedControl`](Self::EncryptedControl) packets
+    pub const ENCRYPTED_CONTROL_TYPE_ID: u16 = 0x7ec2;
+
+    /// The handshake extension that offers message tags, see
+    /// [`message_tags`](crate::options::Session::message_tags)
+    pub const MESSAGE_TAGS_TYPE_ID: u16 = 0x7ec3;
+
+    /// Whether `ty` is an extension type that is taken, by SRT itself or by this implementation
+    pub fn is_reserved_type(ty: u16) -> bool {
+        ty <= Self::MAX_TYPE_ID
+            || matches!(
+                ty,
+                Self::CONGESTION_EXPERIENCED_TYPE_ID
+                    | Self::ECHO_REQUEST_TYPE_ID
+                    | Self::ECHO_REPLY_TYPE_ID
+                    | Self::ENCRYPTED_CONTROL_TYPE_ID
+            )
+    }
+
     pub fn parse<T: Buf>(
         packet_type: u16,
         buf: &mut T,
@@ -364,13 +456,57 @@ impl SrtControlPacket {
                         .collect::<Result<_, _>>()?,
                 )))
             }
+            // the group id, then a word with the type, the flags and the weight of the member, which
+            // older versions of libsrt leave out
             8 => {
-                let ty = buf.get_u8().into();
-                let flags = GroupFlags::from_bits_truncate(buf.get_u8());
-                let weight = buf.get_u16_le();
-                Ok(Group { ty, flags, weight })
+                if buf.remaining() < 4 {
+                    return Err(PacketParseError::NotEnoughData);
+                }
+                let id = SocketId(buf.get_u32());
+                let (ty, flags, weight) = if buf.remaining() >= 4 {
+                    (
+                        buf.get_u8().into(),
+                        GroupFlags::from_bits_truncate(buf.get_u8()),
+                        buf.get_u16(),
+                    )
+                } else {
+                    (GroupType::Undefined, GroupFlags::empty(), 0)
+                };
+                Ok(Group {
+                    id,
+                    ty,
+                    flags,
+                    weight,
+                })
+            }
+            Self::CONGESTION_EXPERIENCED_TYPE_ID if buf.remaining() >= 4 => {
+                Ok(CongestionExperienced(buf.get_u32()))
+            }
+            Self::ECHO_REQUEST_TYPE_ID if buf.remaining() >= 4 => {
+                Ok(EchoRequest { id: buf.get_u32() })
             }
-            _ => Err(PacketParseError::UnsupportedSrtExtensionType(packet_type)),
+            Self::ECHO_REPLY_TYPE_ID if buf.remaining() >= 12 => Ok(EchoReply {
+                id: buf.get_u32(),
+                timestamp: TimeStamp::from_micros(buf.get_u32()),
+                requests_received: buf.get_u32(),
+            }),
+            Self::ENCRYPTED_CONTROL_TYPE_ID if buf.remaining() >= 12 => {
+                let flags = buf.get_u8();
+                let key = match DataEncryption::try_from(flags)? {
+                    DataEncryption::None => return Err(PacketParseError::BadDataEncryption(flags)),
+                    key => key,
+                };
+                buf.advance(3);
+                Ok(EncryptedControl {
+                    key,
+                    nonce: buf.get_u64(),
+                    payload: buf.copy_to_bytes(buf.remaining()),
+                })
+            }
+            ty => Ok(Extension {
+                ty,
+                payload: buf.copy_to_bytes(buf.remaining()),
+            }),
         }
     }
 
@@ -388,6 +524,11 @@ impl SrtControlPacket {
             Congestion(_) => 6,
             Filter(_) => 7,
             Group { .. } => 8,
+            CongestionExperienced(_) => Self::CONGESTION_EXPERIENCED_TYPE_ID,
+            EchoRequest { .. } => Self::ECHO_REQUEST_TYPE_ID,
+            EchoReply { .. } => Self::ECHO_REPLY_TYPE_ID,
+            EncryptedControl { .. } => Self::ENCRYPTED_CONTROL_TYPE_ID,
+            Extension { ty, .. } => *ty,
         }
     }
     pub fn serialize<T: BufMut>(&self, into: &mut T) {
@@ -403,10 +544,41 @@ impl SrtControlPacket {
             Filter(filter) => {
                 string_to_le_bytes(&format!("{filter}"), into);
             }
-            Group { ty, flags, weight } => {
+            Group {
+                id,
+                ty,
+                flags,
+                weight,
+            } => {
+                into.put_u32(id.0);
                 into.put_u8((*ty).into());
                 into.put_u8(flags.bits());
-                into.put_u16_le(*weight);
+                into.put_u16(*weight);
+            }
+            CongestionExperienced(packets) => into.put_u32(*packets),
+            EchoRequest { id } => into.put_u32(*id),
+            EchoReply {
+                id,
+                timestamp,
+                requests_received,
+            } => {
+                into.put_u32(*id);
+                into.put_u32(timestamp.as_micros());
+                into.put_u32(*requests_received);
+            }
+            EncryptedControl {
+                key,
+                nonce,
+                payload,
+            } => {
+                into.put_u32(u32::from(*key as u8) << 24);
+                into.put_u64(*nonce);
+                into.put_slice(payload);
+                into.put_bytes(0, (4 - payload.len() % 4) % 4);
+            }
+            Extension { payload, .. } => {
+                into.put_slice(payload);
+                into.put_bytes(0, (4 - payload.len() % 4) % 4);
             }
             Reject => {}
             StreamId(str) | Congestion(str) => {
@@ -428,10 +600,15 @@ impl SrtControlPacket {
                 4 + k.salt.len() as u16 / 4 + k.wrapped_keys.len() as u16 / 4
             }
             Congestion(str) | StreamId(str) => ((str.len() + 3) / 4) as u16, // round up to nearest multiple of 4
-            // 1 32-bit word packed with type, flags, and weight
-            Group { .. } => 1,
+            // 2 32-bit words, the group id and one packed with type, flags, and weight
+            Group { .. } => 2,
+            CongestionExperienced(_) => 1,
+            EchoRequest { .. } => 1,
+            EchoReply { .. } => 3,
+            EncryptedControl { payload, .. } => 3 + payload.len().div_ceil(4) as u16,
             Filter(filter) => ((format!("{filter}").len() + 3) / 4) as u16, // TODO: not optimial performace, but probably okay
-            _ => unimplemented!("{:?}", self),
+            Extension { payload, .. } => payload.len().div_ceil(4) as u16,
+            Reject => 0,
         }
     }
 }
@@ -591,7 +768,7 @@ impl KeyingMaterialMessage {
         })
     }
 
-    fn serialize<T: BufMut>(&self, into: &mut T) {
+    pub fn serialize<T: BufMut>(&self, into: &mut T) {
         // first 32-bit word:
         //
         //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
@@ -655,8 +832,31 @@ impl fmt::Debug for SrtControlPacket {
             SrtControlPacket::StreamId(sid) => write!(f, "streamid={sid}"),
             SrtControlPacket::Congestion(ctype) => write!(f, "congestion={ctype}"),
             SrtControlPacket::Filter(filter) => write!(f, "filter={filter:?}"),
-            SrtControlPacket::Group { ty, flags, weight } => {
-                write!(f, "group=({ty:?}, {flags:?}, {weight:?})")
+            SrtControlPacket::Group {
+                id,
+                ty,
+                flags,
+                weight,
+            } => {
+                write!(f, "group=({id:?}, {ty:?}, {flags:?}, {weight:?})")
+            }
+            SrtControlPacket::CongestionExperienced(packets) => write!(f, "ce={packets}"),
+            SrtControlPacket::EchoRequest { id } => write!(f, "echo={id}"),
+            SrtControlPacket::EchoReply {
+                id,
+                timestamp,
+                requests_received,
+            } => write!(
+                f,
+                "echoreply={id}, {timestamp:?}, {requests_received} received"
+            ),
+            SrtControlPacket::EncryptedControl {
+                key,
+                nonce,
+                payload,
+            } => write!(f, "encrypted={key:?}, {nonce}, {} bytes", payload.len()),
+            SrtControlPacket::Extension { ty, payload } => {
+                write!(f, "ext={ty}, {} bytes", payload.len())
             }
         }
     }
@@ -683,6 +883,9 @@ mod tests {
 
     use std::{io::Cursor, time::Duration};
 
+    use assert_matches::assert_matches;
+    use bytes::Bytes;
+
     #[test]
     fn deser_ser_shake() {
         let handshake = Packet::Control(ControlPacket {
@@ -720,6 +923,106 @@ mod tests {
         assert_eq!(sid, deser);
     }
 
+    #[test]
+    fn ser_deser_empty_sid() {
+        let sid = Packet::Control(ControlPacket {
+            timestamp: TimeStamp::from_micros(123),
+            dest_sockid: SocketId(1234),
+            control_type: ControlTypes::Srt(SrtControlPacket::StreamId(String::new())),
+        });
+
+        let mut buf = Vec::new();
+        sid.serialize(&mut buf);
+        assert_eq!(buf.len(), 16);
+
+        let deser = Packet::parse(&mut Cursor::new(buf), false).unwrap();
+
+        assert_eq!(sid, deser);
+    }
+
+    #[test]
+    fn extension_padding() {
+        let extension = Packet::Control(ControlPacket {
+            timestamp: TimeStamp::from_micros(123),
+            dest_sockid: SocketId(1234),
+            control_type: ControlTypes::Srt(SrtControlPacket::Extension {
+                ty: 0x100,
+                payload: Bytes::from_static(b"hello"),
+            }),
+        });
+
+        let mut buf = Vec::new();
+        extension.serialize(&mut buf);
+        assert_eq!(buf.len(), 16 + 8);
+
+        let deser = Packet::parse(&mut Cursor::new(buf), false).unwrap();
+        assert_matches!(
+            deser.control().map(|c| &c.control_type),
+            Some(ControlTypes::Srt(SrtControlPacket::Extension { ty: 0x100, payload }))
+                if payload[..] == b"hello\0\0\0"[..]
+        );
+    }
+
+    #[test]
+    fn congestion_experienced() {
+        let report = Packet::Control(ControlPacket {
+            timestamp: TimeStamp::from_micros(123),
+            dest_sockid: SocketId(1234),
+            control_type: ControlTypes::Srt(SrtControlPacket::CongestionExperienced(7)),
+        });
+
+        let mut buf = Vec::new();
+        report.serialize(&mut buf);
+        assert_eq!(buf.len(), 16 + 4);
+
+        let deser = Packet::parse(&mut Cursor::new(buf), false).unwrap();
+        assert_eq!(report, deser);
+    }
+
+    #[test]
+    fn echo() {
+        for control_type in [
+            SrtControlPacket::EchoRequest { id: 3 },
+            SrtControlPacket::EchoReply {
+                id: 3,
+                timestamp: TimeStamp::from_micros(456),
+                requests_received: 2,
+            },
+        ] {
+            let echo = Packet::Control(ControlPacket {
+                timestamp: TimeStamp::from_micros(123),
+                dest_sockid: SocketId(1234),
+                control_type: ControlTypes::Srt(control_type),
+            });
+
+            let mut buf = Vec::new();
+            echo.serialize(&mut buf);
+            let deser = Packet::parse(&mut Cursor::new(buf), false).unwrap();
+            assert_eq!(echo, deser);
+        }
+    }
+
+    #[test]
+    fn encrypted_control() {
+        let packet = Packet::Control(ControlPacket {
+            timestamp: TimeStamp::from_micros(123),
+            dest_sockid: SocketId(1234),
+            control_type: ControlTypes::Srt(SrtControlPacket::EncryptedControl {
+                key: DataEncryption::Odd,
+                nonce: 1 << 40,
+                payload: Bytes::from_static(b"12345678"),
+            }),
+        });
+
+        let mut buf = Vec::new();
+        packet.serialize(&mut buf);
+        assert_eq!(buf.len(), 16 + 12 + 8);
+        assert_eq!(buf[16], DataEncryption::Odd as u8);
+
+        let deser = Packet::parse(&mut Cursor::new(buf), false).unwrap();
+        assert_eq!(packet, deser);
+    }
+
     #[test]
     fn srt_key_message_debug() {
         let salt = b"\x00\x00\x00\x00\x00\x00\x00\x00\x85\x2c\x3c\xcd\x02\x65\x1a\x22";
@@ -737,4 +1040,25 @@ mod tests {
 
         assert_eq!(format!("{km:?}"), "KeyingMaterialMessage { pt: KeyingMaterial, key_flags: KeyFlags(EVEN), keki: 0, cipher: Ctr, auth: None }")
     }
+
+    #[test]
+    fn group_without_member_data() {
+        use super::{GroupFlags, GroupType};
+
+        // older versions of libsrt only send the group id
+        let mut buf = &[0x40, 0, 0, 1][..];
+        assert_eq!(
+            SrtControlPacket::parse(8, &mut buf),
+            Ok(SrtControlPacket::Group {
+                id: SocketId(0x4000_0001),
+                ty: GroupType::Undefined,
+                flags: GroupFlags::empty(),
+                weight: 0,
+            })
+        );
+        assert_eq!(
+            SrtControlPacket::parse(8, &mut &[0x40, 0][..]),
+            Err(PacketParseError::NotEnoughData)
+        );
+    }
 }
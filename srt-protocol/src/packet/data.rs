@@ -1,5 +1,5 @@
-use std::cmp::min;
-use std::{convert::TryFrom, fmt};
+use core::cmp::min;
+use core::{convert::TryFrom, fmt};
 
 use bitflags::bitflags;
 use bytes::{Buf, BufMut, Bytes};
@@ -86,7 +86,13 @@ pub enum DataEncryption {
 }
 
 impl DataPacket {
+    /// Parses a data packet from the whole of `buf`, starting with its 16 byte header, see
+    /// [`Packet::parse`](super::Packet::parse)
     pub fn parse(buf: &mut impl Buf) -> Result<DataPacket, PacketParseError> {
+        if buf.remaining() < 16 {
+            return Err(PacketParseError::NotEnoughData);
+        }
+
         // get the sequence number, which is the last 31 bits of the header
         let seq_number = SeqNumber::new_truncate(buf.get_u32());
 
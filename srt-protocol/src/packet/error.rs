@@ -1,4 +1,5 @@
-use std::{error::Error, fmt, str::Utf8Error};
+use alloc::string::String;
+use core::{error::Error, fmt, str::Utf8Error};
 
 #[derive(Debug, Eq, PartialEq)]
 #[non_exhaustive]
@@ -1,5 +1,44 @@
-// Packet structures
-// see https://tools.ietf.org/html/draft-gg-udt-03#page-5
+//! The SRT packets, with their wire format, for analyzers, test harnesses and filters that look
+//! into SRT traffic without running a connection.
+//!
+//! A UDP payload is parsed with [`Packet::parse`], into a [`DataPacket`] or a [`ControlPacket`],
+//! the [`ControlTypes`] of which hold the handshakes, ACKs, NAKs and the SRT extensions like
+//! [`SrtHandshake`] and the [`KeyingMaterialMessage`]. [`Packet::serialize`] writes one back.
+//! Parsing never panics, however malformed the input, so untrusted datagrams can be fed to it.
+//!
+//! This module only needs `core` and `alloc`, i.e. it builds with `default-features = false`.
+//!
+//! ```
+//! use srt_protocol::packet::*;
+//!
+//! // an induction handshake, as a caller sends it first
+//! let induction = Packet::Control(ControlPacket {
+//!     timestamp: TimeStamp::from_micros(0),
+//!     dest_sockid: SocketId(0),
+//!     control_type: ControlTypes::Handshake(HandshakeControlInfo {
+//!         init_seq_num: SeqNumber::new_truncate(1234),
+//!         max_packet_size: srt_protocol::options::PacketSize(1500),
+//!         max_flow_size: srt_protocol::options::PacketCount(8192),
+//!         shake_type: ShakeType::Induction,
+//!         socket_id: SocketId(5678),
+//!         syn_cookie: 0,
+//!         peer_addr: [127, 0, 0, 1].into(),
+//!         info: HandshakeVsInfo::V4(SocketType::Datagram),
+//!     }),
+//! });
+//! let mut datagram = Vec::new();
+//! induction.serialize(&mut datagram);
+//!
+//! let packet = Packet::parse(&mut &datagram[..], false).unwrap();
+//! let handshake = packet.control().and_then(ControlPacket::handshake).unwrap();
+//! assert_eq!(handshake.shake_type, ShakeType::Induction);
+//! assert_eq!(packet, induction);
+//!
+//! // a truncated one is an error
+//! assert!(Packet::parse(&mut &datagram[..30], false).is_err());
+//! ```
+//!
+//! See <https://tools.ietf.org/html/draft-sharabayko-srt> for the format of each packet.
 
 mod control;
 mod data;
@@ -18,11 +57,13 @@ pub use seq_number::*;
 pub use socket_id::*;
 pub use time::*;
 
-use std::{
-    fmt::{self, Debug, Formatter},
-    io,
-    net::SocketAddr,
-};
+cfg_std! {
+    mod receive;
+
+    pub use receive::*;
+}
+
+use core::fmt::{self, Debug, Formatter};
 
 use bytes::{Buf, BufMut};
 
@@ -92,6 +133,10 @@ impl Packet {
         }
     }
 
+    /// Parses a packet from the whole of `buf`, the payload of a UDP datagram.
+    ///
+    /// `is_ipv6` is whether the datagram came over IPv6, which is how the peer address in a
+    /// handshake is encoded.
     pub fn parse<T: Buf>(buf: &mut T, is_ipv6: bool) -> Result<Packet, PacketParseError> {
         // Buffer must be at least 16 bytes,
         // the length of a header packet
@@ -112,6 +157,7 @@ impl Packet {
         })
     }
 
+    /// Writes the packet in its wire format, the payload of a UDP datagram, to `into`
     pub fn serialize<T: BufMut>(&self, into: &mut T) {
         match *self {
             Packet::Control(ref control) => {
@@ -145,57 +191,79 @@ impl Debug for Packet {
     }
 }
 
-#[derive(Debug)]
-pub enum ReceivePacketError {
-    Parse(PacketParseError),
-    Io(io::Error),
-}
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
 
-impl From<io::Error> for ReceivePacketError {
-    fn from(error: io::Error) -> Self {
-        ReceivePacketError::Io(error)
+    use super::*;
+
+    // the control types, SRT extension types, and a few unknown ones of each
+    fn control_type() -> impl Strategy<Value = u16> {
+        prop_oneof![0u16..=9, Just(0x7ffe), Just(0x7fff)]
     }
-}
 
-impl From<PacketParseError> for ReceivePacketError {
-    fn from(error: PacketParseError) -> Self {
-        ReceivePacketError::Parse(error)
+    fn extension_type() -> impl Strategy<Value = u16> {
+        prop_oneof![
+            0u16..=9,
+            Just(SrtControlPacket::CONGESTION_EXPERIENCED_TYPE_ID),
+            Just(SrtControlPacket::ECHO_REQUEST_TYPE_ID),
+            Just(SrtControlPacket::ECHO_REPLY_TYPE_ID),
+            Just(SrtControlPacket::ENCRYPTED_CONTROL_TYPE_ID),
+            Just(SrtControlPacket::MESSAGE_TAGS_TYPE_ID),
+            any::<u16>(),
+        ]
     }
-}
 
-impl fmt::Display for ReceivePacketError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use ReceivePacketError::*;
-        match self {
-            Parse(e) => <PacketParseError as fmt::Display>::fmt(e, f),
-            Io(e) => <io::Error as fmt::Display>::fmt(e, f),
+    proptest! {
+        #[test]
+        fn parse_never_panics(bytes in prop::collection::vec(any::<u8>(), 0..256), is_ipv6: bool) {
+            let _ = Packet::parse(&mut &bytes[..], is_ipv6);
         }
-    }
-}
 
-impl std::error::Error for ReceivePacketError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        use ReceivePacketError::*;
-        match self {
-            Parse(e) => Some(e),
-            Io(e) => Some(e),
+        #[test]
+        fn parse_control_never_panics(
+            control_type in control_type(),
+            subtype in extension_type(),
+            // mostly short ones, which run out halfway through the fields
+            body in prop_oneof![
+                prop::collection::vec(any::<u8>(), 0..16),
+                prop::collection::vec(any::<u8>(), 0..256),
+            ],
+            is_ipv6: bool,
+        ) {
+            let mut bytes = Vec::new();
+            bytes.put_u16(0x8000 | control_type);
+            bytes.put_u16(subtype);
+            bytes.put_bytes(0, 12);
+            bytes.extend(body);
+            let _ = Packet::parse(&mut &bytes[..], is_ipv6);
         }
-    }
-}
 
-// NOTE: Eq, PartialEq are only here to accommodate structural comparison in tests without losing
-//  the ability to surface network errors for logging purposes, improve on this as needed, but don't
-//  remove.
-impl Eq for ReceivePacketError {}
-impl PartialEq for ReceivePacketError {
-    fn eq(&self, other: &Self) -> bool {
-        use ReceivePacketError::*;
-        match (self, other) {
-            (Parse(s), Parse(o)) => s.eq(o),
-            (Io(s), Io(o)) => s.kind().eq(&o.kind()) && s.raw_os_error().eq(&o.raw_os_error()),
-            _ => false,
+        #[test]
+        fn parse_handshake_extensions_never_panics(
+            flags: u16,
+            extensions in prop::collection::vec(
+                (extension_type(), any::<u16>(), prop::collection::vec(any::<u8>(), 0..64)),
+                0..4,
+            ),
+            honest_sizes: bool,
+        ) {
+            let mut bytes = Vec::new();
+            bytes.put_u16(0x8000);
+            bytes.put_bytes(0, 14);
+            // a HSv5 conclusion, with the extension flags
+            bytes.put_u32(5);
+            bytes.put_u16(0);
+            bytes.put_u16(flags);
+            bytes.put_bytes(0, 12);
+            bytes.put_i32(-1);
+            bytes.put_bytes(0, 24);
+            for (ty, size_words, payload) in extensions {
+                bytes.put_u16(ty);
+                bytes.put_u16(if honest_sizes { (payload.len() / 4) as u16 } else { size_words });
+                bytes.extend(payload);
+            }
+            let _ = Packet::parse(&mut &bytes[..], false);
         }
     }
 }
-
-pub type ReceivePacketResult = Result<(Packet, SocketAddr), ReceivePacketError>;
@@ -1,7 +1,7 @@
 use rand::distributions::{Distribution, Standard};
 use rand::Rng;
 
-use std::fmt;
+use core::fmt;
 
 /// A newtype wrapper for strongly-typed SocketIDs
 #[derive(Copy, Clone, Eq, PartialEq, Hash)]
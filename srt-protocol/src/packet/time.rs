@@ -1,13 +1,16 @@
-use std::{
+use core::{
     cmp::Ordering,
     convert::TryInto,
     fmt,
     num::Wrapping,
     ops::{Add, Div, Mul, Neg, Sub},
-    time::{Duration, Instant},
+    time::Duration,
     u32,
 };
 
+#[cfg(feature = "std")]
+use crate::protocol::time::Instant;
+
 /// Timestamp in us after creation
 /// These wrap every 2^32 microseconds
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -22,6 +25,7 @@ impl TimeSpan {
     pub const MIN: TimeSpan = TimeSpan::from_micros(i32::MIN);
     pub const ZERO: TimeSpan = TimeSpan::from_micros(0);
 
+    #[cfg(feature = "std")]
     pub fn from_interval(begin: Instant, end: Instant) -> Self {
         if begin <= end {
             Self::ZERO + (end - begin)
@@ -243,6 +247,7 @@ impl Sub<TimeSpan> for Duration {
     }
 }
 
+#[cfg(feature = "std")]
 impl Add<TimeSpan> for Instant {
     type Output = Instant;
 
@@ -257,6 +262,7 @@ impl Add<TimeSpan> for Instant {
     }
 }
 
+#[cfg(feature = "std")]
 impl Sub<TimeSpan> for Instant {
     type Output = Instant;
 
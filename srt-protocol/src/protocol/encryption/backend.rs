@@ -0,0 +1,486 @@
+use std::sync::OnceLock;
+
+use thiserror::Error;
+
+/// The primitives the encryption of SRT is built from, so they can come from a certified module,
+/// e.g. OpenSSL in FIPS mode, instead of the pure Rust crates of `RustCrypto`
+///
+/// Keys are 16, 24 or 32 bytes, for AES-128, AES-192 and AES-256, anything else is a
+/// [`CryptoError::KeyLength`]. The backend is installed for the whole process with
+/// [`install_crypto_backend`].
+pub trait CryptoBackend: Send + Sync {
+    /// Apply the AES-CTR keystream of `key` to `data`, `iv` is the first counter block and is
+    /// incremented as a big endian number. SRT payloads never carry out of the last two bytes,
+    /// so the width of the counter doesn't matter.
+    fn aes_ctr(&self, key: &[u8], iv: &[u8; 16], data: &mut [u8]) -> Result<(), CryptoError>;
+
+    /// Encrypt `data` in place with AES-GCM, authenticating `aad` along with it, and return the
+    /// 16 byte tag
+    fn aes_gcm_seal(
+        &self,
+        key: &[u8],
+        iv: &[u8; 12],
+        aad: &[u8],
+        data: &mut [u8],
+    ) -> Result<[u8; 16], CryptoError>;
+
+    /// Decrypt `data` in place with AES-GCM, [`CryptoError::Integrity`] if `tag` doesn't match
+    /// it and `aad`
+    fn aes_gcm_open(
+        &self,
+        key: &[u8],
+        iv: &[u8; 12],
+        aad: &[u8],
+        data: &mut [u8],
+        tag: &[u8; 16],
+    ) -> Result<(), CryptoError>;
+
+    /// Wrap `keys` with `kek` as in RFC 3394 with the default initial value, the result is 8
+    /// bytes longer. `keys` has to be a multiple of 8 bytes, and at least 16.
+    fn aes_wrap(&self, kek: &[u8], keys: &[u8]) -> Result<Vec<u8>, CryptoError>;
+
+    /// Unwrap `wrapped` with `kek` as in RFC 3394, [`CryptoError::Integrity`] if the integrity
+    /// check fails, i.e. it was wrapped with another key
+    fn aes_unwrap(&self, kek: &[u8], wrapped: &[u8]) -> Result<Vec<u8>, CryptoError>;
+
+    /// PBKDF2 with HMAC-SHA1, filling `key`
+    fn pbkdf2_hmac_sha1(
+        &self,
+        password: &[u8],
+        salt: &[u8],
+        rounds: u32,
+        key: &mut [u8],
+    ) -> Result<(), CryptoError>;
+}
+
+#[derive(Error, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CryptoError {
+    #[error("no crypto backend, enable the rust-crypto or openssl feature or call install_crypto_backend")]
+    NoBackend,
+    #[error("a {0} byte key isn't an AES key")]
+    KeyLength(usize),
+    #[error("{0} bytes can't be wrapped or unwrapped, it has to be a multiple of 8")]
+    WrapLength(usize),
+    #[error("the integrity check failed")]
+    Integrity,
+    #[error("the crypto backend failed")]
+    Backend,
+}
+
+static BACKEND: OnceLock<&'static dyn CryptoBackend> = OnceLock::new();
+
+/// Use `backend` for the encryption of all connections of the process, the backend already in
+/// use is returned if there is one. It has to be installed before the first encrypted connection,
+/// which otherwise settles on `RustCrypto`, or `OpenSsl` without the rust-crypto feature.
+pub fn install_crypto_backend(
+    backend: &'static dyn CryptoBackend,
+) -> Result<(), &'static dyn CryptoBackend> {
+    BACKEND.set(backend)?;
+    Ok(())
+}
+
+pub(crate) fn crypto_backend() -> Result<&'static dyn CryptoBackend, CryptoError> {
+    match default_backend() {
+        Some(default) => Ok(*BACKEND.get_or_init(|| default)),
+        None => BACKEND.get().copied().ok_or(CryptoError::NoBackend),
+    }
+}
+
+#[cfg(feature = "rust-crypto")]
+fn default_backend() -> Option<&'static dyn CryptoBackend> {
+    Some(&RustCrypto)
+}
+
+#[cfg(all(not(feature = "rust-crypto"), feature = "openssl"))]
+fn default_backend() -> Option<&'static dyn CryptoBackend> {
+    Some(&OpenSsl)
+}
+
+#[cfg(not(any(feature = "rust-crypto", feature = "openssl")))]
+fn default_backend() -> Option<&'static dyn CryptoBackend> {
+    None
+}
+
+// RFC 3394 works on 8 byte blocks, and needs at least two of them besides the integrity check
+fn check_wrap_length(length: usize, min: usize) -> Result<(), CryptoError> {
+    if !length.is_multiple_of(8) || length < min {
+        Err(CryptoError::WrapLength(length))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "rust-crypto")]
+pub use rust_crypto::RustCrypto;
+
+#[cfg(feature = "rust-crypto")]
+mod rust_crypto {
+    use aes::{Aes128, Aes192, Aes256};
+    use aes_gcm::{aead::AeadInPlace, AesGcm};
+    use cipher::{
+        consts::U12,
+        generic_array::{typenum::consts::U16, ArrayLength, GenericArray},
+        BlockCipher, BlockDecrypt, BlockEncrypt, BlockSizeUser, KeyInit, KeyIvInit, StreamCipher,
+    };
+    use hmac::Hmac;
+    use sha1::Sha1;
+
+    use super::{super::wrap, check_wrap_length, CryptoBackend, CryptoError};
+
+    type Aes128Ctr = ctr::Ctr64BE<Aes128>;
+    type Aes192Ctr = ctr::Ctr64BE<Aes192>;
+    type Aes256Ctr = ctr::Ctr64BE<Aes256>;
+
+    /// The pure Rust implementations of the RustCrypto project, what's used unless another
+    /// backend is installed, behind the `rust-crypto` feature
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct RustCrypto;
+
+    impl CryptoBackend for RustCrypto {
+        fn aes_ctr(&self, key: &[u8], iv: &[u8; 16], data: &mut [u8]) -> Result<(), CryptoError> {
+            let iv = iv[..].into();
+            match key.len() {
+                16 => Aes128Ctr::new(key.into(), iv).apply_keystream(data),
+                24 => Aes192Ctr::new(key.into(), iv).apply_keystream(data),
+                32 => Aes256Ctr::new(key.into(), iv).apply_keystream(data),
+                length => return Err(CryptoError::KeyLength(length)),
+            }
+            Ok(())
+        }
+
+        fn aes_gcm_seal(
+            &self,
+            key: &[u8],
+            iv: &[u8; 12],
+            aad: &[u8],
+            data: &mut [u8],
+        ) -> Result<[u8; 16], CryptoError> {
+            fn seal_with<C>(
+                key: &[u8],
+                iv: &[u8; 12],
+                aad: &[u8],
+                data: &mut [u8],
+            ) -> Result<[u8; 16], CryptoError>
+            where
+                C: BlockCipher + BlockSizeUser<BlockSize = U16> + BlockEncrypt + KeyInit,
+            {
+                let tag = AesGcm::<C, U12>::new(key.into())
+                    .encrypt_in_place_detached(iv.into(), aad, data)
+                    .map_err(|_| CryptoError::Backend)?;
+                Ok(tag.into())
+            }
+            match key.len() {
+                16 => seal_with::<Aes128>(key, iv, aad, data),
+                24 => seal_with::<Aes192>(key, iv, aad, data),
+                32 => seal_with::<Aes256>(key, iv, aad, data),
+                length => Err(CryptoError::KeyLength(length)),
+            }
+        }
+
+        fn aes_gcm_open(
+            &self,
+            key: &[u8],
+            iv: &[u8; 12],
+            aad: &[u8],
+            data: &mut [u8],
+            tag: &[u8; 16],
+        ) -> Result<(), CryptoError> {
+            fn open_with<C>(
+                key: &[u8],
+                iv: &[u8; 12],
+                aad: &[u8],
+                data: &mut [u8],
+                tag: &[u8; 16],
+            ) -> Result<(), CryptoError>
+            where
+                C: BlockCipher + BlockSizeUser<BlockSize = U16> + BlockEncrypt + KeyInit,
+            {
+                AesGcm::<C, U12>::new(key.into())
+                    .decrypt_in_place_detached(iv.into(), aad, data, tag.into())
+                    .map_err(|_| CryptoError::Integrity)
+            }
+            match key.len() {
+                16 => open_with::<Aes128>(key, iv, aad, data, tag),
+                24 => open_with::<Aes192>(key, iv, aad, data, tag),
+                32 => open_with::<Aes256>(key, iv, aad, data, tag),
+                length => Err(CryptoError::KeyLength(length)),
+            }
+        }
+
+        fn aes_wrap(&self, kek: &[u8], keys: &[u8]) -> Result<Vec<u8>, CryptoError> {
+            fn wrap_with<K>(kek: &K, keys: &[u8]) -> Vec<u8>
+            where
+                K: BlockEncrypt,
+                K::BlockSize: ArrayLength<GenericArray<u8, U16>>,
+            {
+                let mut wrapped = vec![0; keys.len() + 8];
+                wrap::aes_wrap(kek, None, &mut wrapped, keys);
+                wrapped
+            }
+            check_wrap_length(keys.len(), 16)?;
+            match kek.len() {
+                16 => Ok(wrap_with(&Aes128::new(kek.into()), keys)),
+                24 => Ok(wrap_with(&Aes192::new(kek.into()), keys)),
+                32 => Ok(wrap_with(&Aes256::new(kek.into()), keys)),
+                length => Err(CryptoError::KeyLength(length)),
+            }
+        }
+
+        fn aes_unwrap(&self, kek: &[u8], wrapped: &[u8]) -> Result<Vec<u8>, CryptoError> {
+            fn unwrap_with<K>(kek: &K, wrapped: &[u8]) -> Result<Vec<u8>, CryptoError>
+            where
+                K: BlockDecrypt,
+                K::BlockSize: ArrayLength<GenericArray<u8, U16>>,
+            {
+                let mut keys = vec![0; wrapped.len() - 8];
+                let mut iv = [0; 8];
+                wrap::aes_unwrap(kek, &mut iv, &mut keys, wrapped);
+                if iv == wrap::DEFAULT_IV {
+                    Ok(keys)
+                } else {
+                    Err(CryptoError::Integrity)
+                }
+            }
+            check_wrap_length(wrapped.len(), 24)?;
+            match kek.len() {
+                16 => unwrap_with(&Aes128::new(kek.into()), wrapped),
+                24 => unwrap_with(&Aes192::new(kek.into()), wrapped),
+                32 => unwrap_with(&Aes256::new(kek.into()), wrapped),
+                length => Err(CryptoError::KeyLength(length)),
+            }
+        }
+
+        fn pbkdf2_hmac_sha1(
+            &self,
+            password: &[u8],
+            salt: &[u8],
+            rounds: u32,
+            key: &mut [u8],
+        ) -> Result<(), CryptoError> {
+            pbkdf2::pbkdf2::<Hmac<Sha1>>(password, salt, rounds, key)
+                .map_err(|_| CryptoError::Backend)
+        }
+    }
+}
+
+#[cfg(feature = "openssl")]
+pub use open_ssl::OpenSsl;
+
+#[cfg(feature = "openssl")]
+mod open_ssl {
+    use openssl::{
+        aes::{self, AesKey},
+        hash::MessageDigest,
+        pkcs5,
+        symm::{self, Cipher, Mode},
+    };
+
+    use super::{check_wrap_length, CryptoBackend, CryptoError};
+
+    /// The system's OpenSSL, behind the `openssl` feature, which runs in FIPS mode when OpenSSL is
+    /// configured for it
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct OpenSsl;
+
+    fn ctr(key: &[u8]) -> Result<Cipher, CryptoError> {
+        match key.len() {
+            16 => Ok(Cipher::aes_128_ctr()),
+            24 => Ok(Cipher::aes_192_ctr()),
+            32 => Ok(Cipher::aes_256_ctr()),
+            length => Err(CryptoError::KeyLength(length)),
+        }
+    }
+
+    fn gcm(key: &[u8]) -> Result<Cipher, CryptoError> {
+        match key.len() {
+            16 => Ok(Cipher::aes_128_gcm()),
+            24 => Ok(Cipher::aes_192_gcm()),
+            32 => Ok(Cipher::aes_256_gcm()),
+            length => Err(CryptoError::KeyLength(length)),
+        }
+    }
+
+    fn aes_key(kek: &[u8], mode: Mode) -> Result<AesKey, CryptoError> {
+        if !matches!(kek.len(), 16 | 24 | 32) {
+            return Err(CryptoError::KeyLength(kek.len()));
+        }
+        let key = match mode {
+            Mode::Encrypt => AesKey::new_encrypt(kek),
+            Mode::Decrypt => AesKey::new_decrypt(kek),
+        };
+        key.map_err(|_| CryptoError::Backend)
+    }
+
+    impl CryptoBackend for OpenSsl {
+        fn aes_ctr(&self, key: &[u8], iv: &[u8; 16], data: &mut [u8]) -> Result<(), CryptoError> {
+            let output =
+                symm::encrypt(ctr(key)?, key, Some(iv), data).map_err(|_| CryptoError::Backend)?;
+            data.copy_from_slice(&output);
+            Ok(())
+        }
+
+        fn aes_gcm_seal(
+            &self,
+            key: &[u8],
+            iv: &[u8; 12],
+            aad: &[u8],
+            data: &mut [u8],
+        ) -> Result<[u8; 16], CryptoError> {
+            let mut tag = [0; 16];
+            let output = symm::encrypt_aead(gcm(key)?, key, Some(iv), aad, data, &mut tag)
+                .map_err(|_| CryptoError::Backend)?;
+            data.copy_from_slice(&output);
+            Ok(tag)
+        }
+
+        fn aes_gcm_open(
+            &self,
+            key: &[u8],
+            iv: &[u8; 12],
+            aad: &[u8],
+            data: &mut [u8],
+            tag: &[u8; 16],
+        ) -> Result<(), CryptoError> {
+            let output = symm::decrypt_aead(gcm(key)?, key, Some(iv), aad, data, tag)
+                .map_err(|_| CryptoError::Integrity)?;
+            data.copy_from_slice(&output);
+            Ok(())
+        }
+
+        fn aes_wrap(&self, kek: &[u8], keys: &[u8]) -> Result<Vec<u8>, CryptoError> {
+            check_wrap_length(keys.len(), 16)?;
+            let kek = aes_key(kek, Mode::Encrypt)?;
+            let mut wrapped = vec![0; keys.len() + 8];
+            aes::wrap_key(&kek, None, &mut wrapped, keys).map_err(|_| CryptoError::Backend)?;
+            Ok(wrapped)
+        }
+
+        fn aes_unwrap(&self, kek: &[u8], wrapped: &[u8]) -> Result<Vec<u8>, CryptoError> {
+            check_wrap_length(wrapped.len(), 24)?;
+            let kek = aes_key(kek, Mode::Decrypt)?;
+            let mut keys = vec![0; wrapped.len() - 8];
+            aes::unwrap_key(&kek, None, &mut keys, wrapped).map_err(|_| CryptoError::Integrity)?;
+            Ok(keys)
+        }
+
+        fn pbkdf2_hmac_sha1(
+            &self,
+            password: &[u8],
+            salt: &[u8],
+            rounds: u32,
+            key: &mut [u8],
+        ) -> Result<(), CryptoError> {
+            let rounds = usize::try_from(rounds).map_err(|_| CryptoError::Backend)?;
+            pkcs5::pbkdf2_hmac(password, salt, rounds, MessageDigest::sha1(), key)
+                .map_err(|_| CryptoError::Backend)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backends() -> Vec<&'static dyn CryptoBackend> {
+        vec![
+            #[cfg(feature = "rust-crypto")]
+            &RustCrypto,
+            #[cfg(feature = "openssl")]
+            &OpenSsl,
+        ]
+    }
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn aes_wrap() {
+        // RFC 3394 4.1, 128 bits of key data with a 128 bit KEK
+        let kek = hex("000102030405060708090A0B0C0D0E0F");
+        let keys = hex("00112233445566778899AABBCCDDEEFF");
+        let wrapped = hex("1FA68B0A8112B447AEF34BD8FB5A7B829D3E862371D2CFE5");
+        for backend in backends() {
+            assert_eq!(backend.aes_wrap(&kek, &keys), Ok(wrapped.clone()));
+            assert_eq!(backend.aes_unwrap(&kek, &wrapped), Ok(keys.clone()));
+
+            let other = hex("0F0E0D0C0B0A09080706050403020100");
+            assert_eq!(
+                backend.aes_unwrap(&other, &wrapped),
+                Err(CryptoError::Integrity)
+            );
+            assert_eq!(
+                backend.aes_unwrap(&kek, &wrapped[..20]),
+                Err(CryptoError::WrapLength(20))
+            );
+            assert_eq!(
+                backend.aes_unwrap(&kek, &wrapped[..16]),
+                Err(CryptoError::WrapLength(16))
+            );
+            assert_eq!(
+                backend.aes_wrap(&kek, &keys[..8]),
+                Err(CryptoError::WrapLength(8))
+            );
+            assert_eq!(
+                backend.aes_wrap(&kek[..15], &keys),
+                Err(CryptoError::KeyLength(15))
+            );
+        }
+    }
+
+    #[test]
+    fn aes_ctr() {
+        // NIST SP 800-38A F.5.1, the first block
+        let key = hex("2b7e151628aed2a6abf7158809cf4f3c");
+        let iv = hex("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff").try_into().unwrap();
+        let plain = hex("6bc1bee22e409f96e93d7e117393172a");
+        for backend in backends() {
+            let mut data = plain.clone();
+            backend.aes_ctr(&key, &iv, &mut data).unwrap();
+            assert_eq!(data, hex("874d6191b620e3261bef6864990db6ce"));
+            assert_eq!(
+                backend.aes_ctr(&key[..12], &iv, &mut data),
+                Err(CryptoError::KeyLength(12))
+            );
+        }
+    }
+
+    #[test]
+    fn aes_gcm() {
+        // test case 2 of the GCM specification, all zero key, IV and plaintext
+        let key = [0; 16];
+        let iv = [0; 12];
+        for backend in backends() {
+            let mut data = [0; 16];
+            let tag = backend.aes_gcm_seal(&key, &iv, &[], &mut data).unwrap();
+            assert_eq!(data[..], hex("0388dace60b6a392f328c2b971b2fe78"));
+            assert_eq!(tag[..], hex("ab6e47d42cec13bdf53a67b21257bddf"));
+
+            let mut tampered = data;
+            tampered[0] ^= 1;
+            assert_eq!(
+                backend.aes_gcm_open(&key, &iv, &[], &mut tampered, &tag),
+                Err(CryptoError::Integrity)
+            );
+            backend
+                .aes_gcm_open(&key, &iv, &[], &mut data, &tag)
+                .unwrap();
+            assert_eq!(data, [0; 16]);
+        }
+    }
+
+    #[test]
+    fn pbkdf2_hmac_sha1() {
+        // RFC 6070, 2 iterations
+        for backend in backends() {
+            let mut key = [0; 20];
+            backend
+                .pbkdf2_hmac_sha1(b"password", b"salt", 2, &mut key)
+                .unwrap();
+            assert_eq!(key[..], hex("ea6c014dc72d6f8ccd1ed92ace1d41f0d8de8957"));
+        }
+    }
+}
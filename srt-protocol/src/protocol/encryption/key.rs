@@ -5,18 +5,18 @@ use std::{
     ops::Deref,
 };
 
-use aes::{Aes128, Aes192, Aes256};
-use cipher::KeyInit;
-use hmac::Hmac;
 use rand::{rngs::OsRng, RngCore};
-use sha1::Sha1;
+use zeroize::Zeroize;
 
 use crate::{
-    packet::SeqNumber,
-    settings::{KeySettings, KeySize, Passphrase},
+    packet::{SeqNumber, SocketId},
+    settings::{KeySettings, KeySize},
 };
 
-use super::wrap;
+use super::{
+    backend::{crypto_backend, CryptoError},
+    provider::{KeyProvider, KeyProviderError},
+};
 
 #[derive(Clone, Eq, PartialEq)]
 pub struct Salt([u8; 16]);
@@ -55,8 +55,32 @@ impl Salt {
             out[i + 10] ^= *b;
         }
 
-        // TODO: the ref impl doesn't put ctr in here....
+        // ctr starts at 0 for every packet, like the reference implementation
         // https://github.com/Haivision/srt/blob/9f7068d4f45eb3276e30fcc6e920f82b387c6852/haicrypt/hcrypt.h#L136-L136
+        // a payload is less than 100 blocks, so counting them never carries into pki, which keeps
+        // the retransmission of a packet on the keystream it was sent with
+
+        StreamInitializationVector(out)
+    }
+
+    /// The IV of an encrypted control packet for the socket `socket_id`, the `nonce` must not
+    /// repeat for the same key. Socket ids are never 0, so it never matches the IV of a data
+    /// packet, and the two directions of a connection don't share IVs.
+    pub fn generate_control_iv_for(
+        &self,
+        socket_id: SocketId,
+        nonce: u64,
+    ) -> StreamInitializationVector {
+        let mut out = [0; 16];
+        out[0..14].copy_from_slice(&self.0[..14]);
+
+        for (i, b) in socket_id.0.to_be_bytes().iter().enumerate() {
+            out[i + 2] ^= *b;
+        }
+        // the low 32 bits go where the packet index of a data packet goes
+        for (i, b) in nonce.to_be_bytes().iter().enumerate() {
+            out[i + 6] ^= *b;
+        }
 
         StreamInitializationVector(out)
     }
@@ -80,7 +104,7 @@ impl StreamInitializationVector {
         Ok(StreamInitializationVector(slice[..].try_into()?))
     }
 
-    pub fn as_bytes(&self) -> &[u8] {
+    pub fn as_bytes(&self) -> &[u8; 16] {
         &self.0
     }
 }
@@ -91,21 +115,6 @@ impl Debug for StreamInitializationVector {
     }
 }
 
-#[derive(Clone, Eq, PartialEq)]
-pub struct WrapInitializationVector([u8; 8]);
-
-impl WrapInitializationVector {
-    pub fn try_from(slice: &[u8]) -> Result<Self, TryFromSliceError> {
-        Ok(WrapInitializationVector(slice[..].try_into()?))
-    }
-}
-
-impl Debug for WrapInitializationVector {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "KeyIV(0x{})", hex::encode_upper(self.0))
-    }
-}
-
 #[derive(Clone, Eq, PartialEq)]
 pub enum EncryptionKey {
     Bytes16([u8; 16]),
@@ -158,6 +167,18 @@ impl EncryptionKey {
     }
 }
 
+// keys, and the key encrypting keys derived from passphrases, don't outlive their use in memory
+impl Drop for EncryptionKey {
+    fn drop(&mut self) {
+        use EncryptionKey::*;
+        match self {
+            Bytes16(key) => key.zeroize(),
+            Bytes24(key) => key.zeroize(),
+            Bytes32(key) => key.zeroize(),
+        }
+    }
+}
+
 impl fmt::Debug for EncryptionKey {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         use EncryptionKey::*;
@@ -174,105 +195,29 @@ impl fmt::Debug for EncryptionKey {
 pub struct KeyEncryptionKey(EncryptionKey);
 
 impl KeyEncryptionKey {
-    pub fn new(key_settings: &KeySettings, salt: &Salt) -> Self {
+    pub fn new(key_settings: &KeySettings, salt: &Salt) -> Result<Self, KeyProviderError> {
         let key_size = key_settings.key_size;
-        let passphrase = &key_settings.passphrase;
-
-        // Generate the key encrypting key from the passphrase, caching it in the struct
-        // https://github.com/Haivision/srt/blob/2ef4ef003c2006df1458de6d47fbe3d2338edf69/haicrypt/hcrypt_sa.c#L69-L103
-
-        // the reference implementation uses the last 8 (at max) bytes of the salt. Sources:
-        // https://github.com/Haivision/srt/blob/2ef4ef003c2006df1458de6d47fbe3d2338edf69/haicrypt/haicrypt.h#L72
-        // https://github.com/Haivision/srt/blob/2ef4ef003c2006df1458de6d47fbe3d2338edf69/haicrypt/hcrypt_sa.c#L77-L85
-
-        fn calculate_pbkdf2(passphrase: &Passphrase, salt: &Salt, key: &mut [u8]) {
-            let salt = salt.0;
-            // is what the reference implementation uses.https://github.com/Haivision/srt/blob/2ef4ef003c2006df1458de6d47fbe3d2338edf69/haicrypt/haicrypt.h#L73
-            const ROUNDS: u32 = 2048;
-            let salt_len = usize::min(8, salt.len());
-            pbkdf2::pbkdf2::<Hmac<Sha1>>(
-                passphrase.as_bytes(),
-                &salt[salt.len() - salt_len..], // last salt_len bytes
-                ROUNDS,
-                &mut *key,
-            )
-            .unwrap();
+        let key = key_settings.key_source.key_encrypting_key(key_size, salt)?;
+
+        // a provider other than a passphrase could hand out a key of the wrong size
+        if key.len() != key_size.as_usize() {
+            return Err(KeyProviderError(format!(
+                "expected a {} byte key, got {} bytes",
+                key_size.as_usize(),
+                key.len()
+            )));
         }
 
-        fn new_key<const N: usize>(passphrase: &Passphrase, salt: &Salt) -> [u8; N] {
-            let mut key = [0u8; N];
-            calculate_pbkdf2(passphrase, salt, &mut key);
-            key
-        }
-
-        use EncryptionKey::*;
-        let key = match key_size {
-            KeySize::AES128 => Bytes16(new_key(passphrase, salt)),
-            KeySize::AES192 => Bytes24(new_key(passphrase, salt)),
-            KeySize::AES256 => Bytes32(new_key(passphrase, salt)),
-            KeySize::Unspecified => Bytes16(new_key(passphrase, salt)),
-        };
-
-        KeyEncryptionKey(key)
+        Ok(KeyEncryptionKey(key))
     }
 
-    pub fn encrypt_wrapped_keys(&self, keys: &[u8]) -> Vec<u8> {
-        let mut encrypted_keys = vec![0; keys.len() + 8];
-        use EncryptionKey::*;
-        match &self.0 {
-            Bytes16(key) => wrap::aes_wrap(
-                &Aes128::new(key[..].into()),
-                None,
-                &mut encrypted_keys,
-                keys,
-            ),
-            Bytes24(key) => wrap::aes_wrap(
-                &Aes192::new(key[..].into()),
-                None,
-                &mut encrypted_keys,
-                keys,
-            ),
-            Bytes32(key) => wrap::aes_wrap(
-                &Aes256::new(key[..].into()),
-                None,
-                &mut encrypted_keys,
-                keys,
-            ),
-        }
-        encrypted_keys
+    pub fn encrypt_wrapped_keys(&self, keys: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        crypto_backend()?.aes_wrap(self.0.as_bytes(), keys)
     }
 
-    pub fn decrypt_wrapped_keys(
-        &self,
-        wrapped_keys: &[u8],
-    ) -> Result<Vec<u8>, WrapInitializationVector> {
-        use EncryptionKey::*;
-        let mut keys = vec![0; wrapped_keys.len() - 8];
-        let mut iv = [0; 8];
-        match &self.0 {
-            Bytes16(key) => wrap::aes_unwrap(
-                &Aes128::new(key[..].into()),
-                &mut iv,
-                &mut keys,
-                wrapped_keys,
-            ),
-            Bytes24(key) => wrap::aes_unwrap(
-                &Aes192::new(key[..].into()),
-                &mut iv,
-                &mut keys,
-                wrapped_keys,
-            ),
-            Bytes32(key) => wrap::aes_unwrap(
-                &Aes256::new(key[..].into()),
-                &mut iv,
-                &mut keys,
-                wrapped_keys,
-            ),
-        }
-        if iv != wrap::DEFAULT_IV {
-            return Err(WrapInitializationVector(iv));
-        }
-        Ok(keys)
+    /// [`CryptoError::Integrity`] if the keys were wrapped with another key
+    pub fn decrypt_wrapped_keys(&self, wrapped_keys: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        crypto_backend()?.aes_unwrap(self.0.as_bytes(), wrapped_keys)
     }
 }
 
@@ -298,6 +243,8 @@ impl fmt::Debug for KeyEncryptionKey {
 
 #[cfg(test)]
 mod test {
+    use crate::settings::KeySource;
+
     use super::*;
 
     #[test]
@@ -305,13 +252,13 @@ mod test {
         // this is an example taken from the reference impl
         let key_settings = KeySettings {
             key_size: KeySize::AES128,
-            passphrase: "password123".into(),
+            key_source: KeySource::Passphrase("password123".into()),
         };
         let expected_kek = &hex::decode(b"08F2758F41E4244D00057C9CEBEB95FC").unwrap()[..];
         let salt =
             Salt::try_from(&hex::decode(b"7D59759C2B1A3F0B06C7028790C81C7D").unwrap()[..]).unwrap();
 
-        let kek = KeyEncryptionKey::new(&key_settings, &salt);
+        let kek = KeyEncryptionKey::new(&key_settings, &salt).unwrap();
 
         assert_eq!(kek.0.as_bytes(), expected_kek);
 
@@ -347,4 +294,18 @@ mod test {
 
         assert_ne!(Salt::new_random(), Salt::new_random());
     }
+
+    #[test]
+    fn generate_control_iv() {
+        let salt =
+            Salt::try_from(&hex::decode(b"87647f8a2361fb1a9e692de576985949").unwrap()[..]).unwrap();
+        let expected_iv = StreamInitializationVector::try_from(
+            &hex::decode(b"87647e882065fb1a9e6c2de5769f0000").unwrap()[..],
+        )
+        .unwrap();
+
+        let iv = salt.generate_control_iv_for(SocketId(0x0102_0304), 0x5_0000_0007);
+
+        assert_eq!(iv, expected_iv);
+    }
 }
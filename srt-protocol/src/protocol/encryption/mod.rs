@@ -1,5 +1,8 @@
+pub mod backend;
 pub mod key;
+pub mod provider;
 pub mod stream;
+#[cfg(feature = "rust-crypto")]
 mod wrap;
 
 use std::fmt::Debug;
@@ -19,21 +22,71 @@ pub enum DecryptionError {
     DecryptionFailure,
 }
 
+/// Key material exchange state, equivalent to `SRT_KM_STATE` in the reference implementation.
+///
+/// See `SRTO_SNDKMSTATE`, `SRTO_RCVKMSTATE` and `SRTO_KMSTATE` in the
+/// [socket options documentation](https://github.com/Haivision/srt/blob/master/docs/API/API-socket-options.md#srt_km_state)
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum KeyMaterialState {
+    /// No encryption
+    #[default]
+    Unsecured,
+    /// Stream encrypted, exchanging keying material
+    Securing,
+    /// Stream encrypted, keying material exchanged, decrypting ok
+    Secured,
+    /// Stream encrypted and no secret to decrypt keying material
+    NoSecret,
+    /// Stream encrypted and wrong secret, cannot decrypt keying material
+    BadSecret,
+}
+
+/// Where the encryption of the sent packets is at, to carry it on in a restored connection
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EncryptionSnapshot {
+    pub stream_keys: StreamEncryptionKeys,
+    pub active_sek: DataEncryption,
+    pub packets_until_key_switch: usize,
+    pub packets_with_active_sek: usize,
+    // a new key was announced, but the peer hasn't confirmed it yet
+    pub refreshing: bool,
+    pub control_nonce: u64,
+}
+
 #[derive(Debug)]
-pub struct Decryption(Option<(StreamEncryptionKeys, KeySettings)>);
+pub struct Decryption(
+    Option<(StreamEncryptionKeys, KeySettings)>,
+    KeyMaterialState,
+);
 
 impl Decryption {
     pub fn new(settings: Option<CipherSettings>) -> Self {
-        Self(settings.map(|settings| (settings.stream_keys, settings.key_settings)))
+        let state = match settings {
+            Some(_) => KeyMaterialState::Secured,
+            None => KeyMaterialState::Unsecured,
+        };
+        Self(
+            settings.map(|settings| (settings.stream_keys, settings.key_settings)),
+            state,
+        )
+    }
+
+    pub fn key_material_state(&self) -> KeyMaterialState {
+        self.1
+    }
+
+    pub fn stream_keys(&self) -> Option<&StreamEncryptionKeys> {
+        self.0.as_ref().map(|(stream_keys, _)| stream_keys)
     }
 
-    pub fn decrypt(&self, packet: DataPacket) -> Result<(usize, DataPacket), DecryptionError> {
+    pub fn decrypt(&mut self, packet: DataPacket) -> Result<(usize, DataPacket), DecryptionError> {
         use DecryptionError::*;
         let mut packet = packet;
         match (packet.encryption, &self.0) {
             (DataEncryption::None, None) => Ok((0, packet)),
             (DataEncryption::None, Some(_)) => Err(UnexpectedUnencryptedPacket(packet)),
             (DataEncryption::Even | DataEncryption::Odd, None) => {
+                self.1 = KeyMaterialState::NoSecret;
                 Err(UnexpectedEncryptedPacket(packet))
             }
             (selected_sek, Some((stream_keys, _))) => {
@@ -50,13 +103,45 @@ impl Decryption {
         }
     }
 
+    /// Decrypt the payload of an [`EncryptedControl`](SrtControlPacket::EncryptedControl) packet
+    /// for the socket `socket_id`
+    pub fn decrypt_control(
+        &self,
+        key: DataEncryption,
+        socket_id: SocketId,
+        nonce: u64,
+        payload: &[u8],
+    ) -> Result<BytesMut, DecryptionError> {
+        let (stream_keys, _) = self.0.as_ref().ok_or(DecryptionError::DecryptionFailure)?;
+        let mut data = BytesMut::from(payload);
+        stream_keys
+            .apply_control_keystream(key, socket_id, nonce, &mut data)
+            .ok_or(DecryptionError::DecryptionFailure)?;
+        Ok(data)
+    }
+
     pub fn refresh_key_material(
         &mut self,
         keying_material: KeyingMaterialMessage,
     ) -> Result<Option<KeyingMaterialMessage>, KeyMaterialError> {
-        let (stream_keys, key_settings) = self.0.as_mut().ok_or(KeyMaterialError::NoKeys)?;
-        *stream_keys = StreamEncryptionKeys::unwrap_from(key_settings, &keying_material)?;
-        Ok(Some(keying_material))
+        let (stream_keys, key_settings) = match self.0.as_mut() {
+            Some(keys) => keys,
+            None => {
+                self.1 = KeyMaterialState::NoSecret;
+                return Err(KeyMaterialError::NoKeys);
+            }
+        };
+        match StreamEncryptionKeys::unwrap_from(key_settings, &keying_material) {
+            Ok(keys) => {
+                stream_keys.update(keys);
+                self.1 = KeyMaterialState::Secured;
+                Ok(Some(keying_material))
+            }
+            Err(error) => {
+                self.1 = KeyMaterialState::BadSecret;
+                Err(error)
+            }
+        }
     }
 }
 
@@ -72,10 +157,16 @@ struct EncryptionState {
     packets_until_pre_announcement: usize,
     packets_until_transmit: usize,
     packets_until_key_switch: usize,
+    // the sequence numbers are the packet index of the IV, so a key runs out after 2^31 packets
+    packets_with_active_sek: usize,
     last_key_material: Option<KeyingMaterialMessage>,
+    // the nonces of encrypted control packets count up across keys, never repeating for any
+    control_nonce: u64,
 }
 
 impl EncryptionState {
+    const PACKETS_PER_KEY: usize = 1 << 31;
+
     fn try_encrypt_packet(&mut self, mut packet: DataPacket) -> Option<(usize, DataPacket)> {
         // this requires an extra copy here...maybe DataPacket should have a BytesMut in it instead...
         let mut data = BytesMut::with_capacity(packet.payload.len());
@@ -85,6 +176,7 @@ impl EncryptionState {
             .encrypt(self.active_sek, packet.seq_number, &mut data)?;
         packet.encryption = self.active_sek;
         packet.payload = data.freeze();
+        self.packets_with_active_sek += 1;
         Some((bytes, packet))
     }
 
@@ -116,13 +208,26 @@ impl EncryptionState {
     fn try_switch_stream_keys(&mut self) {
         use DataEncryption::*;
         if self.packets_until_key_switch == 0 {
-            self.packets_until_key_switch = self.key_refresh.period();
-            if self.last_key_material.is_none() {
+            let period = self.key_refresh.period();
+            self.packets_until_key_switch = period;
+            // the peer didn't confirm the new key yet, so the old one carries on, unless another
+            // period would take it past the end of its keystream
+            let exhausted = self.packets_with_active_sek + period > Self::PACKETS_PER_KEY;
+            if self.last_key_material.is_none() || exhausted {
                 self.active_sek = match self.active_sek {
                     Even => Odd,
                     Odd => Even,
                     None => None,
                 };
+                self.packets_with_active_sek = 0;
+            }
+            if exhausted && self.last_key_material.is_some() {
+                // the key switched away from is spent, replace it right away and announce it
+                // along with the one in use
+                self.last_key_material = self
+                    .stream_keys
+                    .commission_next_key(self.active_sek, &self.key_settings);
+                self.packets_until_transmit = 0;
             }
         }
     }
@@ -140,7 +245,9 @@ impl Encryption {
                 - settings.key_refresh.pre_announcement_period(),
             packets_until_transmit: 0,
             packets_until_key_switch: settings.key_refresh.period(),
+            packets_with_active_sek: 0,
             last_key_material: None,
+            control_nonce: 0,
         }))
     }
 
@@ -165,6 +272,69 @@ impl Encryption {
         }
     }
 
+    /// Encrypt the serialized control packet `data` for the socket `socket_id` with the active
+    /// key, returning the key and the nonce it was encrypted with
+    pub fn encrypt_control(
+        &mut self,
+        socket_id: SocketId,
+        data: &mut [u8],
+    ) -> Option<(DataEncryption, u64)> {
+        let this = self.0.as_mut()?;
+        let nonce = this.control_nonce;
+        this.stream_keys
+            .apply_control_keystream(this.active_sek, socket_id, nonce, data)?;
+        this.control_nonce += 1;
+        Some((this.active_sek, nonce))
+    }
+
+    pub fn snapshot(&self) -> Option<EncryptionSnapshot> {
+        self.0.as_ref().map(|this| EncryptionSnapshot {
+            stream_keys: this.stream_keys.clone(),
+            active_sek: this.active_sek,
+            packets_until_key_switch: this.packets_until_key_switch,
+            packets_with_active_sek: this.packets_with_active_sek,
+            refreshing: this.last_key_material.is_some(),
+            control_nonce: this.control_nonce,
+        })
+    }
+
+    /// Carry on encrypting where a snapshot left off, re-announcing the new key if the peer hadn't
+    /// confirmed it yet
+    pub fn restore(&mut self, snapshot: EncryptionSnapshot) {
+        if let Some(this) = &mut self.0 {
+            let period = this.key_refresh.period();
+            let pre_announcement_period = this.key_refresh.pre_announcement_period();
+            // the pre-announcement is always the same number of packets ahead of the key switch
+            let until_switch = snapshot.packets_until_key_switch;
+            this.packets_until_pre_announcement = if until_switch >= pre_announcement_period {
+                until_switch - pre_announcement_period
+            } else {
+                until_switch + period - pre_announcement_period
+            };
+            this.packets_until_key_switch = until_switch;
+            this.packets_with_active_sek = snapshot.packets_with_active_sek;
+            this.packets_until_transmit = 0;
+            this.last_key_material = snapshot
+                .refreshing
+                .then(|| snapshot.stream_keys.wrap_with(&this.key_settings))
+                .flatten();
+            this.stream_keys = snapshot.stream_keys;
+            this.active_sek = snapshot.active_sek;
+            this.control_nonce = snapshot.control_nonce;
+        }
+    }
+
+    pub fn key_material_state(&self) -> KeyMaterialState {
+        match &self.0 {
+            Some(EncryptionState {
+                last_key_material: Some(_),
+                ..
+            }) => KeyMaterialState::Securing,
+            Some(_) => KeyMaterialState::Secured,
+            None => KeyMaterialState::Unsecured,
+        }
+    }
+
     pub fn handle_key_refresh_response(
         &mut self,
         keying_material: KeyingMaterialMessage,
@@ -190,7 +360,7 @@ mod tests {
     fn key_settings() -> KeySettings {
         KeySettings {
             key_size: KeySize::AES192,
-            passphrase: "1234567890".into(),
+            key_source: KeySource::Passphrase("1234567890".into()),
         }
     }
 
@@ -224,7 +394,7 @@ mod tests {
         assert_ne!(encrypted_packet, original_packet);
         assert_eq!(key_material, None);
 
-        let decryption = Decryption::new(Some(settings));
+        let mut decryption = Decryption::new(Some(settings));
         let (bytes, decrypted_packet) = decryption.decrypt(encrypted_packet).unwrap();
         assert_eq!(bytes, original_packet.payload.len());
         assert_eq!(decrypted_packet, original_packet);
@@ -265,6 +435,58 @@ mod tests {
         assert_eq!(with_keys(false).decrypt(packet.clone()), Ok((0, packet)));
     }
 
+    #[test]
+    fn key_material_state() {
+        use KeyMaterialState::*;
+        let settings = CipherSettings {
+            key_refresh: KeyMaterialRefreshSettings::new(3_000, 1_000).unwrap(),
+            ..new_settings()
+        };
+        let packet = data_packet(DataEncryption::None, "test key_material_state");
+
+        assert_eq!(Encryption::new(None).key_material_state(), Unsecured);
+        assert_eq!(Decryption::new(None).key_material_state(), Unsecured);
+
+        let mut encryption = Encryption::new(Some(settings.clone()));
+        assert_eq!(encryption.key_material_state(), Secured);
+
+        let count = settings.key_refresh.period() - settings.key_refresh.pre_announcement_period();
+        for _ in 0..count {
+            encryption.encrypt(packet.clone()).unwrap();
+        }
+        let (_, encrypted_packet, km) = encryption.encrypt(packet.clone()).unwrap();
+        let key_material = km.unwrap();
+        assert_eq!(encryption.key_material_state(), Securing);
+
+        let mut decryption = Decryption::new(None);
+        assert!(decryption.decrypt(encrypted_packet).is_err());
+        assert_eq!(decryption.key_material_state(), NoSecret);
+
+        let wrong_secret = CipherSettings {
+            key_settings: KeySettings {
+                key_size: KeySize::AES192,
+                key_source: KeySource::Passphrase("0987654321".into()),
+            },
+            ..settings.clone()
+        };
+        let mut decryption = Decryption::new(Some(wrong_secret));
+        assert_eq!(decryption.key_material_state(), Secured);
+        assert!(decryption
+            .refresh_key_material(key_material.clone())
+            .is_err());
+        assert_eq!(decryption.key_material_state(), BadSecret);
+
+        let mut decryption = Decryption::new(Some(settings));
+        assert_eq!(
+            decryption.refresh_key_material(key_material.clone()),
+            Ok(Some(key_material.clone()))
+        );
+        assert_eq!(decryption.key_material_state(), Secured);
+
+        assert_eq!(encryption.handle_key_refresh_response(key_material), Ok(()));
+        assert_eq!(encryption.key_material_state(), Secured);
+    }
+
     #[test]
     fn refresh_key_material() {
         let settings = CipherSettings {
@@ -330,6 +552,43 @@ mod tests {
         assert_eq!(decrypted_packet, original_packet);
     }
 
+    #[test]
+    fn key_exhaustion() {
+        let settings = CipherSettings {
+            key_refresh: KeyMaterialRefreshSettings::new(3_000, 1_000).unwrap(),
+            ..new_settings()
+        };
+        let original_packet = data_packet(DataEncryption::None, "test key_exhaustion");
+        let mut encryption = Encryption::new(Some(settings.clone()));
+        let snapshot = encryption.snapshot().unwrap();
+        let spent_key_material = snapshot.stream_keys.wrap_with(&settings.key_settings);
+        // the new key hasn't been confirmed, and the old one is almost spent
+        encryption.restore(EncryptionSnapshot {
+            packets_until_key_switch: 10,
+            packets_with_active_sek: EncryptionState::PACKETS_PER_KEY - 3_000,
+            refreshing: true,
+            ..snapshot
+        });
+
+        for _ in 0..10 {
+            let (_, packet, _) = encryption.encrypt(original_packet.clone()).unwrap();
+            assert_eq!(packet.encryption, DataEncryption::Even);
+        }
+        // it switches anyway, announcing a replacement for the spent key straight away
+        let (_, _, km) = encryption.encrypt(original_packet.clone()).unwrap();
+        let key_material = km.unwrap();
+        assert_ne!(Some(&key_material), spent_key_material.as_ref());
+        assert_eq!(encryption.key_material_state(), KeyMaterialState::Securing);
+        let (_, packet, km) = encryption.encrypt(original_packet.clone()).unwrap();
+        assert_eq!(km, None);
+        assert_eq!(packet.encryption, DataEncryption::Odd);
+
+        let mut decryption = Decryption::new(Some(settings));
+        decryption.refresh_key_material(key_material).unwrap();
+        let (_, decrypted_packet) = decryption.decrypt(packet).unwrap();
+        assert_eq!(decrypted_packet, original_packet);
+    }
+
     #[test]
     fn retry_refresh_key_material() {
         let settings = CipherSettings {
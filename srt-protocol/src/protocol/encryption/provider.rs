@@ -0,0 +1,313 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Debug, Display, Formatter},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    options::KeyDerivation,
+    settings::{KeySize, Passphrase},
+};
+
+use super::{
+    backend::{crypto_backend, CryptoError},
+    key::{EncryptionKey, Salt},
+};
+
+/// Where the key encrypting key (KEK) of a connection comes from, which wraps the stream keys in
+/// the keying material exchanged with the peer
+///
+/// A [`Passphrase`] derives it with PBKDF2 like libsrt does. Other providers can fetch it from a
+/// KMS or an HSM instead, so the secret never has to be in the configuration. Both peers have to
+/// come up with the same key for the same salt.
+///
+/// The salt is chosen by the initiator of the connection and stays the same for its lifetime,
+/// refreshing the stream keys asks for the key of the same salt again.
+pub trait KeyProvider: Send + Sync {
+    /// The KEK of `key_size` for the connection using `salt`, [`KeySize::Unspecified`] stands
+    /// for AES-128
+    fn key_encrypting_key(
+        &self,
+        key_size: KeySize,
+        salt: &Salt,
+    ) -> Result<EncryptionKey, KeyProviderError>;
+
+    /// The salt to start a connection with as its initiator
+    fn new_salt(&self) -> Salt {
+        Salt::new_random()
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct KeyProviderError(pub String);
+
+impl Display for KeyProviderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "key provider failed: {}", self.0)
+    }
+}
+
+impl std::error::Error for KeyProviderError {}
+
+impl From<CryptoError> for KeyProviderError {
+    fn from(error: CryptoError) -> Self {
+        KeyProviderError(error.to_string())
+    }
+}
+
+/// A [`KeyProvider`] shared by the settings of connections, two are equal if they're the same
+/// provider
+#[derive(Clone)]
+pub struct SharedKeyProvider(Arc<dyn KeyProvider>);
+
+impl SharedKeyProvider {
+    pub fn new(provider: impl KeyProvider + 'static) -> Self {
+        Self(Arc::new(provider))
+    }
+}
+
+impl From<Arc<dyn KeyProvider>> for SharedKeyProvider {
+    fn from(provider: Arc<dyn KeyProvider>) -> Self {
+        Self(provider)
+    }
+}
+
+impl KeyProvider for SharedKeyProvider {
+    fn key_encrypting_key(
+        &self,
+        key_size: KeySize,
+        salt: &Salt,
+    ) -> Result<EncryptionKey, KeyProviderError> {
+        self.0.key_encrypting_key(key_size, salt)
+    }
+
+    fn new_salt(&self) -> Salt {
+        self.0.new_salt()
+    }
+}
+
+impl PartialEq for SharedKeyProvider {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SharedKeyProvider {}
+
+impl Debug for SharedKeyProvider {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedKeyProvider").finish()
+    }
+}
+
+impl KeyProvider for Passphrase {
+    fn key_encrypting_key(
+        &self,
+        key_size: KeySize,
+        salt: &Salt,
+    ) -> Result<EncryptionKey, KeyProviderError> {
+        derive_key(self, &KeyDerivation::default(), key_size, salt)
+    }
+}
+
+// the keys derived last, a derivation costs a couple of milliseconds
+const DERIVED_KEYS: usize = 64;
+
+struct DerivedKey {
+    iterations: u32,
+    salt: Vec<u8>,
+    key: EncryptionKey,
+}
+
+/// The keys derived from a passphrase, which it keeps, see
+/// [`KeyDerivation`](crate::options::KeyDerivation)
+#[derive(Default)]
+pub(crate) struct DerivedKeys(Mutex<VecDeque<DerivedKey>>);
+
+/// Derive the key encrypting key from a passphrase, or take it from the keys derived before
+pub(crate) fn derive_key(
+    passphrase: &Passphrase,
+    derivation: &KeyDerivation,
+    key_size: KeySize,
+    salt: &Salt,
+) -> Result<EncryptionKey, KeyProviderError> {
+    // Generate the key encrypting key from the passphrase
+    // https://github.com/Haivision/srt/blob/2ef4ef003c2006df1458de6d47fbe3d2338edf69/haicrypt/hcrypt_sa.c#L69-L103
+
+    // the reference implementation uses the last 8 (at max) bytes of the salt. Sources:
+    // https://github.com/Haivision/srt/blob/2ef4ef003c2006df1458de6d47fbe3d2338edf69/haicrypt/haicrypt.h#L72
+    // https://github.com/Haivision/srt/blob/2ef4ef003c2006df1458de6d47fbe3d2338edf69/haicrypt/hcrypt_sa.c#L77-L85
+    // 2048 iterations is what the reference implementation uses
+    // https://github.com/Haivision/srt/blob/2ef4ef003c2006df1458de6d47fbe3d2338edf69/haicrypt/haicrypt.h#L73
+    let salt = salt.as_slice();
+    let salt = &salt[salt.len() - usize::min(derivation.salt_length, salt.len())..];
+    let key_size = match key_size {
+        KeySize::Unspecified => KeySize::AES128,
+        key_size => key_size,
+    };
+
+    let cached = |k: &DerivedKey| {
+        k.iterations == derivation.iterations
+            && k.salt == salt
+            && k.key.len() == key_size.as_usize()
+    };
+    {
+        let mut derived = passphrase.derived_keys().0.lock().unwrap();
+        if let Some(i) = derived.iter().position(cached) {
+            let hit = derived.remove(i).unwrap();
+            let key = hit.key.clone();
+            derived.push_back(hit);
+            return Ok(key);
+        }
+    }
+
+    fn new_key<const N: usize>(
+        passphrase: &Passphrase,
+        salt: &[u8],
+        iterations: u32,
+    ) -> Result<[u8; N], CryptoError> {
+        let mut key = [0u8; N];
+        crypto_backend()?.pbkdf2_hmac_sha1(passphrase.as_bytes(), salt, iterations, &mut key)?;
+        Ok(key)
+    }
+
+    use EncryptionKey::*;
+    let iterations = derivation.iterations;
+    let key = match key_size {
+        KeySize::AES192 => Bytes24(new_key(passphrase, salt, iterations)?),
+        KeySize::AES256 => Bytes32(new_key(passphrase, salt, iterations)?),
+        _ => Bytes16(new_key(passphrase, salt, iterations)?),
+    };
+
+    let mut derived = passphrase.derived_keys().0.lock().unwrap();
+    if derived.len() == DERIVED_KEYS {
+        derived.pop_front();
+    }
+    derived.push_back(DerivedKey {
+        iterations,
+        salt: salt.to_vec(),
+        key: key.clone(),
+    });
+    Ok(key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Fixed(EncryptionKey);
+
+    impl KeyProvider for Fixed {
+        fn key_encrypting_key(
+            &self,
+            key_size: KeySize,
+            _: &Salt,
+        ) -> Result<EncryptionKey, KeyProviderError> {
+            match key_size {
+                KeySize::AES128 | KeySize::Unspecified => Ok(self.0.clone()),
+                _ => Err(KeyProviderError("only AES-128".into())),
+            }
+        }
+    }
+
+    #[test]
+    fn shared() {
+        let provider = SharedKeyProvider::new(Fixed(EncryptionKey::Bytes16([7; 16])));
+        let salt = Salt::new_random();
+
+        assert_eq!(provider, provider.clone());
+        assert_ne!(
+            provider,
+            SharedKeyProvider::new(Fixed(EncryptionKey::Bytes16([7; 16])))
+        );
+        assert_eq!(format!("{provider:?}"), "SharedKeyProvider");
+        assert_eq!(
+            provider.key_encrypting_key(KeySize::AES128, &salt),
+            Ok(EncryptionKey::Bytes16([7; 16]))
+        );
+        assert_eq!(
+            provider
+                .key_encrypting_key(KeySize::AES256, &salt)
+                .map_err(|e| e.to_string()),
+            Err("key provider failed: only AES-128".to_string())
+        );
+    }
+
+    #[test]
+    fn key_derivation() {
+        let passphrase = Passphrase::from("password123");
+        let salt = Salt::new_random();
+        let derive = |derivation: &KeyDerivation| {
+            derive_key(&passphrase, derivation, KeySize::AES128, &salt).unwrap()
+        };
+
+        let default = derive(&KeyDerivation::default());
+        assert_eq!(
+            passphrase.key_encrypting_key(KeySize::AES128, &salt),
+            Ok(default.clone())
+        );
+        assert_eq!(
+            passphrase.key_encrypting_key(KeySize::Unspecified, &salt),
+            Ok(default.clone())
+        );
+        // the initial salt only picks the salt of new connections
+        assert_eq!(
+            derive(&KeyDerivation {
+                initial_salt: Some(Salt::new_random()),
+                ..Default::default()
+            }),
+            default
+        );
+        assert_ne!(
+            derive(&KeyDerivation {
+                iterations: 4096,
+                ..Default::default()
+            }),
+            default
+        );
+        assert_ne!(
+            derive(&KeyDerivation {
+                salt_length: 16,
+                ..Default::default()
+            }),
+            default
+        );
+        assert_eq!(
+            derive_key(
+                &passphrase,
+                &KeyDerivation::default(),
+                KeySize::AES256,
+                &salt
+            )
+            .unwrap()
+            .len(),
+            32
+        );
+    }
+
+    #[test]
+    fn derived_keys_kept_with_passphrase() {
+        let passphrase = Passphrase::from("password123");
+        let salt = Salt::new_random();
+        let count = |p: &Passphrase| p.derived_keys().0.lock().unwrap().len();
+
+        let key = derive_key(&passphrase, &Default::default(), KeySize::AES128, &salt).unwrap();
+        let clone = passphrase.clone();
+        assert_eq!(count(&clone), 1);
+        assert_eq!(
+            derive_key(&clone, &Default::default(), KeySize::AES128, &salt),
+            Ok(key.clone())
+        );
+        assert_eq!(count(&clone), 1);
+
+        // an equal passphrase made anew doesn't share the keys
+        let other = Passphrase::from("password123");
+        assert_eq!(other, passphrase);
+        assert_eq!(count(&other), 0);
+        assert_eq!(
+            derive_key(&other, &Default::default(), KeySize::AES128, &salt),
+            Ok(key)
+        );
+        assert_eq!(count(&passphrase), 1);
+    }
+}
@@ -1,26 +1,27 @@
 use std::fmt::Debug;
 
-use aes::cipher::StreamCipher;
-use cipher::KeyIvInit;
-
-type Aes128Ctr = ctr::Ctr64BE<aes::Aes128>;
-type Aes192Ctr = ctr::Ctr64BE<aes::Aes192>;
-type Aes256Ctr = ctr::Ctr64BE<aes::Aes256>;
+use log::warn;
 
 use crate::{
     packet::*,
     settings::{KeySettings, KeySize},
 };
 
-use super::key::*;
+use super::{
+    backend::{crypto_backend, CryptoError},
+    key::*,
+    provider::KeyProviderError,
+};
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum KeyMaterialError {
     NoKeys,
     InvalidSaltLength,
     InvalidKeyFlags(KeyFlags, KeySize, usize),
-    InvalidInitializationVector(WrapInitializationVector),
+    UnwrapFailed,
     InvalidRefreshResponse(KeyingMaterialMessage),
+    KeyProvider(KeyProviderError),
+    Crypto(CryptoError),
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -40,8 +41,12 @@ impl StreamEncryptionKeys {
     }
 
     pub fn new_random(key_size: KeySize) -> Self {
+        Self::new_random_with_salt(Salt::new_random(), key_size)
+    }
+
+    pub fn new_random_with_salt(salt: Salt, key_size: KeySize) -> Self {
         Self {
-            salt: Salt::new_random(),
+            salt,
             even_key: Some(EncryptionKey::new_random(key_size)),
             odd_key: Some(EncryptionKey::new_random(key_size)),
         }
@@ -62,6 +67,14 @@ impl StreamEncryptionKeys {
         self.wrap_with(key_settings)
     }
 
+    /// The size of the keys the stream is encrypted with, [`KeySize::Unspecified`] before
+    /// there are any
+    pub fn key_size(&self) -> KeySize {
+        let key = self.even_key.as_ref().or(self.odd_key.as_ref());
+        key.and_then(|key| KeySize::from_raw(key.len() as u16))
+            .unwrap_or_default()
+    }
+
     pub fn first_active_sek(&self) -> DataEncryption {
         if self.even_key.is_some() {
             DataEncryption::Even
@@ -79,7 +92,7 @@ impl StreamEncryptionKeys {
         use KeyMaterialError::*;
         // TODO: revisit errors, KeyingMaterialMessage has a lot of fields that ought be validated
         let salt = Salt::try_from(key_material.salt.as_slice()).map_err(|_| InvalidSaltLength)?;
-        let kek = KeyEncryptionKey::new(key_settings, &salt);
+        let kek = KeyEncryptionKey::new(key_settings, &salt).map_err(KeyProvider)?;
 
         if key_material.key_flags.bits().count_ones() as usize * key_settings.key_size.as_usize()
             + 8
@@ -95,7 +108,10 @@ impl StreamEncryptionKeys {
         let wrapped_keys = key_material.wrapped_keys.as_slice();
         let keys = kek
             .decrypt_wrapped_keys(wrapped_keys)
-            .map_err(InvalidInitializationVector)?;
+            .map_err(|e| match e {
+                CryptoError::Integrity => UnwrapFailed,
+                e => Crypto(e),
+            })?;
 
         let key_flags = key_material.key_flags;
         let key_size = kek.len();
@@ -117,8 +133,31 @@ impl StreamEncryptionKeys {
         })
     }
 
+    /// Take on the keys of a keying material message from the peer. One with a single key leaves
+    /// the other one as it was, e.g. libsrt's once it decommissioned the old key, which may
+    /// still be needed for retransmissions.
+    pub fn update(&mut self, keys: StreamEncryptionKeys) {
+        if keys.salt != self.salt {
+            *self = keys;
+            return;
+        }
+        if keys.even_key.is_some() {
+            self.even_key = keys.even_key;
+        }
+        if keys.odd_key.is_some() {
+            self.odd_key = keys.odd_key;
+        }
+    }
+
+    /// None without keys, or when the key source fails
     pub fn wrap_with(&self, key_settings: &KeySettings) -> Option<KeyingMaterialMessage> {
-        let kek = KeyEncryptionKey::new(key_settings, &self.salt);
+        let kek = match KeyEncryptionKey::new(key_settings, &self.salt) {
+            Ok(kek) => kek,
+            Err(e) => {
+                warn!("can't wrap the stream keys: {e}");
+                return None;
+            }
+        };
 
         let mut keys = Vec::new();
         if let Some(k) = &self.even_key {
@@ -128,7 +167,13 @@ impl StreamEncryptionKeys {
             keys.extend(k.as_bytes());
         }
 
-        let wrapped_keys = kek.encrypt_wrapped_keys(keys.as_slice());
+        let wrapped_keys = match kek.encrypt_wrapped_keys(keys.as_slice()) {
+            Ok(wrapped_keys) => wrapped_keys,
+            Err(e) => {
+                warn!("can't wrap the stream keys: {e}");
+                return None;
+            }
+        };
 
         Some(KeyingMaterialMessage {
             pt: PacketType::KeyingMaterial,
@@ -155,13 +200,9 @@ impl StreamEncryptionKeys {
         let sek = self.get_key(sek_selection)?;
         let iv = self.salt.generate_strean_iv_for(seq_number);
 
-        let nonce = iv.as_bytes();
-        use EncryptionKey::*;
-        match sek {
-            Bytes16(key) => Aes128Ctr::new(key.into(), nonce[..].into()).apply_keystream(data),
-            Bytes24(key) => Aes192Ctr::new(key.into(), nonce[..].into()).apply_keystream(data),
-            Bytes32(key) => Aes256Ctr::new(key.into(), nonce[..].into()).apply_keystream(data),
-        };
+        crypto_backend()
+            .and_then(|backend| backend.aes_ctr(sek.as_bytes(), iv.as_bytes(), data))
+            .ok()?;
 
         Some(data.len())
     }
@@ -175,13 +216,28 @@ impl StreamEncryptionKeys {
         let sek = self.get_key(sek_selection)?;
         let iv = self.salt.generate_strean_iv_for(seq_number);
 
-        let nonce = iv.as_bytes();
-        use EncryptionKey::*;
-        match sek {
-            Bytes16(key) => Aes128Ctr::new(key.into(), nonce[..].into()).apply_keystream(data),
-            Bytes24(key) => Aes192Ctr::new(key.into(), nonce[..].into()).apply_keystream(data),
-            Bytes32(key) => Aes256Ctr::new(key.into(), nonce[..].into()).apply_keystream(data),
-        };
+        crypto_backend()
+            .and_then(|backend| backend.aes_ctr(sek.as_bytes(), iv.as_bytes(), data))
+            .ok()?;
+
+        Some(data.len())
+    }
+
+    /// Encrypt or decrypt the payload of a control packet for the socket `socket_id`, see
+    /// [`Salt::generate_control_iv_for`]
+    pub fn apply_control_keystream(
+        &self,
+        sek_selection: DataEncryption,
+        socket_id: SocketId,
+        nonce: u64,
+        data: &mut [u8],
+    ) -> Option<usize> {
+        let sek = self.get_key(sek_selection)?;
+        let iv = self.salt.generate_control_iv_for(socket_id, nonce);
+
+        crypto_backend()
+            .and_then(|backend| backend.aes_ctr(sek.as_bytes(), iv.as_bytes(), data))
+            .ok()?;
 
         Some(data.len())
     }
@@ -199,15 +255,95 @@ impl StreamEncryptionKeys {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::settings::KeySource;
     use assert_matches::assert_matches;
 
     fn key_settings() -> KeySettings {
         KeySettings {
             key_size: KeySize::AES128,
-            passphrase: "password123".into(),
+            key_source: KeySource::Passphrase("password123".into()),
         }
     }
 
+    // payloads encrypted with OpenSSL's AES-128-CTR and the IV of the reference implementation,
+    // which is what it encrypts with, around the sequence number wrap and with both keys
+    const CAPTURE: [(DataEncryption, u32, &str); 8] = [
+        (DataEncryption::Even, 0x7fff_fffe, "1d3f39097745d634059e46252e52f30c9a6ee2893049fbc196d07ac39000c406ea2ca15a24df8cf60e3760"),
+        (DataEncryption::Even, 0x7fff_ffff, "b809ce2442a7943f061c912ad66dd7320c1cf8ef1bf2a086fce6812b1c58dfd43322e7ebedb12eb2f485dd"),
+        (DataEncryption::Even, 0, "1427c6f21a91055a3eaf184ffe3765846cb91ab2fa0d52c6dccd3031cb69980c2f95b211d141529043c2ed"),
+        (DataEncryption::Even, 1, "4ccdc0f204d3809ffb95203f0e721f5aee8367d7d8cb345f63c091da6448c46af9efd284db36f20ac42321"),
+        (DataEncryption::Odd, 0x7fff_fffe, "70fdffa3dd1ff8d7d8cec22b656476464093ead7dcbea5acea17f9dc9a7ce051a86b44d540c0dd09f3f9"),
+        (DataEncryption::Odd, 0x7fff_ffff, "ed19f4fdbfe74e018dc08e0018adf189d15c37525fcc806e49ff4506f93596d8bca418b716984d3273aa"),
+        (DataEncryption::Odd, 0, "3c31b47a9dae78c4b0ff48b51b8cc0181e11f4f6049d1d9b3867f87032e4a2a376c7cfbbe5366361d358"),
+        (DataEncryption::Odd, 1, "36daae33b40d2bdeb795bfcc89a22abbf99fc749f09d52a85587f18523421e40bf510ee13056d5eb52ad"),
+    ];
+
+    fn capture_keys() -> StreamEncryptionKeys {
+        StreamEncryptionKeys {
+            salt: Salt::try_from(&hex::decode("87647f8a2361fb1a9e692de576985949").unwrap())
+                .unwrap(),
+            even_key: EncryptionKey::try_from(
+                &hex::decode("0dabc86e2f32b4a7b9bba2f3312ae422").unwrap(),
+            )
+            .ok(),
+            odd_key: EncryptionKey::try_from(
+                &hex::decode("5c1f0e9a7b3d2c4e6f8091a2b3c4d5e6").unwrap(),
+            )
+            .ok(),
+        }
+    }
+
+    fn plaintext(sek: DataEncryption, seq_number: u32) -> String {
+        let key = if sek == DataEncryption::Even {
+            "even"
+        } else {
+            "odd"
+        };
+        format!("packet {seq_number:08x} encrypted with the {key} key")
+    }
+
+    #[test]
+    fn reordered_capture() {
+        let keys = capture_keys();
+        // out of order, across the wrap and the key switch, with retransmissions
+        for i in [2, 0, 5, 1, 7, 3, 0, 6, 4, 2, 5] {
+            let (sek, seq_number, encrypted) = CAPTURE[i];
+            let mut data = hex::decode(encrypted).unwrap();
+            assert_eq!(
+                keys.decrypt(sek, SeqNumber(seq_number), &mut data),
+                Some(data.len())
+            );
+            assert_eq!(String::from_utf8(data).unwrap(), plaintext(sek, seq_number));
+        }
+
+        for (sek, seq_number, encrypted) in CAPTURE {
+            let mut data = plaintext(sek, seq_number).into_bytes();
+            keys.encrypt(sek, SeqNumber(seq_number), &mut data);
+            assert_eq!(hex::encode(data), encrypted);
+        }
+    }
+
+    #[test]
+    fn update_single_key() {
+        let mut keys = capture_keys();
+        let mut odd_only = capture_keys();
+        odd_only.even_key = None;
+        odd_only.odd_key = Some(EncryptionKey::new_random(KeySize::AES128));
+        keys.update(odd_only.clone());
+
+        // the even key is still there for the retransmissions
+        let (sek, seq_number, encrypted) = CAPTURE[3];
+        let mut data = hex::decode(encrypted).unwrap();
+        keys.decrypt(sek, SeqNumber(seq_number), &mut data);
+        assert_eq!(String::from_utf8(data).unwrap(), plaintext(sek, seq_number));
+        assert_eq!(keys.odd_key, odd_only.odd_key);
+
+        // new keys altogether
+        let other = StreamEncryptionKeys::new_random(KeySize::AES128);
+        keys.update(other.clone());
+        assert_eq!(keys, other);
+    }
+
     #[test]
     fn wrap_keys() {
         let salt = b"\x00\x00\x00\x00\x00\x00\x00\x00\x85\x2c\x3c\xcd\x02\x65\x1a\x22";
@@ -218,7 +354,7 @@ mod test {
                 .ok(),
         };
 
-        let kek = KeyEncryptionKey::new(&key_settings(), &stream_encryption.salt);
+        let kek = KeyEncryptionKey::new(&key_settings(), &stream_encryption.salt).unwrap();
         assert_eq!(
             kek.as_bytes(),
             b"\xe9\xa0\xa4\x30\x2f\x59\xd0\x63\xc8\x83\x32\xbe\x35\x88\x82\x08"
@@ -249,7 +385,7 @@ mod test {
     fn bad_password() {
         let key_settings = &KeySettings {
             key_size: KeySize::AES128,
-            passphrase: "badpassword".into(),
+            key_source: KeySource::Passphrase("badpassword".into()),
         };
         let key_material = KeyingMaterialMessage {
             pt: PacketType::KeyingMaterial,
@@ -276,7 +412,7 @@ mod test {
             even_key: None,
         };
 
-        let kek = KeyEncryptionKey::new(&key_settings(), &stream_encryption.salt);
+        let kek = KeyEncryptionKey::new(&key_settings(), &stream_encryption.salt).unwrap();
         assert_eq!(
             kek.as_bytes(),
             b"\xde#\x1b\xfd9\x93z\xfb\xc3w\xa7\x80\xee\x80'\xa3"
@@ -0,0 +1,203 @@
+use std::{cmp::max, collections::BTreeMap, mem::size_of, ops::Range};
+
+use crate::packet::SeqNumber;
+
+/// Lost sequence numbers, kept as disjoint ranges so a burst of thousands of lost packets is a
+/// single entry. Every operation is O(log n) in the number of ranges, plus the ranges it removes.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LossList {
+    // start -> exclusive end, no range is empty and no two overlap or touch
+    ranges: BTreeMap<SeqNumber, SeqNumber>,
+    len: usize,
+}
+
+impl LossList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lost packets, not ranges
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// The heap the ranges take, give or take the nodes of the map that are only half full
+    pub fn memory_usage(&self) -> usize {
+        2 * self.ranges.len() * size_of::<(SeqNumber, SeqNumber)>()
+    }
+
+    pub fn first(&self) -> Option<SeqNumber> {
+        self.ranges.keys().next().copied()
+    }
+
+    pub fn contains(&self, seq_number: SeqNumber) -> bool {
+        self.ranges
+            .range(..=seq_number)
+            .next_back()
+            .is_some_and(|(_, &end)| seq_number < end)
+    }
+
+    pub fn ranges(&self) -> impl Iterator<Item = Range<SeqNumber>> + '_ {
+        self.ranges.iter().map(|(&start, &end)| start..end)
+    }
+
+    pub fn insert(&mut self, seq_number: SeqNumber) {
+        self.insert_range(seq_number..seq_number + 1)
+    }
+
+    pub fn insert_range(&mut self, range: Range<SeqNumber>) {
+        if range.is_empty() {
+            return;
+        }
+        let (mut start, mut end) = (range.start, range.end);
+        if let Some((&s, &e)) = self.ranges.range(..=start).next_back() {
+            if e >= start {
+                start = s;
+                end = max(end, e);
+                self.remove_entry(s, e);
+            }
+        }
+        while let Some((&s, &e)) = self.ranges.range(start..=end).next() {
+            end = max(end, e);
+            self.remove_entry(s, e);
+        }
+        self.ranges.insert(start, end);
+        self.len += (end - start) as usize;
+    }
+
+    pub fn pop_first(&mut self) -> Option<SeqNumber> {
+        let (&start, &end) = self.ranges.iter().next()?;
+        self.remove_entry(start, end);
+        if start + 1 < end {
+            self.ranges.insert(start + 1, end);
+            self.len += (end - (start + 1)) as usize;
+        }
+        Some(start)
+    }
+
+    /// Returns whether it was lost
+    pub fn remove(&mut self, seq_number: SeqNumber) -> bool {
+        self.remove_range(seq_number..seq_number + 1) > 0
+    }
+
+    /// Returns how many of them were lost
+    pub fn remove_range(&mut self, range: Range<SeqNumber>) -> usize {
+        if range.is_empty() {
+            return 0;
+        }
+        let before = self.len;
+        if let Some((&s, &e)) = self.ranges.range(..range.start).next_back() {
+            if e > range.start {
+                self.remove_entry(s, e);
+                self.insert_entry(s, range.start);
+                self.insert_entry(range.end, e);
+            }
+        }
+        while let Some((&s, &e)) = self.ranges.range(range.clone()).next() {
+            self.remove_entry(s, e);
+            self.insert_entry(range.end, e);
+        }
+        before - self.len
+    }
+
+    /// Returns how many lost packets there were before `seq_number`
+    pub fn remove_before(&mut self, seq_number: SeqNumber) -> usize {
+        match self.first() {
+            Some(first) if first < seq_number => self.remove_range(first..seq_number),
+            _ => 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+        self.len = 0;
+    }
+
+    fn remove_entry(&mut self, start: SeqNumber, end: SeqNumber) {
+        self.ranges.remove(&start);
+        self.len -= (end - start) as usize;
+    }
+
+    // what's left of a range that was cut, if anything
+    fn insert_entry(&mut self, start: SeqNumber, end: SeqNumber) {
+        if start < end {
+            self.ranges.insert(start, end);
+            self.len += (end - start) as usize;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ranges(list: &LossList) -> Vec<Range<SeqNumber>> {
+        list.ranges().collect()
+    }
+
+    #[test]
+    fn merges_and_splits() {
+        let mut list = LossList::new();
+        list.insert_range(SeqNumber(10)..SeqNumber(20));
+        list.insert_range(SeqNumber(30)..SeqNumber(40));
+        list.insert(SeqNumber(20));
+        list.insert_range(SeqNumber(25)..SeqNumber(30));
+        assert_eq!(
+            ranges(&list),
+            [SeqNumber(10)..SeqNumber(21), SeqNumber(25)..SeqNumber(40)]
+        );
+        assert_eq!(list.len(), 26);
+
+        // bridging the gap merges everything
+        list.insert_range(SeqNumber(15)..SeqNumber(26));
+        assert_eq!(ranges(&list), [SeqNumber(10)..SeqNumber(40)]);
+        assert_eq!(list.len(), 30);
+
+        assert!(list.remove(SeqNumber(20)));
+        assert!(!list.remove(SeqNumber(20)));
+        assert!(!list.contains(SeqNumber(20)));
+        assert!(list.contains(SeqNumber(21)));
+        assert_eq!(list.remove_range(SeqNumber(30)..SeqNumber(50)), 10);
+        assert_eq!(
+            ranges(&list),
+            [SeqNumber(10)..SeqNumber(20), SeqNumber(21)..SeqNumber(30)]
+        );
+
+        assert_eq!(list.pop_first(), Some(SeqNumber(10)));
+        assert_eq!(list.first(), Some(SeqNumber(11)));
+        assert_eq!(list.remove_before(SeqNumber(25)), 13);
+        assert_eq!(ranges(&list), [SeqNumber(25)..SeqNumber(30)]);
+        assert_eq!(list.len(), 5);
+
+        list.clear();
+        assert!(list.is_empty());
+        assert_eq!(list.pop_first(), None);
+    }
+
+    #[test]
+    fn wrap_around() {
+        let mut list = LossList::new();
+        list.insert_range(SeqNumber(0) - 6..SeqNumber(5));
+        assert_eq!(list.len(), 11);
+        assert_eq!(list.remove_before(SeqNumber(0)), 6);
+        assert_eq!(list.pop_first(), Some(SeqNumber(0)));
+        assert_eq!(ranges(&list), [SeqNumber(1)..SeqNumber(5)]);
+    }
+
+    #[test]
+    fn burst() {
+        // a burst of lost packets is one range, however it's reported
+        let mut list = LossList::new();
+        for seq_number in 0..100_000 {
+            list.insert(SeqNumber(seq_number * 2));
+            list.insert(SeqNumber(seq_number * 2 + 1));
+        }
+        assert_eq!(ranges(&list), [SeqNumber(0)..SeqNumber(200_000)]);
+        assert_eq!(list.remove_before(SeqNumber(150_000)), 150_000);
+        assert_eq!(list.len(), 50_000);
+    }
+}
@@ -1,7 +1,11 @@
-pub mod encryption;
-pub mod handshake;
-pub mod output;
-pub mod pending_connection;
-pub mod receiver;
-pub mod sender;
 pub mod time;
+
+cfg_std! {
+    pub mod encryption;
+    pub mod handshake;
+    pub mod loss_list;
+    pub mod output;
+    pub mod pending_connection;
+    pub mod receiver;
+    pub mod sender;
+}
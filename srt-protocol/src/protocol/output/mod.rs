@@ -1,12 +1,13 @@
-use std::{
-    collections::VecDeque,
-    time::{Duration, Instant},
-};
+mod rate;
+
+pub use rate::DeliveryRate;
+
+use std::{cmp::max, collections::VecDeque, mem::size_of, time::Duration};
 
 use crate::{
     connection::ConnectionSettings,
     packet::*,
-    protocol::time::{TimeBase, Timer},
+    protocol::time::{Instant, TimeBase, Timer},
 };
 
 #[derive(Debug)]
@@ -15,6 +16,11 @@ pub struct Output {
     time_base: TimeBase,
     packets: VecDeque<Packet>,
     keepalive: Timer,
+    keepalive_interval: Duration,
+    adaptive_keepalive: bool,
+    last_peer_packet: Instant,
+    // how many 32-bit words of loss list fit into a NAK packet
+    max_loss_list_len: usize,
 }
 
 impl Output {
@@ -23,7 +29,11 @@ impl Output {
             remote_sockid: settings.remote_sockid,
             time_base: TimeBase::new(settings.socket_start_time),
             packets: VecDeque::new(),
-            keepalive: Timer::new(settings.socket_start_time, Duration::from_secs(1)),
+            keepalive: Timer::new(settings.socket_start_time, settings.keepalive_interval),
+            keepalive_interval: settings.keepalive_interval,
+            adaptive_keepalive: settings.adaptive_keepalive,
+            last_peer_packet: settings.socket_start_time,
+            max_loss_list_len: settings.max_packet_size.0 as usize / 4,
         }
     }
 
@@ -33,11 +43,14 @@ impl Output {
 
     pub fn send_control(&mut self, now: Instant, control: ControlTypes) {
         self.keepalive.reset(now);
-        self.packets.push_back(Packet::Control(ControlPacket {
-            timestamp: self.time_base.timestamp_from(now),
-            dest_sockid: self.remote_sockid,
-            control_type: control,
-        }));
+        let timestamp = self.time_base.timestamp_from(now);
+        if let Some(control_type) = self.coalesce(timestamp, control) {
+            self.packets.push_back(Packet::Control(ControlPacket {
+                timestamp,
+                dest_sockid: self.remote_sockid,
+                control_type,
+            }));
+        }
     }
 
     pub fn send_data(&mut self, now: Instant, data: DataPacket) {
@@ -45,7 +58,21 @@ impl Output {
         self.packets.push_back(Packet::Data(data));
     }
 
+    pub fn on_peer_packet(&mut self, now: Instant) {
+        self.last_peer_packet = now;
+    }
+
     pub fn ensure_alive(&mut self, now: Instant) {
+        // nothing heard from the peer for a while, the NAT may have dropped the mapping, so try
+        // harder to set it up again, until the peer is heard from
+        let period = if self.adaptive_keepalive
+            && now.saturating_duration_since(self.last_peer_packet) > self.keepalive_interval
+        {
+            self.keepalive_interval / 4
+        } else {
+            self.keepalive_interval
+        };
+        self.keepalive.set_period(period);
         if self.keepalive.check_expired(now).is_some() {
             self.send_control(now, ControlTypes::KeepAlive)
         }
@@ -54,4 +81,50 @@ impl Output {
     pub fn pop_packet(&mut self) -> Option<Packet> {
         self.packets.pop_front()
     }
+
+    /// The packets queued to go out, which come from the send buffer or are control packets
+    pub fn memory_usage(&self) -> usize {
+        let payloads: usize = self
+            .packets
+            .iter()
+            .filter_map(|p| p.data())
+            .map(|p| p.payload.len())
+            .sum();
+        self.packets.capacity() * size_of::<Packet>() + payloads
+    }
+
+    // Everything queued goes out in the same flush, so under heavy loss a loss report or a light
+    // ACK still in the queue takes in the next one, rather than each of them taking a packet.
+    // Light ACKs are cumulative, but only the latest ACK of any kind can be moved on, the peer
+    // must not see it go backwards.
+    fn coalesce(&mut self, timestamp: TimeStamp, control: ControlTypes) -> Option<ControlTypes> {
+        use Acknowledgement::Lite;
+        use ControlTypes::*;
+        let Some(queued) = self
+            .packets
+            .iter_mut()
+            .rev()
+            .find_map(|packet| match packet {
+                Packet::Control(queued) => match (&queued.control_type, &control) {
+                    (Nak(_), Nak(_)) | (Ack(_), Ack(Lite(_))) => Some(queued),
+                    _ => None,
+                },
+                _ => None,
+            })
+        else {
+            return Some(control);
+        };
+        match (&mut queued.control_type, control) {
+            (Nak(queued_list), Nak(loss_list)) => {
+                match queued_list.try_merge(&loss_list, self.max_loss_list_len) {
+                    Some(merged) => *queued_list = merged,
+                    None => return Some(Nak(loss_list)),
+                }
+            }
+            (Ack(Lite(queued_ack)), Ack(Lite(ack))) => *queued_ack = max(*queued_ack, ack),
+            (_, control) => return Some(control),
+        }
+        queued.timestamp = timestamp;
+        None
+    }
 }
@@ -0,0 +1,87 @@
+use std::{cmp::max, time::Duration};
+
+use crate::{options::DataRate, protocol::time::Instant};
+
+/// Caps the rate the received messages are released to the application at, so that whatever
+/// forwards them, e.g. to a set-top box over UDP, doesn't pass on the burst of messages that are
+/// released at once when a loss is recovered.
+///
+/// Each message released pushes the next release out by the time its payload takes at the rate.
+/// What was left unused while idle carries over, up to [`BURST`](Self::BURST), so a steady stream
+/// isn't held back by the timer waking up late.
+#[derive(Debug)]
+pub struct DeliveryRate {
+    rate: DataRate,
+    next_release: Option<Instant>,
+}
+
+impl DeliveryRate {
+    const BURST: Duration = Duration::from_millis(5);
+
+    pub fn new(rate: DataRate) -> Self {
+        Self {
+            rate,
+            next_release: None,
+        }
+    }
+
+    /// When the next message may be released, any time if `None`
+    pub fn next_release(&self) -> Option<Instant> {
+        self.next_release
+    }
+
+    pub fn is_ready(&self, now: Instant) -> bool {
+        self.next_release.is_none_or(|next| next <= now)
+    }
+
+    pub fn on_release(&mut self, now: Instant, bytes: usize) {
+        let earliest = now.checked_sub(Self::BURST).unwrap_or(now);
+        let start = self
+            .next_release
+            .map_or(earliest, |next| max(next, earliest));
+        let nanos = bytes as u128 * 1_000_000_000 / u128::from(max(self.rate.0, 1));
+        let spacing = Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX));
+        self.next_release = Some(start + spacing);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MILLIS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn delivery_rate() {
+        let start = Instant::now() + Duration::from_secs(1);
+        // 1000 bytes take 1 ms
+        let mut rate = DeliveryRate::new(DataRate(1_000_000));
+        assert!(rate.is_ready(start));
+
+        // a burst after being idle gets through at once
+        for _ in 0..5 {
+            assert!(rate.is_ready(start));
+            rate.on_release(start, 1000);
+        }
+        assert_eq!(rate.next_release(), Some(start));
+        rate.on_release(start, 1000);
+        assert_eq!(rate.next_release(), Some(start + MILLIS));
+        assert!(!rate.is_ready(start));
+
+        // then it's spaced out at the rate
+        let now = start + MILLIS;
+        assert!(rate.is_ready(now));
+        rate.on_release(now, 2000);
+        assert_eq!(rate.next_release(), Some(start + 3 * MILLIS));
+        assert!(!rate.is_ready(start + 2 * MILLIS));
+
+        // waking up late doesn't lose time, up to the burst
+        let now = start + 4 * MILLIS;
+        rate.on_release(now, 1000);
+        assert_eq!(rate.next_release(), Some(start + 4 * MILLIS));
+
+        let now = start + 100 * MILLIS;
+        rate.on_release(now, 1000);
+        assert_eq!(rate.next_release(), Some(now - 4 * MILLIS));
+    }
+}
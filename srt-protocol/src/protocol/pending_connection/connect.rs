@@ -1,8 +1,5 @@
 use std::io::ErrorKind;
-use std::{
-    net::{IpAddr, SocketAddr},
-    time::Instant,
-};
+use std::net::{IpAddr, SocketAddr};
 
 use log::info;
 use ConnectError::*;
@@ -10,7 +7,8 @@ use ConnectState::*;
 use ConnectionResult::*;
 
 use crate::{
-    connection::Connection, packet::*, protocol::handshake::Handshake, settings::ConnInitSettings,
+    connection::Connection, packet::*, protocol::handshake::Handshake, protocol::time::Instant,
+    settings::ConnInitSettings,
 };
 
 use super::{
@@ -134,6 +132,9 @@ impl Connect {
             (ShakeType::Conclusion, 5, from) if from == self.remote => {
                 let settings = match initiator.finish_hsv5_initiation(&info, from, now) {
                     Ok(s) => s,
+                    Err(Mismatched(mismatch)) => {
+                        return Reject(None, ConnectionReject::Mismatched(mismatch))
+                    }
                     Err(rr) => return NotHandled(rr),
                 };
 
@@ -214,8 +215,8 @@ mod test {
     use rand::random;
 
     use crate::{
-        options::{self, PacketCount, PacketSize},
-        protocol::pending_connection::ConnectionReject,
+        options::{self, PacketCount, PacketSize, SrtVersion},
+        protocol::pending_connection::{ConnectionReject, SettingsMismatch},
     };
 
     use super::*;
@@ -281,6 +282,85 @@ mod test {
         );
     }
 
+    #[test]
+    fn message_tags_not_answered() {
+        // a responder that doesn't know of tags, e.g. libsrt, leaves them out of its answer
+        let settings = ConnInitSettings {
+            message_tags: true,
+            ..test_settings()
+        };
+        let mut c = Connect::new(
+            test_remote(),
+            [127, 0, 0, 1].into(),
+            settings,
+            None,
+            random(),
+        );
+        c.handle_tick(Instant::now());
+
+        let handshake = |shake_type, info| {
+            Packet::Control(ControlPacket {
+                timestamp: TimeStamp::from_micros(0),
+                dest_sockid: TEST_SOCKID,
+                control_type: ControlTypes::Handshake(HandshakeControlInfo {
+                    syn_cookie: 5554,
+                    socket_id: SocketId(5678),
+                    info,
+                    init_seq_num: SeqNumber(0),
+                    max_packet_size: PacketSize(1500),
+                    max_flow_size: PacketCount(8192),
+                    shake_type,
+                    peer_addr: [127, 0, 0, 1].into(),
+                }),
+            })
+        };
+        let induction = handshake(
+            ShakeType::Induction,
+            HandshakeVsInfo::V5(HsV5Info::default()),
+        );
+        let resp = c.handle_packet(Ok((induction, test_remote())), Instant::now());
+        assert_matches!(
+            resp,
+            ConnectionResult::SendPacket((
+                Packet::Control(ControlPacket {
+                    control_type: ControlTypes::Handshake(HandshakeControlInfo {
+                        info: HandshakeVsInfo::V5(HsV5Info {
+                            message_tags: true,
+                            ..
+                        }),
+                        ..
+                    }),
+                    ..
+                }),
+                _
+            ))
+        );
+
+        let conclusion = handshake(
+            ShakeType::Conclusion,
+            HandshakeVsInfo::V5(HsV5Info {
+                ext_hs: Some(SrtControlPacket::HandshakeResponse(SrtHandshake {
+                    version: SrtVersion::CURRENT,
+                    flags: SrtShakeFlags::SUPPORTED,
+                    send_latency: Duration::from_millis(20),
+                    recv_latency: Duration::from_millis(20),
+                })),
+                ..HsV5Info::default()
+            }),
+        );
+        let resp = c.handle_packet(Ok((conclusion, test_remote())), Instant::now());
+        assert_matches!(
+            resp,
+            ConnectionResult::Reject(
+                None,
+                ConnectionReject::Mismatched(SettingsMismatch::MessageTags {
+                    local: true,
+                    remote: false,
+                }),
+            )
+        );
+    }
+
     fn test_remote() -> SocketAddr {
         ([127, 0, 0, 1], 6666).into()
     }
@@ -289,22 +369,56 @@ mod test {
         Connect::new(
             test_remote(),
             [127, 0, 0, 1].into(),
-            ConnInitSettings {
-                local_sockid: TEST_SOCKID,
-                key_settings: None,
-                key_refresh: Default::default(),
-                send_latency: Duration::from_millis(20),
-                recv_latency: Duration::from_millis(20),
-                bandwidth: Default::default(),
-                statistics_interval: Duration::from_secs(1),
-                recv_buffer_size: options::PacketCount(8192),
-                send_buffer_size: options::PacketCount(8192),
-                max_packet_size: options::PacketSize(1500),
-                max_flow_size: options::PacketCount(8192),
-                peer_idle_timeout: Duration::from_secs(5),
-            },
+            test_settings(),
             sid,
             random(),
         )
     }
+
+    fn test_settings() -> ConnInitSettings {
+        ConnInitSettings {
+            local_sockid: TEST_SOCKID,
+            key_settings: None,
+            key_refresh: Default::default(),
+            send_latency: Duration::from_millis(20),
+            recv_latency: Duration::from_millis(20),
+            bandwidth: Default::default(),
+            statistics_interval: Duration::from_secs(1),
+            recv_buffer_size: options::PacketCount(8192),
+            send_buffer_size: options::PacketCount(8192),
+            send_buffer_bytes: None,
+            send_buffer_time: None,
+            send_buffer_policy: options::SendBufferPolicy::DropOldest,
+            max_packet_size: options::PacketSize(1500),
+            max_flow_size: options::PacketCount(8192),
+            peer_idle_timeout: Duration::from_secs(5),
+            ack2_mode: options::Ack2Mode::EveryFullAck,
+            lite_ack_rtt_sampling: false,
+            max_burst: options::PacketCount(0),
+            duplicate_interval: None,
+            fast_retransmit: None,
+            sequence_restart_window: options::PacketCount(0),
+            skip_gaps: false,
+            immediate_nak: false,
+            light_ack_interval: PacketCount(64),
+            max_delivery_rate: None,
+            too_late_packet_drop: true,
+            nak_report: true,
+            half_close: false,
+            limit_payload_size: false,
+            keepalive_interval: Duration::from_secs(1),
+            adaptive_keepalive: false,
+            message_tags: false,
+            ecn: false,
+            message_api: true,
+            peer_address_policy: options::PeerAddressPolicy::Rebind,
+            encrypt_control: false,
+            min_latency: Duration::ZERO,
+            max_latency: Duration::MAX,
+            latency_policy: options::LatencyPolicy::Clamp,
+            min_version: options::SrtVersion::new(1, 0, 0),
+            init_seq_num: None,
+            socket_start_time: None,
+        }
+    }
 }
@@ -2,9 +2,10 @@ use std::{
     collections::hash_map::DefaultHasher,
     hash::{Hash, Hasher},
     net::SocketAddr,
-    time::{SystemTime, UNIX_EPOCH},
 };
 
+use crate::protocol::time::{SystemTime, UNIX_EPOCH};
+
 pub fn gen_cookie(saddr: &SocketAddr) -> i32 {
     let time_mins = SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -0,0 +1,176 @@
+use std::{fmt, mem, net::SocketAddr, sync::Arc};
+
+use crate::{packet::*, protocol::time::Instant};
+
+/// Whether a handshake was sent to or received from the peer
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum HandshakeDirection {
+    Sent,
+    Received,
+}
+
+/// A handshake sent or received while connecting, as reported to a [`HandshakeHook`]. Going by
+/// these, a connection that times out shows which phase it got stuck in, e.g. inductions that are
+/// never answered, or a conclusion a firewall drops, without a packet capture.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct HandshakeEvent {
+    pub direction: HandshakeDirection,
+    pub time: Instant,
+    /// Where the handshake was sent to or came from
+    pub remote: SocketAddr,
+    /// The phase, i.e. induction, conclusion, waveahand, agreement, or a rejection and its reason
+    pub shake_type: ShakeType,
+    /// How many handshakes of this phase went this direction so far, this one included, so
+    /// retries count up from 1
+    pub attempt: u32,
+    pub syn_cookie: i32,
+    /// The socket id of the side that sent the handshake
+    pub socket_id: SocketId,
+}
+
+pub type HandshakeHook = Arc<dyn Fn(&HandshakeEvent) + Send + Sync>;
+
+/// Numbers the handshakes of a connection attempt and reports them to a [`HandshakeHook`], if
+/// there is one
+#[derive(Default)]
+pub struct HandshakeTelemetry {
+    hook: Option<HandshakeHook>,
+    attempts: Vec<(HandshakeDirection, mem::Discriminant<ShakeType>, u32)>,
+}
+
+impl HandshakeTelemetry {
+    pub fn new(hook: Option<HandshakeHook>) -> Self {
+        Self {
+            hook,
+            attempts: Vec::new(),
+        }
+    }
+
+    pub fn sent(&mut self, now: Instant, packet: &Packet, to: SocketAddr) {
+        self.report(HandshakeDirection::Sent, now, packet, to);
+    }
+
+    pub fn received(&mut self, now: Instant, packet: &Packet, from: SocketAddr) {
+        self.report(HandshakeDirection::Received, now, packet, from);
+    }
+
+    fn report(
+        &mut self,
+        direction: HandshakeDirection,
+        time: Instant,
+        packet: &Packet,
+        remote: SocketAddr,
+    ) {
+        let hook = match &self.hook {
+            Some(hook) => hook,
+            None => return,
+        };
+        let handshake = match packet.control().map(|c| &c.control_type) {
+            Some(ControlTypes::Handshake(handshake)) => handshake,
+            _ => return,
+        };
+        let phase = mem::discriminant(&handshake.shake_type);
+        let attempt = match self
+            .attempts
+            .iter_mut()
+            .find(|(d, p, _)| *d == direction && *p == phase)
+        {
+            Some((_, _, count)) => {
+                *count += 1;
+                *count
+            }
+            None => {
+                self.attempts.push((direction, phase, 1));
+                1
+            }
+        };
+        hook(&HandshakeEvent {
+            direction,
+            time,
+            remote,
+            shake_type: handshake.shake_type,
+            attempt,
+            syn_cookie: handshake.syn_cookie,
+            socket_id: handshake.socket_id,
+        });
+    }
+}
+
+impl fmt::Debug for HandshakeTelemetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HandshakeTelemetry")
+            .field("hook", &self.hook.is_some())
+            .field("attempts", &self.attempts)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use crate::options::{PacketCount, PacketSize};
+
+    use super::*;
+
+    fn handshake(shake_type: ShakeType) -> Packet {
+        Packet::Control(ControlPacket {
+            timestamp: TimeStamp::from_micros(0),
+            dest_sockid: SocketId(0),
+            control_type: ControlTypes::Handshake(HandshakeControlInfo {
+                init_seq_num: SeqNumber(0),
+                max_packet_size: PacketSize(1500),
+                max_flow_size: PacketCount(8192),
+                shake_type,
+                socket_id: SocketId(7),
+                syn_cookie: 1234,
+                peer_addr: [127, 0, 0, 1].into(),
+                info: HandshakeVsInfo::V4(SocketType::Datagram),
+            }),
+        })
+    }
+
+    #[test]
+    fn attempts() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut telemetry = HandshakeTelemetry::new(Some(Arc::new({
+            let events = events.clone();
+            move |event: &HandshakeEvent| events.lock().unwrap().push(*event)
+        })));
+        let now = Instant::now();
+        let remote = ([127, 0, 0, 1], 2000).into();
+
+        telemetry.sent(now, &handshake(ShakeType::Induction), remote);
+        telemetry.sent(now, &handshake(ShakeType::Induction), remote);
+        telemetry.received(now, &handshake(ShakeType::Induction), remote);
+        telemetry.sent(now, &handshake(ShakeType::Conclusion), remote);
+        // not a handshake
+        telemetry.received(
+            now,
+            &Packet::Control(ControlPacket {
+                timestamp: TimeStamp::from_micros(0),
+                dest_sockid: SocketId(0),
+                control_type: ControlTypes::KeepAlive,
+            }),
+            remote,
+        );
+
+        let events = events.lock().unwrap();
+        let summary: Vec<_> = events
+            .iter()
+            .map(|e| (e.direction, e.shake_type, e.attempt))
+            .collect();
+        use HandshakeDirection::*;
+        assert_eq!(
+            summary,
+            [
+                (Sent, ShakeType::Induction, 1),
+                (Sent, ShakeType::Induction, 2),
+                (Received, ShakeType::Induction, 1),
+                (Sent, ShakeType::Conclusion, 1),
+            ]
+        );
+        assert_eq!(events[0].syn_cookie, 1234);
+        assert_eq!(events[0].socket_id, SocketId(7));
+    }
+}
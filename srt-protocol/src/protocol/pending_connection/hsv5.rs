@@ -3,12 +3,14 @@
 use std::{
     cmp::{max, min},
     net::SocketAddr,
-    time::Instant,
+    time::Duration,
 };
 
-use crate::{connection::ConnectionSettings, options::*, packet::*, settings::*};
+use crate::{
+    connection::ConnectionSettings, options::*, packet::*, protocol::time::Instant, settings::*,
+};
 
-use super::{ConnectError, ConnectionReject};
+use super::{ConnectError, ConnectionReject, SettingsMismatch};
 
 #[allow(clippy::large_enum_variant)]
 pub enum GenHsv5Result {
@@ -40,7 +42,7 @@ pub fn gen_hsv5_response(
         induction_time,
         with_hsv5.clone(),
         incoming.clone(),
-        None,
+        ConnectionSettingsOverride::default(),
     )
 }
 
@@ -51,12 +53,10 @@ pub fn gen_access_control_response(
     induction_time: Instant,
     with_hsv5: HandshakeControlInfo,
     incoming: HsV5Info,
-    key_settings: Option<KeySettings>,
+    settings_override: ConnectionSettingsOverride,
 ) -> GenHsv5Result {
     // apply parameters generated by acceptor
-    if let Some(ks) = key_settings {
-        settings.key_settings = Some(ks);
-    }
+    settings_override.apply(settings);
 
     let hs = match incoming.ext_hs {
         Some(SrtControlPacket::HandshakeRequest(hs)) => hs,
@@ -64,12 +64,52 @@ pub fn gen_access_control_response(
         None => return GenHsv5Result::NotHandled(ConnectError::ExpectedExtFlags),
     };
 
+    if hs.version < settings.min_version {
+        return GenHsv5Result::Reject(ConnectionReject::Mismatched(SettingsMismatch::Version {
+            min: settings.min_version,
+            remote: hs.version,
+        }));
+    }
+
+    let message_api = !hs.flags.contains(SrtShakeFlags::STREAM);
+    if message_api != settings.message_api {
+        return GenHsv5Result::Reject(ConnectionReject::Mismatched(SettingsMismatch::MessageApi {
+            local: settings.message_api,
+            remote: message_api,
+        }));
+    }
+
+    if incoming.message_tags != settings.message_tags {
+        return GenHsv5Result::Reject(ConnectionReject::Mismatched(
+            SettingsMismatch::MessageTags {
+                local: settings.message_tags,
+                remote: incoming.message_tags,
+            },
+        ));
+    }
+
+    let (send_latency, recv_latency) = match (
+        negotiate_latency(settings, settings.send_latency, hs.recv_latency),
+        negotiate_latency(settings, settings.recv_latency, hs.send_latency),
+    ) {
+        (Ok(send_latency), Ok(recv_latency)) => (send_latency, recv_latency),
+        (Err(mismatch), _) | (_, Err(mismatch)) => {
+            return GenHsv5Result::Reject(ConnectionReject::Mismatched(mismatch))
+        }
+    };
+
     // crypto
+    let encryption_mismatch = || {
+        GenHsv5Result::Reject(ConnectionReject::Mismatched(SettingsMismatch::Encryption {
+            local: settings.key_settings.as_ref().map(|k| k.key_size),
+            remote: incoming.ext_km.as_ref().map(|_| incoming.key_size),
+        }))
+    };
     let cipher = match (&settings.key_settings, &incoming.ext_km) {
         // ok, both sides have crypto
         (Some(key_settings), Some(SrtControlPacket::KeyRefreshRequest(km))) => {
             if key_settings.key_size != incoming.key_size {
-                unimplemented!("Key size mismatch");
+                return encryption_mismatch();
             }
 
             let cipher = match CipherSettings::new(key_settings, &settings.key_refresh, km) {
@@ -85,13 +125,12 @@ pub fn gen_access_control_response(
         // ok, neither have crypto
         (None, None) => None,
         // bad cases
-        (Some(_), Some(_)) => unimplemented!("Expected kmreq"),
-        (Some(_), None) => {
+        (Some(_), Some(_)) => {
             return GenHsv5Result::Reject(ConnectionReject::Rejecting(
-                CoreRejectReason::Unsecure.into(),
+                CoreRejectReason::BadSecret.into(),
             ))
         }
-        (None, Some(_)) => unimplemented!("expected no secrets"),
+        (Some(_), None) | (None, Some(_)) => return encryption_mismatch(),
     };
 
     let outgoing_ext_km = cipher
@@ -104,6 +143,24 @@ pub fn gen_access_control_response(
         None
     };
 
+    // only the peers of this implementation offer it
+    let encrypt_control =
+        settings.encrypt_control && incoming.encrypted_control && cipher.is_some();
+    let ecn = settings.ecn && incoming.congestion_experienced;
+
+    // a member of a libsrt bonding group is accepted as a connection of its own, the peer expects
+    // the id of the group on this side in return, the same for all the members of its group, so
+    // its own id stands in for one
+    let ext_group = match incoming.ext_group {
+        Some(SrtControlPacket::Group { id, ty, flags, .. }) => Some(SrtControlPacket::Group {
+            id,
+            ty,
+            flags,
+            weight: 0,
+        }),
+        _ => None,
+    };
+
     let rtt = now - induction_time;
 
     GenHsv5Result::Accept(
@@ -112,38 +169,87 @@ pub fn gen_access_control_response(
                 .as_ref()
                 .map(|c| c.key_settings.key_size)
                 .unwrap_or(KeySize::Unspecified),
+            // the negotiated latencies, like libsrt, so the peer picks up any clamping
             ext_hs: Some(SrtControlPacket::HandshakeResponse(SrtHandshake {
                 version: SrtVersion::CURRENT,
-                flags: SrtShakeFlags::SUPPORTED,
-                send_latency: settings.send_latency,
-                recv_latency: settings.recv_latency,
+                flags: settings.handshake_flags(),
+                send_latency,
+                recv_latency,
             })),
             ext_km: outgoing_ext_km.map(SrtControlPacket::KeyRefreshResponse),
-            ext_group: None,
+            ext_group,
             sid,
+            encrypted_control: encrypt_control,
+            message_tags: settings.message_tags,
+            congestion_experienced: ecn,
         }),
         ConnectionSettings {
             remote: from,
             rtt,
-            socket_start_time: now.checked_sub(rtt / 2).unwrap(), // initiate happened 0.5RTT ago
+            // initiate happened 0.5RTT ago
+            socket_start_time: settings
+                .socket_start_time
+                .unwrap_or_else(|| now.checked_sub(rtt / 2).unwrap()),
             remote_sockid: with_hsv5.socket_id,
             init_seq_num: with_hsv5.init_seq_num,
             cipher,
             stream_id: incoming.sid,
             max_flow_size: max(settings.max_flow_size, with_hsv5.max_flow_size),
             max_packet_size: min(settings.max_packet_size, with_hsv5.max_packet_size),
-            send_tsbpd_latency: max(settings.send_latency, hs.recv_latency),
-            recv_tsbpd_latency: max(settings.recv_latency, hs.send_latency),
+            send_tsbpd_latency: send_latency,
+            recv_tsbpd_latency: recv_latency,
             bandwidth: settings.bandwidth.clone(),
             local_sockid: settings.local_sockid,
             recv_buffer_size: settings.recv_buffer_size,
             send_buffer_size: settings.send_buffer_size,
+            send_buffer_bytes: settings.send_buffer_bytes,
+            send_buffer_time: settings.send_buffer_time,
+            send_buffer_policy: settings.send_buffer_policy,
             statistics_interval: settings.statistics_interval,
             peer_idle_timeout: settings.peer_idle_timeout,
+            ack2_mode: settings.ack2_mode,
+            lite_ack_rtt_sampling: settings.lite_ack_rtt_sampling,
+            max_burst: settings.max_burst,
+            duplicate_interval: settings.duplicate_interval,
+            fast_retransmit: settings.fast_retransmit,
+            sequence_restart_window: settings.sequence_restart_window,
+            skip_gaps: settings.skip_gaps,
+            immediate_nak: settings.immediate_nak,
+            light_ack_interval: settings.light_ack_interval,
+            max_delivery_rate: settings.max_delivery_rate,
+            half_close: settings.half_close,
+            limit_payload_size: settings.limit_payload_size,
+            keepalive_interval: settings.keepalive_interval,
+            adaptive_keepalive: settings.adaptive_keepalive,
+            message_tags: settings.message_tags,
+            ecn,
+            peer_address_policy: settings.peer_address_policy,
+            encrypt_control,
+            peer_version: hs.version,
+            peer_flags: hs.flags,
+            local_flags: settings.handshake_flags(),
         },
     )
 }
 
+// the latency of one direction, the larger of the two proposals within the latency policy, or
+// why the connection is to be rejected
+fn negotiate_latency(
+    settings: &ConnInitSettings,
+    own: Duration,
+    peer: Duration,
+) -> Result<Duration, SettingsMismatch> {
+    let latency = max(own, peer);
+    if latency > settings.max_latency && settings.latency_policy == LatencyPolicy::Reject {
+        return Err(SettingsMismatch::Latency {
+            local: own,
+            remote: peer,
+            max: settings.max_latency,
+        });
+    }
+    Ok(latency.clamp(settings.min_latency, settings.max_latency))
+}
+
 #[derive(Debug, Clone)] // TOOD: make not clone
 pub struct StartedInitiator {
     cipher: Option<CipherSettings>,
@@ -183,13 +289,16 @@ pub fn start_hsv5_initiation(
             key_size: self_crypto_size,
             ext_hs: Some(SrtControlPacket::HandshakeRequest(SrtHandshake {
                 version: SrtVersion::CURRENT,
-                flags: SrtShakeFlags::SUPPORTED,
+                flags: settings.handshake_flags(),
                 send_latency: settings.send_latency,
                 recv_latency: settings.recv_latency,
             })),
             ext_km,
             ext_group: None,
             sid: streamid.clone(),
+            encrypted_control: settings.encrypt_control && cipher.is_some(),
+            message_tags: settings.message_tags,
+            congestion_experienced: settings.ecn,
         }),
         StartedInitiator {
             cipher,
@@ -221,25 +330,64 @@ impl StartedInitiator {
 
         // todo: validate km!
 
+        // a responder that doesn't know of tags leaves them out
+        if incoming.message_tags != self.settings.message_tags {
+            return Err(ConnectError::Mismatched(SettingsMismatch::MessageTags {
+                local: self.settings.message_tags,
+                remote: incoming.message_tags,
+            }));
+        }
+
+        let local_flags = self.settings.handshake_flags();
+        let encrypt_control =
+            self.settings.encrypt_control && self.cipher.is_some() && incoming.encrypted_control;
         // validate response
         Ok(ConnectionSettings {
             remote: from,
             rtt: now - self.initiate_time,
-            socket_start_time: self.initiate_time,
+            socket_start_time: self
+                .settings
+                .socket_start_time
+                .unwrap_or(self.initiate_time),
             init_seq_num: response.init_seq_num,
             remote_sockid: response.socket_id,
             cipher: self.cipher,
             stream_id: self.streamid,
             max_flow_size: max(self.settings.max_flow_size, response.max_flow_size),
             max_packet_size: min(self.settings.max_packet_size, response.max_packet_size),
-            send_tsbpd_latency: max(self.settings.send_latency, hs.recv_latency),
-            recv_tsbpd_latency: max(self.settings.recv_latency, hs.send_latency),
+            // the responder has the final say, it may have clamped our proposal
+            send_tsbpd_latency: hs.recv_latency,
+            recv_tsbpd_latency: hs.send_latency,
             bandwidth: self.settings.bandwidth,
             local_sockid: self.settings.local_sockid,
             recv_buffer_size: self.settings.recv_buffer_size,
             send_buffer_size: self.settings.send_buffer_size,
+            send_buffer_bytes: self.settings.send_buffer_bytes,
+            send_buffer_time: self.settings.send_buffer_time,
+            send_buffer_policy: self.settings.send_buffer_policy,
             statistics_interval: self.settings.statistics_interval,
             peer_idle_timeout: self.settings.peer_idle_timeout,
+            ack2_mode: self.settings.ack2_mode,
+            lite_ack_rtt_sampling: self.settings.lite_ack_rtt_sampling,
+            max_burst: self.settings.max_burst,
+            duplicate_interval: self.settings.duplicate_interval,
+            fast_retransmit: self.settings.fast_retransmit,
+            sequence_restart_window: self.settings.sequence_restart_window,
+            skip_gaps: self.settings.skip_gaps,
+            immediate_nak: self.settings.immediate_nak,
+            light_ack_interval: self.settings.light_ack_interval,
+            max_delivery_rate: self.settings.max_delivery_rate,
+            half_close: self.settings.half_close,
+            limit_payload_size: self.settings.limit_payload_size,
+            keepalive_interval: self.settings.keepalive_interval,
+            adaptive_keepalive: self.settings.adaptive_keepalive,
+            message_tags: self.settings.message_tags,
+            ecn: self.settings.ecn && incoming.congestion_experienced,
+            peer_address_policy: self.settings.peer_address_policy,
+            encrypt_control,
+            peer_version: hs.version,
+            peer_flags: hs.flags,
+            local_flags,
         })
     }
 }
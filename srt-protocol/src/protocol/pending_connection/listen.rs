@@ -1,11 +1,11 @@
-use std::{convert::TryInto, net::SocketAddr, time::Instant};
+use std::{convert::TryInto, net::SocketAddr};
 
-use crate::{packet::*, protocol::handshake::Handshake, settings::*};
+use crate::{packet::*, protocol::handshake::Handshake, protocol::time::Instant, settings::*};
 
 use super::{
     cookie::gen_cookie, hsv5::gen_access_control_response, hsv5::GenHsv5Result,
     AccessControlRequest, AccessControlResponse, ConnectError, Connection, ConnectionReject,
-    ConnectionResult,
+    ConnectionResult, HandshakeInfo,
 };
 
 use ConnectionResult::*;
@@ -75,9 +75,14 @@ impl Listen {
             AccessControlRequested(state, timestamp, shake, info) => {
                 use AccessControlResponse::*;
                 match response {
-                    Accepted(key_settings) => {
-                        self.accept_connection(now, &state, timestamp, shake, info, key_settings)
-                    }
+                    Accepted(settings_override) => self.accept_connection(
+                        now,
+                        &state,
+                        timestamp,
+                        shake,
+                        info,
+                        settings_override,
+                    ),
                     Rejected(rr) => self.make_rejection(
                         &shake,
                         state.from,
@@ -211,8 +216,15 @@ impl Listen {
                 if self.enable_access_control {
                     self.request_access(from, local_socket_id, timestamp, state, shake, incoming)
                 } else {
-                    let key_settings = self.settings().key_settings.clone();
-                    self.accept_connection(now, &state, timestamp, shake, incoming, key_settings)
+                    let settings_override = ConnectionSettingsOverride::default();
+                    self.accept_connection(
+                        now,
+                        &state,
+                        timestamp,
+                        shake,
+                        incoming,
+                        settings_override,
+                    )
                 }
             }
             (ShakeType::Conclusion, VERSION_5, syn_cookie) => NotHandled(
@@ -237,6 +249,15 @@ impl Listen {
         // TODO: handle StreamId parsing error
         let stream_id = incoming.sid.clone().and_then(|s| s.try_into().ok());
         let remote_socket_id = shake.socket_id;
+        let handshake = HandshakeInfo {
+            max_packet_size: shake.max_packet_size,
+            max_flow_size: shake.max_flow_size,
+            key_size: incoming.key_size,
+            srt: match &incoming.ext_hs {
+                Some(SrtControlPacket::HandshakeRequest(hs)) => Some(*hs),
+                _ => None,
+            },
+        };
 
         self.state = AccessControlRequested(state, timestamp, shake, incoming);
 
@@ -245,6 +266,7 @@ impl Listen {
             remote,
             remote_socket_id,
             stream_id,
+            handshake,
         })
     }
 
@@ -255,7 +277,7 @@ impl Listen {
         timestamp: TimeStamp,
         shake: HandshakeControlInfo,
         info: HsV5Info,
-        key_settings: Option<KeySettings>,
+        settings_override: ConnectionSettingsOverride,
     ) -> ConnectionResult {
         let response = gen_access_control_response(
             now,
@@ -264,7 +286,7 @@ impl Listen {
             state.induction_time,
             shake.clone(),
             info,
-            key_settings,
+            settings_override,
         );
         let (hsv5, settings) = match response {
             GenHsv5Result::Accept(h, c) => (h, c),
@@ -280,6 +302,8 @@ impl Listen {
             control_type: ControlTypes::Handshake(HandshakeControlInfo {
                 syn_cookie: state.cookie,
                 socket_id: self.init_settings.local_sockid,
+                // the smaller payload size of the two, the caller takes it on
+                max_packet_size: settings.max_packet_size,
                 info: hsv5,
                 shake_type: ShakeType::Conclusion,
                 ..shake // TODO: this will pass peer wrong
@@ -333,7 +357,7 @@ mod test {
     use bytes::Bytes;
     use rand::random;
 
-    use crate::options::*;
+    use crate::{options::*, protocol::pending_connection::SettingsMismatch};
 
     use super::*;
 
@@ -377,6 +401,9 @@ mod test {
                 })),
                 ext_km: None,
                 ext_group: None,
+                encrypted_control: false,
+                message_tags: false,
+                congestion_experienced: false,
                 sid: None,
             }),
         }
@@ -423,6 +450,48 @@ mod test {
         );
     }
 
+    #[test]
+    fn group_member() {
+        let mut l = test_listen();
+        l.handle_packet(
+            Instant::now(),
+            Ok((build_hs_pack(test_induction()), conn_addr())),
+        );
+
+        // a member of a bonding group gets a group id back, which libsrt insists on
+        let mut conclusion = test_conclusion();
+        if let HandshakeVsInfo::V5(info) = &mut conclusion.info {
+            info.ext_group = Some(SrtControlPacket::Group {
+                id: SocketId(0x4000_0001),
+                ty: GroupType::Broadcast,
+                flags: GroupFlags::empty(),
+                weight: 5,
+            });
+        }
+        let resp = l.handle_packet(Instant::now(), Ok((build_hs_pack(conclusion), conn_addr())));
+        assert_matches!(
+            resp,
+            Connected(
+                Some(_),
+                Connection {
+                    handshake: Handshake::Listener(ControlTypes::Handshake(HandshakeControlInfo {
+                        info: HandshakeVsInfo::V5(HsV5Info {
+                            ext_group: Some(SrtControlPacket::Group {
+                                id: SocketId(0x4000_0001),
+                                ty: GroupType::Broadcast,
+                                weight: 0,
+                                ..
+                            }),
+                            ..
+                        }),
+                        ..
+                    })),
+                    ..
+                },
+            )
+        );
+    }
+
     #[test]
     fn send_data_packet() {
         let mut l = test_listen();
@@ -569,4 +638,270 @@ mod test {
             )
         );
     }
+
+    #[test]
+    fn accept_with_settings_override() {
+        let mut l = Listen::new(ConnInitSettings::default(), true);
+
+        let resp = l.handle_packet(
+            Instant::now(),
+            Ok((build_hs_pack(test_induction()), conn_addr())),
+        );
+        assert_matches!(resp, SendPacket(_));
+
+        let resp = l.handle_packet(
+            Instant::now(),
+            Ok((build_hs_pack(test_conclusion()), conn_addr())),
+        );
+        assert_matches!(resp, RequestAccess(_));
+
+        let resp = l.handle_access_control_response(
+            Instant::now(),
+            AccessControlResponse::Accepted(ConnectionSettingsOverride {
+                latency: Some(Duration::from_secs(3)),
+                recv_buffer_size: Some(PacketCount(100)),
+                bandwidth: Some(LiveBandwidthMode::Max(DataRate(1_000_000))),
+                ..Default::default()
+            }),
+        );
+        let settings = match resp {
+            Connected(_, connection) => connection.settings,
+            resp => panic!("expected connection, got {resp:?}"),
+        };
+        assert_eq!(settings.recv_tsbpd_latency, Duration::from_secs(3));
+        assert_eq!(settings.send_tsbpd_latency, Duration::from_secs(3));
+        assert_eq!(settings.recv_buffer_size, PacketCount(100));
+        assert_eq!(
+            settings.bandwidth,
+            LiveBandwidthMode::Max(DataRate(1_000_000))
+        );
+        assert_eq!(
+            settings.send_buffer_size,
+            ConnInitSettings::default().send_buffer_size
+        );
+    }
+
+    #[test]
+    fn latency_policy() {
+        // the caller proposes to send at 1s and receive at 2s
+        let settings = ConnInitSettings {
+            min_latency: Duration::from_millis(1200),
+            max_latency: Duration::from_millis(1500),
+            ..ConnInitSettings::default()
+        };
+        let mut l = Listen::new(settings.clone(), false);
+        l.handle_packet(
+            Instant::now(),
+            Ok((build_hs_pack(test_induction()), conn_addr())),
+        );
+        let resp = l.handle_packet(
+            Instant::now(),
+            Ok((build_hs_pack(test_conclusion()), conn_addr())),
+        );
+        let connection = match resp {
+            Connected(_, connection) => connection,
+            resp => panic!("expected connection, got {resp:?}"),
+        };
+        assert_eq!(
+            connection.settings.send_tsbpd_latency,
+            Duration::from_millis(1500)
+        );
+        assert_eq!(
+            connection.settings.recv_tsbpd_latency,
+            Duration::from_millis(1200)
+        );
+        // the caller is told what was agreed on
+        assert_matches!(
+            connection.handshake,
+            Handshake::Listener(ControlTypes::Handshake(HandshakeControlInfo {
+                info: HandshakeVsInfo::V5(HsV5Info {
+                    ext_hs: Some(SrtControlPacket::HandshakeResponse(SrtHandshake {
+                        send_latency,
+                        recv_latency,
+                        ..
+                    })),
+                    ..
+                }),
+                ..
+            })) if send_latency == Duration::from_millis(1500)
+                && recv_latency == Duration::from_millis(1200)
+        );
+
+        let mut l = Listen::new(
+            ConnInitSettings {
+                latency_policy: LatencyPolicy::Reject,
+                ..settings
+            },
+            false,
+        );
+        l.handle_packet(
+            Instant::now(),
+            Ok((build_hs_pack(test_induction()), conn_addr())),
+        );
+        let resp = l.handle_packet(
+            Instant::now(),
+            Ok((build_hs_pack(test_conclusion()), conn_addr())),
+        );
+        // the caller's 2s for this side to send at is too much
+        assert_matches!(
+            resp,
+            Reject(
+                Some((Packet::Control(ControlPacket {
+                    control_type: ControlTypes::Handshake(HandshakeControlInfo {
+                        shake_type: ShakeType::Rejection(reason),
+                        ..
+                    }),
+                    ..
+                }), _)),
+                ConnectionReject::Mismatched(SettingsMismatch::Latency { local, remote, max }),
+            ) if reason == RejectReason::Server(ServerRejectReason::Unacceptable)
+                && local == ConnInitSettings::default().send_latency
+                && remote == Duration::from_secs(2)
+                && max == Duration::from_millis(1500)
+        );
+    }
+
+    #[test]
+    fn min_version() {
+        let mut l = Listen::new(
+            ConnInitSettings {
+                min_version: SrtVersion::new(1, 6, 0),
+                ..ConnInitSettings::default()
+            },
+            false,
+        );
+        l.handle_packet(
+            Instant::now(),
+            Ok((build_hs_pack(test_induction()), conn_addr())),
+        );
+        let resp = l.handle_packet(
+            Instant::now(),
+            Ok((build_hs_pack(test_conclusion()), conn_addr())),
+        );
+        assert_matches!(
+            resp,
+            Reject(
+                _,
+                ConnectionReject::Mismatched(SettingsMismatch::Version { min, remote }),
+            ) if min == SrtVersion::new(1, 6, 0) && remote == SrtVersion::CURRENT
+        );
+    }
+
+    #[test]
+    fn handshake_flags() {
+        let settings = ConnInitSettings {
+            too_late_packet_drop: false,
+            ..ConnInitSettings::default()
+        };
+        let mut l = Listen::new(settings, false);
+        l.handle_packet(
+            Instant::now(),
+            Ok((build_hs_pack(test_induction()), conn_addr())),
+        );
+        let resp = l.handle_packet(
+            Instant::now(),
+            Ok((build_hs_pack(test_conclusion()), conn_addr())),
+        );
+        let connection = match resp {
+            Connected(_, connection) => connection,
+            resp => panic!("expected connection, got {resp:?}"),
+        };
+        let flags = SrtShakeFlags::SUPPORTED - SrtShakeFlags::TLPKTDROP;
+        assert_eq!(connection.settings.local_flags, flags);
+        assert!(connection.settings.receives_with_tsbpd());
+        assert!(!connection.settings.receiver_drops_too_late());
+        // the caller still drops too late packets, so this side's sender does too
+        assert!(connection.settings.sender_drops_too_late());
+        assert_matches!(
+            connection.handshake,
+            Handshake::Listener(ControlTypes::Handshake(HandshakeControlInfo {
+                info: HandshakeVsInfo::V5(HsV5Info {
+                    ext_hs: Some(SrtControlPacket::HandshakeResponse(SrtHandshake {
+                        flags: sent,
+                        ..
+                    })),
+                    ..
+                }),
+                ..
+            })) if sent == flags
+        );
+
+        // a caller using the stream API
+        let mut conclusion = test_conclusion();
+        if let HandshakeVsInfo::V5(HsV5Info {
+            ext_hs: Some(SrtControlPacket::HandshakeRequest(hs)),
+            ..
+        }) = &mut conclusion.info
+        {
+            hs.flags |= SrtShakeFlags::STREAM;
+        }
+        let mut l = test_listen();
+        l.handle_packet(
+            Instant::now(),
+            Ok((build_hs_pack(test_induction()), conn_addr())),
+        );
+        let resp = l.handle_packet(
+            Instant::now(),
+            Ok((build_hs_pack(conclusion.clone()), conn_addr())),
+        );
+        assert_matches!(
+            resp,
+            Reject(
+                _,
+                ConnectionReject::Mismatched(SettingsMismatch::MessageApi {
+                    local: true,
+                    remote: false
+                }),
+            )
+        );
+
+        // is taken by a listener in stream mode too
+        let mut l = Listen::new(
+            ConnInitSettings {
+                message_api: false,
+                ..ConnInitSettings::default()
+            },
+            false,
+        );
+        l.handle_packet(
+            Instant::now(),
+            Ok((build_hs_pack(test_induction()), conn_addr())),
+        );
+        let resp = l.handle_packet(Instant::now(), Ok((build_hs_pack(conclusion), conn_addr())));
+        let connection = match resp {
+            Connected(_, connection) => connection,
+            resp => panic!("expected connection, got {resp:?}"),
+        };
+        assert!(connection.settings.stream_mode());
+        assert!(!connection.settings.receiver_drops_too_late());
+    }
+
+    #[test]
+    fn not_a_key_request() {
+        let mut l = Listen::new(
+            ConnInitSettings {
+                key_settings: Some(KeySettings {
+                    key_size: KeySize::AES128,
+                    key_source: KeySource::Passphrase("1234567890".into()),
+                }),
+                ..ConnInitSettings::default()
+            },
+            false,
+        );
+        let mut conclusion = test_conclusion();
+        if let HandshakeVsInfo::V5(info) = &mut conclusion.info {
+            info.key_size = KeySize::AES128;
+            info.ext_km = Some(SrtControlPacket::StreamId("key".into()));
+        }
+        l.handle_packet(
+            Instant::now(),
+            Ok((build_hs_pack(test_induction()), conn_addr())),
+        );
+        let resp = l.handle_packet(Instant::now(), Ok((build_hs_pack(conclusion), conn_addr())));
+        assert_matches!(
+            resp,
+            Reject(_, ConnectionReject::Rejecting(reason))
+                if reason == RejectReason::Core(CoreRejectReason::BadSecret)
+        );
+    }
 }
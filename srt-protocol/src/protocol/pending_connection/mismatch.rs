@@ -0,0 +1,92 @@
+use std::{fmt, time::Duration};
+
+use crate::{
+    options::{KeySize, SrtVersion},
+    packet::{CoreRejectReason, RejectReason, ServerRejectReason},
+};
+
+/// Why a caller was rejected for what it offered in its handshake, with the values of both sides,
+/// so whoever reads the error can tell which setting to change, and on which side
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SettingsMismatch {
+    /// One side encrypts and the other doesn't, or they use different key sizes. `None` for a
+    /// side without a passphrase.
+    Encryption {
+        local: Option<KeySize>,
+        remote: Option<KeySize>,
+    },
+    /// The latency the peer proposed for a direction is more than this side accepts, with
+    /// [`LatencyPolicy::Reject`](crate::options::LatencyPolicy::Reject)
+    Latency {
+        local: Duration,
+        remote: Duration,
+        max: Duration,
+    },
+    /// One side sends messages and the other a byte stream, see
+    /// [`message_api`](crate::options::Session::message_api)
+    MessageApi { local: bool, remote: bool },
+    /// One side tags its messages and the other doesn't, see
+    /// [`message_tags`](crate::options::Session::message_tags)
+    MessageTags { local: bool, remote: bool },
+    /// The peer's SRT version is older than the minimum set here
+    Version { min: SrtVersion, remote: SrtVersion },
+}
+
+impl SettingsMismatch {
+    /// The code the peer is sent in the rejection
+    pub fn reason(&self) -> RejectReason {
+        use SettingsMismatch::*;
+        match self {
+            Encryption { .. } => CoreRejectReason::Unsecure.into(),
+            Latency { .. } => ServerRejectReason::Unacceptable.into(),
+            MessageApi { .. } => CoreRejectReason::MessageApi.into(),
+            MessageTags { .. } => ServerRejectReason::Unacceptable.into(),
+            Version { .. } => CoreRejectReason::Version.into(),
+        }
+    }
+}
+
+impl fmt::Display for SettingsMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use SettingsMismatch::*;
+        let key = |key_size: &Option<KeySize>| match key_size {
+            None => "unencrypted".to_string(),
+            Some(KeySize::Unspecified) => "encrypted".to_string(),
+            Some(key_size) => format!("encrypted with AES-{}", key_size.as_usize() * 8),
+        };
+        match self {
+            Encryption { local, remote } => write!(
+                f,
+                "encryption mismatch, {} here and {} on the peer: set the same passphrase and key size on both sides",
+                key(local),
+                key(remote)
+            ),
+            Latency { local, remote, max } => write!(
+                f,
+                "latency mismatch, {local:?} here and {remote:?} on the peer, over the maximum of {max:?}: lower the latency of the peer or raise the maximum here"
+            ),
+            MessageApi { local, remote } => {
+                let api = |message_api: &bool| if *message_api { "messages" } else { "a byte stream" };
+                write!(
+                    f,
+                    "message API mismatch, {} here and {} on the peer: set the same message API on both sides",
+                    api(local),
+                    api(remote)
+                )
+            }
+            MessageTags { local, remote } => {
+                let tags = |message_tags: &bool| if *message_tags { "enabled" } else { "disabled" };
+                write!(
+                    f,
+                    "message tags mismatch, {} here and {} on the peer: set the same message tags on both sides",
+                    tags(local),
+                    tags(remote)
+                )
+            }
+            Version { min, remote } => write!(
+                f,
+                "version mismatch, the peer runs SRT {remote}, older than the minimum of {min}: upgrade the peer or lower the minimum version here"
+            ),
+        }
+    }
+}
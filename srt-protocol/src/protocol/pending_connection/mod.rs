@@ -1,13 +1,23 @@
 pub mod connect;
+mod events;
 mod hsv5;
 pub mod listen;
+mod mismatch;
 pub mod rendezvous;
 
 pub(crate) mod cookie;
 
 use std::{error::Error, fmt, io, net::SocketAddr};
 
-use crate::{connection::Connection, options::StreamId, packet::*, settings::KeySettings};
+pub use events::{HandshakeDirection, HandshakeEvent, HandshakeHook, HandshakeTelemetry};
+pub use mismatch::SettingsMismatch;
+
+use crate::{
+    connection::Connection,
+    options::{KeySize, PacketCount, PacketSize, StreamId},
+    packet::*,
+    settings::ConnectionSettingsOverride,
+};
 
 #[non_exhaustive]
 #[derive(Debug)]
@@ -30,6 +40,8 @@ pub enum ConnectError {
     ExpectedNoExtFlags,
     ExpectedAccessControlResponse,
     ParseFailed(PacketParseError),
+    /// The responder's answer doesn't go with the settings here
+    Mismatched(SettingsMismatch),
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -38,11 +50,24 @@ pub struct AccessControlRequest {
     pub remote: SocketAddr,
     pub remote_socket_id: SocketId,
     pub stream_id: Option<StreamId>,
+    pub handshake: HandshakeInfo,
+}
+
+/// What the caller proposed in its conclusion handshake, before it's negotiated with the settings
+/// of this side
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HandshakeInfo {
+    pub max_packet_size: PacketSize,
+    pub max_flow_size: PacketCount,
+    /// The key size the caller encrypts with, [`KeySize::Unspecified`] if it doesn't
+    pub key_size: KeySize,
+    /// The caller's HSREQ extension, with its SRT version, flags and latencies
+    pub srt: Option<SrtHandshake>,
 }
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum AccessControlResponse {
-    Accepted(Option<KeySettings>),
+    Accepted(ConnectionSettingsOverride),
     Rejected(RejectReason),
     Dropped,
 }
@@ -54,6 +79,9 @@ pub enum ConnectionReject {
 
     /// remote rejected local
     Rejected(RejectReason),
+
+    /// local rejected remote, as what it offered doesn't go with the settings here
+    Mismatched(SettingsMismatch),
 }
 
 #[derive(Debug)]
@@ -108,6 +136,7 @@ impl fmt::Display for ConnectError {
                 write!(f, "Initiator did not expect handshake flags, but got some")
             }
             ParseFailed(e) => write!(f, "Failed to parse packet: {e}"),
+            Mismatched(mismatch) => write!(f, "{mismatch}"),
             ExpectedAccessControlResponse => write!(
                 f,
                 "Expected an access control response but instead received a packet from the peer"
@@ -124,6 +153,7 @@ impl fmt::Display for ConnectionReject {
         match self {
             Rejecting(rr) => write!(f, "Local server rejected remote: {rr}"),
             Rejected(rr) => write!(f, "Remote rejected connection: {rr}"),
+            Mismatched(mismatch) => write!(f, "Local server rejected remote: {mismatch}"),
         }
     }
 }
@@ -132,6 +162,7 @@ impl ConnectionReject {
     fn reason(&self) -> RejectReason {
         match self {
             ConnectionReject::Rejecting(r) | ConnectionReject::Rejected(r) => *r,
+            ConnectionReject::Mismatched(mismatch) => mismatch.reason(),
         }
     }
 }
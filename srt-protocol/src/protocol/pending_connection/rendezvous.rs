@@ -1,4 +1,4 @@
-use std::{cmp::Ordering, io::ErrorKind, net::SocketAddr, time::Instant};
+use std::{cmp::Ordering, io::ErrorKind, net::SocketAddr};
 
 use log::{debug, info};
 
@@ -11,6 +11,7 @@ use crate::{
     connection::{Connection, ConnectionSettings},
     packet::*,
     protocol::handshake::Handshake,
+    protocol::time::Instant,
     settings::*,
 };
 
@@ -317,6 +318,9 @@ impl Rendezvous {
                     let settings =
                         match initiator.finish_hsv5_initiation(info, self.remote_public, now) {
                             Ok(s) => s,
+                            Err(Mismatched(mismatch)) => {
+                                return Reject(None, ConnectionReject::Mismatched(mismatch))
+                            }
                             Err(r) => return NotHandled(r),
                         };
 
@@ -383,6 +387,9 @@ impl Rendezvous {
                     let settings =
                         match initiator.finish_hsv5_initiation(info, self.remote_public, now) {
                             Ok(s) => s,
+                            Err(Mismatched(mismatch)) => {
+                                return Reject(None, ConnectionReject::Mismatched(mismatch))
+                            }
                             Err(r) => return NotHandled(r),
                         };
 
@@ -432,6 +439,9 @@ impl Rendezvous {
                     let connection =
                         match initiator.finish_hsv5_initiation(info, self.remote_public, now) {
                             Ok(c) => c,
+                            Err(Mismatched(mismatch)) => {
+                                return Reject(None, ConnectionReject::Mismatched(mismatch))
+                            }
                             Err(e) => return NotHandled(e),
                         };
 
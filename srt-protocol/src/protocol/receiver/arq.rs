@@ -1,7 +1,4 @@
-use std::{
-    ops::Range,
-    time::{Duration, Instant},
-};
+use std::{ops::Range, time::Duration};
 
 use array_init::from_iter;
 use arraydeque::{behavior::Wrapping, ArrayDeque};
@@ -13,12 +10,14 @@ use crate::{
     protocol::{
         receiver::{
             buffer::{MessageError, ReceiveBuffer},
-            history::AckHistoryWindow,
-            time::ClockAdjustment,
+            history::{AckHistoryWindow, SequenceClass, SequenceHistoryWindow},
+            time::{ClockAdjustment, InterarrivalJitter},
             DataPacketAction, DataPacketError,
         },
+        time::Instant,
         time::Rtt,
     },
+    statistics::{DurationHistogram, MemoryUsage},
 };
 
 #[derive(Debug)]
@@ -152,6 +151,7 @@ impl LinkCapacityEstimate {
 pub struct AutomaticRepeatRequestAlgorithm {
     link_capacity_estimate: LinkCapacityEstimate,
     arrival_speed: ArrivalSpeed,
+    interarrival_jitter: InterarrivalJitter,
 
     /// https://tools.ietf.org/html/draft-gg-udt-03#page-12
     /// Receiver's Loss List: It is a list of tuples whose values include:
@@ -167,7 +167,17 @@ pub struct AutomaticRepeatRequestAlgorithm {
     /// one if no more free space in the array.
     ack_history_window: AckHistoryWindow,
 
+    /// Tells apart sequence numbers that belong to the current stream from those of a sender that
+    /// restarted with a new initial sequence number.
+    sequence_window: SequenceHistoryWindow,
+
     rtt: Rtt,
+
+    /// The time from the first NAK for a lost packet to its retransmission
+    retransmit_delay: DurationHistogram,
+
+    /// Whether the sender marks retransmitted packets, i.e. it negotiated REXMITFLG
+    retransmit_flag: bool,
 }
 
 impl AutomaticRepeatRequestAlgorithm {
@@ -176,21 +186,43 @@ impl AutomaticRepeatRequestAlgorithm {
         tsbpd_latency: Duration,
         init_seq_num: SeqNumber,
         buffer_size_packets: PacketCount,
+        sequence_restart_window: PacketCount,
+        skip_gaps: bool,
+        too_late_packet_drop: bool,
     ) -> Self {
+        let mut receive_buffer = ReceiveBuffer::new(
+            socket_start_time,
+            tsbpd_latency,
+            init_seq_num,
+            buffer_size_packets,
+        );
+        receive_buffer.set_skip_gaps(skip_gaps);
+        receive_buffer.set_too_late_packet_drop(too_late_packet_drop);
         Self {
             link_capacity_estimate: LinkCapacityEstimate::new(),
             arrival_speed: ArrivalSpeed::new(),
-            receive_buffer: ReceiveBuffer::new(
-                socket_start_time,
-                tsbpd_latency,
-                init_seq_num,
-                buffer_size_packets,
-            ),
+            interarrival_jitter: InterarrivalJitter::default(),
+            receive_buffer,
             ack_history_window: AckHistoryWindow::new(tsbpd_latency, init_seq_num),
+            sequence_window: SequenceHistoryWindow::new(sequence_restart_window),
             rtt: Rtt::default(),
+            retransmit_delay: DurationHistogram::default(),
+            retransmit_flag: false,
         }
     }
 
+    pub fn set_retransmit_flag(&mut self, retransmit_flag: bool) {
+        self.retransmit_flag = retransmit_flag;
+    }
+
+    pub fn set_light_ack_interval(&mut self, interval: PacketCount) {
+        self.ack_history_window.set_light_ack_interval(interval);
+    }
+
+    pub fn set_stream_mode(&mut self, stream_mode: bool) {
+        self.receive_buffer.set_stream_mode(stream_mode);
+    }
+
     pub fn is_flushed(&self) -> bool {
         self.receive_buffer.is_empty()
             && self
@@ -203,8 +235,12 @@ impl AutomaticRepeatRequestAlgorithm {
             .unacked_packet_count(self.receive_buffer.next_ack_dsn())
     }
 
-    pub fn next_message_release_time(&self) -> Option<Instant> {
-        self.receive_buffer.next_message_release_time()
+    pub fn is_in_window(&self, seq_number: SeqNumber) -> bool {
+        self.receive_buffer.is_in_window(seq_number)
+    }
+
+    pub fn next_progress_time(&self) -> Option<Instant> {
+        self.receive_buffer.next_progress_time()
     }
 
     pub fn clear(&mut self) {
@@ -221,9 +257,18 @@ impl AutomaticRepeatRequestAlgorithm {
         self.receive_buffer.synchronize_clock(now, now_ts)
     }
 
+    pub fn peer_clock_offset(&self) -> Option<TimeSpan> {
+        self.receive_buffer.peer_clock_offset()
+    }
+
     pub fn on_full_ack_event(&mut self, now: Instant) -> Option<Acknowledgement> {
+        // The space left in the receive buffer, which the sender doesn't send new packets past.
+        // Every slot of the buffer holds a packet of any size, so that's the free slots, whatever
+        // the payload sizes. Lost packets keep their slot until they're recovered or dropped.
+        //
         // NOTE: if a Full ACK is sent when the receive buffer is full, the Sender will stall
-        if self.receive_buffer.buffer_available() == 0 {
+        let buffer_available = self.receive_buffer.buffer_available();
+        if buffer_available == 0 {
             return None;
         }
 
@@ -235,21 +280,34 @@ impl AutomaticRepeatRequestAlgorithm {
 
         let arrival_speed = self.arrival_speed.calculate();
 
+        // in the order the reference implementation sends them: packets/s received
+        // (ACKD_RCVSPEED), the link capacity in packets/s (ACKD_BANDWIDTH), bytes/s received
+        // (ACKD_RCVRATE)
         let statistics = AckStatistics {
             rtt: self.rtt,
-            buffer_available: self.receive_buffer.buffer_available() as u32,
+            buffer_available: u32::try_from(buffer_available).unwrap_or(u32::MAX),
             packet_receive_rate: arrival_speed.map(|(packets, _)| packets),
-            estimated_link_capacity: arrival_speed.map(|(_, bytes)| bytes),
-            data_receive_rate: self.link_capacity_estimate.calculate(),
+            estimated_link_capacity: self.link_capacity_estimate.calculate(),
+            data_receive_rate: arrival_speed.map(|(_, bytes)| bytes),
         };
 
         Some(Acknowledgement::Full(dsn, statistics, fasn))
     }
 
+    pub fn rtt(&self) -> Rtt {
+        self.rtt
+    }
+
     pub fn on_nak_event(&mut self, now: Instant) -> Option<CompressedLossList> {
         self.receive_buffer.prepare_loss_list(now, self.rtt.mean())
     }
 
+    // the losses whose retransmission should have arrived by now
+    pub fn overdue_loss_list(&mut self, now: Instant) -> Option<CompressedLossList> {
+        let window = self.rtt.mean() + self.rtt.variance() * 4;
+        self.receive_buffer.prepare_overdue_loss_list(now, window)
+    }
+
     pub fn handle_data_packet(
         &mut self,
         now: Instant,
@@ -257,18 +315,42 @@ impl AutomaticRepeatRequestAlgorithm {
     ) -> Result<DataPacketAction, DataPacketError> {
         let seq_number = packet.seq_number;
         let size = packet.payload.len();
+        let timestamp = packet.timestamp;
+        let retransmitted = self.retransmit_flag && packet.retransmitted;
+        let expected = self.receive_buffer.next_packet_dsn();
+        if self.sequence_window.classify(expected, seq_number) == SequenceClass::Restart {
+            self.restart(seq_number);
+        }
+        let loss_report = self.receive_buffer.first_loss_report(seq_number);
         let action = match self.receive_buffer.push_packet(now, packet)? {
             DataPacketAction::Received { lrsn, recovered } => {
-                if !recovered {
+                // with the flag, a packet filling a gap without it was reordered rather than lost
+                let recovered = recovered && (retransmitted || !self.retransmit_flag);
+                if !recovered && !retransmitted {
                     self.update_link_estimates(now, seq_number, size);
                 }
+                if let Some(loss_report) = loss_report.filter(|_| recovered) {
+                    self.retransmit_delay
+                        .record(now.saturating_duration_since(loss_report));
+                }
                 self.next_light_ack(lrsn, recovered)
             }
             action => action,
         };
+        // retransmitted packets would count the recovery delay as jitter
+        if !action.is_recovered() && !retransmitted {
+            self.interarrival_jitter.record_data_packet(now, timestamp);
+        }
         Ok(action)
     }
 
+    // the sender restarted with a new initial sequence number, anything buffered from before the
+    // restart will never be completed
+    fn restart(&mut self, seq_number: SeqNumber) {
+        self.receive_buffer.restart(seq_number);
+        self.ack_history_window.reset(seq_number);
+    }
+
     fn update_link_estimates(&mut self, now: Instant, seq_number: SeqNumber, size: usize) {
         // 4) If the sequence number of the current data packet is 16n + 1,
         //     where n is an integer, record the time interval between this
@@ -318,9 +400,59 @@ impl AutomaticRepeatRequestAlgorithm {
         self.receive_buffer.pop_next_message(now)
     }
 
+    /// The packets received without a gap from `seq_number` on, before they're released
+    pub fn in_order_packets_from(
+        &self,
+        seq_number: SeqNumber,
+    ) -> impl Iterator<Item = &DataPacket> + '_ {
+        self.receive_buffer.in_order_packets_from(seq_number)
+    }
+
+    /// The first packet that wasn't received yet, or given up on
+    pub fn next_in_order(&self) -> SeqNumber {
+        self.receive_buffer.next_ack_dsn()
+    }
+
+    pub fn snapshot(&self) -> (SeqNumber, Vec<DataPacket>) {
+        self.receive_buffer.snapshot()
+    }
+
+    pub fn memory_usage(&self, usage: &mut MemoryUsage) {
+        self.receive_buffer.memory_usage(usage);
+        usage.history += self.ack_history_window.memory_usage();
+    }
+
+    /// Refill the receive buffer of a receiver that starts at the first sequence number of a
+    /// snapshot. The gaps between the packets are lost packets again, and reported as such.
+    pub fn restore(&mut self, now: Instant, packets: Vec<DataPacket>) {
+        for packet in packets {
+            let _ = self.receive_buffer.push_packet(now, packet);
+        }
+    }
+
     pub fn rx_acknowledged_time(&self) -> Duration {
         self.receive_buffer.rx_acknowledged_time()
     }
+
+    pub fn rx_acknowledged_packets(&self) -> usize {
+        self.receive_buffer.acknowledged_len()
+    }
+
+    pub fn rx_buffered_packets(&self) -> usize {
+        self.receive_buffer.len()
+    }
+
+    pub fn rx_delivery_jitter(&self) -> Duration {
+        self.receive_buffer.delivery_jitter()
+    }
+
+    pub fn rx_interarrival_jitter(&self) -> Duration {
+        self.interarrival_jitter.jitter()
+    }
+
+    pub fn rx_retransmit_delay(&self) -> DurationHistogram {
+        self.retransmit_delay
+    }
 }
 
 #[cfg(test)]
@@ -355,6 +487,9 @@ mod automatic_repeat_request_algorithm {
             Duration::from_secs(2),
             init_seq_num,
             PacketCount(8192),
+            PacketCount(0),
+            false,
+            true,
         );
 
         assert_eq!(arq.on_full_ack_event(start), None);
@@ -403,6 +538,198 @@ mod automatic_repeat_request_algorithm {
         );
     }
 
+    #[test]
+    fn handle_sender_restart() {
+        let start = Instant::now();
+        let init_seq_num = SeqNumber(5);
+        let mut arq = AutomaticRepeatRequestAlgorithm::new(
+            start,
+            Duration::from_secs(2),
+            init_seq_num,
+            PacketCount(8192),
+            PacketCount(1000),
+            false,
+            true,
+        );
+
+        let data = |seq_number| DataPacket {
+            seq_number,
+            ..basic_pack()
+        };
+
+        assert_eq!(
+            arq.handle_data_packet(start, data(init_seq_num)),
+            Ok(Received {
+                lrsn: init_seq_num + 1,
+                recovered: false
+            })
+        );
+
+        // gaps inside the window are still losses
+        assert_eq!(
+            arq.handle_data_packet(start, data(init_seq_num + 3)),
+            Ok(ReceivedWithLoss(
+                (init_seq_num + 1..init_seq_num + 3).into()
+            ))
+        );
+
+        // a sequence number outside the window restarts the stream there instead of
+        // reporting a huge loss
+        let restart_seq_num = init_seq_num + 1_000_000;
+        assert_eq!(
+            arq.handle_data_packet(start, data(restart_seq_num)),
+            Ok(Received {
+                lrsn: restart_seq_num + 1,
+                recovered: false
+            })
+        );
+        assert_eq!(
+            arq.handle_data_packet(start, data(restart_seq_num + 1)),
+            Ok(Received {
+                lrsn: restart_seq_num + 2,
+                recovered: false
+            })
+        );
+        assert_eq!(arq.on_nak_event(start + Duration::from_secs(1)), None);
+    }
+
+    #[test]
+    fn retransmit_flag() {
+        let start = Instant::now();
+        let init_seq_num = SeqNumber(5);
+        let data = |seq_number, retransmitted| DataPacket {
+            seq_number,
+            retransmitted,
+            ..basic_pack()
+        };
+        let new_arq = |retransmit_flag| {
+            let mut arq = AutomaticRepeatRequestAlgorithm::new(
+                start,
+                Duration::from_secs(2),
+                init_seq_num,
+                PacketCount(8192),
+                PacketCount(0),
+                false,
+                true,
+            );
+            arq.set_retransmit_flag(retransmit_flag);
+            let _ = arq.handle_data_packet(start, data(init_seq_num, false));
+            assert_eq!(
+                arq.handle_data_packet(start, data(init_seq_num + 3, false)),
+                Ok(ReceivedWithLoss(
+                    (init_seq_num + 1..init_seq_num + 3).into()
+                ))
+            );
+            arq
+        };
+
+        // a packet filling a gap without the flag was only reordered
+        let mut arq = new_arq(true);
+        assert_eq!(
+            arq.handle_data_packet(start, data(init_seq_num + 1, false)),
+            Ok(Received {
+                lrsn: init_seq_num + 2,
+                recovered: false
+            })
+        );
+        assert_eq!(
+            arq.handle_data_packet(start, data(init_seq_num + 2, true)),
+            Ok(Received {
+                lrsn: init_seq_num + 4,
+                recovered: true
+            })
+        );
+
+        // without REXMITFLG, any packet filling a gap is taken as recovered
+        let mut arq = new_arq(false);
+        assert_eq!(
+            arq.handle_data_packet(start, data(init_seq_num + 1, false)),
+            Ok(Received {
+                lrsn: init_seq_num + 2,
+                recovered: true
+            })
+        );
+    }
+
+    #[test]
+    fn retransmit_delay() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let init_seq_num = SeqNumber(5);
+        let data = |seq_number, retransmitted| DataPacket {
+            seq_number,
+            retransmitted,
+            ..basic_pack()
+        };
+        let mut arq = AutomaticRepeatRequestAlgorithm::new(
+            start,
+            Duration::from_secs(2),
+            init_seq_num,
+            PacketCount(8192),
+            PacketCount(0),
+            false,
+            true,
+        );
+        arq.set_retransmit_flag(true);
+        let _ = arq.handle_data_packet(start, data(init_seq_num, false));
+        let _ = arq.handle_data_packet(start, data(init_seq_num + 4, false));
+
+        // timed from the first report, even if the loss was reported again since
+        let _ = arq.on_nak_event(start + ms(50));
+        let _ = arq.handle_data_packet(start + ms(30), data(init_seq_num + 1, true));
+        let _ = arq.handle_data_packet(start + ms(70), data(init_seq_num + 2, true));
+        // reordered rather than retransmitted
+        let _ = arq.handle_data_packet(start + ms(80), data(init_seq_num + 3, false));
+        // duplicates of what was already recovered
+        let _ = arq.handle_data_packet(start + ms(90), data(init_seq_num + 1, true));
+
+        let delay = arq.rx_retransmit_delay();
+        assert_eq!(delay.count(), 2);
+        assert_eq!(delay.mean(), Some(ms(50)));
+        assert_eq!(delay.quantile(1.0), Some(ms(100)));
+    }
+
+    #[test]
+    fn ack_rates() {
+        let start = Instant::now();
+        let init_seq_num = SeqNumber(0);
+        let mut arq = AutomaticRepeatRequestAlgorithm::new(
+            start,
+            Duration::from_secs(2),
+            init_seq_num,
+            PacketCount(8192),
+            PacketCount(0),
+            false,
+            true,
+        );
+
+        // a packet every millisecond, with each probing pair 0.5ms apart
+        let mut now = start;
+        for n in 0..320 {
+            now += match n % 16 {
+                1 => Duration::from_micros(500),
+                2 => Duration::from_micros(1500),
+                _ => Duration::from_millis(1),
+            };
+            let _ = arq.handle_data_packet(
+                now,
+                DataPacket {
+                    seq_number: init_seq_num + n,
+                    payload: Bytes::from(vec![0; 1000]),
+                    ..basic_pack()
+                },
+            );
+        }
+
+        let statistics = match arq.on_full_ack_event(now) {
+            Some(Acknowledgement::Full(_, statistics, _)) => statistics,
+            ack => panic!("expected a full ACK, got {ack:?}"),
+        };
+        assert_eq!(statistics.packet_receive_rate, Some(1000));
+        assert_eq!(statistics.estimated_link_capacity, Some(2000));
+        assert_eq!(statistics.data_receive_rate, Some(1000 * 1000));
+    }
+
     #[test]
     fn ack_event() {
         let start = Instant::now();
@@ -412,6 +739,9 @@ mod automatic_repeat_request_algorithm {
             Duration::from_secs(2),
             init_seq_num,
             PacketCount(8192),
+            PacketCount(0),
+            false,
+            true,
         );
 
         assert_eq!(
@@ -480,6 +810,9 @@ mod automatic_repeat_request_algorithm {
             Duration::from_secs(2),
             init_seq_num,
             PacketCount(8192),
+            PacketCount(0),
+            false,
+            true,
         );
 
         let _ = arq.handle_data_packet(
@@ -522,6 +855,9 @@ mod automatic_repeat_request_algorithm {
             Duration::from_secs(1),
             init_seq_num,
             PacketCount(8192),
+            PacketCount(0),
+            false,
+            true,
         );
 
         let _ = arq.handle_data_packet(
@@ -573,6 +909,9 @@ mod automatic_repeat_request_algorithm {
             tsbpd_latency,
             init_seq_num,
             PacketCount(8192),
+            PacketCount(0),
+            false,
+            true,
         );
 
         let now = start;
@@ -710,4 +1049,54 @@ mod automatic_repeat_request_algorithm {
         }
         assert_eq!(link_capacity_estimate.calculate(), Some(1_000));
     }
+
+    #[test]
+    fn buffer_available_varied_payloads() {
+        let start = Instant::now();
+        let init_seq_num = SeqNumber(5);
+        let mut arq = AutomaticRepeatRequestAlgorithm::new(
+            start,
+            Duration::from_secs(2),
+            init_seq_num,
+            PacketCount(100),
+            PacketCount(0),
+            false,
+            true,
+        );
+
+        // tiny and full size payloads take a slot each, and so do the packets lost in between
+        let sizes = [1, 1456, 7, 1316, 188, 1, 1456];
+        for (i, size) in sizes.into_iter().enumerate() {
+            let _ = arq.handle_data_packet(
+                start,
+                DataPacket {
+                    seq_number: init_seq_num + i as u32 * 2,
+                    message_loc: PacketLocation::ONLY,
+                    payload: Bytes::from(vec![0; size]),
+                    ..basic_pack()
+                },
+            );
+        }
+        let buffer_available = |ack| match ack {
+            Some(Acknowledgement::Full(_, statistics, _)) => statistics.buffer_available,
+            ack => panic!("expected a full ack, got {ack:?}"),
+        };
+        assert_eq!(buffer_available(arq.on_full_ack_event(start)), 100 - 13);
+
+        // the lost packets are recovered, and the first message is released
+        for i in 0..6 {
+            let _ = arq.handle_data_packet(
+                start,
+                DataPacket {
+                    seq_number: init_seq_num + i * 2 + 1,
+                    message_loc: PacketLocation::ONLY,
+                    payload: Bytes::from(vec![0; 600]),
+                    ..basic_pack()
+                },
+            );
+        }
+        let now = start + Duration::from_secs(3);
+        assert_matches!(arq.pop_next_message(now), Ok(Some(_)));
+        assert_eq!(buffer_available(arq.on_full_ack_event(now)), 100 - 12);
+    }
 }
@@ -1,18 +1,17 @@
 use std::{
-    cmp::min,
-    collections::VecDeque,
-    convert::TryFrom,
-    ops::Range,
-    time::{Duration, Instant},
+    cmp::min, collections::VecDeque, convert::TryFrom, mem::size_of, ops::Range, time::Duration,
 };
 
 use bytes::{Bytes, BytesMut};
 use take_until::TakeUntilExt;
 
-use crate::{options::PacketCount, packet::*};
+use crate::{
+    options::PacketCount, packet::*, protocol::loss_list::LossList, protocol::time::Instant,
+    statistics::MemoryUsage,
+};
 
 use super::{
-    time::{ClockAdjustment, SynchronizedRemoteClock},
+    time::{ClockAdjustment, Jitter, SynchronizedRemoteClock},
     DataPacketAction, DataPacketError,
 };
 
@@ -20,6 +19,7 @@ use super::{
 pub struct LostPacket {
     data_sequence_number: SeqNumber,
     feedback_time: Instant,
+    first_feedback_time: Instant,
     k: i32,
 }
 
@@ -28,6 +28,7 @@ impl LostPacket {
         Self {
             data_sequence_number,
             feedback_time,
+            first_feedback_time: feedback_time,
             k: 2,
         }
     }
@@ -88,13 +89,10 @@ impl BufferPacket {
 
     pub fn lost_ready_for_feedback_mut(
         &mut self,
-        now: Instant,
-        rtt_mean: TimeSpan,
+        ready: impl Fn(&LostPacket) -> bool,
     ) -> Option<&mut LostPacket> {
         match self {
-            BufferPacket::Lost(lost) if now > lost.feedback_time + (rtt_mean * lost.k) => {
-                Some(lost)
-            }
+            BufferPacket::Lost(lost) if ready(lost) => Some(lost),
             _ => None,
         }
     }
@@ -176,8 +174,25 @@ pub struct ReceiveBuffer {
     seqno0: SeqNumber,
 
     remote_clock: SynchronizedRemoteClock,
+    // a ring indexed by offset from seqno0, allocated up front and never grown since it can't
+    // hold more than max_buffer_size packets
     buffer: VecDeque<BufferPacket>,
     max_buffer_size: PacketCount,
+    // the packets in the buffer that are BufferPacket::Lost
+    lost: LossList,
+
+    // how late messages are released compared to their TSBPD release time, smoothed
+    delivery_jitter: Jitter,
+
+    // release messages after a gap at their TSBPD time, without the grace period
+    skip_gaps: bool,
+
+    // skip missing packets once the message after them is too late, otherwise wait for them
+    too_late_packet_drop: bool,
+
+    // the data is a byte stream, each packet is released once it's in order, without waiting
+    // for the rest of its message
+    stream_mode: bool,
 }
 
 impl ReceiveBuffer {
@@ -194,9 +209,26 @@ impl ReceiveBuffer {
             remote_clock: SynchronizedRemoteClock::new(socket_start_time),
             buffer: VecDeque::with_capacity(max_buffer_size.into()),
             max_buffer_size,
+            lost: LossList::new(),
+            delivery_jitter: Jitter::default(),
+            skip_gaps: false,
+            too_late_packet_drop: true,
+            stream_mode: false,
         }
     }
 
+    pub fn set_skip_gaps(&mut self, skip_gaps: bool) {
+        self.skip_gaps = skip_gaps;
+    }
+
+    pub fn set_too_late_packet_drop(&mut self, too_late_packet_drop: bool) {
+        self.too_late_packet_drop = too_late_packet_drop;
+    }
+
+    pub fn set_stream_mode(&mut self, stream_mode: bool) {
+        self.stream_mode = stream_mode;
+    }
+
     pub fn is_empty(&self) -> bool {
         self.buffer.is_empty()
     }
@@ -205,8 +237,28 @@ impl ReceiveBuffer {
         self.lrsn
     }
 
+    /// The first sequence number in the buffer and the packets received from there on that weren't
+    /// released yet
+    pub fn snapshot(&self) -> (SeqNumber, Vec<DataPacket>) {
+        let packets = self
+            .buffer
+            .iter()
+            .filter_map(|p| p.data_packet().cloned())
+            .collect();
+        (self.seqno0, packets)
+    }
+
     pub fn clear(&mut self) {
         self.buffer.clear();
+        self.lost.clear();
+    }
+
+    /// Discard everything buffered and continue from a new sequence number
+    pub fn restart(&mut self, seq_number: SeqNumber) {
+        self.buffer.clear();
+        self.lost.clear();
+        self.seqno0 = seq_number;
+        self.lrsn = seq_number;
     }
 
     pub fn synchronize_clock(
@@ -217,13 +269,50 @@ impl ReceiveBuffer {
         self.remote_clock.synchronize(now, now_ts)
     }
 
+    pub fn peer_clock_offset(&self) -> Option<TimeSpan> {
+        self.remote_clock.offset()
+    }
+
     /// Buffer available, in packets
     pub fn buffer_available(&self) -> usize {
         usize::from(self.max_buffer_size) - self.buffer.len()
     }
 
+    pub fn memory_usage(&self, usage: &mut MemoryUsage) {
+        let payloads: usize = self
+            .buffer
+            .iter()
+            .filter_map(|p| p.data_packet())
+            .map(|p| p.payload.len())
+            .sum();
+        usage.receive_buffer += self.buffer.capacity() * size_of::<BufferPacket>() + payloads;
+        usage.loss_lists += self.lost.memory_usage();
+    }
+
+    /// Packets the buffer spans, received or not
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Packets received without a gap, i.e. those that can be acknowledged
+    pub fn acknowledged_len(&self) -> usize {
+        (self.lrsn - self.seqno0) as usize
+    }
+
+    /// The packets received without a gap from `seq_number` on, that weren't released yet
+    pub fn in_order_packets_from(
+        &self,
+        seq_number: SeqNumber,
+    ) -> impl Iterator<Item = &DataPacket> + '_ {
+        let end = self.acknowledged_len();
+        let start = min(self.clamped_index_for_seqno(seq_number), end);
+        self.buffer
+            .range(start..end)
+            .filter_map(BufferPacket::data_packet)
+    }
+
     // next expected packet (1 + last received packet)
-    fn next_packet_dsn(&self) -> SeqNumber {
+    pub fn next_packet_dsn(&self) -> SeqNumber {
         self.seqno0 + u32::try_from(self.buffer.len()).unwrap()
     }
 
@@ -237,6 +326,20 @@ impl ReceiveBuffer {
         }
     }
 
+    /// Whether the buffer has room for `seq_number`, now or once what's ahead of it is released
+    pub fn is_in_window(&self, seq_number: SeqNumber) -> bool {
+        seq_number >= self.seqno0
+            && ((seq_number - self.seqno0) as usize) < usize::from(self.max_buffer_size)
+    }
+
+    /// When `seq_number` was first reported lost, if it's still missing
+    pub fn first_loss_report(&self, seq_number: SeqNumber) -> Option<Instant> {
+        match self.buffer.get(self.index_for_seqno(seq_number)?)? {
+            BufferPacket::Lost(lost) => Some(lost.first_feedback_time),
+            _ => None,
+        }
+    }
+
     // index in buffer for a given sequence number clamped to 0 or buffer.len()
     fn clamped_index_for_seqno(&self, seq_number: SeqNumber) -> usize {
         min(seq_number.saturating_sub(self.seqno0), self.buffer.len())
@@ -248,17 +351,25 @@ impl ReceiveBuffer {
         data: DataPacket,
     ) -> Result<DataPacketAction, DataPacketError> {
         use std::cmp::Ordering::*;
-        match data.seq_number.cmp(&self.next_packet_dsn()) {
+        let result = match data.seq_number.cmp(&self.next_packet_dsn()) {
             Equal => self.append_next(data),
             Greater => self.append_with_loss(now, data),
             Less => self.recover_data(data),
-        }
+        };
+        self.debug_assert_invariants();
+        result
     }
 
     pub fn pop_next_message(
         &mut self,
         now: Instant,
     ) -> Result<Option<(Instant, Bytes)>, MessageError> {
+        let result = self.pop_message(now);
+        self.debug_assert_invariants();
+        result
+    }
+
+    fn pop_message(&mut self, now: Instant) -> Result<Option<(Instant, Bytes)>, MessageError> {
         let timestamp = match self.front_ts() {
             Some(timestamp) => timestamp,
             None => {
@@ -270,7 +381,8 @@ impl ReceiveBuffer {
         };
 
         let sent_time = self.remote_clock.instant_from(timestamp);
-        if now < sent_time + self.tsbpd_latency {
+        let due_time = sent_time + self.tsbpd_latency;
+        if now < due_time {
             return Ok(None);
         }
 
@@ -286,6 +398,8 @@ impl ReceiveBuffer {
 
         self.seqno0 += u32::try_from(packet_count).unwrap();
 
+        self.delivery_jitter.add(now - due_time);
+
         let release_time = self.remote_clock.monotonic_instant_from(timestamp);
         let message = if packet_count == 1 {
             self.release_single_packet_message(release_time)
@@ -333,18 +447,40 @@ impl ReceiveBuffer {
         // and increased by 1 each time the number is fed back. Compress
         // (according to section 6.4) and send these numbers back to the sender
         // in an NAK packet.
-        let loss_list = self
-            .buffer
-            .range_mut(self.lost_list_index()..)
-            .filter_map(|p| p.lost_ready_for_feedback_mut(now, rtt_mean))
-            .map(|lost| {
+        self.feed_back_losses(now, |lost| now > lost.feedback_time + (rtt_mean * lost.k))
+    }
+
+    /// The losses last fed back more than `window` ago, regardless of how often they were
+    pub fn prepare_overdue_loss_list(
+        &mut self,
+        now: Instant,
+        window: TimeSpan,
+    ) -> Option<CompressedLossList> {
+        self.feed_back_losses(now, |lost| now > lost.feedback_time + window)
+    }
+
+    fn feed_back_losses(
+        &mut self,
+        now: Instant,
+        ready: impl Fn(&LostPacket) -> bool,
+    ) -> Option<CompressedLossList> {
+        let mut loss_list = Vec::new();
+        for range in self.lost.ranges() {
+            let first = (range.start - self.seqno0) as usize;
+            let last = (range.end - self.seqno0) as usize;
+            let ready = self
+                .buffer
+                .range_mut(first..last)
+                .filter_map(|p| p.lost_ready_for_feedback_mut(&ready));
+            for lost in ready {
                 // increment k and change feedback time, returning sequence numbers
                 lost.k += 1;
                 lost.feedback_time = now;
-                lost.data_sequence_number
-            });
+                loss_list.push(lost.data_sequence_number);
+            }
+        }
 
-        CompressedLossList::try_from_iter(loss_list)
+        CompressedLossList::try_from_iter(loss_list.into_iter())
     }
 
     /// Returns how many packets were actually dropped
@@ -352,6 +488,7 @@ impl ReceiveBuffer {
         // if start of the range has been dropped already, just drop everything after
         let first_idx = self.clamped_index_for_seqno(range.start);
         let last_idx = self.clamped_index_for_seqno(range.end);
+        self.lost.remove_range(range);
         self.buffer
             .range_mut(first_idx..last_idx)
             .filter_map(|p| p.drop_unreceived())
@@ -361,11 +498,34 @@ impl ReceiveBuffer {
     pub fn next_message_release_time(&self) -> Option<Instant> {
         self.buffer
             .front()
-            .filter(|p| p.is_first())?
+            .filter(|p| p.is_first() || self.stream_mode)?
             .data_packet()
             .map(|d| self.remote_clock.instant_from(d.timestamp) + self.tsbpd_latency)
     }
 
+    /// The next time pop_next_message can make progress: either the release time of the message
+    /// at the front of the buffer, or if that message can't be released because packets are
+    /// missing, the time they will be dropped as too late.
+    pub fn next_progress_time(&self) -> Option<Instant> {
+        let front = self.buffer.front()?;
+        if (front.is_first() || self.stream_mode) && self.next_message_packet_count().is_some() {
+            return self.next_message_release_time();
+        }
+        if !self.too_late_packet_drop {
+            return None;
+        }
+        self.buffer
+            .iter()
+            .skip(1)
+            .find(|p| p.is_first())?
+            .data_packet()
+            .map(|d| self.remote_clock.instant_from(d.timestamp) + self.too_late_window())
+    }
+
+    pub fn delivery_jitter(&self) -> Duration {
+        self.delivery_jitter.get()
+    }
+
     fn append_next(&mut self, data: DataPacket) -> Result<DataPacketAction, DataPacketError> {
         if self.buffer_available() == 0 {
             Err(DataPacketError::BufferFull {
@@ -416,6 +576,7 @@ impl ReceiveBuffer {
             })?;
 
         self.buffer.get_mut(index).unwrap().update_data(data)?;
+        self.lost.remove(seq_number);
 
         // first lost packet was recovered, update LRSN
         if self.lrsn == seq_number {
@@ -441,17 +602,14 @@ impl ReceiveBuffer {
             let loss = LostPacket::new(lost.start + i, now);
             self.buffer.push_back(BufferPacket::Lost(loss));
         }
-    }
-
-    fn lost_list_index(&self) -> usize {
-        self.buffer
-            .iter()
-            .take_while(|b| b.data_packet().is_some())
-            .count()
+        self.lost.insert_range(lost.clone());
     }
 
     fn next_message_packet_count(&self) -> Option<usize> {
         let first = self.buffer.front()?.data_packet()?;
+        if self.stream_mode {
+            return Some(1);
+        }
         self.buffer
             .iter()
             // once stabilized in std, take_while & filter_map can be replaced with map_while
@@ -460,10 +618,21 @@ impl ReceiveBuffer {
             .calculate()
     }
 
+    fn too_late_window(&self) -> Duration {
+        if self.skip_gaps {
+            self.tsbpd_latency
+        } else {
+            self.tsbpd_latency + Duration::from_millis(5)
+        }
+    }
+
     /// Drops the packets that are deemed to be too late
     /// i.e.: there is a packet after it that is ready to be released
     fn drop_too_late_packets(&mut self, now: Instant) -> Option<MessageError> {
-        let latency_window = self.tsbpd_latency + Duration::from_millis(5);
+        if !self.too_late_packet_drop {
+            return None;
+        }
+        let latency_window = self.too_late_window();
         // Not only does it have to be non-none, it also has to be a First (don't drop half messages)
         let (index, seq_number, timestamp) = self
             .buffer
@@ -480,6 +649,7 @@ impl ReceiveBuffer {
 
         let delay = TimeSpan::from_interval(timestamp + self.tsbpd_latency, now);
         let drop_count = self.buffer.drain(0..index).count();
+        self.lost.remove_before(seq_number);
 
         self.seqno0 = seq_number;
         self.recalculate_lrsn(0);
@@ -499,6 +669,37 @@ impl ReceiveBuffer {
             .unwrap_or_else(|| self.next_packet_dsn())
     }
 
+    // The sequence number bookkeeping is modular, so an off by one anywhere near the wrap around
+    // shows up as a buffer that appears to span half the sequence number space.
+    fn debug_assert_invariants(&self) {
+        let next = self.next_packet_dsn();
+        debug_assert!(
+            self.buffer.len() < SeqNumber::MAX_DIFF as usize,
+            "receive buffer spans {} packets",
+            self.buffer.len()
+        );
+        // clear() on the close timeout empties the buffer and leaves lrsn as it was
+        debug_assert!(
+            self.seqno0 <= self.lrsn && (self.buffer.is_empty() || self.lrsn <= next),
+            "lrsn {} is outside of the receive buffer {}..{}",
+            self.lrsn,
+            self.seqno0,
+            next
+        );
+        debug_assert!(
+            self.buffer
+                .front()
+                .is_none_or(|p| p.data_sequence_number() == self.seqno0),
+            "receive buffer front does not match seqno0 {}",
+            self.seqno0
+        );
+        debug_assert!(
+            self.lost.first().is_none_or(|lost| lost >= self.seqno0),
+            "lost packet is before the receive buffer front {}",
+            self.seqno0
+        );
+    }
+
     pub fn rx_acknowledged_time(&self) -> Duration {
         let start_idx = 0;
         let end_idx = self.clamped_index_for_seqno(self.lrsn - 1);
@@ -573,9 +774,52 @@ mod receive_buffer {
         );
         assert_eq!(buf.next_ack_dsn(), init_seq_num + 1);
         assert_eq!(buf.next_message_release_time(), Some(start + tsbpd));
+        // the rest of the message is missing, so there's nothing to wait for
+        assert_eq!(buf.next_progress_time(), None);
         assert_eq!(buf.pop_next_message(start + tsbpd * 2), Ok(None));
     }
 
+    #[test]
+    fn stream_mode() {
+        let tsbpd = Duration::from_secs(2);
+        let start = Instant::now();
+        let init_seq_num = SeqNumber(5);
+
+        let mut buf = ReceiveBuffer::new(start, tsbpd, init_seq_num, PacketCount(8192));
+        buf.set_too_late_packet_drop(false);
+        buf.set_stream_mode(true);
+
+        // the middle of a message, the rest of it is still on the way
+        let packet = |n: u32, message_loc| DataPacket {
+            seq_number: init_seq_num + n,
+            message_loc,
+            payload: Bytes::from(vec![n as u8]),
+            ..basic_pack()
+        };
+        let _ = buf.push_packet(start, packet(0, PacketLocation::FIRST));
+        let _ = buf.push_packet(start, packet(2, PacketLocation::empty()));
+
+        // the packets are released one by one as soon as they're in order, and due
+        assert_eq!(buf.next_message_release_time(), Some(start + tsbpd));
+        assert_eq!(buf.pop_next_message(start), Ok(None));
+        assert_eq!(
+            buf.pop_next_message(start + tsbpd),
+            Ok(Some((start, Bytes::from_static(&[0]))))
+        );
+        assert_eq!(buf.pop_next_message(start + tsbpd), Ok(None));
+        assert_eq!(buf.next_message_release_time(), None);
+
+        let _ = buf.push_packet(start + tsbpd, packet(1, PacketLocation::empty()));
+        assert_eq!(
+            buf.pop_next_message(start + tsbpd),
+            Ok(Some((start, Bytes::from_static(&[1]))))
+        );
+        assert_eq!(
+            buf.pop_next_message(start + tsbpd),
+            Ok(Some((start, Bytes::from_static(&[2]))))
+        );
+    }
+
     #[test]
     fn multi_packet_message_lost_last_packet() {
         let tsbpd = Duration::from_secs(2);
@@ -600,6 +844,7 @@ mod receive_buffer {
         );
         assert_eq!(buf.next_ack_dsn(), init_seq_num + 1);
         assert_eq!(buf.next_message_release_time(), Some(start + tsbpd));
+        assert_eq!(buf.next_progress_time(), None);
         assert_eq!(buf.pop_next_message(start + tsbpd * 2), Ok(None));
 
         // 1 lost packet
@@ -616,6 +861,11 @@ mod receive_buffer {
         );
         assert_eq!(buf.next_ack_dsn(), init_seq_num + 1);
         assert_eq!(buf.next_message_release_time(), Some(start + tsbpd));
+        // the incomplete message is dropped once the next message is too late
+        assert_eq!(
+            buf.next_progress_time(),
+            Some(start + tsbpd + Duration::from_millis(5))
+        );
         assert_eq!(
             buf.pop_next_message(start + tsbpd * 2),
             Err(MessageError {
@@ -649,6 +899,7 @@ mod receive_buffer {
         );
         assert_eq!(buf.next_ack_dsn(), init_seq_num + 1);
         assert_eq!(buf.next_message_release_time(), Some(start + tsbpd));
+        assert_eq!(buf.next_progress_time(), None);
         assert_eq!(buf.pop_next_message(start + tsbpd * 2), Ok(None));
 
         assert_eq!(
@@ -667,6 +918,7 @@ mod receive_buffer {
         );
         assert_eq!(buf.next_ack_dsn(), init_seq_num + 2);
         assert_eq!(buf.next_message_release_time(), Some(start + tsbpd));
+        assert_eq!(buf.next_progress_time(), None);
         assert_eq!(buf.pop_next_message(start + tsbpd), Ok(None));
     }
 
@@ -871,6 +1123,75 @@ mod receive_buffer {
         assert_eq!(buf.prepare_loss_list(now, mean_rtt), None);
     }
 
+    #[test]
+    fn prepare_overdue_loss_list() {
+        let start = Instant::now();
+        let init_seq_num = SeqNumber(5);
+        let window = TimeSpan::from_micros(1_000);
+        let mut buf = ReceiveBuffer::new(
+            start,
+            Duration::from_secs(2),
+            init_seq_num,
+            PacketCount(8192),
+        );
+        let data = |seq_number| DataPacket {
+            seq_number,
+            ..basic_pack()
+        };
+
+        let _ = buf.push_packet(start, data(init_seq_num));
+        let _ = buf.push_packet(start, data(init_seq_num + 3));
+        assert_eq!(buf.prepare_overdue_loss_list(start + window, window), None);
+
+        let now = start + window * 2;
+        let _ = buf.push_packet(now, data(init_seq_num + 6));
+        // only the first gap is overdue, the second was just reported
+        assert_eq!(
+            buf.prepare_overdue_loss_list(now, window),
+            Some((1..3).map(|a| init_seq_num + a).collect())
+        );
+        assert_eq!(buf.prepare_overdue_loss_list(now, window), None);
+
+        // the window doesn't back off with repeated reports
+        let now = now + window * 2;
+        assert_eq!(
+            buf.prepare_overdue_loss_list(now, window),
+            Some([1, 2, 4, 5].iter().map(|a| init_seq_num + *a).collect())
+        );
+    }
+
+    #[test]
+    fn loss_burst() {
+        let start = Instant::now();
+        let mean_rtt = TimeSpan::from_micros(10_000);
+        let mut buf = ReceiveBuffer::new(
+            start,
+            Duration::from_secs(2),
+            SeqNumber(0),
+            PacketCount(100_000),
+        );
+
+        let packet = |seq_number| DataPacket {
+            seq_number,
+            ..basic_pack()
+        };
+        buf.push_packet(start, packet(SeqNumber(0))).unwrap();
+        buf.push_packet(start, packet(SeqNumber(50_001))).unwrap();
+        buf.push_packet(start, packet(SeqNumber(25_000))).unwrap();
+
+        let now = start + mean_rtt * 3;
+        assert_eq!(
+            buf.prepare_loss_list(now, mean_rtt)
+                .map(|l| l.iter_compressed().count()),
+            Some(4)
+        );
+        assert_eq!(buf.drop_packets(SeqNumber(0)..SeqNumber(40_000)), 39_998);
+        assert_eq!(
+            buf.prepare_loss_list(now + mean_rtt * 4, mean_rtt),
+            Some((SeqNumber(40_000)..SeqNumber(50_001)).into())
+        );
+    }
+
     #[test]
     fn drop_too_late_packets() {
         let _ = pretty_env_logger::try_init();
@@ -951,6 +1272,103 @@ mod receive_buffer {
         assert_eq!(buf.next_ack_dsn(), init_seq_num + 6);
     }
 
+    #[test]
+    fn skip_gaps() {
+        let tsbpd = Duration::from_secs(2);
+        let start = Instant::now();
+        let init_seq_num = SeqNumber(5);
+        let later = TimeSpan::from_millis(20);
+
+        let mut buf = ReceiveBuffer::new(start, tsbpd, init_seq_num, PacketCount(8192));
+        buf.set_skip_gaps(true);
+
+        // the second packet is lost, the ones around it were sent 20ms apart
+        let _ = buf.push_packet(
+            start,
+            DataPacket {
+                seq_number: init_seq_num,
+                message_loc: PacketLocation::ONLY,
+                payload: b"before"[..].into(),
+                ..basic_pack()
+            },
+        );
+        let _ = buf.push_packet(
+            start,
+            DataPacket {
+                seq_number: init_seq_num + 2,
+                message_loc: PacketLocation::ONLY,
+                message_number: MsgNumber(2),
+                timestamp: TimeStamp::MIN + later,
+                payload: b"after"[..].into(),
+                ..basic_pack()
+            },
+        );
+        assert_eq!(
+            buf.pop_next_message(start + tsbpd),
+            Ok(Some((start, b"before"[..].into())))
+        );
+        assert_eq!(buf.next_progress_time(), Some(start + tsbpd + later));
+        assert_eq!(buf.pop_next_message(start + tsbpd + later / 2), Ok(None));
+
+        // the gap is skipped right at the TSBPD time of the message after it
+        let now = start + tsbpd + later;
+        assert_eq!(
+            buf.pop_next_message(now),
+            Err(MessageError {
+                too_late_packets: init_seq_num + 1..init_seq_num + 2,
+                delay: TimeSpan::ZERO
+            })
+        );
+        assert_eq!(
+            buf.pop_next_message(now),
+            Ok(Some((start + later, b"after"[..].into())))
+        );
+        assert_eq!(buf.next_ack_dsn(), init_seq_num + 3);
+    }
+
+    #[test]
+    fn no_too_late_packet_drop() {
+        let tsbpd = Duration::from_secs(2);
+        let start = Instant::now();
+        let init_seq_num = SeqNumber(5);
+
+        let mut buf = ReceiveBuffer::new(start, tsbpd, init_seq_num, PacketCount(8192));
+        buf.set_too_late_packet_drop(false);
+
+        // the first packet is lost
+        let _ = buf.push_packet(
+            start,
+            DataPacket {
+                seq_number: init_seq_num + 1,
+                message_loc: PacketLocation::ONLY,
+                payload: b"yas"[..].into(),
+                ..basic_pack()
+            },
+        );
+        assert_eq!(buf.next_progress_time(), None);
+
+        // it's waited for however late it is
+        let now = start + tsbpd * 10;
+        assert_eq!(buf.pop_next_message(now), Ok(None));
+        let _ = buf.push_packet(
+            now,
+            DataPacket {
+                seq_number: init_seq_num,
+                message_loc: PacketLocation::ONLY,
+                payload: b"hello"[..].into(),
+                ..basic_pack()
+            },
+        );
+        assert_eq!(
+            buf.pop_next_message(now),
+            Ok(Some((start, b"hello"[..].into())))
+        );
+        assert_eq!(
+            buf.pop_next_message(now),
+            Ok(Some((start, b"yas"[..].into())))
+        );
+    }
+
     #[test]
     fn drop_message() {
         let tsbpd = Duration::from_secs(2);
@@ -1200,4 +1618,76 @@ mod receive_buffer {
         );
         assert_eq!(buf.rx_acknowledged_time(), Duration::from_micros(0));
     }
+
+    #[test]
+    fn steady_state() {
+        // a second at 100k packets/sec, 10ms of latency, so about 1000 packets are buffered
+        let tsbpd = Duration::from_millis(10);
+        let start = Instant::now();
+        let mut buf = ReceiveBuffer::new(start, tsbpd, SeqNumber(0), PacketCount(8192));
+        let capacity = buf.buffer.capacity();
+
+        let packet = |i: u32| DataPacket {
+            seq_number: SeqNumber(i),
+            message_number: MsgNumber(i),
+            timestamp: TimeStamp::from_micros(i * 10),
+            ..basic_pack()
+        };
+        let mut released = 0;
+        for i in 0..100_000 {
+            let now = start + Duration::from_micros(u64::from(i) * 10);
+            // every 100th packet is lost and recovered 10 packets later
+            if i % 100 != 0 {
+                buf.push_packet(now, packet(i)).unwrap();
+            }
+            if i % 100 == 10 {
+                buf.push_packet(now, packet(i - 10)).unwrap();
+            }
+            while buf.pop_next_message(now).unwrap().is_some() {
+                released += 1;
+            }
+        }
+        assert_eq!(released, 99_000);
+        assert_eq!(buf.buffer.capacity(), capacity);
+    }
+
+    // cargo test --release -p srt-protocol receiver::buffer::receive_buffer::throughput -- --ignored
+    #[test]
+    #[ignore]
+    fn throughput() {
+        // 10 seconds of a 100k packets/sec stream with 1% loss, through the buffer as fast as it
+        // goes, which has to be well above real time
+        const PACKETS: u32 = 1_000_000;
+        let tsbpd = Duration::from_millis(120);
+        let start = Instant::now();
+        let mut buf = ReceiveBuffer::new(start, tsbpd, SeqNumber(0), PacketCount(65536));
+        let packet = |i: u32| DataPacket {
+            seq_number: SeqNumber(i),
+            message_number: MsgNumber(i),
+            timestamp: TimeStamp::from_micros(i * 10),
+            payload: Bytes::from_static(&[0; 1316]),
+            ..basic_pack()
+        };
+
+        let began = std::time::Instant::now();
+        let mut released = 0;
+        for i in 0..PACKETS {
+            let now = start + Duration::from_micros(u64::from(i) * 10);
+            if i % 100 != 0 {
+                buf.push_packet(now, packet(i)).unwrap();
+            }
+            if i % 100 == 50 {
+                buf.push_packet(now, packet(i - 50)).unwrap();
+            }
+            while buf.pop_next_message(now).unwrap().is_some() {
+                released += 1;
+            }
+        }
+        let elapsed = began.elapsed();
+
+        let rate = f64::from(PACKETS) / elapsed.as_secs_f64();
+        println!("{PACKETS} packets in {elapsed:?}, {rate:.0} packets/sec");
+        assert!(released > PACKETS - 20_000, "{released}");
+        assert!(rate > 100_000., "{rate:.0} packets/sec");
+    }
 }
@@ -1,9 +1,10 @@
-use std::{
-    collections::VecDeque,
-    time::{Duration, Instant},
-};
+use std::{collections::VecDeque, mem::size_of, time::Duration};
 
-use crate::packet::{FullAckSeqNumber, SeqNumber, TimeSpan};
+use crate::{
+    options::PacketCount,
+    packet::{FullAckSeqNumber, SeqNumber, TimeSpan},
+    protocol::time::Instant,
+};
 
 #[derive(Debug)]
 struct AckHistoryEntry {
@@ -18,6 +19,7 @@ pub struct AckHistoryWindow {
     tsbpd_latency: Duration,
     last_ack_dsn: SeqNumber,
     largest_ack2_dsn: SeqNumber,
+    light_ack_interval: u32,
     buffer: VecDeque<AckHistoryEntry>,
 }
 
@@ -29,10 +31,21 @@ impl AckHistoryWindow {
             tsbpd_latency,
             last_ack_dsn: initial_dsn,
             largest_ack2_dsn: initial_dsn,
+            light_ack_interval: Self::LIGHT_ACK_PACKET_INTERVAL,
             buffer: VecDeque::with_capacity(20_000 * tsbpd_latency.as_secs_f32() as usize),
         }
     }
 
+    pub fn memory_usage(&self) -> usize {
+        self.buffer.capacity() * size_of::<AckHistoryEntry>()
+    }
+
+    pub fn set_light_ack_interval(&mut self, interval: PacketCount) {
+        self.light_ack_interval = u32::try_from(interval.0)
+            .unwrap_or(SeqNumber::MAX_DIFF)
+            .max(1);
+    }
+
     pub fn unacked_packet_count(&self, lrsn: SeqNumber) -> u32 {
         if lrsn < self.largest_ack2_dsn {
             return 0;
@@ -46,6 +59,7 @@ impl AckHistoryWindow {
 
     pub fn reset(&mut self, lrsn: SeqNumber) {
         self.buffer.clear();
+        self.last_ack_dsn = lrsn;
         self.largest_ack2_dsn = lrsn;
     }
 
@@ -120,7 +134,7 @@ impl AckHistoryWindow {
 
     #[must_use]
     pub fn next_light_ack(&mut self, next_dsn: SeqNumber) -> Option<SeqNumber> {
-        if next_dsn >= self.last_ack_dsn + Self::LIGHT_ACK_PACKET_INTERVAL {
+        if next_dsn >= self.last_ack_dsn + self.light_ack_interval {
             self.last_ack_dsn = next_dsn;
             Some(next_dsn)
         } else {
@@ -135,6 +149,39 @@ impl AckHistoryWindow {
     }
 }
 
+#[derive(Debug, Eq, PartialEq)]
+pub enum SequenceClass {
+    /// Close enough to the expected sequence number to be new data, a loss, a retransmission or a
+    /// duplicate
+    InWindow,
+    /// Too far from the expected sequence number to belong to the current stream, the sender has
+    /// restarted with a new initial sequence number
+    Restart,
+}
+
+/// Classifies the sequence numbers of incoming DATA packets relative to the next expected one
+#[derive(Debug)]
+pub struct SequenceHistoryWindow {
+    window: u32,
+}
+
+impl SequenceHistoryWindow {
+    /// A window of zero packets disables restart detection
+    pub fn new(window: PacketCount) -> Self {
+        Self {
+            window: u32::try_from(window.0).unwrap_or(SeqNumber::MAX_DIFF),
+        }
+    }
+
+    pub fn classify(&self, expected: SeqNumber, seq_number: SeqNumber) -> SequenceClass {
+        if self.window > 0 && seq_number.distance(expected) > self.window {
+            SequenceClass::Restart
+        } else {
+            SequenceClass::InWindow
+        }
+    }
+}
+
 #[cfg(test)]
 mod ack_history_window {
     use super::*;
@@ -158,6 +205,30 @@ mod ack_history_window {
         }
     }
 
+    #[test]
+    fn light_ack_interval() {
+        let initial_dsn = SeqNumber(1);
+        let mut window = AckHistoryWindow::new(Duration::from_secs(1), initial_dsn);
+        window.set_light_ack_interval(PacketCount(1));
+
+        // every packet is acknowledged
+        for i in 1..10 {
+            assert_eq!(
+                window.next_light_ack(initial_dsn + i),
+                Some(initial_dsn + i)
+            );
+        }
+        // nothing new to acknowledge
+        assert_eq!(window.next_light_ack(initial_dsn + 9), None);
+
+        window.set_light_ack_interval(PacketCount(4));
+        assert_eq!(window.next_light_ack(initial_dsn + 12), None);
+        assert_eq!(
+            window.next_light_ack(initial_dsn + 13),
+            Some(initial_dsn + 13)
+        );
+    }
+
     #[test]
     fn full_ack() {
         let start = Instant::now();
@@ -234,3 +305,30 @@ mod ack_history_window {
         assert_eq!(window.next_light_ack(next_dsn), None);
     }
 }
+
+#[cfg(test)]
+mod sequence_history_window {
+    use super::*;
+
+    use SequenceClass::*;
+
+    #[test]
+    fn classify() {
+        let expected = SeqNumber(100);
+
+        let disabled = SequenceHistoryWindow::new(PacketCount(0));
+        assert_eq!(disabled.classify(expected, expected + 1_000_000), InWindow);
+
+        let window = SequenceHistoryWindow::new(PacketCount(1000));
+        assert_eq!(window.classify(expected, expected), InWindow);
+        assert_eq!(window.classify(expected, expected + 1000), InWindow);
+        assert_eq!(window.classify(expected, expected - 1000), InWindow);
+        assert_eq!(window.classify(expected, expected + 1001), Restart);
+        assert_eq!(window.classify(expected, expected - 1001), Restart);
+
+        // the window spans the sequence number wrap around
+        let expected = SeqNumber(SeqNumber::MAX - 10);
+        assert_eq!(window.classify(expected, expected + 500), InWindow);
+        assert_eq!(window.classify(expected, SeqNumber(5_000)), Restart);
+    }
+}
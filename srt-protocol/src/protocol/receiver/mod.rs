@@ -3,22 +3,20 @@ mod buffer;
 mod history;
 mod time;
 
-use std::{
-    ops::RangeInclusive,
-    time::{Duration, Instant},
-};
+use std::{ops::RangeInclusive, time::Duration};
 
 use arq::AutomaticRepeatRequestAlgorithm;
 
 use crate::{
-    connection::ConnectionSettings,
+    connection::{snapshot::ReceiverSnapshot, ConnectionSettings},
     packet::*,
     protocol::{
-        encryption::{Decryption, DecryptionError},
+        encryption::{Decryption, DecryptionError, KeyMaterialState},
         output::Output,
+        time::Instant,
         time::Timers,
     },
-    statistics::SocketStatistics,
+    statistics::{MemoryUsage, SocketStatistics},
 };
 
 #[derive(Debug, Eq, PartialEq)]
@@ -63,18 +61,41 @@ pub enum DataPacketAction {
 pub struct Receiver {
     pub arq: AutomaticRepeatRequestAlgorithm,
     pub decryption: Decryption,
+    periodic_nak: bool,
+    // report overdue losses as data packets arrive, rather than on the NAK timer
+    immediate_nak: bool,
+    // the peer marks retransmitted packets, rather than using the bit for the message number
+    retransmit_flag: bool,
+    // Congestion Experienced marks not reported to the sender yet
+    congestion_experienced: u32,
 }
 
 impl Receiver {
     pub fn new(settings: ConnectionSettings) -> Self {
+        let mut arq = AutomaticRepeatRequestAlgorithm::new(
+            settings.socket_start_time,
+            // without TSBPD messages are released as soon as they're complete and in order
+            if settings.receives_with_tsbpd() {
+                settings.recv_tsbpd_latency
+            } else {
+                Duration::ZERO
+            },
+            settings.init_seq_num,
+            settings.recv_buffer_size,
+            settings.sequence_restart_window,
+            settings.skip_gaps,
+            settings.receiver_drops_too_late(),
+        );
+        arq.set_retransmit_flag(settings.peer_supports_retransmit_flag());
+        arq.set_light_ack_interval(settings.light_ack_interval);
+        arq.set_stream_mode(settings.stream_mode());
         Self {
-            arq: AutomaticRepeatRequestAlgorithm::new(
-                settings.socket_start_time,
-                settings.recv_tsbpd_latency,
-                settings.init_seq_num,
-                settings.recv_buffer_size,
-            ),
+            arq,
+            periodic_nak: settings.sends_periodic_nak(),
+            immediate_nak: settings.immediate_nak && settings.sends_periodic_nak(),
+            retransmit_flag: settings.peer_supports_retransmit_flag(),
             decryption: Decryption::new(settings.cipher),
+            congestion_experienced: 0,
         }
     }
 
@@ -85,6 +106,46 @@ impl Receiver {
     pub fn rx_acknowledged_time(&self) -> Duration {
         self.arq.rx_acknowledged_time()
     }
+
+    pub fn rx_acknowledged_packets(&self) -> u64 {
+        u64::try_from(self.arq.rx_acknowledged_packets()).unwrap()
+    }
+
+    pub fn rx_buffered_packets(&self) -> u64 {
+        u64::try_from(self.arq.rx_buffered_packets()).unwrap()
+    }
+
+    pub fn key_material_state(&self) -> KeyMaterialState {
+        self.decryption.key_material_state()
+    }
+
+    pub fn memory_usage(&self, usage: &mut MemoryUsage) {
+        self.arq.memory_usage(usage);
+    }
+
+    pub(crate) fn snapshot(&self) -> ReceiverSnapshot {
+        let (first_sequence_number, buffer) = self.arq.snapshot();
+        ReceiverSnapshot {
+            first_sequence_number,
+            buffer,
+            keys: self.decryption.stream_keys().cloned(),
+        }
+    }
+
+    /// Pick up where the receiver of a snapshot left off, with the packets it hadn't released yet
+    pub(crate) fn restore(
+        mut settings: ConnectionSettings,
+        now: Instant,
+        snapshot: ReceiverSnapshot,
+    ) -> Self {
+        settings.init_seq_num = snapshot.first_sequence_number;
+        if let (Some(cipher), Some(keys)) = (&mut settings.cipher, snapshot.keys) {
+            cipher.stream_keys = keys;
+        }
+        let mut receiver = Self::new(settings);
+        receiver.arq.restore(now, snapshot.buffer);
+        receiver
+    }
 }
 
 pub struct ReceiverContext<'a> {
@@ -122,6 +183,7 @@ impl<'a> ReceiverContext<'a> {
         let bytes = data.wire_size() as u64;
         self.stats.rx_data += 1;
         self.stats.rx_bytes += bytes;
+        let retransmitted = self.receiver.retransmit_flag && data.retransmitted;
 
         let data = self
             .receiver
@@ -137,9 +199,11 @@ impl<'a> ReceiverContext<'a> {
 
         match data {
             Ok(action) => {
-                if action.is_recovered() {
+                if retransmitted || action.is_recovered() {
                     self.stats.rx_retransmit_data += 1;
-                } else {
+                    self.stats.rx_retransmit_delay = self.receiver.arq.rx_retransmit_delay();
+                }
+                if !action.is_recovered() {
                     self.stats.rx_unique_data += 1;
                     self.stats.rx_unique_bytes += bytes;
                 }
@@ -166,19 +230,42 @@ impl<'a> ReceiverContext<'a> {
                         self.stats.rx_decrypt_errors += 1;
                         self.stats.rx_decrypt_error_bytes += bytes;
                     }
-                    DiscardedDuplicate { .. } => {}
+                    // the original or an earlier retransmission made it after all
+                    DiscardedDuplicate { .. } => {
+                        if retransmitted {
+                            self.stats.rx_retransmit_data += 1;
+                        }
+                    }
                 }
             }
         }
+
+        if self.receiver.immediate_nak {
+            if let Some(loss_list) = self.receiver.arq.overdue_loss_list(now) {
+                self.output.send_control(now, Nak(loss_list));
+            }
+        }
+
+        self.update_gauges();
+    }
+
+    /// Data packets that arrived with an ECN Congestion Experienced mark, reported to the sender
+    /// on the next full ACK
+    pub fn handle_congestion_experienced(&mut self, packets: u32) {
+        self.stats.rx_congestion_experienced += u64::from(packets);
+        self.receiver.congestion_experienced =
+            self.receiver.congestion_experienced.saturating_add(packets);
     }
 
     pub fn handle_ack2_packet(&mut self, now: Instant, seq_num: FullAckSeqNumber) {
         self.stats.rx_ack2 += 1;
         let rtt = self.receiver.arq.handle_ack2_packet(now, seq_num);
-        if let Some(rtt) = rtt {
-            self.timers.update_rtt(rtt);
-            //self.warn("ack not found", now, &seq_num);
-            self.stats.rx_ack2_errors += 1;
+        match rtt {
+            Some(rtt) => self.timers.update_rtt(rtt),
+            None => {
+                //self.warn("ack not found", now, &seq_num);
+                self.stats.rx_ack2_errors += 1;
+            }
         }
     }
 
@@ -189,6 +276,8 @@ impl<'a> ReceiverContext<'a> {
             //self.warn("packets dropped", now, &(dropped, drop));
             self.stats.rx_dropped_data += dropped;
         }
+
+        self.update_gauges();
     }
 
     pub fn handle_key_refresh_request(
@@ -224,9 +313,20 @@ impl<'a> ReceiverContext<'a> {
             // receiver buffer size).
             self.output.send_control(now, ControlTypes::Ack(ack));
         }
+        // echo the marks along with the ACKs, like a TCP receiver sets ECE
+        if self.receiver.congestion_experienced > 0 {
+            let packets = std::mem::take(&mut self.receiver.congestion_experienced);
+            self.output.send_control(
+                now,
+                ControlTypes::Srt(SrtControlPacket::CongestionExperienced(packets)),
+            );
+        }
     }
 
     pub fn on_nak_event(&mut self, now: Instant) {
+        if !self.receiver.periodic_nak {
+            return;
+        }
         if let Some(loss_list) = self.receiver.arq.on_nak_event(now) {
             self.output.send_control(now, ControlTypes::Nak(loss_list));
         }
@@ -234,7 +334,19 @@ impl<'a> ReceiverContext<'a> {
 
     pub fn on_close_timeout(&mut self, _now: Instant) {
         //self.debug("timed out", now, &self.receiver.arq);
-        self.receiver.arq.clear()
+        self.receiver.arq.clear();
+        self.update_gauges();
+    }
+
+    pub fn update_gauges(&mut self) {
+        self.stats.rx_acknowledged_time = self.receiver.rx_acknowledged_time();
+        self.stats.rx_acknowledged_data = self.receiver.rx_acknowledged_packets();
+        self.stats.rx_buffered_data = self.receiver.rx_buffered_packets();
+        self.stats.rx_delivery_jitter = self.receiver.arq.rx_delivery_jitter();
+        self.stats.rx_interarrival_jitter = self.receiver.arq.rx_interarrival_jitter();
+        let rtt = self.receiver.arq.rtt();
+        self.stats.rx_average_rtt = rtt.mean_as_duration();
+        self.stats.rx_rtt_variance = rtt.variance_as_duration();
     }
 }
 
@@ -1,14 +1,15 @@
-use std::time::Instant;
+use std::time::Duration;
 
 use stats::OnlineStats;
 
 use crate::{
     packet::{TimeSpan, TimeStamp},
-    protocol::time::TimeBase,
+    protocol::time::{Instant, TimeBase},
 };
 
 #[derive(Debug)]
 pub struct SynchronizedRemoteClock {
+    socket_start_time: Instant,
     drift_deviation_tolerance: TimeSpan,
     time_base: TimeBase,
     last_monotonic_instant: Option<Instant>,
@@ -31,6 +32,7 @@ impl SynchronizedRemoteClock {
             //       It wasn't in the reference implementation, but I added it because the reference
             //       implementation is susceptible to invalid clock adjustments during periods of
             //       acute network latency
+            socket_start_time: now,
             drift_deviation_tolerance: Self::DRIFT_DEVIATION_TOLERANCE,
             time_base: TimeBase::new(now),
             last_monotonic_instant: None,
@@ -80,6 +82,52 @@ impl SynchronizedRemoteClock {
     pub fn instant_from(&self, ts: TimeStamp) -> Instant {
         self.time_base.instant_from(ts)
     }
+
+    /// The peer's timestamps minus ours, once the first packet set the time base. It includes
+    /// the delay on the way here, the peer stamps its packets that much before they arrive.
+    pub fn offset(&self) -> Option<TimeSpan> {
+        self.stats.as_ref()?;
+        Some(self.time_base.timestamp_from(self.socket_start_time) - TimeStamp::MIN)
+    }
+}
+
+/// A jitter estimate, smoothed the same way as the interarrival jitter of RFC 3550:
+/// J += (|D| - J) / 16
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Jitter(Duration);
+
+impl Jitter {
+    pub fn add(&mut self, deviation: Duration) {
+        self.0 = (self.0 * 15 + deviation) / 16;
+    }
+
+    pub fn get(&self) -> Duration {
+        self.0
+    }
+}
+
+/// The interarrival jitter of RFC 3550 section 6.4.1: how much the transit time of consecutive
+/// packets varies, from their arrival times and the timestamps the sender gave them
+#[derive(Debug, Default)]
+pub struct InterarrivalJitter {
+    last: Option<(Instant, TimeStamp)>,
+    jitter: Jitter,
+}
+
+impl InterarrivalJitter {
+    pub fn record_data_packet(&mut self, now: Instant, ts: TimeStamp) {
+        if let Some((last_arrival, last_ts)) = self.last.replace((now, ts)) {
+            // D(i-1, i) = (R_i - R_i-1) - (S_i - S_i-1)
+            let received = now.saturating_duration_since(last_arrival).as_micros() as i64;
+            let sent = i64::from((ts - last_ts).as_micros());
+            self.jitter
+                .add(Duration::from_micros((received - sent).unsigned_abs()));
+        }
+    }
+
+    pub fn jitter(&self) -> Duration {
+        self.jitter.get()
+    }
 }
 
 #[cfg(test)]
@@ -141,6 +189,27 @@ mod synchronized_remote_clock {
         }
     }
 
+    #[test]
+    fn offset() {
+        let start = Instant::now();
+        let start_ts = TimeStamp::from_micros(5_000_000);
+        let mut clock = SynchronizedRemoteClock::new(start);
+        assert_eq!(clock.offset(), None);
+
+        // the peer's clock is 5s ahead, less the 10ms its first packet took to get here
+        let arrival = start + Duration::from_millis(10);
+        clock.synchronize(arrival, start_ts);
+        let offset = TimeSpan::from_millis(5_000 - 10);
+        assert_eq!(clock.offset(), Some(offset));
+
+        // then its packets take a millisecond longer
+        for tick in 1..=SynchronizedRemoteClock::MAX_SAMPLES as u64 {
+            let now = arrival + Duration::from_millis(tick + 1);
+            clock.synchronize(now, start_ts + Duration::from_millis(tick));
+        }
+        assert_eq!(clock.offset(), Some(offset - TimeSpan::from_millis(1)));
+    }
+
     proptest! {
         #[test]
         fn monotonic_instant(drift_micros: i32) {
@@ -166,3 +235,33 @@ mod synchronized_remote_clock {
         }
     }
 }
+
+#[cfg(test)]
+mod interarrival_jitter {
+    use super::*;
+
+    #[test]
+    fn interarrival_jitter() {
+        let start = Instant::now();
+        let mut jitter = InterarrivalJitter::default();
+
+        // sent and received at a steady pace, the delay doesn't matter
+        for i in 0..10 {
+            let now = start + Duration::from_millis(50 + i * 10);
+            jitter.record_data_packet(now, TimeStamp::from_micros(i as u32 * 10_000));
+        }
+        assert_eq!(jitter.jitter(), Duration::ZERO);
+
+        // one packet 6ms late, then back on time
+        let late = start + Duration::from_millis(50 + 10 * 10 + 6);
+        jitter.record_data_packet(late, TimeStamp::from_micros(100_000));
+        let expected = Duration::from_millis(6) / 16;
+        assert_eq!(jitter.jitter(), expected);
+        let on_time = start + Duration::from_millis(50 + 11 * 10);
+        jitter.record_data_packet(on_time, TimeStamp::from_micros(110_000));
+        assert_eq!(
+            jitter.jitter(),
+            (expected * 15 + Duration::from_millis(6)) / 16
+        );
+    }
+}
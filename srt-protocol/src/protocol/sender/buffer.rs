@@ -1,7 +1,8 @@
 use std::{
     cmp::{max, Reverse},
-    collections::{BTreeSet, VecDeque},
+    collections::VecDeque,
     convert::TryFrom,
+    mem::size_of,
     ops::Range,
     time::Duration,
 };
@@ -10,27 +11,61 @@ use keyed_priority_queue::KeyedPriorityQueue;
 
 use crate::{
     connection::ConnectionSettings,
-    options::{ByteCount, PacketCount},
+    options::{ByteCount, SendBufferPolicy},
     packet::*,
-    protocol::time::{Rtt, Timers},
+    protocol::{
+        loss_list::LossList,
+        time::{Rtt, Timers},
+    },
+    statistics::MemoryUsage,
 };
 
 #[derive(Debug)]
 pub struct SendBuffer {
     latency_window: Duration,
+    // how long the peer holds packets for before it releases them
+    peer_latency: Duration,
+    // how far the peer's time base lags the timestamps, it's set by the arrival of the first
+    // packets, half the RTT of the handshake after they were sent
+    peer_base_offset: TimeSpan,
     flow_window_size: usize,
+    // the end of the space the receiver advertised in its last full ACK
+    peer_window_end: Option<SeqNumber>,
     buffer: VecDeque<SendBufferEntry>,
     max_buffer_size: usize,
+    max_buffer_bytes: Option<usize>,
+    max_buffer_time: Option<Duration>,
+    // otherwise new messages are only pushed while there is room, and never push out older ones
+    drop_oldest: bool,
+    // the wire size of a packet with the largest payload, what a new message needs room for
+    max_packet_wire_size: usize,
     buffer_len_bytes: usize, // Invariant: buffer_len_bytes = sum of wire sizes of buffer
+    // Invariant: unacked_bytes = sum of wire sizes of the packets of buffer before next_send
+    unacked_bytes: usize,
     next_send: SeqNumber,
     next_full_ack: FullAckSeqNumber,
     // 1) Sender's Loss List: The sender's loss list is used to store the
     //    sequence numbers of the lost packets fed back by the receiver
     //    through NAK packets or inserted in a timeout event. The numbers
     //    are stored in increasing order.
-    lost_list: BTreeSet<SeqNumber>,
+    lost_list: LossList,
     rtt: Rtt,
     rto_queue: KeyedPriorityQueue<SeqNumber, Reverse<(TimeStamp, SeqNumber)>>,
+    duplicate_interval: Option<Duration>,
+    // packets sent for the first time and when to send their copy, in order
+    duplicate_queue: VecDeque<(TimeStamp, SeqNumber)>,
+    // see options::Sender::fast_retransmit, how many ACKs in a row repeated the last ACK number
+    fast_retransmit: Option<u32>,
+    last_ack_number: Option<SeqNumber>,
+    duplicate_acks: u32,
+    // only peers that support it get the retransmitted flag set
+    retransmit_flag: bool,
+    // packets too late to be delivered are only dropped if the peer drops them too
+    too_late_packet_drop: bool,
+    // the packets of messages dropped before the peer acknowledged them, so the drop requests
+    // answering NAKs for them carry the right message number
+    dropped_messages: VecDeque<(Range<SeqNumber>, MsgNumber)>,
+    dropped_message_count: u64,
 }
 
 #[derive(Debug)]
@@ -39,9 +74,13 @@ struct SendBufferEntry {
     // this is transmit count, including the one that may be lost
     // ie, the first time a packet is sent, this is one
     transmit_count: i32,
+    // when the packet was first sent, used to take RTT samples from light ACKs
+    first_sent: Option<TimeStamp>,
+    // when the time to live of the packet's message runs out
+    expires: Option<TimeStamp>,
 }
 
-type DroppedPackets = (PacketCount, ByteCount);
+type DroppedPackets = (Range<SeqNumber>, ByteCount);
 type PushDataResult = Result<(), DroppedPackets>;
 
 impl SendBuffer {
@@ -49,41 +88,65 @@ impl SendBuffer {
         Self {
             buffer: VecDeque::new(),
             buffer_len_bytes: 0,
+            unacked_bytes: 0,
             next_send: settings.init_seq_num,
             next_full_ack: FullAckSeqNumber::INITIAL,
-            lost_list: BTreeSet::new(),
+            lost_list: LossList::new(),
             flow_window_size: settings.max_flow_size.0 as usize,
+            peer_window_end: None,
             max_buffer_size: settings.send_buffer_size.0 as usize,
+            max_buffer_bytes: settings.send_buffer_bytes.map(|b| b.0 as usize),
+            max_buffer_time: settings.send_buffer_time,
+            drop_oldest: settings.send_buffer_policy == SendBufferPolicy::DropOldest,
+            max_packet_wire_size: DataPacket::HEADER_SIZE + settings.max_packet_size.0 as usize,
+            peer_latency: settings.send_tsbpd_latency,
+            peer_base_offset: TimeSpan::from_micros((settings.rtt.as_micros() / 2) as i32),
             latency_window: max(
                 settings.send_tsbpd_latency + settings.send_tsbpd_latency / 4, // 125% of TSBPD
                 Duration::from_secs(1),
             ),
             rtt: Rtt::default(),
             rto_queue: Default::default(),
+            duplicate_interval: settings.duplicate_interval,
+            duplicate_queue: VecDeque::new(),
+            fast_retransmit: settings.fast_retransmit,
+            last_ack_number: None,
+            duplicate_acks: 0,
+            retransmit_flag: settings.peer_supports_retransmit_flag(),
+            too_late_packet_drop: settings.sender_drops_too_late(),
+            dropped_messages: VecDeque::new(),
+            dropped_message_count: 0,
         }
     }
 
-    pub fn push_data(&mut self, packet: DataPacket) -> PushDataResult {
-        let result = if self.buffer.len() < self.max_buffer_size {
-            Ok(())
-        } else if let Some(entry) = self.buffer.pop_front() {
-            self.buffer_len_bytes -= entry.packet.wire_size();
-
-            // remove packet from lost list if we are dropping it
-            if self.lost_list.first() == Some(&entry.packet.seq_number) {
-                self.pop_lost_list();
-            }
-
-            Err((PacketCount(1), ByteCount(entry.packet.wire_size() as u64)))
-        } else {
-            Ok(())
-        };
+    /// Push a packet of a message that isn't sent anymore once `expires` has passed
+    pub fn push_data(&mut self, packet: DataPacket, expires: Option<TimeStamp>) -> PushDataResult {
+        let size = packet.wire_size();
+        let mut result = Ok(());
+        while self.drop_oldest && self.is_full(size, packet.timestamp) {
+            let Some((range, bytes)) = self.drop_front_message(packet.message_number) else {
+                break;
+            };
+            // the oldest messages go first, so the drops are contiguous
+            result = match result {
+                Ok(()) => Err((range, bytes)),
+                Err((dropped, total)) => {
+                    Err((dropped.start..range.end, ByteCount(total.0 + bytes.0)))
+                }
+            };
+        }
 
-        self.buffer_len_bytes += packet.wire_size();
+        self.buffer_len_bytes += size;
+        if packet.seq_number < self.next_send {
+            self.unacked_bytes += size;
+        }
         self.buffer.push_back(SendBufferEntry {
             packet,
             transmit_count: 0,
+            first_sent: None,
+            expires,
         });
+        self.debug_assert_invariants();
 
         result
     }
@@ -93,16 +156,24 @@ impl SendBuffer {
     }
 
     pub fn has_packets_to_send(&self) -> bool {
-        self.get(self.next_send).is_some() || !self.lost_list.is_empty()
+        self.get(self.next_send).is_some()
+            || !self.lost_list.is_empty()
+            || !self.duplicate_queue.is_empty()
     }
 
     pub fn duration(&self) -> Duration {
-        match (self.buffer.front(), self.buffer.back()) {
-            (Some(f), Some(l)) => Duration::from_micros(
-                u64::try_from((l.packet.timestamp - f.packet.timestamp).as_micros()).unwrap_or(0),
-            ),
-            _ => Duration::from_secs(0),
-        }
+        self.buffer
+            .back()
+            .map_or(Duration::ZERO, |l| self.span_to(l.packet.timestamp))
+    }
+
+    /// Whether a full sized packet could be pushed without dropping any others
+    pub fn has_room(&self) -> bool {
+        self.buffer.len() < self.max_buffer_size
+            && self
+                .max_buffer_bytes
+                .is_none_or(|max| self.buffer_len_bytes + self.max_packet_wire_size <= max)
+            && self.max_buffer_time.is_none_or(|max| self.duration() < max)
     }
 
     pub fn len(&self) -> usize {
@@ -113,58 +184,204 @@ impl SendBuffer {
         self.buffer_len_bytes
     }
 
+    pub fn unacked_len(&self) -> usize {
+        self.number_of_unacked_packets()
+    }
+
+    pub fn unacked_len_bytes(&self) -> usize {
+        self.unacked_bytes
+    }
+
+    // how long ago the oldest packet still waiting for an ACK was first sent
+    pub fn oldest_unacked_age(&self, ts_now: TimeStamp) -> Duration {
+        self.buffer
+            .front()
+            .and_then(|e| e.first_sent)
+            .and_then(|first_sent| u64::try_from((ts_now - first_sent).as_micros()).ok())
+            .map_or(Duration::ZERO, Duration::from_micros)
+    }
+
+    pub fn memory_usage(&self, usage: &mut MemoryUsage) {
+        let payloads: usize = self.buffer.iter().map(|e| e.packet.payload.len()).sum();
+        usage.send_buffer += self.buffer.capacity() * size_of::<SendBufferEntry>() + payloads;
+        usage.loss_lists += self.lost_list.memory_usage();
+        // the queue keeps a map of the keys next to the heap
+        usage.history += 2 * self.rto_queue.len() * size_of::<(TimeStamp, SeqNumber)>()
+            + self.duplicate_queue.capacity() * size_of::<(TimeStamp, SeqNumber)>()
+            + self.dropped_messages.capacity() * size_of::<(Range<SeqNumber>, MsgNumber)>();
+    }
+
+    /// The messages dropped from the buffer, in whole or in part, before the peer acknowledged them
+    pub fn dropped_message_count(&self) -> u64 {
+        self.dropped_message_count
+    }
+
+    pub fn rtt(&self) -> Rtt {
+        self.rtt
+    }
+
+    pub fn lost_list_len(&self) -> usize {
+        self.lost_list.len()
+    }
+
+    pub fn next_send_seq_number(&self) -> SeqNumber {
+        self.next_send
+    }
+
+    /// The packets in the buffer, oldest first, and the first of them that wasn't sent yet
+    pub fn snapshot(&self) -> (Vec<DataPacket>, SeqNumber) {
+        let packets = self.buffer.iter().map(|e| e.packet.clone()).collect();
+        (packets, self.next_send)
+    }
+
+    /// Refill an empty buffer from a snapshot. The packets that were sent before are retransmitted
+    /// once their RTO expires, unless they are acknowledged or NAKed first.
+    pub fn restore(&mut self, ts_now: TimeStamp, packets: Vec<DataPacket>, next_send: SeqNumber) {
+        let rto = self.rtt.mean() + 4 * self.rtt.variance() + 2 * Timers::SYN;
+        for packet in packets {
+            let seq_number = packet.seq_number;
+            let sent = seq_number < next_send;
+            if sent {
+                let _ = self
+                    .rto_queue
+                    .push(seq_number, Reverse((ts_now + rto, seq_number)));
+            }
+            self.buffer_len_bytes += packet.wire_size();
+            if seq_number < next_send {
+                self.unacked_bytes += packet.wire_size();
+            }
+            self.buffer.push_back(SendBufferEntry {
+                packet,
+                transmit_count: i32::from(sent),
+                first_sent: sent.then_some(ts_now),
+                expires: None,
+            });
+        }
+        self.next_send = next_send;
+        self.debug_assert_invariants();
+    }
+
     pub fn update_largest_acked_seq_number(
         &mut self,
         ack_number: SeqNumber,
         full_ack: Option<FullAckSeqNumber>,
         rtt: Option<Rtt>,
     ) -> Result<AckAction, AckError> {
-        use AckError::*;
-        let first = self.front_packet().ok_or(SendBufferEmpty)?;
-        let next = self.next_send;
-        if ack_number < first || ack_number > next {
-            return Err(InvalidAck {
-                ack_number,
-                first,
-                next,
-            });
-        }
+        self.check_ack(ack_number, full_ack, rtt)?;
 
         if let Some(rtt) = rtt {
             self.rtt = rtt;
         }
-
         if let Some(received_full_ack) = full_ack {
-            if received_full_ack < self.next_full_ack {
-                return Err(InvalidFullAck {
-                    received_full_ack,
-                    next_full_ack: self.next_full_ack,
-                });
-            }
             self.next_full_ack = received_full_ack + 1;
         }
 
-        let mut recovered = 0;
+        let recovered = self.lost_list.remove_before(ack_number) as u64;
         let mut received = 0;
-        while self.peek_next_lost(ack_number).is_some() {
-            let _ = self.pop_lost_list();
-            recovered += 1;
-        }
 
         while self.front_packet().filter(|f| *f < ack_number).is_some() {
-            let p = self.pop_front().unwrap();
-            self.buffer_len_bytes = self.buffer_len_bytes.saturating_sub(p.packet.wire_size());
+            let _ = self.pop_front().unwrap();
 
             received += 1;
         }
+        while let Some((packets, _)) = self.dropped_messages.front() {
+            if packets.end > ack_number {
+                break;
+            }
+            let _ = self.dropped_messages.pop_front();
+        }
+        let fast_retransmit = self.count_duplicate_ack(ack_number);
+        self.debug_assert_invariants();
 
         Ok(AckAction {
             received,
             recovered,
             send_ack2: full_ack,
+            fast_retransmit,
         })
     }
 
+    // The packet an ACK number stops at is taken as lost once enough ACKs in a row repeat it,
+    // only once, later ones are left to NAKs and the retransmission timeout
+    fn count_duplicate_ack(&mut self, ack_number: SeqNumber) -> Option<SeqNumber> {
+        let threshold = self.fast_retransmit?;
+        let repeated = self.last_ack_number.replace(ack_number) == Some(ack_number);
+        let outstanding = ack_number < self.next_send && self.front_packet() == Some(ack_number);
+        if !repeated || !outstanding {
+            self.duplicate_acks = 0;
+            return None;
+        }
+        self.duplicate_acks += 1;
+        if self.duplicate_acks != threshold || self.lost_list.contains(ack_number) {
+            return None;
+        }
+        self.lost_list.insert(ack_number);
+        Some(ack_number)
+    }
+
+    /// Whether an ACK fits what was sent: it doesn't acknowledge packets that weren't sent yet,
+    /// go back on an earlier one, or carry an RTT the peer can't have measured
+    pub fn check_ack(
+        &self,
+        ack_number: SeqNumber,
+        full_ack: Option<FullAckSeqNumber>,
+        rtt: Option<Rtt>,
+    ) -> Result<(), AckError> {
+        use AckError::*;
+        let next = self.next_send;
+        let first = self.front_packet().unwrap_or(next);
+        if ack_number < first || ack_number > next {
+            return Err(InvalidAck {
+                ack_number,
+                first,
+                next,
+            });
+        }
+        match (full_ack, rtt) {
+            (Some(received_full_ack), _) if received_full_ack < self.next_full_ack => {
+                Err(InvalidFullAck {
+                    received_full_ack,
+                    next_full_ack: self.next_full_ack,
+                })
+            }
+            (_, Some(rtt)) if !rtt.is_plausible() => Err(ImplausibleRtt(rtt)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether a NAK fits what was sent: it only reports packets that were sent, and not ones
+    /// from more than a flow window before the oldest still waiting for an ACK, which would also
+    /// take a long time to go through
+    pub fn check_loss_report(&self, nak: &CompressedLossList) -> bool {
+        let next = self.next_send;
+        let oldest = self.front_packet().unwrap_or(next) - self.flow_window_size as u32;
+        nak.iter_ranges()
+            .all(|range| oldest <= range.start && range.start < range.end && range.end <= next)
+    }
+
+    /// The receive buffer space a full ACK advertised, in packets from its ACK number on. New
+    /// packets aren't sent past it until a later full ACK makes room.
+    pub fn update_peer_buffer_available(&mut self, ack_number: SeqNumber, available: u32) {
+        self.peer_window_end = Some(ack_number + available);
+    }
+
+    // A light ACK is sent by the receiver as soon as it receives the packet before ack_number, so
+    // the time since that packet was sent is an RTT sample, as long as it was only sent once.
+    pub fn sample_light_ack_rtt(
+        &mut self,
+        ts_now: TimeStamp,
+        ack_number: SeqNumber,
+    ) -> Option<Rtt> {
+        let entry = self.get(ack_number - 1)?;
+        let first_sent = entry.first_sent.filter(|_| entry.transmit_count == 1)?;
+        let sample = ts_now - first_sent;
+        if sample < TimeSpan::ZERO {
+            return None;
+        }
+        self.rtt.update(sample);
+        Some(self.rtt)
+    }
+
     pub fn add_to_loss_list(
         &mut self,
         nak: CompressedLossList,
@@ -187,7 +404,12 @@ impl SendBuffer {
 
     fn send_next_packet(&mut self, ts_now: TimeStamp) -> Option<DataPacket> {
         let packet_to_send = self.send_packet(ts_now, self.next_send)?;
+        if let Some(interval) = self.duplicate_interval {
+            self.duplicate_queue
+                .push_back((ts_now + interval, self.next_send));
+        }
         self.next_send += 1; // increment after send_packet, which can return None
+        self.unacked_bytes += packet_to_send.wire_size();
         Some(packet_to_send)
     }
 
@@ -218,6 +440,21 @@ impl SendBuffer {
         self.send_packet(ts_now, next_rto)
     }
 
+    // the copy doesn't count as a transmission, it's meant to make retransmissions unnecessary
+    fn send_next_duplicate_packet(&mut self, ts_now: TimeStamp) -> Option<DataPacket> {
+        while let Some(&(due, seq_number)) = self.duplicate_queue.front() {
+            if due > ts_now {
+                return None;
+            }
+            let _ = self.duplicate_queue.pop_front();
+            // acknowledged or dropped in the meantime
+            if let Some(entry) = self.get(seq_number).filter(|e| !e.is_expired(ts_now)) {
+                return Some(entry.packet.clone());
+            }
+        }
+        None
+    }
+
     // All packets that are sent go through this function
     // It records transmit count and sets the per entry RTO timer
     fn send_packet(&mut self, ts_now: TimeStamp, seq_number: SeqNumber) -> Option<DataPacket> {
@@ -239,13 +476,17 @@ impl SendBuffer {
         // clone packet first, then update retransmitted flag
         // this way, only the first will have it as false
         let packet = entry.packet.clone();
-        entry.packet.retransmitted = true;
+        entry.packet.retransmitted = self.retransmit_flag;
         entry.transmit_count += 1;
+        entry.first_sent.get_or_insert(ts_now);
 
         Some(packet)
     }
 
     fn drop_too_late_packets(&mut self, ts_now: TimeStamp) -> Option<Range<SeqNumber>> {
+        if !self.too_late_packet_drop {
+            return None;
+        }
         let latency_window = self.latency_window;
         let front = &self
             .buffer
@@ -267,34 +508,163 @@ impl SendBuffer {
             }
         }
 
-        let drop_range = first..last + 1;
-
         let count = last - first + 1;
-        let _ = self.buffer.drain(0..count as usize).count();
+        self.drop_front(count as usize).map(|(range, _)| range)
+    }
 
-        // remove any lost packets from loss list
-        while let Some(&seq) = self.lost_list.iter().next() {
-            if drop_range.contains(&seq) {
-                self.lost_list.remove(&seq);
-            } else {
-                break;
+    // Like libsrt, the time to live of a message is only checked when one of its packets is about
+    // to be sent, so a message that made it to the peer in time isn't dropped. Its packets stay
+    // in the buffer until the peer acknowledges past them, they just aren't sent anymore.
+    fn drop_expired_message(&mut self, ts_now: TimeStamp) -> Option<(MsgNumber, Range<SeqNumber>)> {
+        let next_rto = self
+            .rto_queue
+            .peek()
+            .filter(|(_, rto)| rto.0 .0 < ts_now)
+            .map(|(seq_number, _)| *seq_number);
+        let seq_number = [self.lost_list.first(), next_rto, Some(self.next_send)]
+            .into_iter()
+            .flatten()
+            .find(|seq_number| self.get(*seq_number).is_some_and(|e| e.is_expired(ts_now)))?;
+        self.drop_message(seq_number)
+    }
+
+    // With too-late packet drop the peer skips a packet that isn't there by its TSBPD time, so
+    // retransmitting a lost packet that can't get there before then only takes bandwidth away
+    // from the ones that still can, when losses come in bursts. Its message is dropped instead.
+    fn drop_late_lost_message(
+        &mut self,
+        ts_now: TimeStamp,
+    ) -> Option<(MsgNumber, Range<SeqNumber>)> {
+        if !self.too_late_packet_drop {
+            return None;
+        }
+        let seq_number = self.lost_list.first()?;
+        // the peer's TSBPD time for the packet, and when it would get there
+        let timestamp = self.get(seq_number)?.packet.timestamp;
+        let deadline = timestamp + self.peer_base_offset + self.peer_latency;
+        let one_way = TimeSpan::from_micros(self.rtt.mean().as_micros() / 2);
+        if ts_now + one_way <= deadline {
+            return None;
+        }
+        self.drop_message(seq_number)
+    }
+
+    // the packets of the message the packet belongs to aren't sent anymore
+    fn drop_message(&mut self, seq_number: SeqNumber) -> Option<(MsgNumber, Range<SeqNumber>)> {
+        let front = self.front_packet()?;
+        let index = (seq_number - front) as usize;
+        let message = self.buffer[index].packet.message_number;
+        let of_message = |e: &&SendBufferEntry| e.packet.message_number == message;
+        let start = index
+            - self
+                .buffer
+                .range(..index)
+                .rev()
+                .take_while(of_message)
+                .count();
+        let end = index + self.buffer.range(index..).take_while(of_message).count();
+        let range = front + start as u32..front + end as u32;
+
+        for offset in 0..end - start {
+            let _ = self.rto_queue.remove(&(range.start + offset as u32));
+        }
+        self.lost_list.remove_range(range.clone());
+        self.skip_to(range.end);
+        self.debug_assert_invariants();
+        Some((message, range))
+    }
+
+    fn is_full(&self, pushing_bytes: usize, pushing: TimeStamp) -> bool {
+        self.buffer.len() >= self.max_buffer_size
+            || self
+                .max_buffer_bytes
+                .is_some_and(|max| self.buffer_len_bytes + pushing_bytes > max)
+            || self
+                .max_buffer_time
+                .is_some_and(|max| self.span_to(pushing) > max)
+    }
+
+    // the time between the oldest packet's source time and `timestamp`
+    fn span_to(&self, timestamp: TimeStamp) -> Duration {
+        self.buffer.front().map_or(Duration::ZERO, |f| {
+            Duration::from_micros(
+                u64::try_from((timestamp - f.packet.timestamp).as_micros()).unwrap_or(0),
+            )
+        })
+    }
+
+    // Once part of a message is gone the rest is useless to the peer, so room is made for new
+    // packets by dropping the oldest message as a whole. A message larger than the buffer can
+    // only push out its own start though, one packet at a time.
+    fn drop_front_message(&mut self, pushing: MsgNumber) -> Option<DroppedPackets> {
+        let message = self.buffer.front()?.packet.message_number;
+        let count = if message == pushing {
+            1
+        } else {
+            self.buffer
+                .iter()
+                .take_while(|e| e.packet.message_number == message)
+                .count()
+        };
+        self.drop_front(count)
+    }
+
+    // drops the first count packets, whether they were sent yet or not
+    fn drop_front(&mut self, count: usize) -> Option<DroppedPackets> {
+        let first = self.front_packet()?;
+        let mut bytes = 0;
+        for entry in self.buffer.drain(..count) {
+            let packet = entry.packet;
+            let seq_number = packet.seq_number;
+            let _ = self.rto_queue.remove(&seq_number);
+            bytes += packet.wire_size();
+            if seq_number < self.next_send {
+                self.unacked_bytes -= packet.wire_size();
+            }
+            match self.dropped_messages.back_mut() {
+                Some((packets, message))
+                    if *message == packet.message_number && packets.end == seq_number =>
+                {
+                    packets.end = seq_number + 1;
+                }
+                _ => {
+                    self.dropped_messages
+                        .push_back((seq_number..seq_number + 1, packet.message_number));
+                    self.dropped_message_count += 1;
+                }
             }
         }
+        // the peer may never acknowledge past them if it went away
+        while self.dropped_messages.len() > self.max_buffer_size {
+            let _ = self.dropped_messages.pop_front();
+        }
+        self.buffer_len_bytes -= bytes;
+
+        let drop_range = first..first + count as u32;
+        // remove any lost packets from loss list
+        self.lost_list.remove_range(drop_range.clone());
+
+        self.skip_to(drop_range.end);
+        self.debug_assert_invariants();
+        Some((drop_range, ByteCount(bytes as u64)))
+    }
 
-        self.next_send = max(self.next_send, last + 1);
-        Some(drop_range)
+    // the number of the message a dropped packet belonged to, if it is still known
+    fn dropped_message_number(&self, seq_number: SeqNumber) -> Option<MsgNumber> {
+        let index = self
+            .dropped_messages
+            .partition_point(|(packets, _)| packets.end <= seq_number);
+        self.dropped_messages
+            .get(index)
+            .filter(|(packets, _)| packets.contains(&seq_number))
+            .map(|(_, message)| *message)
     }
 
     fn flush_on_close(&mut self, should_drain: bool) -> Option<DataPacket> {
         if should_drain && self.buffer.len() == 1 {
             // self.next_send = None; TODO: i'm not sure what functionality this was supposed to expose
 
-            let p = self.pop_front().map(|p| p.packet);
-            // This needs to be saturating because of the hack in Self::push_data, can be regular subtract otherwise
-            self.buffer_len_bytes = self
-                .buffer_len_bytes
-                .saturating_sub(p.as_ref().unwrap().wire_size());
-            p
+            self.pop_front().map(|p| p.packet)
         } else {
             None
         }
@@ -302,6 +672,9 @@ impl SendBuffer {
 
     fn flow_window_exceeded(&self) -> bool {
         self.number_of_unacked_packets() > self.flow_window_size
+            || self
+                .peer_window_end
+                .is_some_and(|end| self.next_send >= end)
     }
 
     fn number_of_unacked_packets(&self) -> usize {
@@ -311,29 +684,73 @@ impl SendBuffer {
     }
 
     fn pop_lost_list(&mut self) -> Option<SeqNumber> {
-        let next = self.lost_list.iter().copied().next()?;
-        let _ = self.lost_list.remove(&next);
-        Some(next)
-    }
-
-    // find the first item that has a sequence number less than seq_num in the loss list
-    fn peek_next_lost(&self, seq_num: SeqNumber) -> Option<SeqNumber> {
-        self.lost_list.range(..seq_num).copied().next()
+        self.lost_list.pop_first()
     }
 
     fn pop_front(&mut self) -> Option<SendBufferEntry> {
         let entry = self.buffer.pop_front()?;
         let _ = self.rto_queue.remove(&entry.packet.seq_number);
+        // This needs to be saturating because of the hack in Self::push_data, can be regular subtract otherwise
+        let size = entry.packet.wire_size();
+        self.buffer_len_bytes = self.buffer_len_bytes.saturating_sub(size);
+        if entry.packet.seq_number < self.next_send {
+            self.unacked_bytes -= size;
+        }
         Some(entry)
     }
 
+    // the packets skipped over aren't sent anymore, but wait for an ACK like the sent ones
+    fn skip_to(&mut self, seq_number: SeqNumber) {
+        if seq_number <= self.next_send {
+            return;
+        }
+        if let Some(front) = self.front_packet() {
+            let start = self.next_send.max(front) - front;
+            let end = seq_number - front;
+            self.unacked_bytes += self
+                .buffer
+                .range(
+                    (start as usize).min(self.buffer.len())..(end as usize).min(self.buffer.len()),
+                )
+                .map(|e| e.packet.wire_size())
+                .sum::<usize>();
+        }
+        self.next_send = seq_number;
+    }
+
     fn get(&self, seq: SeqNumber) -> Option<&SendBufferEntry> {
         self.buffer.get((seq - self.front_packet()?) as usize)
     }
 
-    fn front_packet(&self) -> Option<SeqNumber> {
+    pub fn front_packet(&self) -> Option<SeqNumber> {
         self.buffer.front().map(|p| p.packet.seq_number)
     }
+
+    // Indexing into the buffer relies on the sequence numbers being consecutive, which has to
+    // hold across the sequence number wrap around.
+    fn debug_assert_invariants(&self) {
+        if let (Some(front), Some(back)) = (self.buffer.front(), self.buffer.back()) {
+            let (front, back) = (front.packet.seq_number, back.packet.seq_number);
+            // callers may leave gaps, but the sequence numbers never go backwards
+            let span = (back - front) as usize + 1;
+            debug_assert!(
+                span >= self.buffer.len() && span < SeqNumber::MAX_DIFF as usize,
+                "send buffer sequence numbers are out of order: {front}..={back}"
+            );
+        }
+        if let (Some(lost), Some(front)) = (self.lost_list.first(), self.front_packet()) {
+            debug_assert!(
+                lost >= front,
+                "lost packet {lost} is before the send buffer front {front}"
+            );
+        }
+    }
+}
+
+impl SendBufferEntry {
+    fn is_expired(&self, ts_now: TimeStamp) -> bool {
+        self.expires.is_some_and(|expires| ts_now > expires)
+    }
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -347,7 +764,7 @@ pub enum AckError {
         first: SeqNumber,
         next: SeqNumber,
     },
-    SendBufferEmpty,
+    ImplausibleRtt(Rtt),
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -355,12 +772,15 @@ pub struct AckAction {
     pub received: u64,
     pub recovered: u64,
     pub send_ack2: Option<FullAckSeqNumber>,
+    // the packet queued for retransmission on duplicate ACKs, see options::Sender::fast_retransmit
+    pub fast_retransmit: Option<SeqNumber>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Loss {
     Added,
-    Dropped,
+    // with the number of the message the packets belonged to, if it is still known
+    Dropped(Option<MsgNumber>),
     Ignored,
 }
 
@@ -380,8 +800,10 @@ where
         let next_send = self.buffer.next_send;
         self.loss_list.next().map(|next| match (front, next_send) {
             (_, next_send) if next >= next_send => (Ignored, next),
-            (Some(front), _) if next < front => (Dropped, next),
-            (None, _) => (Dropped, next),
+            (Some(front), _) if next < front => {
+                (Dropped(self.buffer.dropped_message_number(next)), next)
+            }
+            (None, _) => (Dropped(self.buffer.dropped_message_number(next)), next),
             (Some(_), _) => {
                 self.buffer.lost_list.insert(next);
                 (Added, next)
@@ -426,7 +848,13 @@ pub enum SenderAction {
     RetransmitRto(DataPacket),
     // Retransmission from NAK
     RetransmitNak(DataPacket),
+    // Copy sent without waiting for a NAK, see options::Sender::duplicate_interval
+    Duplicate(DataPacket),
     Drop(Range<SeqNumber>),
+    // the time to live of the message ran out before all of it was sent
+    DropExpired(MsgNumber, Range<SeqNumber>),
+    // a lost packet of the message wouldn't reach the peer in time if it was retransmitted
+    DropLate(MsgNumber, Range<SeqNumber>),
     WaitForInput,
     // sender flow window exceeded"
     WaitForAck {
@@ -474,6 +902,11 @@ impl<'a> SenderAlgorithmIterator<'a> {
         Some(SenderAction::RetransmitRto(p))
     }
 
+    fn duplicate(&mut self, p: DataPacket) -> Option<SenderAction> {
+        self.packets_to_send = self.packets_to_send.saturating_sub(1);
+        Some(SenderAction::Duplicate(p))
+    }
+
     fn wait_for_input(&mut self) -> Option<SenderAction> {
         self.packets_to_send = 0;
         Some(SenderAction::WaitForInput)
@@ -516,6 +949,10 @@ impl<'a> Iterator for SenderAlgorithmIterator<'a> {
         //      1).
         if let Some(range) = self.buffer.drop_too_late_packets(self.ts_now) {
             self.drop(range)
+        } else if let Some((message, range)) = self.buffer.drop_expired_message(self.ts_now) {
+            Some(SenderAction::DropExpired(message, range))
+        } else if let Some((message, range)) = self.buffer.drop_late_lost_message(self.ts_now) {
+            Some(SenderAction::DropLate(message, range))
         }
         //   1) If the sender's loss list is not empty, retransmit the first
         //      packet in the list and remove it from the list. Go to 5).
@@ -525,6 +962,8 @@ impl<'a> Iterator for SenderAlgorithmIterator<'a> {
             self.retransmit_nak(p)
         } else if let Some(p) = self.buffer.send_next_rto_packet(self.ts_now) {
             self.retransmit_rto(p)
+        } else if let Some(p) = self.buffer.send_next_duplicate_packet(self.ts_now) {
+            self.duplicate(p)
         }
         //   4)
         //        a. If the number of unacknowledged packets exceeds the
@@ -571,7 +1010,7 @@ mod test {
     use assert_matches::assert_matches;
     use bytes::Bytes;
 
-    use crate::options::{PacketCount, PacketSize};
+    use crate::options::{Ack2Mode, PacketCount, PacketSize, PeerAddressPolicy, SrtVersion};
 
     const MILLIS: Duration = Duration::from_millis(1);
     const TSBPD: Duration = Duration::from_secs(2);
@@ -593,7 +1032,32 @@ mod test {
             bandwidth: Default::default(),
             recv_buffer_size: PacketCount(8196),
             send_buffer_size: PacketCount(8196),
+            send_buffer_bytes: None,
+            send_buffer_time: None,
+            send_buffer_policy: SendBufferPolicy::DropOldest,
             statistics_interval: Duration::from_secs(10),
+            peer_idle_timeout: Duration::from_secs(5),
+            ack2_mode: Ack2Mode::EveryFullAck,
+            lite_ack_rtt_sampling: false,
+            max_burst: PacketCount(0),
+            duplicate_interval: None,
+            fast_retransmit: None,
+            sequence_restart_window: PacketCount(0),
+            skip_gaps: false,
+            immediate_nak: false,
+            light_ack_interval: PacketCount(64),
+            max_delivery_rate: None,
+            half_close: false,
+            limit_payload_size: false,
+            keepalive_interval: Duration::from_secs(1),
+            adaptive_keepalive: false,
+            message_tags: false,
+            ecn: false,
+            peer_address_policy: PeerAddressPolicy::Rebind,
+            encrypt_control: false,
+            peer_version: SrtVersion::CURRENT,
+            peer_flags: SrtShakeFlags::SUPPORTED,
+            local_flags: SrtShakeFlags::SUPPORTED,
         }
     }
 
@@ -625,7 +1089,7 @@ mod test {
         let start = TimeStamp::MIN;
         let mut buffer = SendBuffer::new(&new_settings());
         for n in 0..=16u32 {
-            let _ = buffer.push_data(test_data_packet(n, false));
+            let _ = buffer.push_data(test_data_packet(n, false), None);
         }
 
         for n in 0..=16 {
@@ -649,7 +1113,7 @@ mod test {
         let mut buffer = SendBuffer::new(&new_settings());
 
         for n in 0..=13 {
-            let _ = buffer.push_data(test_data_packet(n, false));
+            let _ = buffer.push_data(test_data_packet(n, false), None);
         }
 
         let actions = buffer
@@ -697,7 +1161,7 @@ mod test {
         let mut buffer = SendBuffer::new(&new_settings());
 
         for n in 0..=2 {
-            let _ = buffer.push_data(test_data_packet(n, false));
+            let _ = buffer.push_data(test_data_packet(n, false), None);
         }
 
         assert_eq!(buffer.next_snd_actions(start, 3, false).count(), 3);
@@ -720,6 +1184,92 @@ mod test {
         );
     }
 
+    #[test]
+    fn duplicate_packets() {
+        use SenderAction::*;
+        let start = TimeStamp::MIN;
+        let mut buffer = SendBuffer::new(&ConnectionSettings {
+            duplicate_interval: Some(10 * MILLIS),
+            ..new_settings()
+        });
+        for n in 0..3 {
+            let _ = buffer.push_data(test_data_packet(n, false), None);
+        }
+
+        assert_eq!(
+            buffer.next_snd_actions(start, 3, false).collect::<Vec<_>>(),
+            vec![
+                send_data_packet(0),
+                send_data_packet(1),
+                send_data_packet(2)
+            ]
+        );
+        assert!(buffer.has_packets_to_send());
+        assert_eq!(
+            buffer
+                .next_snd_actions(start + 5 * MILLIS, 3, false)
+                .collect::<Vec<_>>(),
+            vec![WaitForInput]
+        );
+
+        // no copy of what was acknowledged by the time it's due
+        let _ = buffer.update_largest_acked_seq_number(SeqNumber(1), None, None);
+        assert_eq!(
+            buffer
+                .next_snd_actions(start + 10 * MILLIS, 3, false)
+                .collect::<Vec<_>>(),
+            vec![
+                Duplicate(test_data_packet(1, true)),
+                Duplicate(test_data_packet(2, true)),
+                WaitForInput
+            ]
+        );
+        assert!(!buffer.has_packets_to_send());
+    }
+
+    #[test]
+    fn fast_retransmit() {
+        use SenderAction::*;
+        let now = TimeStamp::MIN;
+        let mut buffer = SendBuffer::new(&ConnectionSettings {
+            fast_retransmit: Some(2),
+            ..new_settings()
+        });
+        for n in 0..6 {
+            let _ = buffer.push_data(test_data_packet(n, false), None);
+        }
+        let _ = buffer.next_snd_actions(now, 6, false).count();
+
+        let fast_retransmit = |buffer: &mut SendBuffer, ack| {
+            buffer
+                .update_largest_acked_seq_number(SeqNumber(ack), None, None)
+                .unwrap()
+                .fast_retransmit
+        };
+        // packet 1 is lost, the ACKs that follow stop short of it
+        assert_eq!(fast_retransmit(&mut buffer, 1), None);
+        assert_eq!(fast_retransmit(&mut buffer, 1), None);
+        assert_eq!(fast_retransmit(&mut buffer, 1), Some(SeqNumber(1)));
+        assert_eq!(
+            buffer.next_snd_actions(now, 1, false).collect::<Vec<_>>(),
+            vec![RetransmitNak(test_data_packet(1, true))]
+        );
+
+        // only once for the same ACK number
+        assert_eq!(fast_retransmit(&mut buffer, 1), None);
+        assert_eq!(fast_retransmit(&mut buffer, 1), None);
+
+        // an ACK that moves on starts the count over
+        assert_eq!(fast_retransmit(&mut buffer, 3), None);
+        assert_eq!(fast_retransmit(&mut buffer, 3), None);
+        assert_eq!(fast_retransmit(&mut buffer, 3), Some(SeqNumber(3)));
+
+        // nothing outstanding, nothing to retransmit
+        assert_eq!(fast_retransmit(&mut buffer, 6), None);
+        assert_eq!(fast_retransmit(&mut buffer, 6), None);
+        assert_eq!(fast_retransmit(&mut buffer, 6), None);
+    }
+
     #[test]
     fn ack() {
         use AckError::*;
@@ -727,7 +1277,7 @@ mod test {
         let mut buffer = SendBuffer::new(&new_settings());
 
         for n in 0..=5 {
-            let _ = buffer.push_data(test_data_packet(n, false));
+            let _ = buffer.push_data(test_data_packet(n, false), None);
         }
 
         let _ = buffer.next_snd_actions(now, 5, false).count();
@@ -737,6 +1287,7 @@ mod test {
                 received: 2,
                 recovered: 0,
                 send_ack2: None,
+                fast_retransmit: None,
             })
         );
         let full_ack = FullAckSeqNumber::new(1);
@@ -746,6 +1297,7 @@ mod test {
                 received: 2,
                 recovered: 0,
                 send_ack2: full_ack,
+                fast_retransmit: None,
             })
         );
 
@@ -777,6 +1329,58 @@ mod test {
                 next: SeqNumber(5)
             })
         );
+
+        // and so should one with an RTT nobody measured, without taking it on
+        let rtt = Rtt::new(TimeSpan::from_micros(-1), TimeSpan::from_micros(0));
+        assert_eq!(
+            buffer.update_largest_acked_seq_number(SeqNumber(5), None, Some(rtt)),
+            Err(ImplausibleRtt(rtt))
+        );
+        assert_eq!(buffer.rtt(), Rtt::default());
+
+        // everything was acknowledged, which the next ACK may say again
+        assert_eq!(
+            buffer.update_largest_acked_seq_number(SeqNumber(5), None, None),
+            Ok(AckAction {
+                received: 1,
+                recovered: 0,
+                send_ack2: None,
+                fast_retransmit: None,
+            })
+        );
+        assert_eq!(buffer.check_ack(SeqNumber(5), None, None), Ok(()));
+    }
+
+    #[test]
+    fn check_loss_report() {
+        let now = TimeStamp::MIN;
+        let settings = ConnectionSettings {
+            max_flow_size: PacketCount(10),
+            ..new_settings()
+        };
+        let mut buffer = SendBuffer::new(&settings);
+        for n in 0..30 {
+            let _ = buffer.push_data(test_data_packet(n, false), None);
+        }
+        // as much as the flow window lets out at a time
+        for ack in [10, 20] {
+            let _ = buffer.next_snd_actions(now, 10, false).count();
+            let _ = buffer.update_largest_acked_seq_number(SeqNumber(ack), None, None);
+        }
+        let _ = buffer.next_snd_actions(now, 10, false).count();
+
+        let report = |range: Range<u32>| {
+            buffer.check_loss_report(
+                &CompressedLossList::try_from_range(SeqNumber(range.start)..SeqNumber(range.end))
+                    .unwrap(),
+            )
+        };
+        assert!(report(20..30));
+        // a flow window back, for packets the sender may have dropped already
+        assert!(report(10..12));
+        assert!(!report(9..12));
+        // not sent yet
+        assert!(!report(25..31));
     }
 
     #[test]
@@ -786,7 +1390,7 @@ mod test {
         let mut buffer = SendBuffer::new(&new_settings());
 
         for n in 0..=2 {
-            let _ = buffer.push_data(test_data_packet(n, false));
+            let _ = buffer.push_data(test_data_packet(n, false), None);
         }
 
         let _ = buffer.next_snd_actions(now, 3, false).count();
@@ -804,7 +1408,7 @@ mod test {
         assert_eq!(
             loss,
             vec![
-                (Dropped, SeqNumber(0)..SeqNumber(1)),
+                (Dropped(None), SeqNumber(0)..SeqNumber(1)),
                 (Added, SeqNumber(1)..SeqNumber(3)),
                 (Ignored, SeqNumber(3)..SeqNumber(4)),
             ]
@@ -818,13 +1422,92 @@ mod test {
         assert_eq!(loss, vec![(Added, SeqNumber(1)..SeqNumber(3)),]);
     }
 
+    #[test]
+    fn light_ack_rtt_sample() {
+        let now = TimeStamp::MIN;
+        let mut buffer = SendBuffer::new(&new_settings());
+
+        for n in 0..=3 {
+            let _ = buffer.push_data(test_data_packet(n, false), None);
+        }
+        let _ = buffer.next_snd_actions(now, 4, false).count();
+
+        // packets that haven't been sent can't be sampled
+        assert_eq!(buffer.sample_light_ack_rtt(now, SeqNumber(5)), None);
+
+        let before = buffer.rtt.mean();
+        let rtt = buffer.sample_light_ack_rtt(now + 200 * MILLIS, SeqNumber(2));
+        assert!(rtt.unwrap().mean() > before);
+
+        // retransmitted packets are ambiguous, so they're not sampled
+        let _ = buffer
+            .add_to_loss_list([SeqNumber(2)].iter().collect())
+            .count();
+        let _ = buffer
+            .next_snd_actions(now + 200 * MILLIS, 1, false)
+            .count();
+        assert_eq!(
+            buffer.sample_light_ack_rtt(now + 400 * MILLIS, SeqNumber(3)),
+            None
+        );
+    }
+
+    #[test]
+    fn unacked_packets() {
+        let now = TimeStamp::MIN;
+        let mut buffer = SendBuffer::new(&new_settings());
+        let wire_size = test_data_packet(0, false).wire_size();
+
+        for n in 0..=3 {
+            let _ = buffer.push_data(test_data_packet(n, false), None);
+        }
+        assert_eq!(buffer.unacked_len(), 0);
+        assert_eq!(buffer.unacked_len_bytes(), 0);
+        assert_eq!(buffer.oldest_unacked_age(now), Duration::ZERO);
+
+        let _ = buffer.next_snd_actions(now, 3, false).count();
+        assert_eq!(buffer.unacked_len(), 3);
+        assert_eq!(buffer.unacked_len_bytes(), 3 * wire_size);
+        let later = now + 30 * MILLIS;
+        assert_eq!(buffer.oldest_unacked_age(later), Duration::from_millis(30));
+
+        // the oldest is the front of the buffer, whatever was sent after it
+        let _ = buffer.next_snd_actions(later, 1, false).count();
+        buffer
+            .update_largest_acked_seq_number(SeqNumber(2), None, None)
+            .unwrap();
+        assert_eq!(buffer.unacked_len(), 2);
+        assert_eq!(buffer.unacked_len_bytes(), 2 * wire_size);
+        assert_eq!(
+            buffer.oldest_unacked_age(later + 10 * MILLIS),
+            Duration::from_millis(40)
+        );
+
+        buffer
+            .update_largest_acked_seq_number(SeqNumber(4), None, None)
+            .unwrap();
+        assert_eq!(buffer.unacked_len(), 0);
+        assert_eq!(buffer.unacked_len_bytes(), 0);
+        assert_eq!(buffer.oldest_unacked_age(later), Duration::ZERO);
+
+        // dropped packets, sent or not, don't wait for an ACK anymore
+        for n in 4..=7 {
+            let _ = buffer.push_data(test_data_packet(n, false), None);
+        }
+        let _ = buffer.next_snd_actions(later, 2, false).count();
+        assert_eq!(buffer.unacked_len_bytes(), 2 * wire_size);
+        let _ = buffer.drop_front(3);
+        assert_eq!(buffer.unacked_len(), 0);
+        assert_eq!(buffer.unacked_len_bytes(), 0);
+    }
+
     #[test]
     fn nak_then_ack() {
         let now = TimeStamp::MIN;
         let mut buffer = SendBuffer::new(&new_settings());
 
         for n in 0..=2 {
-            let _ = buffer.push_data(test_data_packet(n, false));
+            let _ = buffer.push_data(test_data_packet(n, false), None);
         }
 
         let _ = buffer.next_snd_actions(now, 3, false).count();
@@ -838,7 +1521,8 @@ mod test {
             Ok(AckAction {
                 received: 3,
                 recovered: 1,
-                send_ack2: None
+                send_ack2: None,
+                fast_retransmit: None,
             })
         );
         assert!(!buffer.has_packets_to_send());
@@ -851,7 +1535,7 @@ mod test {
         let start = TimeStamp::MIN;
         let mut buffer = SendBuffer::new(&new_settings());
         for n in 0..=4 {
-            let _ = buffer.push_data(test_data_packet(n, false));
+            let _ = buffer.push_data(test_data_packet(n, false), None);
         }
 
         // drop queued packets when they are too late
@@ -907,7 +1591,7 @@ mod test {
         let wire_size = test_data_packet(0, false).wire_size();
 
         for n in 0..10 {
-            let _ = buffer.push_data(test_data_packet(n, false));
+            let _ = buffer.push_data(test_data_packet(n, false), None);
             assert_eq!(buffer.duration(), Duration::from_millis(1) * n);
             assert_eq!(buffer.len(), n as usize + 1);
             assert_eq!(buffer.len_bytes(), wire_size * (n as usize + 1));
@@ -945,7 +1629,7 @@ mod test {
 
         let max_flow_size = new_settings().max_flow_size.0 as u32 + 1;
         for n in 0..max_flow_size {
-            assert_eq!(buffer.push_data(test_data_packet(n, false)), Ok(()));
+            assert_eq!(buffer.push_data(test_data_packet(n, false), None), Ok(()));
         }
 
         // if the buffer is full of unsent packets it
@@ -963,20 +1647,185 @@ mod test {
         assert!(!buffer.flow_window_exceeded());
     }
 
+    #[test]
+    fn peer_buffer_available() {
+        let mut buffer = SendBuffer::new(&new_settings());
+        for n in 0..10 {
+            assert_eq!(buffer.push_data(test_data_packet(n, false), None), Ok(()));
+        }
+        let sent = |buffer: &mut SendBuffer| {
+            buffer
+                .next_snd_actions(TimeStamp::MIN, 10, false)
+                .filter(|action| matches!(action, SenderAction::Send(_)))
+                .count()
+        };
+
+        // room for 3 more packets after the first one
+        buffer.update_peer_buffer_available(SeqNumber(1), 3);
+        assert_eq!(sent(&mut buffer), 4);
+        assert!(buffer.flow_window_exceeded());
+
+        // the receiver released some data
+        buffer.update_peer_buffer_available(SeqNumber(1), 5);
+        assert!(!buffer.flow_window_exceeded());
+        assert_eq!(sent(&mut buffer), 2);
+        assert!(buffer.flow_window_exceeded());
+    }
+
     #[test]
     fn max_send_buffer_size() {
         let mut buffer = SendBuffer::new(&new_settings());
 
         let send_buffer_size = new_settings().send_buffer_size.0 as u32;
         for n in 0..send_buffer_size {
-            assert_eq!(buffer.push_data(test_data_packet(n, false)), Ok(()));
+            assert_eq!(buffer.push_data(test_data_packet(n, false), None), Ok(()));
         }
 
-        let expected_dropped_bytes = test_data_packet(0, false).wire_size() as u64;
+        // the whole first message goes, both of its packets
+        let expected_dropped_bytes = 2 * test_data_packet(0, false).wire_size() as u64;
         let overflow_packet = test_data_packet(send_buffer_size, false);
         assert_eq!(
-            buffer.push_data(overflow_packet),
-            Err((PacketCount(1), ByteCount(expected_dropped_bytes)))
+            buffer.push_data(overflow_packet, None),
+            Err((
+                SeqNumber(0)..SeqNumber(2),
+                ByteCount(expected_dropped_bytes)
+            ))
+        );
+        assert_eq!(buffer.dropped_message_count(), 1);
+        // which made room for another packet
+        let next_packet = test_data_packet(send_buffer_size + 1, false);
+        assert_eq!(buffer.push_data(next_packet, None), Ok(()));
+    }
+
+    #[test]
+    fn overflow_drops_whole_messages() {
+        use Loss::*;
+        let now = TimeStamp::MIN;
+        let settings = ConnectionSettings {
+            send_buffer_size: PacketCount(4),
+            ..new_settings()
+        };
+        let wire_size = test_data_packet(0, false).wire_size() as u64;
+
+        // messages 0 and 1, none of them sent yet
+        let mut buffer = SendBuffer::new(&settings);
+        for n in 0..4 {
+            assert_eq!(buffer.push_data(test_data_packet(n, false), None), Ok(()));
+        }
+        assert_eq!(
+            buffer.push_data(test_data_packet(4, false), None),
+            Err((SeqNumber(0)..SeqNumber(2), ByteCount(2 * wire_size)))
+        );
+        // sending picks up after the dropped message
+        assert_eq!(
+            buffer.next_snd_actions(now, 1, false).collect::<Vec<_>>(),
+            vec![send_data_packet(2)]
+        );
+        // and a NAK for it is answered with its message number
+        assert_eq!(
+            buffer
+                .add_to_loss_list([SeqNumber(1)].iter().collect())
+                .collect::<Vec<_>>(),
+            vec![(Dropped(Some(MsgNumber(0))), SeqNumber(1)..SeqNumber(2))]
+        );
+
+        // a message larger than the buffer only pushes out its own start
+        let mut buffer = SendBuffer::new(&settings);
+        let packet = |n| DataPacket {
+            message_number: MsgNumber(7),
+            ..test_data_packet(n, false)
+        };
+        for n in 0..4 {
+            assert_eq!(buffer.push_data(packet(n), None), Ok(()));
+        }
+        for n in 4..6 {
+            assert_eq!(
+                buffer.push_data(packet(n), None),
+                Err((SeqNumber(n - 4)..SeqNumber(n - 3), ByteCount(wire_size)))
+            );
+        }
+        assert_eq!(buffer.len(), 4);
+        assert_eq!(buffer.dropped_message_count(), 1);
+    }
+
+    #[test]
+    fn time_limit_drops_oldest_messages() {
+        let settings = ConnectionSettings {
+            send_buffer_time: Some(Duration::from_millis(3)),
+            ..new_settings()
+        };
+
+        // the packets are a millisecond apart, messages 0 and 1 span 3ms
+        let mut buffer = SendBuffer::new(&settings);
+        for n in 0..4 {
+            assert_eq!(buffer.push_data(test_data_packet(n, false), None), Ok(()));
+        }
+        assert!(!buffer.has_room());
+
+        let wire_size = test_data_packet(0, false).wire_size() as u64;
+        assert_eq!(
+            buffer.push_data(test_data_packet(4, false), None),
+            Err((SeqNumber(0)..SeqNumber(2), ByteCount(2 * wire_size)))
+        );
+        assert_eq!(buffer.duration(), Duration::from_millis(2));
+        assert!(buffer.has_room());
+    }
+
+    #[test]
+    fn full_buffer_without_drop_oldest() {
+        let settings = ConnectionSettings {
+            send_buffer_size: PacketCount(4),
+            send_buffer_policy: SendBufferPolicy::Block,
+            ..new_settings()
+        };
+
+        // whoever pushes is supposed to wait for room, a message pushed anyway is kept
+        let mut buffer = SendBuffer::new(&settings);
+        for n in 0..4 {
+            assert_eq!(buffer.push_data(test_data_packet(n, false), None), Ok(()));
+        }
+        assert!(!buffer.has_room());
+        assert_eq!(buffer.push_data(test_data_packet(4, false), None), Ok(()));
+        assert_eq!(buffer.len(), 5);
+        assert_eq!(buffer.dropped_message_count(), 0);
+    }
+
+    #[test]
+    fn byte_limit_drops_oldest_messages() {
+        let now = TimeStamp::MIN;
+        let wire_size = test_data_packet(0, false).wire_size();
+        let settings = ConnectionSettings {
+            send_buffer_bytes: Some(ByteCount(6 * wire_size as u64)),
+            ..new_settings()
+        };
+
+        // messages 0, 1 and 2, the first of them sent already
+        let mut buffer = SendBuffer::new(&settings);
+        for n in 0..6 {
+            assert_eq!(buffer.push_data(test_data_packet(n, false), None), Ok(()));
+        }
+        assert_eq!(
+            buffer.next_snd_actions(now, 2, false).collect::<Vec<_>>(),
+            vec![send_data_packet(0), send_data_packet(1)]
+        );
+        assert_eq!(buffer.len_bytes(), 6 * wire_size);
+
+        // room for a packet three times as large takes two messages
+        let large = DataPacket {
+            payload: Bytes::from(vec![0; 2 * wire_size]),
+            ..test_data_packet(6, false)
+        };
+        assert_eq!(
+            buffer.push_data(large, None),
+            Err((SeqNumber(0)..SeqNumber(4), ByteCount(4 * wire_size as u64)))
+        );
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.len_bytes(), 5 * wire_size);
+        assert_eq!(buffer.dropped_message_count(), 2);
+        // sending carries on with the oldest message left
+        assert_eq!(
+            buffer.next_snd_actions(now, 1, false).collect::<Vec<_>>(),
+            vec![send_data_packet(4)]
         );
     }
 
@@ -986,7 +1835,7 @@ mod test {
         let mut buffer = SendBuffer::new(&new_settings());
 
         for n in 0..=2 {
-            assert_matches!(buffer.push_data(test_data_packet(n, false)), Ok(_));
+            assert_matches!(buffer.push_data(test_data_packet(n, false), None), Ok(_));
         }
 
         let _ = buffer.next_snd_actions(now, 3, false).count();
@@ -995,11 +1844,142 @@ mod test {
             .count();
 
         for n in 3..=8195 {
-            assert_matches!(buffer.push_data(test_data_packet(n, false)), Ok(_));
+            assert_matches!(buffer.push_data(test_data_packet(n, false), None), Ok(_));
         }
-        assert_matches!(buffer.push_data(test_data_packet(8296, false)), Err(_));
-        assert_matches!(buffer.push_data(test_data_packet(8297, false)), Err(_));
+        assert_matches!(
+            buffer.push_data(test_data_packet(8296, false), None),
+            Err(_)
+        );
+        assert_matches!(buffer.push_data(test_data_packet(8297, false), None), Ok(_));
+        assert_matches!(
+            buffer.push_data(test_data_packet(8298, false), None),
+            Err(_)
+        );
 
         buffer.send_next_lost_packet(now);
     }
+
+    #[test]
+    fn expired_messages() {
+        use SenderAction::*;
+        let start = TimeStamp::MIN;
+        let mut buffer = SendBuffer::new(&new_settings());
+
+        // messages 0 and 1 expire, message 2 doesn't
+        let expires = Some(start + 10 * MILLIS);
+        for n in 0..6 {
+            let expires = if n < 4 { expires } else { None };
+            assert_eq!(
+                buffer.push_data(test_data_packet(n, false), expires),
+                Ok(())
+            );
+        }
+        assert_eq!(
+            buffer.next_snd_actions(start, 2, false).collect::<Vec<_>>(),
+            vec![send_data_packet(0), send_data_packet(1)]
+        );
+
+        // neither the lost packet nor the ones not sent yet are sent once expired
+        let now = start + 20 * MILLIS;
+        let _ = buffer
+            .add_to_loss_list([SeqNumber(1)].iter().collect())
+            .count();
+        assert_eq!(
+            buffer.next_snd_actions(now, 10, false).collect::<Vec<_>>(),
+            vec![
+                DropExpired(MsgNumber(0), SeqNumber(0)..SeqNumber(2)),
+                DropExpired(MsgNumber(1), SeqNumber(2)..SeqNumber(4)),
+                send_data_packet(4),
+                send_data_packet(5),
+                WaitForInput,
+            ]
+        );
+
+        // the peer is told again if it missed the drop request
+        let _ = buffer
+            .add_to_loss_list([SeqNumber(3)].iter().collect())
+            .count();
+        assert_eq!(
+            buffer.next_snd_actions(now, 10, false).collect::<Vec<_>>(),
+            vec![
+                DropExpired(MsgNumber(1), SeqNumber(2)..SeqNumber(4)),
+                WaitForInput
+            ]
+        );
+    }
+
+    #[test]
+    fn late_lost_packets() {
+        use SenderAction::*;
+        let start = TimeStamp::MIN;
+        let lost = || [SeqNumber(1), SeqNumber(4)].iter().collect();
+
+        let mut buffer = SendBuffer::new(&new_settings());
+        for n in 0..6 {
+            assert_eq!(buffer.push_data(test_data_packet(n, false), None), Ok(()));
+        }
+        assert_eq!(buffer.next_snd_actions(start, 6, false).count(), 6);
+
+        // packet 1 can't get there within its latency anymore, half the RTT after now, packet 4 can
+        let now = start + TSBPD - 3 * MILLIS;
+        let _ = buffer.add_to_loss_list(lost()).count();
+        assert_eq!(
+            buffer
+                .next_snd_actions(now, 10, false)
+                .take(2)
+                .collect::<Vec<_>>(),
+            vec![
+                DropLate(MsgNumber(0), SeqNumber(0)..SeqNumber(2)),
+                nak_retransmit_packet(4),
+            ]
+        );
+
+        // the peer doesn't drop packets, so they are retransmitted however late
+        let mut settings = new_settings();
+        settings.peer_flags.remove(SrtShakeFlags::TLPKTDROP);
+        let mut buffer = SendBuffer::new(&settings);
+        for n in 0..6 {
+            assert_eq!(buffer.push_data(test_data_packet(n, false), None), Ok(()));
+        }
+        assert_eq!(buffer.next_snd_actions(start, 6, false).count(), 6);
+
+        let _ = buffer.add_to_loss_list(lost()).count();
+        assert_eq!(
+            buffer
+                .next_snd_actions(start + 2 * TSBPD, 10, false)
+                .take(2)
+                .collect::<Vec<_>>(),
+            vec![nak_retransmit_packet(1), nak_retransmit_packet(4)]
+        );
+
+        // the peer's time base lags by half the RTT of the handshake, the same as the RTT since,
+        // so the packets have until their timestamp plus the latency
+        let mut settings = new_settings();
+        settings.rtt = 10 * MILLIS;
+        let mut buffer = SendBuffer::new(&settings);
+        for n in 0..6 {
+            assert_eq!(buffer.push_data(test_data_packet(n, false), None), Ok(()));
+        }
+        assert_eq!(buffer.next_snd_actions(start, 6, false).count(), 6);
+
+        let _ = buffer.add_to_loss_list(lost()).count();
+        assert_eq!(
+            buffer
+                .next_snd_actions(start + TSBPD - 3 * MILLIS, 10, false)
+                .take(2)
+                .collect::<Vec<_>>(),
+            vec![nak_retransmit_packet(1), nak_retransmit_packet(4)]
+        );
+        let _ = buffer.add_to_loss_list(lost()).count();
+        assert_eq!(
+            buffer
+                .next_snd_actions(start + TSBPD + 2 * MILLIS, 10, false)
+                .take(2)
+                .collect::<Vec<_>>(),
+            vec![
+                DropLate(MsgNumber(0), SeqNumber(0)..SeqNumber(2)),
+                nak_retransmit_packet(4),
+            ]
+        );
+    }
 }
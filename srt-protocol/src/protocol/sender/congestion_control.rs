@@ -1,10 +1,14 @@
-use std::{
-    cmp::max,
-    time::{Duration, Instant},
-};
+use std::{cmp::max, time::Duration};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use crate::options::{
-    ByteCount, DataRate, LiveBandwidthMode, PacketCount, PacketPeriod, PacketRate, Percent,
+use crate::{
+    options::{
+        ByteCount, DataRate, LiveBandwidthMode, PacketCount, PacketPeriod, PacketRate, PacketSize,
+        Percent,
+    },
+    packet::{Packet, SeqNumber},
+    protocol::time::{Instant, Timers},
 };
 
 #[derive(Debug, Default)]
@@ -87,17 +91,67 @@ pub struct SenderCongestionControl {
     next: Option<Instant>,
     estimation: InputRateEstimation,
     bandwidth_mode: LiveBandwidthMode,
+    // packet size used by the rate increase formula, including headers
+    mss: f64,
+    // the current packet send period in microseconds, once the input rate has been estimated
+    snd_period: Option<f64>,
+    // the LiveCC period, the sender never sends faster than this
+    min_period: f64,
+    // loss never slows the sender down past twice the input rate, the rest is needed to recover
+    // lost packets, otherwise a live sender would fall further and further behind its input
+    max_period: f64,
+    loss: LossState,
+    last_rate_control: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct LossState {
+    // a loss report arrived since the last rate increase
+    loss: bool,
+    // what was sent next when the period was last increased, losses before this don't count
+    last_dec_seq: SeqNumber,
+    last_dec_period: f64,
+    nak_count: u32,
+    dec_count: u32,
+    avg_nak_num: u32,
+    dec_random: u32,
+    rng: StdRng,
 }
 
 // https://datatracker.ietf.org/doc/html/draft-sharabayko-srt-00#section-5.1.2
 impl SenderCongestionControl {
     const GIGABIT: DataRate = DataRate(1_000_000_000 / 8);
-
-    pub fn new(bandwidth_mode: LiveBandwidthMode) -> Self {
+    // rate control interval, SYN in microseconds
+    const RC_INTERVAL: f64 = 10_000.;
+    // draft-sharabayko-srt-01 section 5.2.2
+    const DECREASE_FACTOR: f64 = 1.03;
+    const MAX_DECREASES: u32 = 5;
+    const RETRANSMIT_HEADROOM: f64 = 2.;
+
+    pub fn new(
+        bandwidth_mode: LiveBandwidthMode,
+        max_packet_size: PacketSize,
+        init_seq_num: SeqNumber,
+    ) -> Self {
         Self {
             next: None,
             estimation: InputRateEstimation::default(),
             bandwidth_mode,
+            mss: (max_packet_size + Packet::HEADER_SIZE).0 as f64,
+            snd_period: None,
+            min_period: 0.,
+            max_period: f64::MAX,
+            loss: LossState {
+                loss: false,
+                last_dec_seq: init_seq_num,
+                last_dec_period: 1.,
+                nak_count: 0,
+                dec_count: 0,
+                avg_nak_num: 0,
+                dec_random: 1,
+                rng: StdRng::seed_from_u64(init_seq_num.as_raw().into()),
+            },
+            last_rate_control: None,
         }
     }
 
@@ -125,7 +179,11 @@ impl SenderCongestionControl {
                 let data_rate = estimate.bytes.mean;
                 let packet_rate = estimate.packets.mean;
 
-                Some(self.calculate_snd_period(PacketRate(packet_rate), DataRate(data_rate)))
+                let min_period = self
+                    .calculate_snd_period(PacketRate(packet_rate), DataRate(data_rate))
+                    .as_secs_f64()
+                    * 1_000_000.;
+                Some(self.update_bounds(min_period, packet_rate))
             }
         };
 
@@ -134,6 +192,111 @@ impl SenderCongestionControl {
         result
     }
 
+    /// ACK driven recovery after a slowdown, at most once per SYN
+    pub fn on_ack(
+        &mut self,
+        now: Instant,
+        estimated_link_capacity: Option<u32>,
+    ) -> Option<Duration> {
+        let snd_period = self.snd_period?;
+        if matches!(self.last_rate_control, Some(last) if now - last < Timers::SYN) {
+            return None;
+        }
+        self.last_rate_control = Some(now);
+
+        // don't speed up right after a loss report
+        if self.loss.loss {
+            self.loss.loss = false;
+            return None;
+        }
+        if snd_period <= self.min_period {
+            return None;
+        }
+
+        // the spare link capacity, in packets per second
+        let capacity = f64::from(estimated_link_capacity.unwrap_or(0));
+        let mut spare = capacity - 1_000_000. / snd_period;
+        if snd_period > self.loss.last_dec_period && capacity / 9. < spare {
+            spare = capacity / 9.;
+        }
+        let inc = if spare <= 0. {
+            1. / self.mss
+        } else {
+            let inc = 10f64.powf((spare * self.mss * 8.).log10().ceil()) * 0.000_001_5 / self.mss;
+            inc.max(1. / self.mss)
+        };
+
+        let snd_period = (snd_period * Self::RC_INTERVAL) / (snd_period * inc + Self::RC_INTERVAL);
+        Some(self.set_snd_period(snd_period))
+    }
+
+    /// NAK triggered slowdown, with the random decrement from the reference implementation's
+    /// FileCC so that repeated loss reports for one congestion event don't compound
+    pub fn on_nak(&mut self, first_lost: SeqNumber, next_send: SeqNumber) -> Option<Duration> {
+        let snd_period = self.snd_period?;
+        let loss = &mut self.loss;
+        loss.loss = true;
+
+        let snd_period = if first_lost >= loss.last_dec_seq {
+            // a new congestion event
+            loss.last_dec_period = snd_period;
+            loss.avg_nak_num = (f64::from(loss.avg_nak_num) * 0.97
+                + f64::from(loss.nak_count) * 0.03)
+                .ceil() as u32;
+            loss.nak_count = 1;
+            loss.dec_count = 1;
+            loss.last_dec_seq = next_send;
+            loss.dec_random = if loss.avg_nak_num > 1 {
+                loss.rng.gen_range(1..=loss.avg_nak_num)
+            } else {
+                1
+            };
+            (snd_period * Self::DECREASE_FACTOR).ceil()
+        } else {
+            let decrease = loss.dec_count < Self::MAX_DECREASES && {
+                loss.nak_count += 1;
+                loss.nak_count.is_multiple_of(loss.dec_random)
+            };
+            loss.dec_count += 1;
+            if !decrease {
+                return None;
+            }
+            loss.last_dec_seq = next_send;
+            (snd_period * Self::DECREASE_FACTOR).ceil()
+        };
+        Some(self.set_snd_period(snd_period))
+    }
+
+    /// ECN triggered slowdown, the same as for a loss report of the last packet sent, just that
+    /// nothing needs to be retransmitted
+    pub fn on_congestion_experienced(&mut self, next_send: SeqNumber) -> Option<Duration> {
+        self.on_nak(next_send - 1, next_send)
+    }
+
+    fn update_bounds(&mut self, min_period: f64, packet_rate: u64) -> Duration {
+        // without a slowdown in effect, follow the LiveCC period
+        let tracking = self
+            .snd_period
+            .is_none_or(|period| period <= self.min_period);
+        self.min_period = min_period;
+        self.max_period = if packet_rate > 0 {
+            (1_000_000. / Self::RETRANSMIT_HEADROOM / packet_rate as f64).max(min_period)
+        } else {
+            f64::MAX
+        };
+        let snd_period = match self.snd_period {
+            Some(period) if !tracking => period,
+            _ => min_period,
+        };
+        self.set_snd_period(snd_period)
+    }
+
+    fn set_snd_period(&mut self, snd_period: f64) -> Duration {
+        let snd_period = snd_period.clamp(self.min_period, self.max_period);
+        self.snd_period = Some(snd_period);
+        Duration::from_micros(snd_period.round() as u64)
+    }
+
     fn calculate_max_data_rate(&self, actual_data_rate: DataRate) -> DataRate {
         use LiveBandwidthMode::*;
         match self.bandwidth_mode {
@@ -168,7 +331,7 @@ mod sender_congestion_control {
 
         let ms = Duration::from_millis;
         let start = Instant::now();
-        let mut control = SenderCongestionControl::new(data_rate);
+        let mut control = SenderCongestionControl::new(data_rate, PacketSize(1316), SeqNumber(0));
 
         // initialize statistics
         control.on_input(start, PacketCount(0), ByteCount(0));
@@ -194,7 +357,7 @@ mod sender_congestion_control {
 
         let micros = Duration::from_micros;
         let start = Instant::now();
-        let mut control = SenderCongestionControl::new(data_rate);
+        let mut control = SenderCongestionControl::new(data_rate, PacketSize(1316), SeqNumber(0));
 
         // initialize statistics
         assert_eq!(control.on_input(start, PacketCount(0), ByteCount(0)), None);
@@ -218,7 +381,7 @@ mod sender_congestion_control {
 
         let micros = Duration::from_micros;
         let start = Instant::now();
-        let mut control = SenderCongestionControl::new(data_rate);
+        let mut control = SenderCongestionControl::new(data_rate, PacketSize(1316), SeqNumber(0));
 
         // initialize statistics
         assert_eq!(control.on_input(start, PacketCount(0), ByteCount(0)), None);
@@ -245,7 +408,7 @@ mod sender_congestion_control {
 
         let micros = Duration::from_micros;
         let start = Instant::now();
-        let mut control = SenderCongestionControl::new(data_rate);
+        let mut control = SenderCongestionControl::new(data_rate, PacketSize(1316), SeqNumber(0));
 
         // initialize statistics
         assert_eq!(control.on_input(start, PacketCount(0), ByteCount(0)), None);
@@ -272,7 +435,7 @@ mod sender_congestion_control {
 
         let micros = Duration::from_micros;
         let start = Instant::now();
-        let mut control = SenderCongestionControl::new(data_rate);
+        let mut control = SenderCongestionControl::new(data_rate, PacketSize(1316), SeqNumber(0));
 
         // initialize statistics
         assert_eq!(control.on_input(start, PacketCount(0), ByteCount(0)), None);
@@ -288,4 +451,159 @@ mod sender_congestion_control {
 
         assert_eq!(snd_period, Some(micros(expected_snd_period)));
     }
+
+    // 1000 packets/s of 1000 bytes at 1MB/s, with 300% overhead the LiveCC period is 250us, and
+    // loss can slow the sender down to 500us
+    fn estimated(start: Instant, overhead: u64) -> SenderCongestionControl {
+        let data_rate = LiveBandwidthMode::Input {
+            rate: DataRate(1_000_000),
+            overhead: Percent(overhead),
+        };
+        let mut control = SenderCongestionControl::new(data_rate, PacketSize(1316), SeqNumber(0));
+        control.on_input(start, PacketCount(0), ByteCount(0));
+        control.on_input(start, PacketCount(100), ByteCount(100_000));
+        let snd_period = control.on_input(
+            start + Duration::from_millis(100),
+            PacketCount(0),
+            ByteCount(0),
+        );
+        assert_eq!(
+            snd_period,
+            Some(Duration::from_micros(1_000 * 100 / (100 + overhead)))
+        );
+        control
+    }
+
+    #[test]
+    fn nak_slowdown() {
+        let start = Instant::now();
+        let mut control = estimated(start, 300);
+
+        // a new congestion event: period = ceil(period * 1.03)
+        assert_eq!(
+            control.on_nak(SeqNumber(10), SeqNumber(20)),
+            Some(Duration::from_micros(258))
+        );
+        assert_eq!(control.loss.last_dec_seq, SeqNumber(20));
+        assert_eq!(control.loss.last_dec_period, 250.);
+        assert_eq!(control.loss.dec_random, 1);
+
+        // losses sent before the slowdown are part of the same event, with dec_random = 1 every
+        // report slows down, up to 5 times per event
+        let mut period = 258.;
+        for n in 0..4 {
+            period = (period * 1.03f64).ceil();
+            assert_eq!(
+                control.on_nak(SeqNumber(11), SeqNumber(21 + n)),
+                Some(Duration::from_micros(period as u64))
+            );
+        }
+        assert_eq!(control.on_nak(SeqNumber(12), SeqNumber(30)), None);
+
+        // a loss after the last slowdown starts a new event
+        assert_eq!(
+            control.on_nak(SeqNumber(30), SeqNumber(40)),
+            Some(Duration::from_micros((period * 1.03f64).ceil() as u64))
+        );
+    }
+
+    #[test]
+    fn nak_random_decrement() {
+        let start = Instant::now();
+        let mut control = estimated(start, 300);
+
+        // avg_nak_num = ceil(avg_nak_num * 0.97 + nak_count * 0.03)
+        control.loss.nak_count = 50;
+        control.loss.avg_nak_num = 10;
+        control.on_nak(SeqNumber(10), SeqNumber(20));
+        assert_eq!(control.loss.avg_nak_num, 12);
+        assert_eq!(control.loss.nak_count, 1);
+        assert!((1..=12).contains(&control.loss.dec_random));
+
+        // only every dec_random-th report within the event slows down
+        let dec_random = 3;
+        control.loss.dec_random = dec_random;
+        let slowed: Vec<_> = (0..4)
+            .map(|_| control.on_nak(SeqNumber(11), SeqNumber(20)).is_some())
+            .collect();
+        assert_eq!(slowed, [false, true, false, false]);
+    }
+
+    #[test]
+    fn nak_slowdown_limit() {
+        let start = Instant::now();
+        let mut control = estimated(start, 300);
+
+        // never slower than twice the input rate, or the sender falls behind
+        for n in 0..30 {
+            control.on_nak(SeqNumber(10 + n * 10), SeqNumber(20 + n * 10));
+        }
+        assert_eq!(control.snd_period, Some(500.));
+    }
+
+    #[test]
+    fn ack_recovery() {
+        let ms = Duration::from_millis;
+        let start = Instant::now();
+        let mut control = estimated(start, 300);
+        control.on_nak(SeqNumber(10), SeqNumber(20));
+
+        // the first ACK after a loss report doesn't speed up
+        assert_eq!(control.on_ack(start + ms(100), Some(10_000)), None);
+        // at most once per SYN
+        assert_eq!(control.on_ack(start + ms(105), Some(10_000)), None);
+
+        // B = capacity - 1e6 / period, limited to capacity / 9 while slower than the last
+        // decrease, inc = max(10^ceil(log10(B * mss * 8)) * 0.0000015 / mss, 1 / mss)
+        let mss = 1316. + 44.;
+        let period = 258.;
+        let spare = 10_000f64 / 9.;
+        let inc = (10f64.powf((spare * mss * 8.).log10().ceil()) * 0.0000015 / mss).max(1. / mss);
+        let expected = period * 10_000. / (period * inc + 10_000.);
+        assert_eq!(
+            control.on_ack(start + ms(110), Some(10_000)),
+            Some(Duration::from_micros(expected.round() as u64))
+        );
+        assert_eq!(control.snd_period, Some(expected));
+
+        // without spare capacity, inc = 1 / mss
+        let inc = 1. / mss;
+        let expected = expected * 10_000. / (expected * inc + 10_000.);
+        assert_eq!(
+            control.on_ack(start + ms(120), None),
+            Some(Duration::from_micros(expected.round() as u64))
+        );
+
+        // recovery stops at the LiveCC period
+        for n in 0..100 {
+            control.on_ack(start + ms(130 + n * 10), Some(1_000_000));
+        }
+        assert_eq!(control.snd_period, Some(250.));
+        assert_eq!(control.on_ack(start + ms(2_000), Some(1_000_000)), None);
+    }
+
+    #[test]
+    fn input_rate_change() {
+        let ms = Duration::from_millis;
+        let start = Instant::now();
+        let mut control = estimated(start, 300);
+        control.on_nak(SeqNumber(10), SeqNumber(20));
+
+        // a slowed down sender keeps its period when the input rate changes
+        control.on_input(start + ms(100), PacketCount(100), ByteCount(100_000));
+        assert_eq!(
+            control.on_input(start + ms(200), PacketCount(0), ByteCount(0)),
+            Some(Duration::from_micros(258))
+        );
+
+        // once recovered, it follows the LiveCC period again
+        control.snd_period = Some(control.min_period);
+        control.on_input(start + ms(200), PacketCount(50), ByteCount(100_000));
+        let snd_period = control.on_input(start + ms(300), PacketCount(0), ByteCount(0));
+        assert!(control.min_period > 250.);
+        assert_eq!(
+            snd_period,
+            Some(Duration::from_micros(control.min_period as u64))
+        );
+    }
 }
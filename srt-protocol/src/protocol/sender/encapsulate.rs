@@ -20,15 +20,31 @@ impl Encapsulation {
         }
     }
 
+    pub fn next_sequence_number(&self) -> SeqNumber {
+        self.next_sequence_number
+    }
+
+    pub fn next_message_number(&self) -> MsgNumber {
+        self.next_message_number
+    }
+
+    /// Carry on from the numbers of a snapshot
+    pub fn restore(&mut self, next_sequence_number: SeqNumber, next_message_number: MsgNumber) {
+        self.next_sequence_number = next_sequence_number;
+        self.next_message_number = next_message_number;
+    }
+
     /// In the case of a message longer than the packet size,
     /// It will be split into multiple packets
     pub fn encapsulate(
         &mut self,
         timestamp: TimeStamp,
         data: Bytes,
+        in_order_delivery: bool,
     ) -> impl Iterator<Item = DataPacket> + '_ {
         MessageEncapsulationIterator {
             timestamp,
+            in_order_delivery,
             message_number: self.next_message_number.increment(),
             remaining: data,
             packet_location: PacketLocation::FIRST,
@@ -47,6 +63,7 @@ struct MessageEncapsulationIterator<'a> {
     packet_location: PacketLocation,
     message_number: MsgNumber,
     timestamp: TimeStamp,
+    in_order_delivery: bool,
 }
 
 impl<'a> Iterator for MessageEncapsulationIterator<'a> {
@@ -70,7 +87,7 @@ impl<'a> Iterator for MessageEncapsulationIterator<'a> {
 
         Some(DataPacket {
             dest_sockid: self.remote_socket_id,
-            in_order_delivery: false, // TODO: research this
+            in_order_delivery: self.in_order_delivery,
             encryption: DataEncryption::None,
             retransmitted: false,
             message_number: self.message_number,
@@ -101,7 +118,12 @@ mod encapsulation {
 
         let mut encapsulation = new_encapsulation();
 
-        assert_eq!(encapsulation.encapsulate(TimeStamp::MAX, data).count(), 1);
+        assert_eq!(
+            encapsulation
+                .encapsulate(TimeStamp::MAX, data, false)
+                .count(),
+            1
+        );
     }
 
     #[test]
@@ -110,6 +132,42 @@ mod encapsulation {
 
         let mut encapsulation = new_encapsulation();
 
-        assert_eq!(encapsulation.encapsulate(TimeStamp::MAX, data).count(), 10);
+        assert_eq!(
+            encapsulation
+                .encapsulate(TimeStamp::MAX, data, false)
+                .count(),
+            10
+        );
+    }
+
+    #[test]
+    fn multi_megabyte_message() {
+        let data = Bytes::from(vec![0u8; 4 * 1024 * 1024 + 1]);
+
+        let mut encapsulation = new_encapsulation();
+        let packets = encapsulation
+            .encapsulate(TimeStamp::MAX, data, false)
+            .collect::<Vec<_>>();
+
+        assert_eq!(packets.len(), 4097);
+        for (n, packet) in packets.iter().enumerate() {
+            let expected_loc = match n {
+                0 => PacketLocation::FIRST,
+                4096 => PacketLocation::LAST,
+                _ => PacketLocation::MIDDLE,
+            };
+            assert_eq!(packet.message_loc, expected_loc, "n={n}");
+            assert_eq!(packet.seq_number, SeqNumber(n as u32));
+            assert_eq!(packet.message_number, MsgNumber(1));
+        }
+        assert_eq!(packets[4096].payload.len(), 1);
+
+        // the next message gets the next message and sequence numbers
+        let next = encapsulation
+            .encapsulate(TimeStamp::MAX, Bytes::from_static(b"next"), false)
+            .next()
+            .unwrap();
+        assert_eq!(next.seq_number, SeqNumber(4097));
+        assert_eq!(next.message_number, MsgNumber(2));
     }
 }
@@ -1,29 +1,31 @@
 mod buffer;
 mod congestion_control;
 mod encapsulate;
+mod pacing;
 
-use std::{
-    convert::TryFrom,
-    time::{Duration, Instant},
-};
-
-use bytes::Bytes;
+use std::{convert::TryFrom, ops::Range, time::Duration};
 
 use crate::{
-    connection::{ConnectionSettings, ConnectionStatus},
+    connection::{
+        delivery::{Deliveries, Delivery},
+        snapshot::SenderSnapshot,
+        ConnectionSettings, ConnectionStatus, SendMessage,
+    },
     options::*,
     packet::*,
     protocol::{
-        encryption::Encryption,
+        encryption::{Encryption, KeyMaterialState},
         output::Output,
+        time::Instant,
         time::{TimeBase, Timers},
     },
-    statistics::SocketStatistics,
+    statistics::{MemoryUsage, SocketStatistics},
 };
 
 use buffer::{AckAction, Loss, SendBuffer, SenderAction};
 use congestion_control::SenderCongestionControl;
 use encapsulate::Encapsulation;
+use pacing::TokenBucket;
 
 #[derive(Debug)]
 pub struct Sender {
@@ -32,6 +34,14 @@ pub struct Sender {
     encryption: Encryption,
     send_buffer: SendBuffer,
     congestion_control: SenderCongestionControl,
+    pacing: TokenBucket,
+    buffer_time: Duration,
+    buffer_busy_since: Option<Instant>,
+    ack2_mode: Ack2Mode,
+    last_ack2: Option<(Instant, FullAckSeqNumber)>,
+    lite_ack_rtt_sampling: bool,
+    last_rtt_update: Option<Instant>,
+    deliveries: Deliveries,
 }
 
 impl Sender {
@@ -41,7 +51,19 @@ impl Sender {
             encapsulation: Encapsulation::new(&settings),
             encryption: Encryption::new(settings.cipher.clone()),
             send_buffer: SendBuffer::new(&settings),
-            congestion_control: SenderCongestionControl::new(settings.bandwidth.clone()),
+            congestion_control: SenderCongestionControl::new(
+                settings.bandwidth.clone(),
+                settings.max_packet_size,
+                settings.init_seq_num,
+            ),
+            pacing: TokenBucket::new(settings.max_burst),
+            buffer_time: Duration::ZERO,
+            buffer_busy_since: None,
+            ack2_mode: settings.ack2_mode,
+            last_ack2: None,
+            lite_ack_rtt_sampling: settings.lite_ack_rtt_sampling,
+            last_rtt_update: None,
+            deliveries: Deliveries::default(),
         }
     }
 
@@ -53,10 +75,23 @@ impl Sender {
         self.send_buffer.has_packets_to_send()
     }
 
+    pub fn has_buffer_room(&self) -> bool {
+        self.send_buffer.has_room()
+    }
+
     pub fn tx_buffered_time(&self) -> Duration {
         self.send_buffer.duration()
     }
 
+    /// The total time the send buffer has held data, up until now
+    pub fn tx_buffer_time(&self, now: Instant) -> Duration {
+        let busy = self
+            .buffer_busy_since
+            .map(|since| now.saturating_duration_since(since))
+            .unwrap_or_default();
+        self.buffer_time + busy
+    }
+
     pub fn tx_buffered_packets(&self) -> u64 {
         u64::try_from(self.send_buffer.len()).unwrap()
     }
@@ -64,6 +99,88 @@ impl Sender {
     pub fn tx_buffered_bytes(&self) -> u64 {
         u64::try_from(self.send_buffer.len_bytes()).unwrap()
     }
+
+    pub fn tx_unacknowledged_packets(&self) -> u64 {
+        u64::try_from(self.send_buffer.unacked_len()).unwrap()
+    }
+
+    pub fn tx_unacknowledged_bytes(&self) -> u64 {
+        u64::try_from(self.send_buffer.unacked_len_bytes()).unwrap()
+    }
+
+    pub fn tx_unacknowledged_age(&self, now: Instant) -> Duration {
+        let ts_now = self.time_base.timestamp_from(now);
+        self.send_buffer.oldest_unacked_age(ts_now)
+    }
+
+    pub fn tx_loss_list_length(&self) -> u64 {
+        u64::try_from(self.send_buffer.lost_list_len()).unwrap()
+    }
+
+    /// Whether `ack_number` acknowledges packets that were sent, and not before the oldest one
+    /// still buffered
+    pub fn is_ack_in_window(&self, ack_number: SeqNumber) -> bool {
+        self.send_buffer.check_ack(ack_number, None, None).is_ok()
+    }
+
+    pub fn key_material_state(&self) -> KeyMaterialState {
+        self.encryption.key_material_state()
+    }
+
+    /// Encrypt a serialized control packet with the key data is sent with, see
+    /// [`Encryption::encrypt_control`]
+    pub fn encrypt_control(
+        &mut self,
+        socket_id: SocketId,
+        data: &mut [u8],
+    ) -> Option<(DataEncryption, u64)> {
+        self.encryption.encrypt_control(socket_id, data)
+    }
+
+    pub fn next_delivery(&mut self) -> Option<(Range<SeqNumber>, Delivery)> {
+        self.deliveries.pop_report()
+    }
+
+    pub fn memory_usage(&self, usage: &mut MemoryUsage) {
+        self.send_buffer.memory_usage(usage);
+        usage.history += self.deliveries.memory_usage();
+    }
+
+    pub(crate) fn snapshot(&self) -> SenderSnapshot {
+        let (buffer, next_send) = self.send_buffer.snapshot();
+        SenderSnapshot {
+            next_sequence_number: self.encapsulation.next_sequence_number(),
+            next_message_number: self.encapsulation.next_message_number(),
+            buffer,
+            next_send,
+            keys: self.encryption.snapshot(),
+        }
+    }
+
+    /// Pick up where the sender of a snapshot left off, the packets it had sent but weren't
+    /// acknowledged yet are retransmitted once their RTO is up
+    pub(crate) fn restore(
+        mut settings: ConnectionSettings,
+        now: Instant,
+        snapshot: SenderSnapshot,
+    ) -> Self {
+        settings.init_seq_num = snapshot
+            .buffer
+            .first()
+            .map_or(snapshot.next_sequence_number, |p| p.seq_number);
+        let mut sender = Self::new(settings);
+        sender
+            .encapsulation
+            .restore(snapshot.next_sequence_number, snapshot.next_message_number);
+        if let Some(keys) = snapshot.keys {
+            sender.encryption.restore(keys);
+        }
+        let ts_now = sender.time_base.timestamp_from(now);
+        sender
+            .send_buffer
+            .restore(ts_now, snapshot.buffer, snapshot.next_send);
+        sender
+    }
 }
 
 pub struct SenderContext<'a> {
@@ -91,11 +208,42 @@ impl<'a> SenderContext<'a> {
         }
     }
 
-    pub fn handle_data(&mut self, now: Instant, item: (Instant, Bytes)) {
-        let (time, data) = item;
+    pub fn handle_data(&mut self, now: Instant, message: SendMessage) {
+        let _ = self.push_data(now, message);
+    }
+
+    pub fn handle_tracked_data(
+        &mut self,
+        now: Instant,
+        message: SendMessage,
+    ) -> Option<Range<SeqNumber>> {
+        let packets = self.push_data(now, message)?;
+        // a message larger than the send buffer pushes its own start out
+        match self.sender.send_buffer.front_packet() {
+            Some(front) if front <= packets.start => self.sender.deliveries.track(packets.clone()),
+            _ => self
+                .sender
+                .deliveries
+                .report(packets.clone(), Delivery::Dropped),
+        }
+        Some(packets)
+    }
+
+    // the sequence numbers of the message's packets
+    fn push_data(&mut self, now: Instant, message: SendMessage) -> Option<Range<SeqNumber>> {
         let (mut packets, mut bytes) = (0, 0);
-        let ts = self.sender.time_base.timestamp_from(time);
-        for packet in self.sender.encapsulation.encapsulate(ts, data) {
+        let mut sequence_numbers: Option<Range<SeqNumber>> = None;
+        let time_base = &self.sender.time_base;
+        let ts = time_base.timestamp_from(message.src_time);
+        let expires = message.expires().map(|at| time_base.timestamp_from(at));
+        let packets_of_message =
+            self.sender
+                .encapsulation
+                .encapsulate(ts, message.data, message.inorder);
+        for packet in packets_of_message {
+            let seq_number = packet.seq_number;
+            let range = sequence_numbers.get_or_insert(seq_number..seq_number);
+            range.end = seq_number + 1;
             if let Some((bytes_enc, packet, km)) = self.sender.encryption.encrypt(packet) {
                 packets += 1;
                 bytes += packet.payload.len() as u64;
@@ -103,9 +251,13 @@ impl<'a> SenderContext<'a> {
                     self.stats.tx_encrypted_data += 1;
                 }
 
-                if let Err((p_count, b_count)) = self.sender.send_buffer.push_data(packet) {
-                    self.stats.tx_dropped_data += p_count.0;
+                if let Err((dropped, b_count)) = self.sender.send_buffer.push_data(packet, expires)
+                {
+                    let count = u64::from(dropped.end - dropped.start);
+                    self.stats.tx_dropped_data += count;
                     self.stats.tx_dropped_bytes += b_count.0;
+                    self.stats.tx_buffer_overflow_data += count;
+                    self.sender.deliveries.on_drop(dropped);
                 }
 
                 let control = km.map(ControlTypes::new_key_refresh_request);
@@ -119,42 +271,86 @@ impl<'a> SenderContext<'a> {
             self.sender
                 .congestion_control
                 .on_input(now, PacketCount(packets), ByteCount(bytes));
-        if let Some(snd_period) = snd_period {
-            self.timers.update_snd_period(snd_period)
-        }
+        self.update_snd_period(snd_period);
+
+        self.update_gauges(now);
+        sequence_numbers
     }
 
     pub fn handle_ack_packet(&mut self, now: Instant, ack: Acknowledgement) {
         self.stats.rx_ack += 1;
-        if matches!(ack, Acknowledgement::Lite(_)) {
+        let light = matches!(ack, Acknowledgement::Lite(_));
+        if light {
             self.stats.rx_light_ack += 1;
         }
+        // before anything takes from it, an ACK that doesn't fit what was sent could have the
+        // sender give up on packets, or time its retransmissions by the peer's say so
+        if self
+            .sender
+            .send_buffer
+            .check_ack(ack.ack_number(), ack.full_ack_seq_number(), ack.rtt())
+            .is_err()
+        {
+            self.stats.rx_ack_ignored += 1;
+            return;
+        }
+        if light {
+            self.sample_light_ack_rtt(now, ack.ack_number());
+        } else if ack.rtt().is_some() {
+            self.sender.last_rtt_update = Some(now);
+        }
 
-        match self.sender.send_buffer.update_largest_acked_seq_number(
+        if let Ok(AckAction {
+            received: _,
+            recovered: _,
+            send_ack2,
+            fast_retransmit,
+        }) = self.sender.send_buffer.update_largest_acked_seq_number(
             ack.ack_number(),
             ack.full_ack_seq_number(),
             ack.rtt(),
         ) {
-            Ok(AckAction {
-                received: _,
-                recovered: _,
-                send_ack2,
-            }) => {
-                // TODO: add received and recovered to connection statistics
-                if let Some(full_ack) = send_ack2 {
-                    self.output.send_control(now, ControlTypes::Ack2(full_ack))
-                }
+            self.sender.deliveries.on_ack(ack.ack_number());
+            if let Some(statistics) = ack.statistics() {
+                self.sender
+                    .send_buffer
+                    .update_peer_buffer_available(ack.ack_number(), statistics.buffer_available);
+            }
+            // TODO: add received and recovered to connection statistics
+            if fast_retransmit.is_some() {
+                self.stats.tx_fast_retransmit_data += 1;
             }
-            Err(_error) => {
-                // self.warn("ack", now, &error);
-                // TODO: add statistic
-                // self.statistics.rx_ack2_errors += 1;
+            if let Some(full_ack) =
+                send_ack2.filter(|full_ack| self.should_send_ack2(now, *full_ack))
+            {
+                self.sender.last_ack2 = Some((now, full_ack));
+                self.output.send_control(now, ControlTypes::Ack2(full_ack))
             }
         }
+
+        let estimated_link_capacity = ack.statistics().and_then(|s| s.estimated_link_capacity);
+        let snd_period = self
+            .sender
+            .congestion_control
+            .on_ack(now, estimated_link_capacity);
+        self.update_snd_period(snd_period);
+
+        self.update_gauges(now);
     }
 
     pub fn handle_nak_packet(&mut self, now: Instant, nak: CompressedLossList) {
         self.stats.rx_nak += 1;
+        // like libsrt, a NAK with a range that doesn't fit what was sent is dropped as a whole
+        if !self.sender.send_buffer.check_loss_report(&nak) {
+            self.stats.rx_nak_ignored += 1;
+            return;
+        }
+        if let Some(first_lost) = nak.iter_decompressed().next() {
+            let next_send = self.sender.send_buffer.next_send_seq_number();
+            let snd_period = self.sender.congestion_control.on_nak(first_lost, next_send);
+            self.update_snd_period(snd_period);
+        }
+
         // 1) Add all sequence numbers carried in the NAK into the sender's loss list.
         for (loss, range) in self.sender.send_buffer.add_to_loss_list(nak) {
             //self.debug("nak", now, &(&loss, &range));
@@ -164,7 +360,7 @@ impl<'a> SenderContext<'a> {
                 Ignored | Added => {
                     self.stats.tx_loss_data += 1;
                 }
-                Dropped => {
+                Dropped(message) => {
                     self.stats.tx_dropped_data += 1;
 
                     // On a Live stream, where each packet is a message, just one NAK with
@@ -180,11 +376,33 @@ impl<'a> SenderContext<'a> {
                     // drop request, if the message is still in the send buffer. We always send
                     self.output.send_control(
                         now,
-                        ControlTypes::new_drop_request(MsgNumber::new_truncate(0), range),
+                        ControlTypes::new_drop_request(
+                            message.unwrap_or_else(|| MsgNumber::new_truncate(0)),
+                            range,
+                        ),
                     )
                 }
             }
         }
+
+        self.update_gauges(now);
+    }
+
+    pub fn handle_congestion_experienced(&mut self, packets: u32) {
+        self.stats.tx_congestion_experienced += u64::from(packets);
+        let next_send = self.sender.send_buffer.next_send_seq_number();
+        let snd_period = self
+            .sender
+            .congestion_control
+            .on_congestion_experienced(next_send);
+        self.update_snd_period(snd_period);
+    }
+
+    fn update_snd_period(&mut self, snd_period: Option<Duration>) {
+        if let Some(snd_period) = snd_period {
+            self.timers.update_snd_period(snd_period);
+            self.stats.tx_snd_period = snd_period;
+        }
     }
 
     pub fn handle_key_refresh_response(&mut self, keying_material: KeyingMaterialMessage) {
@@ -205,26 +423,58 @@ impl<'a> SenderContext<'a> {
     pub fn on_snd_event(&mut self, now: Instant, elapsed_periods: u32) {
         use SenderAction::*;
         let ts_now = self.sender.time_base.timestamp_from(now);
+        let packets_to_send = self.sender.pacing.refill(elapsed_periods);
+        let mut sent = 0;
         let actions = self.sender.send_buffer.next_snd_actions(
             ts_now,
-            elapsed_periods,
+            packets_to_send,
             self.status.should_drain_send_buffer(),
         );
         for action in actions {
             match action {
                 Send(d) => {
+                    sent += 1;
                     self.stats.tx_unique_data += 1;
+                    self.stats.tx_unique_bytes += d.wire_size() as u64;
                     self.output.send_data(now, d);
                 }
                 RetransmitNak(d) => {
+                    sent += 1;
                     self.stats.tx_retransmit_data += 1;
+                    self.stats.tx_retransmit_bytes += d.wire_size() as u64;
                     self.output.send_data(now, d);
                 }
                 RetransmitRto(d) => {
+                    sent += 1;
                     self.stats.tx_retransmit_data += 1;
+                    self.stats.tx_retransmit_bytes += d.wire_size() as u64;
+                    self.output.send_data(now, d);
+                }
+                Duplicate(d) => {
+                    sent += 1;
+                    self.stats.tx_duplicate_data += 1;
+                    self.stats.tx_duplicate_bytes += d.wire_size() as u64;
                     self.output.send_data(now, d);
                 }
-                Drop(_) => {}
+                Drop(range) => {
+                    self.stats.tx_dropped_data += u64::from(range.end - range.start);
+                    self.sender.deliveries.on_drop(range);
+                }
+                // the peer isn't going to drop it on its own, it may not be too late yet
+                DropExpired(message, range) => {
+                    self.stats.tx_dropped_data += u64::from(range.end - range.start);
+                    self.sender.deliveries.on_drop(range.clone());
+                    self.output
+                        .send_control(now, ControlTypes::new_drop_request(message, range));
+                }
+                // it will be, the peer can skip it right away rather than wait for its TSBPD time
+                DropLate(message, range) => {
+                    self.stats.tx_dropped_data += u64::from(range.end - range.start);
+                    self.stats.tx_late_dropped_messages += 1;
+                    self.sender.deliveries.on_drop(range.clone());
+                    self.output
+                        .send_control(now, ControlTypes::new_drop_request(message, range));
+                }
                 WaitForInput => {
                     break;
                 }
@@ -233,5 +483,64 @@ impl<'a> SenderContext<'a> {
                 }
             }
         }
+        self.sender.pacing.consume(sent);
+
+        self.update_gauges(now);
+    }
+
+    fn should_send_ack2(&self, now: Instant, full_ack: FullAckSeqNumber) -> bool {
+        match (self.sender.ack2_mode, self.sender.last_ack2) {
+            (Ack2Mode::EveryFullAck, _) | (Ack2Mode::Throttled, None) => true,
+            // the peer didn't receive the last ACK2 and is repeating the ACK
+            (Ack2Mode::Throttled, Some((_, last))) if last == full_ack => true,
+            (Ack2Mode::Throttled, Some((last_time, _))) => now - last_time >= Timers::SYN,
+        }
+    }
+
+    // fill in RTT samples from light ACKs when full ACKs haven't been updating the RTT
+    fn sample_light_ack_rtt(&mut self, now: Instant, ack_number: SeqNumber) {
+        let stale = match self.sender.last_rtt_update {
+            Some(last) => now - last >= Timers::SYN,
+            None => true,
+        };
+        if !self.sender.lite_ack_rtt_sampling || !stale {
+            return;
+        }
+        let ts_now = self.sender.time_base.timestamp_from(now);
+        if self
+            .sender
+            .send_buffer
+            .sample_light_ack_rtt(ts_now, ack_number)
+            .is_some()
+        {
+            self.sender.last_rtt_update = Some(now);
+        }
+    }
+
+    fn update_gauges(&mut self, now: Instant) {
+        self.update_buffer_time(now);
+        self.stats.tx_buffered_time = self.sender.tx_buffered_time();
+        self.stats.tx_buffered_data = self.sender.tx_buffered_packets();
+        self.stats.tx_buffered_bytes = self.sender.tx_buffered_bytes();
+        self.stats.tx_loss_list_length = self.sender.tx_loss_list_length();
+        self.stats.tx_dropped_messages = self.sender.send_buffer.dropped_message_count();
+        self.stats.tx_unacknowledged_data = self.sender.tx_unacknowledged_packets();
+        self.stats.tx_unacknowledged_bytes = self.sender.tx_unacknowledged_bytes();
+        let rtt = self.sender.send_buffer.rtt();
+        self.stats.tx_average_rtt = rtt.mean_as_duration();
+        self.stats.tx_rtt_variance = rtt.variance_as_duration();
+    }
+
+    // accumulate the time during which the send buffer was not empty (usSndDuration)
+    fn update_buffer_time(&mut self, now: Instant) {
+        let sender = &mut self.sender;
+        match (sender.send_buffer.is_flushed(), sender.buffer_busy_since) {
+            (false, None) => sender.buffer_busy_since = Some(now),
+            (true, Some(since)) => {
+                sender.buffer_time += now.saturating_duration_since(since);
+                sender.buffer_busy_since = None;
+            }
+            _ => {}
+        }
     }
 }
@@ -0,0 +1,70 @@
+use std::cmp::min;
+
+use crate::options::PacketCount;
+
+/// Limits how many packets go out in a single send tick.
+///
+/// One token is earned per elapsed send period, up to `max_burst` tokens, and every packet sent
+/// (fresh or retransmitted) spends one. Without a limit, every elapsed period is spent at once.
+#[derive(Debug)]
+pub struct TokenBucket {
+    max_burst: Option<u32>,
+    tokens: u32,
+}
+
+impl TokenBucket {
+    pub fn new(max_burst: PacketCount) -> Self {
+        let max_burst = u32::try_from(max_burst.0).unwrap_or(u32::MAX);
+        Self {
+            max_burst: Some(max_burst).filter(|max| *max > 0),
+            tokens: 0,
+        }
+    }
+
+    /// Returns the number of packets that may be sent in this tick
+    pub fn refill(&mut self, elapsed_periods: u32) -> u32 {
+        match self.max_burst {
+            Some(max_burst) => {
+                self.tokens = min(self.tokens.saturating_add(elapsed_periods), max_burst);
+                self.tokens
+            }
+            None => elapsed_periods,
+        }
+    }
+
+    pub fn consume(&mut self, packets: u32) {
+        self.tokens = self.tokens.saturating_sub(packets);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unlimited() {
+        let mut bucket = TokenBucket::new(PacketCount(0));
+        assert_eq!(bucket.refill(1), 1);
+        bucket.consume(1);
+        assert_eq!(bucket.refill(50), 50);
+    }
+
+    #[test]
+    fn burst_limit() {
+        let mut bucket = TokenBucket::new(PacketCount(4));
+
+        // a late tick can't send more than the burst
+        assert_eq!(bucket.refill(10), 4);
+        bucket.consume(4);
+        assert_eq!(bucket.refill(1), 1);
+
+        // unspent tokens carry over, up to the burst
+        bucket.consume(0);
+        assert_eq!(bucket.refill(2), 3);
+        assert_eq!(bucket.refill(2), 4);
+
+        // the 16n probe packet can overspend the bucket
+        bucket.consume(5);
+        assert_eq!(bucket.refill(1), 1);
+    }
+}
@@ -1,5 +1,4 @@
-use std::time::Instant;
-
+use super::Instant;
 use crate::packet::{TimeSpan, TimeStamp};
 
 #[derive(Copy, Clone, Debug)]
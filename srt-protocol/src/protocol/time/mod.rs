@@ -1,21 +1,33 @@
-mod base;
 mod rtt;
-mod timer;
 
-pub use base::*;
 pub use rtt::*;
-pub use timer::*;
 
-use std::{
-    cmp::{max, min},
-    time::{Duration, Instant},
-};
+cfg_std! {
+    mod base;
+    mod timer;
+
+    pub use base::*;
+    pub use timer::*;
+
+    // std's Instant::now panics on wasm32-unknown-unknown, where the browser's clock is read instead.
+    // Everywhere else these are std's own, so nothing changes for callers
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    pub use std::time::{Instant, SystemTime, UNIX_EPOCH};
+    #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+    pub use web_time::{Instant, SystemTime, UNIX_EPOCH};
+
+    use std::{
+        cmp::{max, min},
+        time::Duration,
+    };
+}
 
 //   The recommended granularity of their periods is microseconds. The
 //   system time is queried after each time bounded UDP receiving (there
 //   will be additional necessary data processing time if a UDP packet is
 //   received) to check if any of the ACK, NAK, or EXP event should be
 //   triggered. The timeout value of UDP receiving should be at least SYN.
+#[cfg(feature = "std")]
 #[derive(Debug)]
 pub struct Timers {
     snd: Timer,
@@ -43,6 +55,7 @@ pub struct Timers {
     statistics: Timer,
 }
 
+#[cfg(feature = "std")]
 impl Timers {
     pub const SYN: Duration = Duration::from_millis(10);
     const EXP_MAX: u32 = 16;
@@ -273,7 +286,7 @@ mod tests {
     fn next_timer() {
         let ms = TimeSpan::from_millis;
         let start = Instant::now();
-        let mut timers = Timers::new(start, Duration::MAX, ms(5_000));
+        let mut timers = Timers::new(start, Duration::MAX, Duration::from_secs(5));
 
         // next timer should be ack, 10ms
         let now = start;
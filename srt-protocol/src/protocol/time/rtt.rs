@@ -1,4 +1,4 @@
-use std::{convert::TryInto, time::Duration};
+use core::{convert::TryInto, time::Duration};
 
 use crate::packet::TimeSpan;
 
@@ -18,6 +18,9 @@ impl Default for Rtt {
 }
 
 impl Rtt {
+    // well past anything a connection that is still up could see
+    const MAX_MEAN: TimeSpan = TimeSpan::from_millis(60_000);
+
     pub fn new(mean: TimeSpan, variance: TimeSpan) -> Self {
         Self { mean, variance }
     }
@@ -33,6 +36,12 @@ impl Rtt {
         );
     }
 
+    /// Whether a peer could have measured it, one that reports anything else is broken or lying
+    pub fn is_plausible(&self) -> bool {
+        (0..=Self::MAX_MEAN.as_micros()).contains(&self.mean.as_micros())
+            && self.variance.as_micros() >= 0
+    }
+
     pub fn mean(&self) -> TimeSpan {
         self.mean
     }
@@ -1,6 +1,8 @@
 use core::u32;
 use std::cmp::max;
-use std::time::{Duration, Instant};
+use std::time::Duration;
+
+use super::Instant;
 
 //4. Timers
 //
@@ -1,26 +1,117 @@
-use std::{convert::TryInto, marker::PhantomData, net::SocketAddr};
+use std::{convert::TryInto, marker::PhantomData, mem, net::SocketAddr, time::Duration};
 
-use crate::{packet::RejectReason, settings::KeySettings};
+use crate::{
+    options::{LiveBandwidthMode, PacketCount},
+    packet::RejectReason,
+    settings::{ConnInitSettings, KeySettings, KeySource, SharedKeyProvider},
+};
+
+/// Settings that apply to a single accepted connection, in place of those the listener was
+/// configured with. Unset fields keep the listener's value.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ConnectionSettingsOverride {
+    /// The receive latency, and the latency requested of the peer (SRTO_LATENCY)
+    pub latency: Option<Duration>,
+    /// The key source and key size to encrypt the connection with
+    pub key_settings: Option<KeySettings>,
+    /// Receive buffer size, in packets
+    pub recv_buffer_size: Option<PacketCount>,
+    /// Send buffer size, in packets
+    pub send_buffer_size: Option<PacketCount>,
+    /// The sender's bandwidth limit (SRTO_MAXBW, SRTO_INPUTBW, ...)
+    pub bandwidth: Option<LiveBandwidthMode>,
+}
+
+impl ConnectionSettingsOverride {
+    /// Fill in the fields that are unset with those of `defaults`
+    pub fn or(self, defaults: Self) -> Self {
+        Self {
+            latency: self.latency.or(defaults.latency),
+            key_settings: self.key_settings.or(defaults.key_settings),
+            recv_buffer_size: self.recv_buffer_size.or(defaults.recv_buffer_size),
+            send_buffer_size: self.send_buffer_size.or(defaults.send_buffer_size),
+            bandwidth: self.bandwidth.or(defaults.bandwidth),
+        }
+    }
+
+    pub fn apply(self, settings: &mut ConnInitSettings) {
+        if let Some(latency) = self.latency {
+            settings.send_latency = latency;
+            settings.recv_latency = latency;
+        }
+        if let Some(key_settings) = self.key_settings {
+            settings.key_settings = Some(key_settings);
+        }
+        if let Some(recv_buffer_size) = self.recv_buffer_size {
+            settings.recv_buffer_size = recv_buffer_size;
+        }
+        if let Some(send_buffer_size) = self.send_buffer_size {
+            settings.send_buffer_size = send_buffer_size;
+        }
+        if let Some(bandwidth) = self.bandwidth {
+            settings.bandwidth = bandwidth;
+        }
+    }
+}
+
+impl From<Option<KeySettings>> for ConnectionSettingsOverride {
+    fn from(key_settings: Option<KeySettings>) -> Self {
+        Self {
+            key_settings,
+            ..Default::default()
+        }
+    }
+}
 
 pub struct AcceptParameters {
-    key_settings: Option<KeySettings>,
+    settings_override: ConnectionSettingsOverride,
 }
 
 impl AcceptParameters {
     pub fn new() -> AcceptParameters {
-        AcceptParameters { key_settings: None }
+        AcceptParameters {
+            settings_override: ConnectionSettingsOverride::default(),
+        }
     }
 
     pub fn set_key_settings(&mut self, passphrase: impl Into<String>, size: u16) -> &mut Self {
-        self.key_settings = Some(KeySettings {
+        self.settings_override.key_settings = Some(KeySettings {
+            key_size: size.try_into().unwrap(),
+            key_source: KeySource::Passphrase(passphrase.into().try_into().unwrap()),
+        });
+        self
+    }
+
+    pub fn set_key_provider(&mut self, provider: SharedKeyProvider, size: u16) -> &mut Self {
+        self.settings_override.key_settings = Some(KeySettings {
             key_size: size.try_into().unwrap(),
-            passphrase: passphrase.into().try_into().unwrap(),
+            key_source: KeySource::Provider(provider),
         });
         self
     }
 
+    pub fn set_latency(&mut self, latency: Duration) -> &mut Self {
+        self.settings_override.latency = Some(latency);
+        self
+    }
+
+    pub fn set_buffer_sizes(&mut self, recv: PacketCount, send: PacketCount) -> &mut Self {
+        self.settings_override.recv_buffer_size = Some(recv);
+        self.settings_override.send_buffer_size = Some(send);
+        self
+    }
+
+    pub fn set_bandwidth(&mut self, bandwidth: LiveBandwidthMode) -> &mut Self {
+        self.settings_override.bandwidth = Some(bandwidth);
+        self
+    }
+
     pub fn take_key_settings(&mut self) -> Option<KeySettings> {
-        self.key_settings.take()
+        self.settings_override.key_settings.take()
+    }
+
+    pub fn take_settings_override(&mut self) -> ConnectionSettingsOverride {
+        mem::take(&mut self.settings_override)
     }
 }
 
@@ -2,7 +2,11 @@ use std::time::Duration;
 
 use rand::random;
 
-use crate::{options, packet::Packet};
+use crate::{
+    options,
+    packet::{Packet, SeqNumber, SrtShakeFlags},
+    protocol::time::Instant,
+};
 
 use super::*;
 
@@ -11,6 +15,8 @@ pub struct ConnInitSettings {
     pub local_sockid: SocketId,
     pub key_settings: Option<KeySettings>,
     pub key_refresh: KeyMaterialRefreshSettings,
+    /// Offer to encrypt control packets, when there's a passphrase
+    pub encrypt_control: bool,
     pub send_latency: Duration,
     pub recv_latency: Duration,
     pub peer_idle_timeout: Duration,
@@ -21,8 +27,44 @@ pub struct ConnInitSettings {
     pub recv_buffer_size: options::PacketCount,
     /// Size of the send buffer, in packets
     pub send_buffer_size: options::PacketCount,
+    /// The most the send buffer may hold, in bytes on the wire
+    pub send_buffer_bytes: Option<options::ByteCount>,
+    /// The most the send buffer may hold, between its oldest and newest packets
+    pub send_buffer_time: Option<Duration>,
+    pub send_buffer_policy: options::SendBufferPolicy,
     pub max_packet_size: options::PacketSize,
     pub max_flow_size: options::PacketCount,
+    pub ack2_mode: options::Ack2Mode,
+    pub lite_ack_rtt_sampling: bool,
+    pub max_burst: options::PacketCount,
+    pub duplicate_interval: Option<Duration>,
+    pub fast_retransmit: Option<u32>,
+    pub sequence_restart_window: options::PacketCount,
+    pub skip_gaps: bool,
+    pub immediate_nak: bool,
+    pub light_ack_interval: options::PacketCount,
+    pub max_delivery_rate: Option<options::DataRate>,
+    pub too_late_packet_drop: bool,
+    pub nak_report: bool,
+    pub half_close: bool,
+    pub limit_payload_size: bool,
+    pub keepalive_interval: Duration,
+    pub adaptive_keepalive: bool,
+    pub message_tags: bool,
+    /// Offer to report Congestion Experienced marks to the peer
+    pub ecn: bool,
+    /// Send and receive messages rather than a byte stream
+    pub message_api: bool,
+    pub peer_address_policy: options::PeerAddressPolicy,
+    pub min_latency: Duration,
+    pub max_latency: Duration,
+    pub latency_policy: options::LatencyPolicy,
+    /// The oldest SRT version of a peer to connect with
+    pub min_version: options::SrtVersion,
+    /// The sequence number to start with when initiating, random if unset
+    pub init_seq_num: Option<SeqNumber>,
+    /// When the timestamps count from, the start of the handshake if unset
+    pub socket_start_time: Option<Instant>,
 }
 
 impl Default for ConnInitSettings {
@@ -38,25 +80,42 @@ impl ConnInitSettings {
             ..self.clone()
         }
     }
+
+    /// The flags of the HSREQ or HSRSP this side sends. Both directions always use TSBPD. A gap
+    /// would corrupt a byte stream, so without the message API packets aren't dropped as too late.
+    pub fn handshake_flags(&self) -> SrtShakeFlags {
+        let mut flags = SrtShakeFlags::TSBPDSND
+            | SrtShakeFlags::TSBPDRCV
+            | SrtShakeFlags::HAICRYPT
+            | SrtShakeFlags::REXMITFLG;
+        flags.set(
+            SrtShakeFlags::TLPKTDROP,
+            self.too_late_packet_drop && self.message_api,
+        );
+        flags.set(SrtShakeFlags::STREAM, !self.message_api);
+        flags.set(SrtShakeFlags::NAKREPORT, self.nak_report);
+        flags
+    }
 }
 
 impl From<options::SocketOptions> for ConnInitSettings {
     fn from(options: options::SocketOptions) -> Self {
         Self {
-            local_sockid: random(),
+            local_sockid: options.session.socket_id.unwrap_or_else(random),
             key_settings: options
                 .encryption
-                .passphrase
-                .clone()
-                .map(|passphrase| KeySettings {
+                .key_source()
+                .map(|key_source| KeySettings {
                     key_size: options.encryption.key_size,
-                    passphrase,
+                    key_source,
                 }),
             key_refresh: KeyMaterialRefreshSettings::new(
                 options.encryption.km_refresh.period.into(),
                 options.encryption.km_refresh.pre_announcement_period.into(),
             )
             .unwrap(),
+            encrypt_control: options.encryption.encrypt_control
+                && options.encryption.key_source().is_some(),
             send_latency: options.sender.peer_latency,
             recv_latency: options.receiver.latency,
             peer_idle_timeout: options.session.peer_idle_timeout,
@@ -66,8 +125,41 @@ impl From<options::SocketOptions> for ConnInitSettings {
                 / (options.session.max_segment_size - Packet::HEADER_SIZE),
             send_buffer_size: options.sender.buffer_size
                 / (options.session.max_segment_size - Packet::HEADER_SIZE),
-            max_packet_size: options.sender.max_payload_size,
+            send_buffer_bytes: options.sender.max_buffer_bytes,
+            send_buffer_time: options.sender.max_buffer_time,
+            send_buffer_policy: options.sender.buffer_full_policy,
+            // without a limit messages are split into packets as large as the MSS allows
+            max_packet_size: match options.sender.max_payload_size {
+                options::PacketSize(0) => options.session.max_segment_size - Packet::HEADER_SIZE,
+                max_payload_size => max_payload_size,
+            },
             max_flow_size: options.sender.flow_control_window_size,
+            ack2_mode: options.sender.ack2_mode,
+            lite_ack_rtt_sampling: options.sender.lite_ack_rtt_sampling,
+            max_burst: options.sender.max_burst,
+            duplicate_interval: options.sender.duplicate_interval,
+            fast_retransmit: options.sender.fast_retransmit,
+            sequence_restart_window: options.receiver.sequence_restart_window,
+            skip_gaps: options.receiver.skip_gaps,
+            immediate_nak: options.receiver.immediate_nak,
+            light_ack_interval: options.receiver.light_ack_interval,
+            max_delivery_rate: options.receiver.max_delivery_rate,
+            too_late_packet_drop: options.receiver.too_late_packet_drop,
+            nak_report: options.receiver.nak_report,
+            half_close: options.session.half_close,
+            limit_payload_size: options.sender.limit_payload_size,
+            keepalive_interval: options.session.keepalive_interval,
+            adaptive_keepalive: options.session.adaptive_keepalive,
+            message_tags: options.session.message_tags,
+            ecn: options.connect.ecn,
+            message_api: options.session.message_api,
+            peer_address_policy: options.session.peer_address_policy,
+            min_latency: options.session.min_latency,
+            max_latency: options.session.max_latency,
+            latency_policy: options.session.latency_policy,
+            min_version: options.connect.min_version,
+            init_seq_num: options.session.initial_sequence_number,
+            socket_start_time: options.session.start_time,
         }
     }
 }
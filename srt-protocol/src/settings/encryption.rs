@@ -1,19 +1,64 @@
 use std::fmt::{self, Debug, Display, Formatter};
 
+use crate::protocol::encryption::provider::derive_key;
+
 pub use crate::{
-    options::{KeySize, Passphrase},
+    options::{KeyDerivation, KeySize, Passphrase},
     packet::{DataEncryption, KeyingMaterialMessage},
     protocol::encryption::{
-        key::WrapInitializationVector,
+        backend::{install_crypto_backend, CryptoBackend, CryptoError},
         key::{EncryptionKey, Salt},
+        provider::{KeyProvider, KeyProviderError, SharedKeyProvider},
         stream::{KeyMaterialError, StreamEncryptionKeys},
+        KeyMaterialState,
     },
 };
 
+#[cfg(feature = "openssl")]
+pub use crate::protocol::encryption::backend::OpenSsl;
+#[cfg(feature = "rust-crypto")]
+pub use crate::protocol::encryption::backend::RustCrypto;
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct KeySettings {
     pub key_size: KeySize,
-    pub passphrase: Passphrase,
+    pub key_source: KeySource,
+}
+
+/// Where the key encrypting key comes from, see [`KeyProvider`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum KeySource {
+    Passphrase(Passphrase),
+    /// A passphrase derived with other parameters than libsrt's or an initial salt
+    DerivedPassphrase(Passphrase, KeyDerivation),
+    Provider(SharedKeyProvider),
+}
+
+impl KeyProvider for KeySource {
+    fn key_encrypting_key(
+        &self,
+        key_size: KeySize,
+        salt: &Salt,
+    ) -> Result<EncryptionKey, KeyProviderError> {
+        match self {
+            KeySource::Passphrase(passphrase) => passphrase.key_encrypting_key(key_size, salt),
+            KeySource::DerivedPassphrase(passphrase, derivation) => {
+                derive_key(passphrase, derivation, key_size, salt)
+            }
+            KeySource::Provider(provider) => provider.key_encrypting_key(key_size, salt),
+        }
+    }
+
+    fn new_salt(&self) -> Salt {
+        match self {
+            KeySource::Passphrase(passphrase) => passphrase.new_salt(),
+            KeySource::DerivedPassphrase(_, derivation) => derivation
+                .initial_salt
+                .clone()
+                .unwrap_or_else(Salt::new_random),
+            KeySource::Provider(provider) => provider.new_salt(),
+        }
+    }
 }
 
 // https://datatracker.ietf.org/doc/html/draft-sharabayko-srt-00#section-6
@@ -39,16 +84,21 @@ pub struct KeyMaterialRefreshSettingsError(usize, usize);
 
 impl Display for KeyMaterialRefreshSettingsError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "KM Refresh Period ({}) must be non-zero and greater than 1/2 the KM Pre Announce Period ({}).", self.0, self.1)
+        write!(f, "KM Refresh Period ({}) must be non-zero, less than 2^31 and greater than 1/2 the KM Pre Announce Period ({}).", self.0, self.1)
     }
 }
 
 impl KeyMaterialRefreshSettings {
+    /// The packet index in the IV of a packet is its 31 bit sequence number, so a key encrypting
+    /// more packets than that would repeat its keystream. The first key of a connection encrypts
+    /// one packet more than the period.
+    pub const MAX_PERIOD: usize = (1 << 31) - 1;
+
     pub fn new(
         period: usize,
         pre_announcement_period: usize,
     ) -> Result<Self, KeyMaterialRefreshSettingsError> {
-        if period > 0 && period / pre_announcement_period >= 2 {
+        if period > 0 && period <= Self::MAX_PERIOD && period / pre_announcement_period >= 2 {
             Ok(Self {
                 period,
                 pre_announcement_period,
@@ -107,7 +157,10 @@ impl CipherSettings {
         Self {
             key_settings: key_settings.clone(),
             key_refresh: km_refresh.clone(),
-            stream_keys: StreamEncryptionKeys::new_random(key_settings.key_size),
+            stream_keys: StreamEncryptionKeys::new_random_with_salt(
+                key_settings.key_source.new_salt(),
+                key_settings.key_size,
+            ),
         }
     }
 
@@ -1,9 +1,11 @@
 mod accesscontrol;
 mod connection;
 mod encryption;
+mod socket_id;
 
 pub use accesscontrol::*;
 pub use connection::*;
 pub use encryption::*;
+pub use socket_id::*;
 
 pub use crate::packet::SocketId;
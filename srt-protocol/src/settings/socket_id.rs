@@ -0,0 +1,78 @@
+use std::{
+    collections::BTreeSet,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use rand::random;
+
+use super::SocketId;
+
+// The socket ids of the connections of the process, whichever UDP socket or listener they are
+// on. Packets are routed by destination socket id, so two connections that picked the same random
+// id on a shared port would get each other's packets.
+static IN_USE: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+
+/// A socket id that no other connection of the process gets until the lease is dropped
+#[derive(Debug, Eq, PartialEq, Hash)]
+pub struct SocketIdLease(SocketId);
+
+impl SocketIdLease {
+    /// A random socket id that isn't in use, other than 0, which handshakes with a listener use
+    pub fn allocate() -> Self {
+        let mut in_use = in_use();
+        loop {
+            let socket_id: SocketId = random();
+            if socket_id != SocketId(0) && in_use.insert(socket_id.0) {
+                return Self(socket_id);
+            }
+        }
+    }
+
+    /// Lease a socket id the application picked, unless it's in use already
+    pub fn reserve(socket_id: SocketId) -> Option<Self> {
+        // the lease may only be made once the id is ours, dropping it releases the id
+        if in_use().insert(socket_id.0) {
+            Some(Self(socket_id))
+        } else {
+            None
+        }
+    }
+
+    pub fn socket_id(&self) -> SocketId {
+        self.0
+    }
+}
+
+impl Drop for SocketIdLease {
+    fn drop(&mut self) {
+        in_use().remove(&self.0 .0);
+    }
+}
+
+fn in_use() -> MutexGuard<'static, BTreeSet<u32>> {
+    IN_USE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn leases_are_exclusive() {
+        let lease = SocketIdLease::allocate();
+        let socket_id = lease.socket_id();
+        assert_ne!(socket_id, SocketId(0));
+        assert_eq!(SocketIdLease::reserve(socket_id), None);
+
+        drop(lease);
+        let lease = SocketIdLease::reserve(socket_id).expect("released");
+        assert_eq!(lease.socket_id(), socket_id);
+    }
+
+    #[test]
+    fn allocate_skips_leased() {
+        let leases: Vec<_> = (0..1000).map(|_| SocketIdLease::allocate()).collect();
+        let unique: BTreeSet<_> = leases.iter().map(|l| l.socket_id().0).collect();
+        assert_eq!(unique.len(), leases.len());
+    }
+}
@@ -0,0 +1,144 @@
+use std::{fmt, time::Duration};
+
+use super::{SocketStatistics, StatisticsWindows, WindowedStatistics};
+
+/// When a [`CongestionAlarm`] goes off: once either rate over the last `window` reaches its
+/// threshold. It clears once both are below them again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CongestionThresholds {
+    /// The time the rates are averaged over, up to [`StatisticsWindows::LONGEST_WINDOW`]
+    pub window: Duration,
+
+    /// Retransmitted packets, as a fraction of all the DATA packets sent
+    pub retransmit_rate: f64,
+
+    /// NAKs received per second
+    pub nak_rate: f64,
+}
+
+impl Default for CongestionThresholds {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(1),
+            retransmit_rate: 0.1,
+            nak_rate: 50.,
+        }
+    }
+}
+
+/// A change of the state of a [`CongestionAlarm`], with the rates that caused it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CongestionEvent {
+    /// Whether the alarm went off, or cleared
+    pub congested: bool,
+    pub statistics: WindowedStatistics,
+}
+
+pub type CongestionHandler = Box<dyn FnMut(&CongestionEvent) + Send>;
+
+/// Watches the retransmit ratio and the NAK rate of a connection, from the snapshots of its
+/// statistics, and tells a handler when they cross the [`CongestionThresholds`] and when they're
+/// back to normal, e.g. for a relay to ask its source for a lower bitrate.
+pub struct CongestionAlarm {
+    thresholds: CongestionThresholds,
+    windows: StatisticsWindows,
+    congested: bool,
+    handler: CongestionHandler,
+}
+
+impl CongestionAlarm {
+    pub fn new(thresholds: CongestionThresholds, handler: CongestionHandler) -> Self {
+        Self {
+            thresholds,
+            windows: StatisticsWindows::new(),
+            congested: false,
+            handler,
+        }
+    }
+
+    pub fn is_congested(&self) -> bool {
+        self.congested
+    }
+
+    /// Feed it a snapshot, the handler is called if the state changed
+    pub fn update(&mut self, stats: &SocketStatistics) {
+        self.windows.update(stats);
+        let statistics = match self.windows.window(self.thresholds.window) {
+            Some(statistics) => statistics,
+            None => return,
+        };
+        let congested = statistics.tx_retransmit_rate >= self.thresholds.retransmit_rate
+            || statistics.rx_nak_rate >= self.thresholds.nak_rate;
+        if congested != self.congested {
+            self.congested = congested;
+            (self.handler)(&CongestionEvent {
+                congested,
+                statistics,
+            });
+        }
+    }
+}
+
+impl fmt::Debug for CongestionAlarm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CongestionAlarm")
+            .field("thresholds", &self.thresholds)
+            .field("congested", &self.congested)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    fn stats(secs: u64, sent: u64, retransmitted: u64, naks: u64) -> SocketStatistics {
+        SocketStatistics {
+            elapsed_time: Duration::from_secs(secs),
+            tx_data: sent,
+            tx_retransmit_data: retransmitted,
+            rx_nak: naks,
+            ..SocketStatistics::new()
+        }
+    }
+
+    #[test]
+    fn congestion_alarm() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut alarm = CongestionAlarm::new(
+            CongestionThresholds::default(),
+            Box::new({
+                let events = events.clone();
+                move |event: &CongestionEvent| events.lock().unwrap().push(*event)
+            }),
+        );
+        let congested = || {
+            events
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|e| e.congested)
+                .collect::<Vec<_>>()
+        };
+
+        alarm.update(&stats(0, 0, 0, 0));
+        alarm.update(&stats(1, 1_000, 10, 5));
+        assert_eq!(congested(), []);
+
+        // a NAK storm
+        alarm.update(&stats(2, 2_000, 30, 105));
+        assert_eq!(congested(), [true]);
+        assert_eq!(events.lock().unwrap()[0].statistics.rx_nak_rate, 100.);
+        assert!(alarm.is_congested());
+
+        // still retransmitting a lot, so it doesn't clear yet
+        alarm.update(&stats(3, 3_000, 230, 110));
+        assert_eq!(congested(), [true]);
+
+        alarm.update(&stats(4, 4_000, 240, 115));
+        assert_eq!(congested(), [true, false]);
+        assert!(!alarm.is_congested());
+    }
+}
@@ -0,0 +1,137 @@
+use super::SocketStatistics;
+
+// the fields that only ever grow over the life of a connection, everything else is a gauge
+macro_rules! counters {
+    ($($field:ident),* $(,)?) => {
+        impl SocketStatistics {
+            // whether no counter of self is behind that of earlier
+            fn follows(&self, earlier: &SocketStatistics) -> bool {
+                true $(&& self.$field >= earlier.$field)*
+            }
+
+            fn subtract_counters(&mut self, earlier: &SocketStatistics) {
+                $(self.$field -= earlier.$field;)*
+            }
+        }
+    };
+}
+
+counters!(
+    elapsed_time,
+    tx_all_packets,
+    rx_all_packets,
+    tx_all_bytes,
+    rx_all_bytes,
+    rx_foreign_packets,
+    tx_encrypted_data,
+    rx_decrypted_data,
+    rx_clock_adjustments,
+    rx_ack2_errors,
+    tx_data,
+    rx_data,
+    tx_unique_data,
+    rx_unique_data,
+    tx_loss_data,
+    rx_loss_data,
+    tx_retransmit_data,
+    rx_retransmit_data,
+    tx_ack,
+    rx_ack,
+    rx_ack_ignored,
+    tx_light_ack,
+    rx_light_ack,
+    tx_nak,
+    rx_nak,
+    rx_nak_ignored,
+    rx_congestion_experienced,
+    tx_congestion_experienced,
+    tx_ack2,
+    rx_ack2,
+    tx_buffer_time,
+    tx_dropped_data,
+    rx_dropped_data,
+    rx_decrypt_errors,
+    tx_bytes,
+    rx_bytes,
+    tx_unique_bytes,
+    rx_unique_bytes,
+    rx_loss_bytes,
+    tx_retransmit_bytes,
+    tx_dropped_bytes,
+    tx_dropped_messages,
+    tx_buffer_overflow_data,
+    tx_late_dropped_messages,
+    tx_fast_retransmit_data,
+    tx_duplicate_data,
+    tx_duplicate_bytes,
+    rx_dropped_bytes,
+    rx_decrypt_error_bytes,
+    rx_belated_data,
+    rx_belated_time,
+    rx_retransmit_delay,
+);
+
+impl SocketStatistics {
+    /// What happened between the `previous` snapshot and this one, like libsrt's `srt_bstats`
+    /// with `clear` set: the counters, e.g. `tx_data` or `rx_loss_bytes`, are what they grew by
+    /// and `elapsed_time` is the time between the snapshots. Gauges, e.g. the round trip time or
+    /// the buffer levels, are the current values.
+    ///
+    /// A `previous` snapshot with a counter ahead of this one, i.e. one of another connection, is
+    /// taken as the start of the connection, so the delta is this snapshot as it is.
+    pub fn delta(&self, previous: &SocketStatistics) -> SocketStatistics {
+        let mut delta = self.clone();
+        if self.follows(previous) {
+            delta.subtract_counters(previous);
+        }
+        delta
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn stats(secs: u64, packets: u64, rtt_ms: u64) -> SocketStatistics {
+        SocketStatistics {
+            elapsed_time: Duration::from_secs(secs),
+            tx_data: packets,
+            tx_bytes: packets * 1_000,
+            tx_average_rtt: Duration::from_millis(rtt_ms),
+            tx_buffered_data: packets / 10,
+            ..SocketStatistics::new()
+        }
+    }
+
+    #[test]
+    fn delta() {
+        let delta = stats(12, 5_000, 30).delta(&stats(10, 3_000, 20));
+        assert_eq!(delta.elapsed_time, Duration::from_secs(2));
+        assert_eq!(delta.tx_data, 2_000);
+        assert_eq!(delta.tx_bytes, 2_000_000);
+        // gauges are current
+        assert_eq!(delta.tx_average_rtt, Duration::from_millis(30));
+        assert_eq!(delta.tx_buffered_data, 500);
+
+        assert_eq!(
+            stats(1, 10, 5).delta(&SocketStatistics::new()),
+            stats(1, 10, 5)
+        );
+    }
+
+    #[test]
+    fn counter_reset() {
+        // a new connection, the counters started over
+        let current = stats(1, 500, 5);
+        assert_eq!(current.delta(&stats(10, 10_000, 20)), current);
+
+        // any counter going backwards is a reset
+        let previous = SocketStatistics {
+            rx_nak: 1,
+            ..stats(0, 0, 0)
+        };
+        assert_eq!(current.delta(&previous), current);
+    }
+}
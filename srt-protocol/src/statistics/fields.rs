@@ -0,0 +1,173 @@
+use std::{fmt, time::Duration};
+
+use super::SocketStatistics;
+
+/// The value of a named statistics field, as reported by [`SocketStatistics::libsrt_fields`] and
+/// [`SocketStatistics::gstreamer_fields`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldValue {
+    Count(u64),
+    Rate(f64),
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Count(count) => write!(f, "{count}"),
+            FieldValue::Rate(rate) => write!(f, "{rate}"),
+        }
+    }
+}
+
+impl SocketStatistics {
+    /// How long the socket has been up, i.e. [`elapsed_time`](#structfield.elapsed_time).
+    pub fn uptime(&self) -> Duration {
+        self.elapsed_time
+    }
+
+    /// The statistics named as they are in libsrt's `SRT_TRACEBSTATS` (e.g. `msTimeStamp`,
+    /// `pktSentTotal`), so they can be fed to dashboards built around srt-live-transmit.
+    pub fn libsrt_fields(&self) -> Vec<(&'static str, FieldValue)> {
+        use FieldValue::*;
+        vec![
+            ("msTimeStamp", Count(millis(self.elapsed_time))),
+            ("pktSentTotal", Count(self.tx_data)),
+            ("pktRecvTotal", Count(self.rx_data)),
+            ("pktSentUniqueTotal", Count(self.tx_unique_data)),
+            ("pktRecvUniqueTotal", Count(self.rx_unique_data)),
+            ("pktSndLossTotal", Count(self.tx_loss_data)),
+            ("pktRcvLossTotal", Count(self.rx_loss_data)),
+            ("pktRetransTotal", Count(self.tx_retransmit_data)),
+            ("pktRcvRetransTotal", Count(self.rx_retransmit_data)),
+            ("pktSentACKTotal", Count(self.tx_ack)),
+            ("pktRecvACKTotal", Count(self.rx_ack)),
+            ("pktSentNAKTotal", Count(self.tx_nak)),
+            ("pktRecvNAKTotal", Count(self.rx_nak)),
+            ("usSndDurationTotal", Count(micros(self.tx_buffer_time))),
+            ("pktSndDropTotal", Count(self.tx_dropped_data)),
+            ("pktRcvDropTotal", Count(self.rx_dropped_data)),
+            ("pktRcvUndecryptTotal", Count(self.rx_decrypt_errors)),
+            ("byteSentTotal", Count(self.tx_bytes)),
+            ("byteRecvTotal", Count(self.rx_bytes)),
+            ("byteSentUniqueTotal", Count(self.tx_unique_bytes)),
+            ("byteRecvUniqueTotal", Count(self.rx_unique_bytes)),
+            ("byteRcvLossTotal", Count(self.rx_loss_bytes)),
+            ("byteRetransTotal", Count(self.tx_retransmit_bytes)),
+            ("byteSndDropTotal", Count(self.tx_dropped_bytes)),
+            ("byteRcvDropTotal", Count(self.rx_dropped_bytes)),
+            ("byteRcvUndecryptTotal", Count(self.rx_decrypt_error_bytes)),
+            ("usPktSndPeriod", Rate(micros_f64(self.tx_snd_period))),
+            ("pktFlowWindow", Count(self.tx_flow_window)),
+            ("pktFlightSize", Count(self.tx_unacknowledged_data)),
+            ("msRTT", Rate(millis_f64(self.tx_average_rtt))),
+            ("mbpsBandwidth", Rate(self.tx_bandwidth as f64)),
+            ("byteAvailSndBuf", Count(self.tx_buffer_available_bytes)),
+            ("byteAvailRcvBuf", Count(self.rx_buffer_available_bytes)),
+            ("pktSndBuf", Count(self.tx_buffered_data)),
+            ("byteSndBuf", Count(self.tx_buffered_bytes)),
+            ("msSndBuf", Count(millis(self.tx_buffered_time))),
+            ("pktRcvBuf", Count(self.rx_acknowledged_data)),
+            ("byteRcvBuf", Count(self.rx_acknowledged_bytes)),
+            ("msRcvBuf", Count(millis(self.rx_acknowledged_time))),
+            ("pktRcvBelated", Count(self.rx_belated_data)),
+        ]
+    }
+
+    /// The statistics named as they are in the `stats` property of the GStreamer `srtsrc` and
+    /// `srtsink` elements (e.g. `packets-sent`, `send-duration-us`).
+    ///
+    /// Rates are averaged over the lifetime of the connection.
+    pub fn gstreamer_fields(&self) -> Vec<(&'static str, FieldValue)> {
+        use FieldValue::*;
+        vec![
+            ("packets-sent", Count(self.tx_data)),
+            ("packets-sent-lost", Count(self.tx_loss_data)),
+            ("packets-retransmitted", Count(self.tx_retransmit_data)),
+            ("packet-ack-received", Count(self.rx_ack)),
+            ("packet-nack-received", Count(self.rx_nak)),
+            ("send-duration-us", Count(micros(self.tx_buffer_time))),
+            ("bytes-sent", Count(self.tx_bytes)),
+            ("bytes-retransmitted", Count(self.tx_retransmit_bytes)),
+            ("bytes-sent-dropped", Count(self.tx_dropped_bytes)),
+            ("packets-sent-dropped", Count(self.tx_dropped_data)),
+            ("send-rate-mbps", Rate(self.mbps(self.tx_bytes))),
+            ("packets-received", Count(self.rx_data)),
+            ("packets-received-lost", Count(self.rx_loss_data)),
+            (
+                "packets-received-retransmitted",
+                Count(self.rx_retransmit_data),
+            ),
+            ("packets-received-dropped", Count(self.rx_dropped_data)),
+            ("packet-ack-sent", Count(self.tx_ack)),
+            ("packet-nack-sent", Count(self.tx_nak)),
+            ("bytes-received", Count(self.rx_bytes)),
+            ("bytes-received-lost", Count(self.rx_loss_bytes)),
+            ("receive-rate-mbps", Rate(self.mbps(self.rx_bytes))),
+            ("bandwidth-mbps", Rate(self.tx_bandwidth as f64)),
+            ("rtt-ms", Rate(millis_f64(self.tx_average_rtt))),
+            ("uptime-ms", Count(millis(self.uptime()))),
+        ]
+    }
+
+    fn mbps(&self, bytes: u64) -> f64 {
+        let seconds = self.elapsed_time.as_secs_f64();
+        if seconds > 0. {
+            bytes as f64 * 8. / seconds / 1_000_000.
+        } else {
+            0.
+        }
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+fn micros(duration: Duration) -> u64 {
+    u64::try_from(duration.as_micros()).unwrap_or(u64::MAX)
+}
+
+fn millis_f64(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1_000.
+}
+
+fn micros_f64(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1_000_000.
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn field(fields: &[(&'static str, FieldValue)], name: &str) -> FieldValue {
+        fields.iter().find(|(n, _)| *n == name).unwrap().1
+    }
+
+    #[test]
+    fn named_fields() {
+        let stats = SocketStatistics {
+            elapsed_time: Duration::from_secs(2),
+            tx_data: 10,
+            tx_bytes: 1_000_000,
+            tx_buffer_time: Duration::from_millis(1500),
+            ..SocketStatistics::new()
+        };
+
+        let libsrt = stats.libsrt_fields();
+        assert_eq!(field(&libsrt, "msTimeStamp"), FieldValue::Count(2_000));
+        assert_eq!(field(&libsrt, "pktSentTotal"), FieldValue::Count(10));
+        assert_eq!(
+            field(&libsrt, "usSndDurationTotal"),
+            FieldValue::Count(1_500_000)
+        );
+
+        let gstreamer = stats.gstreamer_fields();
+        assert_eq!(field(&gstreamer, "packets-sent"), FieldValue::Count(10));
+        assert_eq!(
+            field(&gstreamer, "send-duration-us"),
+            FieldValue::Count(1_500_000)
+        );
+        assert_eq!(field(&gstreamer, "send-rate-mbps"), FieldValue::Rate(4.));
+        assert_eq!(field(&gstreamer, "uptime-ms"), FieldValue::Count(2_000));
+    }
+}
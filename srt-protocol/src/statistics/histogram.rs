@@ -0,0 +1,136 @@
+use std::{cmp::Ordering, ops::SubAssign, time::Duration};
+
+/// How a duration that is measured over and over is spread, in buckets from a millisecond to a
+/// few seconds, fine enough to compare it with a latency setting.
+///
+/// Like the other counters of [`SocketStatistics`](super::SocketStatistics), the buckets only ever
+/// grow, and a [`delta`](super::SocketStatistics::delta) has what was measured in between.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct DurationHistogram {
+    /// The number of samples up to each of [`BOUNDS`](Self::BOUNDS) and over the one before it,
+    /// with those over the last bound in the last bucket.
+    pub buckets: [u64; 13],
+
+    /// The sum of all the samples.
+    pub total: Duration,
+}
+
+impl DurationHistogram {
+    pub const BOUNDS: [Duration; 12] = [
+        Duration::from_millis(1),
+        Duration::from_millis(2),
+        Duration::from_millis(5),
+        Duration::from_millis(10),
+        Duration::from_millis(20),
+        Duration::from_millis(50),
+        Duration::from_millis(100),
+        Duration::from_millis(200),
+        Duration::from_millis(500),
+        Duration::from_secs(1),
+        Duration::from_secs(2),
+        Duration::from_secs(5),
+    ];
+
+    pub fn record(&mut self, sample: Duration) {
+        let bucket = Self::BOUNDS.partition_point(|bound| *bound < sample);
+        self.buckets[bucket] += 1;
+        self.total += sample;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        let count = u32::try_from(self.count())
+            .ok()
+            .filter(|count| *count > 0)?;
+        Some(self.total / count)
+    }
+
+    /// The bound of the bucket the `quantile`, e.g. 0.99, of the samples falls in, i.e. at least
+    /// that share of the samples were no longer than it. [`Duration::MAX`] if it falls over the
+    /// last bound, `None` without samples.
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((count as f64 * quantile).ceil() as u64).clamp(1, count);
+        let mut seen = 0;
+        let bucket = self.buckets.iter().position(|samples| {
+            seen += samples;
+            seen >= rank
+        })?;
+        Some(Self::BOUNDS.get(bucket).copied().unwrap_or(Duration::MAX))
+    }
+}
+
+// one histogram follows another when it has at least as many samples in every bucket
+impl PartialOrd for DurationHistogram {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let buckets = self.buckets.iter().zip(other.buckets.iter());
+        let ahead = buckets.clone().any(|(a, b)| a > b) || self.total > other.total;
+        let behind = buckets.clone().any(|(a, b)| a < b) || self.total < other.total;
+        match (ahead, behind) {
+            (false, false) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Greater),
+            (false, true) => Some(Ordering::Less),
+            (true, true) => None,
+        }
+    }
+}
+
+impl SubAssign for DurationHistogram {
+    fn sub_assign(&mut self, earlier: Self) {
+        for (samples, earlier) in self.buckets.iter_mut().zip(earlier.buckets) {
+            *samples -= earlier;
+        }
+        self.total -= earlier.total;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MILLIS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn histogram() {
+        let mut histogram = DurationHistogram::default();
+        assert_eq!(histogram.mean(), None);
+        assert_eq!(histogram.quantile(0.5), None);
+
+        for ms in [3, 4, 5, 30, 30, 30, 40, 90, 150, 6000] {
+            histogram.record(ms * MILLIS);
+        }
+        assert_eq!(histogram.count(), 10);
+        assert_eq!(histogram.buckets[2], 3);
+        assert_eq!(histogram.buckets[5], 4);
+        assert_eq!(histogram.buckets[12], 1);
+        assert_eq!(histogram.mean(), Some(Duration::from_micros(638_200)));
+
+        assert_eq!(histogram.quantile(0.0), Some(5 * MILLIS));
+        assert_eq!(histogram.quantile(0.5), Some(50 * MILLIS));
+        assert_eq!(histogram.quantile(0.8), Some(100 * MILLIS));
+        assert_eq!(histogram.quantile(0.9), Some(200 * MILLIS));
+        assert_eq!(histogram.quantile(1.0), Some(Duration::MAX));
+
+        let earlier = histogram;
+        histogram.record(10 * MILLIS);
+        assert!(histogram >= earlier);
+        assert!(earlier <= histogram);
+
+        let mut delta = histogram;
+        delta -= earlier;
+        assert_eq!(delta.count(), 1);
+        assert_eq!(delta.buckets[3], 1);
+        assert_eq!(delta.mean(), Some(10 * MILLIS));
+
+        // not comparable, each has samples the other doesn't
+        let mut other = DurationHistogram::default();
+        other.record(MILLIS);
+        assert_eq!(other.partial_cmp(&delta), None);
+    }
+}
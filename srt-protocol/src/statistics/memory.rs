@@ -0,0 +1,27 @@
+/// Roughly how much memory a connection takes, in bytes, by what it is for. It counts what the
+/// buffers and lists hold, and the room they have allocated, to tell how many connections fit in
+/// a process, rather than account for every allocation.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct MemoryUsage {
+    /// The connection itself, what it takes even with nothing buffered.
+    pub state: usize,
+
+    /// The packets waiting to be sent or acknowledged, with their payloads.
+    pub send_buffer: usize,
+
+    /// The packets waiting to be released, with their payloads, and the room for the rest of the
+    /// receive buffer, which is allocated up front.
+    pub receive_buffer: usize,
+
+    /// The lost packets the sender is to retransmit and the receiver is waiting for.
+    pub loss_lists: usize,
+
+    /// What is kept to answer ACK2s and report deliveries.
+    pub history: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.state + self.send_buffer + self.receive_buffer + self.loss_lists + self.history
+    }
+}
@@ -1,7 +1,23 @@
+mod alarm;
+mod delta;
+mod fields;
+mod histogram;
+mod memory;
+mod quality;
+mod window;
+
 pub use super::listener::ListenerStatistics;
+pub use alarm::{CongestionAlarm, CongestionEvent, CongestionHandler, CongestionThresholds};
+pub use fields::FieldValue;
+pub use histogram::DurationHistogram;
+pub use memory::MemoryUsage;
+pub use quality::{DefaultQuality, QualityFormula, QualityScore};
+pub use window::{StatisticsWindows, WindowedStatistics};
 
 use std::time::Duration;
 
+use crate::settings::KeyMaterialState;
+
 /// SRT provides a powerful set of statistical data on a socket. This data can be used to keep an eye
 /// on a socket's health and track faulty behavior.
 ///
@@ -19,9 +35,25 @@ pub struct SocketStatistics {
     pub tx_all_bytes: u64,
     pub rx_all_bytes: u64,
 
+    /// The packets addressed to this socket that came from somewhere the peer isn't allowed to
+    /// be, see [`peer_address_policy`](crate::options::Session::peer_address_policy). They're
+    /// dropped and not counted in [rx_all_packets](#rx_all_packets).
+    pub rx_foreign_packets: u64,
+
     pub tx_encrypted_data: u64,
     pub rx_decrypted_data: u64,
 
+    /// The key material state of the sending direction, i.e. whether the peer is able to decrypt
+    /// the stream sent by this socket (`SRTO_SNDKMSTATE`).
+    pub tx_km_state: KeyMaterialState, // SRTO_SNDKMSTATE
+
+    /// The key material state of the receiving direction, i.e. whether this socket is able to
+    /// decrypt the stream sent by the peer (`SRTO_RCVKMSTATE`).
+    ///
+    /// A connected socket reporting [`KeyMaterialState::NoSecret`] or
+    /// [`KeyMaterialState::BadSecret`] here is receiving content it cannot decrypt.
+    pub rx_km_state: KeyMaterialState, // SRTO_RCVKMSTATE
+
     pub rx_clock_adjustments: u64,
     pub rx_clock_drift_mean: i64,
     pub rx_clock_drift_stddev: i64,
@@ -133,6 +165,11 @@ pub struct SocketStatistics {
     /// The total number of received ACK (Acknowledgement) control packets.
     pub rx_ack: u64, // pktRecvACKTotal
 
+    /// The total number of received ACK control packets that were ignored, for acknowledging
+    /// packets that weren't sent yet, going back on an earlier ACK, or reporting an RTT the peer
+    /// can't have measured.
+    pub rx_ack_ignored: u64,
+
     pub tx_light_ack: u64,
 
     pub rx_light_ack: u64,
@@ -143,6 +180,18 @@ pub struct SocketStatistics {
     /// The total number of received NAK (Negative Acknowledgement) control packets.
     pub rx_nak: u64, // pktRecvNAKTotal
 
+    /// The total number of received NAK control packets that were ignored, for reporting the loss
+    /// of packets that weren't sent yet, or were sent long before the ones waiting for an ACK.
+    pub rx_nak_ignored: u64,
+
+    /// The total number of DATA packets received with an ECN Congestion Experienced mark, when
+    /// ECN is enabled and the platform reports it.
+    pub rx_congestion_experienced: u64,
+
+    /// The total number of Congestion Experienced marks the peer reported for the DATA packets
+    /// sent to it.
+    pub tx_congestion_experienced: u64,
+
     /// The total number of sent ACK2 (Acknowledgement Acknowledgement) control packets.
     pub tx_ack2: u64,
 
@@ -245,6 +294,36 @@ pub struct SocketStatistics {
     /// all the headers (20 bytes IPv4 + 8 bytes UDP + 16 bytes SRT).
     pub tx_dropped_bytes: u64, // byteSndDropTotal
 
+    /// The total number of messages the SRT sender dropped, in whole or in part, before the peer
+    /// acknowledged them. A message spanning many packets counts once, however many of its packets
+    /// were dropped.
+    pub tx_dropped_messages: u64,
+
+    /// The total number of DATA packets the SRT sender dropped to make room in a full send buffer,
+    /// i.e. over [`buffer_size`](crate::options::Sender::buffer_size) or
+    /// [`max_buffer_bytes`](crate::options::Sender::max_buffer_bytes), sent or not. They're
+    /// included in [tx_dropped_data](#tx_dropped_data) as well.
+    pub tx_buffer_overflow_data: u64,
+
+    /// The total number of messages dropped instead of retransmitting a lost packet of theirs, as
+    /// it couldn't have reached the receiver before its TSBPD time anymore. Their packets are
+    /// included in [tx_dropped_data](#tx_dropped_data).
+    pub tx_late_dropped_messages: u64,
+
+    /// The total number of DATA packets the SRT sender took as lost on duplicate ACKs, see
+    /// [`fast_retransmit`](crate::options::Sender::fast_retransmit). Their retransmissions are
+    /// included in [tx_retransmit_data](#tx_retransmit_data).
+    pub tx_fast_retransmit_data: u64,
+
+    /// The total number of copies of DATA packets the SRT sender sent ahead of any loss report,
+    /// see [`duplicate_interval`](crate::options::Sender::duplicate_interval). They're included in
+    /// [tx_data](#tx_data), not in [tx_retransmit_data](#tx_retransmit_data).
+    pub tx_duplicate_data: u64,
+
+    /// Same as [tx_duplicate_data](#tx_duplicate_data), but expressed in bytes, including payload
+    /// and all the headers (20 bytes IPv4 + 8 bytes UDP + 16 bytes SRT).
+    pub tx_duplicate_bytes: u64,
+
     /// Same as [rx_dropped_data](#rx_dropped_data), but expressed in bytes, including payload and
     /// all the headers (20 bytes IPv4 + 8 bytes UDP + 16 bytes SRT). Bytes for the dropped packets'
     /// payloads are estimated based on the average packet size.
@@ -323,6 +402,16 @@ pub struct SocketStatistics {
     /// at that moment.
     pub tx_unacknowledged_data: u64, // pktFlightSize
 
+    /// `tx_unacknowledged_data` in bytes, including all headers (SRT+UDP+IP).
+    pub tx_unacknowledged_bytes: u64,
+
+    /// How long ago the oldest packet still waiting for an ACK was first sent, zero when nothing
+    /// is in flight.
+    ///
+    /// Packets are dropped once this grows past 125% of the peer latency (at least a second), so
+    /// a value creeping towards that means the link is about to start losing data.
+    pub tx_unacknowledged_age: Duration,
+
     /// Smoothed round-trip time (SRTT), an exponentially-weighted moving average (EWMA) of an
     /// endpoint's RTT samples, in milliseconds.
     ///
@@ -333,6 +422,11 @@ pub struct SocketStatistics {
     pub tx_average_rtt: Duration, // msRTT
     pub rx_average_rtt: Duration,
 
+    /// The RTT variance (RTTVar) kept along with the smoothed RTT, the average deviation of the
+    /// RTT samples from it.
+    pub tx_rtt_variance: Duration,
+    pub rx_rtt_variance: Duration,
+
     /// Estimated bandwidth of the network link.
     ///
     /// The bandwidth is estimated at the receiver. The estimation is based on the time between two
@@ -397,6 +491,12 @@ pub struct SocketStatistics {
     // TODO: also calculate average
     pub tx_buffered_time: Duration, // msSndBuf
 
+    /// The number of packets in the sender's loss list, i.e. reported lost by the receiver (or
+    /// timed out) and waiting to be retransmitted.
+    ///
+    /// This is a gauge, updated whenever the sender handles input, an ACK or a NAK, or sends.
+    pub tx_loss_list_length: u64,
+
     // Timestamp-based Packet Delivery Delay value of the peer.
     // If `SRTO_TSBPDMODE` is on (default for **live mode**), it
     // returns the value of `SRTO_PEERLATENCY`, otherwise 0.
@@ -425,6 +525,25 @@ pub struct SocketStatistics {
     /// TODO: also calculate average
     pub rx_acknowledged_time: Duration, // msRcvBuf
 
+    /// The number of packets the receiver's buffer currently spans, including packets that are
+    /// still missing. When this reaches the receive buffer size, further packets are dropped.
+    ///
+    /// This is a gauge, updated whenever the receiver handles a packet or releases data.
+    pub rx_buffered_data: u64,
+
+    /// How late messages are released to the application compared to their TSBPD release time,
+    /// as a moving average over recent messages.
+    ///
+    /// This is a gauge, updated whenever data is released.
+    pub rx_delivery_jitter: Duration,
+
+    /// How much the transit time of consecutive data packets varies, from their arrival times and
+    /// the timestamps the sender gave them, smoothed like the interarrival jitter of RFC 3550.
+    /// Retransmitted packets aren't counted.
+    ///
+    /// This is a gauge, updated whenever the receiver handles a packet or releases data.
+    pub rx_interarrival_jitter: Duration,
+
     // Timestamp-based Packet Delivery Delay value set on the socket via `SRTO_RCVLATENCY` or `SRTO_LATENCY`.
     // The value is used to apply TSBPD delay for reading the received data on the socket.
     //
@@ -488,6 +607,17 @@ pub struct SocketStatistics {
     /// Accumulated difference between the current time and the time-to-play of a packet that is
     /// received late.
     pub rx_belated_time: Duration, // pktRcvAvgBelatedTime
+
+    /// How long lost packets took to be retransmitted, from the first NAK that reported them to
+    /// the arrival of the retransmission, whether or not that NAK made it. The latency needs to
+    /// cover most of it, or the packets that take longer are dropped as too late.
+    ///
+    /// Packets that arrive out of order rather than retransmitted aren't counted, if the peer marks
+    /// retransmitted packets.
+    pub rx_retransmit_delay: DurationHistogram,
+
+    /// Roughly how much memory the connection takes, see [`MemoryUsage`].
+    pub memory: MemoryUsage,
 }
 
 impl SocketStatistics {
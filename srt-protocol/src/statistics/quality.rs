@@ -0,0 +1,153 @@
+use std::fmt;
+
+use super::SocketStatistics;
+
+/// Sums the health of a connection up in a single number, from 0 for unusable to 100 for perfect
+pub trait QualityFormula: Send + Sync + 'static {
+    /// Scores the period `stats` covers: a [`delta`](SocketStatistics::delta), so the counters
+    /// are what happened within the period and the gauges are current.
+    fn score(&self, stats: &SocketStatistics) -> u8;
+}
+
+impl<F> QualityFormula for F
+where
+    F: Fn(&SocketStatistics) -> u8 + Send + Sync + 'static,
+{
+    fn score(&self, stats: &SocketStatistics) -> u8 {
+        self(stats)
+    }
+}
+
+/// The formula [`QualityScore`] uses unless told otherwise. It starts from 100 and takes off up
+/// to
+/// - 40 for loss, all of it once 10% of the packets to receive went missing
+/// - 20 for retransmissions, all of it once a quarter of the packets sent were retransmitted
+/// - 20 for RTT variance, all of it once the variance is half the RTT
+/// - 20 for buffer health, all of it once 5% of the packets were dropped as too late, or the
+///   oldest unacknowledged packet is a second old, when the sender starts dropping at the
+///   lowest latencies
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DefaultQuality;
+
+impl QualityFormula for DefaultQuality {
+    fn score(&self, stats: &SocketStatistics) -> u8 {
+        let loss = ratio(
+            stats.rx_loss_data,
+            stats.rx_unique_data + stats.rx_loss_data,
+        );
+        let retransmit = ratio(stats.tx_retransmit_data, stats.tx_data);
+        let rtt_variance = if stats.tx_average_rtt.is_zero() {
+            0.
+        } else {
+            stats.tx_rtt_variance.as_secs_f64() / stats.tx_average_rtt.as_secs_f64()
+        };
+        let dropped = ratio(
+            stats.tx_dropped_data + stats.rx_dropped_data,
+            stats.tx_data + stats.rx_data,
+        );
+        // as a fraction of the limits
+        let buffer = (dropped / 0.05).max(stats.tx_unacknowledged_age.as_secs_f64());
+
+        let penalty = |weight: f64, value: f64, limit: f64| weight * (value / limit).min(1.);
+        let score = 100.
+            - penalty(40., loss, 0.1)
+            - penalty(20., retransmit, 0.25)
+            - penalty(20., rtt_variance, 0.5)
+            - penalty(20., buffer, 1.);
+        score.round().clamp(0., 100.) as u8
+    }
+}
+
+/// Scores a connection from the snapshots of its statistics, e.g. the ones its statistics stream
+/// yields, with a [`QualityFormula`], [`DefaultQuality`] unless given another.
+pub struct QualityScore {
+    formula: Box<dyn QualityFormula>,
+    sampled: SocketStatistics,
+}
+
+impl QualityScore {
+    pub fn new(formula: impl QualityFormula) -> Self {
+        Self {
+            formula: Box::new(formula),
+            sampled: SocketStatistics::new(),
+        }
+    }
+
+    /// Score with `formula` from now on, the next period still starts at the last snapshot
+    pub fn set_formula(&mut self, formula: impl QualityFormula) {
+        self.formula = Box::new(formula);
+    }
+
+    /// The score of the period between the snapshot given to the previous call and `stats`, or
+    /// the whole connection so far for the first call.
+    pub fn score(&mut self, stats: &SocketStatistics) -> u8 {
+        let delta = stats.delta(&self.sampled);
+        self.sampled = stats.clone();
+        self.formula.score(&delta)
+    }
+}
+
+impl Default for QualityScore {
+    fn default() -> Self {
+        Self::new(DefaultQuality)
+    }
+}
+
+impl fmt::Debug for QualityScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QualityScore")
+            .field("sampled", &self.sampled)
+            .finish_non_exhaustive()
+    }
+}
+
+fn ratio(part: u64, whole: u64) -> f64 {
+    if whole > 0 {
+        part as f64 / whole as f64
+    } else {
+        0.
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn stats(secs: u64, sent: u64, retransmitted: u64, lost: u64) -> SocketStatistics {
+        SocketStatistics {
+            elapsed_time: Duration::from_secs(secs),
+            tx_data: sent,
+            tx_retransmit_data: retransmitted,
+            rx_unique_data: sent,
+            rx_loss_data: lost,
+            tx_average_rtt: Duration::from_millis(20),
+            tx_rtt_variance: Duration::from_millis(1),
+            ..SocketStatistics::new()
+        }
+    }
+
+    #[test]
+    fn default_quality() {
+        assert_eq!(DefaultQuality.score(&stats(1, 1_000, 0, 0)), 98);
+        // 5% loss costs half the loss points
+        assert_eq!(DefaultQuality.score(&stats(1, 950, 0, 50)), 78);
+        // past the limits every point is taken
+        let awful = SocketStatistics {
+            tx_rtt_variance: Duration::from_millis(20),
+            tx_dropped_data: 500,
+            ..stats(1, 1_000, 500, 1_000)
+        };
+        assert_eq!(DefaultQuality.score(&awful), 0);
+    }
+
+    #[test]
+    fn score_periods() {
+        let mut quality = QualityScore::new(|stats: &SocketStatistics| stats.rx_loss_data as u8);
+        assert_eq!(quality.score(&stats(1, 100, 0, 5)), 5);
+        assert_eq!(quality.score(&stats(2, 200, 0, 7)), 2);
+        // a new connection starts over
+        assert_eq!(quality.score(&stats(1, 100, 0, 1)), 1);
+    }
+}
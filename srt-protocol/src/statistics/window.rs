@@ -0,0 +1,222 @@
+use std::{collections::VecDeque, time::Duration};
+
+use super::SocketStatistics;
+
+/// Loss rate, retransmit rate, NAK rate and throughput averaged over the last second, ten seconds or minute,
+/// worked out from the [`SocketStatistics`] snapshots of a connection.
+///
+/// Feed it every snapshot, e.g. each one the statistics stream of a socket yields, with
+/// [`update`](Self::update). The counters in a snapshot are totals since the socket was created,
+/// so a snapshot that went backwards, i.e. one of a new connection, discards the history instead
+/// of producing negative deltas.
+#[derive(Debug, Default, Clone)]
+pub struct StatisticsWindows {
+    // oldest first, at most one of them older than the longest window
+    samples: VecDeque<Sample>,
+}
+
+/// The averages over one window, see [`StatisticsWindows`]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct WindowedStatistics {
+    /// The time the averages are over. Shorter than the window until the connection has been up
+    /// for as long.
+    pub duration: Duration,
+
+    /// Received packets lost, as a fraction of the unique packets received plus the lost ones
+    pub rx_loss_rate: f64,
+
+    /// Retransmitted packets, as a fraction of all the DATA packets sent
+    pub tx_retransmit_rate: f64,
+
+    /// NAKs received per second, a storm of them means the path to the peer is congested
+    pub rx_nak_rate: f64,
+
+    /// Sending throughput in Mbps, including retransmissions
+    pub tx_mbps: f64,
+
+    /// Receiving throughput in Mbps, including retransmissions
+    pub rx_mbps: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    elapsed_time: Duration,
+    tx_data: u64,
+    tx_retransmit_data: u64,
+    tx_bytes: u64,
+    rx_unique_data: u64,
+    rx_loss_data: u64,
+    rx_bytes: u64,
+    rx_nak: u64,
+}
+
+impl Sample {
+    // whether every counter of self is at least that of earlier
+    fn follows(&self, earlier: &Sample) -> bool {
+        self.elapsed_time >= earlier.elapsed_time
+            && self.tx_data >= earlier.tx_data
+            && self.tx_retransmit_data >= earlier.tx_retransmit_data
+            && self.tx_bytes >= earlier.tx_bytes
+            && self.rx_unique_data >= earlier.rx_unique_data
+            && self.rx_loss_data >= earlier.rx_loss_data
+            && self.rx_bytes >= earlier.rx_bytes
+            && self.rx_nak >= earlier.rx_nak
+    }
+}
+
+impl From<&SocketStatistics> for Sample {
+    fn from(stats: &SocketStatistics) -> Self {
+        Self {
+            elapsed_time: stats.elapsed_time,
+            tx_data: stats.tx_data,
+            tx_retransmit_data: stats.tx_retransmit_data,
+            tx_bytes: stats.tx_bytes,
+            rx_unique_data: stats.rx_unique_data,
+            rx_loss_data: stats.rx_loss_data,
+            rx_bytes: stats.rx_bytes,
+            rx_nak: stats.rx_nak,
+        }
+    }
+}
+
+impl StatisticsWindows {
+    pub const LONGEST_WINDOW: Duration = Duration::from_secs(60);
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, stats: &SocketStatistics) {
+        let sample = Sample::from(stats);
+        if matches!(self.samples.back(), Some(last) if !sample.follows(last)) {
+            self.samples.clear();
+        }
+        self.samples.push_back(sample);
+        while matches!(
+            self.samples.get(1),
+            Some(next) if next.elapsed_time + Self::LONGEST_WINDOW <= sample.elapsed_time
+        ) {
+            let _ = self.samples.pop_front();
+        }
+    }
+
+    pub fn one_second(&self) -> Option<WindowedStatistics> {
+        self.window(Duration::from_secs(1))
+    }
+
+    pub fn ten_seconds(&self) -> Option<WindowedStatistics> {
+        self.window(Duration::from_secs(10))
+    }
+
+    pub fn one_minute(&self) -> Option<WindowedStatistics> {
+        self.window(Self::LONGEST_WINDOW)
+    }
+
+    /// The averages over the last `length`, up to [`LONGEST_WINDOW`](Self::LONGEST_WINDOW), or
+    /// None until there are two snapshots to go by.
+    pub fn window(&self, length: Duration) -> Option<WindowedStatistics> {
+        let last = self.samples.back()?;
+        let first = self
+            .samples
+            .iter()
+            .rev()
+            .find(|sample| sample.elapsed_time + length <= last.elapsed_time)
+            .or_else(|| self.samples.front())?;
+        let duration = last.elapsed_time - first.elapsed_time;
+        if duration.is_zero() {
+            return None;
+        }
+
+        let rx_loss = last.rx_loss_data - first.rx_loss_data;
+        let rx_unique = last.rx_unique_data - first.rx_unique_data;
+        let mbps = |bytes: u64| bytes as f64 * 8. / duration.as_secs_f64() / 1_000_000.;
+        Some(WindowedStatistics {
+            duration,
+            rx_loss_rate: ratio(rx_loss, rx_unique + rx_loss),
+            tx_retransmit_rate: ratio(
+                last.tx_retransmit_data - first.tx_retransmit_data,
+                last.tx_data - first.tx_data,
+            ),
+            rx_nak_rate: (last.rx_nak - first.rx_nak) as f64 / duration.as_secs_f64(),
+            tx_mbps: mbps(last.tx_bytes - first.tx_bytes),
+            rx_mbps: mbps(last.rx_bytes - first.rx_bytes),
+        })
+    }
+}
+
+fn ratio(part: u64, whole: u64) -> f64 {
+    if whole > 0 {
+        part as f64 / whole as f64
+    } else {
+        0.
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stats(secs: u64, packets: u64, lost: u64) -> SocketStatistics {
+        SocketStatistics {
+            elapsed_time: Duration::from_secs(secs),
+            tx_data: packets + lost,
+            tx_retransmit_data: lost,
+            tx_bytes: (packets + lost) * 1_000,
+            rx_unique_data: packets,
+            rx_loss_data: lost,
+            rx_bytes: packets * 1_000,
+            rx_nak: lost / 10,
+            ..SocketStatistics::new()
+        }
+    }
+
+    #[test]
+    fn windows() {
+        let mut windows = StatisticsWindows::new();
+        assert_eq!(windows.one_second(), None);
+        windows.update(&stats(0, 0, 0));
+        assert_eq!(windows.one_second(), None);
+
+        // 1000 packets a second, the last 10 seconds with 10% loss
+        for secs in 1..=120u64 {
+            let lossy = secs.saturating_sub(110);
+            windows.update(&stats(secs, secs * 1_000 - lossy * 100, lossy * 100));
+        }
+        assert_eq!(windows.samples.len(), 61);
+
+        let second = windows.one_second().unwrap();
+        assert_eq!(second.duration, Duration::from_secs(1));
+        assert_eq!(second.rx_loss_rate, 0.1);
+        assert_eq!(second.tx_retransmit_rate, 0.1);
+        assert_eq!(second.rx_nak_rate, 10.);
+        assert_eq!(second.tx_mbps, 8.);
+        assert_eq!(second.rx_mbps, 7.2);
+
+        let ten_seconds = windows.ten_seconds().unwrap();
+        assert_eq!(ten_seconds.duration, Duration::from_secs(10));
+        assert_eq!(ten_seconds.rx_loss_rate, 0.1);
+
+        let minute = windows.one_minute().unwrap();
+        assert_eq!(minute.duration, Duration::from_secs(60));
+        assert!((minute.rx_loss_rate - 1. / 60.).abs() < 1e-9);
+
+        // longer than the history
+        assert_eq!(windows.window(Duration::from_secs(600)), Some(minute));
+    }
+
+    #[test]
+    fn counter_reset() {
+        let mut windows = StatisticsWindows::new();
+        windows.update(&stats(0, 0, 0));
+        windows.update(&stats(10, 10_000, 0));
+
+        // a new connection, the counters started over
+        windows.update(&stats(1, 500, 500));
+        assert_eq!(windows.one_second(), None);
+        windows.update(&stats(2, 1_500, 500));
+        let second = windows.one_second().unwrap();
+        assert_eq!(second.duration, Duration::from_secs(1));
+        assert_eq!(second.rx_loss_rate, 0.);
+        assert_eq!(second.rx_mbps, 8.);
+    }
+}
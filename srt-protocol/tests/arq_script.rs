@@ -0,0 +1,75 @@
+// scripted regression tests for the receiver's ARQ, see script/mod.rs for the DSL
+
+pub mod script;
+
+use script::{Event::*, *};
+
+#[test]
+fn loss_recovered_before_release() {
+    let mut script = Script::new();
+
+    script.at(10).data(0, 10, "a");
+    script.at(20).data(2, 20, "c");
+    script.at(25).data(3, 25, "d");
+    script.expect(&[(20, Ack(1)), (20, Nak(vec![1]))]);
+
+    // the ACK only moves past the loss once it's recovered
+    script.at(40).retransmit(1, 15, "b");
+    script.at(50).expect(&[(40, Ack(1)), (50, Ack(4))]);
+
+    // every message is released at its TSBPD time
+    script.at(125).expect(&[
+        (70, Ack(4)),
+        (90, Ack(4)),
+        (110, Ack(4)),
+        (110, Delivered("a".into())),
+        (115, Delivered("b".into())),
+        (120, Delivered("c".into())),
+        (125, Delivered("d".into())),
+    ]);
+}
+
+#[test]
+fn unrecovered_loss_dropped_too_late() {
+    let mut script = Script::new();
+
+    script.at(10).data(0, 10, "a");
+    script.at(20).data(2, 20, "c");
+
+    // the loss is reported again every NAK period until it's given up on
+    script.at(130).expect(&[
+        (20, Ack(1)),
+        (20, Nak(vec![1])),
+        (40, Ack(1)),
+        (60, Ack(1)),
+        (60, Nak(vec![1])),
+        (80, Ack(1)),
+        (100, Ack(1)),
+        (100, Nak(vec![1])),
+        (110, Delivered("a".into())),
+        (120, Ack(1)),
+        (125, Delivered("c".into())),
+        (130, Ack(3)),
+    ]);
+}
+
+#[test]
+fn duplicate_retransmission_delivered_once() {
+    let mut script = Script::new();
+
+    script.at(10).data(0, 10, "a");
+    script.at(20).data(2, 20, "c");
+    script.at(40).retransmit(1, 15, "b");
+    script.at(45).retransmit(1, 15, "b");
+    script.ignore();
+
+    script.at(120).expect(&[
+        (50, Ack(3)),
+        (70, Ack(3)),
+        (90, Ack(3)),
+        (110, Ack(3)),
+        (110, Delivered("a".into())),
+        (115, Delivered("b".into())),
+        (120, Delivered("c".into())),
+    ]);
+}
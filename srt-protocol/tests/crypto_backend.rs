@@ -0,0 +1,117 @@
+#![cfg(feature = "rust-crypto")]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use srt_protocol::{options::KeyDerivation, packet::*, settings::*};
+
+// counts what it's asked to do and leaves the doing to RustCrypto
+struct Counting {
+    ctr: AtomicUsize,
+    wrap: AtomicUsize,
+    unwrap: AtomicUsize,
+    pbkdf2: AtomicUsize,
+}
+
+impl CryptoBackend for Counting {
+    fn aes_ctr(&self, key: &[u8], iv: &[u8; 16], data: &mut [u8]) -> Result<(), CryptoError> {
+        self.ctr.fetch_add(1, Ordering::SeqCst);
+        RustCrypto.aes_ctr(key, iv, data)
+    }
+
+    fn aes_gcm_seal(
+        &self,
+        key: &[u8],
+        iv: &[u8; 12],
+        aad: &[u8],
+        data: &mut [u8],
+    ) -> Result<[u8; 16], CryptoError> {
+        RustCrypto.aes_gcm_seal(key, iv, aad, data)
+    }
+
+    fn aes_gcm_open(
+        &self,
+        key: &[u8],
+        iv: &[u8; 12],
+        aad: &[u8],
+        data: &mut [u8],
+        tag: &[u8; 16],
+    ) -> Result<(), CryptoError> {
+        RustCrypto.aes_gcm_open(key, iv, aad, data, tag)
+    }
+
+    fn aes_wrap(&self, kek: &[u8], keys: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        self.wrap.fetch_add(1, Ordering::SeqCst);
+        RustCrypto.aes_wrap(kek, keys)
+    }
+
+    fn aes_unwrap(&self, kek: &[u8], wrapped: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        self.unwrap.fetch_add(1, Ordering::SeqCst);
+        RustCrypto.aes_unwrap(kek, wrapped)
+    }
+
+    fn pbkdf2_hmac_sha1(
+        &self,
+        password: &[u8],
+        salt: &[u8],
+        rounds: u32,
+        key: &mut [u8],
+    ) -> Result<(), CryptoError> {
+        self.pbkdf2.fetch_add(1, Ordering::SeqCst);
+        RustCrypto.pbkdf2_hmac_sha1(password, salt, rounds, key)
+    }
+}
+
+static BACKEND: Counting = Counting {
+    ctr: AtomicUsize::new(0),
+    wrap: AtomicUsize::new(0),
+    unwrap: AtomicUsize::new(0),
+    pbkdf2: AtomicUsize::new(0),
+};
+
+#[test]
+fn installed_backend() {
+    assert!(install_crypto_backend(&BACKEND).is_ok());
+    assert!(install_crypto_backend(&RustCrypto).is_err());
+
+    let key_settings = KeySettings {
+        key_size: KeySize::AES256,
+        key_source: KeySource::Passphrase("password123".into()),
+    };
+    let keys = StreamEncryptionKeys::new_random(key_settings.key_size);
+    let key_material = keys.wrap_with(&key_settings).unwrap();
+    let unwrapped = StreamEncryptionKeys::unwrap_from(&key_settings, &key_material).unwrap();
+    assert_eq!(unwrapped, keys);
+
+    let mut data = *b"carried by a certified module";
+    let seq_number = SeqNumber::new_truncate(1234);
+    keys.encrypt(DataEncryption::Even, seq_number, &mut data);
+    assert_ne!(&data, b"carried by a certified module");
+    unwrapped.decrypt(DataEncryption::Even, seq_number, &mut data);
+    assert_eq!(&data, b"carried by a certified module");
+
+    // unwrapping took the key derived for wrapping
+    assert_eq!(BACKEND.pbkdf2.load(Ordering::SeqCst), 1);
+    assert_eq!(BACKEND.wrap.load(Ordering::SeqCst), 1);
+    assert_eq!(BACKEND.unwrap.load(Ordering::SeqCst), 1);
+    assert_eq!(BACKEND.ctr.load(Ordering::SeqCst), 2);
+
+    // connections started with the same initial salt derive the key once
+    let key_settings = KeySettings {
+        key_size: KeySize::AES128,
+        key_source: KeySource::DerivedPassphrase(
+            "password123".into(),
+            KeyDerivation {
+                initial_salt: Some(Salt::new_random()),
+                ..Default::default()
+            },
+        ),
+    };
+    for _ in 0..10 {
+        let keys = StreamEncryptionKeys::new_random_with_salt(
+            key_settings.key_source.new_salt(),
+            key_settings.key_size,
+        );
+        keys.wrap_with(&key_settings).unwrap();
+    }
+    assert_eq!(BACKEND.pbkdf2.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,262 @@
+// a small DSL to script the packets a connection receives, and assert exactly what it sends and
+// releases in response, e.g.
+//
+//     script.at(10).data(0, 10, "a").at(20).data(2, 20, "c");
+//     script.at(30).expect(&[(20, Nak(vec![1]))]);
+
+use std::{
+    fmt,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+
+use srt_protocol::{
+    connection::{Action, Connection, ConnectionSettings, DuplexConnection, Input},
+    options::*,
+    packet::*,
+    protocol::handshake::Handshake,
+};
+
+/// What the connection did, the sequence numbers are the raw values, a NAK lists each lost packet
+#[derive(Clone, PartialEq, Eq)]
+pub enum Event {
+    Ack(u32),
+    LightAck(u32),
+    Nak(Vec<u32>),
+    KeepAlive,
+    Delivered(Bytes),
+    Sent(Box<Packet>),
+    Closed,
+}
+
+impl fmt::Debug for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Ack(seq) => write!(f, "Ack({seq})"),
+            Event::LightAck(seq) => write!(f, "LightAck({seq})"),
+            Event::Nak(lost) => write!(f, "Nak({lost:?})"),
+            Event::KeepAlive => write!(f, "KeepAlive"),
+            Event::Delivered(data) => write!(f, "Delivered({data:?})"),
+            Event::Sent(packet) => write!(f, "Sent({packet:?})"),
+            Event::Closed => write!(f, "Closed"),
+        }
+    }
+}
+
+impl From<Packet> for Event {
+    fn from(packet: Packet) -> Self {
+        use Acknowledgement::*;
+        match &packet {
+            Packet::Control(ControlPacket { control_type, .. }) => match control_type {
+                ControlTypes::Ack(Full(seq, _, _) | Small(seq, _)) => Event::Ack(seq.as_raw()),
+                ControlTypes::Ack(Lite(seq)) => Event::LightAck(seq.as_raw()),
+                ControlTypes::Nak(loss_list) => Event::Nak(
+                    loss_list
+                        .clone()
+                        .into_iter_decompressed()
+                        .map(|seq| seq.as_raw())
+                        .collect(),
+                ),
+                ControlTypes::KeepAlive => Event::KeepAlive,
+                _ => Event::Sent(Box::new(packet)),
+            },
+            Packet::Data(_) => Event::Sent(Box::new(packet)),
+        }
+    }
+}
+
+/// Drives a connection through a script, all the times are in milliseconds since its start
+pub struct Script {
+    start: Instant,
+    now: Instant,
+    connection: DuplexConnection,
+    events: Vec<(u64, Event)>,
+}
+
+impl Default for Script {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Script {
+    pub const LATENCY: Duration = Duration::from_millis(100);
+
+    pub fn new() -> Self {
+        Self::with_settings(|_| {})
+    }
+
+    pub fn with_settings(configure: impl FnOnce(&mut ConnectionSettings)) -> Self {
+        let start = Instant::now();
+        let mut settings = new_connection_settings(start);
+        configure(&mut settings);
+        Script {
+            start,
+            now: start,
+            connection: DuplexConnection::new(Connection {
+                settings,
+                handshake: Handshake::Connector,
+            }),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn connection(&mut self) -> &mut DuplexConnection {
+        &mut self.connection
+    }
+
+    /// Advance to `ms`, running the timers that expire on the way
+    pub fn at(&mut self, ms: u64) -> &mut Self {
+        let until = self.start + Duration::from_millis(ms);
+        assert!(until >= self.now, "the script can't go back in time");
+        loop {
+            let next = self.connection.next_timer(self.now).max(self.now);
+            if next > until {
+                break;
+            }
+            self.now = next;
+            self.handle(Input::Timer);
+            if self.now == until {
+                break;
+            }
+        }
+        self.now = until;
+        self.handle(Input::Timer);
+        self
+    }
+
+    /// A single packet message with the message number `seq + 1`, sent by the peer at `sent`
+    pub fn data(&mut self, seq: u32, sent: u64, payload: &'static str) -> &mut Self {
+        self.packet(Packet::Data(Self::data_packet(seq, sent, payload, false)))
+    }
+
+    /// Like [`data`](Self::data), flagged as a retransmission
+    pub fn retransmit(&mut self, seq: u32, sent: u64, payload: &'static str) -> &mut Self {
+        self.packet(Packet::Data(Self::data_packet(seq, sent, payload, true)))
+    }
+
+    pub fn control(&mut self, control_type: ControlTypes) -> &mut Self {
+        let timestamp = Self::timestamp((self.now - self.start).as_millis() as u64);
+        self.packet(Packet::Control(ControlPacket {
+            timestamp,
+            dest_sockid: local_sockid(),
+            control_type,
+        }))
+    }
+
+    pub fn packet(&mut self, packet: Packet) -> &mut Self {
+        self.handle(Input::Packet(Ok((packet, remote_addr()))))
+    }
+
+    /// Assert the connection did exactly this since the last expectation, at these times
+    #[track_caller]
+    pub fn expect(&mut self, events: &[(u64, Event)]) -> &mut Self {
+        assert_eq!(self.events, events);
+        self.events.clear();
+        self
+    }
+
+    /// Forget what the connection did so far, e.g. the timers that aren't under test
+    pub fn ignore(&mut self) -> &mut Self {
+        self.events.clear();
+        self
+    }
+
+    fn handle(&mut self, input: Input) -> &mut Self {
+        let ms = (self.now - self.start).as_millis() as u64;
+        let mut input = input;
+        loop {
+            input = match self.connection.handle_input(self.now, input) {
+                Action::SendPacket((packet, _)) => {
+                    self.events.push((ms, packet.into()));
+                    Input::PacketSent
+                }
+                Action::ReleaseData((_, data)) => {
+                    self.events.push((ms, Event::Delivered(data)));
+                    Input::DataReleased
+                }
+                Action::UpdateStatistics(_) => Input::StatisticsUpdated,
+                Action::WaitForData(_) => break,
+                Action::Close => {
+                    self.events.push((ms, Event::Closed));
+                    break;
+                }
+            };
+        }
+        self
+    }
+
+    fn data_packet(seq: u32, sent: u64, payload: &'static str, retransmitted: bool) -> DataPacket {
+        DataPacket {
+            seq_number: SeqNumber(seq),
+            message_loc: PacketLocation::ONLY,
+            in_order_delivery: false,
+            encryption: DataEncryption::None,
+            retransmitted,
+            message_number: MsgNumber(seq + 1),
+            timestamp: Self::timestamp(sent),
+            dest_sockid: local_sockid(),
+            payload: Bytes::from_static(payload.as_bytes()),
+        }
+    }
+
+    fn timestamp(ms: u64) -> TimeStamp {
+        TimeStamp::from_micros(u32::try_from(ms * 1_000).unwrap())
+    }
+}
+
+fn remote_addr() -> SocketAddr {
+    ([127, 0, 0, 1], 2223).into()
+}
+
+fn local_sockid() -> SocketId {
+    SocketId(2)
+}
+
+fn new_connection_settings(start: Instant) -> ConnectionSettings {
+    ConnectionSettings {
+        remote: remote_addr(),
+        remote_sockid: SocketId(3),
+        local_sockid: local_sockid(),
+        socket_start_time: start,
+        rtt: Duration::default(),
+        init_seq_num: SeqNumber(0),
+        max_packet_size: PacketSize(1316),
+        max_flow_size: PacketCount(8192),
+        send_tsbpd_latency: Script::LATENCY,
+        recv_tsbpd_latency: Script::LATENCY,
+        cipher: None,
+        stream_id: None,
+        bandwidth: Default::default(),
+        recv_buffer_size: PacketCount(8192),
+        send_buffer_size: PacketCount(8192),
+        send_buffer_bytes: None,
+        send_buffer_time: None,
+        send_buffer_policy: SendBufferPolicy::DropOldest,
+        statistics_interval: Duration::from_secs(1),
+        peer_idle_timeout: Duration::from_secs(5),
+        ack2_mode: Ack2Mode::EveryFullAck,
+        lite_ack_rtt_sampling: false,
+        max_burst: PacketCount(0),
+        duplicate_interval: None,
+        fast_retransmit: None,
+        sequence_restart_window: PacketCount(0),
+        skip_gaps: false,
+        immediate_nak: false,
+        light_ack_interval: PacketCount(64),
+        max_delivery_rate: None,
+        half_close: false,
+        limit_payload_size: false,
+        keepalive_interval: Duration::from_secs(1),
+        adaptive_keepalive: false,
+        message_tags: false,
+        ecn: false,
+        peer_address_policy: PeerAddressPolicy::Rebind,
+        encrypt_control: false,
+        peer_version: SrtVersion::CURRENT,
+        peer_flags: SrtShakeFlags::SUPPORTED,
+        local_flags: SrtShakeFlags::SUPPORTED,
+    }
+}
@@ -0,0 +1,134 @@
+// lossy transfer that starts just before the sequence number wraps, exercising the
+// wraparound-aware invariants in the send and receive buffers
+
+use std::{
+    cmp::min,
+    str,
+    time::{Duration, Instant},
+};
+
+use log::{info, trace};
+use rand::{distributions::Bernoulli, prelude::StdRng, SeedableRng};
+use rand_distr::Normal;
+use srt_protocol::{connection::Input, options::*, packet::*};
+
+pub mod simulator;
+
+use simulator::*;
+
+#[test]
+fn sequence_number_wraparound() {
+    let _ = pretty_env_logger::try_init();
+
+    let seeds = [
+        2653521395853419221,
+        5766315222680582398,
+        11048561010350239716,
+    ];
+    for &seed in &seeds {
+        do_wraparound_test(seed, 10_000);
+    }
+}
+
+fn do_wraparound_test(seed: u64, count: usize) {
+    info!("Seed is: {}, count is: {}", seed, count);
+
+    const PACKET_SPACING: Duration = Duration::from_millis(1);
+    const DROP_RATE: f64 = 0.05;
+    let delay_mean = Duration::from_secs_f64(20e-3);
+    let delay_stdev = Duration::from_secs_f64(4e-3);
+    // wrap about halfway through the transfer
+    let init_seq_num = SeqNumber(SeqNumber::MAX - count as u32 / 2);
+
+    let start = Instant::now();
+
+    let mut simulation = RandomLossSimulation {
+        rng: StdRng::seed_from_u64(seed),
+        delay_dist: Normal::new(delay_mean.as_secs_f64(), delay_stdev.as_secs_f64()).unwrap(),
+        drop_dist: Bernoulli::new(DROP_RATE).unwrap(),
+    };
+    let (mut network, mut sender, mut receiver) = simulation.build_with_init_seq_num(
+        start,
+        Duration::from_secs(1),
+        PacketCount(8192),
+        init_seq_num,
+    );
+    input_data_simulation(start, count, PACKET_SPACING, &mut network.sender);
+
+    let mut now = start;
+    let mut next_data = 1i32;
+    let mut dropped = 0i32;
+    let mut received = 0i32;
+    loop {
+        let sender_next_time = if sender.is_open() {
+            assert_eq!(sender.next_data(now), None);
+
+            while let Some(packet) = sender.next_packet(now) {
+                match simulation.next_packet_schedule(now) {
+                    Some(release_at) => network.send(release_at, packet),
+                    None => trace!("Dropping {:?}", packet),
+                }
+            }
+
+            let next_timer = sender.check_timers(now);
+            let (next_time, input) = network.sender.select_next_input(now, next_timer);
+            match input {
+                Input::Data(data) => sender.handle_data_input(next_time, data),
+                Input::Packet(packet) => sender.handle_packet_input(next_time, packet),
+                _ => {}
+            };
+            Some(next_time)
+        } else {
+            None
+        };
+
+        let receiver_next_time = if receiver.is_open() {
+            while let Some((_, payload)) = receiver.next_data(now) {
+                let actual: i32 = str::from_utf8(&payload[..]).unwrap().parse().unwrap();
+                assert!(
+                    actual >= next_data,
+                    "Out of order delivery: got {actual}, expected at least {next_data}"
+                );
+                dropped += actual - next_data;
+                next_data = actual + 1;
+                received += 1;
+            }
+
+            while let Some(packet) = receiver.next_packet(now) {
+                match simulation.next_packet_schedule(now) {
+                    Some(release_at) => network.send(release_at, packet),
+                    None => trace!("Dropping {:?}", packet),
+                }
+            }
+
+            let next_timer = receiver.check_timers(now);
+            let (next_time, input) = network.receiver.select_next_input(now, next_timer);
+            match input {
+                Input::Data(data) => receiver.handle_data_input(now, data),
+                Input::Packet(packet) => receiver.handle_packet_input(now, packet),
+                _ => {}
+            };
+            Some(next_time)
+        } else {
+            None
+        };
+
+        let next_time = match (sender_next_time, receiver_next_time) {
+            (Some(s), Some(r)) => min(s, r),
+            (Some(s), None) => s,
+            (None, Some(r)) => r,
+            _ => break,
+        };
+
+        now = next_time;
+    }
+
+    info!("Received: {}", received);
+
+    assert_eq!(
+        next_data,
+        count as i32 + 1,
+        "Transfer did not reach the end"
+    );
+    assert!(dropped < 15, "Expected less than 15 drops, got {dropped}");
+}
@@ -166,6 +166,27 @@ impl RandomLossSimulation {
         recv_buffer_size: PacketCount,
     ) -> (NetworkSimulator, DuplexConnection, DuplexConnection) {
         let sender = self.new_connection_settings(start, latency);
+        Self::connect(sender, recv_buffer_size)
+    }
+
+    pub fn build_with_init_seq_num(
+        &mut self,
+        start: Instant,
+        latency: Duration,
+        recv_buffer_size: PacketCount,
+        init_seq_num: SeqNumber,
+    ) -> (NetworkSimulator, DuplexConnection, DuplexConnection) {
+        let sender = ConnectionSettings {
+            init_seq_num,
+            ..self.new_connection_settings(start, latency)
+        };
+        Self::connect(sender, recv_buffer_size)
+    }
+
+    fn connect(
+        sender: ConnectionSettings,
+        recv_buffer_size: PacketCount,
+    ) -> (NetworkSimulator, DuplexConnection, DuplexConnection) {
         let receiver = ConnectionSettings {
             remote: (sender.remote.ip(), sender.remote.port().wrapping_add(1)).into(),
             remote_sockid: sender.local_sockid,
@@ -213,7 +234,32 @@ impl RandomLossSimulation {
             bandwidth: Default::default(),
             recv_buffer_size: PacketCount(8192),
             send_buffer_size: PacketCount(8192),
+            send_buffer_bytes: None,
+            send_buffer_time: None,
+            send_buffer_policy: SendBufferPolicy::DropOldest,
             statistics_interval: Duration::from_secs(1),
+            peer_idle_timeout: Duration::from_secs(5),
+            ack2_mode: Ack2Mode::EveryFullAck,
+            lite_ack_rtt_sampling: false,
+            max_burst: PacketCount(0),
+            duplicate_interval: None,
+            fast_retransmit: None,
+            sequence_restart_window: PacketCount(0),
+            skip_gaps: false,
+            immediate_nak: false,
+            light_ack_interval: PacketCount(64),
+            max_delivery_rate: None,
+            half_close: false,
+            limit_payload_size: false,
+            keepalive_interval: Duration::from_secs(1),
+            adaptive_keepalive: false,
+            message_tags: false,
+            ecn: false,
+            peer_address_policy: PeerAddressPolicy::Rebind,
+            encrypt_control: false,
+            peer_version: SrtVersion::CURRENT,
+            peer_flags: SrtShakeFlags::SUPPORTED,
+            local_flags: SrtShakeFlags::SUPPORTED,
         }
     }
 }
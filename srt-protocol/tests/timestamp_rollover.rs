@@ -10,7 +10,7 @@ use log::trace;
 
 use srt_protocol::{
     connection::{Connection, ConnectionSettings, DuplexConnection, Input},
-    options::{PacketCount, PacketSize},
+    options::{Ack2Mode, PacketCount, PacketSize, PeerAddressPolicy, SendBufferPolicy, SrtVersion},
     packet::*,
     protocol::handshake::Handshake,
 };
@@ -45,7 +45,32 @@ fn timestamp_rollover() {
         bandwidth: Default::default(),
         recv_buffer_size: PacketCount(8192),
         send_buffer_size: PacketCount(8192),
+        send_buffer_bytes: None,
+        send_buffer_time: None,
+        send_buffer_policy: SendBufferPolicy::DropOldest,
         statistics_interval: Duration::from_secs(1),
+        peer_idle_timeout: Duration::from_secs(5),
+        ack2_mode: Ack2Mode::EveryFullAck,
+        lite_ack_rtt_sampling: false,
+        max_burst: PacketCount(0),
+        duplicate_interval: None,
+        fast_retransmit: None,
+        sequence_restart_window: PacketCount(0),
+        skip_gaps: false,
+        immediate_nak: false,
+        light_ack_interval: PacketCount(64),
+        max_delivery_rate: None,
+        half_close: false,
+        limit_payload_size: false,
+        keepalive_interval: Duration::from_secs(1),
+        adaptive_keepalive: false,
+        message_tags: false,
+        ecn: false,
+        peer_address_policy: PeerAddressPolicy::Rebind,
+        encrypt_control: false,
+        peer_version: SrtVersion::CURRENT,
+        peer_flags: SrtShakeFlags::SUPPORTED,
+        local_flags: SrtShakeFlags::SUPPORTED,
     };
 
     let s2 = ConnectionSettings {
@@ -65,7 +90,32 @@ fn timestamp_rollover() {
         bandwidth: Default::default(),
         recv_buffer_size: PacketCount(8192),
         send_buffer_size: PacketCount(8192),
+        send_buffer_bytes: None,
+        send_buffer_time: None,
+        send_buffer_policy: SendBufferPolicy::DropOldest,
         statistics_interval: Duration::from_secs(1),
+        peer_idle_timeout: Duration::from_secs(5),
+        ack2_mode: Ack2Mode::EveryFullAck,
+        lite_ack_rtt_sampling: false,
+        max_burst: PacketCount(0),
+        duplicate_interval: None,
+        fast_retransmit: None,
+        sequence_restart_window: PacketCount(0),
+        skip_gaps: false,
+        immediate_nak: false,
+        light_ack_interval: PacketCount(64),
+        max_delivery_rate: None,
+        half_close: false,
+        limit_payload_size: false,
+        keepalive_interval: Duration::from_secs(1),
+        adaptive_keepalive: false,
+        message_tags: false,
+        ecn: false,
+        peer_address_policy: PeerAddressPolicy::Rebind,
+        encrypt_control: false,
+        peer_version: SrtVersion::CURRENT,
+        peer_flags: SrtShakeFlags::SUPPORTED,
+        local_flags: SrtShakeFlags::SUPPORTED,
     };
 
     const PACKET_RATE: u32 = 10; // 10 packet/s
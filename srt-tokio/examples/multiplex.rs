@@ -5,19 +5,31 @@ use std::{
 
 use bytes::Bytes;
 use futures::{stream, SinkExt, StreamExt};
-use tokio::time::sleep;
+use tokio::{signal, time::sleep};
 
 use srt_tokio::SrtListener;
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     let port = 3333;
-    let (_binding, mut incoming) = SrtListener::builder().bind(port).await?;
+    let (mut binding, incoming) = SrtListener::builder().bind(port).await?;
 
     println!("SRT Multiplex Server is listening on port: {port}");
 
-    while let Some(request) = incoming.incoming().next().await {
-        let mut srt_socket = request.accept(None).await.unwrap();
+    let mut sockets = incoming.accept_all();
+    loop {
+        let mut srt_socket = tokio::select! {
+            srt_socket = sockets.next() => match srt_socket {
+                Some(Ok(srt_socket)) => srt_socket,
+                // one client failing to connect doesn't stop the others
+                Some(Err(e)) => {
+                    println!("\nAccept failed: {e}");
+                    continue;
+                }
+                None => break,
+            },
+            _ = signal::ctrl_c() => break,
+        };
         tokio::spawn(async move {
             let client_desc = format!(
                 "(ip_port: {}, sockid: {})",
@@ -48,5 +60,11 @@ async fn main() -> Result<(), Error> {
             println!("\nClient {client_desc} disconnected");
         });
     }
+
+    // the clients get what was already sent to them, for up to 5 seconds
+    println!("\nShutting down");
+    binding
+        .shutdown(Instant::now() + Duration::from_secs(5))
+        .await;
     Ok(())
 }
@@ -0,0 +1,62 @@
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Where a socket gets the current time from.
+///
+/// srt-protocol derives everything time related, packet timestamps, TSBPD release times and
+/// timers, from the instants it's handed, so swapping the clock moves a whole connection onto
+/// another timebase. Several senders into one facility can use a clock disciplined by PTP or NTP
+/// to share it. The clock must never go backwards, and the instants passed to and returned from
+/// the socket's `Sink + Stream` are on it, not on [`Instant::now`].
+///
+/// Timers still sleep on tokio's clock for however long is left on this one.
+pub trait Clock: Send + Sync + 'static {
+    fn now(&self) -> Instant;
+}
+
+/// The system's monotonic clock, what sockets use unless told otherwise
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+// what sockets and their tasks hold on to
+#[derive(Clone)]
+pub(crate) struct SharedClock(Arc<dyn Clock>);
+
+impl SharedClock {
+    pub fn new(clock: impl Clock) -> Self {
+        SharedClock(Arc::new(clock))
+    }
+
+    pub fn now(&self) -> Instant {
+        self.0.now()
+    }
+
+    // the tokio instant for a deadline on this clock, deadlines already past are due now
+    pub fn deadline(&self, at: Instant) -> tokio::time::Instant {
+        let left = at
+            .checked_duration_since(self.now())
+            .unwrap_or(Duration::ZERO);
+        tokio::time::Instant::now() + left
+    }
+}
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        SharedClock::new(SystemClock)
+    }
+}
+
+impl fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SharedClock").finish_non_exhaustive()
+    }
+}
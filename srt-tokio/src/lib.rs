@@ -1,7 +1,7 @@
-#![forbid(unsafe_code)]
+#![deny(unsafe_code)]
 #![recursion_limit = "256"]
 
-//! Implementation of [SRT](https://www.haivision.com/products/srt-secure-reliable-transport/) in pure safe rust.
+//! Implementation of [SRT](https://www.haivision.com/products/srt-secure-reliable-transport/) in pure rust, safe apart from the platform specific socket and timer calls in `net`.
 //!
 //! Generally used for live video streaming across lossy but high bandwidth connections.
 //!
@@ -47,16 +47,31 @@
 //! ```
 //!
 
+mod clock;
 mod listener;
 mod net;
 mod socket;
 mod watch;
 
-pub use net::bind_socket;
+pub use net::{bind_socket, Binder, DatagramSocket, Resolver, SystemResolver};
 pub use srt_protocol::access;
+#[cfg(feature = "packet_telemetry")]
+pub use srt_protocol::connection::telemetry;
+pub use srt_protocol::connection::{
+    DelaySummary, Delivery, EchoSample, EchoStatistics, PayloadTooLarge, SendMessage, StallEvent,
+};
 pub use srt_protocol::options;
+pub use srt_protocol::protocol::pending_connection::{HandshakeDirection, HandshakeEvent};
+pub use srt_protocol::statistics;
 
 pub use crate::{
-    listener::{ConnectionRequest, ListenerStatistics, SrtIncoming, SrtListener},
-    socket::{SocketStatistics, SrtSocket, SrtSocketBuilder},
+    clock::{Clock, SystemClock},
+    listener::{
+        ConnectionRequest, HandshakeInfo, ListenerConnection, ListenerStatistics, SrtIncoming,
+        SrtListener, VirtualListeners,
+    },
+    socket::{
+        AsyncKeyProvider, DriverThread, Impairment, PendingDelivery, SocketStatistics, SrtFramed,
+        SrtSocket, SrtSocketBuilder,
+    },
 };
@@ -1,13 +1,26 @@
-use std::{convert::TryInto, io, time::Duration};
+use std::{convert::TryInto, io, sync::Arc, time::Duration};
 
+#[cfg(feature = "packet_telemetry")]
+use srt_protocol::connection::{
+    telemetry::{PacketEvent, PacketHook},
+    ConnectionSettings,
+};
 use tokio::net::UdpSocket;
 
-use crate::options::*;
+use crate::{net::Binder, options::*};
 
-use super::{SrtIncoming, SrtListener};
+use super::{session::Configure, SrtIncoming, SrtListener};
+
+#[cfg(feature = "packet_telemetry")]
+type NewPacketHook = Arc<dyn Fn(&ConnectionSettings) -> PacketHook + Send + Sync>;
 
 #[derive(Default)]
-pub struct SrtListenerBuilder(SocketOptions, Option<UdpSocket>);
+pub struct SrtListenerBuilder(
+    SocketOptions,
+    Option<UdpSocket>,
+    Option<Arc<dyn Binder>>,
+    #[cfg(feature = "packet_telemetry")] Option<NewPacketHook>,
+);
 
 /// Struct to build a multiplexed listener.
 ///
@@ -71,11 +84,46 @@ impl SrtListenerBuilder {
         self
     }
 
+    // SRTO_UDP_SNDBUF
+    /// Set the kernel send buffer size of the UDP socket. The OS may grant less, which is logged.
+    pub fn udp_send_buffer_size(mut self, size: ByteCount) -> Self {
+        self.0.connect.udp_send_buffer_size = size;
+        self
+    }
+
+    // SRTO_UDP_RCVBUF
+    /// Set the kernel receive buffer size of the UDP socket. The OS may grant less, which is logged.
+    pub fn udp_recv_buffer_size(mut self, size: ByteCount) -> Self {
+        self.0.connect.udp_recv_buffer_size = size;
+        self
+    }
+
     pub fn socket(mut self, socket: UdpSocket) -> Self {
         self.1 = Some(socket);
         self
     }
 
+    /// Open the socket the listener and its connections run on with `binder` instead of binding
+    /// a UDP socket, see [`SrtSocketBuilder::binder`](crate::SrtSocketBuilder::binder)
+    pub fn binder(mut self, binder: impl Binder) -> Self {
+        self.2 = Some(Arc::new(binder));
+        self
+    }
+
+    /// Calls `new_hook` for each connection the listener opens, the hook it returns is called
+    /// with every packet that connection sends or receives.
+    #[cfg(feature = "packet_telemetry")]
+    pub fn packet_hook<H>(
+        mut self,
+        new_hook: impl Fn(&ConnectionSettings) -> H + Send + Sync + 'static,
+    ) -> Self
+    where
+        H: FnMut(&PacketEvent) + Send + 'static,
+    {
+        self.3 = Some(Arc::new(move |settings| Box::new(new_hook(settings))));
+        self
+    }
+
     pub fn with<O>(mut self, options: O) -> Self
     where
         SocketOptions: OptionsOf<O>,
@@ -91,14 +139,27 @@ impl SrtListenerBuilder {
     }
 
     pub async fn bind(
-        self,
+        mut self,
         local: impl TryInto<SocketAddress>,
     ) -> Result<(SrtListener, SrtIncoming), io::Error> {
+        let configure = self.take_configure();
         let options = ListenerOptions::with(local, self.0)?;
         match self.1 {
-            None => SrtListener::bind(options).await,
-            Some(socket) => SrtListener::bind_with_socket(options, socket).await,
+            None => SrtListener::bind_with_binder(options, self.2.as_deref(), configure).await,
+            Some(socket) => SrtListener::bind_with_datagrams(options, socket.into(), configure),
+        }
+    }
+
+    // settings for each connection that aren't part of the socket options
+    fn take_configure(&mut self) -> Option<Configure> {
+        #[cfg(feature = "packet_telemetry")]
+        if let Some(new_hook) = self.3.take() {
+            return Some(Arc::new(move |connection| {
+                let hook = new_hook(connection.settings());
+                connection.set_packet_hook(hook);
+            }));
         }
+        None
     }
 }
 
@@ -125,6 +186,8 @@ mod tests {
             .latency(Duration::from_secs(1))
             .encryption(0, "super secret passcode")
             .bandwidth(LiveBandwidthMode::Max(DataRate(1_000_000)))
+            .udp_send_buffer_size(ByteCount(1_000_000))
+            .udp_recv_buffer_size(ByteCount(1_000_000))
             .socket(socket)
             .bind(9999)
             .await
@@ -1,26 +1,40 @@
 mod builder;
 mod session;
 mod state;
+mod virtual_listeners;
 
-use std::{io, sync::Arc};
+use std::{io, time::Instant};
 
 use futures::{channel::mpsc, prelude::*};
-use srt_protocol::settings::ConnInitSettings;
+use srt_protocol::{
+    access::RejectReason,
+    settings::{ConnInitSettings, ConnectionSettingsOverride, SocketId},
+};
 use tokio::{net::UdpSocket, sync::oneshot, task::JoinHandle};
 
-use crate::net::bind_socket;
+use crate::SrtSocket;
 
-use super::{net::PacketSocket, options::*, watch};
+use session::Configure;
+use state::AdminRequest;
+
+use super::{
+    net::{Binder, Datagrams, PacketSocket},
+    options::*,
+    watch,
+};
 
 pub use builder::SrtListenerBuilder;
-pub use session::ConnectionRequest;
+pub use session::{ConnectionRequest, ListenerConnection};
+pub use srt_protocol::listener::HandshakeInfo;
 pub use srt_protocol::statistics::ListenerStatistics;
+pub use virtual_listeners::VirtualListeners;
 
 #[derive(Debug)]
 pub struct SrtListener {
     settings: ConnInitSettings,
     statistics_receiver: watch::Receiver<ListenerStatistics>,
-    close_req: Option<oneshot::Sender<()>>,
+    close_req: Option<oneshot::Sender<Option<Instant>>>,
+    admin_sender: mpsc::Sender<AdminRequest>,
     task: JoinHandle<()>,
 }
 
@@ -35,18 +49,34 @@ impl SrtListener {
     }
 
     pub async fn bind(options: Valid<ListenerOptions>) -> Result<(Self, SrtIncoming), io::Error> {
-        let socket = bind_socket(&options.socket).await?;
-        Self::bind_with_socket(options, socket).await
+        Self::bind_with_binder(options, None, None).await
     }
 
     pub async fn bind_with_socket(
         options: Valid<ListenerOptions>,
         socket: UdpSocket,
+    ) -> Result<(Self, SrtIncoming), io::Error> {
+        Self::bind_with_datagrams(options, socket.into(), None)
+    }
+
+    async fn bind_with_binder(
+        options: Valid<ListenerOptions>,
+        binder: Option<&dyn Binder>,
+        configure: Option<Configure>,
+    ) -> Result<(Self, SrtIncoming), io::Error> {
+        let socket = Datagrams::bind(binder, &options.socket).await?;
+        Self::bind_with_datagrams(options, socket, configure)
+    }
+
+    fn bind_with_datagrams(
+        options: Valid<ListenerOptions>,
+        socket: Datagrams,
+        configure: Option<Configure>,
     ) -> Result<(Self, SrtIncoming), io::Error> {
         use state::SrtListenerState;
         let socket_options = options.into_value().socket;
         let local_address = socket.local_addr()?;
-        let socket = PacketSocket::from_socket(Arc::new(socket), 1024 * 1024);
+        let socket = PacketSocket::from_socket(socket, 1024 * 1024);
         let settings = ConnInitSettings::from(socket_options);
         let (close_req, close_resp) = oneshot::channel();
         let (request_sender, request_receiver) = mpsc::channel(100);
@@ -58,7 +88,9 @@ impl SrtListener {
             request_sender,
             statistics_sender,
             close_resp,
+            configure,
         );
+        let admin_sender = state.admin_sender();
         let task = tokio::spawn(async move {
             state.run_loop().await;
         });
@@ -67,6 +99,7 @@ impl SrtListener {
                 settings,
                 statistics_receiver,
                 close_req: Some(close_req),
+                admin_sender,
                 task,
             },
             SrtIncoming { request_receiver },
@@ -81,8 +114,45 @@ impl SrtListener {
         &mut self.statistics_receiver
     }
 
+    /// The connections accepted on this listener that are still open, with their latest
+    /// statistics, e.g. for an admin endpoint. Empty once the listener is closed.
+    pub async fn connections(&self) -> Vec<ListenerConnection> {
+        let (reply, connections) = oneshot::channel();
+        let request = AdminRequest::Connections(reply);
+        if self.admin_sender.clone().send(request).await.is_err() {
+            return vec![];
+        }
+        connections.await.unwrap_or_default()
+    }
+
+    /// Abort the open connection with this local socket id, its peer is sent a Shutdown and the
+    /// application's socket ends. Returns whether there was one.
+    pub async fn kick(&self, socket_id: SocketId) -> bool {
+        let (reply, kicked) = oneshot::channel();
+        let request = AdminRequest::Kick(socket_id, reply);
+        if self.admin_sender.clone().send(request).await.is_err() {
+            return false;
+        }
+        kicked.await.unwrap_or(false)
+    }
+
     pub async fn close(&mut self) {
-        let _ = self.close_req.take().unwrap().send(());
+        let _ = self.close_req.take().unwrap().send(None);
+        (&mut self.task).await.unwrap();
+    }
+
+    /// Stop accepting connections and close the ones that are open gracefully, resolving once
+    /// they are all closed, or at `deadline`.
+    ///
+    /// New handshakes are rejected with [`ServerRejectReason::Down`] and the
+    /// [`incoming`](SrtIncoming::incoming) requests end. Each open connection takes no more data
+    /// from its socket, sends what was queued and then tells the peer with a Shutdown, like
+    /// closing the socket would. Connections still open at the deadline are aborted, their peers
+    /// are sent a Shutdown right away.
+    ///
+    /// [`ServerRejectReason::Down`]: srt_protocol::packet::ServerRejectReason::Down
+    pub async fn shutdown(&mut self, deadline: Instant) {
+        let _ = self.close_req.take().unwrap().send(Some(deadline));
         (&mut self.task).await.unwrap();
     }
 }
@@ -91,6 +161,62 @@ impl SrtIncoming {
     pub fn incoming(&mut self) -> &mut impl Stream<Item = ConnectionRequest> {
         &mut self.request_receiver
     }
+
+    /// Wait for the next connection request, the way a TCP server accepts connections.
+    ///
+    /// The request stays pending until it's answered with
+    /// [`accept_with_override`](ConnectionRequest::accept_with_override) or
+    /// [`reject`](ConnectionRequest::reject), which leaves the application time to look at what
+    /// the caller proposed in its handshake, e.g. to hold the number of connections to a limit.
+    pub async fn accept(&mut self) -> Result<(ConnectionRequest, HandshakeInfo), io::Error> {
+        let request = self
+            .request_receiver
+            .next()
+            .await
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "listener closed"))?;
+        let handshake = request.handshake().clone();
+        Ok((request, handshake))
+    }
+
+    /// Route the connection requests to several virtual listeners by their stream id, each with
+    /// its own settings, see [`VirtualListeners`]
+    pub fn into_virtual_listeners(self) -> VirtualListeners {
+        VirtualListeners::new(self)
+    }
+
+    /// Accept every connection request, yielding connected sockets.
+    pub fn accept_all(self) -> impl Stream<Item = Result<SrtSocket, io::Error>> + Unpin {
+        self.accept_with(|_| Ok(ConnectionSettingsOverride::default()))
+    }
+
+    /// Accept or reject each connection request as decided by `decide`, yielding connected
+    /// sockets for the accepted ones.
+    ///
+    /// `decide` returns the settings to apply to that connection only, or the reason the request
+    /// is rejected. Rejected requests are not yielded.
+    pub fn accept_with<F>(
+        self,
+        mut decide: F,
+    ) -> impl Stream<Item = Result<SrtSocket, io::Error>> + Unpin
+    where
+        F: FnMut(&ConnectionRequest) -> Result<ConnectionSettingsOverride, RejectReason>,
+    {
+        Box::pin(
+            self.request_receiver
+                .then(move |request| {
+                    let decision = decide(&request);
+                    async move {
+                        match decision {
+                            Ok(settings_override) => {
+                                Some(request.accept_with_override(settings_override).await)
+                            }
+                            Err(reason) => request.reject(reason).await.err().map(Err),
+                        }
+                    }
+                })
+                .filter_map(future::ready),
+        )
+    }
 }
 
 impl Drop for SrtListener {
@@ -108,7 +234,7 @@ mod tests {
     use futures::{channel::oneshot, future::join_all, prelude::*};
     use log::{debug, info};
 
-    use crate::{access::*, SrtSocket};
+    use crate::SrtSocket;
 
     use super::*;
 
@@ -289,6 +415,195 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn accept_up_to_limit() -> Result<()> {
+        use srt_protocol::{
+            options::{KeySize, SrtVersion},
+            packet::ServerRejectReason,
+        };
+
+        let _ = pretty_env_logger::try_init();
+
+        let (_server, mut incoming) = SrtListener::builder()
+            .encryption(16, "super secret passcode")
+            .bind("127.0.0.1:4003")
+            .await?;
+        let listener = tokio::spawn(async move {
+            let mut connections = vec![];
+            for _ in 0..2 {
+                let (request, handshake) = incoming.accept().await.unwrap();
+                assert_eq!(handshake.key_size, KeySize::AES128);
+                let srt = handshake.srt.expect("HSREQ");
+                assert_eq!(srt.version, SrtVersion::CURRENT);
+                assert_eq!(srt.recv_latency, Duration::from_millis(250));
+                if connections.is_empty() {
+                    let socket = request.accept_with_override(Default::default()).await;
+                    connections.push(socket.unwrap());
+                } else {
+                    let reason = ServerRejectReason::Overload.into();
+                    request.reject(reason).await.unwrap();
+                }
+            }
+            connections
+        });
+
+        let caller = || {
+            SrtSocket::builder()
+                .latency(Duration::from_millis(250))
+                .encryption(16, "super secret passcode")
+                .call("127.0.0.1:4003", None)
+        };
+        let _first = caller().await?;
+        assert!(caller().await.is_err());
+        assert_eq!(listener.await?.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn shutdown() -> Result<()> {
+        let _ = pretty_env_logger::try_init();
+
+        let (mut server, mut incoming) = SrtListener::builder().bind("127.0.0.1:4004").await?;
+        let receivers = (0..2).map(|_| async {
+            let mut receiver = SrtSocket::builder().call("127.0.0.1:4004", None).await?;
+            let mut received = 0;
+            while receiver.try_next().await?.is_some() {
+                received += 1;
+            }
+            Ok::<_, io::Error>(received)
+        });
+        let receivers = tokio::spawn(futures::future::try_join_all(receivers));
+
+        // the senders are left open
+        let mut senders = vec![];
+        for _ in 0..2 {
+            let (request, _) = incoming.accept().await?;
+            let sender = request.accept(None).await?;
+            senders.push(sender);
+        }
+        for i in 0..100 {
+            for sender in &mut senders {
+                sender
+                    .send((Instant::now(), Bytes::from(format!("{i}"))))
+                    .await?;
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let start = Instant::now();
+        server.shutdown(start + Duration::from_secs(10)).await;
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // everything queued was delivered before the peers were told
+        assert_eq!(receivers.await??, [100, 100]);
+        for sender in &mut senders {
+            assert!(sender.next().await.is_none());
+            assert!(sender.send((Instant::now(), Bytes::new())).await.is_err());
+        }
+        assert!(incoming.accept().await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn connections_and_kick() -> Result<()> {
+        let _ = pretty_env_logger::try_init();
+
+        let (server, mut incoming) = SrtListener::builder().bind("127.0.0.1:4006").await?;
+        let caller = tokio::spawn(async {
+            let mut caller = SrtSocket::builder()
+                .call("127.0.0.1:4006", Some("admin"))
+                .await?;
+            while caller.try_next().await?.is_some() {}
+            Ok::<_, io::Error>(())
+        });
+
+        let (request, _) = incoming.accept().await?;
+        let mut sender = request.accept(None).await?;
+
+        let connections = server.connections().await;
+        assert_eq!(connections.len(), 1);
+        let connection = &connections[0];
+        assert_eq!(connection.settings.stream_id.as_deref(), Some("admin"));
+        assert_eq!(
+            connection.settings.local_sockid,
+            sender.settings().local_sockid
+        );
+        assert_eq!(connection.settings.remote, sender.settings().remote);
+
+        assert!(!server.kick(SocketId(0)).await);
+        assert!(server.kick(sender.settings().local_sockid).await);
+
+        // both ends are closed, without waiting for a timeout
+        tokio::time::timeout(Duration::from_secs(2), caller).await???;
+        assert!(sender.next().await.is_none());
+        assert!(server.connections().await.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn overflow() -> Result<()> {
+        use tokio::time::{sleep, timeout};
+
+        let _ = pretty_env_logger::try_init();
+
+        let (mut server, mut incoming) = SrtListener::builder().bind("127.0.0.1:4005").await?;
+
+        // a connection flooded with more than the application, which never reads, takes
+        let flood = tokio::spawn(async {
+            let mut sender = SrtSocket::builder().call("127.0.0.1:4005", None).await?;
+            for i in 0..5000 {
+                sender
+                    .send((Instant::now(), Bytes::from(vec![0; 1316])))
+                    .await?;
+                if i % 10 == 0 {
+                    sleep(Duration::from_millis(1)).await;
+                }
+            }
+            Ok::<_, io::Error>(sender)
+        });
+        let (request, _) = incoming.accept().await?;
+        let _flooded = request.accept(None).await?;
+        sleep(Duration::from_millis(500)).await;
+
+        // doesn't hold up another one
+        let other = tokio::spawn(async {
+            let mut sender = SrtSocket::builder().call("127.0.0.1:4005", None).await?;
+            for i in 0..100 {
+                sender
+                    .send((Instant::now(), Bytes::from(format!("{i}"))))
+                    .await?;
+                sleep(Duration::from_millis(1)).await;
+            }
+            sender.close().await?;
+            Ok::<_, io::Error>(())
+        });
+        let (request, _) = timeout(Duration::from_secs(2), incoming.accept()).await??;
+        let mut receiver = request.accept(None).await?;
+        let received = timeout(Duration::from_secs(5), async {
+            let mut received = 0;
+            while receiver.try_next().await?.is_some() {
+                received += 1;
+            }
+            Ok::<_, io::Error>(received)
+        })
+        .await??;
+        assert_eq!(received, 100);
+        other.await??;
+
+        let statistics = timeout(
+            Duration::from_secs(5),
+            server
+                .statistics()
+                .clone()
+                .filter(|s| future::ready(s.delegate_dropped_packets > 0))
+                .next(),
+        )
+        .await?;
+        assert!(statistics.is_some());
+        flood.abort();
+        Ok(())
+    }
+
     #[tokio::test]
     async fn multiplex_timeout() {
         use bytes::Bytes;
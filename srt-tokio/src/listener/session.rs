@@ -1,11 +1,11 @@
-use std::{io::ErrorKind, net::SocketAddr};
+use std::{io::ErrorKind, net::SocketAddr, sync::Arc};
 
 use futures::{
     channel::{mpsc, oneshot},
     SinkExt,
 };
 use srt_protocol::{
-    connection::{Connection, ConnectionSettings},
+    connection::{Connection, ConnectionSettings, DuplexConnection},
     listener::*,
     options::*,
     packet::*,
@@ -14,9 +14,10 @@ use srt_protocol::{
 use tokio::task::JoinHandle;
 
 use crate::{
+    clock::SharedClock,
     net::PacketSocket,
-    socket::factory::{self, SrtSocketFactory, SrtSocketTaskFactory},
-    SrtSocket,
+    socket::factory::{self, Command, SrtSocketFactory, SrtSocketTaskFactory},
+    watch, SocketStatistics, SrtSocket,
 };
 
 #[derive(Debug)]
@@ -25,6 +26,7 @@ pub struct ConnectionRequest {
     request: AccessControlRequest,
     settings_receiver: oneshot::Receiver<(ConnectionSettings, JoinHandle<()>)>,
     socket_factory: SrtSocketFactory,
+    defaults: Option<Arc<ConnectionSettingsOverride>>,
 }
 
 impl ConnectionRequest {
@@ -40,13 +42,32 @@ impl ConnectionRequest {
     pub fn stream_id(&self) -> Option<&StreamId> {
         self.request.stream_id.as_ref()
     }
+    /// What the caller proposed in its handshake
+    pub fn handshake(&self) -> &HandshakeInfo {
+        &self.request.handshake
+    }
 
     pub async fn accept(
         self,
         key_settings: Option<KeySettings>,
     ) -> Result<SrtSocket, std::io::Error> {
+        self.accept_with_override(key_settings.into()).await
+    }
+
+    /// Accept the connection, with settings that apply to this connection only
+    ///
+    /// Requests routed by [`VirtualListeners`](super::VirtualListeners) take the settings of the
+    /// listener they were routed to for the fields left unset here.
+    pub async fn accept_with_override(
+        self,
+        settings_override: ConnectionSettingsOverride,
+    ) -> Result<SrtSocket, std::io::Error> {
+        let settings_override = match self.defaults {
+            Some(defaults) => settings_override.or(ConnectionSettingsOverride::clone(&defaults)),
+            None => settings_override,
+        };
         self.response_sender
-            .send(AccessControlResponse::Accepted(key_settings))
+            .send(AccessControlResponse::Accepted(settings_override))
             .await?;
 
         let (settings, jh) = self
@@ -54,7 +75,9 @@ impl ConnectionRequest {
             .await
             .map_err(|e| std::io::Error::new(ErrorKind::NotConnected, e))?;
 
-        Ok(self.socket_factory.create_socket(settings, jh))
+        Ok(self
+            .socket_factory
+            .create_socket(settings, SharedClock::default(), jh))
     }
 
     pub async fn reject(self, reason: RejectReason) -> Result<(), std::io::Error> {
@@ -62,8 +85,20 @@ impl ConnectionRequest {
             .send(AccessControlResponse::Rejected(reason))
             .await
     }
+
+    pub(crate) fn with_defaults(mut self, defaults: Arc<ConnectionSettingsOverride>) -> Self {
+        self.defaults = Some(defaults);
+        self
+    }
 }
 
+// settings for each connection the listener opens that aren't part of the socket options
+pub type Configure = Arc<dyn Fn(&mut DuplexConnection) + Send + Sync>;
+
+// how many packets a connection can fall behind the listener before they are dropped, it doesn't
+// wait for one connection while the others go without
+const CONNECTION_QUEUE: usize = 1024;
+
 #[derive(Debug)]
 pub struct PendingConnection {
     settings_sender: oneshot::Sender<(ConnectionSettings, JoinHandle<()>)>,
@@ -91,6 +126,7 @@ impl PendingConnection {
             response_sender,
             settings_receiver,
             socket_factory,
+            defaults: None,
         };
 
         (state, request)
@@ -100,33 +136,93 @@ impl PendingConnection {
         self,
         socket: &PacketSocket,
         connection: Connection,
+        configure: Option<&Configure>,
     ) -> Result<OpenConnection, ()> {
-        let (packet_sender, socket) = socket.clone_channel(100);
-        let (handle, settings) = self.task_factory.spawn_task(socket, connection);
+        let (packet_sender, socket) = socket.clone_channel(CONNECTION_QUEUE);
+        let command_sender = self.task_factory.command_sender();
+        let statistics = self.task_factory.statistics_receiver();
+        let mut connection = DuplexConnection::new(connection);
+        if let Some(configure) = configure {
+            configure(&mut connection);
+        }
+        let (handle, settings) = self.task_factory.spawn_task(
+            socket,
+            connection,
+            SharedClock::default(),
+            // the listener holds the lease for as long as the session exists
+            None,
+        );
         self.settings_sender
-            .send((settings, handle))
+            .send((settings.clone(), handle))
             .ok()
             .ok_or(())?;
-        Ok(OpenConnection { packet_sender })
+        Ok(OpenConnection {
+            packet_sender,
+            command_sender,
+            settings,
+            statistics,
+        })
     }
 }
 
 #[derive(Debug)]
 pub struct OpenConnection {
     packet_sender: mpsc::Sender<ReceivePacketResult>,
+    command_sender: mpsc::Sender<Command>,
+    settings: ConnectionSettings,
+    statistics: watch::Receiver<SocketStatistics>,
 }
 
 impl OpenConnection {
-    pub async fn send(&mut self, packet: (Packet, SocketAddr)) -> Result<(), ()> {
+    /// Queue a packet for the connection, Ok(false) when the queue is full and it's dropped
+    pub fn send(&mut self, packet: (Packet, SocketAddr)) -> Result<bool, ()> {
         match self.packet_sender.try_send(Ok(packet)) {
-            Err(e) if e.is_full() => self.packet_sender.send(e.into_inner()).await.ok().ok_or(()),
-            r => r.ok().ok_or(()),
+            Ok(()) => Ok(true),
+            Err(e) if e.is_full() => Ok(false),
+            Err(_) => Err(()),
         }
     }
 
     pub async fn close(&mut self) -> Result<(), ()> {
         self.packet_sender.close().await.ok().ok_or(())
     }
+
+    /// Flush what the application queued and tell the peer, the connection keeps receiving
+    /// packets until it's done
+    pub fn shutdown(&mut self) {
+        let _ = self.command_sender.try_send(Command::Shutdown);
+    }
+
+    /// Close right away, the peer is sent a Shutdown
+    pub fn abort(&mut self) {
+        let _ = self.command_sender.try_send(Command::Abort);
+    }
+
+    /// Whether the task driving the connection is done
+    pub fn is_closed(&self) -> bool {
+        self.packet_sender.is_closed()
+    }
+
+    pub fn local_socket_id(&self) -> SocketId {
+        self.settings.local_sockid
+    }
+
+    pub fn describe(&self) -> ListenerConnection {
+        ListenerConnection {
+            settings: self.settings.clone(),
+            statistics: self.statistics.borrow().clone(),
+        }
+    }
+}
+
+/// A connection a listener accepted that is still open, see [`SrtListener::connections`]
+///
+/// [`SrtListener::connections`]: crate::SrtListener::connections
+#[derive(Debug, Clone)]
+pub struct ListenerConnection {
+    pub settings: ConnectionSettings,
+    /// As of the last update the connection published
+    pub statistics: SocketStatistics,
 }
 
 #[derive(Debug)]
@@ -6,12 +6,22 @@ use std::{
 
 use futures::{channel::mpsc, future::Fuse, prelude::*, select, FutureExt, SinkExt};
 use srt_protocol::{connection::Connection, listener::*, packet::*, settings::ConnInitSettings};
-use tokio::sync::oneshot;
+use tokio::{sync::oneshot, task::yield_now, time::sleep_until};
 
-use crate::{net::PacketSocket, watch};
+use crate::{
+    net::{PacketSocket, PACKET_BUDGET},
+    watch,
+};
 
 use super::session::*;
 
+// what SrtListener asks of the running listener on the application's behalf
+#[derive(Debug)]
+pub enum AdminRequest {
+    Connections(oneshot::Sender<Vec<ListenerConnection>>),
+    Kick(SocketId, oneshot::Sender<bool>),
+}
+
 pub struct SrtListenerState {
     local_address: SocketAddr,
     listener: MultiplexListener,
@@ -22,7 +32,12 @@ pub struct SrtListenerState {
     statistics_sender: watch::Sender<ListenerStatistics>,
     pending_connections: HashMap<SessionId, PendingConnection>,
     open_connections: HashMap<SessionId, OpenConnection>,
-    close_recvr: Fuse<oneshot::Receiver<()>>,
+    // closes right away with None, or shuts down gracefully until the deadline
+    close_recvr: Fuse<oneshot::Receiver<Option<Instant>>>,
+    admin_sender: mpsc::Sender<AdminRequest>,
+    admin_receiver: mpsc::Receiver<AdminRequest>,
+    shutdown_deadline: Option<Instant>,
+    configure: Option<Configure>,
 }
 
 impl SrtListenerState {
@@ -32,10 +47,12 @@ impl SrtListenerState {
         settings: ConnInitSettings,
         request_sender: mpsc::Sender<ConnectionRequest>,
         statistics_sender: watch::Sender<ListenerStatistics>,
-        close_recvr: oneshot::Receiver<()>,
+        close_recvr: oneshot::Receiver<Option<Instant>>,
+        configure: Option<Configure>,
     ) -> Self {
         let listener = MultiplexListener::new(Instant::now(), local_address, settings);
         let (response_sender, response_receiver) = mpsc::channel(100);
+        let (admin_sender, admin_receiver) = mpsc::channel(16);
         Self {
             local_address,
             listener,
@@ -47,15 +64,25 @@ impl SrtListenerState {
             pending_connections: Default::default(),
             open_connections: Default::default(),
             close_recvr: close_recvr.fuse(),
+            admin_sender,
+            admin_receiver,
+            shutdown_deadline: None,
+            configure,
         }
     }
 
+    pub fn admin_sender(&self) -> mpsc::Sender<AdminRequest> {
+        self.admin_sender.clone()
+    }
+
     pub async fn run_loop(mut self) {
         use Action::*;
         let mut input = Input::Timer;
         let mut timer_interval = tokio::time::interval(Duration::from_millis(100));
         let start = Instant::now();
         let elapsed = |now: Instant| TimeSpan::from_interval(start, now);
+        // the connections take the packets delegated to them in turns with the listener
+        let mut packets_in_a_row = 0;
         loop {
             let now = Instant::now();
             log::debug!(
@@ -87,7 +114,10 @@ impl SrtListenerState {
                     next.input_from(self.open_connection(session_id, connection).await)
                 }
                 DelegatePacket(session_id, packet) => {
-                    next.input_from(self.delegate_packet(session_id, packet).await)
+                    match self.delegate_packet(session_id, packet) {
+                        Ok(false) => next.overflow(),
+                        result => next.input_from(result),
+                    }
                 }
                 DropConnection(session_id) => {
                     next.input_from(self.drop_connection(session_id).await)
@@ -95,12 +125,52 @@ impl SrtListenerState {
                 UpdateStatistics(statistics) => {
                     next.input_from(self.statistics_sender.send(statistics.clone()))
                 }
-                WaitForInput => select! {
-                    packet = self.socket.receive().fuse() => Input::Packet(packet),
-                    response = self.response_receiver.next() => Input::AccessResponse(response),
-                    _ = timer_interval.tick().fuse() => Input::Timer,
-                    _ = &mut self.close_recvr => break,
-                },
+                WaitForInput => {
+                    if let Some(deadline) = self.shutdown_deadline {
+                        self.open_connections.retain(|_, conn| !conn.is_closed());
+                        if self.open_connections.is_empty() {
+                            break;
+                        }
+                        if Instant::now() >= deadline {
+                            for conn in self.open_connections.values_mut() {
+                                conn.abort();
+                            }
+                            break;
+                        }
+                    }
+                    let shutdown_timeout = async {
+                        match self.shutdown_deadline {
+                            Some(deadline) => sleep_until(deadline.into()).await,
+                            None => future::pending().await,
+                        }
+                    };
+                    if packets_in_a_row == PACKET_BUDGET {
+                        packets_in_a_row = 0;
+                        yield_now().await;
+                    }
+                    let input = select! {
+                        packet = self.socket.receive().fuse() => Input::Packet(packet),
+                        response = self.response_receiver.next() => Input::AccessResponse(response),
+                        _ = timer_interval.tick().fuse() => Input::Timer,
+                        _ = shutdown_timeout.fuse() => Input::Timer,
+                        request = self.admin_receiver.select_next_some() => {
+                            self.handle_admin(request);
+                            Input::Timer
+                        }
+                        close = &mut self.close_recvr => match close {
+                            Ok(Some(deadline)) => {
+                                self.start_shutdown(deadline);
+                                Input::Timer
+                            }
+                            _ => break,
+                        },
+                    };
+                    packets_in_a_row = match input {
+                        Input::Packet(_) => packets_in_a_row + 1,
+                        _ => 0,
+                    };
+                    input
+                }
                 Close => break,
             }
         }
@@ -110,11 +180,46 @@ impl SrtListenerState {
         }
     }
 
+    // connections flush and close, while new ones are turned away
+    fn start_shutdown(&mut self, deadline: Instant) {
+        self.shutdown_deadline = Some(deadline);
+        self.request_sender.close_channel();
+        for conn in self.open_connections.values_mut() {
+            conn.shutdown();
+        }
+    }
+
+    fn handle_admin(&mut self, request: AdminRequest) {
+        // the ones whose task is done are no longer open, though the listener still routes to them
+        // until it hears of it
+        let open = self
+            .open_connections
+            .values_mut()
+            .filter(|c| !c.is_closed());
+        match request {
+            AdminRequest::Connections(reply) => {
+                let _ = reply.send(open.map(|c| c.describe()).collect());
+            }
+            AdminRequest::Kick(socket_id, reply) => {
+                let connection = open.into_iter().find(|c| c.local_socket_id() == socket_id);
+                let _ = reply.send(connection.map(|c| c.abort()).is_some());
+            }
+        }
+    }
+
     async fn request_access(
         &mut self,
         session_id: SessionId,
         request: AccessControlRequest,
     ) -> Result<(), ()> {
+        if self.shutdown_deadline.is_some() {
+            let rejected = AccessControlResponse::Rejected(ServerRejectReason::Down.into());
+            return self
+                .response_sender
+                .try_send((session_id, rejected))
+                .ok()
+                .ok_or(());
+        }
         let request_sender = &mut self.request_sender;
         let response_sender = self.response_sender.clone();
         let (pending, request) =
@@ -144,7 +249,12 @@ impl SrtListenerState {
     ) -> Result<usize, ()> {
         let (packet, connection) = *connection;
         let pending = self.pending_connections.remove(&session_id).ok_or(())?;
-        let active = pending.transition_to_open(&self.socket, connection)?;
+        let mut active =
+            pending.transition_to_open(&self.socket, connection, self.configure.as_ref())?;
+        // accepted by the application just as the listener started shutting down
+        if self.shutdown_deadline.is_some() {
+            active.shutdown();
+        }
         let _ = self.open_connections.insert(session_id, active);
         match packet {
             Some(packet) => self.socket.send(packet).await.ok().ok_or(()),
@@ -152,14 +262,14 @@ impl SrtListenerState {
         }
     }
 
-    async fn delegate_packet(
+    fn delegate_packet(
         &mut self,
         session_id: SessionId,
         packet: (Packet, SocketAddr),
-    ) -> Result<(), ()> {
+    ) -> Result<bool, ()> {
         match self.open_connections.get_mut(&session_id) {
-            Some(connection) => connection.send(packet).await,
-            None => Ok(()),
+            Some(connection) => connection.send(packet),
+            None => Ok(true),
         }
     }
 
@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use futures::{channel::mpsc, prelude::*};
+use log::info;
+use srt_protocol::{packet::ServerRejectReason, settings::ConnectionSettingsOverride};
+
+use super::{ConnectionRequest, SrtIncoming};
+
+#[derive(Debug)]
+struct VirtualListener {
+    stream_id_prefix: String,
+    settings: Arc<ConnectionSettingsOverride>,
+    request_sender: mpsc::Sender<ConnectionRequest>,
+}
+
+/// Several virtual listeners on the port of one [`SrtListener`](super::SrtListener), each with
+/// its own passphrase and settings, e.g. to serve several tenants on one port without any of
+/// them knowing the credentials of the others.
+///
+/// The connection requests are routed by the stream id the caller asked for, to the listener
+/// registered with the longest prefix of it. Those that match none are rejected, and so are those
+/// for a listener with 100 requests that the application hasn't taken yet.
+///
+/// # Examples:
+/// ```no_run
+/// # use srt_protocol::settings::{KeySettings, KeySource};
+/// # use srt_tokio::{access::ConnectionSettingsOverride, options::KeySize, SrtListener};
+/// # use futures::StreamExt;
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), std::io::Error> {
+/// let (_listener, incoming) = SrtListener::builder().bind(3333).await?;
+/// let mut listeners = incoming.into_virtual_listeners();
+/// let mut tenant = listeners.register(
+///     "tenant/",
+///     ConnectionSettingsOverride {
+///         key_settings: Some(KeySettings {
+///             key_size: KeySize::AES128,
+///             key_source: KeySource::Passphrase("the tenant's passphrase".into()),
+///         }),
+///         ..Default::default()
+///     },
+/// );
+/// tokio::spawn(listeners.run());
+///
+/// while let Some(request) = tenant.incoming().next().await {
+///     let socket = request.accept(None).await?;
+///     // ...
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct VirtualListeners {
+    incoming: SrtIncoming,
+    listeners: Vec<VirtualListener>,
+}
+
+impl VirtualListeners {
+    pub(crate) fn new(incoming: SrtIncoming) -> Self {
+        Self {
+            incoming,
+            listeners: Vec::new(),
+        }
+    }
+
+    /// Register a listener for the stream ids starting with `stream_id_prefix`, the requests
+    /// routed to it are accepted with `settings` unless the application overrides them.
+    pub fn register(
+        &mut self,
+        stream_id_prefix: impl Into<String>,
+        settings: ConnectionSettingsOverride,
+    ) -> SrtIncoming {
+        let (request_sender, request_receiver) = mpsc::channel(100);
+        self.listeners.push(VirtualListener {
+            stream_id_prefix: stream_id_prefix.into(),
+            settings: Arc::new(settings),
+            request_sender,
+        });
+        SrtIncoming { request_receiver }
+    }
+
+    /// Route the connection requests until the listener is closed
+    pub async fn run(mut self) {
+        while let Some(request) = self.incoming.incoming().next().await {
+            let stream_id = request.stream_id().map(|s| &s[..]).unwrap_or_default();
+            let listener = self
+                .listeners
+                .iter_mut()
+                .filter(|l| stream_id.starts_with(&l.stream_id_prefix))
+                .max_by_key(|l| l.stream_id_prefix.len());
+            let Some(listener) = listener else {
+                info!("no listener for stream id {:?}", stream_id);
+                let _ = request.reject(ServerRejectReason::Notfound.into()).await;
+                continue;
+            };
+
+            // a tenant that falls behind has its own requests turned away, rather than holding up
+            // those of the others
+            let request = request.with_defaults(listener.settings.clone());
+            if let Err(e) = listener.request_sender.try_send(request) {
+                let reason = if e.is_full() {
+                    info!("too many requests for {:?}", listener.stream_id_prefix);
+                    ServerRejectReason::Overload
+                } else {
+                    ServerRejectReason::Notfound
+                };
+                let _ = e.into_inner().reject(reason.into()).await;
+            }
+        }
+    }
+}
@@ -12,14 +12,93 @@ use std::{
 
 use bytes::BytesMut;
 use futures::channel::mpsc::Receiver;
-use futures::{channel::mpsc, prelude::*};
+use futures::{channel::mpsc, future::poll_fn, prelude::*};
+use log::warn;
 use socket2::{Domain, Protocol, Socket, Type};
 use srt_protocol::packet::{Packet, ReceivePacketResult};
-use tokio::net::UdpSocket;
-use trust_dns_resolver::TokioAsyncResolver;
+use tokio::{io::ReadBuf, net::UdpSocket};
 
 use crate::options::*;
 
+mod transport;
+
+pub(crate) use transport::Network;
+pub use transport::{Binder, DatagramSocket, Resolver, SystemResolver};
+
+#[cfg(windows)]
+mod windows;
+
+#[cfg(windows)]
+pub use windows::TimerResolution;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod ecn;
+
+// the ECN bits of received datagrams aren't read on other platforms
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+mod ecn {
+    use std::{io, net::SocketAddr};
+
+    use bytes::BytesMut;
+    use tokio::net::UdpSocket;
+
+    pub fn enable(_socket: &socket2::Socket, _ipv6: bool) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn is_enabled(_socket: &UdpSocket) -> bool {
+        false
+    }
+
+    pub fn try_recv_buf_from(
+        socket: &UdpSocket,
+        buf: &mut BytesMut,
+    ) -> io::Result<(usize, SocketAddr, bool)> {
+        let (size, from) = socket.try_recv_buf_from(buf)?;
+        Ok((size, from, false))
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod timer;
+
+// sockets sleep on tokio's timers alone on other platforms
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+mod timer {
+    use std::{io, time::Duration};
+
+    pub struct PreciseTimer;
+
+    impl PreciseTimer {
+        pub fn new() -> io::Result<Self> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+
+        pub async fn sleep(&self, _duration: Duration) -> io::Result<()> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+    }
+}
+
+pub(crate) use timer::PreciseTimer;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) mod thread;
+
+// threads are scheduled as the OS sees fit on other platforms
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) mod thread {
+    use std::io;
+
+    pub fn set_realtime_priority(_priority: u8) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn pin_to_core(_core: usize) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
 pub async fn bind_socket(options: &SocketOptions) -> Result<UdpSocket, io::Error> {
     let socket = Socket::new(
         if options.connect.local.is_ipv4() {
@@ -37,23 +116,53 @@ pub async fn bind_socket(options: &SocketOptions) -> Result<UdpSocket, io::Error
     socket.set_nonblocking(true)?; // required for passing to tokio
     socket.set_recv_buffer_size(recv_buffer_size)?;
     socket.set_send_buffer_size(send_buffer_size)?;
-    socket.bind(&options.connect.local.into())?;
+    check_buffer_size("send", send_buffer_size, socket.send_buffer_size()?);
+    check_buffer_size("receive", recv_buffer_size, socket.recv_buffer_size()?);
+    #[cfg(windows)]
+    windows::disable_udp_connreset(&socket)?;
+    if options.connect.ecn {
+        if let Err(e) = ecn::enable(&socket, options.connect.local.is_ipv6()) {
+            warn!("ECN not available on this socket: {}", e);
+        }
+    }
+    // e.g. a caller on a fixed source port that's taken, which a firewall may insist on
+    let local = options.connect.local;
+    socket.bind(&local.into()).map_err(|e| match e.kind() {
+        ErrorKind::AddrInUse => {
+            io::Error::new(ErrorKind::AddrInUse, format!("{local} is already in use"))
+        }
+        _ => e,
+    })?;
 
     UdpSocket::from_std(socket.into())
 }
 
-pub async fn lookup_remote_host(remote: &SocketAddress) -> Result<SocketAddr, io::Error> {
+// the OS silently clamps buffer sizes to its limits, e.g. net.core.rmem_max on Linux, and too
+// small buffers drop packets at high bitrates
+fn check_buffer_size(kind: &str, requested: usize, reported: usize) {
+    // Linux reports double the size to account for its bookkeeping overhead
+    let granted = if cfg!(target_os = "linux") {
+        reported / 2
+    } else {
+        reported
+    };
+    if granted < requested {
+        warn!(
+            "UDP {} buffer size clamped by the OS: requested {} bytes, got {}",
+            kind, requested, granted
+        );
+    }
+}
+
+pub async fn lookup_remote_host(
+    resolver: &dyn Resolver,
+    remote: &SocketAddress,
+) -> Result<SocketAddr, io::Error> {
     use SocketHost::*;
     let mut remote_address = match &remote.host {
         Domain(domain) => {
-            let resolver = TokioAsyncResolver::tokio_from_system_conf().map_err(|e| {
-                io::Error::new(
-                    ErrorKind::NotFound,
-                    format!("Cannot load DNS resolver from system configuration: {}", e),
-                )
-            })?;
-            let response = resolver.lookup_ip(domain).await?;
-            let address = response.iter().next().ok_or_else(|| {
+            let addresses = resolver.lookup_ip(domain).await?;
+            let address = addresses.into_iter().next().ok_or_else(|| {
                 io::Error::new(ErrorKind::NotFound, OptionsError::InvalidRemoteAddress)
             })?;
             match address {
@@ -70,18 +179,65 @@ pub async fn lookup_remote_host(remote: &SocketAddress) -> Result<SocketAddr, io
     Ok(remote_address)
 }
 
+// the socket packets go out on, a UDP socket unless the application brought its own network
+#[derive(Clone)]
+pub enum Datagrams {
+    Udp(Arc<UdpSocket>),
+    Custom(Arc<dyn DatagramSocket>),
+}
+
+impl Datagrams {
+    pub async fn bind(
+        binder: Option<&dyn Binder>,
+        options: &SocketOptions,
+    ) -> Result<Self, io::Error> {
+        match binder {
+            Some(binder) => Ok(Datagrams::Custom(binder.bind(options).await?)),
+            None => Ok(Datagrams::Udp(Arc::new(bind_socket(options).await?))),
+        }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+        match self {
+            Datagrams::Udp(socket) => socket.local_addr(),
+            Datagrams::Custom(socket) => socket.local_addr(),
+        }
+    }
+}
+
+impl From<UdpSocket> for Datagrams {
+    fn from(socket: UdpSocket) -> Self {
+        Datagrams::Udp(Arc::new(socket))
+    }
+}
+
+// how many packets in a row a task takes from a socket before it lets the other tasks of the
+// runtime, e.g. the other connections of a listener, have their turn. A flood that's always ready
+// would otherwise keep the thread to itself and the timers of the others late
+pub const PACKET_BUDGET: usize = 32;
+
 pub struct PacketSocket {
-    socket: Arc<UdpSocket>,
+    socket: Datagrams,
     stream: Option<mpsc::Receiver<ReceivePacketResult>>,
     buffer: BytesMut,
+    // custom sockets read into initialized memory, which is zeroed once rather than per datagram
+    datagram: Vec<u8>,
+    ecn: bool,
+    congestion_experienced: u32,
 }
 
 impl PacketSocket {
-    pub fn from_socket(socket: Arc<UdpSocket>, buffer_capacity: usize) -> Self {
+    pub fn from_socket(socket: Datagrams, buffer_capacity: usize) -> Self {
         Self {
+            ecn: match &socket {
+                Datagrams::Udp(socket) => ecn::is_enabled(socket),
+                Datagrams::Custom(_) => false,
+            },
             socket,
             stream: None,
             buffer: BytesMut::with_capacity(buffer_capacity),
+            datagram: Vec::new(),
+            congestion_experienced: 0,
         }
     }
 
@@ -96,14 +252,56 @@ impl PacketSocket {
                 socket: self.socket.clone(),
                 stream: Some(packet_receiver),
                 buffer: BytesMut::with_capacity(self.buffer.capacity()),
+                datagram: Vec::new(),
+                // the packets are received and parsed by the owner of the socket, so connections
+                // on a channel don't see the ECN marks
+                ecn: false,
+                congestion_experienced: 0,
             },
         )
     }
 
+    /// The same socket, with its UDP socket registered with the reactor of the runtime this is
+    /// called on, unless it's shared with other connections
+    pub fn reregister(self) -> io::Result<Self> {
+        let socket = match self.socket {
+            Datagrams::Udp(socket) => match Arc::try_unwrap(socket) {
+                Ok(socket) => Datagrams::Udp(Arc::new(UdpSocket::from_std(socket.into_std()?)?)),
+                Err(socket) => Datagrams::Udp(socket),
+            },
+            socket => socket,
+        };
+        Ok(Self { socket, ..self })
+    }
+
+    /// A duplicate of the underlying UDP socket, e.g. to pass to another process
+    pub fn try_clone_std(&self) -> Result<std::net::UdpSocket, io::Error> {
+        match &self.socket {
+            Datagrams::Udp(socket) => socket2::SockRef::from(&**socket)
+                .try_clone()
+                .map(Into::into),
+            Datagrams::Custom(_) => Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "only connections on a UDP socket can be detached",
+            )),
+        }
+    }
+
+    /// The number of data packets received with an ECN Congestion Experienced mark since the
+    /// last call
+    pub fn take_congestion_experienced(&mut self) -> u32 {
+        std::mem::take(&mut self.congestion_experienced)
+    }
+
     pub async fn send(&mut self, packet: (Packet, SocketAddr)) -> Result<usize, io::Error> {
         self.buffer.clear();
         packet.0.serialize(&mut self.buffer);
-        self.socket.send_to(&self.buffer, packet.1).await
+        match &self.socket {
+            Datagrams::Udp(socket) => socket.send_to(&self.buffer, packet.1).await,
+            Datagrams::Custom(socket) => {
+                poll_fn(|cx| socket.poll_send_to(cx, &self.buffer, packet.1)).await
+            }
+        }
     }
 
     pub async fn receive(&mut self) -> ReceivePacketResult {
@@ -121,21 +319,50 @@ impl PacketSocket {
 
     async fn socket_receive(&mut self) -> ReceivePacketResult {
         loop {
-            self.socket.readable().await?;
             self.buffer.clear();
-            return match self.socket.try_recv_buf_from(&mut self.buffer) {
-                Ok((size, from)) => self.parse(size, from),
+            let received = match &self.socket {
+                Datagrams::Udp(socket) => {
+                    socket.readable().await?;
+                    if self.ecn {
+                        ecn::try_recv_buf_from(socket, &mut self.buffer)
+                    } else {
+                        socket
+                            .try_recv_buf_from(&mut self.buffer)
+                            .map(|(size, from)| (size, from, false))
+                    }
+                }
+                Datagrams::Custom(socket) => {
+                    if self.datagram.is_empty() {
+                        self.datagram.resize(self.buffer.capacity(), 0);
+                    }
+                    let mut buf = ReadBuf::new(&mut self.datagram);
+                    match poll_fn(|cx| socket.poll_recv_from(cx, &mut buf)).await {
+                        Ok(from) => {
+                            let size = buf.filled().len();
+                            return Self::parse(&self.socket, &self.datagram[..size], from);
+                        }
+                        Err(e) => Err(e),
+                    }
+                }
+            };
+            return match received {
+                Ok((size, from, congestion_experienced)) => {
+                    let result = Self::parse(&self.socket, &self.buffer[..size], from);
+                    if congestion_experienced && matches!(result, Ok((Packet::Data(_), _))) {
+                        self.congestion_experienced += 1;
+                    }
+                    result
+                }
                 Err(e) if e.kind() == ErrorKind::WouldBlock => continue,
                 Err(e) => Err(e.into()),
             };
         }
     }
 
-    fn parse(&mut self, size: usize, from: SocketAddr) -> ReceivePacketResult {
-        let packet = Packet::parse(
-            &mut Cursor::new(&self.buffer[0..size]),
-            self.socket.local_addr()?.is_ipv6(),
-        )?;
+    fn parse(socket: &Datagrams, datagram: &[u8], from: SocketAddr) -> ReceivePacketResult {
+        // a custom socket that doesn't tell its address is taken to be on IPv4
+        let ipv6 = socket.local_addr().is_ok_and(|local| local.is_ipv6());
+        let packet = Packet::parse(&mut Cursor::new(datagram), ipv6)?;
         Ok((packet, from))
     }
 }
@@ -162,10 +389,80 @@ mod tests {
             host: SocketHost::Domain("localhost".to_string()),
             port: 3000,
         };
-        let remote_host = lookup_remote_host(&socket_address).await.unwrap();
+        let remote_host = lookup_remote_host(&SystemResolver, &socket_address)
+            .await
+            .unwrap();
         assert_eq!(
             remote_host,
             SocketAddr::new(V4(Ipv4Addr::new(127, 0, 0, 1)), 3000)
         );
     }
+
+    #[tokio::test]
+    async fn udp_buffer_sizes() {
+        // sizes well below any OS limit, so they can't be clamped, Linux reports double of them
+        let applied =
+            |requested: usize, reported: usize| (requested..=2 * requested).contains(&reported);
+        let mut options = SocketOptions::default();
+        options.connect.udp_send_buffer_size = ByteCount(48 * 1024);
+        options.connect.udp_recv_buffer_size = ByteCount(80 * 1024);
+        let socket = bind_socket(&options).await.unwrap();
+
+        let socket = socket2::SockRef::from(&socket);
+        assert!(applied(48 * 1024, socket.send_buffer_size().unwrap()));
+        assert!(applied(80 * 1024, socket.recv_buffer_size().unwrap()));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn congestion_experienced() {
+        use bytes::Bytes;
+        use srt_protocol::packet::*;
+
+        let mut options = SocketOptions::default();
+        options.connect.local = "127.0.0.1:0".parse().unwrap();
+        options.connect.ecn = true;
+        let receiver = bind_socket(&options).await.unwrap();
+        let local = receiver.local_addr().unwrap();
+        let mut receiver = PacketSocket::from_socket(receiver.into(), 1500);
+
+        // what a congested L4S queue does to ECT(0) datagrams
+        let sender = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket2::SockRef::from(&sender).set_tos(0b11).unwrap();
+        let data = Packet::Data(DataPacket {
+            seq_number: SeqNumber(0),
+            message_loc: PacketLocation::ONLY,
+            in_order_delivery: false,
+            encryption: DataEncryption::None,
+            retransmitted: false,
+            message_number: MsgNumber(0),
+            timestamp: TimeStamp::MIN,
+            dest_sockid: SocketId(1),
+            payload: Bytes::from_static(b"marked"),
+        });
+        let mut buffer = Vec::new();
+        data.serialize(&mut buffer);
+        sender.send_to(&buffer, local).unwrap();
+
+        let (received, _) = receiver.receive().await.unwrap();
+        assert_eq!(received, data);
+        assert_eq!(receiver.take_congestion_experienced(), 1);
+        assert_eq!(receiver.take_congestion_experienced(), 0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn precise_timer() {
+        use std::time::{Duration, Instant};
+
+        let timer = PreciseTimer::new().unwrap();
+        for _ in 0..10 {
+            let start = Instant::now();
+            timer.sleep(Duration::from_micros(200)).await.unwrap();
+            let elapsed = start.elapsed();
+            assert!(elapsed >= Duration::from_micros(200), "{elapsed:?}");
+        }
+        // a zero sleep doesn't arm the timer, which would never fire
+        timer.sleep(Duration::ZERO).await.unwrap();
+    }
 }
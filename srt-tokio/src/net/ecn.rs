@@ -0,0 +1,144 @@
+// ECN on Linux. Outgoing datagrams are marked ECN capable with ECT(0), and reading the ECN bits of
+// received datagrams takes recvmsg, they only come with the IP_TOS/IPV6_TCLASS control messages.
+#![allow(unsafe_code)]
+
+use std::{
+    io, mem,
+    net::SocketAddr,
+    os::fd::{AsRawFd, RawFd},
+    ptr,
+};
+
+use bytes::BytesMut;
+use socket2::SockAddr;
+use tokio::{io::Interest, net::UdpSocket};
+
+const ECN_MASK: u8 = 0b11;
+const ECT_0: u8 = 0b10;
+const CE: u8 = 0b11;
+
+/// Marks outgoing datagrams with ECT(0) and asks for the ECN bits of received ones
+pub fn enable(socket: &impl AsRawFd, ipv6: bool) -> io::Result<()> {
+    let fd = socket.as_raw_fd();
+    if ipv6 {
+        set_option(fd, libc::IPPROTO_IPV6, libc::IPV6_TCLASS, ECT_0.into())?;
+        set_option(fd, libc::IPPROTO_IPV6, libc::IPV6_RECVTCLASS, 1)?;
+        // a dual stack socket gets IPv4 datagrams too, an IPv6 only one doesn't need these
+        let _ = set_option(fd, libc::IPPROTO_IP, libc::IP_TOS, ECT_0.into());
+        let _ = set_option(fd, libc::IPPROTO_IP, libc::IP_RECVTOS, 1);
+        Ok(())
+    } else {
+        set_option(fd, libc::IPPROTO_IP, libc::IP_TOS, ECT_0.into())?;
+        set_option(fd, libc::IPPROTO_IP, libc::IP_RECVTOS, 1)
+    }
+}
+
+/// Whether the socket reports the ECN bits of received datagrams, e.g. after [`enable`] or when
+/// a socket handed over by another process had it enabled
+pub fn is_enabled(socket: &UdpSocket) -> bool {
+    let fd = socket.as_raw_fd();
+    match socket.local_addr() {
+        Ok(SocketAddr::V4(_)) => get_option(fd, libc::IPPROTO_IP, libc::IP_RECVTOS),
+        Ok(SocketAddr::V6(_)) => get_option(fd, libc::IPPROTO_IPV6, libc::IPV6_RECVTCLASS),
+        Err(_) => return false,
+    }
+    .is_ok_and(|value| value != 0)
+}
+
+/// [`UdpSocket::try_recv_buf_from`], along with whether the datagram was marked Congestion
+/// Experienced
+pub fn try_recv_buf_from(
+    socket: &UdpSocket,
+    buf: &mut BytesMut,
+) -> io::Result<(usize, SocketAddr, bool)> {
+    socket.try_io(Interest::READABLE, || recv_from(socket.as_raw_fd(), buf))
+}
+
+fn recv_from(fd: RawFd, buf: &mut BytesMut) -> io::Result<(usize, SocketAddr, bool)> {
+    let spare = buf.spare_capacity_mut();
+    let mut iov = libc::iovec {
+        iov_base: spare.as_mut_ptr().cast(),
+        iov_len: spare.len(),
+    };
+    // SAFETY: all zeros is a valid sockaddr_storage and msghdr
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    // room for one IP_TOS or IPV6_TCLASS control message, aligned for cmsghdr
+    let mut control = [0u64; 8];
+    msg.msg_name = ptr::addr_of_mut!(storage).cast();
+    msg.msg_namelen = mem::size_of_val(&storage) as libc::socklen_t;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = mem::size_of_val(&control) as _;
+
+    // SAFETY: msg points to buffers that live until the call returns
+    let received = unsafe { libc::recvmsg(fd, &mut msg, 0) };
+    if received < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let size = received as usize;
+    // SAFETY: recvmsg initialized `size` bytes of the spare capacity, it truncates datagrams
+    // that don't fit
+    unsafe { buf.set_len(buf.len() + size.min(iov.iov_len)) };
+
+    // SAFETY: recvmsg wrote msg_namelen bytes of the address
+    let from = unsafe { SockAddr::new(storage, msg.msg_namelen) }
+        .as_socket()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not an IP address"))?;
+
+    Ok((size, from, ecn_bits(&msg) == Some(CE)))
+}
+
+fn ecn_bits(msg: &libc::msghdr) -> Option<u8> {
+    // SAFETY: msg was filled in by recvmsg, the macros stay within msg_controllen
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(msg);
+        while let Some(header) = cmsg.as_ref() {
+            let data = libc::CMSG_DATA(cmsg);
+            match (header.cmsg_level, header.cmsg_type) {
+                (libc::IPPROTO_IP, libc::IP_TOS) => return Some(*data & ECN_MASK),
+                (libc::IPPROTO_IPV6, libc::IPV6_TCLASS) => {
+                    let tclass = ptr::read_unaligned(data.cast::<libc::c_int>());
+                    return Some(tclass as u8 & ECN_MASK);
+                }
+                _ => cmsg = libc::CMSG_NXTHDR(msg, cmsg),
+            }
+        }
+    }
+    None
+}
+
+fn set_option(
+    fd: RawFd,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    // SAFETY: the option value is a c_int that outlives the call
+    let result = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            ptr::addr_of!(value).cast(),
+            mem::size_of_val(&value) as libc::socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn get_option(fd: RawFd, level: libc::c_int, name: libc::c_int) -> io::Result<libc::c_int> {
+    let mut value: libc::c_int = 0;
+    let mut len = mem::size_of_val(&value) as libc::socklen_t;
+    // SAFETY: the option value is a c_int that outlives the call
+    let result =
+        unsafe { libc::getsockopt(fd, level, name, ptr::addr_of_mut!(value).cast(), &mut len) };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(value)
+}
@@ -0,0 +1,35 @@
+// Scheduling of the thread a connection runs on by itself, see DriverThread
+#![allow(unsafe_code)]
+
+use std::{io, mem};
+
+/// Run the calling thread under SCHED_FIFO at `priority`, from 1 to 99, which usually takes
+/// CAP_SYS_NICE or an RLIMIT_RTPRIO that allows it
+pub fn set_realtime_priority(priority: u8) -> io::Result<()> {
+    let param = libc::sched_param {
+        sched_priority: priority.into(),
+    };
+    if unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Keep the calling thread on the CPU `core`
+pub fn pin_to_core(core: usize) -> io::Result<()> {
+    if core >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("there is no CPU core {core}"),
+        ));
+    }
+    let result = unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
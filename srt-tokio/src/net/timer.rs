@@ -0,0 +1,86 @@
+// High resolution timers on Linux. tokio's timer wheel has a 1ms granularity and rounds up, so a
+// sender pacing packets tens of microseconds apart would release them in bursts every 1-2ms. A
+// timerfd wakes the task through the reactor with the precision of the kernel's hrtimers, without
+// spinning.
+#![allow(unsafe_code)]
+
+use std::{
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    ptr,
+    time::Duration,
+};
+
+use tokio::io::unix::AsyncFd;
+
+/// A timerfd registered with the tokio reactor, one sleep at a time
+pub struct PreciseTimer(AsyncFd<OwnedFd>);
+
+impl PreciseTimer {
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(PreciseTimer(AsyncFd::new(unsafe {
+            OwnedFd::from_raw_fd(fd)
+        })?))
+    }
+
+    pub async fn sleep(&self, duration: Duration) -> io::Result<()> {
+        // an all zero value disarms the timer rather than firing it right away
+        if duration.is_zero() {
+            return Ok(());
+        }
+        // re-arming also resets the expirations of an earlier sleep that was cancelled
+        self.arm(duration)?;
+        loop {
+            let mut guard = self.0.readable().await?;
+            match guard.try_io(|fd| read_expirations(fd.as_raw_fd())) {
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn arm(&self, duration: Duration) -> io::Result<()> {
+        let value = libc::itimerspec {
+            it_interval: libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            it_value: libc::timespec {
+                tv_sec: duration.as_secs() as libc::time_t,
+                tv_nsec: duration.subsec_nanos().into(),
+            },
+        };
+        let result =
+            unsafe { libc::timerfd_settime(self.0.as_raw_fd(), 0, &value, ptr::null_mut()) };
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn read_expirations(fd: i32) -> io::Result<()> {
+    let mut expirations = 0u64;
+    let result = unsafe {
+        libc::read(
+            fd,
+            (&mut expirations as *mut u64).cast(),
+            std::mem::size_of_val(&expirations),
+        )
+    };
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
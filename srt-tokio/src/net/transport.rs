@@ -0,0 +1,131 @@
+use std::{
+    fmt, io,
+    io::ErrorKind,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::future::BoxFuture;
+use srt_protocol::protocol::pending_connection::HandshakeHook;
+use tokio::{io::ReadBuf, net::UdpSocket};
+use trust_dns_resolver::TokioAsyncResolver;
+
+use crate::{options::*, AsyncKeyProvider, DriverThread};
+
+/// How the host names of remote addresses are turned into IP addresses, e.g. through service
+/// discovery or a split-horizon DNS instead of the system's resolver.
+pub trait Resolver: Send + Sync + 'static {
+    /// The addresses of `host`, the first of which is connected to
+    fn lookup_ip<'a>(&'a self, host: &'a str) -> BoxFuture<'a, io::Result<Vec<IpAddr>>>;
+}
+
+/// The DNS resolver of the system's configuration, what sockets use unless told otherwise
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn lookup_ip<'a>(&'a self, host: &'a str) -> BoxFuture<'a, io::Result<Vec<IpAddr>>> {
+        Box::pin(async move {
+            let resolver = TokioAsyncResolver::tokio_from_system_conf().map_err(|e| {
+                io::Error::new(
+                    ErrorKind::NotFound,
+                    format!("Cannot load DNS resolver from system configuration: {}", e),
+                )
+            })?;
+            Ok(resolver.lookup_ip(host).await?.iter().collect())
+        })
+    }
+}
+
+/// A socket the packets of a connection or a listener are sent and received on as datagrams,
+/// e.g. one of a userspace network stack.
+///
+/// Datagrams are received into buffers large enough for the biggest SRT packet, and truncated
+/// ones are dropped as malformed.
+pub trait DatagramSocket: Send + Sync + 'static {
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        target: SocketAddr,
+    ) -> Poll<io::Result<usize>>;
+
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<SocketAddr>>;
+}
+
+impl DatagramSocket for UdpSocket {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        UdpSocket::local_addr(self)
+    }
+
+    fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        target: SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        UdpSocket::poll_send_to(self, cx, buf, target)
+    }
+
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<SocketAddr>> {
+        UdpSocket::poll_recv_from(self, cx, buf)
+    }
+}
+
+/// How sockets and listeners open the datagram socket they run on, instead of binding a UDP
+/// socket with [`bind_socket`](crate::bind_socket).
+///
+/// The local address, the buffer sizes and the other [`Connect`] options are the binder's to
+/// honor. Only connections on a UDP socket see ECN marks or can be
+/// [detached](crate::SrtSocket::detach).
+pub trait Binder: Send + Sync + 'static {
+    fn bind<'a>(
+        &'a self,
+        options: &'a SocketOptions,
+    ) -> BoxFuture<'a, io::Result<Arc<dyn DatagramSocket>>>;
+}
+
+// what builders hold on to and the handshakes look up their peers with, and where the connection
+// is driven from once it's up
+#[derive(Clone)]
+pub(crate) struct Network {
+    pub resolver: Arc<dyn Resolver>,
+    pub binder: Option<Arc<dyn Binder>>,
+    pub handshake_hook: Option<HandshakeHook>,
+    pub key_provider: Option<Arc<dyn AsyncKeyProvider>>,
+    pub driver: Option<DriverThread>,
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self {
+            resolver: Arc::new(SystemResolver),
+            binder: None,
+            handshake_hook: None,
+            key_provider: None,
+            driver: None,
+        }
+    }
+}
+
+impl fmt::Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Network")
+            .field("binder", &self.binder.is_some())
+            .field("handshake_hook", &self.handshake_hook.is_some())
+            .field("key_provider", &self.key_provider.is_some())
+            .field("driver", &self.driver)
+            .finish_non_exhaustive()
+    }
+}
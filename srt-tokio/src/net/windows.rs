@@ -0,0 +1,87 @@
+// Windows specifics. tokio already does overlapped (IOCP) I/O on UDP sockets, what's left is the
+// timer resolution and the way Windows reports ICMP errors on UDP sockets.
+#![allow(unsafe_code)]
+
+use std::{ffi::c_void, io, os::windows::io::AsRawSocket, ptr};
+
+use socket2::Socket;
+
+#[link(name = "winmm")]
+extern "system" {
+    fn timeBeginPeriod(period: u32) -> u32;
+    fn timeEndPeriod(period: u32) -> u32;
+}
+
+#[link(name = "ws2_32")]
+extern "system" {
+    fn WSAIoctl(
+        socket: usize,
+        control_code: u32,
+        in_buffer: *const c_void,
+        in_buffer_len: u32,
+        out_buffer: *mut c_void,
+        out_buffer_len: u32,
+        bytes_returned: *mut u32,
+        overlapped: *mut c_void,
+        completion_routine: *mut c_void,
+    ) -> i32;
+}
+
+// _WSAIOW(IOC_VENDOR, 12)
+const SIO_UDP_CONNRESET: u32 = 0x9800_000C;
+
+const TIMER_PERIOD_MS: u32 = 1;
+
+/// Raises the system timer resolution to 1ms for as long as it's alive.
+///
+/// By default timers only fire every 15.6ms on Windows, which is too coarse for pacing packets
+/// and too late for TSBPD with a 120ms latency.
+pub struct TimerResolution(bool);
+
+impl TimerResolution {
+    pub fn new() -> Self {
+        // TIMERR_NOERROR is 0
+        TimerResolution(unsafe { timeBeginPeriod(TIMER_PERIOD_MS) } == 0)
+    }
+}
+
+impl Default for TimerResolution {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for TimerResolution {
+    fn drop(&mut self) {
+        if self.0 {
+            unsafe { timeEndPeriod(TIMER_PERIOD_MS) };
+        }
+    }
+}
+
+/// Stops ICMP port unreachable messages from failing the next receive with WSAECONNRESET.
+///
+/// A socket shared by several connections, like a listener's, would otherwise see errors for
+/// peers that have gone away.
+pub fn disable_udp_connreset(socket: &Socket) -> io::Result<()> {
+    let enable: u32 = 0;
+    let mut bytes_returned = 0;
+    let result = unsafe {
+        WSAIoctl(
+            socket.as_raw_socket() as usize,
+            SIO_UDP_CONNRESET,
+            &enable as *const u32 as *const c_void,
+            std::mem::size_of_val(&enable) as u32,
+            ptr::null_mut(),
+            0,
+            &mut bytes_returned,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
@@ -1,14 +1,46 @@
 use std::net::SocketAddr;
-use std::{convert::TryInto, io, net::IpAddr, time::Duration};
+use std::{
+    convert::TryInto,
+    io,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use log::LevelFilter;
+#[cfg(feature = "packet_telemetry")]
+use srt_protocol::connection::telemetry::{PacketEvent, PacketHook};
+use srt_protocol::{
+    connection::{ConnectionSnapshot, DuplexConnection},
+    packet::{SeqNumber, SocketId},
+    protocol::pending_connection::HandshakeEvent,
+    settings::{KeyProvider, SharedKeyProvider},
+};
 use tokio::net::UdpSocket;
 
-use crate::options::*;
+use crate::{
+    clock::{Clock, SharedClock},
+    net::{Binder, Network, Resolver},
+    options::*,
+};
 
-use super::SrtSocket;
+use super::{AsyncKeyProvider, DriverThread, SrtSocket};
 
 #[derive(Default)]
-pub struct SrtSocketBuilder(SocketOptions, Option<UdpSocket>);
+pub struct SrtSocketBuilder(
+    SocketOptions,
+    Option<UdpSocket>,
+    SharedClock,
+    LogOverride,
+    Network,
+    #[cfg(feature = "packet_telemetry")] Option<PacketHook>,
+);
+
+#[derive(Debug, Default)]
+struct LogOverride {
+    context: Option<String>,
+    level: Option<LevelFilter>,
+}
 
 /// Struct to build sockets.
 ///
@@ -62,14 +94,18 @@ impl SrtSocketBuilder {
         self
     }
 
-    /// Sets the port to bind to. In general, to be used for [`Listen`] and [`Rendezvous`], but generally not [`Call`].
+    /// Sets the port to bind to, before connecting. For [`Call`] it's the source port, e.g. for a
+    /// firewall that only lets the caller out from that one, it's picked by the OS otherwise.
+    /// For [`Rendezvous`] it defaults to the port of the peer, which sends to it.
+    ///
+    /// Connecting fails with [`io::ErrorKind::AddrInUse`] if the port is taken.
     pub fn local_port(mut self, port: u16) -> Self {
         let local = self.0.connect.local;
         self.0.connect.local = SocketAddr::new(local.ip(), port);
         self
     }
 
-    /// Sets the local address (ip:port) to bind to. In general, to be used for [`Listen`] and [`Rendezvous`], but generally not [`Call`].
+    /// Sets the local address (ip:port) to bind to, see [`local_port`](Self::local_port)
     pub fn local(mut self, address: impl TryInto<SocketAddress>) -> Self {
         let address = address
             .try_into()
@@ -104,6 +140,30 @@ impl SrtSocketBuilder {
 
         self
     }
+
+    /// Encrypt with the key encrypting key from `provider` instead of one derived from a
+    /// passphrase, see [`Encryption::key_provider`].
+    ///
+    /// # Panics:
+    /// * size is not 0, 16, 24, or 32.
+    pub fn key_provider(mut self, key_size: u16, provider: impl KeyProvider + 'static) -> Self {
+        self.0.encryption.key_size = key_size.try_into().unwrap();
+        self.0.encryption.key_provider = Some(SharedKeyProvider::new(provider));
+
+        self
+    }
+
+    /// Like [`key_provider`](Self::key_provider), for a provider that fetches the keys
+    /// asynchronously, see [`AsyncKeyProvider`].
+    ///
+    /// # Panics:
+    /// * size is not 0, 16, 24, or 32.
+    pub fn async_key_provider(mut self, key_size: u16, provider: impl AsyncKeyProvider) -> Self {
+        self.0.encryption.key_size = key_size.try_into().unwrap();
+        self.4.key_provider = Some(Arc::new(provider));
+
+        self
+    }
     /// the minimum latency to receive at
     pub fn receive_latency(mut self, latency: Duration) -> Self {
         self.0.receiver.latency = latency;
@@ -121,11 +181,105 @@ impl SrtSocketBuilder {
         self
     }
 
+    /// What happens to new messages once the send buffer is full, see
+    /// [`Sender::buffer_full_policy`]
+    pub fn buffer_full_policy(mut self, policy: SendBufferPolicy) -> Self {
+        self.0.sender.buffer_full_policy = policy;
+        self
+    }
+
+    // SRTO_UDP_SNDBUF
+    /// Set the kernel send buffer size of the UDP socket. The OS may grant less, which is logged.
+    pub fn udp_send_buffer_size(mut self, size: ByteCount) -> Self {
+        self.0.connect.udp_send_buffer_size = size;
+        self
+    }
+
+    // SRTO_UDP_RCVBUF
+    /// Set the kernel receive buffer size of the UDP socket. The OS may grant less, which is logged.
+    pub fn udp_recv_buffer_size(mut self, size: ByteCount) -> Self {
+        self.0.connect.udp_recv_buffer_size = size;
+        self
+    }
+
+    /// Mark outgoing datagrams as ECN capable and react to the Congestion Experienced marks on
+    /// received ones, see [`Connect::ecn`]
+    pub fn ecn(mut self, ecn: bool) -> Self {
+        self.0.connect.ecn = ecn;
+        self
+    }
+
+    /// Use this socket id instead of a random one, see [`Session::socket_id`]
+    pub fn socket_id(mut self, socket_id: SocketId) -> Self {
+        self.0.session.socket_id = Some(socket_id);
+        self
+    }
+
+    /// Start sending data at this sequence number instead of a random one, when this side
+    /// initiates the handshake, see [`Session::initial_sequence_number`]
+    pub fn initial_sequence_number(mut self, seq_number: SeqNumber) -> Self {
+        self.0.session.initial_sequence_number = Some(seq_number);
+        self
+    }
+
+    /// Count the timestamps of the connection from `start_time`, which is on the clock of the
+    /// socket, see [`Session::start_time`]
+    pub fn start_time(mut self, start_time: Instant) -> Self {
+        self.0.session.start_time = Some(start_time);
+        self
+    }
+
+    /// Whether the connection follows its peer to another address, see
+    /// [`Session::peer_address_policy`]
+    pub fn peer_address_policy(mut self, policy: PeerAddressPolicy) -> Self {
+        self.0.session.peer_address_policy = policy;
+        self
+    }
+
     pub fn socket(mut self, socket: UdpSocket) -> Self {
         self.1 = Some(socket);
         self
     }
 
+    /// Look up the host names of remote addresses with `resolver` instead of the system's DNS
+    /// resolver.
+    pub fn resolver(mut self, resolver: impl Resolver) -> Self {
+        self.4.resolver = Arc::new(resolver);
+        self
+    }
+
+    /// Open the socket the connection runs on with `binder` instead of binding a UDP socket,
+    /// e.g. to run on a userspace network stack. A socket set with [`socket`](Self::socket) takes
+    /// precedence.
+    pub fn binder(mut self, binder: impl Binder) -> Self {
+        self.4.binder = Some(Arc::new(binder));
+        self
+    }
+
+    /// Calls `hook` with every handshake sent or received while connecting, with its phase,
+    /// attempt and cookie, to see which phase a connection that times out is stuck in.
+    pub fn handshake_hook(
+        mut self,
+        hook: impl Fn(&HandshakeEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.4.handshake_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Drive the connection on a thread of its own, see [`DriverThread`].
+    pub fn driver_thread(mut self, driver: DriverThread) -> Self {
+        self.4.driver = Some(driver);
+        self
+    }
+
+    /// Timestamps packets and schedules their release with `clock` instead of the system clock.
+    ///
+    /// The instants sent to and received from the socket are on this clock too, see [`Clock`].
+    pub fn clock(mut self, clock: impl Clock) -> Self {
+        self.2 = SharedClock::new(clock);
+        self
+    }
+
     pub fn with<O>(mut self, options: O) -> Self
     where
         SocketOptions: OptionsOf<O>,
@@ -140,6 +294,26 @@ impl SrtSocketBuilder {
         self
     }
 
+    /// Log the records of the connection to the `srt_protocol::connection::<context>` target, see
+    /// [`SrtSocket::set_log_context`].
+    pub fn log_context(mut self, context: impl Into<String>) -> Self {
+        self.3.context = Some(context.into());
+        self
+    }
+
+    /// Override the maximum level the connection logs at, see [`SrtSocket::set_log_level`].
+    pub fn log_level(mut self, level: LevelFilter) -> Self {
+        self.3.level = Some(level);
+        self
+    }
+
+    /// Calls `hook` with every packet the socket sends or receives once it is connected.
+    #[cfg(feature = "packet_telemetry")]
+    pub fn packet_hook(mut self, hook: impl FnMut(&PacketEvent) + Send + 'static) -> Self {
+        self.5 = Some(Box::new(hook));
+        self
+    }
+
     pub async fn listen_on(
         self,
         local: impl TryInto<SocketAddress>,
@@ -147,35 +321,81 @@ impl SrtSocketBuilder {
         self.local(local).listen().await
     }
 
-    pub async fn listen(self) -> Result<SrtSocket, io::Error> {
+    pub async fn listen(mut self) -> Result<SrtSocket, io::Error> {
+        let configure = self.take_configure();
         Self::bind(
             ListenerOptions { socket: self.0 }.try_validate()?.into(),
             self.1,
+            &self.4,
+            self.2,
+            configure,
         )
         .await
     }
 
     pub async fn call(
-        self,
+        mut self,
         remote: impl TryInto<SocketAddress>,
         stream_id: Option<&str>,
     ) -> Result<SrtSocket, io::Error> {
+        let configure = self.take_configure();
         let options = CallerOptions::with(remote, stream_id, self.0)?;
-        Self::bind(options.into(), self.1).await
+        Self::bind(options.into(), self.1, &self.4, self.2, configure).await
     }
 
     pub async fn rendezvous(
-        self,
+        mut self,
         remote: impl TryInto<SocketAddress>,
     ) -> Result<SrtSocket, io::Error> {
+        let configure = self.take_configure();
         let options = RendezvousOptions::with(remote, self.0)?;
-        Self::bind(options.into(), self.1).await
+        Self::bind(options.into(), self.1, &self.4, self.2, configure).await
+    }
+
+    /// Carry on with a connection another process [detached](SrtSocket::detach), on the UDP
+    /// socket it handed over along with the snapshot, which has to be set with
+    /// [`socket`](Self::socket). The socket options don't apply, the connection was negotiated
+    /// already.
+    pub fn restore(mut self, snapshot: ConnectionSnapshot) -> Result<SrtSocket, io::Error> {
+        let configure = self.take_configure();
+        let socket = self.1.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "restoring a connection requires its socket",
+            )
+        })?;
+        SrtSocket::restore_with_socket(snapshot, socket, &self.4, self.2, configure)
     }
 
-    async fn bind(options: BindOptions, socket: Option<UdpSocket>) -> Result<SrtSocket, io::Error> {
+    // settings for the connection that aren't part of the socket options
+    fn take_configure(&mut self) -> impl FnOnce(&mut DuplexConnection) + Send {
+        let log = std::mem::take(&mut self.3);
+        #[cfg(feature = "packet_telemetry")]
+        let packet_hook = self.5.take();
+        move |connection: &mut DuplexConnection| {
+            if let Some(context) = log.context {
+                connection.set_log_context(&context);
+            }
+            connection.set_log_level(log.level);
+            #[cfg(feature = "packet_telemetry")]
+            if let Some(hook) = packet_hook {
+                connection.set_packet_hook(hook);
+            }
+        }
+    }
+
+    async fn bind(
+        options: BindOptions,
+        socket: Option<UdpSocket>,
+        network: &Network,
+        clock: SharedClock,
+        configure: impl FnOnce(&mut DuplexConnection),
+    ) -> Result<SrtSocket, io::Error> {
         match socket {
-            None => SrtSocket::bind(options).await,
-            Some(socket) => SrtSocket::bind_with_socket(options, socket).await,
+            None => SrtSocket::bind_configured(options, network, clock, configure).await,
+            Some(socket) => {
+                SrtSocket::bind_with_socket(options, socket.into(), network, clock, configure).await
+            }
         }
     }
 }
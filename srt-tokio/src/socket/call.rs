@@ -10,25 +10,38 @@ use tokio::time::interval;
 use srt_protocol::{
     connection::Connection,
     options::*,
-    protocol::pending_connection::{connect::Connect, ConnectionResult},
+    protocol::pending_connection::{connect::Connect, ConnectionResult, HandshakeTelemetry},
+    settings::*,
 };
 
-use crate::net::{lookup_remote_host, PacketSocket};
+use crate::{
+    clock::SharedClock,
+    net::{lookup_remote_host, PacketSocket, Resolver},
+};
+
+use super::key_provider::KeyCache;
 
 pub async fn bind_with(
     mut socket: PacketSocket,
     options: Valid<CallerOptions>,
+    resolver: &dyn Resolver,
+    keys: Option<&KeyCache>,
+    mut telemetry: HandshakeTelemetry,
+    clock: &SharedClock,
 ) -> Result<(PacketSocket, Connection), io::Error> {
     let stream_id = options.stream_id.as_ref().map(|s| s.to_string());
-    let remote = lookup_remote_host(&options.remote).await?;
+    let remote = lookup_remote_host(resolver, &options.remote).await?;
+
+    let init_settings: ConnInitSettings = options.socket.clone().into();
+    let starting_seqno = init_settings.init_seq_num.unwrap_or_else(rand::random);
 
     let mut tick_interval = interval(Duration::from_millis(100));
     let mut connect = Connect::new(
         remote,
         options.socket.connect.local.ip(),
-        options.socket.clone().into(),
+        init_settings,
         stream_id.clone(),
-        rand::random(),
+        starting_seqno,
     );
 
     let start_time = Instant::now();
@@ -39,13 +52,19 @@ pub async fn bind_with(
         }
 
         let result = select! {
-            now = tick_interval.tick().fuse() => {
+            _ = tick_interval.tick().fuse() => {
                 trace!("caller interval elapsed, passing tick");
-                connect.handle_tick(now.into())
+                connect.handle_tick(clock.now())
             }
             packet = socket.receive().fuse() => {
                 trace!("caller got packet {packet:?}");
-                connect.handle_packet(packet, Instant::now())
+                if let Ok((packet, from)) = &packet {
+                    telemetry.received(clock.now(), packet, *from);
+                }
+                if let Some(keys) = keys {
+                    keys.prefetch(&packet).await;
+                }
+                connect.handle_packet(packet, clock.now())
             }
         };
 
@@ -53,6 +72,7 @@ pub async fn bind_with(
         use ConnectionResult::*;
         match result {
             SendPacket(packet) => {
+                telemetry.sent(clock.now(), &packet.0, packet.1);
                 let _ = socket.send(packet.clone()).await?;
             }
             NotHandled(e) => {
@@ -60,12 +80,14 @@ pub async fn bind_with(
             }
             Reject(rp, rr) => {
                 if let Some(packet) = rp {
+                    telemetry.sent(clock.now(), &packet.0, packet.1);
                     let _ = socket.send(packet).await?;
                 }
                 return Err(io::Error::new(io::ErrorKind::ConnectionRefused, rr));
             }
             Connected(p, connection) => {
                 if let Some(packet) = p {
+                    telemetry.sent(clock.now(), &packet.0, packet.1);
                     let _ = socket.send(packet).await?;
                 }
                 return Ok((socket, connection));
@@ -0,0 +1,77 @@
+use std::{
+    future::Future,
+    io,
+    panic::{self, AssertUnwindSafe},
+    thread,
+};
+
+use futures::channel::oneshot;
+use log::warn;
+use tokio::{runtime, task::JoinHandle};
+
+use crate::net::thread::{pin_to_core, set_realtime_priority};
+
+/// Runs the task that drives a connection, i.e. runs the protocol and sends and receives its
+/// packets, on an OS thread of its own rather than on the tokio runtime, set with
+/// [`SrtSocketBuilder::driver_thread`](crate::SrtSocketBuilder::driver_thread).
+///
+/// The thread runs its own single threaded runtime, with its own timers and its own registration
+/// of the UDP socket, so the packets go out on time however busy the application's runtime is.
+/// The socket talks to it over the same channels as to a task.
+///
+/// The priority and the core are only supported on Linux, and the priority usually takes
+/// `CAP_SYS_NICE`. If they can't be set, a warning is logged and the connection runs all the same.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DriverThread {
+    /// Run the thread with this real time priority, from 1 to 99, under `SCHED_FIFO`, ahead of
+    /// anything that isn't real time. `None` leaves it at the priority it was started with.
+    pub realtime_priority: Option<u8>,
+
+    /// Keep the thread on this CPU core, e.g. one isolated from the rest of the system.
+    pub core: Option<usize>,
+}
+
+impl DriverThread {
+    /// Runs `task` on a new thread, the handle finishes when it did, or panics if it did
+    pub(crate) fn spawn<F>(
+        &self,
+        name: String,
+        task: impl FnOnce() -> F + Send + 'static,
+    ) -> io::Result<JoinHandle<()>>
+    where
+        F: Future<Output = ()>,
+    {
+        let (done_sender, done) = oneshot::channel();
+        let this = self.clone();
+        thread::Builder::new().name(name).spawn(move || {
+            this.configure();
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                let runtime = runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .expect("building the runtime of the driver thread");
+                // the task is set up on the runtime, it may register with its reactor
+                runtime.block_on(async move { task().await })
+            }));
+            let _ = done_sender.send(result);
+        })?;
+        Ok(tokio::spawn(async move {
+            if let Ok(Err(panic)) = done.await {
+                panic::resume_unwind(panic);
+            }
+        }))
+    }
+
+    fn configure(&self) {
+        if let Some(priority) = self.realtime_priority {
+            if let Err(e) = set_realtime_priority(priority) {
+                warn!("Could not set real time priority {priority} on the driver thread: {e}");
+            }
+        }
+        if let Some(core) = self.core {
+            if let Err(e) = pin_to_core(core) {
+                warn!("Could not pin the driver thread to core {core}: {e}");
+            }
+        }
+    }
+}
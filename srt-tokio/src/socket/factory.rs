@@ -1,73 +1,317 @@
-use std::time::Instant;
+use std::{
+    cmp::min,
+    collections::HashMap,
+    fmt, io, net,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
 
 use bytes::Bytes;
-use futures::{channel::mpsc, prelude::*, select, stream::StreamExt};
-use log::{error, trace};
+use futures::{
+    channel::{mpsc, oneshot},
+    prelude::*,
+    select,
+    stream::{FusedStream, StreamExt},
+};
+use log::{error, trace, LevelFilter};
 use srt_protocol::{
-    connection::{Action, Connection, ConnectionSettings, DuplexConnection, Input},
-    packet::TimeSpan,
+    connection::{
+        extension::ExtensionHandler, gap::GapHandler, rebind::RebindHandler, stall::StallHandler,
+        tap::TapHandler, ConnectionSettings, ConnectionSnapshot, Delivery, DuplexConnection,
+        EchoSample, Input, SendMessage,
+    },
+    options::SendBufferPolicy,
+    packet::{SeqNumber, TimeSpan},
+    settings::SocketIdLease,
+};
+use tokio::{
+    task::{yield_now, JoinHandle},
+    time::sleep_until,
+};
+
+use crate::{
+    clock::SharedClock,
+    net::{PacketSocket, PreciseTimer, PACKET_BUDGET},
+    statistics::{CongestionHandler, CongestionThresholds, QualityScore},
+    watch, SocketStatistics, SrtSocket,
+};
+
+use super::{
+    driver::DriverThread,
+    impairment::{Impairer, Impairment},
 };
-use tokio::{task::JoinHandle, time::sleep_until};
 
-use crate::{net::PacketSocket, watch, SocketStatistics, SrtSocket};
+/// Requests from an [`SrtSocket`] to its driver task that aren't data
+pub enum Command {
+    SendControlExtension(u16, Bytes),
+    SetExtensionHandler(ExtensionHandler),
+    SetGapHandler(GapHandler),
+    SetTapHandler(TapHandler),
+    SetRebindHandler(RebindHandler),
+    SetStallHandler(Duration, StallHandler),
+    SetCongestionAlarm(CongestionThresholds, CongestionHandler),
+    SetImpairment(Impairment),
+    SetLogContext(String),
+    SetLogLevel(Option<LevelFilter>),
+    /// Stop driving the connection without telling the peer, handing over its state and the UDP
+    /// socket it runs on
+    Detach(oneshot::Sender<io::Result<Detached>>),
+    /// Send an echo request, the answer comes back once the peer replied
+    Ping(oneshot::Sender<EchoSample>),
+    /// How far the peer's clock is ahead of this one, as far as the receiver knows yet
+    PeerClockOffset(oneshot::Sender<Option<TimeSpan>>),
+    /// Close right away, the peer is sent a Shutdown, e.g. when the listener it was accepted on
+    /// is aborted
+    Abort,
+    /// Take no more data and close once what was queued is sent, as if the socket was closed,
+    /// e.g. when the listener it was accepted on shuts down
+    Shutdown,
+}
+
+pub type Detached = (ConnectionSnapshot, net::UdpSocket);
+
+// wakeups closer than this are timed by the kernel's high resolution timers where available
+const PRECISE_TIMER_THRESHOLD: Duration = Duration::from_millis(2);
+
+impl fmt::Debug for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::SendControlExtension(ty, payload) => f
+                .debug_tuple("SendControlExtension")
+                .field(ty)
+                .field(payload)
+                .finish(),
+            Command::SetExtensionHandler(_) => f.write_str("SetExtensionHandler"),
+            Command::SetGapHandler(_) => f.write_str("SetGapHandler"),
+            Command::SetTapHandler(_) => f.write_str("SetTapHandler"),
+            Command::SetRebindHandler(_) => f.write_str("SetRebindHandler"),
+            Command::SetStallHandler(threshold, _) => {
+                f.debug_tuple("SetStallHandler").field(threshold).finish()
+            }
+            Command::SetCongestionAlarm(thresholds, _) => f
+                .debug_tuple("SetCongestionAlarm")
+                .field(thresholds)
+                .finish(),
+            Command::SetImpairment(impairment) => {
+                f.debug_tuple("SetImpairment").field(impairment).finish()
+            }
+            Command::SetLogContext(context) => {
+                f.debug_tuple("SetLogContext").field(context).finish()
+            }
+            Command::SetLogLevel(level) => f.debug_tuple("SetLogLevel").field(level).finish(),
+            Command::Detach(_) => f.write_str("Detach"),
+            Command::Ping(_) => f.write_str("Ping"),
+            Command::PeerClockOffset(_) => f.write_str("PeerClockOffset"),
+            Command::Abort => f.write_str("Abort"),
+            Command::Shutdown => f.write_str("Shutdown"),
+        }
+    }
+}
+
+// data to send, and where to report its delivery if anyone asked
+pub type DataInput = (SendMessage, Option<oneshot::Sender<Delivery>>);
+
+/// The sending half of the data channel, a `Sink` of [`SendMessage`]s and plain `(Instant, Bytes)`, along with whether
+/// the connection's send buffer was full when its task last looked
+#[derive(Debug)]
+pub struct DataSender(mpsc::Sender<DataInput>, Arc<AtomicBool>);
+
+impl DataSender {
+    pub fn is_send_buffer_full(&self) -> bool {
+        self.1.load(Ordering::Relaxed)
+    }
+
+    pub fn try_send(&mut self, message: SendMessage) -> Result<(), SendMessage> {
+        self.0
+            .try_send((message, None))
+            .map_err(|e| e.into_inner().0)
+    }
+
+    pub async fn send_tracked(
+        &mut self,
+        message: SendMessage,
+    ) -> Result<oneshot::Receiver<Delivery>, mpsc::SendError> {
+        let (sender, receiver) = oneshot::channel();
+        self.0.send((message, Some(sender))).await?;
+        Ok(receiver)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.0.is_closed()
+    }
+}
 
+impl Sink<SendMessage> for DataSender {
+    type Error = mpsc::SendError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.0).poll_ready(cx)
+    }
+    fn start_send(mut self: Pin<&mut Self>, message: SendMessage) -> Result<(), Self::Error> {
+        Pin::new(&mut self.0).start_send((message, None))
+    }
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.0).poll_close(cx)
+    }
+}
+
+impl Sink<(Instant, Bytes)> for DataSender {
+    type Error = mpsc::SendError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Sink::<SendMessage>::poll_ready(self, cx)
+    }
+    fn start_send(
+        self: Pin<&mut Self>,
+        (srctime, data): (Instant, Bytes),
+    ) -> Result<(), Self::Error> {
+        self.start_send(SendMessage::new(srctime, data))
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Sink::<SendMessage>::poll_flush(self, cx)
+    }
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Sink::<SendMessage>::poll_close(self, cx)
+    }
+}
+
+// The driver task is the only owner of the connection state, the SrtSocket handle talks to it
+// over bounded channels, so neither side ever waits on a lock held by the other.
 struct SrtSocketState {
     socket: PacketSocket,
     connection: DuplexConnection,
     statistics_sender: watch::Sender<SocketStatistics>,
     output_data_sender: mpsc::Sender<(Instant, Bytes)>,
-    input_data_receiver: mpsc::Receiver<(Instant, Bytes)>,
+    input_data_receiver: mpsc::Receiver<DataInput>,
+    command_receiver: mpsc::Receiver<Command>,
+    abort_receiver: oneshot::Receiver<()>,
+    send_buffer_full: Arc<AtomicBool>,
+    clock: SharedClock,
+    socket_id: Option<SocketIdLease>,
 }
 
 impl SrtSocketState {
-    pub async fn run_loop(self) {
-        // Using run_input_loop breaks a couple of the stransmit_interop tests.
-        // Both stransmit_decrypt and stransmit_server run indefinitely. For now,
-        // run_handler_loop exclusively, until a fix is found or an API decision
-        // is reached.
-        if Instant::now().elapsed().as_nanos() != 0 {
-            self.run_handler_loop().await;
-        } else {
-            self.run_input_loop().await;
-        }
+    async fn run_with_timer_resolution(self) {
+        // held for as long as the connection runs, the OS counts the requests
+        #[cfg(windows)]
+        let _timer_resolution = crate::net::TimerResolution::new();
+        self.run().await
     }
 
-    async fn run_handler_loop(self) {
+    async fn run(self) {
         let local_sockid = self.connection.settings().local_sockid;
+        // another connection may have the socket id once this one is done
+        let _socket_id = self.socket_id;
         let mut socket = self.socket;
         let mut input_data = self.input_data_receiver.fuse();
+        let mut commands = self.command_receiver.fuse();
+        // canceled rather than sent when the socket is closed before it's dropped
+        let mut abort = self.abort_receiver.fuse();
         let mut output_data = self.output_data_sender;
         let mut connection = self.connection;
         let statistics_sender = self.statistics_sender;
+        let clock = self.clock;
+        // keyed by the first sequence number of the message
+        let mut deliveries = HashMap::<SeqNumber, oneshot::Sender<Delivery>>::new();
+        // keyed by the id of the echo request
+        let mut pings = HashMap::<u32, oneshot::Sender<EchoSample>>::new();
+        let mut impairer = Impairer::default();
+        let precise_timer = PreciseTimer::new().ok();
+        let send_buffer_policy = connection.settings().send_buffer_policy;
+        let send_buffer_full = self.send_buffer_full;
+        let mut packets_in_a_row = 0;
         while connection.is_open() {
-            if connection.should_update_statistics(Instant::now()) {
+            let now = clock.now();
+            if connection.should_update_statistics(now) {
+                connection.update_statistics(now);
                 let _ = statistics_sender.send(connection.statistics().clone());
             }
 
-            while let Some(packet) = connection.next_packet(Instant::now()) {
+            while let Some(packet) = connection.next_packet(clock.now()) {
+                impairer.push(clock.now(), packet);
+            }
+            while let Some(packet) = impairer.next_due(clock.now()) {
                 if let Err(e) = socket.send(packet).await {
-                    error!("Error while sending packet: {:?}", e); // TODO: real error handling
+                    // TODO: real error handling
+                    error!(
+                        target: connection.log_target(),
+                        "Error while sending packet: {:?}",
+                        e
+                    );
                 }
             }
 
-            while let Some(data) = connection.next_data(Instant::now()) {
+            while let Some(data) = connection.next_data(clock.now()) {
                 if output_data.is_closed() {
                     continue;
                 }
                 if let Err(e) = output_data.send(data).await {
-                    error!("Error while releasing packet {:?}", e);
+                    error!(
+                        target: connection.log_target(),
+                        "Error while releasing packet {:?}",
+                        e
+                    );
+                }
+            }
+
+            while let Some((packets, delivery)) = connection.next_delivery() {
+                if let Some(sender) = deliveries.remove(&packets.start) {
+                    let _ = sender.send(delivery);
+                }
+            }
+
+            while let Some(sample) = connection.next_echo_reply() {
+                if let Some(sender) = pings.remove(&sample.id) {
+                    let _ = sender.send(sample);
                 }
             }
 
-            let timeout = connection.check_timers(Instant::now());
+            // with half close the peer can finish sending while the connection stays open
+            if connection.is_receiver_closed() && !output_data.is_closed() {
+                output_data.close_channel();
+            }
+
+            // unless the oldest messages make room, new ones wait in the channel until there is
+            // some, and the socket's Sink is pending once that fills up. Like the other streams,
+            // the channel isn't polled again once it ended
+            let full = connection.is_send_buffer_full();
+            send_buffer_full.store(full, Ordering::Relaxed);
+            let take_input = (!full || send_buffer_policy == SendBufferPolicy::DropOldest)
+                && !input_data.is_terminated();
+
+            let timeout = connection.check_timers(clock.now());
+            let timeout = impairer
+                .next_release()
+                .map_or(timeout, |release| min(release, timeout));
             let timeout_fut = async {
-                let now = Instant::now();
+                let now = clock.now();
                 trace!(
                     "{:?} scheduling wakeup at {:?}",
                     local_sockid,
                     TimeSpan::from_interval(timeout, now),
                 );
-                sleep_until(timeout.into()).await
+                let deadline = clock.deadline(timeout);
+                // tokio's timers would release packets paced closer than 1ms apart in bursts. The
+                // timer sleeps for what's left on the socket's clock, and tokio's timer still
+                // races it so a paused tokio clock is followed too
+                let left = timeout.saturating_duration_since(now);
+                match &precise_timer {
+                    Some(timer) if left < PRECISE_TIMER_THRESHOLD => select! {
+                        result = timer.sleep(left).fuse() => if result.is_err() {
+                            sleep_until(deadline).await
+                        },
+                        _ = sleep_until(deadline).fuse() => {},
+                    },
+                    _ => sleep_until(deadline).await,
+                }
             };
 
             let input = select! {
@@ -77,66 +321,132 @@ impl SrtSocketState {
                 packet = socket.receive().fuse() =>
                     Input::Packet(packet),
                 // new packet queued
-                data = input_data.next() => {
-                    Input::Data(data)
+                data = async {
+                    if take_input {
+                        input_data.next().await
+                    } else {
+                        future::pending().await
+                    }
+                }.fuse() => match data {
+                    Some((message, Some(delivery))) => {
+                        match connection.handle_tracked_data_input(clock.now(), message) {
+                            Some(packets) => {
+                                deliveries.insert(packets.start, delivery);
+                            }
+                            None => {
+                                let _ = delivery.send(Delivery::Dropped);
+                            }
+                        }
+                        continue;
+                    }
+                    Some((message, None)) => {
+                        connection.handle_message_input(clock.now(), message);
+                        continue;
+                    }
+                    None => Input::Data(None),
+                },
+                // the socket handle wants something else, ends once the handle is dropped
+                command = commands.select_next_some() => {
+                    match command {
+                        Command::SetImpairment(impairment) => impairer.set(impairment),
+                        Command::Detach(reply) => match socket.try_clone_std() {
+                            Ok(udp_socket) => {
+                                // what was queued is part of the connection to hand over
+                                while let Ok((message, _)) = input_data.get_mut().try_recv() {
+                                    connection.handle_message_input(clock.now(), message);
+                                }
+                                let _ = reply.send(Ok((connection.snapshot(), udp_socket)));
+                                return;
+                            }
+                            Err(e) => {
+                                let _ = reply.send(Err(e));
+                            }
+                        },
+                        // what's left in the channel is still taken, then it ends
+                        Command::Shutdown => input_data.get_mut().close(),
+                        Command::Ping(reply) => {
+                            // the connection forgets requests that go unanswered for long
+                            pings.retain(|_, sender| !sender.is_canceled());
+                            pings.insert(connection.send_echo_request(clock.now()), reply);
+                        }
+                        command => Self::handle_command(&mut connection, clock.now(), command),
+                    }
+                    continue;
+                }
+                // the socket was dropped without being closed
+                aborted = abort => {
+                    if aborted.is_ok() {
+                        connection.abort(clock.now());
+                    }
+                    continue;
                 }
             };
 
             match input {
-                Input::Packet(packet) => connection.handle_packet_input(Instant::now(), packet),
-                Input::Data(data) => connection.handle_data_input(Instant::now(), data),
-                _ => {}
-            }
-        }
-        if let Err(e) = output_data.close().await {
-            error!("Error while closing data output stream {:?}", e);
-        }
-    }
-
-    async fn run_input_loop(self) {
-        let mut socket = self.socket;
-        let mut input_data = self.input_data_receiver.fuse();
-        let mut output_data = self.output_data_sender;
-        let mut connection = self.connection;
-        let statistics_sender = self.statistics_sender;
-        let mut input = Input::Timer;
-        loop {
-            let now = Instant::now();
-            input = match connection.handle_input(now, input) {
-                Action::Close => break,
-                Action::ReleaseData(data) => {
-                    if !output_data.is_closed() {
-                        if let Err(e) = output_data.send(data).await {
-                            error!("Error while releasing data {:?}", e);
-                        }
+                Input::Packet(packet) => {
+                    connection.handle_packet_input(clock.now(), packet);
+                    let congestion_experienced = socket.take_congestion_experienced();
+                    if congestion_experienced > 0 {
+                        connection.handle_congestion_experienced(congestion_experienced);
                     }
-                    Input::DataReleased
-                }
-                Action::SendPacket(packet) => {
-                    if let Err(e) = socket.send(packet).await {
-                        error!("Error while seding packet: {:?}", e); // TODO: real error handling
+                    packets_in_a_row += 1;
+                    if packets_in_a_row == PACKET_BUDGET {
+                        packets_in_a_row = 0;
+                        yield_now().await;
                     }
-                    Input::PacketSent
                 }
-                Action::UpdateStatistics(statistics) => {
-                    let _ = statistics_sender.send(statistics.clone());
-                    Input::StatisticsUpdated
-                }
-                Action::WaitForData(wait) => {
-                    let timeout = now + wait;
-                    select! {
-                        _ = sleep_until(timeout.into()).fuse() => Input::Timer,
-                        packet = socket.receive().fuse() =>
-                            Input::Packet(packet),
-                        res = input_data.next() => {
-                            Input::Data(res)
-                        }
-                    }
+                Input::Data(data) => {
+                    packets_in_a_row = 0;
+                    connection.handle_data_input(clock.now(), data)
                 }
+                _ => packets_in_a_row = 0,
+            }
+        }
+        // e.g. the Shutdown of an aborted connection
+        while let Some(packet) = connection.next_packet(clock.now()) {
+            if let Err(e) = socket.send(packet).await {
+                error!(
+                    target: connection.log_target(),
+                    "Error while sending packet: {:?}",
+                    e
+                );
             }
         }
         if let Err(e) = output_data.close().await {
-            error!("Error while closing data output stream {:?}", e);
+            error!(
+                target: connection.log_target(),
+                "Error while closing data output stream {:?}",
+                e
+            );
+        }
+    }
+
+    fn handle_command(connection: &mut DuplexConnection, now: Instant, command: Command) {
+        match command {
+            Command::SendControlExtension(ty, payload) => {
+                connection.send_control_extension(now, ty, payload)
+            }
+            Command::SetExtensionHandler(handler) => connection.set_extension_handler(handler),
+            Command::SetGapHandler(handler) => connection.set_gap_handler(handler),
+            Command::SetTapHandler(handler) => connection.set_tap_handler(handler),
+            Command::SetRebindHandler(handler) => connection.set_rebind_handler(handler),
+            Command::SetStallHandler(threshold, handler) => {
+                connection.set_stall_handler(threshold, handler)
+            }
+            Command::SetCongestionAlarm(thresholds, handler) => {
+                connection.set_congestion_alarm(thresholds, handler)
+            }
+            // taken care of by the driver task
+            Command::SetImpairment(_)
+            | Command::Detach(_)
+            | Command::Ping(_)
+            | Command::Shutdown => {}
+            Command::SetLogContext(context) => connection.set_log_context(&context),
+            Command::SetLogLevel(level) => connection.set_log_level(level),
+            Command::PeerClockOffset(reply) => {
+                let _ = reply.send(connection.peer_clock_offset());
+            }
+            Command::Abort => connection.abort(now),
         }
     }
 }
@@ -144,17 +454,30 @@ impl SrtSocketState {
 #[derive(Debug)]
 pub struct SrtSocketFactory {
     output_data_receiver: mpsc::Receiver<(Instant, Bytes)>,
-    input_data_sender: mpsc::Sender<(Instant, Bytes)>,
+    input_data_sender: mpsc::Sender<DataInput>,
     statistics_receiver: watch::Receiver<SocketStatistics>,
+    command_sender: mpsc::Sender<Command>,
+    abort_sender: oneshot::Sender<()>,
+    send_buffer_full: Arc<AtomicBool>,
 }
 
 impl SrtSocketFactory {
-    pub fn create_socket(self, settings: ConnectionSettings, task: JoinHandle<()>) -> SrtSocket {
+    pub fn create_socket(
+        self,
+        settings: ConnectionSettings,
+        clock: SharedClock,
+        task: JoinHandle<()>,
+    ) -> SrtSocket {
         SrtSocket {
             settings,
+            clock,
             output_data_receiver: self.output_data_receiver.peekable(),
-            input_data_sender: self.input_data_sender,
+            input_data_sender: DataSender(self.input_data_sender, self.send_buffer_full),
             statistics_receiver: self.statistics_receiver,
+            sampled_statistics: SocketStatistics::new(),
+            quality: QualityScore::default(),
+            command_sender: self.command_sender,
+            abort_sender: Some(self.abort_sender),
             task,
         }
     }
@@ -163,29 +486,87 @@ impl SrtSocketFactory {
 #[derive(Debug)]
 pub struct SrtSocketTaskFactory {
     output_data_sender: mpsc::Sender<(Instant, Bytes)>,
-    input_data_receiver: mpsc::Receiver<(Instant, Bytes)>,
+    input_data_receiver: mpsc::Receiver<DataInput>,
     statistics_sender: watch::Sender<SocketStatistics>,
+    statistics_receiver: watch::Receiver<SocketStatistics>,
+    command_receiver: mpsc::Receiver<Command>,
+    command_sender: mpsc::Sender<Command>,
+    abort_receiver: oneshot::Receiver<()>,
+    send_buffer_full: Arc<AtomicBool>,
 }
 
 impl SrtSocketTaskFactory {
+    /// Another way to send commands to the task, besides the socket, for whoever holds on to
+    /// the connection, e.g. a listener
+    pub fn command_sender(&self) -> mpsc::Sender<Command> {
+        self.command_sender.clone()
+    }
+
+    /// The statistics the task publishes, for whoever holds on to the connection
+    pub fn statistics_receiver(&self) -> watch::Receiver<SocketStatistics> {
+        self.statistics_receiver.clone()
+    }
+
     pub fn spawn_task(
         self,
         socket: PacketSocket,
-        connection: Connection,
+        connection: DuplexConnection,
+        clock: SharedClock,
+        socket_id: Option<SocketIdLease>,
     ) -> (JoinHandle<()>, ConnectionSettings) {
-        let settings = connection.settings.clone();
+        let settings = connection.settings().clone();
+        let state = self.into_state(socket, connection, clock, socket_id);
+        let handle = tokio::spawn(state.run_with_timer_resolution());
+        (handle, settings)
+    }
 
-        let state = SrtSocketState {
+    /// Like [`spawn_task`](Self::spawn_task), on a thread of its own
+    pub fn spawn_thread(
+        self,
+        socket: PacketSocket,
+        connection: DuplexConnection,
+        clock: SharedClock,
+        socket_id: Option<SocketIdLease>,
+        driver: &DriverThread,
+    ) -> io::Result<(JoinHandle<()>, ConnectionSettings)> {
+        let settings = connection.settings().clone();
+        let state = self.into_state(socket, connection, clock, socket_id);
+        let name = format!("{:?}", settings.local_sockid);
+        let handle = driver.spawn(name, move || async move {
+            // on the reactor of the thread rather than that of the application's runtime
+            let socket = match state.socket.reregister() {
+                Ok(socket) => socket,
+                Err(e) => {
+                    error!("Could not move the socket to the driver thread: {e}");
+                    return;
+                }
+            };
+            SrtSocketState { socket, ..state }
+                .run_with_timer_resolution()
+                .await
+        })?;
+        Ok((handle, settings))
+    }
+
+    fn into_state(
+        self,
+        socket: PacketSocket,
+        connection: DuplexConnection,
+        clock: SharedClock,
+        socket_id: Option<SocketIdLease>,
+    ) -> SrtSocketState {
+        SrtSocketState {
             socket,
-            connection: DuplexConnection::new(connection),
+            connection,
             statistics_sender: self.statistics_sender,
             output_data_sender: self.output_data_sender,
             input_data_receiver: self.input_data_receiver,
-        };
-
-        let handle = tokio::spawn(async move { state.run_loop().await });
-
-        (handle, settings)
+            command_receiver: self.command_receiver,
+            abort_receiver: self.abort_receiver,
+            send_buffer_full: self.send_buffer_full,
+            clock,
+            socket_id,
+        }
     }
 }
 
@@ -193,17 +574,28 @@ pub fn split_new() -> (SrtSocketFactory, SrtSocketTaskFactory) {
     let (output_data_sender, output_data_receiver) = mpsc::channel(128);
     let (input_data_sender, input_data_receiver) = mpsc::channel(128);
     let (statistics_sender, statistics_receiver) = watch::channel();
+    let (command_sender, command_receiver) = mpsc::channel(16);
+    let (abort_sender, abort_receiver) = oneshot::channel();
+    let send_buffer_full = Arc::new(AtomicBool::new(false));
 
     let socket_factory = SrtSocketFactory {
         output_data_receiver,
         input_data_sender,
-        statistics_receiver,
+        statistics_receiver: statistics_receiver.clone(),
+        command_sender: command_sender.clone(),
+        abort_sender,
+        send_buffer_full: send_buffer_full.clone(),
     };
 
     let state_factory = SrtSocketTaskFactory {
         output_data_sender,
         input_data_receiver,
         statistics_sender,
+        statistics_receiver,
+        command_receiver,
+        command_sender,
+        abort_receiver,
+        send_buffer_full,
     };
 
     (socket_factory, state_factory)
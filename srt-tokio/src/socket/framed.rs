@@ -0,0 +1,31 @@
+use std::io;
+
+use tokio_util::codec::Framed;
+
+use super::SrtSocket;
+
+/// A connection in stream mode with a [`tokio_util::codec`] codec over it, e.g.
+/// [`LengthDelimitedCodec`](tokio_util::codec::LengthDelimitedCodec), to exchange frames of any
+/// size, delimited the way the peer expects. Created with [`SrtSocket::framed`].
+///
+/// Each frame is encoded into a single write, and what is received is decoded as it arrives,
+/// whatever the packets it came in.
+pub type SrtFramed<C> = Framed<SrtSocket, C>;
+
+impl SrtSocket {
+    /// Puts `codec` over the byte stream of the connection.
+    ///
+    /// Fails with [`InvalidInput`](io::ErrorKind::InvalidInput) unless the connection is in stream
+    /// mode, i.e. with [`message_api`](crate::options::Session::message_api) off on both sides.
+    /// With messages, one dropped for being too late would take part of a frame with it and
+    /// everything after it would be decoded out of step.
+    pub fn framed<C>(self, codec: C) -> io::Result<SrtFramed<C>> {
+        if !self.settings.stream_mode() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "framing needs a connection in stream mode, with the message API off on both sides",
+            ));
+        }
+        Ok(Framed::new(self, codec))
+    }
+}
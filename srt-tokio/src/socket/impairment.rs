@@ -0,0 +1,178 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use srt_protocol::packet::Packet;
+
+/// Network impairments applied to the packets a socket sends, see [`SrtSocket::set_impairment`].
+///
+/// It lets a canary check how players cope with a bad network, on a production socket and without
+/// the privileges netem needs. Every packet is affected, control packets included.
+///
+/// [`SrtSocket::set_impairment`]: crate::SrtSocket::set_impairment
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Impairment {
+    /// The fraction of packets that aren't sent at all, from 0 to 1
+    pub drop_rate: f64,
+
+    /// The fraction of packets sent twice, from 0 to 1
+    pub duplicate_rate: f64,
+
+    /// Every packet is held back for a random time of up to this, so they're reordered too
+    pub jitter: Duration,
+}
+
+impl Impairment {
+    // the rates are probabilities, anything else (NaN too) can't be drawn from
+    pub(crate) fn validate(&self) -> io::Result<()> {
+        for (name, rate) in [
+            ("drop_rate", self.drop_rate),
+            ("duplicate_rate", self.duplicate_rate),
+        ] {
+            if !(0. ..=1.).contains(&rate) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{name} must be from 0 to 1, not {rate}"),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// the packets held back by jitter, keyed by when to send them, then in the order they came in
+#[derive(Debug)]
+pub struct Impairer {
+    impairment: Impairment,
+    rng: StdRng,
+    delayed: BTreeMap<(Instant, u64), (Packet, SocketAddr)>,
+    count: u64,
+}
+
+impl Default for Impairer {
+    fn default() -> Self {
+        Self::new(StdRng::from_entropy())
+    }
+}
+
+impl Impairer {
+    fn new(rng: StdRng) -> Self {
+        Self {
+            impairment: Impairment::default(),
+            rng,
+            delayed: BTreeMap::new(),
+            count: 0,
+        }
+    }
+
+    pub fn set(&mut self, impairment: Impairment) {
+        self.impairment = impairment;
+    }
+
+    /// The packet a connection sent, after which the ones due are in `next_due`
+    pub fn push(&mut self, now: Instant, packet: (Packet, SocketAddr)) {
+        let impairment = self.impairment;
+        if self.rng.gen_bool(impairment.drop_rate) {
+            return;
+        }
+        let copies = if self.rng.gen_bool(impairment.duplicate_rate) {
+            2
+        } else {
+            1
+        };
+        for _ in 0..copies {
+            let delay = impairment.jitter.mul_f64(self.rng.gen());
+            self.count += 1;
+            self.delayed
+                .insert((now + delay, self.count), packet.clone());
+        }
+    }
+
+    pub fn next_due(&mut self, now: Instant) -> Option<(Packet, SocketAddr)> {
+        let entry = self.delayed.first_entry().filter(|e| e.key().0 <= now)?;
+        Some(entry.remove())
+    }
+
+    /// When the next packet held back is due
+    pub fn next_release(&self) -> Option<Instant> {
+        self.delayed.keys().next().map(|(time, _)| *time)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use srt_protocol::packet::*;
+
+    use super::*;
+
+    fn packet(n: u32) -> (Packet, SocketAddr) {
+        let data = DataPacket {
+            seq_number: SeqNumber(n),
+            message_loc: PacketLocation::ONLY,
+            in_order_delivery: false,
+            encryption: DataEncryption::None,
+            retransmitted: false,
+            message_number: MsgNumber(n),
+            timestamp: TimeStamp::MIN,
+            dest_sockid: SocketId(1),
+            payload: Bytes::new(),
+        };
+        (Packet::Data(data), ([127, 0, 0, 1], 1234).into())
+    }
+
+    fn seq_number(packet: &(Packet, SocketAddr)) -> u32 {
+        match &packet.0 {
+            Packet::Data(data) => data.seq_number.0,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn impairment() {
+        let now = Instant::now();
+        let mut impairer = Impairer::new(StdRng::seed_from_u64(0));
+
+        // nothing by default
+        impairer.push(now, packet(0));
+        assert_eq!(impairer.next_due(now).as_ref().map(seq_number), Some(0));
+        assert_eq!(impairer.next_due(now), None);
+
+        impairer.set(Impairment {
+            drop_rate: 0.2,
+            duplicate_rate: 0.1,
+            jitter: Duration::from_millis(10),
+        });
+        for n in 0..10_000 {
+            impairer.push(now, packet(n));
+        }
+        let first = impairer.next_release().unwrap();
+        let mut sent = Vec::new();
+        while let Some(packet) = impairer.next_due(now + Duration::from_millis(10)) {
+            sent.push(seq_number(&packet));
+        }
+        assert!(first >= now && first < now + Duration::from_millis(1));
+        assert!((8_600..9_000).contains(&sent.len()), "{}", sent.len());
+        assert!(sent.windows(2).any(|w| w[0] > w[1]), "reordered");
+        sent.sort_unstable();
+        sent.dedup();
+        assert!((7_800..8_200).contains(&sent.len()), "{}", sent.len());
+    }
+
+    #[test]
+    fn validate() {
+        let impairment = |drop_rate, duplicate_rate| Impairment {
+            drop_rate,
+            duplicate_rate,
+            jitter: Duration::ZERO,
+        };
+        assert!(impairment(0., 1.).validate().is_ok());
+        assert!(impairment(f64::NAN, 0.).validate().is_err());
+        assert!(impairment(0., 1.5).validate().is_err());
+        assert!(impairment(-0.1, 0.).validate().is_err());
+    }
+}
@@ -0,0 +1,277 @@
+use std::{
+    collections::VecDeque,
+    fmt, io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::future::BoxFuture;
+use log::warn;
+use srt_protocol::{
+    options::{BindOptions, Encryption},
+    packet::{
+        ControlPacket, ControlTypes, HandshakeVsInfo, Packet, ReceivePacketResult, SrtControlPacket,
+    },
+    settings::{EncryptionKey, KeyProvider, KeyProviderError, KeySize, Salt, SharedKeyProvider},
+};
+use tokio::time::Instant;
+
+/// A [`KeyProvider`] that takes a round trip to get the key encrypting key, e.g. to a KMS or an
+/// HSM, see [`SrtSocketBuilder::async_key_provider`](crate::SrtSocketBuilder::async_key_provider)
+///
+/// The key for the salt this side starts a connection with is fetched before the handshake, and
+/// the one for the salt the peer starts it with when its keying material arrives, before the
+/// handshake carries on. The salt stays the same when the stream keys are refreshed, so refreshes
+/// use the key fetched during the handshake and never wait for the provider.
+///
+/// Any peer can send keying material with a salt of its choosing, so the keys for peers' salts
+/// are fetched at most 8 times a second, and only the 16 used last are kept.
+pub trait AsyncKeyProvider: Send + Sync + 'static {
+    /// The key encrypting key of `key_size` for the connection using `salt`, like
+    /// [`KeyProvider::key_encrypting_key`]
+    fn key_encrypting_key(
+        &self,
+        key_size: KeySize,
+        salt: Salt,
+    ) -> BoxFuture<'static, Result<EncryptionKey, KeyProviderError>>;
+}
+
+// the options get a cache for the keys of `provider` in its place, with the key the connection
+// starts with as the initiator already in it
+pub(crate) async fn attach(
+    options: BindOptions,
+    provider: Option<&Arc<dyn AsyncKeyProvider>>,
+) -> Result<(BindOptions, Option<Arc<KeyCache>>), io::Error> {
+    let Some(provider) = provider else {
+        return Ok((options, None));
+    };
+    use BindOptions::*;
+    let key_size = match &options {
+        Listen(options) => options.socket.encryption.key_size,
+        Call(options) => options.socket.encryption.key_size,
+        Rendezvous(options) => options.socket.encryption.key_size,
+    };
+    let keys = Arc::new(KeyCache::new(provider.clone(), key_size));
+    let shared = SharedKeyProvider::from(keys.clone() as Arc<dyn KeyProvider>);
+    let set = |encryption: &mut Encryption| encryption.key_provider = Some(shared);
+    let options = match options {
+        Listen(options) => Listen(options.set(|o| set(&mut o.socket.encryption))?),
+        Call(options) => {
+            keys.fetch_initial().await.map_err(io::Error::other)?;
+            Call(options.set(|o| set(&mut o.socket.encryption))?)
+        }
+        Rendezvous(options) => {
+            keys.fetch_initial().await.map_err(io::Error::other)?;
+            Rendezvous(options.set(|o| set(&mut o.socket.encryption))?)
+        }
+    };
+    Ok((options, Some(keys)))
+}
+
+// the keys kept for the salts of peers, the least recently used is evicted for another
+const MAX_CACHED_KEYS: usize = 16;
+
+// any peer can send keying material with a new salt, so only this many fetches for the salts of
+// peers are made in a window, those over it leave the handshake to reject the peer
+const MAX_PEER_FETCHES: usize = 8;
+const PEER_FETCH_WINDOW: Duration = Duration::from_secs(1);
+
+// hands the connection, which asks for keys synchronously, those fetched ahead of time
+pub(crate) struct KeyCache {
+    provider: Arc<dyn AsyncKeyProvider>,
+    key_size: KeySize,
+    salt: Salt,
+    // the key for the salt this side starts with, which is never evicted
+    initial: Mutex<Option<EncryptionKey>>,
+    // least recently used first
+    keys: Mutex<VecDeque<(KeySize, Salt, EncryptionKey)>>,
+    peer_fetches: Mutex<VecDeque<Instant>>,
+}
+
+impl KeyCache {
+    pub fn new(provider: Arc<dyn AsyncKeyProvider>, key_size: KeySize) -> Self {
+        Self {
+            provider,
+            key_size,
+            salt: Salt::new_random(),
+            initial: Mutex::new(None),
+            keys: Mutex::new(VecDeque::new()),
+            peer_fetches: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    // for the salt this side starts the connection with as the initiator
+    pub async fn fetch_initial(&self) -> Result<(), KeyProviderError> {
+        if self.initial.lock().unwrap().is_some() {
+            return Ok(());
+        }
+        let key = self
+            .provider
+            .key_encrypting_key(self.key_size, self.salt.clone())
+            .await?;
+        *self.initial.lock().unwrap() = Some(key);
+        Ok(())
+    }
+
+    // for the salt of the keying material in a handshake from the peer, a failure is left for the
+    // handshake to reject the peer for, like for a wrong passphrase
+    pub async fn prefetch(&self, packet: &ReceivePacketResult) {
+        let Ok((
+            Packet::Control(ControlPacket {
+                control_type: ControlTypes::Handshake(handshake),
+                ..
+            }),
+            _,
+        )) = packet
+        else {
+            return;
+        };
+        let HandshakeVsInfo::V5(info) = &handshake.info else {
+            return;
+        };
+        let Some(SrtControlPacket::KeyRefreshRequest(km)) = &info.ext_km else {
+            return;
+        };
+        let Ok(salt) = Salt::try_from(&km.salt) else {
+            return;
+        };
+        if let Err(e) = self.fetch_for_peer(salt).await {
+            warn!("Fetching the key for the peer's keying material failed: {e}");
+        }
+    }
+
+    async fn fetch_for_peer(&self, salt: Salt) -> Result<(), KeyProviderError> {
+        if self.cached(self.key_size, &salt).is_some() {
+            return Ok(());
+        }
+        self.take_peer_fetch()?;
+        let key = self
+            .provider
+            .key_encrypting_key(self.key_size, salt.clone())
+            .await?;
+        let mut keys = self.keys.lock().unwrap();
+        if keys.len() >= MAX_CACHED_KEYS {
+            keys.pop_front();
+        }
+        keys.push_back((self.key_size, salt, key));
+        Ok(())
+    }
+
+    fn take_peer_fetch(&self) -> Result<(), KeyProviderError> {
+        let now = Instant::now();
+        let mut fetches = self.peer_fetches.lock().unwrap();
+        while let Some(&at) = fetches.front() {
+            if now.duration_since(at) < PEER_FETCH_WINDOW {
+                break;
+            }
+            fetches.pop_front();
+        }
+        if fetches.len() >= MAX_PEER_FETCHES {
+            return Err(KeyProviderError(format!(
+                "more than {MAX_PEER_FETCHES} keys were asked for in {PEER_FETCH_WINDOW:?}"
+            )));
+        }
+        fetches.push_back(now);
+        Ok(())
+    }
+
+    fn cached(&self, key_size: KeySize, salt: &Salt) -> Option<EncryptionKey> {
+        if key_size == self.key_size && *salt == self.salt {
+            if let Some(key) = &*self.initial.lock().unwrap() {
+                return Some(key.clone());
+            }
+        }
+        let mut keys = self.keys.lock().unwrap();
+        let index = keys
+            .iter()
+            .position(|(size, s, _)| *size == key_size && s == salt)?;
+        let entry = keys.remove(index)?;
+        let key = entry.2.clone();
+        keys.push_back(entry);
+        Some(key)
+    }
+}
+
+impl KeyProvider for KeyCache {
+    fn key_encrypting_key(
+        &self,
+        key_size: KeySize,
+        salt: &Salt,
+    ) -> Result<EncryptionKey, KeyProviderError> {
+        self.cached(key_size, salt)
+            .ok_or_else(|| KeyProviderError(format!("no key was fetched for {salt:?}")))
+    }
+
+    fn new_salt(&self) -> Salt {
+        self.salt.clone()
+    }
+}
+
+impl fmt::Debug for KeyCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyCache")
+            .field("key_size", &self.key_size)
+            .field("salt", &self.salt)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Provider(AtomicUsize);
+
+    impl AsyncKeyProvider for Arc<Provider> {
+        fn key_encrypting_key(
+            &self,
+            key_size: KeySize,
+            _salt: Salt,
+        ) -> BoxFuture<'static, Result<EncryptionKey, KeyProviderError>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move { Ok(EncryptionKey::new_random(key_size)) })
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bounded() {
+        let provider = Arc::new(Provider::default());
+        let keys = KeyCache::new(Arc::new(provider.clone()), KeySize::AES128);
+        keys.fetch_initial().await.unwrap();
+
+        let salts = (0..MAX_CACHED_KEYS + 1)
+            .map(|_| Salt::new_random())
+            .collect::<Vec<_>>();
+        for salt in &salts {
+            tokio::time::advance(PEER_FETCH_WINDOW).await;
+            keys.fetch_for_peer(salt.clone()).await.unwrap();
+        }
+        assert_eq!(provider.0.load(Ordering::SeqCst), MAX_CACHED_KEYS + 2);
+
+        // the least recently used is evicted, but never the key this side starts with
+        let cached = |salt| keys.key_encrypting_key(KeySize::AES128, salt).is_ok();
+        assert!(!cached(&salts[0]));
+        assert!(cached(&salts[1]));
+        assert!(cached(&salts[MAX_CACHED_KEYS]));
+        assert!(cached(&keys.new_salt()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_limited() {
+        let provider = Arc::new(Provider::default());
+        let keys = KeyCache::new(Arc::new(provider.clone()), KeySize::AES128);
+
+        for _ in 0..MAX_PEER_FETCHES {
+            keys.fetch_for_peer(Salt::new_random()).await.unwrap();
+        }
+        assert!(keys.fetch_for_peer(Salt::new_random()).await.is_err());
+        assert_eq!(provider.0.load(Ordering::SeqCst), MAX_PEER_FETCHES);
+
+        tokio::time::advance(PEER_FETCH_WINDOW).await;
+        keys.fetch_for_peer(Salt::new_random()).await.unwrap();
+        assert_eq!(provider.0.load(Ordering::SeqCst), MAX_PEER_FETCHES + 1);
+    }
+}
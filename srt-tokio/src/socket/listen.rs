@@ -1,19 +1,24 @@
-use std::{io, time::Instant};
+use std::io;
 
 use log::{debug, warn};
 
 use srt_protocol::{
     connection::Connection,
     options::*,
-    protocol::pending_connection::{listen::Listen, ConnectionResult},
+    protocol::pending_connection::{listen::Listen, ConnectionResult, HandshakeTelemetry},
     settings::*,
 };
 
-use crate::net::PacketSocket;
+use crate::{clock::SharedClock, net::PacketSocket};
+
+use super::key_provider::KeyCache;
 
 pub async fn bind_with(
     mut socket: PacketSocket,
     options: Valid<ListenerOptions>,
+    keys: Option<&KeyCache>,
+    mut telemetry: HandshakeTelemetry,
+    clock: &SharedClock,
 ) -> Result<(PacketSocket, Connection), io::Error> {
     let init_settings: ConnInitSettings = options.socket.clone().into();
     let socket_id = init_settings.local_sockid;
@@ -22,21 +27,35 @@ pub async fn bind_with(
     loop {
         let packet = socket.receive().await;
         debug!("{:?}:listen  - {:?}", socket_id, packet);
+        if let Ok((packet, from)) = &packet {
+            telemetry.received(clock.now(), packet, *from);
+        }
+        if let Some(keys) = keys {
+            keys.prefetch(&packet).await;
+        }
 
-        let result = listen.handle_packet(Instant::now(), packet);
+        let result = listen.handle_packet(clock.now(), packet);
         debug!("{:?}:listen  - {:?}", socket_id, result);
 
         use ConnectionResult::*;
         match result {
             SendPacket(packet) => {
+                telemetry.sent(clock.now(), &packet.0, packet.1);
                 let _ = socket.send(packet).await?;
             }
             NotHandled(e) => {
                 warn!("{:?}", e);
             }
-            Reject(_, _) => todo!(),
+            Reject(rp, rr) => {
+                if let Some(packet) = rp {
+                    telemetry.sent(clock.now(), &packet.0, packet.1);
+                    let _ = socket.send(packet).await?;
+                }
+                return Err(io::Error::new(io::ErrorKind::ConnectionRefused, rr));
+            }
             Connected(p, connection) => {
                 if let Some(packet) = p {
+                    telemetry.sent(clock.now(), &packet.0, packet.1);
                     let _ = socket.send(packet).await?;
                 }
                 return Ok((socket, connection));
@@ -1,29 +1,40 @@
 mod builder;
 mod call;
+mod driver;
+mod framed;
+mod key_provider;
 mod listen;
 mod rendezvous;
 
 pub(crate) mod factory;
+mod impairment;
 
 use std::{
     fmt::Debug,
-    io,
+    io, net,
+    ops::Range,
     pin::Pin,
-    sync::Arc,
     task::{Context, Poll},
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use bytes::{BufMut, Bytes, BytesMut};
 use futures::{
-    channel::mpsc::{self, TrySendError},
+    channel::{mpsc, oneshot},
     prelude::*,
     ready,
     stream::Peekable,
 };
 use srt_protocol::{
-    connection::ConnectionSettings,
-    options::{OptionsError, OptionsOf, SocketOptions, Validation},
+    connection::{
+        ConnectionInfo, ConnectionSettings, ConnectionSnapshot, Delivery, DuplexConnection,
+        EchoSample, SendMessage, StallEvent,
+    },
+    options::{OptionsError, OptionsOf, SendBufferPolicy, SocketOptions, Validation},
+    packet::{DataPacket, SeqNumber, SocketId, SrtControlPacket, TimeSpan},
+    protocol::pending_connection::HandshakeTelemetry,
+    settings::{KeyMaterialState, SocketIdLease},
+    statistics::{CongestionEvent, CongestionThresholds, QualityFormula, QualityScore},
 };
 use tokio::{
     io::{AsyncRead, AsyncWrite},
@@ -31,9 +42,13 @@ use tokio::{
     task::JoinHandle,
 };
 
-use super::{net::*, options::BindOptions, watch};
+use super::{clock::SharedClock, net::*, options::BindOptions, watch};
 
 pub use builder::SrtSocketBuilder;
+pub use driver::DriverThread;
+pub use framed::SrtFramed;
+pub use impairment::Impairment;
+pub use key_provider::AsyncKeyProvider;
 pub use srt_protocol::statistics::SocketStatistics;
 
 /// Connected SRT connection, generally created with [`SrtSocketBuilder`](crate::SrtSocketBuilder).
@@ -43,12 +58,30 @@ pub use srt_protocol::statistics::SocketStatistics;
 ///
 /// The sockets yield and consume `(Instant, Bytes)`, representing the data and the origin instant. This instant
 /// defines when the packet will be released on the receiving side, at more or less one latency later.
+/// They also consume [`SendMessage`](crate::SendMessage)s, which carry the per message options.
+///
+/// # Closing and dropping
+/// Closing the socket ([`close()`](Self::close)) finishes sending what was queued before
+/// telling the peer. Dropping a socket that wasn't closed aborts the connection instead: the
+/// peer gets a Shutdown right away, queued data is discarded and the port is released as soon as
+/// the socket's task sees it, without waiting for any timeout.
+///
+/// # Cancellation
+/// Receiving is cancel safe, a `next()` that is dropped before it completes doesn't lose any
+/// data. A `send()` that is dropped before it completes may or may not have queued its item;
+/// queued items are sent as usual.
 #[derive(Debug)]
 pub struct SrtSocket {
     output_data_receiver: Peekable<mpsc::Receiver<(Instant, Bytes)>>,
-    input_data_sender: mpsc::Sender<(Instant, Bytes)>,
+    input_data_sender: factory::DataSender,
     statistics_receiver: watch::Receiver<SocketStatistics>,
+    sampled_statistics: SocketStatistics,
+    quality: QualityScore,
+    command_sender: mpsc::Sender<factory::Command>,
+    // a channel of its own, so a full command queue can't keep a drop from aborting
+    abort_sender: Option<oneshot::Sender<()>>,
     settings: ConnectionSettings,
+    clock: SharedClock,
     task: JoinHandle<()>,
 }
 
@@ -58,9 +91,71 @@ impl SrtSocket {
     }
 
     pub fn try_send(&mut self, srctime: Instant, data: Bytes) -> Result<(), (Instant, Bytes)> {
-        self.input_data_sender
-            .try_send((srctime, data))
-            .map_err(TrySendError::into_inner)
+        self.try_send_message(SendMessage::new(srctime, data))
+            .map_err(Into::into)
+    }
+
+    /// Like [`try_send`](Self::try_send), with the per message options of [`SendMessage`]
+    pub fn try_send_message(&mut self, message: SendMessage) -> Result<(), SendMessage> {
+        if self.settings.check_payload_size(&message).is_err() {
+            return Err(message);
+        }
+        if self.settings.send_buffer_policy != SendBufferPolicy::DropOldest
+            && self.input_data_sender.is_send_buffer_full()
+        {
+            return Err(message);
+        }
+        self.input_data_sender.try_send(message)
+    }
+
+    /// Queue a message like `send()`, returning a future that resolves once the whole message
+    /// was acknowledged by the peer, or was dropped because it was too late or the send buffer
+    /// overflowed. It fails with [`io::ErrorKind::NotConnected`] if the connection ends first.
+    pub async fn send_tracked(
+        &mut self,
+        srctime: Instant,
+        data: Bytes,
+    ) -> io::Result<PendingDelivery> {
+        self.send_tracked_message(SendMessage::new(srctime, data))
+            .await
+    }
+
+    /// Like [`send_tracked`](Self::send_tracked), with the per message options of
+    /// [`SendMessage`]
+    pub async fn send_tracked_message(
+        &mut self,
+        message: SendMessage,
+    ) -> io::Result<PendingDelivery> {
+        self.check_payload_size(&message)?;
+        self.check_send_buffer()?;
+        let receiver = self
+            .input_data_sender
+            .send_tracked(message)
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::NotConnected, e))?;
+        Ok(PendingDelivery(receiver))
+    }
+
+    // a message larger than the payload size fails with io::ErrorKind::InvalidInput, carrying the
+    // PayloadTooLarge, rather than being split
+    fn check_payload_size(&self, message: &SendMessage) -> io::Result<()> {
+        self.settings
+            .check_payload_size(message)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    // with SendBufferPolicy::Error, a full send buffer fails with io::ErrorKind::WouldBlock
+    fn check_send_buffer(&self) -> io::Result<()> {
+        if self.settings.send_buffer_policy == SendBufferPolicy::Error
+            && self.input_data_sender.is_send_buffer_full()
+        {
+            Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "the send buffer is full",
+            ))
+        } else {
+            Ok(())
+        }
     }
 
     pub fn with<O>(options: O) -> SrtSocketBuilder
@@ -72,31 +167,338 @@ impl SrtSocket {
     }
 
     pub async fn bind(options: BindOptions) -> Result<Self, io::Error> {
+        Self::bind_configured(options, &Network::default(), SharedClock::default(), |_| {}).await
+    }
+
+    async fn bind_configured(
+        options: BindOptions,
+        network: &Network,
+        clock: SharedClock,
+        configure: impl FnOnce(&mut DuplexConnection),
+    ) -> Result<Self, io::Error> {
         use BindOptions::*;
         let socket_options = match &options {
             Listen(options) => &options.socket,
             Call(options) => &options.socket,
             Rendezvous(options) => &options.socket,
         };
-        let socket = bind_socket(socket_options).await?;
-        Self::bind_with_socket(options, socket).await
+        let socket = Datagrams::bind(network.binder.as_deref(), socket_options).await?;
+        Self::bind_with_socket(options, socket, network, clock, configure).await
     }
 
-    async fn bind_with_socket(options: BindOptions, socket: UdpSocket) -> Result<Self, io::Error> {
-        let socket = PacketSocket::from_socket(Arc::new(socket), 1024 * 1024);
+    async fn bind_with_socket(
+        options: BindOptions,
+        socket: Datagrams,
+        network: &Network,
+        clock: SharedClock,
+        configure: impl FnOnce(&mut DuplexConnection),
+    ) -> Result<Self, io::Error> {
+        Self::check_local_port(&options, &socket)?;
+        let socket = PacketSocket::from_socket(socket, 1024 * 1024);
+        let (options, socket_id) = Self::lease_socket_id(options)?;
+        let (options, keys) = key_provider::attach(options, network.key_provider.as_ref()).await?;
+        let keys = keys.as_deref();
 
         use BindOptions::*;
+        let resolver = &*network.resolver;
+        let telemetry = HandshakeTelemetry::new(network.handshake_hook.clone());
         let (socket, connection) = match options {
-            Listen(options) => listen::bind_with(socket, options).await?,
-            Call(options) => call::bind_with(socket, options).await?,
-            Rendezvous(options) => rendezvous::bind_with(socket, options).await?,
+            Listen(options) => listen::bind_with(socket, options, keys, telemetry, &clock).await?,
+            Call(options) => {
+                call::bind_with(socket, options, resolver, keys, telemetry, &clock).await?
+            }
+            Rendezvous(options) => {
+                rendezvous::bind_with(socket, options, resolver, keys, telemetry, &clock).await?
+            }
+        };
+
+        Self::spawn(
+            socket,
+            DuplexConnection::new(connection),
+            clock,
+            configure,
+            socket_id,
+            network.driver.as_ref(),
+        )
+    }
+
+    // a socket or binder handed in has to be on the port the peer expects, or that the firewall
+    // lets through, as far as it tells which port it's on
+    fn check_local_port(options: &BindOptions, socket: &Datagrams) -> Result<(), io::Error> {
+        use BindOptions::*;
+        let expected = match options {
+            Listen(_) => return Ok(()),
+            Call(options) => options.socket.connect.local.port(),
+            Rendezvous(options) => options.socket.connect.local.port(),
+        };
+        let Ok(bound) = socket.local_addr().map(|local| local.port()) else {
+            return Ok(());
+        };
+        if expected != 0 && bound != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                format!("the socket is bound to port {bound}, not the local port {expected}"),
+            ));
+        }
+        Ok(())
+    }
+
+    fn restore_with_socket(
+        snapshot: ConnectionSnapshot,
+        socket: UdpSocket,
+        network: &Network,
+        clock: SharedClock,
+        configure: impl FnOnce(&mut DuplexConnection),
+    ) -> Result<Self, io::Error> {
+        let socket = PacketSocket::from_socket(socket.into(), 1024 * 1024);
+        let connection = DuplexConnection::restore(snapshot, clock.now());
+        let socket_id = Self::reserve_socket_id(connection.settings().local_sockid)?;
+        let driver = network.driver.as_ref();
+        Self::spawn(
+            socket,
+            connection,
+            clock,
+            configure,
+            Some(socket_id),
+            driver,
+        )
+    }
+
+    // Packets are told apart by their socket id, which connections sharing a port, or bound to
+    // the same one with SO_REUSEPORT, only get unique ids for if they are leased process wide
+    fn lease_socket_id(
+        options: BindOptions,
+    ) -> Result<(BindOptions, Option<SocketIdLease>), io::Error> {
+        use BindOptions::*;
+        let socket_options = match &options {
+            Listen(options) => &options.socket,
+            Call(options) => &options.socket,
+            Rendezvous(options) => &options.socket,
         };
+        if let Some(socket_id) = socket_options.session.socket_id {
+            let lease = Self::reserve_socket_id(socket_id)?;
+            return Ok((options, Some(lease)));
+        }
 
+        let lease = SocketIdLease::allocate();
+        let set = |socket: &mut SocketOptions| socket.session.socket_id = Some(lease.socket_id());
+        let options = match options {
+            Listen(options) => Listen(options.set(|o| set(&mut o.socket))?),
+            Call(options) => Call(options.set(|o| set(&mut o.socket))?),
+            Rendezvous(options) => Rendezvous(options.set(|o| set(&mut o.socket))?),
+        };
+        Ok((options, Some(lease)))
+    }
+
+    // the peer of a connection with an id that's in use would get the packets of the other one
+    fn reserve_socket_id(socket_id: SocketId) -> Result<SocketIdLease, io::Error> {
+        SocketIdLease::reserve(socket_id).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("socket id {socket_id:?} is in use by another connection"),
+            )
+        })
+    }
+
+    fn spawn(
+        socket: PacketSocket,
+        mut connection: DuplexConnection,
+        clock: SharedClock,
+        configure: impl FnOnce(&mut DuplexConnection),
+        socket_id: Option<SocketIdLease>,
+        driver: Option<&DriverThread>,
+    ) -> Result<Self, io::Error> {
         let (new_socket, new_state) = factory::split_new();
-        let (task, settings) = new_state.spawn_task(socket, connection);
-        let socket = new_socket.create_socket(settings, task);
+        configure(&mut connection);
+        let (task, settings) = match driver {
+            None => new_state.spawn_task(socket, connection, clock.clone(), socket_id),
+            Some(driver) => {
+                new_state.spawn_thread(socket, connection, clock.clone(), socket_id, driver)?
+            }
+        };
+        Ok(new_socket.create_socket(settings, clock, task))
+    }
+
+    /// Send a user-defined SRT control extension packet to the peer.
+    ///
+    /// `ty` must be above the extension types used by SRT itself,
+    /// [`SrtControlPacket::MAX_TYPE_ID`], and not one this implementation uses, see
+    /// [`SrtControlPacket::is_reserved_type`]. The payload is padded with zeros to 32-bit words.
+    pub async fn send_control_extension(&mut self, ty: u16, payload: Bytes) -> io::Result<()> {
+        if SrtControlPacket::is_reserved_type(ty) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("SRT control extension type {ty} is reserved"),
+            ));
+        }
+        self.send_command(factory::Command::SendControlExtension(ty, payload))
+            .await
+    }
+
+    /// Call `handler` with the type and payload of every SRT control extension packet the peer
+    /// sends from now on. Extensions that arrive before it is installed are dropped.
+    pub async fn set_extension_handler(
+        &mut self,
+        handler: impl FnMut(Instant, u16, Bytes) + Send + 'static,
+    ) -> io::Result<()> {
+        self.send_command(factory::Command::SetExtensionHandler(Box::new(handler)))
+            .await
+    }
 
-        Ok(socket)
+    /// Call `handler` with the sequence numbers of every run of packets that was skipped because
+    /// it couldn't be recovered in time. It's called before the message that follows the gap is
+    /// passed on, so markers can be inserted into the output in order.
+    pub async fn set_gap_handler(
+        &mut self,
+        handler: impl FnMut(Instant, Range<SeqNumber>) + Send + 'static,
+    ) -> io::Result<()> {
+        self.send_command(factory::Command::SetGapHandler(Box::new(handler)))
+            .await
+    }
+
+    /// Call `handler` with every data packet as soon as it and those before it are in, recovered
+    /// or given up on, rather than one latency after it was sent, e.g. to record the stream while
+    /// it's played out as usual from the socket.
+    pub async fn set_tap_handler(
+        &mut self,
+        handler: impl FnMut(Instant, &DataPacket) + Send + 'static,
+    ) -> io::Result<()> {
+        self.send_command(factory::Command::SetTapHandler(Box::new(handler)))
+            .await
+    }
+
+    /// Call `handler` with the old and the new address of the peer whenever the connection
+    /// follows it to another one, e.g. after its NAT picked a new port. Which moves it follows is
+    /// up to [`SrtSocketBuilder::peer_address_policy`].
+    pub async fn set_rebind_handler(
+        &mut self,
+        handler: impl FnMut(Instant, net::SocketAddr, net::SocketAddr) + Send + 'static,
+    ) -> io::Result<()> {
+        self.send_command(factory::Command::SetRebindHandler(Box::new(handler)))
+            .await
+    }
+
+    /// Call `handler` once no new data, retransmissions aside, arrived from the peer for
+    /// `threshold` while the connection is up, and again when it comes back, e.g. for a player
+    /// to show that the signal is lost well before the connection times out.
+    pub async fn set_stall_handler(
+        &mut self,
+        threshold: Duration,
+        handler: impl FnMut(Instant, &StallEvent) + Send + 'static,
+    ) -> io::Result<()> {
+        self.send_command(factory::Command::SetStallHandler(
+            threshold,
+            Box::new(handler),
+        ))
+        .await
+    }
+
+    /// Call `handler` when the retransmit ratio or the rate of NAKs from the peer, averaged over
+    /// the window of `thresholds`, reach them, and again when both are back below, e.g. for a
+    /// relay to ask its source for a lower bitrate. It's checked every statistics interval, see
+    /// [`CongestionAlarm`](crate::statistics::CongestionAlarm).
+    pub async fn set_congestion_alarm(
+        &mut self,
+        thresholds: CongestionThresholds,
+        handler: impl FnMut(&CongestionEvent) + Send + 'static,
+    ) -> io::Result<()> {
+        self.send_command(factory::Command::SetCongestionAlarm(
+            thresholds,
+            Box::new(handler),
+        ))
+        .await
+    }
+
+    /// Impair the packets this socket sends from now on, e.g. drop some of them, to test how the
+    /// peer copes with a bad network. [`Impairment::default`] turns it off again.
+    ///
+    /// Fails with [`InvalidInput`](io::ErrorKind::InvalidInput) for a rate that isn't from 0 to 1.
+    pub async fn set_impairment(&mut self, impairment: Impairment) -> io::Result<()> {
+        impairment.validate()?;
+        self.send_command(factory::Command::SetImpairment(impairment))
+            .await
+    }
+
+    /// Log the records of this connection to the `srt_protocol::connection::<context>` target, so
+    /// e.g. `RUST_LOG=srt_protocol::connection::my-stream=debug` debugs just this one. Records of
+    /// the driver task go there too.
+    pub async fn set_log_context(&mut self, context: impl Into<String>) -> io::Result<()> {
+        self.send_command(factory::Command::SetLogContext(context.into()))
+            .await
+    }
+
+    /// Override the maximum level this connection logs at, instead of the global
+    /// [`log::max_level`]. `None` removes the override.
+    pub async fn set_log_level(&mut self, level: Option<log::LevelFilter>) -> io::Result<()> {
+        self.send_command(factory::Command::SetLogLevel(level))
+            .await
+    }
+
+    /// Stop running the connection without closing it, for another process to carry on with it
+    /// as if nothing happened, e.g. during an upgrade. Returns the state of the connection and a
+    /// duplicate of the UDP socket it runs on, which the other process passes to
+    /// [`SrtSocketBuilder::socket`] and [`SrtSocketBuilder::restore`]. Passing them on, e.g. over
+    /// a unix socket with `SCM_RIGHTS`, is up to the application.
+    ///
+    /// Data that was queued is handed over, data that was released but not read yet isn't. The
+    /// snapshot holds the passphrase and the stream keys of an encrypted connection.
+    ///
+    /// The socket of a listener is shared by all of its connections, and the listener keeps
+    /// reading from it, so only connections with a socket of their own can be detached cleanly.
+    pub async fn detach(mut self) -> io::Result<(ConnectionSnapshot, net::UdpSocket)> {
+        let (sender, receiver) = oneshot::channel();
+        self.send_command(factory::Command::Detach(sender)).await?;
+        receiver
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::NotConnected, e))?
+    }
+
+    /// Measure the round trip to the peer with an echo request, which it answers right away on
+    /// the control channel, regardless of the data flow. The answer also tells the offset between
+    /// the clocks of the two ends and how many requests the peer received, see
+    /// [`EchoStatistics`](crate::EchoStatistics) to sum up a series of them.
+    ///
+    /// Doesn't resolve if the request or the answer gets lost, so use it with a timeout.
+    pub async fn ping(&mut self) -> io::Result<EchoSample> {
+        let (sender, receiver) = oneshot::channel();
+        self.send_command(factory::Command::Ping(sender)).await?;
+        receiver
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::NotConnected, e))
+    }
+
+    /// The peer's clock minus this one, as the receiver estimates it from the time stamps of the
+    /// peer's packets and corrects it for drift. It includes the delay on the way here: the
+    /// peer's time stamp `t` arrived around `t - offset` on this clock. That lines up the feeds
+    /// of several peers, e.g. cameras, without assuming their clocks are synchronized.
+    ///
+    /// `None` until the peer sent a packet, see [`DuplexConnection::peer_clock_offset`].
+    pub async fn peer_clock_offset(&mut self) -> io::Result<Option<TimeSpan>> {
+        let (sender, receiver) = oneshot::channel();
+        self.send_command(factory::Command::PeerClockOffset(sender))
+            .await?;
+        receiver
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::NotConnected, e))
+    }
+
+    async fn send_command(&mut self, command: factory::Command) -> io::Result<()> {
+        self.command_sender
+            .send(command)
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::NotConnected, e))
+    }
+
+    /// `SinkExt::flush`, which would need to be told the item type, as the socket is a `Sink` of
+    /// both `(Instant, Bytes)` and `SendMessage`
+    pub async fn flush(&mut self) -> Result<(), io::Error> {
+        futures::SinkExt::<SendMessage>::flush(self).await
+    }
+
+    /// `SinkExt::close`, which would need to be told the item type, as the socket is a `Sink` of
+    /// both `(Instant, Bytes)` and `SendMessage`
+    pub async fn close(&mut self) -> Result<(), io::Error> {
+        futures::SinkExt::<SendMessage>::close(self).await
     }
 
     pub async fn close_and_finish(&mut self) -> Result<(), io::Error> {
@@ -109,7 +511,11 @@ impl SrtSocket {
         &mut self,
     ) -> (
         Pin<&mut Peekable<impl Stream<Item = (Instant, Bytes)> + Unpin>>,
-        Pin<&mut (impl Sink<(Instant, Bytes), Error = impl Debug> + Unpin)>,
+        Pin<
+            &mut (impl Sink<SendMessage, Error = impl Debug>
+                      + Sink<(Instant, Bytes), Error = impl Debug>
+                      + Unpin),
+        >,
     ) {
         (
             Pin::new(&mut self.output_data_receiver),
@@ -123,9 +529,79 @@ impl SrtSocket {
         &self.settings
     }
 
+    /// What was negotiated with the peer during the handshake, for logging
+    pub fn info(&self) -> ConnectionInfo {
+        self.settings.info()
+    }
+
     pub fn statistics(&mut self) -> &mut (impl Stream<Item = SocketStatistics> + Clone) {
         &mut self.statistics_receiver
     }
+
+    /// The statistics since the previous call, or since the socket connected for the first one,
+    /// see [`SocketStatistics::delta`]. Like [`statistics`](Self::statistics) it goes by the
+    /// latest snapshot the socket published.
+    pub fn stats_delta(&mut self) -> SocketStatistics {
+        let current = self.statistics_receiver.borrow().clone();
+        let delta = current.delta(&self.sampled_statistics);
+        self.sampled_statistics = current;
+        delta
+    }
+
+    /// A score from 0 to 100 of the link quality since the previous call, or since the socket
+    /// connected for the first one, for showing as "one number" in a UI. Goes by the latest
+    /// snapshot the socket published, scored with [`DefaultQuality`] unless
+    /// [`set_quality_formula`](Self::set_quality_formula) was called.
+    pub fn quality(&mut self) -> u8 {
+        let current = self.statistics_receiver.borrow().clone();
+        self.quality.score(&current)
+    }
+
+    /// Score the [`quality`](Self::quality) with `formula` from now on
+    pub fn set_quality_formula(&mut self, formula: impl QualityFormula) {
+        self.quality.set_formula(formula);
+    }
+
+    /// The most recently reported key material state of the sending direction (`SRTO_SNDKMSTATE`).
+    ///
+    /// Changes are also published immediately on the [`statistics`](Self::statistics) stream.
+    pub fn sender_key_material_state(&self) -> KeyMaterialState {
+        self.statistics_receiver.borrow().tx_km_state
+    }
+
+    /// The most recently reported key material state of the receiving direction (`SRTO_RCVKMSTATE`).
+    ///
+    /// A connected socket reporting [`KeyMaterialState::NoSecret`] or [`KeyMaterialState::BadSecret`]
+    /// is receiving content it cannot decrypt.
+    pub fn receiver_key_material_state(&self) -> KeyMaterialState {
+        self.statistics_receiver.borrow().rx_km_state
+    }
+}
+
+impl Drop for SrtSocket {
+    fn drop(&mut self) {
+        // a closed socket is flushing what was sent, and it's not our business to stop it
+        if let Some(sender) = self.abort_sender.take() {
+            if !self.input_data_sender.is_closed() {
+                let _ = sender.send(());
+            }
+        }
+    }
+}
+
+/// What became of a message queued with [`SrtSocket::send_tracked`]
+#[derive(Debug)]
+pub struct PendingDelivery(oneshot::Receiver<Delivery>);
+
+impl Future for PendingDelivery {
+    type Output = io::Result<Delivery>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        Poll::Ready(
+            ready!(Pin::new(&mut self.0).poll(cx))
+                .map_err(|e| io::Error::new(io::ErrorKind::NotConnected, e)),
+        )
+    }
 }
 
 impl Stream for SrtSocket {
@@ -136,47 +612,74 @@ impl Stream for SrtSocket {
     }
 }
 
-impl Sink<(Instant, Bytes)> for SrtSocket {
+impl Sink<SendMessage> for SrtSocket {
     type Error = io::Error;
 
     fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
-        Poll::Ready(Ok(ready!(
-            Pin::new(&mut self.input_data_sender).poll_ready(cx)
-        )
+        self.check_send_buffer()?;
+        Poll::Ready(Ok(ready!(Sink::<SendMessage>::poll_ready(
+            Pin::new(&mut self.input_data_sender),
+            cx
+        ))
         .map_err(|e| io::Error::new(io::ErrorKind::NotConnected, e))?))
     }
-    fn start_send(mut self: Pin<&mut Self>, item: (Instant, Bytes)) -> Result<(), Self::Error> {
-        self.input_data_sender
-            .start_send(item)
+    fn start_send(mut self: Pin<&mut Self>, message: SendMessage) -> Result<(), Self::Error> {
+        self.check_payload_size(&message)?;
+        Pin::new(&mut self.input_data_sender)
+            .start_send(message)
             .map_err(|e| io::Error::new(io::ErrorKind::NotConnected, e))
     }
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.input_data_sender)
-            .poll_flush(cx)
+        Sink::<SendMessage>::poll_flush(Pin::new(&mut self.input_data_sender), cx)
             .map_err(|e| io::Error::new(io::ErrorKind::NotConnected, e))
     }
     fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.input_data_sender)
-            .poll_close(cx)
+        Sink::<SendMessage>::poll_close(Pin::new(&mut self.input_data_sender), cx)
             .map_err(|e| io::Error::new(io::ErrorKind::NotConnected, e))
     }
 }
 
+// the plain messages of before per message options, with the defaults of the socket
+impl Sink<(Instant, Bytes)> for SrtSocket {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Sink::<SendMessage>::poll_ready(self, cx)
+    }
+    fn start_send(
+        self: Pin<&mut Self>,
+        (srctime, data): (Instant, Bytes),
+    ) -> Result<(), Self::Error> {
+        self.start_send(SendMessage::new(srctime, data))
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Sink::<SendMessage>::poll_flush(self, cx)
+    }
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Sink::<SendMessage>::poll_close(self, cx)
+    }
+}
+
 impl AsyncRead for SrtSocket {
     fn poll_read(
-        self: Pin<&mut Self>,
+        mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &mut tokio::io::ReadBuf<'_>,
     ) -> Poll<io::Result<()>> {
-        match self.poll_next(cx) {
-            Poll::Ready(Some(Ok((_instant, data)))) => {
-                buf.put_slice(&data);
-                Poll::Ready(Ok(()))
+        // what doesn't fit in `buf` is left for the next read
+        let remaining = buf.remaining();
+        let receiver = Pin::new(&mut self.output_data_receiver);
+        if let Some((_instant, data)) = ready!(receiver.poll_peek_mut(cx)) {
+            if data.len() > remaining {
+                buf.put_slice(&data.split_to(remaining));
+                return Poll::Ready(Ok(()));
             }
-            Poll::Ready(Some(Err(e))) => Poll::Ready(Err(e)),
-            Poll::Ready(None) => Poll::Ready(Ok(())),
-            Poll::Pending => Poll::Pending,
         }
+        let receiver = Pin::new(&mut self.output_data_receiver);
+        if let Some((_instant, data)) = ready!(receiver.poll_next(cx)) {
+            buf.put_slice(&data);
+        }
+        Poll::Ready(Ok(()))
     }
 }
 
@@ -186,13 +689,19 @@ impl AsyncWrite for SrtSocket {
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
+        // a message that doesn't fit in a packet is refused, so write the part of it that does
+        let buf = match self.settings.max_message_size() {
+            Some(max_size) => &buf[..buf.len().min(max_size)],
+            None => buf,
+        };
         let mut write_buf = BytesMut::new();
         write_buf.put_slice(&buf);
-        match self.as_mut().poll_ready(cx) {
+        match Sink::<SendMessage>::poll_ready(self.as_mut(), cx) {
             Poll::Ready(Ok(())) => {
+                let now = self.clock.now();
                 match self
                     .as_mut()
-                    .start_send((Instant::now(), write_buf.freeze()))
+                    .start_send(SendMessage::new(now, write_buf.freeze()))
                 {
                     Ok(_) => Poll::Ready(Ok(buf.len())),
                     Err(e) => Poll::Ready(Err(e)),
@@ -204,10 +713,10 @@ impl AsyncWrite for SrtSocket {
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        futures::Sink::poll_flush(self, cx)
+        Sink::<SendMessage>::poll_flush(self, cx)
     }
 
     fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        futures::Sink::poll_close(self, cx)
+        Sink::<SendMessage>::poll_close(self, cx)
     }
 }
@@ -1,7 +1,4 @@
-use std::{
-    io,
-    time::{Duration, Instant},
-};
+use std::{io, time::Duration};
 
 use futures::{prelude::*, select};
 use log::{debug, warn};
@@ -10,42 +7,67 @@ use tokio::time::interval;
 use srt_protocol::{
     connection::Connection,
     options::*,
-    protocol::pending_connection::{rendezvous::Rendezvous, ConnectionResult},
+    protocol::pending_connection::{rendezvous::Rendezvous, ConnectionResult, HandshakeTelemetry},
     settings::*,
 };
 
-use crate::net::{lookup_remote_host, PacketSocket};
+use crate::{
+    clock::SharedClock,
+    net::{lookup_remote_host, PacketSocket, Resolver},
+};
+
+use super::key_provider::KeyCache;
 
 pub async fn bind_with(
     mut socket: PacketSocket,
     options: Valid<RendezvousOptions>,
+    resolver: &dyn Resolver,
+    keys: Option<&KeyCache>,
+    mut telemetry: HandshakeTelemetry,
+    clock: &SharedClock,
 ) -> Result<(PacketSocket, Connection), io::Error> {
     let local_addr = options.socket.connect.local;
-    let remote_public = lookup_remote_host(&options.remote).await?;
-    let starting_seqno = rand::random();
+    let remote_public = lookup_remote_host(resolver, &options.remote).await?;
     let init_settings: ConnInitSettings = options.socket.clone().into();
+    let starting_seqno = init_settings.init_seq_num.unwrap_or_else(rand::random);
     let socket_id = init_settings.local_sockid;
 
     let mut tick_interval = interval(Duration::from_millis(100));
     let mut rendezvous = Rendezvous::new(local_addr, remote_public, init_settings, starting_seqno);
     loop {
         let result = select! {
-            now = tick_interval.tick().fuse() => rendezvous.handle_tick(now.into()),
-            packet = socket.receive().fuse() => rendezvous.handle_packet(packet, Instant::now()),
+            _ = tick_interval.tick().fuse() => rendezvous.handle_tick(clock.now()),
+            packet = socket.receive().fuse() => {
+                if let Ok((packet, from)) = &packet {
+                    telemetry.received(clock.now(), packet, *from);
+                }
+                if let Some(keys) = keys {
+                    keys.prefetch(&packet).await;
+                }
+                rendezvous.handle_packet(packet, clock.now())
+            }
         };
 
         debug!("{:?}:rendezvous - {:?}", socket_id, result);
         use ConnectionResult::*;
         match result {
             SendPacket(packet) => {
+                telemetry.sent(clock.now(), &packet.0, packet.1);
                 let _ = socket.send(packet).await?;
             }
             NotHandled(e) => {
                 warn!("rendezvous {:?} error: {}", socket_id, e);
             }
-            Reject(_, _) => todo!(),
+            Reject(rp, rr) => {
+                if let Some(packet) = rp {
+                    telemetry.sent(clock.now(), &packet.0, packet.1);
+                    let _ = socket.send(packet).await?;
+                }
+                return Err(io::Error::new(io::ErrorKind::ConnectionRefused, rr));
+            }
             Connected(p, connection) => {
                 if let Some(packet) = p {
+                    telemetry.sent(clock.now(), &packet.0, packet.1);
                     let _ = socket.send(packet).await?;
                 }
                 return Ok((socket, connection));
@@ -1,7 +1,7 @@
 use std::{
     convert::{TryFrom, TryInto},
     io,
-    time::Instant,
+    time::{Duration, Instant, SystemTime},
 };
 
 use assert_matches::assert_matches;
@@ -10,8 +10,10 @@ use futures::{future::try_join_all, stream, SinkExt, StreamExt};
 use log::info;
 
 use srt_protocol::{
-    access::*, packet::CoreRejectReason, protocol::pending_connection::ConnectionReject,
-    settings::KeySettings,
+    access::*,
+    packet::CoreRejectReason,
+    protocol::pending_connection::ConnectionReject,
+    settings::{KeySettings, KeySource},
 };
 
 use srt_tokio::{
@@ -133,7 +135,7 @@ async fn set_password() {
             if let Ok(mut sender) = request
                 .accept(Some(KeySettings {
                     key_size: KeySize::AES128,
-                    passphrase,
+                    key_source: KeySource::Passphrase(passphrase),
                 }))
                 .await
             {
@@ -186,3 +188,58 @@ async fn set_password() {
     server.close().await;
     listener.await.unwrap();
 }
+
+#[tokio::test]
+async fn token_authentication() {
+    let _ = pretty_env_logger::try_init();
+
+    let authenticator = TokenAuthenticator::new("ingest secret");
+    let (mut server, incoming) = SrtListener::builder().bind(2002).await.unwrap();
+    let validator = authenticator.clone();
+    let mut accepted = incoming.accept_with(move |request| {
+        let stream_id = request.stream_id().map(|id| id.as_str());
+        validator.validate(stream_id, SystemTime::now())?;
+        Ok(ConnectionSettingsOverride::default())
+    });
+    let listener = tokio::spawn(async move {
+        while let Some(socket) = accepted.next().await {
+            socket.unwrap().close().await.unwrap();
+        }
+    });
+
+    let acl = || {
+        AccessControlList(vec![StandardAccessControlEntry::ResourceName(
+            "live".into(),
+        )
+        .into()])
+    };
+    let expires = SystemTime::now() + Duration::from_secs(60);
+    let token = authenticator.sign(acl(), expires);
+    SrtSocket::builder()
+        .call("127.0.0.1:2002", Some(token.as_str()))
+        .await
+        .unwrap()
+        .close()
+        .await
+        .unwrap();
+
+    let expired = authenticator.sign(acl(), SystemTime::now() - Duration::from_secs(60));
+    let forged = TokenAuthenticator::new("guessed secret").sign(acl(), expires);
+    for stream_id in [expired.as_str(), forged.as_str(), "#!::r=live"] {
+        let err = SrtSocket::builder()
+            .call("127.0.0.1:2002", Some(stream_id))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        assert_eq!(
+            err.get_ref().map(|e| e.downcast_ref::<ConnectionReject>()),
+            Some(Some(&ConnectionReject::Rejected(
+                CoreRejectReason::BadSecret.into()
+            ))),
+            "{stream_id}"
+        );
+    }
+
+    server.close().await;
+    listener.await.unwrap();
+}
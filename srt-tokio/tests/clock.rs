@@ -0,0 +1,48 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{SinkExt, TryStreamExt};
+use srt_tokio::{Clock, SrtSocket};
+use tokio::time::timeout;
+
+// an hour ahead of the system clock, like one disciplined to another timebase
+#[derive(Clone, Copy)]
+struct Ahead(Duration);
+
+impl Clock for Ahead {
+    fn now(&self) -> Instant {
+        Instant::now() + self.0
+    }
+}
+
+#[tokio::test]
+async fn external_clock() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let clock = Ahead(Duration::from_secs(3600));
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder().listen_on(":5601"),
+        SrtSocket::builder()
+            .clock(clock)
+            .call("127.0.0.1:5601", None),
+    )?;
+
+    // timestamped on the caller's clock, it's neither an hour early nor an hour late
+    caller.send((clock.now(), Bytes::from("on time"))).await?;
+    let (released, data) = timeout(Duration::from_secs(1), listener.try_next())
+        .await??
+        .expect("connection closed");
+    assert_eq!(data, "on time");
+    let now = Instant::now();
+    assert!(
+        released <= now && now - released < Duration::from_secs(1),
+        "released at {:?} from now",
+        now.checked_duration_since(released)
+    );
+
+    caller.close().await?;
+    Ok(())
+}
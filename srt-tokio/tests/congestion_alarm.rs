@@ -0,0 +1,71 @@
+use std::{
+    io,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{SinkExt, TryStreamExt};
+use srt_tokio::{statistics::CongestionThresholds, Impairment, SrtSocket};
+use tokio::time::sleep;
+
+async fn send(socket: &mut SrtSocket, count: usize) -> io::Result<()> {
+    for _ in 0..count {
+        socket.send((Instant::now(), Bytes::from("data"))).await?;
+        sleep(Duration::from_millis(2)).await;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn congestion_alarm() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder()
+            .latency(Duration::from_millis(500))
+            .listen_on(":5743"),
+        SrtSocket::builder()
+            .latency(Duration::from_millis(500))
+            .set(|options| options.session.statistics_interval = Duration::from_millis(200))
+            .call("127.0.0.1:5743", None),
+    )?;
+
+    let alarms = Arc::new(Mutex::new(Vec::new()));
+    caller
+        .set_congestion_alarm(
+            CongestionThresholds {
+                window: Duration::from_secs(1),
+                retransmit_rate: 0.05,
+                nak_rate: f64::INFINITY,
+            },
+            {
+                let alarms = alarms.clone();
+                move |event| alarms.lock().unwrap().push(event.congested)
+            },
+        )
+        .await?;
+
+    let receive = async {
+        while listener.try_next().await?.is_some() {}
+        io::Result::Ok(())
+    };
+    let sending = async {
+        send(&mut caller, 200).await?;
+        // a lossy path, the losses are retransmitted
+        caller
+            .set_impairment(Impairment {
+                drop_rate: 0.2,
+                ..Impairment::default()
+            })
+            .await?;
+        send(&mut caller, 300).await?;
+        caller.set_impairment(Impairment::default()).await?;
+        send(&mut caller, 500).await?;
+        caller.close().await
+    };
+    futures::try_join!(receive, sending)?;
+
+    assert_eq!(*alarms.lock().unwrap(), [true, false]);
+    Ok(())
+}
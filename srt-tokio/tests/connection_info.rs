@@ -0,0 +1,32 @@
+use std::{io, time::Duration};
+
+use futures::try_join;
+use srt_protocol::options::KeySize;
+use srt_tokio::SrtSocket;
+
+#[tokio::test]
+async fn negotiated_parameters() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let listener = SrtSocket::builder()
+        .latency(Duration::from_millis(120))
+        .encryption(32, "password123")
+        .listen_on(":5765");
+    let caller = SrtSocket::builder()
+        .latency(Duration::from_millis(300))
+        .encryption(32, "password123")
+        .call("127.0.0.1:5765", Some("live/info"));
+    let (listener, caller) = try_join!(listener, caller)?;
+
+    let (listener, caller) = (listener.info(), caller.info());
+    for info in [&listener, &caller] {
+        assert_eq!(info.send_latency, Duration::from_millis(300));
+        assert_eq!(info.recv_latency, Duration::from_millis(300));
+        assert_eq!(info.key_size, KeySize::AES256);
+        assert_eq!(info.stream_id.as_deref(), Some("live/info"));
+    }
+    assert_eq!(listener.local_sockid, caller.remote_sockid);
+    assert_eq!(listener.remote_sockid, caller.local_sockid);
+    assert_eq!(caller.remote.port(), 5765);
+    Ok(())
+}
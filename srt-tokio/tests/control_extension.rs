@@ -0,0 +1,53 @@
+use std::{io, time::Duration};
+
+use bytes::Bytes;
+use futures::{channel::mpsc, StreamExt};
+use srt_tokio::SrtSocket;
+use tokio::time::sleep;
+
+const EXTENSION_TYPE: u16 = 1000;
+
+#[tokio::test]
+async fn control_extension() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder().listen_on(":5401"),
+        SrtSocket::builder().call("127.0.0.1:5401", None),
+    )?;
+
+    let (received_sender, mut received) = mpsc::unbounded();
+    listener
+        .set_extension_handler(move |_, ty, payload| {
+            let _ = received_sender.unbounded_send((ty, payload));
+        })
+        .await?;
+
+    // extension types used by SRT itself are rejected up front
+    let reserved = caller.send_control_extension(3, Bytes::new()).await;
+    assert_eq!(
+        reserved.unwrap_err().kind(),
+        io::ErrorKind::InvalidInput,
+        "reserved extension type was accepted"
+    );
+
+    // the handler may not be installed yet when the first one arrives
+    // payloads are padded to 32-bit words
+    let payload = Bytes::from_static(b"custom metadata!");
+    let send = async {
+        loop {
+            let sent = caller.send_control_extension(EXTENSION_TYPE, payload.clone());
+            if let Err(e) = sent.await {
+                return e;
+            }
+            sleep(Duration::from_millis(50)).await;
+        }
+    };
+    let result = tokio::select! {
+        result = received.next() => result,
+        e = send => return Err(e),
+    };
+    assert_eq!(result, Some((EXTENSION_TYPE, payload)));
+
+    Ok(())
+}
@@ -1,5 +1,6 @@
 use std::time::{Duration, Instant};
 
+use srt_protocol::settings::KeyMaterialState;
 use srt_tokio::SrtSocket;
 
 use bytes::Bytes;
@@ -33,6 +34,11 @@ async fn test_crypto(size: u16) {
     let (_, by) = recvr.try_next().await.unwrap().unwrap();
     info!("Got data");
     assert_eq!(&by[..], b"Hello");
+    assert_eq!(recvr.sender_key_material_state(), KeyMaterialState::Secured);
+    assert_eq!(
+        recvr.receiver_key_material_state(),
+        KeyMaterialState::Secured
+    );
     recvr.close().await.unwrap();
     info!("Receiver closed");
     t.await.unwrap();
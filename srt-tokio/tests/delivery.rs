@@ -0,0 +1,74 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{SinkExt, TryStreamExt};
+use srt_tokio::{Delivery, SendMessage, SrtSocket};
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn tracked_delivery() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder().listen_on(":5701"),
+        SrtSocket::builder().call("127.0.0.1:5701", None),
+    )?;
+
+    caller
+        .send((Instant::now(), Bytes::from("untracked")))
+        .await?;
+    let delivery = caller
+        .send_tracked(Instant::now(), Bytes::from("tracked"))
+        .await?;
+    assert_eq!(
+        timeout(Duration::from_secs(1), delivery).await??,
+        Delivery::Acknowledged
+    );
+
+    for expected in ["untracked", "tracked"] {
+        let (_, data) = timeout(Duration::from_secs(1), listener.try_next())
+            .await??
+            .expect("connection closed");
+        assert_eq!(data, expected);
+    }
+
+    caller.close().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn expired_message() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder().listen_on(":5704"),
+        SrtSocket::builder().call("127.0.0.1:5704", None),
+    )?;
+
+    // the time to live ran out before the first packet went out
+    let stale = SendMessage::new(
+        Instant::now() - Duration::from_millis(10),
+        Bytes::from("stale"),
+    )
+    .with_ttl(Duration::ZERO);
+    let delivery = caller.send_tracked_message(stale).await?;
+    assert_eq!(
+        timeout(Duration::from_secs(1), delivery).await??,
+        Delivery::Dropped
+    );
+
+    let fresh = SendMessage::new(Instant::now(), Bytes::from("fresh"))
+        .with_ttl(Duration::from_secs(1))
+        .with_inorder(true);
+    caller.send(fresh).await?;
+    let (_, data) = timeout(Duration::from_secs(1), listener.try_next())
+        .await??
+        .expect("connection closed");
+    assert_eq!(data, "fresh");
+
+    caller.close().await?;
+    Ok(())
+}
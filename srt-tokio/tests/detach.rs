@@ -0,0 +1,56 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use bytes::{Bytes, BytesMut};
+use futures::{SinkExt, TryStreamExt};
+use srt_protocol::connection::ConnectionSnapshot;
+use srt_tokio::SrtSocket;
+use tokio::{net::UdpSocket, time::sleep};
+
+#[tokio::test]
+async fn detach_and_restore() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder()
+            .encryption(16, "password123")
+            .listen_on(":5710"),
+        SrtSocket::builder()
+            .encryption(16, "password123")
+            .call("127.0.0.1:5710", None),
+    )?;
+
+    for i in 0..5 {
+        caller
+            .send((Instant::now(), Bytes::from(format!("{i}"))))
+            .await?;
+    }
+    let (snapshot, socket) = caller.detach().await?;
+
+    // the hand-over to the new process
+    let mut serialized = BytesMut::new();
+    snapshot.serialize(Instant::now(), &mut serialized);
+    sleep(Duration::from_millis(50)).await;
+    let snapshot = ConnectionSnapshot::parse(Instant::now(), &mut serialized.freeze()).unwrap();
+    socket.set_nonblocking(true)?;
+
+    let mut caller = SrtSocket::builder()
+        .socket(UdpSocket::from_std(socket)?)
+        .restore(snapshot)?;
+    for i in 5..10 {
+        caller
+            .send((Instant::now(), Bytes::from(format!("{i}"))))
+            .await?;
+    }
+
+    for i in 0..10 {
+        let (_, data) = listener.try_next().await?.unwrap();
+        assert_eq!(data, format!("{i}"));
+    }
+
+    caller.close().await?;
+    assert_eq!(listener.try_next().await?, None);
+    Ok(())
+}
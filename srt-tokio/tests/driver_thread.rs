@@ -0,0 +1,50 @@
+use std::{
+    io, thread,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{FutureExt, SinkExt, TryStreamExt};
+use srt_tokio::{DriverThread, SrtSocket};
+
+#[tokio::test]
+async fn runs_while_the_runtime_is_blocked() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let driver = DriverThread {
+        core: Some(0),
+        ..Default::default()
+    };
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder()
+            .latency(Duration::from_millis(20))
+            .driver_thread(driver.clone())
+            .listen_on(":5772"),
+        SrtSocket::builder()
+            .latency(Duration::from_millis(20))
+            .driver_thread(driver)
+            .call("127.0.0.1:5772", None),
+    )?;
+
+    for i in 0..10u8 {
+        caller
+            .send((Instant::now(), Bytes::from(vec![i; 100])))
+            .await?;
+    }
+
+    // nothing else runs on this runtime meanwhile, the threads send, acknowledge and release
+    // the messages on their own
+    thread::sleep(Duration::from_millis(500));
+    for i in 0..10u8 {
+        let (_, data) = listener
+            .try_next()
+            .now_or_never()
+            .expect("released while the runtime was blocked")?
+            .unwrap();
+        assert_eq!(data, vec![i; 100]);
+    }
+
+    caller.close().await?;
+    assert_eq!(listener.try_next().await?, None);
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt, TryStreamExt};
+use srt_tokio::{options::*, SrtSocket};
+use tokio::{
+    net::UdpSocket,
+    time::{sleep, timeout},
+};
+
+// well below the default peer idle timeout of 5s
+const PROMPTLY: Duration = Duration::from_secs(1);
+
+#[tokio::test]
+async fn drop_sends_shutdown() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder().listen_on(":5501"),
+        SrtSocket::builder()
+            .bandwidth(LiveBandwidthMode::Max(DataRate(100_000)))
+            .call("127.0.0.1:5501", None),
+    )?;
+
+    // closing would send all of this first, dropping doesn't wait for it
+    for _ in 0..100 {
+        caller
+            .send((Instant::now(), Bytes::from(vec![0; 1316])))
+            .await?;
+    }
+    drop(caller);
+
+    // whatever made it across is still delivered
+    let end = timeout(PROMPTLY, async {
+        while listener.try_next().await?.is_some() {}
+        Ok::<_, io::Error>(())
+    })
+    .await;
+    assert!(
+        matches!(end, Ok(Ok(()))),
+        "peer didn't see the dropped socket close: {end:?}"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn drop_releases_port() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (_listener, caller) = futures::try_join!(
+        SrtSocket::builder().listen_on(":5502"),
+        SrtSocket::builder()
+            .local_port(5503)
+            .call("127.0.0.1:5502", None),
+    )?;
+
+    drop(caller);
+
+    // the socket's task exits on its own time, give it a moment
+    let start = Instant::now();
+    while let Err(e) = UdpSocket::bind("0.0.0.0:5503").await {
+        assert!(start.elapsed() < PROMPTLY, "port is still bound: {e}");
+        sleep(Duration::from_millis(10)).await;
+    }
+    Ok(())
+}
+
+// receiving with a timeout cancels next() over and over, that must not lose anything
+#[tokio::test]
+async fn cancelled_receive() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder().listen_on(":5504"),
+        SrtSocket::builder().call("127.0.0.1:5504", None),
+    )?;
+
+    let send = async {
+        for i in 0..20 {
+            caller
+                .send((Instant::now(), Bytes::from(i.to_string())))
+                .await?;
+            sleep(Duration::from_millis(20)).await;
+        }
+        caller.close().await
+    };
+    let receive = async {
+        let mut received = vec![];
+        loop {
+            match timeout(Duration::from_millis(3), listener.next()).await {
+                Ok(Some(item)) => received.push(item?.1),
+                Ok(None) => break,
+                Err(_) => continue,
+            }
+        }
+        let expected: Vec<_> = (0..20).map(|i| Bytes::from(i.to_string())).collect();
+        assert_eq!(received, expected);
+        Ok(())
+    };
+    futures::try_join!(send, receive)?;
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt, TryStreamExt};
+use srt_tokio::SrtSocket;
+
+async fn connect(port: u16, listener_encrypts_control: bool) -> io::Result<(SrtSocket, SrtSocket)> {
+    let address = format!("127.0.0.1:{port}");
+    futures::try_join!(
+        SrtSocket::builder()
+            .encryption(16, "password123")
+            .set(|options| options.encryption.encrypt_control = listener_encrypts_control)
+            .listen_on(port),
+        SrtSocket::builder()
+            .encryption(16, "password123")
+            .set(|options| options.encryption.encrypt_control = true)
+            .set(|options| options.session.statistics_interval = Duration::from_millis(200))
+            .call(address.as_str(), None),
+    )
+}
+
+#[tokio::test]
+async fn encrypted_control() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut listener, mut caller) = connect(5755, true).await?;
+    assert!(caller.settings().encrypt_control);
+    assert!(listener.settings().encrypt_control);
+
+    for i in 0..20 {
+        caller
+            .send((Instant::now(), Bytes::from(format!("hello {i}"))))
+            .await?;
+        let (_, data) = listener.try_next().await?.unwrap();
+        assert_eq!(data, format!("hello {i}"));
+    }
+
+    // the ACKs made it back through the encryption
+    let mut statistics = caller.statistics().clone();
+    tokio::time::timeout(Duration::from_secs(2), async {
+        while statistics.next().await.unwrap().rx_ack == 0 {}
+    })
+    .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn not_offered() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    // it takes both sides, like with libsrt, which doesn't know about it
+    let (mut listener, mut caller) = connect(5756, false).await?;
+    assert!(!caller.settings().encrypt_control);
+    assert!(!listener.settings().encrypt_control);
+
+    caller.send((Instant::now(), Bytes::from("hello"))).await?;
+    let (_, data) = listener.try_next().await?.unwrap();
+    assert_eq!(data, "hello");
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use std::{io, time::Instant};
+
+use bytes::Bytes;
+use futures::{join, SinkExt, TryStreamExt};
+use srt_tokio::{options::SocketOptions, SrtSocket};
+use tokio::io::AsyncReadExt;
+use tokio_util::codec::LengthDelimitedCodec;
+
+fn stream_mode(options: &mut SocketOptions) {
+    options.session.message_api = false
+}
+
+#[tokio::test]
+async fn length_delimited() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (listener, caller) = futures::try_join!(
+        SrtSocket::builder().set(stream_mode).listen_on(":5769"),
+        SrtSocket::builder()
+            .set(stream_mode)
+            .call("127.0.0.1:5769", None),
+    )?;
+    let mut listener = listener.framed(LengthDelimitedCodec::new())?;
+    let mut caller = caller.framed(LengthDelimitedCodec::new())?;
+
+    // smaller than a packet, several packets, and more than a read takes at once
+    let frames: Vec<Bytes> = [1, 100, 5_000, 20_000, 0, 7]
+        .iter()
+        .map(|&size| (0..size).map(|i: u32| i as u8).collect())
+        .collect();
+    let send = async {
+        for frame in &frames {
+            caller.send(frame.clone()).await?;
+        }
+        SinkExt::<Bytes>::close(&mut caller).await
+    };
+    let receive = async {
+        let mut received = vec![];
+        while let Some(frame) = listener.try_next().await? {
+            received.push(frame.freeze());
+        }
+        io::Result::Ok(received)
+    };
+    let (sent_result, received) = join!(send, receive);
+    sent_result?;
+    assert_eq!(received?, frames);
+    Ok(())
+}
+
+#[tokio::test]
+async fn needs_stream_mode() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (listener, mut caller) = futures::try_join!(
+        SrtSocket::builder().listen_on(":5770"),
+        SrtSocket::builder().call("127.0.0.1:5770", None),
+    )?;
+    assert_eq!(
+        listener
+            .framed(LengthDelimitedCodec::new())
+            .err()
+            .map(|e| e.kind()),
+        Some(io::ErrorKind::InvalidInput)
+    );
+    caller.close().await
+}
+
+#[tokio::test]
+async fn partial_reads() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder().listen_on(":5771"),
+        SrtSocket::builder().call("127.0.0.1:5771", None),
+    )?;
+    let message: Bytes = (0..1000u32).map(|i| i as u8).collect();
+    caller.send((Instant::now(), message.clone())).await?;
+    caller.close().await?;
+
+    // a message is read over as many reads as it takes, then the end of the stream
+    let mut received = vec![];
+    let mut buf = [0; 300];
+    loop {
+        let read = listener.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        assert!(read <= 300);
+        received.extend_from_slice(&buf[..read]);
+    }
+    assert_eq!(received, message);
+    Ok(())
+}
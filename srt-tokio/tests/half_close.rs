@@ -0,0 +1,55 @@
+use std::{io, time::Instant};
+
+use bytes::Bytes;
+use futures::{SinkExt, TryStreamExt};
+use srt_tokio::SrtSocket;
+use tokio::spawn;
+
+const REQUEST: [&str; 3] = ["request 1", "request 2", "request 3"];
+const RESPONSE: [&str; 2] = ["response 1", "response 2"];
+
+// the client finishes sending its request before reading the response, the server reads the
+// whole request before it starts to respond
+#[tokio::test]
+async fn request_response() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let server = spawn(async {
+        let mut socket = SrtSocket::builder()
+            .set(|options| options.session.half_close = true)
+            .listen_on(":5301")
+            .await?;
+
+        let mut request = vec![];
+        while let Some((_, payload)) = socket.try_next().await? {
+            request.push(payload);
+        }
+        assert_eq!(request, REQUEST.map(Bytes::from));
+
+        for response in RESPONSE {
+            socket.send((Instant::now(), Bytes::from(response))).await?;
+        }
+        socket.close().await?;
+
+        Ok::<_, io::Error>(())
+    });
+
+    let mut socket = SrtSocket::builder()
+        .set(|options| options.session.half_close = true)
+        .call("127.0.0.1:5301", None)
+        .await?;
+
+    for request in REQUEST {
+        socket.send((Instant::now(), Bytes::from(request))).await?;
+    }
+    // closing only finishes the request, the response can still be read
+    socket.close().await?;
+
+    let mut response = vec![];
+    while let Some((_, payload)) = socket.try_next().await? {
+        response.push(payload);
+    }
+    assert_eq!(response, RESPONSE.map(Bytes::from));
+
+    server.await.unwrap()
+}
@@ -0,0 +1,93 @@
+use std::{
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use srt_protocol::packet::ShakeType;
+use srt_tokio::{HandshakeDirection, HandshakeEvent, SrtSocket};
+
+use HandshakeDirection::*;
+
+fn collect() -> (
+    Arc<Mutex<Vec<HandshakeEvent>>>,
+    impl Fn(&HandshakeEvent) + Send + Sync + 'static,
+) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let hook = {
+        let events = events.clone();
+        move |event: &HandshakeEvent| events.lock().unwrap().push(*event)
+    };
+    (events, hook)
+}
+
+fn phases(events: &Mutex<Vec<HandshakeEvent>>) -> Vec<(HandshakeDirection, ShakeType, u32)> {
+    events
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|e| (e.direction, e.shake_type, e.attempt))
+        .collect()
+}
+
+#[tokio::test]
+async fn handshake_events() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (listener_events, listener_hook) = collect();
+    let (caller_events, caller_hook) = collect();
+    let (_listener, _caller) = futures::try_join!(
+        SrtSocket::builder()
+            .handshake_hook(listener_hook)
+            .listen_on(":5741"),
+        SrtSocket::builder()
+            .handshake_hook(caller_hook)
+            .call("127.0.0.1:5741", None),
+    )?;
+
+    assert_eq!(
+        phases(&caller_events),
+        [
+            (Sent, ShakeType::Induction, 1),
+            (Received, ShakeType::Induction, 1),
+            (Sent, ShakeType::Conclusion, 1),
+            (Received, ShakeType::Conclusion, 1),
+        ]
+    );
+    assert_eq!(
+        phases(&listener_events),
+        [
+            (Received, ShakeType::Induction, 1),
+            (Sent, ShakeType::Induction, 1),
+            (Received, ShakeType::Conclusion, 1),
+            (Sent, ShakeType::Conclusion, 1),
+        ]
+    );
+
+    // the caller echoes the cookie the listener handed out in its induction response
+    let caller_events = caller_events.lock().unwrap();
+    assert_eq!(caller_events[1].syn_cookie, caller_events[2].syn_cookie);
+    assert_eq!(caller_events[0].remote, "127.0.0.1:5741".parse().unwrap());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unanswered_inductions() {
+    let _ = pretty_env_logger::try_init();
+
+    let (events, hook) = collect();
+    let result = SrtSocket::builder()
+        .set(|options| options.connect.timeout = Duration::from_secs(1))
+        .handshake_hook(hook)
+        .call("127.0.0.1:5742", None)
+        .await;
+    assert!(result.is_err());
+
+    // nothing but retried inductions, so it's the induction that gets no answer
+    let phases = phases(&events);
+    assert!(phases.len() > 1, "{phases:?}");
+    for (i, phase) in phases.into_iter().enumerate() {
+        assert_eq!(phase, (Sent, ShakeType::Induction, i as u32 + 1));
+    }
+}
@@ -51,10 +51,8 @@ async fn high_bandwidth() -> Result<(), Error> {
                 expected: DataRate(RATE_MBPS * 1_000_000),
                 overhead: Percent(20),
             })
-            .set(|options| {
-                options.sender.buffer_size = buffer_size * 10;
-                options.connect.udp_send_buffer_size = ByteCount(5_000_000);
-            })
+            .udp_send_buffer_size(ByteCount(5_000_000))
+            .set(|options| options.sender.buffer_size = buffer_size * 10)
             .call("127.0.0.1:6654", None)
             .await?;
 
@@ -71,9 +69,9 @@ async fn high_bandwidth() -> Result<(), Error> {
 
     let recv_fut = async move {
         let mut sock = SrtSocket::builder()
+            .udp_recv_buffer_size(ByteCount(5_000_000))
             .set(|options| {
                 options.receiver.buffer_size = buffer_size * 2;
-                options.connect.udp_recv_buffer_size = ByteCount(5_000_000);
                 options.session.statistics_interval = Duration::from_secs(1);
             })
             .latency(latency)
@@ -0,0 +1,56 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{SinkExt, TryStreamExt};
+use srt_tokio::{Impairment, SrtSocket};
+use tokio::time::{sleep, timeout};
+
+#[tokio::test]
+async fn impairment() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder()
+            .latency(Duration::from_millis(500))
+            .listen_on(":5702"),
+        SrtSocket::builder()
+            .latency(Duration::from_millis(500))
+            .call("127.0.0.1:5702", None),
+    )?;
+
+    caller
+        .set_impairment(Impairment {
+            drop_rate: 0.1,
+            duplicate_rate: 0.1,
+            jitter: Duration::from_millis(5),
+        })
+        .await?;
+
+    // the losses are recovered and the duplicates and reordering are taken care of
+    let receive = async {
+        for expected in 0..200u32 {
+            let (_, data) = timeout(Duration::from_secs(2), listener.try_next())
+                .await??
+                .expect("connection closed");
+            assert_eq!(data, expected.to_string());
+        }
+        io::Result::Ok(())
+    };
+    let send = async {
+        for n in 0..200u32 {
+            caller
+                .send((Instant::now(), Bytes::from(n.to_string())))
+                .await?;
+            sleep(Duration::from_millis(2)).await;
+        }
+        caller.set_impairment(Impairment::default()).await?;
+        io::Result::Ok(())
+    };
+    futures::try_join!(receive, send)?;
+
+    caller.close().await?;
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{future::BoxFuture, SinkExt, TryStreamExt};
+use srt_protocol::{
+    options::PacketCount,
+    settings::{
+        EncryptionKey, KeyMaterialState, KeyProvider, KeyProviderError, KeySize, Passphrase, Salt,
+    },
+};
+use srt_tokio::{AsyncKeyProvider, SrtSocket};
+use tokio::time::sleep;
+
+// stands in for a KMS holding the secret, and takes a while to answer
+#[derive(Clone)]
+struct Kms {
+    secret: Passphrase,
+    requests: Arc<AtomicUsize>,
+}
+
+impl Kms {
+    fn new(secret: &str) -> Self {
+        Self {
+            secret: secret.into(),
+            requests: Arc::default(),
+        }
+    }
+
+    fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+}
+
+impl AsyncKeyProvider for Kms {
+    fn key_encrypting_key(
+        &self,
+        key_size: KeySize,
+        salt: Salt,
+    ) -> BoxFuture<'static, Result<EncryptionKey, KeyProviderError>> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        let secret = self.secret.clone();
+        Box::pin(async move {
+            sleep(Duration::from_millis(50)).await;
+            secret.key_encrypting_key(key_size, &salt)
+        })
+    }
+}
+
+async fn exchange(mut sender: SrtSocket, mut receiver: SrtSocket) -> io::Result<()> {
+    for i in 0..200 {
+        sender
+            .send((Instant::now(), Bytes::from(format!("{i}"))))
+            .await?;
+        sleep(Duration::from_millis(1)).await;
+    }
+    for i in 0..200 {
+        let (_, data) = receiver.try_next().await?.unwrap();
+        assert_eq!(data, format!("{i}"));
+    }
+    assert_eq!(
+        receiver.receiver_key_material_state(),
+        KeyMaterialState::Secured
+    );
+    sender.close().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn async_key_provider() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    // the keys are refreshed a few times while exchanging
+    let km_refresh = |options: &mut srt_protocol::options::SocketOptions| {
+        options.encryption.km_refresh.period = PacketCount(64);
+        options.encryption.km_refresh.pre_announcement_period = PacketCount(16);
+    };
+
+    // the caller fetches the key for its salt before connecting
+    let kms = Kms::new("kept in the kms");
+    let (listener, caller) = futures::try_join!(
+        SrtSocket::builder()
+            .encryption(16, "kept in the kms")
+            .set(km_refresh)
+            .listen_on(":5758"),
+        SrtSocket::builder()
+            .async_key_provider(16, kms.clone())
+            .set(km_refresh)
+            .call("127.0.0.1:5758", None),
+    )?;
+    exchange(caller, listener).await?;
+    assert_eq!(kms.requests(), 1);
+
+    // the listener fetches the key for the caller's salt when its keying material arrives
+    let kms = Kms::new("kept in the kms");
+    let (listener, caller) = futures::try_join!(
+        SrtSocket::builder()
+            .async_key_provider(16, kms.clone())
+            .set(km_refresh)
+            .listen_on(":5759"),
+        SrtSocket::builder()
+            .key_provider(16, Passphrase::from("kept in the kms"))
+            .set(km_refresh)
+            .call("127.0.0.1:5759", None),
+    )?;
+    exchange(listener, caller).await?;
+    assert_eq!(kms.requests(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn async_key_provider_wrong_key() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let listener = tokio::spawn(
+        SrtSocket::builder()
+            .encryption(16, "kept in the kms")
+            .listen_on(":5760"),
+    );
+    let caller = SrtSocket::builder()
+        .async_key_provider(16, Kms::new("not the one in the kms"))
+        .call("127.0.0.1:5760", None)
+        .await;
+    assert_eq!(
+        caller.err().map(|e| e.kind()),
+        Some(io::ErrorKind::ConnectionRefused)
+    );
+    listener.abort();
+    Ok(())
+}
@@ -3,8 +3,6 @@ use srt_tokio::SrtSocket;
 use std::time::Duration;
 use tokio::time::sleep;
 
-use futures::prelude::*;
-
 async fn test_latency_exchange(
     connector_send_latency: Duration,
     connector_rec_latency: Duration,
@@ -61,3 +59,33 @@ async fn latency_exchange() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn latency_clamped() -> Result<()> {
+    let from_secs = Duration::from_secs;
+
+    let _ = pretty_env_logger::try_init();
+
+    let connector = SrtSocket::builder()
+        .latency(from_secs(5))
+        .call("127.0.0.1:4201", None);
+
+    let listener = SrtSocket::builder()
+        .latency(from_secs(1))
+        .set(|options| options.session.max_latency = from_secs(2))
+        .listen_on(":4201");
+
+    let (c, l) = futures::join!(connector, listener);
+    let (mut c, mut l) = (c?, l?);
+
+    // the caller goes along with the latency the listener clamped its proposal to
+    for settings in [c.settings(), l.settings()] {
+        assert_eq!(settings.send_tsbpd_latency, from_secs(2));
+        assert_eq!(settings.recv_tsbpd_latency, from_secs(2));
+    }
+
+    c.close().await?;
+    l.close().await?;
+
+    Ok(())
+}
@@ -0,0 +1,248 @@
+// Interop checks against srt-live-transmit, run with
+//
+//     cargo test -p srt-tokio --features libsrt_interop --test libsrt_interop
+//
+// Every check is run against each binary in SRT_LIVE_TRANSMIT (a PATH style list, e.g.
+// `/opt/srt-1.4.4/bin/srt-live-transmit:/opt/srt-1.5.3/bin/srt-live-transmit`), so wire format
+// regressions show up against more than one libsrt version. Without it, srt-live-transmit is
+// looked up on PATH.
+#![cfg(feature = "libsrt_interop")]
+
+use std::{
+    env,
+    net::{SocketAddr, SocketAddrV4},
+    path::{Path, PathBuf},
+    process::{Child, Command},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Error};
+use bytes::Bytes;
+use futures::{future::try_join, stream, SinkExt, Stream, StreamExt};
+use log::info;
+use srt_tokio::{options::*, SrtSocket};
+use tokio::net::UdpSocket;
+use tokio_util::{codec::BytesCodec, udp::UdpFramed};
+
+const PACKETS: u32 = 1_000;
+const PASSPHRASE: &str = "password123";
+
+fn live_transmit_binaries() -> Vec<PathBuf> {
+    match env::var_os("SRT_LIVE_TRANSMIT") {
+        Some(paths) => env::split_paths(&paths).collect(),
+        None => vec!["srt-live-transmit".into()],
+    }
+}
+
+// kills the peer when a check fails, so the next one can reuse its ports
+struct LiveTransmit(Child);
+
+impl LiveTransmit {
+    fn spawn(binary: &Path, source: &str, target: &str) -> Result<Self, Error> {
+        let child = Command::new(binary)
+            .arg(source)
+            .arg(target)
+            .arg("-a:no") // don't reconnect
+            .arg("-loglevel:debug")
+            .spawn()
+            .with_context(|| format!("could not run {}", binary.display()))?;
+        Ok(LiveTransmit(child))
+    }
+}
+
+impl Drop for LiveTransmit {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn log_version(binary: &Path) -> Result<(), Error> {
+    let output = Command::new(binary)
+        .arg("-version")
+        .output()
+        .with_context(|| format!("could not run {}", binary.display()))?;
+    info!(
+        "testing against {}: {}{}",
+        binary.display(),
+        String::from_utf8_lossy(&output.stdout).trim(),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(())
+}
+
+fn counting_stream(packets: u32) -> impl Stream<Item = Bytes> {
+    tokio_stream::StreamExt::throttle(
+        stream::iter(0..packets).map(|i| Bytes::from(i.to_string())),
+        Duration::from_millis(1),
+    )
+    .boxed()
+}
+
+async fn receive_counting(
+    packets: u32,
+    mut stream: impl Stream<Item = Bytes> + Unpin,
+) -> Result<(), Error> {
+    let mut i = 0;
+    while let Some(payload) = stream.next().await {
+        assert_eq!(payload, i.to_string());
+        i += 1;
+        if i == packets {
+            return Ok(());
+        }
+    }
+    anyhow::bail!("stream ended after {i} of {packets} packets")
+}
+
+async fn udp_send(packets: u32, port: u16) -> Result<(), Error> {
+    let mut socket = UdpFramed::new(UdpSocket::bind("127.0.0.1:0").await?, BytesCodec::new());
+    let target = SocketAddr::V4(SocketAddrV4::new([127, 0, 0, 1].into(), port));
+    let mut stream = counting_stream(packets).map(|b| Ok((b, target)));
+    socket.send_all(&mut stream).await?;
+    Ok(())
+}
+
+async fn udp_receive(packets: u32, port: u16) -> Result<(), Error> {
+    let socket = UdpFramed::new(
+        UdpSocket::bind(("127.0.0.1", port)).await?,
+        BytesCodec::new(),
+    );
+    receive_counting(packets, socket.map(|r| r.unwrap().0.freeze())).await
+}
+
+async fn srt_send(mut socket: SrtSocket, packets: u32) -> Result<(), Error> {
+    let mut stream = counting_stream(packets).map(|b| Ok((Instant::now(), b)));
+    socket.send_all(&mut stream).await?;
+    socket.close().await?;
+    Ok(())
+}
+
+async fn srt_receive(socket: SrtSocket, packets: u32) -> Result<(), Error> {
+    receive_counting(packets, socket.map(|r| r.unwrap().1)).await
+}
+
+fn assert_latency(socket: &SrtSocket, latency: Duration) {
+    assert_eq!(socket.settings().send_tsbpd_latency, latency);
+    assert_eq!(socket.settings().recv_tsbpd_latency, latency);
+}
+
+// libsrt calls srt-rs, the larger latency wins
+#[tokio::test]
+async fn caller_handshake() -> Result<(), Error> {
+    let _ = pretty_env_logger::try_init();
+
+    for binary in live_transmit_binaries() {
+        log_version(&binary)?;
+        let _peer =
+            LiveTransmit::spawn(&binary, "udp://:3101", "srt://127.0.0.1:3102?latency=250")?;
+
+        let socket = SrtSocket::builder()
+            .latency(Duration::from_millis(120))
+            .listen_on(3102)
+            .await?;
+        assert_latency(&socket, Duration::from_millis(250));
+
+        try_join(srt_receive(socket, PACKETS), udp_send(PACKETS, 3101)).await?;
+    }
+    Ok(())
+}
+
+// srt-rs calls libsrt, the larger latency wins
+#[tokio::test]
+async fn listener_handshake() -> Result<(), Error> {
+    let _ = pretty_env_logger::try_init();
+
+    for binary in live_transmit_binaries() {
+        log_version(&binary)?;
+        let _peer = LiveTransmit::spawn(&binary, "srt://:3104?latency=80", "udp://127.0.0.1:3105")?;
+
+        let receive = udp_receive(PACKETS, 3105);
+        let send = async {
+            let socket = SrtSocket::builder()
+                .latency(Duration::from_millis(300))
+                .call("127.0.0.1:3104", None)
+                .await?;
+            assert_latency(&socket, Duration::from_millis(300));
+            srt_send(socket, PACKETS).await
+        };
+        try_join(send, receive).await?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn encryption() -> Result<(), Error> {
+    let _ = pretty_env_logger::try_init();
+
+    for binary in live_transmit_binaries() {
+        log_version(&binary)?;
+        for key_size in [16, 24, 32] {
+            let _peer = LiveTransmit::spawn(
+                &binary,
+                "udp://:3106",
+                &format!("srt://:3107?passphrase={PASSPHRASE}&pbkeylen={key_size}"),
+            )?;
+
+            let socket = SrtSocket::builder()
+                .encryption(key_size, PASSPHRASE)
+                .call("127.0.0.1:3107", None)
+                .await?;
+            assert!(socket.settings().cipher.is_some());
+
+            try_join(srt_receive(socket, PACKETS), udp_send(PACKETS, 3106)).await?;
+        }
+    }
+    Ok(())
+}
+
+// both sides refresh keys several times over the course of the transfer
+#[tokio::test]
+async fn key_material_refresh() -> Result<(), Error> {
+    let _ = pretty_env_logger::try_init();
+
+    for binary in live_transmit_binaries() {
+        log_version(&binary)?;
+
+        // libsrt refreshes, srt-rs receives
+        {
+            let _peer = LiveTransmit::spawn(
+                &binary,
+                "udp://:3108",
+                &format!(
+                    "srt://:3109?passphrase={PASSPHRASE}&pbkeylen=16&kmrefreshrate=128&kmpreannounce=60"
+                ),
+            )?;
+
+            let socket = SrtSocket::builder()
+                .encryption(16, PASSPHRASE)
+                .call("127.0.0.1:3109", None)
+                .await?;
+
+            try_join(srt_receive(socket, PACKETS), udp_send(PACKETS, 3108)).await?;
+        }
+
+        // srt-rs refreshes, libsrt receives
+        {
+            let _peer = LiveTransmit::spawn(
+                &binary,
+                &format!("srt://127.0.0.1:3110?passphrase={PASSPHRASE}&pbkeylen=16"),
+                "udp://127.0.0.1:3111",
+            )?;
+
+            let receive = udp_receive(PACKETS, 3111);
+            let send = async {
+                let socket = SrtSocket::builder()
+                    .encryption(16, PASSPHRASE)
+                    .set(|options| {
+                        options.encryption.km_refresh.period = PacketCount(128);
+                        options.encryption.km_refresh.pre_announcement_period = PacketCount(60);
+                    })
+                    .listen_on(3110)
+                    .await?;
+                srt_send(socket, PACKETS).await
+            };
+            try_join(send, receive).await?;
+        }
+    }
+    Ok(())
+}
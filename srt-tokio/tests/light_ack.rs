@@ -0,0 +1,51 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt, TryStreamExt};
+use srt_protocol::options::PacketCount;
+use srt_tokio::SrtSocket;
+use tokio::time::sleep;
+
+#[tokio::test]
+async fn ack_every_packet() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder()
+            .set(|options| {
+                options.receiver.light_ack_interval = PacketCount(1);
+                options.session.statistics_interval = Duration::from_millis(200);
+            })
+            .listen_on(":5744"),
+        SrtSocket::builder().call("127.0.0.1:5744", None),
+    )?;
+
+    let receive = async {
+        for _ in 0..100 {
+            listener.try_next().await?.expect("connection closed");
+        }
+        io::Result::Ok(())
+    };
+    let send = async {
+        for _ in 0..100 {
+            caller.send((Instant::now(), Bytes::from("data"))).await?;
+            sleep(Duration::from_millis(1)).await;
+        }
+        io::Result::Ok(())
+    };
+    futures::try_join!(receive, send)?;
+
+    // each packet is acknowledged as it arrives rather than with the next full ACK
+    while let Some(stats) = listener.statistics().next().await {
+        if stats.rx_unique_data >= 100 {
+            assert!(stats.tx_light_ack >= 90, "{}", stats.tx_light_ack);
+            break;
+        }
+    }
+
+    caller.close().await?;
+    Ok(())
+}
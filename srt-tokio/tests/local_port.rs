@@ -0,0 +1,124 @@
+use std::{
+    io,
+    net::SocketAddr,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{future::BoxFuture, join};
+use srt_tokio::{options::SocketOptions, Binder, DatagramSocket, SrtListener, SrtSocket};
+use tokio::{io::ReadBuf, net::UdpSocket};
+
+#[tokio::test]
+async fn caller_source_port() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (_listener, mut incoming) = SrtListener::builder().bind(5761).await?;
+    let accept = async {
+        let (request, _) = incoming.accept().await?;
+        let remote = request.remote();
+        request.accept(None).await?;
+        Ok::<_, io::Error>(remote)
+    };
+    let call = SrtSocket::builder()
+        .local_port(5762)
+        .call("127.0.0.1:5761", None);
+    let (remote, caller) = join!(accept, call);
+    assert_eq!(remote?.port(), 5762);
+    caller?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn local_port_in_use() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let _taken = UdpSocket::bind("0.0.0.0:5763").await?;
+    let error = SrtSocket::builder()
+        .local_port(5763)
+        .set(|options| options.connect.timeout = Duration::from_millis(500))
+        .call("127.0.0.1:5761", None)
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+    assert!(error.to_string().contains("5763"), "{error}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn rendezvous_socket_on_another_port() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    // the peer sends to 5764, where this side wouldn't be
+    let socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let error = SrtSocket::builder()
+        .socket(socket)
+        .rendezvous("127.0.0.1:5764")
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::AddrNotAvailable);
+    Ok(())
+}
+
+// a network stack that doesn't tell the address it's on
+struct Anonymous;
+
+struct AnonymousSocket(UdpSocket);
+
+impl Binder for Anonymous {
+    fn bind<'a>(
+        &'a self,
+        options: &'a SocketOptions,
+    ) -> BoxFuture<'a, io::Result<Arc<dyn DatagramSocket>>> {
+        Box::pin(async move {
+            let socket = UdpSocket::bind(options.connect.local).await?;
+            Ok(Arc::new(AnonymousSocket(socket)) as Arc<dyn DatagramSocket>)
+        })
+    }
+}
+
+impl DatagramSocket for AnonymousSocket {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        target: SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        self.0.poll_send_to(cx, buf, target)
+    }
+
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<SocketAddr>> {
+        self.0.poll_recv_from(cx, buf)
+    }
+}
+
+#[tokio::test]
+async fn binder_without_local_addr() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    // the port can't be checked, and isn't
+    let (_listener, mut incoming) = SrtListener::builder().bind(5781).await?;
+    let accept = async {
+        let (request, _) = incoming.accept().await?;
+        let remote = request.remote();
+        request.accept(None).await?;
+        Ok::<_, io::Error>(remote)
+    };
+    let call = SrtSocket::builder()
+        .binder(Anonymous)
+        .local_port(5782)
+        .call("127.0.0.1:5781", None);
+    let (remote, caller) = join!(accept, call);
+    assert_eq!(remote?.port(), 5782);
+    caller?;
+    Ok(())
+}
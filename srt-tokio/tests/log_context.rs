@@ -0,0 +1,71 @@
+use std::{
+    io,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{SinkExt, TryStreamExt};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use srt_tokio::SrtSocket;
+use tokio::time::sleep;
+
+// the level and target of every record
+struct Capture(Mutex<Vec<(Level, String)>>);
+
+impl Log for Capture {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        let entry = (record.level(), record.target().to_string());
+        self.0.lock().unwrap().push(entry);
+    }
+
+    fn flush(&self) {}
+}
+
+static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+
+fn debug_records(target: &str) -> usize {
+    CAPTURE
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(level, t)| *level == Level::Debug && t == target)
+        .count()
+}
+
+#[tokio::test]
+async fn log_context() -> io::Result<()> {
+    log::set_logger(&CAPTURE).unwrap();
+    log::set_max_level(LevelFilter::Warn);
+
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder()
+            .log_context("listener")
+            .log_level(LevelFilter::Debug)
+            .listen_on(":5703"),
+        SrtSocket::builder().call("127.0.0.1:5703", None),
+    )?;
+
+    caller.set_log_context("caller").await?;
+    caller.set_log_level(Some(LevelFilter::Debug)).await?;
+    sleep(Duration::from_millis(100)).await;
+
+    for _ in 0..10 {
+        caller.send((Instant::now(), Bytes::from("hello"))).await?;
+        listener.try_next().await?;
+    }
+    caller.close().await?;
+    sleep(Duration::from_millis(100)).await;
+
+    assert!(debug_records("srt_protocol::connection::listener") > 0);
+    assert!(debug_records("srt_protocol::connection::caller") > 0);
+    // every other connection logs at the global level
+    assert_eq!(debug_records("srt_protocol::connection"), 0);
+
+    Ok(())
+}
@@ -1,11 +1,14 @@
-use std::time::{Duration, Instant};
+use std::{
+    io,
+    time::{Duration, Instant},
+};
 
 use anyhow::Result;
 use bytes::Bytes;
 use futures::prelude::*;
 
-use srt_tokio::SrtSocket;
-use tokio::time::sleep;
+use srt_tokio::{options::PacketSize, PayloadTooLarge, SrtSocket};
+use tokio::{io::AsyncWriteExt, time::sleep};
 
 const PACKET_SIZE: usize = 15 * 1500;
 
@@ -46,3 +49,50 @@ async fn message_splitting() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn oversized_message() -> Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    // the smaller payload size of the two is agreed on
+    let (listener, mut caller) = futures::try_join!(
+        SrtSocket::builder()
+            .set(|options| options.sender.max_payload_size = PacketSize(1200))
+            .listen_on(":5746"),
+        SrtSocket::builder()
+            .set(|options| options.sender.limit_payload_size = true)
+            .call("127.0.0.1:5746", None),
+    )?;
+    assert_eq!(caller.settings().max_packet_size, PacketSize(1200));
+
+    let error = caller
+        .send((Instant::now(), Bytes::from(vec![0; 1201])))
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(
+        error.get_ref().unwrap().downcast_ref::<PayloadTooLarge>(),
+        Some(&PayloadTooLarge {
+            size: 1201,
+            max: PacketSize(1200)
+        })
+    );
+    assert!(caller
+        .try_send(Instant::now(), Bytes::from(vec![0; 1201]))
+        .is_err());
+
+    // the connection carries on
+    caller
+        .send((Instant::now(), Bytes::from(vec![0; 1200])))
+        .await?;
+
+    // a write sends as much as fits in a packet
+    assert_eq!(caller.write(&[1; 1500]).await?, 1200);
+
+    caller.close().await?;
+    let received = listener.try_collect::<Vec<_>>().await?;
+    assert_eq!(received.len(), 2);
+    assert_eq!(received[0].1.len(), 1200);
+    assert_eq!(received[1].1, Bytes::from(vec![1; 1200]));
+    Ok(())
+}
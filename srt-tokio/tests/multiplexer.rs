@@ -1,6 +1,11 @@
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use srt_tokio::{SrtListener, SrtSocket};
+use srt_protocol::settings::{KeySettings, KeySource};
+use srt_tokio::{
+    access::{ConnectionSettingsOverride, RejectReason},
+    options::KeySize,
+    SrtListener, SrtSocket,
+};
 
 use anyhow::Result;
 use bytes::Bytes;
@@ -58,4 +63,160 @@ async fn multiplexer() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn multiplexer_accept_with() -> Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (finished_send, finished_recv) = oneshot::channel();
+
+    let listener = tokio::spawn(async {
+        let (_server, incoming) = SrtListener::builder().bind(2002).await.unwrap();
+        let mut sockets = incoming.accept_with(|request| match request.stream_id() {
+            Some(stream_id) if stream_id.as_str() == "reject" => Err(RejectReason::User(42)),
+            // a different SLA for this stream
+            _ => Ok(ConnectionSettingsOverride {
+                latency: Some(Duration::from_secs(2)),
+                ..Default::default()
+            }),
+        });
+
+        let mut fused_finish = finished_recv.fuse();
+        while let Some(sender) =
+            futures::select!(res = sockets.next().fuse() => res, _ = fused_finish => None)
+        {
+            let mut sender = sender.unwrap();
+            let mut stream =
+                stream::iter(Some(Ok((Instant::now(), Bytes::from("asdf")))).into_iter());
+
+            tokio::spawn(async move {
+                sender.send_all(&mut stream).await.unwrap();
+                sender.close().await.unwrap();
+                info!("Sender finished");
+            });
+        }
+    });
+
+    let mut join_handles = vec![];
+    for i in 0..4 {
+        join_handles.push(tokio::spawn(async move {
+            let stream_id = if i % 2 == 0 { "reject" } else { "accept" };
+            let result = SrtSocket::builder()
+                .call("127.0.0.1:2002", Some(stream_id))
+                .await;
+            if i % 2 == 0 {
+                assert!(result.is_err());
+                return;
+            }
+
+            let mut recvr = result.unwrap();
+            assert_eq!(recvr.settings().recv_tsbpd_latency, Duration::from_secs(2));
+            let first = recvr.next().await;
+            assert_eq!(first.unwrap().unwrap().1, "asdf");
+            let second = recvr.next().await;
+            assert!(second.is_none());
+        }));
+    }
+
+    for handle in join_all(join_handles).await {
+        handle?;
+    }
+    finished_send.send(()).unwrap();
+    listener.await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn virtual_listeners() -> Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    fn key_settings(passphrase: &str) -> Option<KeySettings> {
+        Some(KeySettings {
+            key_size: KeySize::AES128,
+            key_source: KeySource::Passphrase(passphrase.into()),
+        })
+    }
+
+    let (_server, incoming) = SrtListener::builder().bind(5745).await?;
+    let mut listeners = incoming.into_virtual_listeners();
+    let tenants = [
+        ("alpha/", "alpha's passphrase", Duration::from_secs(2)),
+        ("beta/", "beta's passphrase", Duration::from_secs(1)),
+    ];
+    for (prefix, passphrase, latency) in tenants {
+        let settings = ConnectionSettingsOverride {
+            latency: Some(latency),
+            key_settings: key_settings(passphrase),
+            ..Default::default()
+        };
+        let mut sockets = listeners.register(prefix, settings).accept_all();
+        tokio::spawn(async move {
+            while let Some(Ok(mut sender)) = sockets.next().await {
+                tokio::spawn(async move {
+                    let message = (Instant::now(), Bytes::from(prefix));
+                    sender.send(message).await.unwrap();
+                    sender.close().await.unwrap();
+                });
+            }
+        });
+    }
+    tokio::spawn(listeners.run());
+
+    let call = |stream_id: &'static str, passphrase: Option<&'static str>| {
+        let mut builder = SrtSocket::builder();
+        if let Some(passphrase) = passphrase {
+            builder = builder.encryption(16, passphrase);
+        }
+        builder.call("127.0.0.1:5745", Some(stream_id))
+    };
+
+    for (prefix, passphrase, latency) in tenants {
+        let stream_id = if prefix == "alpha/" {
+            "alpha/live"
+        } else {
+            "beta/live"
+        };
+        let mut socket = call(stream_id, Some(passphrase)).await?;
+        assert_eq!(socket.settings().recv_tsbpd_latency, latency);
+        assert_eq!(socket.next().await.unwrap()?.1, prefix);
+        assert!(socket.next().await.is_none());
+    }
+
+    // the credentials of one tenant don't get into the other's streams
+    assert!(call("alpha/live", Some("beta's passphrase")).await.is_err());
+    assert!(call("alpha/live", None).await.is_err());
+    // and there's nothing else on this port
+    assert!(call("gamma/live", Some("alpha's passphrase"))
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn virtual_listener_full() -> Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (_server, incoming) = SrtListener::builder().bind(5776).await?;
+    let mut listeners = incoming.into_virtual_listeners();
+    // the application never gets to the requests of this one
+    let _busy = listeners.register("busy/", Default::default());
+    let mut idle = listeners.register("idle/", Default::default()).accept_all();
+    tokio::spawn(async move { while let Some(Ok(_socket)) = idle.next().await {} });
+    tokio::spawn(listeners.run());
+
+    let call = |stream_id| SrtSocket::builder().call("127.0.0.1:5776", Some(stream_id));
+    let waiting: Vec<_> = (0..101).map(|_| tokio::spawn(call("busy/live"))).collect();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // the full listener turns the next ones away, and doesn't hold up the other
+    let error = call("busy/live").await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::ConnectionRefused);
+    call("idle/live").await?;
+
+    for handle in waiting {
+        handle.abort();
+    }
+    Ok(())
+}
+
 // crypto!!
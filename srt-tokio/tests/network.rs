@@ -0,0 +1,190 @@
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{future::BoxFuture, SinkExt, TryStreamExt};
+use srt_tokio::{options::SocketOptions, Binder, DatagramSocket, Resolver, SrtListener, SrtSocket};
+use tokio::{
+    io::ReadBuf,
+    net::UdpSocket,
+    time::{sleep, timeout},
+};
+
+// service discovery that knows a single service
+struct Discovery;
+
+impl Resolver for Discovery {
+    fn lookup_ip<'a>(&'a self, host: &'a str) -> BoxFuture<'a, io::Result<Vec<IpAddr>>> {
+        Box::pin(async move {
+            match host {
+                "stream.service" => Ok(vec![Ipv4Addr::LOCALHOST.into()]),
+                _ => Err(io::ErrorKind::NotFound.into()),
+            }
+        })
+    }
+}
+
+// stands in for a userspace network stack
+#[derive(Clone, Default)]
+struct Counting(Arc<AtomicUsize>);
+
+struct CountedSocket(UdpSocket, Arc<AtomicUsize>);
+
+impl Binder for Counting {
+    fn bind<'a>(
+        &'a self,
+        options: &'a SocketOptions,
+    ) -> BoxFuture<'a, io::Result<Arc<dyn DatagramSocket>>> {
+        Box::pin(async move {
+            let socket = UdpSocket::bind(options.connect.local).await?;
+            Ok(Arc::new(CountedSocket(socket, self.0.clone())) as Arc<dyn DatagramSocket>)
+        })
+    }
+}
+
+impl DatagramSocket for CountedSocket {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.0.local_addr()
+    }
+
+    fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        target: SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        let sent = self.0.poll_send_to(cx, buf, target);
+        if let Poll::Ready(Ok(_)) = sent {
+            self.1.fetch_add(1, Ordering::Relaxed);
+        }
+        sent
+    }
+
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<SocketAddr>> {
+        self.0.poll_recv_from(cx, buf)
+    }
+}
+
+#[tokio::test]
+async fn custom_network() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let sent = Counting::default();
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder().listen_on(":5731"),
+        SrtSocket::builder()
+            .resolver(Discovery)
+            .binder(sent.clone())
+            .call("stream.service:5731", None),
+    )?;
+    assert!(sent.0.load(Ordering::Relaxed) > 0);
+
+    caller.send((Instant::now(), Bytes::from("hello"))).await?;
+    let (_, data) = listener.try_next().await?.expect("connection closed");
+    assert_eq!(data, "hello");
+
+    // there's no UDP socket to hand over
+    assert_eq!(
+        caller.detach().await.map(|_| ()).map_err(|e| e.kind()),
+        Err(io::ErrorKind::Unsupported)
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn unknown_service() {
+    let result = SrtSocket::builder()
+        .resolver(Discovery)
+        .call("other.service:5732", None)
+        .await;
+    assert_eq!(
+        result.map(|_| ()).map_err(|e| e.kind()),
+        Err(io::ErrorKind::NotFound)
+    );
+}
+
+// a socket that, once flooded, always has another datagram, one that isn't an SRT packet
+#[derive(Clone, Default)]
+struct Flooding(Arc<AtomicBool>);
+
+struct FloodedSocket(UdpSocket, Arc<AtomicBool>);
+
+impl Binder for Flooding {
+    fn bind<'a>(
+        &'a self,
+        options: &'a SocketOptions,
+    ) -> BoxFuture<'a, io::Result<Arc<dyn DatagramSocket>>> {
+        Box::pin(async move {
+            let socket = UdpSocket::bind(options.connect.local).await?;
+            Ok(Arc::new(FloodedSocket(socket, self.0.clone())) as Arc<dyn DatagramSocket>)
+        })
+    }
+}
+
+impl DatagramSocket for FloodedSocket {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.0.local_addr()
+    }
+
+    fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        target: SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        self.0.poll_send_to(cx, buf, target)
+    }
+
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<SocketAddr>> {
+        if self.1.load(Ordering::Relaxed) {
+            buf.put_slice(&[0; 4]);
+            return Poll::Ready(Ok(([127, 0, 0, 1], 9).into()));
+        }
+        self.0.poll_recv_from(cx, buf)
+    }
+}
+
+// on a single threaded runtime, a connection or a listener that always has another packet still
+// lets the other tasks have their turn
+#[tokio::test(flavor = "current_thread")]
+async fn flooded_socket_yields() -> io::Result<()> {
+    let flooding = Flooding::default();
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder().listen_on(":5779"),
+        SrtSocket::builder()
+            .binder(flooding.clone())
+            .call("127.0.0.1:5779", None),
+    )?;
+    flooding.0.store(true, Ordering::Relaxed);
+    timeout(Duration::from_secs(1), sleep(Duration::from_millis(10))).await?;
+    flooding.0.store(false, Ordering::Relaxed);
+
+    caller.send((Instant::now(), Bytes::from("hello"))).await?;
+    let (_, data) = listener.try_next().await?.expect("connection closed");
+    assert_eq!(data, "hello");
+
+    let flooding = Flooding::default();
+    let (_server, _incoming) = SrtListener::builder()
+        .binder(flooding.clone())
+        .bind(":5780")
+        .await?;
+    flooding.0.store(true, Ordering::Relaxed);
+    timeout(Duration::from_secs(1), sleep(Duration::from_millis(10))).await?;
+    Ok(())
+}
@@ -0,0 +1,60 @@
+#![cfg(feature = "packet_telemetry")]
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use anyhow::Result;
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use srt_protocol::packet::SocketId;
+use srt_tokio::{
+    telemetry::{PacketDirection, PacketEvent, PacketKind},
+    SrtListener, SrtSocket,
+};
+
+#[tokio::test]
+async fn listener_packet_hook() -> Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let hook_events = events.clone();
+    let (_listener, mut incoming) = SrtListener::builder()
+        .packet_hook(move |settings| {
+            let events = hook_events.clone();
+            let socket_id = settings.local_sockid;
+            move |event: &PacketEvent| {
+                events
+                    .lock()
+                    .unwrap()
+                    .push((socket_id, event.direction, event.kind()));
+            }
+        })
+        .bind(5773)
+        .await?;
+
+    let accept = tokio::spawn(async move {
+        let request = incoming.incoming().next().await.unwrap();
+        let mut sender = request.accept(None).await.unwrap();
+        let socket_id = sender.settings().local_sockid;
+        sender
+            .send((Instant::now(), Bytes::from("asdf")))
+            .await
+            .unwrap();
+        sender.close().await.unwrap();
+        socket_id
+    });
+
+    let mut receiver = SrtSocket::builder().call("127.0.0.1:5773", None).await?;
+    assert_eq!(receiver.next().await.unwrap()?.1, "asdf");
+    assert!(receiver.next().await.is_none());
+    let socket_id: SocketId = accept.await?;
+
+    // the connection's hook saw what it sent and what came back from the caller
+    let events = events.lock().unwrap();
+    assert!(events.iter().all(|(id, _, _)| *id == socket_id));
+    assert!(events.contains(&(socket_id, PacketDirection::Sent, PacketKind::Data)));
+    assert!(events.contains(&(socket_id, PacketDirection::Received, PacketKind::Ack)));
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bytes::{Bytes, BytesMut};
+use futures::{SinkExt, StreamExt, TryStreamExt};
+use srt_protocol::packet::{
+    DataEncryption, DataPacket, MsgNumber, Packet, PacketLocation, TimeStamp,
+};
+use srt_tokio::{options::PeerAddressPolicy, SrtSocket};
+use tokio::{net::UdpSocket, time::timeout};
+
+async fn connect(port: u16, policy: PeerAddressPolicy) -> io::Result<(SrtSocket, SrtSocket)> {
+    let address = format!("127.0.0.1:{port}");
+    futures::try_join!(
+        SrtSocket::builder().listen_on(port),
+        SrtSocket::builder()
+            .local_port(port + 1)
+            .peer_address_policy(policy)
+            .set(|options| options.session.statistics_interval = Duration::from_millis(200))
+            .call(address.as_str(), None),
+    )
+}
+
+// a packet for the caller from somewhere else, with its socket id and the first sequence number
+// the listener sends, as the peer's next data packet would be
+async fn intrude(intruder: &UdpSocket, caller: &SrtSocket, port: u16) -> io::Result<()> {
+    let data = Packet::Data(DataPacket {
+        seq_number: caller.settings().init_seq_num,
+        message_loc: PacketLocation::ONLY,
+        in_order_delivery: false,
+        encryption: DataEncryption::None,
+        retransmitted: false,
+        message_number: MsgNumber(0),
+        timestamp: TimeStamp::MIN,
+        dest_sockid: caller.settings().local_sockid,
+        payload: Bytes::from_static(b"intruder"),
+    });
+    let mut buffer = BytesMut::new();
+    data.serialize(&mut buffer);
+    intruder.send_to(&buffer, ("127.0.0.1", port)).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn reject() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut listener, mut caller) = connect(5751, PeerAddressPolicy::Reject).await?;
+    let rebinds = Arc::new(Mutex::new(Vec::new()));
+    caller
+        .set_rebind_handler({
+            let rebinds = rebinds.clone();
+            move |_, old, new| rebinds.lock().unwrap().push((old, new))
+        })
+        .await?;
+
+    let intruder = UdpSocket::bind("127.0.0.1:0").await?;
+    intrude(&intruder, &caller, 5752).await?;
+
+    let mut statistics = caller.statistics().clone();
+    timeout(Duration::from_secs(2), async {
+        while statistics.next().await.unwrap().rx_foreign_packets == 0 {}
+    })
+    .await?;
+    assert!(rebinds.lock().unwrap().is_empty());
+
+    // the connection carries on with the peer
+    caller.send((Instant::now(), Bytes::from("hello"))).await?;
+    let (_, data) = listener.try_next().await?.unwrap();
+    assert_eq!(data, "hello");
+    Ok(())
+}
+
+#[tokio::test]
+async fn rebind() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (_listener, mut caller) = connect(5753, PeerAddressPolicy::Rebind).await?;
+    let rebinds = Arc::new(Mutex::new(Vec::new()));
+    caller
+        .set_rebind_handler({
+            let rebinds = rebinds.clone();
+            move |_, old, new| rebinds.lock().unwrap().push((old, new))
+        })
+        .await?;
+
+    let intruder = UdpSocket::bind("127.0.0.1:0").await?;
+    intrude(&intruder, &caller, 5754).await?;
+
+    // the caller follows whoever gets the socket id and the sequence numbers right, which is what
+    // the policy is there to prevent
+    caller.send((Instant::now(), Bytes::from("hello"))).await?;
+    let mut buffer = [0; 1500];
+    let (_, from) = timeout(Duration::from_secs(2), intruder.recv_from(&mut buffer)).await??;
+    let listener: SocketAddr = "127.0.0.1:5753".parse().unwrap();
+    assert_eq!(from.port(), 5754);
+    assert_eq!(
+        *rebinds.lock().unwrap(),
+        [(listener, intruder.local_addr()?)]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn default_stays_on_host() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (_listener, mut caller) = connect(5777, PeerAddressPolicy::default()).await?;
+    let rebinds = Arc::new(Mutex::new(Vec::new()));
+    caller
+        .set_rebind_handler({
+            let rebinds = rebinds.clone();
+            move |_, old, new| rebinds.lock().unwrap().push((old, new))
+        })
+        .await?;
+
+    // only another port of the peer's own address is followed, not another host
+    let intruder = UdpSocket::bind("127.0.0.2:0").await?;
+    intrude(&intruder, &caller, 5778).await?;
+
+    let mut statistics = caller.statistics().clone();
+    timeout(Duration::from_secs(2), async {
+        while statistics.next().await.unwrap().rx_foreign_packets == 0 {}
+    })
+    .await?;
+    assert!(rebinds.lock().unwrap().is_empty());
+    Ok(())
+}
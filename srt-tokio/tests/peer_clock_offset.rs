@@ -0,0 +1,54 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{SinkExt, TryStreamExt};
+use srt_protocol::packet::TimeSpan;
+use srt_tokio::SrtSocket;
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn peer_clock_offset() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder().listen_on(":5750"),
+        SrtSocket::builder().call("127.0.0.1:5750", None),
+    )?;
+
+    // some packets each way
+    for _ in 0..3 {
+        caller
+            .send((Instant::now(), Bytes::from("to listener")))
+            .await?;
+        listener
+            .send((Instant::now(), Bytes::from("to caller")))
+            .await?;
+        listener.try_next().await?;
+        caller.try_next().await?;
+    }
+
+    let caller_offset = caller.peer_clock_offset().await?.unwrap();
+    let listener_offset = listener.peer_clock_offset().await?.unwrap();
+
+    // the clocks of both ends started at about the same time, what's left is the delay of the
+    // packets that set the time base, which includes the time they were queued at the peer
+    for offset in [caller_offset, listener_offset] {
+        assert!(
+            offset.abs() < TimeSpan::from_millis(50),
+            "{caller_offset:?} {listener_offset:?}"
+        );
+    }
+
+    // an echo sample measures the offset from halfway through the round trip instead
+    let sample = timeout(Duration::from_secs(1), caller.ping()).await??;
+    let difference = sample.offset - caller_offset;
+    assert!(
+        difference.abs() < TimeSpan::from_millis(50),
+        "{sample:?} {caller_offset:?}"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use std::{io, time::Duration};
+
+use srt_tokio::{EchoStatistics, Impairment, SrtSocket};
+use tokio::time::{sleep, timeout};
+
+#[tokio::test]
+async fn ping() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder().listen_on(":5720"),
+        SrtSocket::builder().call("127.0.0.1:5720", None),
+    )?;
+
+    let mut statistics = EchoStatistics::default();
+    for id in 1..=5 {
+        statistics.on_request();
+        let sample = timeout(Duration::from_secs(1), caller.ping()).await??;
+        assert_eq!(sample.id, id);
+        assert_eq!(sample.requests_received, id);
+        assert!(sample.rtt < Duration::from_millis(500), "{sample:?}");
+        statistics.on_reply(sample);
+    }
+    assert_eq!(statistics.lost(), 0);
+    assert_eq!(statistics.forward_lost(), 0);
+    let rtt = statistics.rtt().unwrap();
+    assert!(rtt.min <= rtt.avg && rtt.avg <= rtt.max);
+
+    // the requests that don't make it to the peer show up in its count
+    caller
+        .set_impairment(Impairment {
+            drop_rate: 1.0,
+            ..Impairment::default()
+        })
+        .await?;
+    assert!(timeout(Duration::from_millis(200), caller.ping())
+        .await
+        .is_err());
+    caller.set_impairment(Impairment::default()).await?;
+    sleep(Duration::from_millis(10)).await;
+
+    let sample = timeout(Duration::from_secs(1), caller.ping()).await??;
+    assert_eq!((sample.id, sample.requests_received), (7, 6));
+    assert_eq!(sample.requests_lost(), 1);
+
+    // both ends can ask
+    let sample = timeout(Duration::from_secs(1), listener.ping()).await??;
+    assert_eq!((sample.id, sample.requests_received), (1, 1));
+
+    Ok(())
+}
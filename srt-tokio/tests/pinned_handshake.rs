@@ -0,0 +1,86 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{SinkExt, TryStreamExt};
+use srt_protocol::packet::{SeqNumber, SocketId};
+use srt_tokio::SrtSocket;
+
+#[tokio::test]
+async fn pinned_handshake() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let start_time = Instant::now() - Duration::from_secs(1);
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder()
+            .socket_id(SocketId(0x1234))
+            // only the initiator picks the sequence numbers
+            .initial_sequence_number(SeqNumber::new_truncate(7))
+            .listen_on(":5721"),
+        SrtSocket::builder()
+            .socket_id(SocketId(0x5678))
+            .initial_sequence_number(SeqNumber::new_truncate(0x7fff_fff0))
+            .start_time(start_time)
+            .call("127.0.0.1:5721", None),
+    )?;
+
+    let settings = caller.settings();
+    assert_eq!(settings.local_sockid, SocketId(0x5678));
+    assert_eq!(settings.remote_sockid, SocketId(0x1234));
+    assert_eq!(settings.init_seq_num, SeqNumber::new_truncate(0x7fff_fff0));
+    assert_eq!(settings.socket_start_time, start_time);
+
+    let settings = listener.settings();
+    assert_eq!(settings.local_sockid, SocketId(0x1234));
+    assert_eq!(settings.remote_sockid, SocketId(0x5678));
+    assert_eq!(settings.init_seq_num, SeqNumber::new_truncate(0x7fff_fff0));
+
+    // the sequence numbers wrap around right away
+    for n in 0..32u32 {
+        caller
+            .send((Instant::now(), Bytes::from(n.to_string())))
+            .await?;
+    }
+    caller.close().await?;
+    for n in 0..32u32 {
+        let (_, data) = listener.try_next().await?.expect("connection closed");
+        assert_eq!(data, n.to_string());
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn reserved_socket_id() {
+    let result = SrtSocket::builder()
+        .socket_id(SocketId(0))
+        .call("127.0.0.1:5722", None)
+        .await;
+    assert_eq!(
+        result.map(|_| ()).map_err(|e| e.kind()),
+        Err(io::ErrorKind::InvalidInput)
+    );
+}
+
+#[tokio::test]
+async fn socket_id_in_use() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (_listener, _caller) = futures::try_join!(
+        SrtSocket::builder()
+            .socket_id(SocketId(0x4321))
+            .listen_on(":5723"),
+        SrtSocket::builder().call("127.0.0.1:5723", None),
+    )?;
+
+    let result = SrtSocket::builder()
+        .socket_id(SocketId(0x4321))
+        .call("127.0.0.1:5724", None)
+        .await;
+    assert_eq!(
+        result.map(|_| ()).map_err(|e| e.kind()),
+        Err(io::ErrorKind::AddrInUse)
+    );
+    Ok(())
+}
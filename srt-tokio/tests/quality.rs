@@ -0,0 +1,41 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt, TryStreamExt};
+use srt_tokio::{statistics::SocketStatistics, SrtSocket};
+
+#[tokio::test]
+async fn quality() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder().listen_on(":5706"),
+        SrtSocket::builder()
+            .set(|options| options.session.statistics_interval = Duration::from_millis(200))
+            .call("127.0.0.1:5706", None),
+    )?;
+
+    for _ in 0..10 {
+        caller.send((Instant::now(), Bytes::from("data"))).await?;
+        listener.try_next().await?.expect("connection closed");
+    }
+    while let Some(stats) = caller.statistics().next().await {
+        if stats.tx_unique_data >= 10 {
+            break;
+        }
+    }
+
+    // nothing is lost over loopback, the tail packets may be retransmitted while they wait for
+    // their ACK
+    assert!(caller.quality() >= 60);
+
+    caller.set_quality_formula(|stats: &SocketStatistics| stats.tx_unique_data as u8);
+    // only what was sent since the previous call is scored
+    assert_eq!(caller.quality(), 0);
+
+    caller.close().await?;
+    Ok(())
+}
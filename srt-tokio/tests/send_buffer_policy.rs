@@ -0,0 +1,93 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt, TryStreamExt};
+use srt_tokio::{
+    options::{DataRate, LiveBandwidthMode, SendBufferPolicy},
+    SrtSocket,
+};
+use tokio::time::sleep;
+
+// the caller can send about 100 of the 4 byte payloads a second, a tenth of the rate they're sent
+// at, so its send buffer of 20ms fills up
+async fn connect(port: u16, policy: SendBufferPolicy) -> io::Result<(SrtSocket, SrtSocket)> {
+    let address = format!("127.0.0.1:{port}");
+    futures::try_join!(
+        SrtSocket::builder()
+            .latency(Duration::from_secs(5))
+            .listen_on(port),
+        SrtSocket::builder()
+            .latency(Duration::from_secs(5))
+            .bandwidth(LiveBandwidthMode::Max(DataRate(400)))
+            .buffer_full_policy(policy)
+            .set(|options| options.sender.max_buffer_time = Some(Duration::from_millis(20)))
+            .call(address.as_str(), None),
+    )
+}
+
+#[tokio::test]
+async fn block() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut listener, mut caller) = connect(5748, SendBufferPolicy::Block).await?;
+
+    let receive = async {
+        let mut received = 0;
+        while listener.try_next().await?.is_some() {
+            received += 1;
+        }
+        io::Result::Ok(received)
+    };
+    let send = async {
+        // once the buffer and the channel to the socket's task are full, sending waits
+        let mut longest = Duration::ZERO;
+        for _ in 0..300 {
+            let start = Instant::now();
+            caller.send((Instant::now(), Bytes::from("data"))).await?;
+            longest = longest.max(start.elapsed());
+            sleep(Duration::from_millis(1)).await;
+        }
+        assert!(longest > Duration::from_millis(5), "{longest:?}");
+
+        let statistics = caller.statistics().next().await.unwrap();
+        assert_eq!(statistics.tx_buffer_overflow_data, 0);
+        caller.close().await
+    };
+    let (received, ()) = futures::try_join!(receive, send)?;
+
+    // nothing was dropped to make room
+    assert_eq!(received, 300);
+    Ok(())
+}
+
+#[tokio::test]
+async fn error() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (_listener, mut caller) = connect(5749, SendBufferPolicy::Error).await?;
+
+    let mut sent = 0;
+    let error = loop {
+        if let Err(e) = caller.send((Instant::now(), Bytes::from("data"))).await {
+            break e;
+        }
+        sent += 1;
+        assert!(sent < 1000, "sending never failed");
+        sleep(Duration::from_millis(1)).await;
+    };
+    assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+
+    // there is room again once the buffered packets were sent and acknowledged
+    loop {
+        match caller.send((Instant::now(), Bytes::from("data"))).await {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                sleep(Duration::from_millis(10)).await
+            }
+            result => break result?,
+        }
+    }
+    caller.close().await
+}
@@ -0,0 +1,87 @@
+use std::io;
+
+use srt_protocol::{
+    options::KeySize,
+    protocol::pending_connection::{ConnectionReject, SettingsMismatch},
+};
+use srt_tokio::SrtSocket;
+
+#[tokio::test]
+async fn encryption_mismatch() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let listener = tokio::spawn(
+        SrtSocket::builder()
+            .encryption(16, "only the listener has it")
+            .listen_on(":5766"),
+    );
+    let caller = SrtSocket::builder().call("127.0.0.1:5766", None).await;
+    assert_eq!(
+        caller.err().map(|e| e.kind()),
+        Some(io::ErrorKind::ConnectionRefused)
+    );
+
+    // the side that rejected knows what both of them offered
+    let error = listener.await.unwrap().unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::ConnectionRefused);
+    assert_eq!(
+        error.get_ref().and_then(|e| e.downcast_ref()),
+        Some(&ConnectionReject::Mismatched(
+            SettingsMismatch::Encryption {
+                local: Some(KeySize::AES128),
+                remote: None,
+            }
+        ))
+    );
+    assert!(error.to_string().contains("passphrase"), "{error}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn message_tags_mismatch() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let listener = tokio::spawn(
+        SrtSocket::builder()
+            .set(|options| options.session.message_tags = true)
+            .listen_on(":5774"),
+    );
+    let caller = SrtSocket::builder().call("127.0.0.1:5774", None).await;
+    assert_eq!(
+        caller.err().map(|e| e.kind()),
+        Some(io::ErrorKind::ConnectionRefused)
+    );
+
+    let error = listener.await.unwrap().unwrap_err();
+    assert_eq!(
+        error.get_ref().and_then(|e| e.downcast_ref()),
+        Some(&ConnectionReject::Mismatched(
+            SettingsMismatch::MessageTags {
+                local: true,
+                remote: false,
+            }
+        ))
+    );
+
+    // and the other way around
+    let listener = tokio::spawn(SrtSocket::builder().listen_on(":5775"));
+    let caller = SrtSocket::builder()
+        .set(|options| options.session.message_tags = true)
+        .call("127.0.0.1:5775", None)
+        .await;
+    assert_eq!(
+        caller.err().map(|e| e.kind()),
+        Some(io::ErrorKind::ConnectionRefused)
+    );
+    let error = listener.await.unwrap().unwrap_err();
+    assert_eq!(
+        error.get_ref().and_then(|e| e.downcast_ref()),
+        Some(&ConnectionReject::Mismatched(
+            SettingsMismatch::MessageTags {
+                local: false,
+                remote: true,
+            }
+        ))
+    );
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{SinkExt, TryStreamExt};
+use srt_tokio::{SrtSocket, StallEvent};
+use tokio::{sync::mpsc, time::timeout};
+
+#[tokio::test]
+async fn stall() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder().listen_on(":5757"),
+        SrtSocket::builder().call("127.0.0.1:5757", None),
+    )?;
+    let (events, mut received) = mpsc::unbounded_channel();
+    listener
+        .set_stall_handler(Duration::from_millis(300), move |_, event| {
+            let _ = events.send(*event);
+        })
+        .await?;
+
+    caller.send((Instant::now(), Bytes::from("before"))).await?;
+    listener.try_next().await?;
+
+    // the connection is kept alive, but the stream stops
+    let start = Instant::now();
+    let event = timeout(Duration::from_secs(2), received.recv()).await?;
+    assert!(
+        matches!(event, Some(StallEvent::Stalled { .. })),
+        "{event:?}"
+    );
+    // well before the peer idle timeout
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    caller.send((Instant::now(), Bytes::from("after"))).await?;
+    let event = timeout(Duration::from_secs(2), received.recv()).await?;
+    let Some(StallEvent::Resumed { stalled_for }) = event else {
+        panic!("{event:?}");
+    };
+    assert!(stalled_for >= Duration::from_millis(300), "{stalled_for:?}");
+    let (_, data) = listener.try_next().await?.unwrap();
+    assert_eq!(data, "after");
+    Ok(())
+}
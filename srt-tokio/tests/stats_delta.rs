@@ -0,0 +1,55 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt, TryStreamExt};
+use srt_tokio::SrtSocket;
+
+async fn send(caller: &mut SrtSocket, listener: &mut SrtSocket, count: u64) -> io::Result<()> {
+    for _ in 0..count {
+        caller.send((Instant::now(), Bytes::from("data"))).await?;
+        listener.try_next().await?.expect("connection closed");
+    }
+    Ok(())
+}
+
+// until the caller published a snapshot that has seen `sent` packets
+async fn sampled(caller: &mut SrtSocket, sent: u64) {
+    while let Some(stats) = caller.statistics().next().await {
+        if stats.tx_unique_data >= sent {
+            break;
+        }
+    }
+}
+
+#[tokio::test]
+async fn stats_delta() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder().listen_on(":5705"),
+        SrtSocket::builder()
+            .set(|options| options.session.statistics_interval = Duration::from_millis(200))
+            .call("127.0.0.1:5705", None),
+    )?;
+
+    send(&mut caller, &mut listener, 3).await?;
+    sampled(&mut caller, 3).await;
+    let first = caller.stats_delta();
+    assert_eq!(first.tx_unique_data, 3);
+
+    send(&mut caller, &mut listener, 1).await?;
+    sampled(&mut caller, 4).await;
+    let second = caller.stats_delta();
+    assert_eq!(second.tx_unique_data, 1);
+    assert_eq!(second.tx_unique_bytes * 3, first.tx_unique_bytes);
+    assert!(!second.elapsed_time.is_zero());
+
+    // nothing new since
+    assert_eq!(caller.stats_delta().tx_unique_data, 0);
+
+    caller.close().await?;
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use std::{io, time::Instant};
+
+use bytes::{Bytes, BytesMut};
+use futures::{join, SinkExt, TryStreamExt};
+use srt_tokio::SrtSocket;
+
+#[tokio::test]
+async fn byte_stream() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let stream_mode =
+        |options: &mut srt_tokio::options::SocketOptions| options.session.message_api = false;
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder().set(stream_mode).listen_on(":5767"),
+        SrtSocket::builder()
+            .set(stream_mode)
+            .call("127.0.0.1:5767", None),
+    )?;
+    assert!(listener.settings().stream_mode());
+
+    // writes of any size, some of them take several packets
+    let sent: Vec<u8> = (0..20_000u32).map(|i| i as u8).collect();
+    let send = async {
+        for chunk in sent.chunks(3_000) {
+            caller
+                .send((Instant::now(), Bytes::copy_from_slice(chunk)))
+                .await?;
+        }
+        caller.close().await
+    };
+    let receive = async {
+        let mut received = BytesMut::new();
+        while let Some((_, data)) = listener.try_next().await? {
+            received.extend_from_slice(&data);
+        }
+        io::Result::Ok(received)
+    };
+    let (sent_result, received) = join!(send, receive);
+    sent_result?;
+    assert_eq!(&received?[..], &sent[..]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn message_api_mismatch() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let listener = tokio::spawn(
+        SrtSocket::builder()
+            .set(|options| options.session.message_api = false)
+            .listen_on(":5768"),
+    );
+    let caller = SrtSocket::builder().call("127.0.0.1:5768", None).await;
+    assert_eq!(
+        caller.err().map(|e| e.kind()),
+        Some(io::ErrorKind::ConnectionRefused)
+    );
+    let error = listener.await.unwrap().unwrap_err();
+    assert!(error.to_string().contains("message API"), "{error}");
+    Ok(())
+}
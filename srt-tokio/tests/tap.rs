@@ -0,0 +1,45 @@
+use std::{
+    io,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{SinkExt, TryStreamExt};
+use srt_tokio::SrtSocket;
+
+#[tokio::test]
+async fn tap_before_tsbpd() -> io::Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let latency = Duration::from_secs(1);
+    let (mut listener, mut caller) = futures::try_join!(
+        SrtSocket::builder().latency(latency).listen_on(":5747"),
+        SrtSocket::builder()
+            .latency(latency)
+            .call("127.0.0.1:5747", None),
+    )?;
+
+    let tapped = Arc::new(Mutex::new(Vec::new()));
+    listener
+        .set_tap_handler({
+            let tapped = tapped.clone();
+            move |now, packet| tapped.lock().unwrap().push((now, packet.payload.clone()))
+        })
+        .await?;
+
+    let sent = Instant::now();
+    caller.send((sent, Bytes::from("recorded"))).await?;
+    let (_, received) = listener.try_next().await?.expect("connection closed");
+    let released = Instant::now();
+
+    // the tap had it right away, the stream one latency later
+    let tapped = tapped.lock().unwrap().clone();
+    assert_eq!(tapped.len(), 1);
+    assert_eq!(tapped[0].1, received);
+    assert!(tapped[0].0 < sent + latency / 2, "{:?}", tapped[0].0 - sent);
+    assert!(released >= sent + latency * 9 / 10);
+
+    caller.close().await?;
+    Ok(())
+}
@@ -0,0 +1,185 @@
+// The admin endpoint, JSON state of the SRT connections and a few controls for headless relays
+//
+//   GET    /connections       every connection, with its statistics
+//   GET    /connections/<id>  one of them
+//   DELETE /connections/<id>  kick it, an output or multiplex client is free to connect again
+//   GET    /log               the log level
+//   PUT    /log               set it, the body is the level, e.g. debug
+//   GET    /health            the packet flow, 503 once the stream stalled
+//
+// Every route needs `Authorization: Bearer <token>` when there's a token, the stream ids can carry
+// access tokens, without one the endpoint only binds to a loopback address
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use anyhow::{bail, format_err, Error};
+use hyper::{
+    header::AUTHORIZATION,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use log::{error, info, LevelFilter};
+use serde_json::{json, Map, Value};
+use tokio::spawn;
+
+use srt_tokio::statistics::FieldValue;
+
+use crate::{
+    connections::{self, Connection},
+    health,
+};
+
+pub fn serve(address: SocketAddr, token: Option<String>) -> Result<(), Error> {
+    if token.is_none() && !address.ip().is_loopback() {
+        bail!(
+            "The admin endpoint needs --admin-token to listen on {}, or a loopback address",
+            address
+        );
+    }
+    let token = Arc::new(token);
+    let server =
+        Server::try_bind(&address)
+            .map_err(|e| format_err!("Failed to bind admin endpoint to {}: {}", address, e))?
+            .serve(make_service_fn(move |_| {
+                let token = token.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request| handle(request, token.clone())))
+                }
+            }));
+    info!("Admin endpoint listening on {}", server.local_addr());
+    spawn(async move {
+        if let Err(e) = server.await {
+            error!("Admin endpoint failed: {}", e);
+        }
+    });
+    Ok(())
+}
+
+async fn handle(
+    request: Request<Body>,
+    token: Arc<Option<String>>,
+) -> Result<Response<Body>, Infallible> {
+    if !authorized(&request, token.as_deref()) {
+        return Ok(status_response(StatusCode::UNAUTHORIZED));
+    }
+    let path = request.uri().path().trim_matches('/').to_string();
+    let path: Vec<_> = path.split('/').collect();
+    let response = match (request.method(), &path[..]) {
+        (&Method::GET, ["connections"]) => {
+            let connections = connections::all();
+            let list = connections.iter().map(|(id, c)| to_json(*id, c)).collect();
+            json_response(Value::Array(list))
+        }
+        (&Method::GET, ["connections", id]) => {
+            let connections = connections::all();
+            match id
+                .parse()
+                .ok()
+                .and_then(|id| Some((id, connections.get(&id)?)))
+            {
+                Some((id, connection)) => json_response(to_json(id, connection)),
+                None => status_response(StatusCode::NOT_FOUND),
+            }
+        }
+        (&Method::DELETE, ["connections", id]) => {
+            let connections = connections::all();
+            match id.parse().ok().and_then(|id: u64| connections.get(&id)) {
+                Some(connection) => {
+                    connection.kick();
+                    status_response(StatusCode::NO_CONTENT)
+                }
+                None => status_response(StatusCode::NOT_FOUND),
+            }
+        }
+        (&Method::GET, ["log"]) => {
+            let level = log::max_level().to_string().to_lowercase();
+            json_response(json!({ "level": level }))
+        }
+        (&Method::PUT, ["log"]) => {
+            let body = hyper::body::to_bytes(request.into_body()).await;
+            let level = body
+                .ok()
+                .and_then(|body| String::from_utf8(body.to_vec()).ok())
+                .and_then(|level| level.trim().parse::<LevelFilter>().ok());
+            match level {
+                Some(level) => {
+                    log::set_max_level(level);
+                    status_response(StatusCode::NO_CONTENT)
+                }
+                None => status_response(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::GET, ["health"]) => match health::state() {
+            Some(state) => {
+                let mut response = json_response(json!({
+                    "packets": state.packets,
+                    "bytes": state.bytes,
+                    "since_last_packet_ms": state.last_flow.map(|last| last.elapsed().as_millis() as u64),
+                    "stalled": state.stalled,
+                }));
+                if state.stalled {
+                    *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                }
+                response
+            }
+            None => status_response(StatusCode::SERVICE_UNAVAILABLE),
+        },
+        _ => status_response(StatusCode::NOT_FOUND),
+    };
+    Ok(response)
+}
+
+fn authorized(request: &Request<Body>, token: Option<&str>) -> bool {
+    let token = match token {
+        Some(token) => token,
+        None => return true,
+    };
+    match request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.as_bytes().strip_prefix(b"Bearer "))
+    {
+        Some(bearer) => constant_time_eq(bearer, token.as_bytes()),
+        None => false,
+    }
+}
+
+// doesn't stop at the first differing byte, so the time taken tells nothing of the token
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn to_json(id: u64, connection: &Connection) -> Value {
+    let statistics: Map<_, _> = connection
+        .statistics
+        .libsrt_fields()
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                FieldValue::Count(count) => json!(count),
+                FieldValue::Rate(rate) => json!(rate),
+            };
+            (name.to_string(), value)
+        })
+        .collect();
+    json!({
+        "id": id,
+        "kind": connection.kind,
+        "peer": connection.peer.to_string(),
+        "stream_id": connection.stream_id,
+        "statistics": statistics,
+    })
+}
+
+fn json_response(value: Value) -> Response<Body> {
+    Response::builder()
+        .header("content-type", "application/json")
+        .body(Body::from(value.to_string()))
+        .unwrap()
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
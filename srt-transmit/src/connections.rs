@@ -0,0 +1,132 @@
+// The SRT connections of the relay with their latest statistics, for the admin endpoint and the
+// metrics exporters
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    task::{Context, Poll},
+};
+
+use anyhow::{format_err, Error};
+use bytes::Bytes;
+use futures::{
+    future::{BoxFuture, Fuse, FusedFuture},
+    prelude::*,
+};
+use tokio::{spawn, sync::Notify};
+
+use srt_tokio::{SocketStatistics, SrtSocket};
+
+use crate::BoxSink;
+
+static CONNECTIONS: Mutex<BTreeMap<u64, Connection>> = Mutex::new(BTreeMap::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+pub struct Connection {
+    pub kind: &'static str,
+    pub peer: SocketAddr,
+    pub stream_id: Option<String>,
+    pub statistics: SocketStatistics,
+    #[cfg(feature = "admin")]
+    kicked: Arc<Notify>,
+}
+
+#[cfg(feature = "admin")]
+impl Connection {
+    pub fn kick(&self) {
+        self.kicked.notify_one();
+    }
+}
+
+// the registered connections by id
+pub fn all() -> MutexGuard<'static, BTreeMap<u64, Connection>> {
+    CONNECTIONS.lock().unwrap()
+}
+
+// a listed connection, until this is dropped
+pub struct Registration {
+    id: u64,
+    kicked: Arc<Notify>,
+}
+
+impl Registration {
+    // resolves once the connection is kicked
+    pub async fn kicked(self) {
+        self.kicked.notified().await
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        CONNECTIONS.lock().unwrap().remove(&self.id);
+    }
+}
+
+pub fn register(kind: &'static str, socket: &mut SrtSocket) -> Registration {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let kicked = Arc::new(Notify::new());
+    let settings = socket.settings();
+    CONNECTIONS.lock().unwrap().insert(
+        id,
+        Connection {
+            kind,
+            peer: settings.remote,
+            stream_id: settings.stream_id.clone(),
+            statistics: SocketStatistics::default(),
+            #[cfg(feature = "admin")]
+            kicked: kicked.clone(),
+        },
+    );
+
+    let mut statistics = socket.statistics().clone();
+    spawn(async move {
+        while let Some(update) = statistics.next().await {
+            match CONNECTIONS.lock().unwrap().get_mut(&id) {
+                Some(connection) => connection.statistics = update,
+                None => break,
+            }
+        }
+    });
+
+    Registration { id, kicked }
+}
+
+// fails once the connection is kicked, which drops it and lets autoreconnect do its thing
+pub fn kickable(sink: BoxSink, registration: Registration) -> BoxSink {
+    Box::pin(Kickable {
+        sink,
+        kicked: registration.kicked().boxed().fuse(),
+    })
+}
+
+struct Kickable {
+    sink: BoxSink,
+    kicked: Fuse<BoxFuture<'static, ()>>,
+}
+
+impl Sink<Bytes> for Kickable {
+    type Error = Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        if self.kicked.is_terminated() || self.kicked.poll_unpin(cx).is_ready() {
+            return Poll::Ready(Err(format_err!("Connection was kicked")));
+        }
+        self.sink.as_mut().poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        self.sink.as_mut().start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.sink.as_mut().poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.sink.as_mut().poll_close(cx)
+    }
+}
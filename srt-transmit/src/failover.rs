@@ -0,0 +1,140 @@
+// Input failover, the data of the first input, in priority order, that hasn't stalled
+//
+// Every input is read all the time, so a backup is live the moment it's needed. Data from an
+// input that isn't the active one is dropped, unless the active input stalled, i.e. sent nothing
+// for the failover timeout or ended, in which case the input switches over to it. Data from an
+// input with a higher priority than the active one switches back to it right away.
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use anyhow::Error;
+use bytes::Bytes;
+use futures::{prelude::*, stream::once};
+use log::{error, info};
+
+use crate::{ByteStream, StreamStream};
+
+struct Input {
+    name: String,
+    // the connections the input makes, None once it ended for good
+    connections: Option<StreamStream>,
+    connection: Option<ByteStream>,
+    last_data: Instant,
+}
+
+impl Input {
+    fn ended(&self) -> bool {
+        self.connections.is_none() && self.connection.is_none()
+    }
+
+    // the next data of the current connection, moving on to the next one if it ended
+    fn poll_data(&mut self, cx: &mut Context) -> Poll<Option<Bytes>> {
+        loop {
+            if let Some(connection) = &mut self.connection {
+                match connection.poll_next_unpin(cx) {
+                    Poll::Ready(Some(Ok(data))) => return Poll::Ready(Some(data)),
+                    // like one that ended, the next connection of the input takes over
+                    Poll::Ready(Some(Err(e))) => {
+                        error!("Input {} connection failed: {}", self.name, e);
+                        self.connection = None;
+                    }
+                    Poll::Ready(None) => self.connection = None,
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+            let connections = match &mut self.connections {
+                Some(connections) => connections,
+                None => return Poll::Ready(None),
+            };
+            match connections.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(connection))) => self.connection = Some(connection),
+                Poll::Ready(Some(Err(e))) => {
+                    error!("Input {} failed: {}", self.name, e);
+                    self.connections = None;
+                }
+                Poll::Ready(None) => self.connections = None,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+struct Failover {
+    inputs: Vec<Input>,
+    active: usize,
+    timeout: Duration,
+}
+
+impl Failover {
+    // whether data that just arrived on input should be passed on
+    fn accept(&mut self, input: usize, now: Instant) -> bool {
+        let active = &self.inputs[self.active];
+        let stalled = active.ended() || now - active.last_data >= self.timeout;
+        self.inputs[input].last_data = now;
+        if input == self.active {
+            return true;
+        }
+        if input < self.active {
+            info!(
+                "Input {} recovered, switching back from {}",
+                self.inputs[input].name, self.inputs[self.active].name
+            );
+        } else if stalled {
+            info!(
+                "Input {} stalled, switching to {}",
+                self.inputs[self.active].name, self.inputs[input].name
+            );
+        } else {
+            return false;
+        }
+        self.active = input;
+        true
+    }
+}
+
+impl Stream for Failover {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        // the inactive inputs first, so the active one can't starve them and leave stale data
+        // queued up for when they're switched to
+        let active = self.active;
+        let order = (0..self.inputs.len())
+            .filter(|input| *input != active)
+            .chain([active]);
+        for input in order {
+            while let Poll::Ready(Some(data)) = self.inputs[input].poll_data(cx) {
+                if self.accept(input, Instant::now()) {
+                    return Poll::Ready(Some(Ok(data)));
+                }
+            }
+        }
+        if self.inputs.iter().all(Input::ended) {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+// inputs are named and in priority order, the first one is the primary
+pub fn failover(inputs: Vec<(String, StreamStream)>, timeout: Duration) -> StreamStream {
+    let now = Instant::now();
+    let failover = Failover {
+        inputs: inputs
+            .into_iter()
+            .map(|(name, connections)| Input {
+                name,
+                connections: Some(connections),
+                connection: None,
+                last_data: now,
+            })
+            .collect(),
+        active: 0,
+        timeout,
+    };
+    once(future::ok(failover.boxed())).boxed()
+}
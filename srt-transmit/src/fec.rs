@@ -0,0 +1,194 @@
+use std::time::Instant;
+
+use anyhow::{bail, Error};
+use bytes::{BufMut, Bytes, BytesMut};
+
+// MPEG-TS in RTP (SMPTE 2022-2), with SMPTE 2022-1 column FEC, what legacy IRDs and RIST simple
+// profile receivers take. The column FEC packets go to the media port + 2.
+//
+// The media packets are laid out row by row in a matrix of L columns and D rows. Each column FEC
+// packet is the XOR of the D media packets of its column, so any one of them that's lost can be
+// rebuilt from the others, and is sent as soon as the last of them was.
+pub struct FecEncoder {
+    columns: u16,
+    rows: u16,
+    ssrc: u32,
+    start: Instant,
+    media_seq: u16,
+    fec_seq: u16,
+    // position of the next media packet in the matrix, row by row
+    position: u16,
+    matrix: Vec<Column>,
+}
+
+#[derive(Default)]
+struct Column {
+    sn_base: u16,
+    length_recovery: u16,
+    pt_recovery: u8,
+    ts_recovery: u32,
+    payload: BytesMut,
+}
+
+const RTP_HEADER_SIZE: usize = 12;
+const FEC_HEADER_SIZE: usize = 16;
+// MP2T, RFC 3551
+const MEDIA_PAYLOAD_TYPE: u8 = 33;
+const FEC_PAYLOAD_TYPE: u8 = 96;
+
+impl FecEncoder {
+    pub fn new(columns: u16, rows: u16) -> Result<Self, Error> {
+        // the limits of SMPTE 2022-1
+        if !(1..=20).contains(&columns) || !(4..=20).contains(&rows) || columns * rows > 100 {
+            bail!(
+                "FEC matrix {}x{} out of range, columns must be 1-20, rows 4-20 and the matrix at most 100 packets",
+                columns,
+                rows
+            );
+        }
+        Ok(FecEncoder {
+            columns,
+            rows,
+            ssrc: std::process::id(),
+            start: Instant::now(),
+            media_seq: 0,
+            fec_seq: 0,
+            position: 0,
+            matrix: (0..columns).map(|_| Column::default()).collect(),
+        })
+    }
+
+    // the RTP packet carrying `payload`, and the column FEC packet it completes, if any
+    pub fn push(&mut self, payload: &[u8]) -> (Bytes, Option<Bytes>) {
+        // 90 kHz, the clock of MPEG-TS
+        let timestamp = (self.start.elapsed().as_micros() * 9 / 100) as u32;
+        let seq = self.media_seq;
+        self.media_seq = self.media_seq.wrapping_add(1);
+
+        let mut media = BytesMut::with_capacity(RTP_HEADER_SIZE + payload.len());
+        put_rtp_header(&mut media, MEDIA_PAYLOAD_TYPE, seq, timestamp, self.ssrc);
+        media.put_slice(payload);
+
+        let row = self.position / self.columns;
+        let column = &mut self.matrix[usize::from(self.position % self.columns)];
+        if row == 0 {
+            *column = Column {
+                sn_base: seq,
+                ..Column::default()
+            };
+        }
+        column.add(payload, timestamp);
+        self.position = (self.position + 1) % (self.columns * self.rows);
+
+        let fec = (row == self.rows - 1).then(|| {
+            let fec_seq = self.fec_seq;
+            self.fec_seq = self.fec_seq.wrapping_add(1);
+            column.packet(fec_seq, self.ssrc, self.columns, self.rows)
+        });
+        (media.freeze(), fec)
+    }
+}
+
+impl Column {
+    fn add(&mut self, payload: &[u8], timestamp: u32) {
+        self.length_recovery ^= payload.len() as u16;
+        self.pt_recovery ^= MEDIA_PAYLOAD_TYPE;
+        self.ts_recovery ^= timestamp;
+        if self.payload.len() < payload.len() {
+            self.payload.resize(payload.len(), 0);
+        }
+        for (xor, byte) in self.payload.iter_mut().zip(payload) {
+            *xor ^= byte;
+        }
+    }
+
+    fn packet(&self, seq: u16, ssrc: u32, columns: u16, rows: u16) -> Bytes {
+        let mut fec =
+            BytesMut::with_capacity(RTP_HEADER_SIZE + FEC_HEADER_SIZE + self.payload.len());
+        // the FEC stream has a timestamp of 0, receivers go by SN base
+        put_rtp_header(&mut fec, FEC_PAYLOAD_TYPE, seq, 0, ssrc);
+        fec.put_u16(self.sn_base);
+        fec.put_u16(self.length_recovery);
+        // E, set, and the PT recovery
+        fec.put_u8(0x80 | self.pt_recovery);
+        // mask, unused
+        fec.put_slice(&[0; 3]);
+        fec.put_u32(self.ts_recovery);
+        // N clear, D clear for a column, type 0 for XOR and index 0
+        fec.put_u8(0);
+        // offset, the distance between the packets of a column, and NA, how many there are
+        fec.put_u8(columns as u8);
+        fec.put_u8(rows as u8);
+        // SN base extension, unused
+        fec.put_u8(0);
+        fec.put_slice(&self.payload);
+        fec.freeze()
+    }
+}
+
+fn put_rtp_header(into: &mut BytesMut, payload_type: u8, seq: u16, timestamp: u32, ssrc: u32) {
+    // version 2, no padding, extension or CSRCs
+    into.put_u8(0x80);
+    // marker clear
+    into.put_u8(payload_type);
+    into.put_u16(seq);
+    into.put_u32(timestamp);
+    into.put_u32(ssrc);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn payload(i: u8) -> Vec<u8> {
+        // the sizes differ, so the length recovery is exercised
+        vec![i; 100 + usize::from(i)]
+    }
+
+    #[test]
+    fn column_fec() {
+        let mut encoder = FecEncoder::new(5, 4).unwrap();
+        let mut media = Vec::new();
+        let mut fec = Vec::new();
+        for i in 0..20 {
+            let (packet, column) = encoder.push(&payload(i));
+            assert_eq!(packet[1], MEDIA_PAYLOAD_TYPE);
+            assert_eq!(u16::from_be_bytes([packet[2], packet[3]]), u16::from(i));
+            assert_eq!(&packet[RTP_HEADER_SIZE..], &payload(i)[..]);
+            media.push(packet);
+            // a column is done with each packet of the last row
+            assert_eq!(column.is_some(), i >= 15);
+            fec.extend(column);
+        }
+        assert_eq!(fec.len(), 5);
+
+        // rebuild packet 7, of the third column, from the rest of it
+        let column = &fec[2];
+        let header = &column[RTP_HEADER_SIZE..RTP_HEADER_SIZE + FEC_HEADER_SIZE];
+        assert_eq!(u16::from_be_bytes([header[0], header[1]]), 2);
+        assert_eq!(&header[13..15], &[5, 4]);
+        let mut length = u16::from_be_bytes([header[2], header[3]]);
+        let mut recovered = column[RTP_HEADER_SIZE + FEC_HEADER_SIZE..].to_vec();
+        for other in [2, 12, 17] {
+            let payload = &media[other][RTP_HEADER_SIZE..];
+            length ^= payload.len() as u16;
+            for (xor, byte) in recovered.iter_mut().zip(payload) {
+                *xor ^= byte;
+            }
+        }
+        recovered.truncate(usize::from(length));
+        assert_eq!(recovered, payload(7));
+
+        // the next matrix starts over
+        let (_, column) = encoder.push(&payload(20));
+        assert_eq!(column, None);
+    }
+
+    #[test]
+    fn matrix_limits() {
+        assert!(FecEncoder::new(0, 10).is_err());
+        assert!(FecEncoder::new(10, 3).is_err());
+        assert!(FecEncoder::new(20, 20).is_err());
+        assert!(FecEncoder::new(10, 10).is_ok());
+    }
+}
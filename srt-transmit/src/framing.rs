@@ -0,0 +1,657 @@
+use std::{
+    collections::HashSet,
+    io,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Error};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::prelude::*;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    time::sleep_until,
+};
+use tokio_util::codec::{
+    AnyDelimiterCodec, AnyDelimiterCodecError, Decoder, Encoder, FramedRead, FramedWrite,
+    LengthDelimitedCodec,
+};
+
+use crate::{BoxSink, ByteStream, MySinkExt};
+
+// how messages are cut out of byte streams like stdin, files and pipes
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Framing {
+    // whatever is available, in chunks of up to this size
+    Raw(usize),
+    // each message is preceded by its length, as a 32-bit big endian integer
+    LengthPrefixed,
+    // newline delimited, the newline isn't part of the message
+    Lines,
+    // each message is preceded by when it arrived, in microseconds since the Unix epoch as a
+    // 64-bit big endian integer, and by its length, as a 32-bit big endian integer. Reading plays
+    // the messages with the timing they arrived with.
+    Timed,
+    // the payloads of the SRT data packets in a pcap capture, played with the timing they were
+    // captured with. Retransmissions and encrypted packets are skipped. Reading only.
+    Pcap(PcapFilter),
+}
+
+// which of the UDP datagrams of a capture are the SRT data packets to play
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct PcapFilter {
+    // the destination port of the packets
+    port: Option<u16>,
+    // the destination socket id of the packets
+    socket_id: Option<u32>,
+}
+
+impl PcapFilter {
+    // without a port or socket id, the packets going where SRT control packets were seen going,
+    // i.e. to the port and the socket id of an SRT connection, as the ACKACKs of a sender do
+    fn any(&self) -> bool {
+        self.port.is_none() && self.socket_id.is_none()
+    }
+
+    fn matches(&self, port: u16, socket_id: u32) -> bool {
+        self.port.is_none_or(|p| p == port) && self.socket_id.is_none_or(|s| s == socket_id)
+    }
+}
+
+// the longest message of timed framing, as for length framing, so a corrupt length doesn't take
+// all the memory there is
+const MAX_TIMED_MESSAGE: usize = 8 * 1024 * 1024;
+
+// the longest frame in a pcap capture, the largest snapshot length of libpcap
+const MAX_PCAP_FRAME: usize = 256 * 1024;
+
+impl Default for Framing {
+    fn default() -> Self {
+        // 7 MPEG-TS packets, what fits into a default SRT payload
+        Framing::Raw(1316)
+    }
+}
+
+impl Framing {
+    pub fn parse<C>(args: impl Iterator<Item = (C, C)>) -> Result<Framing, Error>
+    where
+        C: std::ops::Deref<Target = str>,
+    {
+        let mut framing = None;
+        let mut chunk = None;
+        let mut filter = PcapFilter::default();
+        for (k, v) in args {
+            match &*k {
+                "framing" => framing = Some(v.parse()?),
+                "chunk" => match v.parse() {
+                    Ok(0) | Err(_) => bail!("Failed to parse chunk as a positive integer: {}", &*v),
+                    Ok(size) => chunk = Some(size),
+                },
+                "port" => match v.parse() {
+                    Ok(0) | Err(_) => bail!("Failed to parse port as a port number: {}", &*v),
+                    Ok(port) => filter.port = Some(port),
+                },
+                "socket_id" => match v.parse() {
+                    Ok(socket_id) => filter.socket_id = Some(socket_id),
+                    Err(_) => bail!("Failed to parse socket_id as an integer: {}", &*v),
+                },
+                unrecog => bail!("Unrecognized parameter '{}' for file", unrecog),
+            }
+        }
+        let framing = match (framing.unwrap_or_default(), chunk) {
+            (Framing::Raw(_), Some(size)) => Framing::Raw(size),
+            (_, Some(_)) => bail!("chunk is only supported for raw framing"),
+            (framing, None) => framing,
+        };
+        match framing {
+            Framing::Pcap(_) => Ok(Framing::Pcap(filter)),
+            _ if !filter.any() => bail!("port and socket_id are only supported for pcap framing"),
+            framing => Ok(framing),
+        }
+    }
+
+    pub fn read(self, read: impl AsyncRead + Send + 'static) -> ByteStream {
+        fn frames<D>(read: impl AsyncRead + Send + 'static, decoder: D) -> ByteStream
+        where
+            D: Decoder<Item = Bytes> + Send + 'static,
+            D::Error: Into<Error>,
+        {
+            FramedRead::new(read, decoder).err_into().boxed()
+        }
+        match self {
+            Framing::Raw(size) => frames(read, ChunkCodec(size)),
+            Framing::LengthPrefixed => FramedRead::new(read, LengthDelimitedCodec::new())
+                .map_ok(BytesMut::freeze)
+                .err_into()
+                .boxed(),
+            Framing::Lines => frames(read, LineCodec::default()),
+            Framing::Timed => pace(FramedRead::new(read, TimedCodec)),
+            Framing::Pcap(filter) => pace(FramedRead::new(read, PcapCodec::new(filter))),
+        }
+    }
+
+    pub fn write(self, write: impl AsyncWrite + Send + 'static) -> Result<BoxSink, Error> {
+        fn frames<E>(write: impl AsyncWrite + Send + 'static, encoder: E) -> BoxSink
+        where
+            E: Encoder<Bytes> + Send + 'static,
+            E::Error: Into<Error>,
+        {
+            FramedWrite::new(write, encoder)
+                .sink_map_err(Into::into)
+                .boxed_sink()
+        }
+        Ok(match self {
+            Framing::Raw(size) => frames(write, ChunkCodec(size)),
+            Framing::LengthPrefixed => frames(write, LengthDelimitedCodec::new()),
+            Framing::Lines => frames(write, LineCodec::default()),
+            Framing::Timed => frames(write, TimedCodec),
+            Framing::Pcap(_) => bail!("pcap framing can only be read"),
+        })
+    }
+
+    // frames a single message, for writers that don't go through write
+    pub fn encode(self, item: Bytes, dst: &mut BytesMut) -> Result<(), Error> {
+        match self {
+            Framing::Raw(size) => ChunkCodec(size).encode(item, dst)?,
+            Framing::LengthPrefixed => LengthDelimitedCodec::new().encode(item, dst)?,
+            Framing::Lines => LineCodec::default().encode(item, dst)?,
+            Framing::Timed => TimedCodec.encode(item, dst)?,
+            Framing::Pcap(_) => bail!("pcap framing can only be read"),
+        }
+        Ok(())
+    }
+}
+
+// passes the messages on with the timing of their timestamps, in microseconds, from the first one
+// on, until one can't be read
+fn pace(
+    messages: impl Stream<Item = Result<(u64, Bytes), io::Error>> + Send + 'static,
+) -> ByteStream {
+    let mut origin = None;
+    messages
+        .err_into()
+        .and_then(move |(time, data)| {
+            let (first, start) = *origin.get_or_insert((time, Instant::now()));
+            let at = start + Duration::from_micros(time.saturating_sub(first));
+            async move {
+                sleep_until(at.into()).await;
+                Ok(data)
+            }
+        })
+        .boxed()
+}
+
+impl FromStr for Framing {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "raw" => Framing::default(),
+            "length" => Framing::LengthPrefixed,
+            "lines" => Framing::Lines,
+            "timed" => Framing::Timed,
+            "pcap" => Framing::Pcap(PcapFilter::default()),
+            unrecog => bail!(
+                "Unexpected value for framing: {}, expected raw, length, lines, timed or pcap",
+                unrecog
+            ),
+        })
+    }
+}
+
+// like BytesCodec, but never more than a chunk per message. Doesn't wait for a whole chunk, live
+// input written in small pieces would otherwise sit in the buffer.
+struct ChunkCodec(usize);
+
+impl Decoder for ChunkCodec {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, io::Error> {
+        if buf.is_empty() {
+            buf.reserve(self.0);
+            Ok(None)
+        } else {
+            let len = buf.len().min(self.0);
+            Ok(Some(buf.split_to(len).freeze()))
+        }
+    }
+}
+
+impl Encoder<Bytes> for ChunkCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), io::Error> {
+        dst.put(item);
+        Ok(())
+    }
+}
+
+// AnyDelimiterCodec only encodes strings
+struct LineCodec(AnyDelimiterCodec);
+
+impl Default for LineCodec {
+    fn default() -> Self {
+        LineCodec(AnyDelimiterCodec::new(b"\n".to_vec(), b"\n".to_vec()))
+    }
+}
+
+impl Decoder for LineCodec {
+    type Item = Bytes;
+    type Error = AnyDelimiterCodecError;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, Self::Error> {
+        self.0.decode(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, Self::Error> {
+        self.0.decode_eof(buf)
+    }
+}
+
+impl Encoder<Bytes> for LineCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), io::Error> {
+        dst.reserve(item.len() + 1);
+        dst.put(item);
+        dst.put_u8(b'\n');
+        Ok(())
+    }
+}
+
+// the messages of Framing::Timed
+struct TimedCodec;
+
+impl Decoder for TimedCodec {
+    type Item = (u64, Bytes);
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, io::Error> {
+        if buf.len() < 12 {
+            return Ok(None);
+        }
+        let len = u32::from_be_bytes(buf[8..12].try_into().unwrap()) as usize;
+        if len > MAX_TIMED_MESSAGE {
+            return Err(too_long(len, MAX_TIMED_MESSAGE));
+        }
+        if buf.len() < 12 + len {
+            buf.reserve(12 + len - buf.len());
+            return Ok(None);
+        }
+        let time = buf.get_u64();
+        buf.advance(4);
+        Ok(Some((time, buf.split_to(len).freeze())))
+    }
+}
+
+impl Encoder<Bytes> for TimedCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), io::Error> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros();
+        if item.len() > MAX_TIMED_MESSAGE {
+            return Err(too_long(item.len(), MAX_TIMED_MESSAGE));
+        }
+        dst.reserve(12 + item.len());
+        dst.put_u64(time as u64);
+        dst.put_u32(item.len() as u32);
+        dst.put(item);
+        Ok(())
+    }
+}
+
+fn too_long(len: usize, max: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("a message of {len} bytes is longer than the maximum of {max}"),
+    )
+}
+
+// the SRT payloads in the UDP datagrams of a pcap file, see pcap-savefile(5)
+struct PcapCodec {
+    // whether the numbers are little endian, whether the fraction of the timestamps is nanoseconds
+    // and the link type, once the file header is read
+    header: Option<(bool, bool, u32)>,
+    filter: PcapFilter,
+    // the destination ports and socket ids SRT control packets were seen going to
+    connections: HashSet<(u16, u32)>,
+}
+
+impl PcapCodec {
+    fn new(filter: PcapFilter) -> Self {
+        PcapCodec {
+            header: None,
+            filter,
+            connections: HashSet::new(),
+        }
+    }
+}
+
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+
+impl Decoder for PcapCodec {
+    type Item = (u64, Bytes);
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, io::Error> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+        let (little_endian, nanos, link_type) = match self.header {
+            Some(header) => header,
+            None => {
+                if buf.len() < 24 {
+                    return Ok(None);
+                }
+                let (little_endian, nanos) = match buf[0..4] {
+                    [0xd4, 0xc3, 0xb2, 0xa1] => (true, false),
+                    [0x4d, 0x3c, 0xb2, 0xa1] => (true, true),
+                    [0xa1, 0xb2, 0xc3, 0xd4] => (false, false),
+                    [0xa1, 0xb2, 0x3c, 0x4d] => (false, true),
+                    _ => return Err(invalid("not a pcap file, pcapng isn't supported")),
+                };
+                let header = buf.split_to(24);
+                let link_type = read_u32(little_endian, &header[20..24]) & 0x0fff_ffff;
+                if ![LINKTYPE_ETHERNET, LINKTYPE_RAW, LINKTYPE_LINUX_SLL].contains(&link_type) {
+                    return Err(invalid(
+                        "unsupported link type, expected Ethernet, raw IP or Linux cooked",
+                    ));
+                }
+                self.header = Some((little_endian, nanos, link_type));
+                (little_endian, nanos, link_type)
+            }
+        };
+        loop {
+            if buf.len() < 16 {
+                return Ok(None);
+            }
+            let captured = read_u32(little_endian, &buf[8..12]) as usize;
+            if captured > MAX_PCAP_FRAME {
+                return Err(too_long(captured, MAX_PCAP_FRAME));
+            }
+            if buf.len() < 16 + captured {
+                buf.reserve(16 + captured - buf.len());
+                return Ok(None);
+            }
+            let record = buf.split_to(16 + captured);
+            let seconds = read_u32(little_endian, &record[0..4]) as u64;
+            let fraction = read_u32(little_endian, &record[4..8]) as u64;
+            let time = seconds * 1_000_000 + if nanos { fraction / 1000 } else { fraction };
+            let Some((port, srt)) = srt_datagram(link_type, &record[16..]) else {
+                continue;
+            };
+            let packet = &record[16 + srt.start..16 + srt.end];
+            let socket_id = u32::from_be_bytes(packet[12..16].try_into().unwrap());
+            if packet[0] & 0x80 != 0 {
+                // control packets of a known type, to a socket that's connected
+                let control_type = u16::from_be_bytes([packet[0], packet[1]]) & 0x7fff;
+                if (control_type <= 7 || control_type == 0x7fff) && socket_id != 0 {
+                    self.connections.insert((port, socket_id));
+                }
+                continue;
+            }
+            let wanted = if self.filter.any() {
+                self.connections.contains(&(port, socket_id))
+            } else {
+                self.filter.matches(port, socket_id)
+            };
+            let encrypted = packet[4] & 0x18 != 0;
+            let retransmitted = packet[4] & 0x04 != 0;
+            if wanted && !encrypted && !retransmitted {
+                let mut record = record.freeze();
+                return Ok(Some((
+                    time,
+                    record
+                        .split_off(16 + srt.start + 16)
+                        .split_to(srt.len() - 16),
+                )));
+            }
+        }
+    }
+}
+
+fn read_u32(little_endian: bool, bytes: &[u8]) -> u32 {
+    let bytes = bytes.try_into().unwrap();
+    if little_endian {
+        u32::from_le_bytes(bytes)
+    } else {
+        u32::from_be_bytes(bytes)
+    }
+}
+
+// the destination port of the UDP datagram in a captured frame and where the SRT packet in it is,
+// if it's long enough for one
+fn srt_datagram(link_type: u32, frame: &[u8]) -> Option<(u16, std::ops::Range<usize>)> {
+    let be16 = |at: usize| Some(u16::from_be_bytes(frame.get(at..at + 2)?.try_into().ok()?));
+    let (mut ip, mut ether_type) = match link_type {
+        LINKTYPE_ETHERNET => (14, be16(12)?),
+        LINKTYPE_LINUX_SLL => (16, be16(14)?),
+        _ => match frame.first()? >> 4 {
+            4 => (0, 0x0800),
+            6 => (0, 0x86dd),
+            _ => return None,
+        },
+    };
+    // VLAN tags
+    while ether_type == 0x8100 || ether_type == 0x88a8 {
+        ether_type = be16(ip + 2)?;
+        ip += 4;
+    }
+    let udp = match ether_type {
+        // not fragmented, UDP
+        0x0800 if be16(ip + 6)? & 0x3fff == 0 && *frame.get(ip + 9)? == 17 => {
+            ip + (*frame.get(ip)? as usize & 0x0f) * 4
+        }
+        0x86dd if *frame.get(ip + 6)? == 17 => ip + 40,
+        _ => return None,
+    };
+    // the length of the datagram leaves out the padding of short Ethernet frames
+    let end = udp + be16(udp + 4)? as usize;
+    let srt = udp + 8;
+    if end > frame.len() || end < srt + 16 {
+        return None;
+    }
+    Some((be16(udp + 2)?, srt..end))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // an Ethernet frame with an IPv4 UDP datagram to port carrying an SRT packet
+    fn frame(port: u16, srt: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0; 12];
+        frame.extend([0x08, 0x00]);
+        let ip_len = 20 + 8 + srt.len();
+        frame.extend([
+            0x45,
+            0,
+            (ip_len >> 8) as u8,
+            ip_len as u8,
+            0,
+            0,
+            0x40,
+            0,
+            64,
+            17,
+        ]);
+        frame.extend([0; 10]);
+        let udp_len = 8 + srt.len();
+        frame.extend([
+            0x07,
+            0xd0,
+            (port >> 8) as u8,
+            port as u8,
+            (udp_len >> 8) as u8,
+            udp_len as u8,
+            0,
+            0,
+        ]);
+        frame.extend(srt);
+        // padded to the minimum Ethernet frame
+        frame.resize(frame.len().max(60), 0);
+        frame
+    }
+
+    fn data(flags: u8, socket_id: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![
+            0,
+            0,
+            0,
+            1,
+            0x80 | flags,
+            0,
+            0,
+            1,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            socket_id,
+        ];
+        packet.extend(payload);
+        packet
+    }
+
+    fn capture(packets: &[(u32, u32, u16, Vec<u8>)]) -> BytesMut {
+        let mut capture = BytesMut::new();
+        capture.put_u32_le(0xa1b2c3d4);
+        capture.put_u16_le(2);
+        capture.put_u16_le(4);
+        capture.put_slice(&[0; 8]);
+        capture.put_u32_le(65535);
+        capture.put_u32_le(LINKTYPE_ETHERNET);
+        for (seconds, micros, port, srt) in packets {
+            let frame = frame(*port, srt);
+            capture.put_u32_le(*seconds);
+            capture.put_u32_le(*micros);
+            capture.put_u32_le(frame.len() as u32);
+            capture.put_u32_le(frame.len() as u32);
+            capture.put_slice(&frame);
+        }
+        capture
+    }
+
+    // an ACKACK to socket 7
+    const ACKACK: [u8; 16] = [0x80, 0x06, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 7];
+
+    #[test]
+    fn pcap() {
+        let mut capture = capture(&[
+            // before the connection is seen
+            (1, 0, 2001, data(0, 7, b"early")),
+            (1, 100_000, 2001, ACKACK.to_vec()),
+            (1, 500_000, 2001, data(0, 7, b"first")),
+            // a control packet, a retransmission and an encrypted packet
+            (
+                1,
+                600_000,
+                2001,
+                vec![0x80, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 7],
+            ),
+            (1, 700_000, 2001, data(0x04, 7, b"first")),
+            (1, 800_000, 2001, data(0x08, 7, b"secret")),
+            // other traffic, like DNS, and another connection
+            (1, 900_000, 53, data(0, 7, b"not srt")),
+            (1, 950_000, 2001, data(0, 8, b"other")),
+            (2, 0, 2001, data(0, 7, b"second")),
+        ]);
+
+        let mut codec = PcapCodec::new(PcapFilter::default());
+        // split anywhere
+        let mut tail = capture.split_off(30);
+        assert_eq!(codec.decode(&mut capture).unwrap(), None);
+        capture.unsplit(tail.split_to(250));
+        assert_eq!(
+            codec.decode(&mut capture).unwrap(),
+            Some((1_500_000, Bytes::from("first")))
+        );
+        capture.unsplit(tail);
+        assert_eq!(
+            codec.decode(&mut capture).unwrap(),
+            Some((2_000_000, Bytes::from("second")))
+        );
+        assert_eq!(codec.decode(&mut capture).unwrap(), None);
+        assert!(capture.is_empty());
+
+        assert!(PcapCodec::new(PcapFilter::default())
+            .decode(&mut BytesMut::from(
+                &[0x0a, 0x0d, 0x0d, 0x0a][..].repeat(6)[..]
+            ))
+            .is_err());
+    }
+
+    #[test]
+    fn pcap_filter() {
+        let packets = [
+            (1, 0, 2001, data(0, 7, b"first")),
+            (1, 100_000, 53, data(0, 7, b"not srt")),
+            (1, 200_000, 2001, data(0, 8, b"other")),
+        ];
+        let decode = |filter: PcapFilter| {
+            let mut capture = capture(&packets);
+            let mut codec = PcapCodec::new(filter);
+            let mut payloads = vec![];
+            while let Some((_, payload)) = codec.decode(&mut capture).unwrap() {
+                payloads.push(payload);
+            }
+            payloads
+        };
+        let filter = |args: &[(&str, &str)]| match Framing::parse(args.iter().copied()).unwrap() {
+            Framing::Pcap(filter) => filter,
+            framing => panic!("{framing:?}"),
+        };
+
+        // without a connection seen, nothing
+        assert!(decode(PcapFilter::default()).is_empty());
+        assert_eq!(
+            decode(filter(&[("framing", "pcap"), ("port", "2001")])),
+            ["first", "other"]
+        );
+        assert_eq!(
+            decode(filter(&[
+                ("framing", "pcap"),
+                ("port", "2001"),
+                ("socket_id", "7")
+            ])),
+            ["first"]
+        );
+        assert!(Framing::parse([("framing", "pcap"), ("port", "0")].into_iter()).is_err());
+        assert!(Framing::parse([("framing", "timed"), ("port", "2001")].into_iter()).is_err());
+
+        // a frame longer than any capture has
+        let mut capture = capture(&[]);
+        capture.put_u32_le(1);
+        capture.put_u32_le(0);
+        capture.put_u32_le(u32::MAX);
+        capture.put_u32_le(u32::MAX);
+        assert!(PcapCodec::new(PcapFilter::default())
+            .decode(&mut capture)
+            .is_err());
+    }
+
+    #[test]
+    fn timed() {
+        let mut buf = BytesMut::new();
+        TimedCodec.encode(Bytes::from("hello"), &mut buf).unwrap();
+        TimedCodec.encode(Bytes::from("world"), &mut buf).unwrap();
+        let (first, hello) = TimedCodec.decode(&mut buf).unwrap().unwrap();
+        let (second, world) = TimedCodec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!((hello, world), (Bytes::from("hello"), Bytes::from("world")));
+        assert!(first <= second);
+        assert_eq!(TimedCodec.decode(&mut buf).unwrap(), None);
+
+        // a length that's corrupt, or not timed framing
+        buf.put_u64(0);
+        buf.put_u32(u32::MAX);
+        assert!(TimedCodec.decode(&mut buf).is_err());
+        assert!(buf.capacity() < MAX_TIMED_MESSAGE);
+    }
+}
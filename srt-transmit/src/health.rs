@@ -0,0 +1,190 @@
+// Supervision by systemd, see sd_notify(3): readiness, a status line with the packet flow, and a
+// watchdog heartbeat that only beats while data flows, so the service manager restarts a relay
+// whose stream stalled
+//
+// Notifications are only sent when systemd set NOTIFY_SOCKET, the heartbeat when it set
+// WATCHDOG_USEC too, i.e. the unit has WatchdogSec. Until the first packet arrives the relay is
+// waiting for its input, which isn't a stall.
+use std::{
+    env,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use tokio::{spawn, time::interval};
+
+static PACKETS: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug)]
+pub struct State {
+    pub packets: u64,
+    pub bytes: u64,
+    // when the packet count last went up
+    pub last_flow: Option<Instant>,
+    pub stalled: bool,
+}
+
+// count a packet relayed from the input to the outputs
+pub fn record(bytes: usize) {
+    PACKETS.fetch_add(1, Ordering::Relaxed);
+    BYTES.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+// the flow as of the last check, None before the relay started
+pub fn state() -> Option<State> {
+    *STATE.lock().unwrap()
+}
+
+// tells the service manager the relay is up and keeps it posted
+pub fn start(stall_after: Duration) {
+    let notifier = Notifier::from_env();
+    let watchdog = watchdog_interval();
+    if let Some(notifier) = &notifier {
+        notifier.notify("READY=1\nSTATUS=Waiting for data");
+    }
+    *STATE.lock().unwrap() = Some(State {
+        packets: 0,
+        bytes: 0,
+        last_flow: None,
+        stalled: false,
+    });
+
+    // beat twice per watchdog period, as sd_watchdog_enabled(3) recommends
+    let period = watchdog.map_or(STATUS_INTERVAL, |w| (w / 2).min(STATUS_INTERVAL));
+    spawn(async move {
+        let mut ticks = interval(period);
+        let mut last_status = Instant::now();
+        let mut last_bytes = 0;
+        loop {
+            let now = ticks.tick().await.into_std();
+            let state = check(now, stall_after);
+            let Some(notifier) = &notifier else {
+                continue;
+            };
+            if watchdog.is_some() && !state.stalled {
+                notifier.notify("WATCHDOG=1");
+            }
+            if now - last_status >= STATUS_INTERVAL {
+                let rate =
+                    (state.bytes - last_bytes) * 8 / (now - last_status).as_millis().max(1) as u64;
+                notifier.notify(&format!("STATUS={}", status(&state, rate)));
+                last_status = now;
+                last_bytes = state.bytes;
+            }
+        }
+    });
+}
+
+pub fn stopping() {
+    if let Some(notifier) = Notifier::from_env() {
+        notifier.notify("STOPPING=1");
+    }
+}
+
+fn check(now: Instant, stall_after: Duration) -> State {
+    let mut state = STATE.lock().unwrap();
+    let state = state.as_mut().unwrap();
+    let packets = PACKETS.load(Ordering::Relaxed);
+    if packets != state.packets {
+        state.packets = packets;
+        state.bytes = BYTES.load(Ordering::Relaxed);
+        state.last_flow = Some(now);
+    }
+    let stalled = matches!(state.last_flow, Some(last) if now - last >= stall_after);
+    if stalled != state.stalled {
+        if stalled {
+            warn!("Stream stalled, no data for {:?}", stall_after);
+        } else {
+            info!("Stream flowing again");
+        }
+        state.stalled = stalled;
+    }
+    *state
+}
+
+fn status(state: &State, kbps: u64) -> String {
+    match state.last_flow {
+        None => "Waiting for data".to_string(),
+        Some(last) if state.stalled => format!(
+            "Stalled for {}s, {} packets relayed",
+            last.elapsed().as_secs(),
+            state.packets
+        ),
+        Some(_) => format!("Relaying {} kb/s, {} packets", kbps, state.packets),
+    }
+}
+
+fn watchdog_interval() -> Option<Duration> {
+    // the watchdog is meant for another process when WATCHDOG_PID is someone else
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse() != Ok(std::process::id()) {
+            return None;
+        }
+    }
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    Some(Duration::from_micros(usec)).filter(|w| !w.is_zero())
+}
+
+#[cfg(unix)]
+struct Notifier {
+    socket: std::os::unix::net::UnixDatagram,
+    address: std::os::unix::net::SocketAddr,
+}
+
+#[cfg(unix)]
+impl Notifier {
+    fn from_env() -> Option<Self> {
+        use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+        let path = env::var_os("NOTIFY_SOCKET")?;
+        let address = match path.to_str().and_then(|p| p.strip_prefix('@')) {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Some(name) => {
+                #[cfg(target_os = "android")]
+                use std::os::android::net::SocketAddrExt;
+                #[cfg(target_os = "linux")]
+                use std::os::linux::net::SocketAddrExt;
+                SocketAddr::from_abstract_name(name)
+            }
+            _ => SocketAddr::from_pathname(&path),
+        };
+        let notifier = address.and_then(|address| {
+            let socket = UnixDatagram::unbound()?;
+            Ok(Self { socket, address })
+        });
+        match notifier {
+            Ok(notifier) => Some(notifier),
+            Err(e) => {
+                warn!("Invalid NOTIFY_SOCKET {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    fn notify(&self, state: &str) {
+        if let Err(e) = self.socket.send_to_addr(state.as_bytes(), &self.address) {
+            warn!("Failed to notify the service manager: {}", e);
+        }
+    }
+}
+
+// there's no systemd to notify
+#[cfg(not(unix))]
+struct Notifier;
+
+#[cfg(not(unix))]
+impl Notifier {
+    fn from_env() -> Option<Self> {
+        None
+    }
+
+    fn notify(&self, _state: &str) {}
+}
@@ -1,11 +1,22 @@
+#[cfg(feature = "admin")]
+mod admin;
+mod connections;
+mod failover;
+mod fec;
+mod framing;
+mod health;
+mod metrics;
+mod ping;
+mod record;
 mod streamer_server;
+mod supervisor;
 
 use std::{
     borrow::Cow,
     convert::TryInto,
     net::{IpAddr, SocketAddr, ToSocketAddrs},
     ops::Deref,
-    path::Path,
+    path::{Path, PathBuf},
     pin::Pin,
     process::exit,
     task::{Context, Poll},
@@ -16,6 +27,7 @@ use anyhow::{anyhow, bail, format_err, Error};
 use bytes::Bytes;
 use clap::{Arg, ArgAction, Command};
 use log::info;
+use socket2::SockRef;
 use url::{Host, Url};
 
 use futures::{
@@ -25,23 +37,21 @@ use futures::{
     stream::{self, once, unfold, BoxStream},
     try_join,
 };
-use tokio::{
-    io::{AsyncRead, AsyncReadExt},
-    net::TcpListener,
-    net::TcpStream,
-    net::UdpSocket,
-    spawn,
-};
-use tokio_util::{codec::BytesCodec, codec::Framed, codec::FramedWrite, udp::UdpFramed};
+use tokio::{net::TcpListener, net::TcpStream, net::UdpSocket, spawn};
+use tokio_util::{codec::BytesCodec, codec::Framed, udp::UdpFramed};
 
 use srt_tokio::{
     options::{
-        url_parse, BindOptions, CallerOptions, ListenerOptions, RendezvousOptions, SocketOptions,
+        url_parse, BindOptions, ByteCount, CallerOptions, DataRate, ListenerOptions,
+        LiveBandwidthMode, PacketCount, PacketSize, Percent, RendezvousOptions, SocketOptions,
     },
     SrtSocket,
 };
 
+use fec::FecEncoder;
+use framing::Framing;
 use streamer_server::*;
+use supervisor::Supervisor;
 
 const AFTER_HELPTEXT: &str = include_str!("helptext.txt");
 
@@ -62,53 +72,119 @@ enum DataType<'a> {
     File(&'a Path),
 }
 
-fn read_to_stream(read: impl AsyncRead + Unpin) -> impl Stream<Item = Result<Bytes, Error>> {
-    stream::unfold(read, |mut source| async move {
-        let mut buf = [0; 1316];
-        let bytes_read = match source.read(&mut buf[..]).await {
-            Ok(0) => return None,
-            Ok(bytes_read) => bytes_read,
-            Err(e) => return Some((Err(Error::from(e)), source)),
-        };
+fn parse_data_type(arg: &str) -> DataType<'_> {
+    match url_parse(arg, false) {
+        Err(_) => DataType::File(Path::new(arg)),
+        Ok(url) => DataType::Url(url),
+    }
+}
+
+// file://con is stdin/stdout, as in srt-live-transmit, anything else is a path, which may be a
+// named pipe
+fn parse_file_url(url: &Url) -> Result<(Option<PathBuf>, Framing), Error> {
+    let framing = Framing::parse(url.query_pairs())?;
+    let path = match url.host_str() {
+        Some("con") => None,
+        None | Some("") => Some(PathBuf::from(url.path())),
+        Some(host) => bail!(
+            "Unexpected host {} in file URL, expected file://con or file:///path",
+            host
+        ),
+    };
+    Ok((path, framing))
+}
 
-        Some((Ok(Bytes::copy_from_slice(&buf[..bytes_read])), source))
+fn read_file(path: Option<PathBuf>, framing: Framing) -> StreamStream {
+    once(async move {
+        Ok(match path {
+            None => framing.read(tokio::io::stdin()),
+            Some(path) => framing.read(tokio::fs::File::open(path).await?),
+        })
     })
+    .boxed()
 }
 
-fn parse_srt_args<C>(args: impl Iterator<Item = (C, C)>) -> Result<SocketOptions, Error>
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum SrtMode {
+    Caller,
+    Listener,
+    Rendezvous,
+}
+
+// the socket options and connection parameters specified in a srt:// url
+struct SrtArgs {
+    options: SocketOptions,
+    mode: Option<SrtMode>,
+    stream_id: Option<String>,
+}
+
+fn parse_millis(key: &str, value: &str) -> Result<Duration, Error> {
+    let millis = value
+        .parse::<f64>()
+        .map_err(|e| format_err!("Failed to parse {key}: {e}"))?;
+    if !millis.is_finite() || millis < 0. {
+        bail!("Failed to parse {key}: expected a positive number of milliseconds");
+    }
+    Ok(Duration::from_secs_f64(millis / 1e3))
+}
+
+fn parse_int(key: &str, value: &str) -> Result<u64, Error> {
+    value
+        .parse()
+        .map_err(|e| format_err!("Failed to parse {key} as an integer: {e}"))
+}
+
+fn parse_srt_args<C>(args: impl Iterator<Item = (C, C)>) -> Result<SrtArgs, Error>
 where
     C: Deref<Target = str>,
 {
     let mut key = false;
     let mut options = SocketOptions::default();
+    let mut mode = None;
+    let mut stream_id = None;
+
+    let mut maxbw = None;
+    let mut inputbw = None;
+    let mut mininputbw = None;
+    let mut oheadbw = None;
+
     for (k, v) in args {
         match &*k {
-            "latency_ms" => {
-                let latency = Duration::from_secs_f64(
-                    v.parse::<f64>()
-                        .map_err(|e| format_err!("Failed to parse latency_ms: {e}"))?
-                        / 1e3,
-                );
+            // options named as they are in srt-live-transmit and ffmpeg, see
+            // https://github.com/Haivision/srt/blob/master/docs/apps/srt-live-transmit.md#medium-srt
+            "latency" | "latency_ms" => {
+                let latency = parse_millis(&k, &v)?;
                 options.sender.peer_latency = latency;
                 options.receiver.latency = latency;
             }
-            "peeridletimeout_ms" => {
-                options.session.peer_idle_timeout = Duration::from_secs_f64(
-                    v.parse::<f64>()
-                        .map_err(|e| format_err!("Failed to parse peeridletimeout_ms: {e}"))?
-                        / 1e3,
-                )
+            "rcvlatency" => options.receiver.latency = parse_millis(&k, &v)?,
+            "peerlatency" => options.sender.peer_latency = parse_millis(&k, &v)?,
+            "peeridletimeo" | "peeridletimeout_ms" => {
+                options.session.peer_idle_timeout = parse_millis(&k, &v)?
             }
-            "interface" => {
+            "conntimeo" => options.connect.timeout = parse_millis(&k, &v)?,
+            "adapter" | "interface" => {
                 options.connect.local.set_ip(match v.parse() {
                     Ok(local) => local,
-                    Err(e) => bail!("Failed to parse interface parameter as ip address: {}", e),
+                    Err(e) => bail!("Failed to parse {} parameter as ip address: {}", &*k, e),
                 });
             }
-            "local_port" => options.connect.local.set_port(match v.parse() {
+            "port" | "local_port" => options.connect.local.set_port(match v.parse() {
                 Ok(addr) => addr,
-                Err(e) => bail!("Failed to parse local_port as a 16-bit integer: {}", e),
+                Err(e) => bail!("Failed to parse {} as a 16-bit integer: {}", &*k, e),
             }),
+            "mode" => {
+                mode = Some(match &*v {
+                    "caller" | "client" => SrtMode::Caller,
+                    "listener" | "server" => SrtMode::Listener,
+                    "rendezvous" => SrtMode::Rendezvous,
+                    unrecog => bail!(
+                        "Unexpected value for mode: {}, expected caller, listener or rendezvous",
+                        unrecog
+                    ),
+                })
+            }
+            "streamid" => stream_id = Some(v.to_string()),
             "passphrase" => {
                 options.encryption.passphrase = Some(v.to_string().try_into()?);
             }
@@ -119,6 +195,15 @@ where
                 options.encryption.key_size = size.try_into()?;
                 key = true;
             }
+            "rcvbuf" => options.receiver.buffer_size = ByteCount(parse_int(&k, &v)?),
+            "sndbuf" => options.sender.buffer_size = ByteCount(parse_int(&k, &v)?),
+            "fc" => options.sender.flow_control_window_size = PacketCount(parse_int(&k, &v)?),
+            "mss" => options.session.max_segment_size = PacketSize(parse_int(&k, &v)?),
+            "payloadsize" => options.sender.max_payload_size = PacketSize(parse_int(&k, &v)?),
+            "maxbw" => maxbw = Some(DataRate(parse_int(&k, &v)?)),
+            "inputbw" => inputbw = Some(DataRate(parse_int(&k, &v)?)),
+            "mininputbw" => mininputbw = Some(DataRate(parse_int(&k, &v)?)),
+            "oheadbw" => oheadbw = Some(Percent(parse_int(&k, &v)?)),
             "rendezvous" | "multiplex" | "autoreconnect" | "stats" => (),
             unrecog => bail!("Unrecgonized parameter '{}' for srt", unrecog),
         }
@@ -126,7 +211,32 @@ where
     if key && options.encryption.passphrase.is_none() {
         bail!("pbkeylen specified with no passphrase")
     }
-    Ok(options)
+
+    // maxbw=0 means relative to the input rate in srt-live-transmit
+    options.sender.bandwidth = match (maxbw, inputbw, mininputbw, oheadbw) {
+        (Some(DataRate(0)) | None, None, None, None) => options.sender.bandwidth,
+        (Some(rate), None, None, None) => LiveBandwidthMode::Max(rate),
+        (Some(DataRate(0)) | None, Some(rate), None, overhead) => LiveBandwidthMode::Input {
+            rate,
+            overhead: overhead.unwrap_or(Percent(5)),
+        },
+        (Some(DataRate(0)) | None, None, Some(expected), overhead) => {
+            LiveBandwidthMode::Estimated {
+                expected,
+                overhead: overhead.unwrap_or(Percent(5)),
+            }
+        }
+        (Some(DataRate(0)) | None, None, None, Some(_)) => {
+            bail!("oheadbw specified with no inputbw or mininputbw")
+        }
+        _ => bail!("Only one of maxbw, inputbw or mininputbw may be specified"),
+    };
+
+    Ok(SrtArgs {
+        options,
+        mode,
+        stream_id,
+    })
 }
 
 // get the local port and address from the input url
@@ -167,10 +277,15 @@ enum ConnectionKind {
     Listen(u16),
 }
 
+struct ConnectionOptions {
+    bind: SocketAddr,
+    ttl: Option<u32>,
+}
+
 fn parse_connection_options<C>(
     args: impl Iterator<Item = (C, C)>,
     kind: ConnectionKind,
-) -> Result<SocketAddr, Error>
+) -> Result<ConnectionOptions, Error>
 where
     C: Deref<Target = str>,
 {
@@ -179,6 +294,7 @@ where
         ConnectionKind::Send => "0.0.0.0:0".parse().unwrap(),
         ConnectionKind::Listen(port) => SocketAddr::new("0.0.0.0".parse().unwrap(), port),
     };
+    let mut ttl = None;
 
     for (k, v) in args {
         match (&*k, &*v, kind) {
@@ -201,11 +317,70 @@ where
             ("local_port", _, ConnectionKind::Listen(_)) => {
                 bail!("local_port is incompatiable with udp listen mode")
             }
+            ("ttl", value, ConnectionKind::Send) => {
+                ttl = match value.parse() {
+                    Ok(ttl @ 1..=255) => Some(ttl),
+                    _ => bail!("Failed to parse ttl parameter '{}' as 1-255", value),
+                }
+            }
+            ("ttl", _, ConnectionKind::Listen(_)) => {
+                bail!("ttl is incompatiable with udp listen mode")
+            }
             (unrecog, _, _) => bail!("Unrecognized udp flag: {}", unrecog),
         }
     }
 
-    Ok(addr)
+    Ok(ConnectionOptions { bind: addr, ttl })
+}
+
+// to a multicast group, ttl is the multicast ttl (hops) and interface also picks the interface
+// the group is sent out of, otherwise that's left to the routing table
+async fn bind_udp_output(
+    options: ConnectionOptions,
+    target: SocketAddr,
+) -> Result<UdpSocket, Error> {
+    let socket = UdpSocket::bind(options.bind).await?;
+    match (target.ip().is_multicast(), options.bind.ip()) {
+        (false, _) => {
+            if let Some(ttl) = options.ttl {
+                socket.set_ttl(ttl)?;
+            }
+        }
+        (true, IpAddr::V4(interface)) => {
+            if let Some(ttl) = options.ttl {
+                socket.set_multicast_ttl_v4(ttl)?;
+            }
+            if !interface.is_unspecified() {
+                SockRef::from(&socket).set_multicast_if_v4(&interface)?;
+            }
+        }
+        (true, IpAddr::V6(interface)) => {
+            if let Some(ttl) = options.ttl {
+                SockRef::from(&socket).set_multicast_hops_v6(ttl)?;
+            }
+            if !interface.is_unspecified() {
+                bail!("interface is not supported for IPv6 multicast output");
+            }
+        }
+    }
+    Ok(socket)
+}
+
+// the FEC matrix of an rtp output, the other settings are the ones of a udp output
+fn parse_rtp_args(url: &Url) -> Result<(FecEncoder, ConnectionOptions), Error> {
+    let (mut columns, mut rows) = (10, 10);
+    let mut rest = Vec::new();
+    for (k, v) in url.query_pairs() {
+        match &*k {
+            "fec_columns" => columns = parse_int(&k, &v)?.try_into()?,
+            "fec_rows" => rows = parse_int(&k, &v)?.try_into()?,
+            _ => rest.push((k, v)),
+        }
+    }
+    Ok((
+        FecEncoder::new(columns, rows)?,
+        parse_connection_options(rest.into_iter(), ConnectionKind::Send)?,
+    ))
 }
 
 fn parse_socket_options(
@@ -213,27 +388,56 @@ fn parse_socket_options(
     input_addr: Option<SocketAddr>,
     input_local_port: u16,
 ) -> Result<BindOptions, Error> {
-    let socket_options = parse_srt_args(input_url.query_pairs())?;
+    let SrtArgs {
+        options: socket_options,
+        mode,
+        stream_id,
+    } = parse_srt_args(input_url.query_pairs())?;
 
     let rendezvous_v = parse_rendezvous(input_url);
 
-    let bind_options = match (input_addr, rendezvous_v.as_deref()) {
-        // address but not rendezvous -> connect
-        (Some(addr), None) => BindOptions::Call(CallerOptions::with(addr, None, socket_options)?),
-        // no address or rendezvous -> listen
-        (None, None) => {
-            if input_url.query_pairs().any(|(a, _)| a == "local_port") {
+    use SrtMode::*;
+    let mode = match (mode, rendezvous_v.as_deref()) {
+        (mode, None) => mode,
+        (None | Some(Rendezvous), Some("")) => Some(Rendezvous),
+        (Some(_), Some("")) => bail!("The rendezvous flag conflicts with the specified mode"),
+        (_, Some(unex)) => bail!("Unexpected value for rendezvous: {}, expected empty", unex),
+    };
+
+    if stream_id.is_some() && !matches!((mode, input_addr), (None | Some(Caller), Some(_))) {
+        bail!("streamid is only supported for caller connections")
+    }
+
+    let bind_options = match (mode, input_addr) {
+        // address but no mode -> connect
+        (None | Some(Caller), Some(addr)) => BindOptions::Call(CallerOptions::with(
+            addr,
+            stream_id.as_deref(),
+            socket_options,
+        )?),
+        // no address or mode -> listen
+        (None | Some(Listener), None) => {
+            if input_url
+                .query_pairs()
+                .any(|(a, _)| a == "local_port" || a == "port")
+            {
                 bail!("local_port is incompatible with listen connection technique")
             }
             BindOptions::Listen(ListenerOptions::with(input_local_port, socket_options)?)
         }
-        // address and rendezvous flag -> rendezvous
-        (Some(addr), Some("")) => {
+        // address and listener mode -> listen on the specified address
+        (Some(Listener), Some(addr)) => {
+            BindOptions::Listen(ListenerOptions::with(addr, socket_options)?)
+        }
+        // address and rendezvous mode -> rendezvous
+        (Some(Rendezvous), Some(addr)) => {
             BindOptions::Rendezvous(RendezvousOptions::with(addr, socket_options)?)
         }
         // various invalid combinations
-        (None, Some("")) => bail!("Cannot have rendezvous connection without host specified"),
-        (_, Some(unex)) => bail!("Unexpected value for rendezvous: {}, expected empty", unex),
+        (Some(Rendezvous), None) => {
+            bail!("Cannot have rendezvous connection without host specified")
+        }
+        (Some(Caller), None) => bail!("Cannot have caller connection without host specified"),
     };
 
     Ok(bind_options)
@@ -267,7 +471,8 @@ async fn make_srt_input(
     input_url: Url,
     input_addr: Option<SocketAddr>,
     input_local_port: u16,
-) -> Result<BoxStream<'static, Bytes>, Error> {
+    losses: record::Losses,
+) -> Result<ByteStream, Error> {
     let bind_options = parse_socket_options(&input_url, input_addr, input_local_port);
 
     // make sure multiplex was not specified
@@ -277,13 +482,26 @@ async fn make_srt_input(
 
     let mut srt_socket = SrtSocket::bind(bind_options?).await?;
     start_stat_task_if_requested(&mut srt_socket, &input_url)?;
-    Ok(srt_socket.map(Result::unwrap).map(|(_, b)| b).boxed())
+    srt_socket
+        .set_gap_handler(move |_, lost| losses.add((lost.end - lost.start).into()))
+        .await?;
+    let kicked = connections::register("input", &mut srt_socket).kicked();
+    Ok(srt_socket
+        .take_until(kicked)
+        .map_ok(|(_, b)| b)
+        .err_into()
+        .boxed())
 }
 
-fn resolve_input(
-    input_url: DataType,
-) -> Result<BoxStream<'static, Result<BoxStream<'static, Bytes>, Error>>, Error> {
+fn resolve_input(input_url: DataType, losses: &record::Losses) -> Result<StreamStream, Error> {
     Ok(match input_url {
+        DataType::Url(input_url) if input_url.scheme() == "file" => {
+            let (path, framing) = parse_file_url(&input_url)?;
+            read_file(path, framing)
+        }
+        DataType::Url(input_url) if input_url.scheme() == "record" => {
+            record::input(record::Settings::parse(&input_url)?)
+        }
         DataType::Url(input_url) => {
             let (input_local_port, input_addr) = local_port_addr(&input_url, "input")?;
             match input_url.scheme() {
@@ -294,33 +512,51 @@ fn resolve_input(
                 ),
                 "udp" => once(async move {
                     Ok(UdpFramed::new(
-                        UdpSocket::bind(&parse_connection_options(
-                            input_url.query_pairs(),
-                            ConnectionKind::Listen(input_local_port),
-                        )?)
+                        UdpSocket::bind(
+                            parse_connection_options(
+                                input_url.query_pairs(),
+                                ConnectionKind::Listen(input_local_port),
+                            )?
+                            .bind,
+                        )
                         .await?,
                         BytesCodec::new(),
                     )
-                    .map(Result::unwrap)
-                    .map(|(b, _)| b.freeze())
+                    .map_ok(|(b, _)| b.freeze())
+                    .err_into()
                     .boxed())
                 })
                 .boxed(),
                 "srt" => {
+                    let losses = losses.clone();
                     if input_url.query_pairs().any(|(k, _)| k == "autoreconnect") {
                         unfold(
                             (input_addr, input_url, input_local_port),
-                            move |(input_addr, input_url, input_local_port)| async move {
-                                Some((
-                                    make_srt_input(input_url.clone(), input_addr, input_local_port)
+                            move |(input_addr, input_url, input_local_port)| {
+                                let losses = losses.clone();
+                                async move {
+                                    Some((
+                                        make_srt_input(
+                                            input_url.clone(),
+                                            input_addr,
+                                            input_local_port,
+                                            losses,
+                                        )
                                         .await,
-                                    (input_addr, input_url, input_local_port),
-                                ))
+                                        (input_addr, input_url, input_local_port),
+                                    ))
+                                }
                             },
                         )
                         .boxed()
                     } else {
-                        once(make_srt_input(input_url, input_addr, input_local_port)).boxed()
+                        once(make_srt_input(
+                            input_url,
+                            input_addr,
+                            input_local_port,
+                            losses,
+                        ))
+                        .boxed()
                     }
                 }
                 "tcp" => {
@@ -331,8 +567,8 @@ fn resolve_input(
                                     Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
                                     Ok(stream) => {
                                         return Ok(Framed::new(stream, BytesCodec::new())
-                                            .map(Result::unwrap)
-                                            .map(|b| b.freeze())
+                                            .map_ok(|b| b.freeze())
+                                            .err_into()
                                             .boxed())
                                     }
                                 }
@@ -341,15 +577,15 @@ fn resolve_input(
                         .boxed()
                     } else {
                         once(async move {
-                            let input = &parse_connection_options(
+                            let input = parse_connection_options(
                                 input_url.query_pairs(),
                                 ConnectionKind::Listen(input_local_port),
                             )?;
-                            let listener = TcpListener::bind(input).await?;
+                            let listener = TcpListener::bind(input.bind).await?;
                             let (stream, _) = listener.accept().await?;
                             Ok(Framed::new(stream, BytesCodec::new())
-                                .map(Result::unwrap)
-                                .map(|b| b.freeze())
+                                .map_ok(|b| b.freeze())
+                                .err_into()
                                 .boxed())
                         })
                         .boxed()
@@ -358,27 +594,27 @@ fn resolve_input(
                 s => bail!("unrecognized scheme: {} designated in input url", s),
             }
         }
-        DataType::File(file) if file == Path::new("-") => once(async move {
-            Ok(read_to_stream(tokio::io::stdin())
-                .map(Result::unwrap)
-                .boxed())
-        })
-        .boxed(),
-        DataType::File(file) => {
-            let file = file.to_owned();
-            once(async move {
-                let f = tokio::fs::File::open(file).await?;
-
-                Ok(read_to_stream(f).map(Result::unwrap).boxed())
-            })
-            .boxed()
-        }
+        DataType::File(file) if file == Path::new("-") => read_file(None, Framing::default()),
+        DataType::File(file) => read_file(Some(file.to_owned()), Framing::default()),
     })
 }
 
+// the data of a connection, which ends with an error if it fails
+type ByteStream = BoxStream<'static, Result<Bytes, Error>>;
+type StreamStream = BoxStream<'static, Result<ByteStream, Error>>;
 type BoxSink = Pin<Box<dyn Sink<Bytes, Error = Error> + Send>>;
 type SinkStream = BoxStream<'static, Result<BoxSink, Error>>;
 
+fn write_file(path: Option<PathBuf>, framing: Framing) -> SinkStream {
+    once(async move {
+        Ok(match path {
+            None => framing.write(tokio::io::stdout())?,
+            Some(path) => framing.write(tokio::fs::File::create(path).await?)?,
+        })
+    })
+    .boxed()
+}
+
 async fn make_srt_ouput(
     output_addr: Option<SocketAddr>,
     output_url: Url,
@@ -411,15 +647,24 @@ async fn make_srt_ouput(
         None => {
             let mut srt_socket = SrtSocket::bind(bind_options).await?;
             start_stat_task_if_requested(&mut srt_socket, &output_url)?;
-            Ok(srt_socket
+            let registration = connections::register("output", &mut srt_socket);
+            let sink = srt_socket
                 .with(|b| future::ok((Instant::now(), b)))
-                .boxed_sink())
+                .boxed_sink();
+            Ok(connections::kickable(sink, registration))
         }
     }
 }
 
-fn resolve_output(output_url: DataType) -> Result<SinkStream, Error> {
+fn resolve_output(output_url: DataType, losses: &record::Losses) -> Result<SinkStream, Error> {
     Ok(match output_url {
+        DataType::Url(output_url) if output_url.scheme() == "file" => {
+            let (path, framing) = parse_file_url(&output_url)?;
+            write_file(path, framing)
+        }
+        DataType::Url(output_url) if output_url.scheme() == "record" => {
+            record::output(record::Settings::parse(&output_url)?, losses.clone())
+        }
         DataType::Url(output_url) => {
             let (output_local_port, output_addr) = local_port_addr(&output_url, "output")?;
             match output_url.scheme() {
@@ -429,10 +674,13 @@ fn resolve_output(output_url: DataType) -> Result<SinkStream, Error> {
                 ),
                 "udp" => once(async move {
                     Ok(UdpFramed::new(
-                        UdpSocket::bind(&parse_connection_options(
-                            output_url.query_pairs(),
-                            ConnectionKind::Send,
-                        )?)
+                        bind_udp_output(
+                            parse_connection_options(
+                                output_url.query_pairs(),
+                                ConnectionKind::Send,
+                            )?,
+                            output_addr.unwrap(),
+                        )
                         .await?,
                         BytesCodec::new(),
                     )
@@ -440,6 +688,32 @@ fn resolve_output(output_url: DataType) -> Result<SinkStream, Error> {
                     .boxed_sink())
                 })
                 .boxed(),
+                "rtp" if output_addr.is_none() => bail!(
+                    "Must designate a ip to send to to send RTP. \
+                     Example: rtp://127.0.0.1:1234, not rtp://:1234"
+                ),
+                "rtp" => once(async move {
+                    let media_addr = output_addr.unwrap();
+                    let mut fec_addr = media_addr;
+                    fec_addr.set_port(media_addr.port().wrapping_add(2));
+                    let (mut encoder, options) = parse_rtp_args(&output_url)?;
+                    Ok(UdpFramed::new(
+                        bind_udp_output(options, media_addr).await?,
+                        BytesCodec::new(),
+                    )
+                    .with_flat_map(move |b: Bytes| {
+                        let (media, fec) = encoder.push(&b);
+                        stream::iter(
+                            Some((media, media_addr))
+                                .into_iter()
+                                .chain(fec.map(|fec| (fec, fec_addr)))
+                                .map(Ok),
+                        )
+                    })
+                    .sink_map_err(Error::from)
+                    .boxed_sink())
+                })
+                .boxed(),
                 "srt" => {
                     if output_url.query_pairs().any(|(k, _)| k == "autoreconnect") {
                         unfold(
@@ -478,11 +752,11 @@ fn resolve_output(output_url: DataType) -> Result<SinkStream, Error> {
                         .boxed()
                     } else {
                         once(async move {
-                            let output = &parse_connection_options(
+                            let output = parse_connection_options(
                                 output_url.query_pairs(),
                                 ConnectionKind::Listen(output_local_port),
                             )?;
-                            let listener = TcpListener::bind(output).await?;
+                            let listener = TcpListener::bind(output.bind).await?;
                             let (stream, _) = listener.accept().await?;
                             Ok(Framed::new(stream, BytesCodec::new())
                                 .with(move |b| future::ready(Ok(b)))
@@ -494,22 +768,8 @@ fn resolve_output(output_url: DataType) -> Result<SinkStream, Error> {
                 s => bail!("unrecognized scheme '{}' designated in output url", s),
             }
         }
-        DataType::File(file) if file == Path::new("-") => once(async move {
-            Ok(FramedWrite::new(tokio::io::stdout(), BytesCodec::new())
-                .with(move |b| future::ready(Ok(b)))
-                .boxed_sink())
-        })
-        .boxed(),
-        DataType::File(file) => {
-            let file = file.to_owned();
-            once(async move {
-                let output = tokio::fs::File::create(file).await?;
-                Ok(FramedWrite::new(output, BytesCodec::new())
-                    .with(move |b| future::ready(Ok(b)))
-                    .boxed_sink())
-            })
-            .boxed()
-        }
+        DataType::File(file) if file == Path::new("-") => write_file(None, Framing::default()),
+        DataType::File(file) => write_file(Some(file.to_owned()), Framing::default()),
     })
 }
 
@@ -605,6 +865,48 @@ impl Sink<Bytes> for MultiSinkFlatten {
     }
 }
 
+// the input of a relay, backed up by the failover inputs if there are any, and its outputs, which
+// record the losses of the inputs
+fn resolve_route<'a>(
+    input: &str,
+    failover: &[&str],
+    failover_after: Duration,
+    outputs: impl Iterator<Item = &'a str>,
+) -> Result<(StreamStream, MultiSinkFlatten), Error> {
+    let losses = record::Losses::default();
+    let stream_stream = if failover.is_empty() {
+        resolve_input(parse_data_type(input), &losses)?
+    } else {
+        let mut inputs = vec![];
+        for input in Some(&input).into_iter().chain(failover) {
+            let stream_stream = resolve_input(parse_data_type(input), &losses)?;
+            inputs.push((input.to_string(), stream_stream));
+        }
+        failover::failover(inputs, failover_after)
+    };
+
+    let mut sink_streams = vec![];
+    for to in outputs.map(|to| resolve_output(parse_data_type(to), &losses)) {
+        sink_streams.push(to?);
+    }
+    Ok((stream_stream, MultiSinkFlatten::new(sink_streams.drain(..))))
+}
+
+// copy the input to the outputs until it ends
+async fn relay(mut stream_stream: StreamStream, mut sinks: MultiSinkFlatten) -> Result<(), Error> {
+    // poll sink and stream in parallel, only yielding when there is something ready for the sink and the stream is good.
+    while let (_, Some(stream)) = try_join!(
+        future::poll_fn(|cx| Pin::new(&mut sinks).poll_ready(cx)),
+        stream_stream.try_next()
+    )? {
+        // let a: () = &mut *stream;
+        sinks
+            .send_all(&mut stream.inspect_ok(|data| health::record(data.len())))
+            .await?;
+    }
+    sinks.close().await
+}
+
 #[tokio::main]
 async fn main() {
     if let Err(e) = run().await {
@@ -614,11 +916,22 @@ async fn main() {
 }
 
 async fn run() -> Result<(), Error> {
-    pretty_env_logger::formatted_builder()
+    // the admin endpoint can change the level later, RUST_LOG's per module directives still apply
+    #[cfg(feature = "admin")]
+    let log_level = pretty_env_logger::formatted_builder()
+        .parse_default_env()
+        .build()
+        .filter();
+    let mut logger = pretty_env_logger::formatted_builder();
+    logger
         .parse_default_env()
         // .format(|buf, record| writeln!(buf, "{} [{}] {}", record.args()))
-        .format_timestamp_micros()
-        .init();
+        .format_timestamp_micros();
+    #[cfg(feature = "admin")]
+    logger.filter_level(log::LevelFilter::Trace);
+    logger.init();
+    #[cfg(feature = "admin")]
+    log::set_max_level(log_level);
 
     let app = Command::new("srt-transmit")
         .version("1.0")
@@ -628,14 +941,83 @@ async fn run() -> Result<(), Error> {
     #[cfg(feature = "console-subscriber")]
     let app = app.arg(Arg::new("console").long("console"));
 
+    #[cfg(feature = "admin")]
+    let app = app
+        .arg(
+            Arg::new("admin")
+                .long("admin")
+                .value_name("ADDRESS")
+                .help("Serves connection state and controls as JSON over HTTP on this address"),
+        )
+        .arg(
+            Arg::new("admin-token")
+                .long("admin-token")
+                .value_name("TOKEN")
+                .help("The bearer token every admin endpoint request needs"),
+        );
+
     let matches = app
-        .arg(Arg::new("FROM").help("Sets the input url").required(true))
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("PATH")
+                .help("Runs the routes of this JSON file instead, reloaded on SIGHUP")
+                .conflicts_with_all(["FROM", "failover"]),
+        )
+        .arg(
+            Arg::new("FROM")
+                .help("Sets the input url")
+                .required_unless_present("config"),
+        )
+        .arg(
+            Arg::new("failover")
+                .long("failover")
+                .value_name("URL")
+                .help("Sets a backup input url, in priority order if given more than once")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("failover-after")
+                .long("failover-after")
+                .value_name("MS")
+                .help("How long an input can go without data before failing over")
+                .default_value("1000"),
+        )
+        .arg(
+            Arg::new("stall-after")
+                .long("stall-after")
+                .value_name("MS")
+                .help("How long the stream can stall before the systemd watchdog isn't fed")
+                .default_value("5000"),
+        )
+        .arg(
+            Arg::new("statsd")
+                .long("statsd")
+                .value_name("ADDRESS")
+                .help("Sends the statistics as gauges to a statsd daemon at this address"),
+        )
+        .arg(
+            Arg::new("textfile")
+                .long("textfile")
+                .value_name("PATH")
+                .help("Writes the statistics to this file in the Prometheus text format"),
+        )
+        .arg(
+            Arg::new("metrics-interval")
+                .long("metrics-interval")
+                .value_name("MS")
+                .help("How often the statistics are sent to statsd or written to the textfile")
+                .default_value("10000"),
+        )
         .arg(
             Arg::new("TO")
                 .help("Sets the output url")
-                .required(true)
+                .required_unless_present("config")
                 .action(ArgAction::Append),
         )
+        .subcommand(ping::command())
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
         .after_help(AFTER_HELPTEXT)
         .get_matches();
 
@@ -644,41 +1026,66 @@ async fn run() -> Result<(), Error> {
         console_subscriber::init();
     }
 
-    // these are required parameters, so unwrapping them is safe
-    let from_str: &String = matches.get_one("FROM").unwrap();
-    let input_url = match url_parse(from_str, false) {
-        Err(_) => DataType::File(Path::new(from_str)),
-        Ok(url) => DataType::Url(url),
-    };
-    let to_strs = matches.get_many("TO").unwrap();
-    let output_urls_iter = to_strs.map(|to_str: &String| match url_parse(to_str, false) {
-        Err(_) => DataType::File(Path::new(to_str)),
-        Ok(url) => DataType::Url(url),
-    });
-
-    // Resolve the receiver side
-    // this will be a future that resolves to a stream of bytes
-    // (all boxed to allow for different protocols)
-    let mut stream_stream = resolve_input(input_url)?;
+    #[cfg(feature = "admin")]
+    if let Some(address) = matches.get_one::<String>("admin") {
+        let address = address
+            .parse()
+            .map_err(|_| format_err!("Failed to parse admin address '{}'", address))?;
+        let token = matches.get_one::<String>("admin-token").cloned();
+        admin::serve(address, token)?;
+    }
 
-    // Resolve the sender side
-    // similar to the receiver side, except a sink instead of a stream
-    let mut sink_streams = vec![];
-    for to in output_urls_iter.map(resolve_output) {
-        sink_streams.push(to?);
+    if let Some(("ping", matches)) = matches.subcommand() {
+        if !ping::run(matches).await? {
+            exit(1);
+        }
+        return Ok(());
     }
 
-    let mut sinks = MultiSinkFlatten::new(sink_streams.drain(..));
+    let stall_after = parse_millis(
+        "stall-after",
+        matches.get_one::<String>("stall-after").unwrap(),
+    )?;
+    let start_metrics = metrics::start(
+        matches.get_one::<String>("statsd").map(String::as_str),
+        matches.get_one::<String>("textfile").map(PathBuf::from),
+        parse_millis(
+            "metrics-interval",
+            matches.get_one::<String>("metrics-interval").unwrap(),
+        )?,
+    );
 
-    // poll sink and stream in parallel, only yielding when there is something ready for the sink and the stream is good.
-    while let (_, Some(stream)) = try_join!(
-        future::poll_fn(|cx| Pin::new(&mut sinks).poll_ready(cx)),
-        stream_stream.try_next()
-    )? {
-        // let a: () = &mut *stream;
-        sinks.send_all(&mut stream.map(Ok)).await?;
+    if let Some(config) = matches.get_one::<String>("config") {
+        let supervisor = Supervisor::start(PathBuf::from(config)).await?;
+        health::start(stall_after);
+        start_metrics.await?;
+        return supervisor.run().await;
     }
 
-    sinks.close().await?;
+    // these are required parameters, so unwrapping them is safe
+    let from_str: &String = matches.get_one("FROM").unwrap();
+    let failover_strs: Vec<&str> = matches
+        .get_many::<String>("failover")
+        .map_or_else(Vec::new, |failover| failover.map(String::as_str).collect());
+    let to_strs = matches.get_many::<String>("TO").unwrap();
+    let failover_after = parse_millis(
+        "failover-after",
+        matches.get_one::<String>("failover-after").unwrap(),
+    )?;
+
+    // Resolve the receiver side, a stream of streams of bytes, and the sender side, similar
+    // except with sinks instead of streams (all boxed to allow for different protocols)
+    let (stream_stream, sinks) = resolve_route(
+        from_str,
+        &failover_strs,
+        failover_after,
+        to_strs.map(String::as_str),
+    )?;
+
+    health::start(stall_after);
+    start_metrics.await?;
+
+    relay(stream_stream, sinks).await?;
+    health::stopping();
     Ok(())
 }
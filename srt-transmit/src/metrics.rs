@@ -0,0 +1,274 @@
+// Periodic export of the relay's statistics to existing monitoring, without the admin endpoint:
+// gauges sent to a statsd daemon over UDP, and a file in the Prometheus text format for
+// node_exporter's textfile collector
+//
+//   srt_transmit.packets                     packets relayed from the input to the outputs
+//   srt_transmit.bytes                       their payload
+//   srt_transmit.stalled                     1 once the stream stalled, see --stall-after
+//   srt_transmit.<kind>.<id>.<field>         the libsrt named statistics of each SRT connection
+//
+// The textfile has the same with underscores, the connections being labels instead, e.g.
+// srt_transmit_pktSentTotal{id="1",kind="output",peer="127.0.0.1:2000",stream_id=""}
+use std::{
+    ffi::OsString,
+    fmt::Write,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{format_err, Error};
+use log::{info, warn};
+use tokio::{
+    fs,
+    net::{lookup_host, UdpSocket},
+    spawn,
+    time::interval,
+};
+
+use srt_tokio::statistics::FieldValue;
+
+use crate::{connections, health};
+
+// fits an ethernet frame with room to spare, statsd daemons take several lines per datagram
+const MAX_DATAGRAM: usize = 1432;
+
+struct Sample {
+    relay: Option<health::State>,
+    connections: Vec<ConnectionSample>,
+}
+
+struct ConnectionSample {
+    id: u64,
+    kind: &'static str,
+    peer: SocketAddr,
+    stream_id: Option<String>,
+    fields: Vec<(&'static str, FieldValue)>,
+}
+
+// exports every period until the relay exits, a no-op without a target
+pub async fn start(
+    statsd: Option<&str>,
+    textfile: Option<PathBuf>,
+    period: Duration,
+) -> Result<(), Error> {
+    let statsd = match statsd {
+        Some(address) => Some(statsd_socket(address).await?),
+        None => None,
+    };
+    if statsd.is_none() && textfile.is_none() {
+        return Ok(());
+    }
+
+    spawn(async move {
+        let mut ticks = interval(period);
+        loop {
+            ticks.tick().await;
+            let sample = sample();
+            if let Some(socket) = &statsd {
+                for datagram in statsd_datagrams(&sample) {
+                    if let Err(e) = socket.send(datagram.as_bytes()).await {
+                        warn!("Failed to send statistics to statsd: {}", e);
+                        break;
+                    }
+                }
+            }
+            if let Some(path) = &textfile {
+                if let Err(e) = write_textfile(path, &prometheus(&sample)).await {
+                    warn!("Failed to write statistics to {}: {}", path.display(), e);
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+async fn statsd_socket(address: &str) -> Result<UdpSocket, Error> {
+    let address = lookup_host(address)
+        .await
+        .ok()
+        .and_then(|mut addresses| addresses.next())
+        .ok_or_else(|| format_err!("Failed to parse statsd address '{}'", address))?;
+    let local: SocketAddr = match address {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(address).await?;
+    info!("Sending statistics to statsd at {}", address);
+    Ok(socket)
+}
+
+fn sample() -> Sample {
+    let connections = connections::all()
+        .iter()
+        .map(|(id, connection)| ConnectionSample {
+            id: *id,
+            kind: connection.kind,
+            peer: connection.peer,
+            stream_id: connection.stream_id.clone(),
+            fields: connection.statistics.libsrt_fields(),
+        })
+        .collect();
+    Sample {
+        relay: health::state(),
+        connections,
+    }
+}
+
+fn relay_values(state: &health::State) -> [(&'static str, u64); 3] {
+    [
+        ("packets", state.packets),
+        ("bytes", state.bytes),
+        ("stalled", state.stalled.into()),
+    ]
+}
+
+// the gauges, as few datagrams as it takes
+fn statsd_datagrams(sample: &Sample) -> Vec<String> {
+    let mut lines = vec![];
+    for (name, value) in sample.relay.iter().flat_map(relay_values) {
+        lines.push(format!("srt_transmit.{name}:{value}|g"));
+    }
+    for connection in &sample.connections {
+        for (name, value) in &connection.fields {
+            lines.push(format!(
+                "srt_transmit.{}.{}.{name}:{value}|g",
+                connection.kind, connection.id
+            ));
+        }
+    }
+
+    let mut datagrams: Vec<String> = vec![];
+    for line in lines {
+        match datagrams.last_mut() {
+            Some(datagram) if datagram.len() + 1 + line.len() <= MAX_DATAGRAM => {
+                datagram.push('\n');
+                datagram.push_str(&line);
+            }
+            _ => datagrams.push(line),
+        }
+    }
+    datagrams
+}
+
+// the text exposition format, each metric's samples together as it requires
+fn prometheus(sample: &Sample) -> String {
+    let mut text = String::new();
+    if let Some(state) = &sample.relay {
+        for (name, value) in relay_values(state) {
+            let kind = if name == "stalled" {
+                "gauge"
+            } else {
+                "counter"
+            };
+            let _ = writeln!(text, "# TYPE srt_transmit_{name} {kind}");
+            let _ = writeln!(text, "srt_transmit_{name} {value}");
+        }
+    }
+    let Some(first) = sample.connections.first() else {
+        return text;
+    };
+    for (i, (name, _)) in first.fields.iter().enumerate() {
+        let _ = writeln!(text, "# TYPE srt_transmit_{name} gauge");
+        for connection in &sample.connections {
+            let _ = writeln!(
+                text,
+                "srt_transmit_{name}{{id=\"{}\",kind=\"{}\",peer=\"{}\",stream_id=\"{}\"}} {}",
+                connection.id,
+                connection.kind,
+                connection.peer,
+                escape(connection.stream_id.as_deref().unwrap_or_default()),
+                connection.fields[i].1
+            );
+        }
+    }
+    text
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// the collector may read at any time, so the file is replaced rather than rewritten
+async fn write_textfile(path: &Path, text: &str) -> std::io::Result<()> {
+    let mut temporary = OsString::from(path);
+    temporary.push(".tmp");
+    fs::write(&temporary, text).await?;
+    fs::rename(&temporary, path).await
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn test_sample() -> Sample {
+        let connection = |id, stream_id: Option<&str>, sent| ConnectionSample {
+            id,
+            kind: "output",
+            peer: ([127, 0, 0, 1], 2000 + id as u16).into(),
+            stream_id: stream_id.map(String::from),
+            fields: vec![
+                ("pktSentTotal", FieldValue::Count(sent)),
+                ("mbpsSendRate", FieldValue::Rate(1.5)),
+            ],
+        };
+        Sample {
+            relay: Some(health::State {
+                packets: 10,
+                bytes: 1316,
+                last_flow: Some(Instant::now()),
+                stalled: true,
+            }),
+            connections: vec![connection(1, None, 4), connection(2, Some("a \"b\""), 5)],
+        }
+    }
+
+    #[test]
+    fn statsd() {
+        assert_eq!(
+            statsd_datagrams(&test_sample()),
+            ["srt_transmit.packets:10|g\n\
+              srt_transmit.bytes:1316|g\n\
+              srt_transmit.stalled:1|g\n\
+              srt_transmit.output.1.pktSentTotal:4|g\n\
+              srt_transmit.output.1.mbpsSendRate:1.5|g\n\
+              srt_transmit.output.2.pktSentTotal:5|g\n\
+              srt_transmit.output.2.mbpsSendRate:1.5|g"]
+        );
+    }
+
+    #[test]
+    fn statsd_split() {
+        let mut sample = test_sample();
+        sample.connections[0].fields = vec![("pktSentTotal", FieldValue::Count(0)); 100];
+        let datagrams = statsd_datagrams(&sample);
+        assert_eq!(datagrams.len(), 3);
+        assert!(datagrams.iter().all(|d| d.len() <= MAX_DATAGRAM));
+        assert_eq!(datagrams.concat().matches("|g").count(), 105);
+    }
+
+    #[test]
+    fn textfile() {
+        assert_eq!(
+            prometheus(&test_sample()),
+            "# TYPE srt_transmit_packets counter\n\
+             srt_transmit_packets 10\n\
+             # TYPE srt_transmit_bytes counter\n\
+             srt_transmit_bytes 1316\n\
+             # TYPE srt_transmit_stalled gauge\n\
+             srt_transmit_stalled 1\n\
+             # TYPE srt_transmit_pktSentTotal gauge\n\
+             srt_transmit_pktSentTotal{id=\"1\",kind=\"output\",peer=\"127.0.0.1:2001\",stream_id=\"\"} 4\n\
+             srt_transmit_pktSentTotal{id=\"2\",kind=\"output\",peer=\"127.0.0.1:2002\",stream_id=\"a \\\"b\\\"\"} 5\n\
+             # TYPE srt_transmit_mbpsSendRate gauge\n\
+             srt_transmit_mbpsSendRate{id=\"1\",kind=\"output\",peer=\"127.0.0.1:2001\",stream_id=\"\"} 1.5\n\
+             srt_transmit_mbpsSendRate{id=\"2\",kind=\"output\",peer=\"127.0.0.1:2002\",stream_id=\"a \\\"b\\\"\"} 1.5\n"
+        );
+    }
+}
@@ -0,0 +1,132 @@
+// srt-transmit ping, the round trip, the one way delays and the loss to an SRT peer
+//
+// Connects like an SRT input or output would and sends echo requests on the control channel,
+// which srt-rs peers answer right away. The peer reports how many requests it received, which
+// tells the requests lost on the way there from the replies lost on the way back.
+use std::time::Duration;
+
+use anyhow::{bail, Error};
+use clap::{Arg, ArgMatches, Command};
+use srt_tokio::{DelaySummary, EchoStatistics, SrtSocket};
+use tokio::time::{interval, timeout, MissedTickBehavior};
+use url::Url;
+
+use crate::{local_port_addr, parse_int, parse_millis, parse_socket_options};
+
+pub fn command() -> Command {
+    Command::new("ping")
+        .about("Measures the round trip, the one way delays and the loss to an SRT peer")
+        .arg(
+            Arg::new("URL")
+                .help("The srt url to connect to, with the same options as the input and output")
+                .required(true),
+        )
+        .arg(
+            Arg::new("count")
+                .long("count")
+                .short('c')
+                .value_name("COUNT")
+                .help("How many echo requests to send")
+                .default_value("10"),
+        )
+        .arg(
+            Arg::new("interval")
+                .long("interval")
+                .short('i')
+                .value_name("MS")
+                .help("The time between echo requests")
+                .default_value("1000"),
+        )
+        .arg(
+            Arg::new("timeout")
+                .long("timeout")
+                .short('W')
+                .value_name("MS")
+                .help("How long to wait for a reply")
+                .default_value("1000"),
+        )
+}
+
+pub async fn run(matches: &ArgMatches) -> Result<bool, Error> {
+    let url: &String = matches.get_one("URL").unwrap();
+    let url = Url::parse(url)?;
+    if url.scheme() != "srt" {
+        bail!("ping needs an srt url, not {}", url.scheme());
+    }
+    let count = parse_int("count", matches.get_one::<String>("count").unwrap())?;
+    let period = parse_millis("interval", matches.get_one::<String>("interval").unwrap())?;
+    let reply_timeout = parse_millis("timeout", matches.get_one::<String>("timeout").unwrap())?;
+    if count == 0 {
+        bail!("count must be at least 1");
+    }
+
+    let (local_port, addr) = local_port_addr(&url, "ping")?;
+    let mut socket = SrtSocket::bind(parse_socket_options(&url, addr, local_port)?).await?;
+    println!("PING {} over SRT", socket.settings().remote);
+
+    let mut statistics = EchoStatistics::default();
+    let mut ticks = interval(period);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    for seq in 1..=count {
+        ticks.tick().await;
+        statistics.on_request();
+        match timeout(reply_timeout, socket.ping()).await {
+            Ok(Ok(sample)) => {
+                statistics.on_reply(sample);
+                let (forward, backward) = statistics.one_way_delays(&sample);
+                println!(
+                    "reply {}: rtt={} ms there~{} ms back~{} ms",
+                    seq,
+                    millis(sample.rtt),
+                    millis(forward),
+                    millis(backward)
+                );
+            }
+            Ok(Err(e)) => {
+                println!("request {seq}: connection closed, {e}");
+                break;
+            }
+            Err(_) => println!(
+                "request {seq}: no reply within {} ms",
+                reply_timeout.as_millis()
+            ),
+        }
+    }
+    socket.close_and_finish().await?;
+
+    println!("--- {url} ping statistics ---");
+    println!(
+        "{} requests sent, {} replies received, {}% lost, {} of them on the way there",
+        statistics.sent(),
+        statistics.received(),
+        statistics.lost() * 100 / statistics.sent(),
+        statistics.forward_lost()
+    );
+    for (name, summary) in [
+        ("rtt", statistics.rtt()),
+        ("there", statistics.forward_delay()),
+        ("back", statistics.backward_delay()),
+    ] {
+        if let Some(summary) = summary {
+            println!("{name} min/avg/max/mdev = {}", summarize(&summary));
+        }
+    }
+    if statistics.received() > 0 {
+        println!(
+            "the one way delays assume the fastest round trip was symmetric, the clocks of the \
+             two ends aren't synchronized"
+        );
+    }
+    Ok(statistics.received() > 0)
+}
+
+fn summarize(summary: &DelaySummary) -> String {
+    [summary.min, summary.avg, summary.max, summary.mdev]
+        .map(millis)
+        .join("/")
+        + " ms"
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64() * 1000.)
+}
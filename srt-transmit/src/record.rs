@@ -0,0 +1,473 @@
+// Archives the stream to a directory as numbered segments, cut by time or size, with an index of
+// the segments and of what's missing from them
+//
+// The index is a file of tab separated lines, appended as things happen. Times are milliseconds
+// since the Unix epoch.
+//
+//   segment <file> <time>                        a segment was started
+//   end <file> <time> <bytes> <messages>         and finished, a segment without one was cut short
+//   lost <time> <packets>                        the SRT input of the route gave up on packets
+//                                                before the next message
+//   silence <time> <milliseconds>                nothing arrived for a while, e.g. the input
+//                                                reconnected
+//
+// Segments are numbered on from those already in the directory, so a restarted recording doesn't
+// overwrite them. A failed write, e.g. to a full disk, cuts the segment short and the recording
+// starts over with the next segment once it can. Writes to the segment and the index are buffered
+// and flushed a second after the first that isn't yet, and when a segment is finished, so a crash
+// loses at most the last second of them.
+//
+// As an input the segments are played in order, with the timing they were recorded with when they
+// were recorded with timed framing.
+use std::{
+    path::PathBuf,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{ready, Context, Poll},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, format_err, Error};
+use bytes::{Bytes, BytesMut};
+use futures::{
+    future::BoxFuture,
+    prelude::*,
+    stream::{self, once, unfold},
+};
+use log::{info, warn};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::{AsyncWriteExt, BufWriter},
+    time::{sleep, sleep_until, Sleep},
+};
+use tokio_util::io::{ReaderStream, StreamReader};
+use url::Url;
+
+use crate::{framing::Framing, parse_int, parse_millis, SinkStream, StreamStream};
+
+const RETRY: Duration = Duration::from_secs(1);
+
+// how long written data may wait in the buffers
+const FLUSH: Duration = Duration::from_secs(1);
+
+// the packets the SRT inputs of a route skipped because they couldn't be recovered in time, shared
+// with the recorders of the route
+#[derive(Clone, Debug, Default)]
+pub struct Losses(Arc<AtomicU64>);
+
+impl Losses {
+    pub fn add(&self, packets: u64) {
+        self.0.fetch_add(packets, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Settings {
+    dir: PathBuf,
+    prefix: String,
+    extension: String,
+    framing: Framing,
+    segment_time: Option<Duration>,
+    segment_bytes: Option<u64>,
+    silence: Duration,
+}
+
+impl Settings {
+    pub fn parse(url: &Url) -> Result<Settings, Error> {
+        match url.host_str() {
+            None | Some("") => {}
+            Some(host) => bail!(
+                "Unexpected host {} in record URL, expected record:///path/to/directory",
+                host
+            ),
+        }
+        let mut settings = Settings {
+            dir: PathBuf::from(url.path()),
+            prefix: "segment".into(),
+            extension: "ts".into(),
+            framing: Framing::default(),
+            segment_time: None,
+            segment_bytes: None,
+            silence: Duration::from_secs(1),
+        };
+        for (k, v) in url.query_pairs() {
+            match &*k {
+                "prefix" if !v.is_empty() && !v.contains('/') => settings.prefix = v.into(),
+                "prefix" => bail!("Invalid prefix '{}', expected a file name", v),
+                "extension" => settings.extension = v.into(),
+                "framing" => match v.parse()? {
+                    Framing::Pcap(_) => bail!("Recordings can't be in pcap framing"),
+                    framing => settings.framing = framing,
+                },
+                "segment_ms" => settings.segment_time = Some(parse_millis(&k, &v)?),
+                "segment_bytes" => settings.segment_bytes = Some(parse_int(&k, &v)?),
+                "silence_ms" => settings.silence = parse_millis(&k, &v)?,
+                unrecog => bail!("Unrecognized parameter '{}' for record", unrecog),
+            }
+        }
+        if settings.segment_time == Some(Duration::ZERO) || settings.segment_bytes == Some(0) {
+            bail!("Segments can't be empty, segment_ms and segment_bytes must be positive");
+        }
+        if settings.segment_time.is_none() && settings.segment_bytes.is_none() {
+            settings.segment_time = Some(Duration::from_secs(60));
+        }
+        Ok(settings)
+    }
+
+    fn file_name(&self, number: u64) -> String {
+        format!("{}-{:06}.{}", self.prefix, number, self.extension)
+    }
+
+    fn number(&self, file_name: &str) -> Option<u64> {
+        let number = file_name
+            .strip_prefix(&self.prefix)?
+            .strip_prefix('-')?
+            .strip_suffix(&self.extension)?
+            .strip_suffix('.')?;
+        if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        number.parse().ok()
+    }
+}
+
+// a recorder, and a new one whenever it fails
+pub fn output(settings: Settings, losses: Losses) -> SinkStream {
+    unfold((settings, true), move |(settings, first)| {
+        let losses = losses.clone();
+        async move {
+            loop {
+                if !first {
+                    sleep(RETRY).await;
+                }
+                match Recorder::open(settings.clone(), losses.clone()).await {
+                    Ok(recorder) => {
+                        let sink = Box::pin(RecordSink {
+                            recorder: Some(recorder),
+                            pending: None,
+                            flush: None,
+                        });
+                        return Some((Ok(sink as _), (settings, false)));
+                    }
+                    // a bad directory is reported right away
+                    Err(e) if first => return Some((Err(e), (settings, false))),
+                    Err(e) => warn!("Failed to restart the recording: {e}"),
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+// the segments of a recording, in order
+pub fn input(settings: Settings) -> StreamStream {
+    once(async move {
+        let mut segments = vec![];
+        let mut entries = fs::read_dir(&settings.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if let Some(number) = entry.file_name().to_str().and_then(|n| settings.number(n)) {
+                segments.push((number, entry.path()));
+            }
+        }
+        if segments.is_empty() {
+            bail!(
+                "There are no segments to play in {}",
+                settings.dir.display()
+            );
+        }
+        segments.sort();
+        let bytes = stream::iter(segments)
+            .then(|(_, path)| File::open(path))
+            .map_ok(ReaderStream::new)
+            .try_flatten()
+            .boxed();
+        Ok(settings.framing.read(StreamReader::new(bytes)))
+    })
+    .boxed()
+}
+
+struct Segment {
+    name: String,
+    file: BufWriter<File>,
+    started: Instant,
+    bytes: u64,
+    messages: u64,
+}
+
+struct Recorder {
+    settings: Settings,
+    index: BufWriter<File>,
+    segment: Option<Segment>,
+    next_number: u64,
+    losses: Losses,
+    lost: u64,
+    last_message: Option<Instant>,
+    // when what's written first went into the buffers, if it's not flushed yet
+    unflushed: Option<Instant>,
+}
+
+impl Recorder {
+    async fn open(settings: Settings, losses: Losses) -> Result<Recorder, Error> {
+        fs::create_dir_all(&settings.dir).await?;
+        let mut next_number = 0;
+        let mut entries = fs::read_dir(&settings.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if let Some(number) = entry.file_name().to_str().and_then(|n| settings.number(n)) {
+                next_number = next_number.max(number + 1);
+            }
+        }
+        let index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(settings.dir.join(format!("{}.index", settings.prefix)))
+            .await?;
+        Ok(Recorder {
+            settings,
+            index: BufWriter::new(index),
+            segment: None,
+            next_number,
+            lost: losses.get(),
+            losses,
+            last_message: None,
+            unflushed: None,
+        })
+    }
+
+    async fn write(&mut self, data: Bytes) -> Result<(), Error> {
+        let now = Instant::now();
+        let mut framed = BytesMut::new();
+        self.settings.framing.encode(data, &mut framed)?;
+        let data = framed.freeze();
+        if let Some(segment) = &self.segment {
+            let full_time = self
+                .settings
+                .segment_time
+                .is_some_and(|time| now - segment.started >= time);
+            let full_size = self
+                .settings
+                .segment_bytes
+                .is_some_and(|bytes| segment.bytes + data.len() as u64 > bytes);
+            if full_time || (full_size && segment.bytes > 0) {
+                self.finish().await?;
+            }
+        }
+        if self.segment.is_none() {
+            self.start(now).await?;
+        }
+
+        if let Some(last) = self.last_message.replace(now) {
+            let silence = now - last;
+            if silence >= self.settings.silence {
+                let line = format!(
+                    "silence\t{}\t{}",
+                    unix_millis(SystemTime::now() - silence),
+                    silence.as_millis()
+                );
+                self.index(line).await?;
+            }
+        }
+        let lost = self.losses.get();
+        if lost > self.lost {
+            let line = format!(
+                "lost\t{}\t{}",
+                unix_millis(SystemTime::now()),
+                lost - self.lost
+            );
+            self.index(line).await?;
+            self.lost = lost;
+        }
+
+        let segment = self.segment.as_mut().unwrap();
+        segment.file.write_all(&data).await?;
+        segment.bytes += data.len() as u64;
+        segment.messages += 1;
+        if now - *self.unflushed.get_or_insert(now) >= FLUSH {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        if let Some(segment) = &mut self.segment {
+            segment.file.flush().await?;
+        }
+        self.index.flush().await?;
+        self.unflushed = None;
+        Ok(())
+    }
+
+    async fn start(&mut self, now: Instant) -> Result<(), Error> {
+        let name = self.settings.file_name(self.next_number);
+        self.next_number += 1;
+        let file = BufWriter::new(File::create(self.settings.dir.join(&name)).await?);
+        info!("Recording to {name}");
+        self.index(format!(
+            "segment\t{}\t{}",
+            name,
+            unix_millis(SystemTime::now())
+        ))
+        .await?;
+        self.segment = Some(Segment {
+            name,
+            file,
+            started: now,
+            bytes: 0,
+            messages: 0,
+        });
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> Result<(), Error> {
+        let Some(mut segment) = self.segment.take() else {
+            return Ok(());
+        };
+        segment.file.flush().await?;
+        segment.file.get_ref().sync_all().await?;
+        let line = format!(
+            "end\t{}\t{}\t{}\t{}",
+            segment.name,
+            unix_millis(SystemTime::now()),
+            segment.bytes,
+            segment.messages
+        );
+        self.index(line).await?;
+        self.flush().await
+    }
+
+    async fn index(&mut self, line: String) -> Result<(), Error> {
+        self.index.write_all(format!("{line}\n").as_bytes()).await?;
+        self.unflushed.get_or_insert_with(Instant::now);
+        Ok(())
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+// runs one operation of the recorder at a time, flushes what's been waiting in the buffers for a
+// while, and finishes the segment when closed
+struct RecordSink {
+    recorder: Option<Recorder>,
+    pending: Option<BoxFuture<'static, Result<Recorder, Error>>>,
+    // wakes the task to flush when the input has nothing to write for a while
+    flush: Option<Pin<Box<Sleep>>>,
+}
+
+impl RecordSink {
+    fn poll_pending(&mut self, cx: &mut Context) -> Poll<Result<(), Error>> {
+        if let Some(pending) = &mut self.pending {
+            let recorder = ready!(pending.poll_unpin(cx));
+            self.pending = None;
+            self.recorder = Some(recorder?);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Sink<Bytes> for RecordSink {
+    type Error = Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.poll_pending(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        let mut recorder = self
+            .recorder
+            .take()
+            .ok_or_else(|| format_err!("The recording failed"))?;
+        self.pending = Some(
+            async move {
+                recorder.write(item).await?;
+                Ok(recorder)
+            }
+            .boxed(),
+        );
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        ready!(self.poll_pending(cx))?;
+        let Some(unflushed) = self.recorder.as_ref().and_then(|r| r.unflushed) else {
+            self.flush = None;
+            return Poll::Ready(Ok(()));
+        };
+        let deadline = (unflushed + FLUSH).into();
+        let flush = match &mut self.flush {
+            Some(flush) if flush.deadline() == deadline => flush,
+            flush => flush.insert(Box::pin(sleep_until(deadline))),
+        };
+        if flush.poll_unpin(cx).is_pending() {
+            return Poll::Ready(Ok(()));
+        }
+        self.flush = None;
+        let mut recorder = self.recorder.take().unwrap();
+        self.pending = Some(
+            async move {
+                recorder.flush().await?;
+                Ok(recorder)
+            }
+            .boxed(),
+        );
+        self.poll_pending(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        ready!(self.poll_pending(cx))?;
+        if let Some(mut recorder) = self.recorder.take() {
+            if recorder.segment.is_some() {
+                self.pending = Some(
+                    async move {
+                        recorder.finish().await?;
+                        Ok(recorder)
+                    }
+                    .boxed(),
+                );
+                return self.poll_pending(cx);
+            }
+            self.recorder = Some(recorder);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let parse = |url: &str| Settings::parse(&Url::parse(url).unwrap());
+
+        let settings = parse("record:///archive/feed").unwrap();
+        assert_eq!(settings.dir, PathBuf::from("/archive/feed"));
+        assert_eq!(settings.segment_time, Some(Duration::from_secs(60)));
+        assert_eq!(settings.segment_bytes, None);
+        assert_eq!(settings.file_name(12), "segment-000012.ts");
+
+        let settings =
+            parse("record:///archive?prefix=feed&extension=mp2&segment_bytes=1000000").unwrap();
+        assert_eq!(settings.segment_time, None);
+        assert_eq!(settings.segment_bytes, Some(1_000_000));
+        assert_eq!(settings.number("feed-000012.mp2"), Some(12));
+        assert_eq!(settings.number("feed-1234567.mp2"), Some(1_234_567));
+        assert_eq!(settings.number("feed-000012.ts"), None);
+        assert_eq!(settings.number("feed-.mp2"), None);
+        assert_eq!(settings.number("feed.index"), None);
+
+        assert!(parse("record://host/archive").is_err());
+        assert!(parse("record:///archive?segment_ms=0").is_err());
+        assert!(parse("record:///archive?prefix=a/b").is_err());
+        assert!(parse("record:///archive?chunk=1316").is_err());
+    }
+}
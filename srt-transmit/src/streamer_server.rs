@@ -62,8 +62,16 @@ impl StreamerServer {
         mut sender: SrtSocket,
         mut input: broadcast::Receiver<(Instant, Bytes)>,
     ) {
+        let mut kicked = crate::connections::register("multiplex", &mut sender)
+            .kicked()
+            .boxed()
+            .fuse();
         loop {
-            match input.recv().await {
+            let received = select! {
+                received = input.recv().fuse() => received,
+                _ = kicked => break,
+            };
+            match received {
                 Ok(data) => {
                     if sender.send(data).await.is_err() {
                         break;
@@ -0,0 +1,312 @@
+// Config file mode, --config=<path>: many relays, called routes, in one process, each with an
+// input, its failover inputs and outputs as on the command line
+//
+//   {
+//     "routes": {
+//       "camera1": {
+//         "input": "srt://:2000",
+//         "failover": ["srt://backup.example.com:2000"],
+//         "failover_after_ms": 500,
+//         "outputs": ["udp://127.0.0.1:1234", "srt://:3000?multiplex"]
+//       }
+//     }
+//   }
+//
+// On SIGHUP the file is read again. Routes that were removed or changed are stopped, new and
+// changed ones started, and the others keep running untouched. A file that can't be read, or with
+// a route that isn't valid, is rejected as a whole and the routes keep running as they were. A
+// route that ended or failed is logged, and started again by the next reload.
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, format_err, Error};
+use futures::{prelude::*, stream::BoxStream};
+use log::{error, info};
+use serde_json::Value;
+use tokio::{fs, spawn, task::JoinHandle};
+
+use crate::{relay, resolve_route};
+
+const DEFAULT_FAILOVER_AFTER: Duration = Duration::from_millis(1000);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Route {
+    input: String,
+    failover: Vec<String>,
+    failover_after: Duration,
+    outputs: Vec<String>,
+}
+
+pub struct Supervisor {
+    path: PathBuf,
+    hangups: BoxStream<'static, ()>,
+    running: BTreeMap<String, (Route, JoinHandle<()>)>,
+}
+
+impl Supervisor {
+    // starts the routes of the config file, a config that isn't valid is an error
+    pub async fn start(path: PathBuf) -> Result<Supervisor, Error> {
+        // before anything else, a SIGHUP terminates the process until it's handled
+        let hangups = hangups()?;
+        let routes = load(&path).await?;
+        let mut supervisor = Supervisor {
+            path,
+            hangups,
+            running: BTreeMap::new(),
+        };
+        supervisor.apply(routes).await?;
+        Ok(supervisor)
+    }
+
+    // reloads the config file on every SIGHUP, for as long as the process runs
+    pub async fn run(mut self) -> Result<(), Error> {
+        while self.hangups.next().await.is_some() {
+            info!("Reloading {}", self.path.display());
+            let result = match load(&self.path).await {
+                Ok(routes) => self.apply(routes).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                error!("Reload rejected, the routes run on as they were: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn apply(&mut self, mut routes: BTreeMap<String, Route>) -> Result<(), Error> {
+        // what the routes to start resolve to, before touching any of the running ones
+        let mut starting = vec![];
+        for (name, route) in &routes {
+            if !self.is_running(name, route) {
+                let resolved = resolve_route(
+                    &route.input,
+                    &route
+                        .failover
+                        .iter()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>(),
+                    route.failover_after,
+                    route.outputs.iter().map(String::as_str),
+                )
+                .map_err(|e| format_err!("Route {}: {}", name, e))?;
+                starting.push((name.clone(), resolved));
+            }
+        }
+
+        // stopped before the new ones start, which may take over their ports
+        let stopping: Vec<String> = self
+            .running
+            .iter()
+            .filter(|(name, _)| !routes.get(*name).is_some_and(|r| self.is_running(name, r)))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in stopping {
+            let (_, task) = self.running.remove(&name).unwrap();
+            task.abort();
+            let _ = task.await;
+            info!("Route {} stopped", name);
+        }
+
+        for (name, (stream_stream, sinks)) in starting {
+            let route = routes.remove(&name).unwrap();
+            info!(
+                "Route {} started, from {} to {}",
+                name,
+                route.input,
+                route.outputs.join(", ")
+            );
+            let task_name = name.clone();
+            let task = spawn(async move {
+                match relay(stream_stream, sinks).await {
+                    Ok(()) => info!("Route {} ended", task_name),
+                    Err(e) => error!("Route {} failed: {}", task_name, e),
+                }
+            });
+            self.running.insert(name, (route, task));
+        }
+        Ok(())
+    }
+
+    // whether the route runs as it is configured, i.e. it's left alone
+    fn is_running(&self, name: &str, route: &Route) -> bool {
+        match self.running.get(name) {
+            Some((running, task)) => running == route && !task.is_finished(),
+            None => false,
+        }
+    }
+}
+
+async fn load(path: &Path) -> Result<BTreeMap<String, Route>, Error> {
+    let config = fs::read_to_string(path)
+        .await
+        .map_err(|e| format_err!("Failed to read config file {}: {}", path.display(), e))?;
+    parse(&config).map_err(|e| format_err!("Failed to parse config file {}: {}", path.display(), e))
+}
+
+fn parse(config: &str) -> Result<BTreeMap<String, Route>, Error> {
+    let config: Value = serde_json::from_str(config)?;
+    let config = config
+        .as_object()
+        .ok_or_else(|| format_err!("expected an object"))?;
+    let mut routes = BTreeMap::new();
+    for (key, value) in config {
+        match (key.as_str(), value) {
+            ("routes", Value::Object(entries)) => {
+                for (name, route) in entries {
+                    let route =
+                        parse_route(route).map_err(|e| format_err!("route {}: {}", name, e))?;
+                    routes.insert(name.clone(), route);
+                }
+            }
+            ("routes", _) => bail!("routes is not an object of routes by name"),
+            (unrecog, _) => bail!("Unrecognized setting '{}'", unrecog),
+        }
+    }
+    Ok(routes)
+}
+
+fn parse_route(route: &Value) -> Result<Route, Error> {
+    let route = route
+        .as_object()
+        .ok_or_else(|| format_err!("expected an object"))?;
+    let mut input = None;
+    let mut failover = vec![];
+    let mut failover_after = DEFAULT_FAILOVER_AFTER;
+    let mut outputs = vec![];
+    for (key, value) in route {
+        match key.as_str() {
+            "input" => input = Some(parse_url(key, value)?),
+            "failover" => failover = parse_urls(key, value)?,
+            "failover_after_ms" => {
+                failover_after = Duration::from_millis(
+                    value
+                        .as_u64()
+                        .ok_or_else(|| format_err!("{} is not a number of milliseconds", key))?,
+                )
+            }
+            "outputs" => outputs = parse_urls(key, value)?,
+            unrecog => bail!("Unrecognized setting '{}'", unrecog),
+        }
+    }
+    let input = input.ok_or_else(|| format_err!("no input"))?;
+    if outputs.is_empty() {
+        bail!("no outputs")
+    }
+    Ok(Route {
+        input,
+        failover,
+        failover_after,
+        outputs,
+    })
+}
+
+fn parse_url(key: &str, value: &Value) -> Result<String, Error> {
+    match value {
+        Value::String(url) => Ok(url.clone()),
+        _ => bail!("{} is not a url", key),
+    }
+}
+
+fn parse_urls(key: &str, value: &Value) -> Result<Vec<String>, Error> {
+    match value {
+        Value::Array(urls) => urls.iter().map(|url| parse_url(key, url)).collect(),
+        _ => bail!("{} is not a list of urls", key),
+    }
+}
+
+#[cfg(unix)]
+fn hangups() -> Result<BoxStream<'static, ()>, Error> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    Ok(stream::poll_fn(move |cx| hangup.poll_recv(cx)).boxed())
+}
+
+// there's no SIGHUP to reload on, the routes run as they were started
+#[cfg(not(unix))]
+fn hangups() -> Result<BoxStream<'static, ()>, Error> {
+    Ok(stream::pending().boxed())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_routes() {
+        let routes = parse(
+            r#"{
+                "routes": {
+                    "a": {
+                        "input": "udp://:2000",
+                        "outputs": ["udp://127.0.0.1:2001", "srt://:2002"]
+                    },
+                    "b": {
+                        "input": "srt://:2003",
+                        "failover": ["srt://:2004"],
+                        "failover_after_ms": 500,
+                        "outputs": ["udp://127.0.0.1:2005"]
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            routes.into_iter().collect::<Vec<_>>(),
+            vec![
+                (
+                    "a".to_string(),
+                    Route {
+                        input: "udp://:2000".to_string(),
+                        failover: vec![],
+                        failover_after: DEFAULT_FAILOVER_AFTER,
+                        outputs: vec![
+                            "udp://127.0.0.1:2001".to_string(),
+                            "srt://:2002".to_string()
+                        ],
+                    }
+                ),
+                (
+                    "b".to_string(),
+                    Route {
+                        input: "srt://:2003".to_string(),
+                        failover: vec!["srt://:2004".to_string()],
+                        failover_after: Duration::from_millis(500),
+                        outputs: vec!["udp://127.0.0.1:2005".to_string()],
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |config| parse(config).unwrap_err().to_string();
+        assert_eq!(error(r#"{"route": {}}"#), "Unrecognized setting 'route'");
+        assert_eq!(
+            error(r#"{"routes": []}"#),
+            "routes is not an object of routes by name"
+        );
+        assert_eq!(
+            error(r#"{"routes": {"a": {"outputs": ["udp://127.0.0.1:2000"]}}}"#),
+            "route a: no input"
+        );
+        assert_eq!(
+            error(r#"{"routes": {"a": {"input": "udp://:2000", "outputs": []}}}"#),
+            "route a: no outputs"
+        );
+        assert_eq!(
+            error(
+                r#"{"routes": {"a": {"input": "udp://:2000", "outputs": "udp://127.0.0.1:2000"}}}"#
+            ),
+            "route a: outputs is not a list of urls"
+        );
+        assert_eq!(
+            error(r#"{"routes": {"a": {"input": "udp://:2000", "latency": 100}}}"#),
+            "route a: Unrecognized setting 'latency'"
+        );
+    }
+}
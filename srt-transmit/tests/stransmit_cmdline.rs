@@ -188,10 +188,15 @@ fn ui_test(flags: &[&str], expected: &str) {
 }
 
 mod stransmit_rs_snd_rcv {
+    use std::{process::Stdio, time::Duration};
+
     use super::test_send;
-    use crate::{build_receiver_socket, find_stransmit_rs, udp_receiver_sock, udp_sender};
+    use crate::{
+        build_receiver_socket, find_stransmit_rs, udp_receiver_sock, udp_sender, ChunkDecoder,
+    };
     use anyhow::Error;
-    use tokio::process::Command;
+    use tokio::{io::AsyncWriteExt, net::UdpSocket, process::Command, time::sleep};
+    use tokio_util::udp::UdpFramed;
 
     #[tokio::test]
     async fn basic() -> Result<(), Error> {
@@ -303,6 +308,31 @@ mod stransmit_rs_snd_rcv {
         .await
     }
 
+    #[tokio::test]
+    async fn stdin_lines() -> Result<(), Error> {
+        let ident: i32 = rand::random();
+        let mut a = Command::new(find_stransmit_rs())
+            .args(["file://con?framing=lines", "udp://127.0.0.1:2030"])
+            .stdin(Stdio::piped())
+            .spawn()?;
+
+        let mut input = a.stdin.take().unwrap();
+        let sender = async move {
+            for _ in 0..100 {
+                input.write_all(format!("asdf{ident}\n").as_bytes()).await?;
+                sleep(Duration::from_millis(100)).await;
+            }
+            Ok::<_, Error>(())
+        };
+        let mut sock = build_receiver_socket(2030, ident).await?;
+        let recvr = udp_receiver_sock(&mut sock, ident);
+
+        futures::try_join!(recvr, sender)?;
+        a.wait().await?;
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn udp_to_udp() -> Result<(), Error> {
         test_send(
@@ -314,6 +344,72 @@ mod stransmit_rs_snd_rcv {
         .await
     }
 
+    #[tokio::test]
+    async fn udp_multicast_output() -> Result<(), Error> {
+        let ident: i32 = rand::random();
+        let mut a = Command::new(find_stransmit_rs())
+            .args([
+                "udp://:2043",
+                "udp://239.255.0.1:2044?interface=127.0.0.1&ttl=1",
+            ])
+            .spawn()?;
+
+        // a group member on the loopback interface the output was told to use
+        let socket = UdpSocket::bind("0.0.0.0:2044").await?;
+        socket.join_multicast_v4("239.255.0.1".parse()?, "127.0.0.1".parse()?)?;
+        let mut sock = UdpFramed::new(socket, ChunkDecoder::new(format!("asdf{ident}").len()));
+        let recvr = udp_receiver_sock(&mut sock, ident);
+        let sender = udp_sender(2043, ident);
+
+        futures::try_join!(recvr, sender)?;
+        a.kill().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rtp_fec_output() -> Result<(), Error> {
+        let mut a = Command::new(find_stransmit_rs())
+            .args([
+                "udp://:2060",
+                "rtp://127.0.0.1:2061?fec_columns=4&fec_rows=4",
+            ])
+            .spawn()?;
+
+        let media = UdpSocket::bind("127.0.0.1:2061").await?;
+        let fec = UdpSocket::bind("127.0.0.1:2063").await?;
+        let sender = UdpSocket::bind("127.0.0.1:0").await?;
+
+        let receive = async {
+            let (mut media_buf, mut fec_buf) = ([0; 1500], [0; 1500]);
+            // the column FEC packets come once the last row of the matrix was sent
+            loop {
+                sender.send_to(b"payload", "127.0.0.1:2060").await?;
+                tokio::select! {
+                    received = media.recv(&mut media_buf) => {
+                        let len = received?;
+                        // RTP version 2, MP2T
+                        assert_eq!(&media_buf[..2], &[0x80, 33]);
+                        assert_eq!(&media_buf[12..len], b"payload");
+                    }
+                    received = fec.recv(&mut fec_buf) => {
+                        let len = received?;
+                        // the XOR of 4 identical payloads
+                        assert_eq!(len, 12 + 16 + 7);
+                        assert!(fec_buf[12 + 16..len].iter().all(|b| *b == 0));
+                        return Ok::<_, Error>(());
+                    }
+                    _ = sleep(Duration::from_millis(10)) => {}
+                }
+            }
+        };
+        let succ = tokio::time::timeout(Duration::from_secs(10), receive).await;
+        a.kill().await?;
+        assert!(matches!(succ, Ok(Ok(()))), "no FEC packet received");
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn multiplex() -> Result<(), Error> {
         test_send(
@@ -378,6 +474,23 @@ mod stransmit_rs_snd_rcv {
         .await
     }
 
+    #[tokio::test]
+    async fn uri_query_parameters() -> Result<(), Error> {
+        test_send(
+            2040,
+            &[
+                "udp://:2040",
+                "srt://127.0.0.1:2041?mode=listener&latency=400&rcvbuf=1000000&maxbw=10000000",
+            ],
+            &[
+                "srt://127.0.0.1:2041?mode=caller&streamid=test&peerlatency=200",
+                "udp://127.0.0.1:2042",
+            ],
+            2042,
+        )
+        .await
+    }
+
     #[tokio::test]
     async fn reconnect() -> Result<(), Error> {
         let srs_path = find_stransmit_rs();
@@ -420,6 +533,477 @@ mod stransmit_rs_snd_rcv {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn failover() -> Result<(), Error> {
+        use tokio::{task::JoinHandle, time::timeout};
+
+        fn send(port: u16, payload: &'static str) -> JoinHandle<()> {
+            tokio::spawn(async move {
+                let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+                loop {
+                    let _ = sock.send_to(payload.as_bytes(), ("127.0.0.1", port)).await;
+                    sleep(Duration::from_millis(20)).await;
+                }
+            })
+        }
+
+        async fn receive(sock: &UdpSocket, payload: &str) {
+            let mut buf = [0; 100];
+            let wait = async {
+                loop {
+                    let (len, _) = sock.recv_from(&mut buf).await.unwrap();
+                    if &buf[..len] == payload.as_bytes() {
+                        break;
+                    }
+                }
+            };
+            timeout(Duration::from_secs(10), wait)
+                .await
+                .unwrap_or_else(|_| panic!("Timeout waiting for {payload}"));
+        }
+
+        let recv_sock = UdpSocket::bind("127.0.0.1:2052").await?;
+        let mut a = Command::new(find_stransmit_rs())
+            .args([
+                "--failover=udp://:2051",
+                "--failover-after=200",
+                "udp://:2050",
+                "udp://127.0.0.1:2052",
+            ])
+            .spawn()?;
+
+        let primary = send(2050, "primary");
+        receive(&recv_sock, "primary").await;
+
+        // the primary stalls
+        primary.abort();
+        let backup = send(2051, "backup");
+        receive(&recv_sock, "backup").await;
+
+        // and recovers
+        let primary = send(2050, "primary");
+        receive(&recv_sock, "primary").await;
+
+        primary.abort();
+        backup.abort();
+        a.kill().await?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn systemd_watchdog() -> Result<(), Error> {
+        use tokio::{net::UnixDatagram, time::timeout};
+
+        async fn next_notification(socket: &UnixDatagram) -> String {
+            let mut buf = [0; 1024];
+            let len = timeout(Duration::from_secs(5), socket.recv(&mut buf))
+                .await
+                .expect("Timeout waiting for a notification")
+                .unwrap();
+            String::from_utf8_lossy(&buf[..len]).into_owned()
+        }
+
+        let path = std::env::temp_dir().join(format!("srt-transmit-{}", rand::random::<u32>()));
+        let notify = UnixDatagram::bind(&path)?;
+        let _recv_sock = UdpSocket::bind("127.0.0.1:2054").await?;
+        let mut a = Command::new(find_stransmit_rs())
+            .args(["--stall-after=500", "udp://:2053", "udp://127.0.0.1:2054"])
+            .env("NOTIFY_SOCKET", &path)
+            .env("WATCHDOG_USEC", "200000")
+            .spawn()?;
+
+        assert!(next_notification(&notify).await.starts_with("READY=1"));
+
+        let sock = UdpSocket::bind("127.0.0.1:0").await?;
+        for _ in 0..20 {
+            sock.send_to(b"data", "127.0.0.1:2053").await?;
+            sleep(Duration::from_millis(50)).await;
+        }
+        loop {
+            let notification = next_notification(&notify).await;
+            assert!(
+                !notification.starts_with("STATUS=Waiting"),
+                "{notification}"
+            );
+            if notification.starts_with("STATUS=Relaying") {
+                break;
+            }
+        }
+
+        // the stream stalls, the watchdog isn't fed anymore
+        loop {
+            if next_notification(&notify)
+                .await
+                .starts_with("STATUS=Stalled")
+            {
+                break;
+            }
+        }
+        let mut buf = [0; 1024];
+        while let Ok(len) = timeout(Duration::from_millis(600), notify.recv(&mut buf)).await {
+            assert_ne!(&buf[..len?], b"WATCHDOG=1");
+        }
+
+        a.kill().await?;
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ping() -> Result<(), Error> {
+        use futures::TryStreamExt;
+        use srt_tokio::SrtSocket;
+
+        let listener = tokio::spawn(async {
+            let mut socket = SrtSocket::builder().listen_on(":2055").await.unwrap();
+            // answers echo requests until the caller closes
+            while socket.try_next().await.unwrap().is_some() {}
+        });
+
+        let output = Command::new(find_stransmit_rs())
+            .args(["ping", "--count=3", "--interval=50", "srt://127.0.0.1:2055"])
+            .output()
+            .await?;
+        let stdout = String::from_utf8(output.stdout)?;
+        assert!(output.status.success(), "{stdout}");
+        assert!(
+            stdout.contains("3 requests sent, 3 replies received, 0% lost"),
+            "{stdout}"
+        );
+        assert!(stdout.contains("rtt min/avg/max/mdev = "), "{stdout}");
+
+        listener.await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn metrics() -> Result<(), Error> {
+        use crate::udp_receiver;
+        use tokio::time::timeout;
+
+        let textfile =
+            std::env::temp_dir().join(format!("srt-transmit-{}.prom", rand::random::<u32>()));
+        let statsd = UdpSocket::bind("127.0.0.1:2066").await?;
+        let srs_path = find_stransmit_rs();
+        let mut a = Command::new(&srs_path)
+            .args([
+                "--statsd=127.0.0.1:2066",
+                &format!("--textfile={}", textfile.display()),
+                "--metrics-interval=100",
+                "udp://:2064",
+                "srt://:2065",
+            ])
+            .spawn()?;
+        let mut b = Command::new(&srs_path)
+            .args(["srt://127.0.0.1:2065", "udp://127.0.0.1:2067"])
+            .spawn()?;
+
+        let ident: i32 = rand::random();
+        futures::try_join!(udp_receiver(2067, ident), udp_sender(2064, ident))?;
+
+        // the relayed packets and the connection, whatever came first
+        let mut buf = [0; 1500];
+        loop {
+            let len = timeout(Duration::from_secs(5), statsd.recv(&mut buf)).await??;
+            let datagram = String::from_utf8_lossy(&buf[..len]).into_owned();
+            if datagram.contains("srt_transmit.output.1.pktSentTotal:") {
+                break;
+            }
+        }
+        sleep(Duration::from_millis(200)).await;
+        let text = std::fs::read_to_string(&textfile)?;
+        assert!(text.contains("\nsrt_transmit_packets "), "{text}");
+        assert!(
+            text.contains(r#"srt_transmit_pktSentTotal{id="1",kind="output",peer="127.0.0.1:"#),
+            "{text}"
+        );
+
+        a.kill().await?;
+        b.kill().await?;
+        std::fs::remove_file(textfile)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn record() -> Result<(), Error> {
+        let dir =
+            std::env::temp_dir().join(format!("srt-transmit-record-{}", rand::random::<u32>()));
+        // the segments of an earlier recording are kept
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("feed-000004.ts"), "earlier")?;
+
+        let mut a = Command::new(find_stransmit_rs())
+            .args([
+                "udp://:2068",
+                &format!(
+                    "record://{}?prefix=feed&segment_bytes=1000&silence_ms=500",
+                    dir.display()
+                ),
+            ])
+            .spawn()?;
+        sleep(Duration::from_millis(500)).await;
+
+        let sock = UdpSocket::bind("127.0.0.1:0").await?;
+        let mut sent = vec![];
+        for i in 0..25 {
+            if i == 20 {
+                sleep(Duration::from_millis(800)).await;
+            }
+            let message = format!("{i:0100}");
+            sock.send_to(message.as_bytes(), "127.0.0.1:2068").await?;
+            sent.extend(message.into_bytes());
+            sleep(Duration::from_millis(10)).await;
+        }
+        // until what's buffered is flushed
+        sleep(Duration::from_millis(1500)).await;
+        a.kill().await?;
+
+        let mut recorded = vec![];
+        for number in 5..8 {
+            recorded.extend(std::fs::read(dir.join(format!("feed-{number:06}.ts")))?);
+        }
+        assert_eq!(recorded, sent);
+        assert!(!dir.join("feed-000008.ts").exists());
+        assert_eq!(std::fs::read(dir.join("feed-000004.ts"))?, b"earlier");
+
+        let index = std::fs::read_to_string(dir.join("feed.index"))?;
+        let events: Vec<Vec<&str>> = index.lines().map(|l| l.split('\t').collect()).collect();
+        let kinds: Vec<&str> = events.iter().map(|e| e[0]).collect();
+        assert_eq!(
+            kinds,
+            ["segment", "end", "segment", "end", "segment", "silence"],
+            "{index}"
+        );
+        assert_eq!(events[0][1], "feed-000005.ts");
+        assert_eq!(events[1][1..2], ["feed-000005.ts"]);
+        assert_eq!(events[1][3..], ["1000", "10"]);
+        assert_eq!(events[3][3..], ["1000", "10"]);
+        assert_eq!(events[4][1], "feed-000007.ts");
+        assert!(events[5][2].parse::<u64>()? >= 500, "{index}");
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replay() -> Result<(), Error> {
+        use std::time::Instant;
+
+        let dir =
+            std::env::temp_dir().join(format!("srt-transmit-replay-{}", rand::random::<u32>()));
+        let recording = format!("record://{}?framing=timed", dir.display());
+
+        let mut a = Command::new(find_stransmit_rs())
+            .args(["udp://:2069", &recording])
+            .spawn()?;
+        sleep(Duration::from_millis(500)).await;
+        let sock = UdpSocket::bind("127.0.0.1:0").await?;
+        for message in ["one", "two", "three"] {
+            sock.send_to(message.as_bytes(), "127.0.0.1:2069").await?;
+            sleep(Duration::from_millis(300)).await;
+        }
+        sleep(Duration::from_millis(1200)).await;
+        a.kill().await?;
+
+        // played back with the timing it was recorded with
+        let sock = UdpSocket::bind("127.0.0.1:2070").await?;
+        let mut b = Command::new(find_stransmit_rs())
+            .args([&recording, "udp://127.0.0.1:2070"])
+            .spawn()?;
+        let mut buf = [0; 1500];
+        let mut received = vec![];
+        for _ in 0..3 {
+            let len = sock.recv(&mut buf).await?;
+            received.push((
+                Instant::now(),
+                String::from_utf8_lossy(&buf[..len]).into_owned(),
+            ));
+        }
+        b.wait().await?;
+
+        let messages: Vec<&str> = received.iter().map(|(_, m)| m.as_str()).collect();
+        assert_eq!(messages, ["one", "two", "three"]);
+        for pair in received.windows(2) {
+            let spacing = pair[1].0 - pair[0].0;
+            assert!(spacing > Duration::from_millis(200), "{spacing:?}");
+            assert!(spacing < Duration::from_millis(500), "{spacing:?}");
+        }
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "admin")]
+    #[tokio::test]
+    async fn admin() -> Result<(), Error> {
+        use crate::udp_receiver;
+        use tokio::{io::AsyncReadExt, net::TcpStream, time::timeout};
+
+        // the body of the response, after checking its status
+        async fn request(method: &str, path: &str, body: &str, status: u16) -> String {
+            request_with(method, path, "Bearer secret", body, status).await
+        }
+        async fn request_with(
+            method: &str,
+            path: &str,
+            authorization: &str,
+            body: &str,
+            status: u16,
+        ) -> String {
+            let mut stream = TcpStream::connect("127.0.0.1:2049").await.unwrap();
+            let request = format!(
+                "{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+                 Authorization: {authorization}\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            assert!(
+                response.starts_with(&format!("HTTP/1.1 {status}")),
+                "{method} {path}: {response}"
+            );
+            response.split("\r\n\r\n").nth(1).unwrap().to_string()
+        }
+
+        let srs_path = find_stransmit_rs();
+        let mut a = Command::new(&srs_path)
+            .args([
+                "--admin=127.0.0.1:2049",
+                "--admin-token=secret",
+                "udp://:2045",
+                "srt://:2046?multiplex",
+            ])
+            .spawn()?;
+        let mut b = Command::new(&srs_path)
+            .args([
+                "srt://127.0.0.1:2046?streamid=admin",
+                "udp://127.0.0.1:2047",
+            ])
+            .spawn()?;
+
+        let ident: i32 = rand::random();
+        futures::try_join!(udp_receiver(2047, ident), udp_sender(2045, ident))?;
+
+        let connections = request("GET", "/connections", "", 200).await;
+        assert!(
+            connections.starts_with(r#"[{"id":1,"kind":"multiplex""#),
+            "{connections}"
+        );
+        assert!(
+            connections.contains(r#""stream_id":"admin""#),
+            "{connections}"
+        );
+        assert!(connections.contains(r#""pktSentTotal":"#), "{connections}");
+        request("GET", "/connections/2", "", 404).await;
+        let health = request("GET", "/health", "", 200).await;
+        assert!(health.contains(r#""stalled":false"#), "{health}");
+
+        // every route needs the token
+        request_with("GET", "/connections", "", "", 401).await;
+        request_with("GET", "/health", "Bearer secre", "", 401).await;
+        request_with("PUT", "/log", "", "debug", 401).await;
+        request_with("DELETE", "/connections/1", "Bearer guess", "", 401).await;
+        request("PUT", "/log", "debug", 204).await;
+        assert_eq!(
+            request("GET", "/log", "", 200).await,
+            r#"{"level":"debug"}"#
+        );
+        request("PUT", "/log", "loud", 400).await;
+
+        // the client's input ends when it's kicked
+        request("DELETE", "/connections/1", "", 204).await;
+        timeout(Duration::from_secs(5), b.wait()).await??;
+        assert_eq!(request("GET", "/connections", "", 200).await, "[]");
+
+        a.kill().await?;
+
+        // without a token only on a loopback address
+        let status = Command::new(&srs_path)
+            .args([
+                "--admin=0.0.0.0:2076",
+                "udp://:2077",
+                "udp://127.0.0.1:2078",
+            ])
+            .status()
+            .await?;
+        assert!(!status.success());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn config_reload() -> Result<(), Error> {
+        use tokio::{fs, time::timeout};
+
+        // sends to the input until something comes out of the output
+        async fn relays(input: u16, output: &UdpSocket) -> Result<(), Error> {
+            let sender = UdpSocket::bind("127.0.0.1:0").await?;
+            let mut buf = [0; 1500];
+            let receive = async {
+                loop {
+                    sender.send_to(b"data", ("127.0.0.1", input)).await?;
+                    tokio::select! {
+                        received = output.recv(&mut buf) => {
+                            assert_eq!(&buf[..received?], b"data");
+                            return Ok::<_, Error>(());
+                        }
+                        _ = sleep(Duration::from_millis(10)) => {}
+                    }
+                }
+            };
+            timeout(Duration::from_secs(10), receive).await?
+        }
+
+        let config = |a_output| {
+            format!(
+                r#"{{"routes": {{
+                    "a": {{"input": "udp://:2071", "outputs": ["udp://127.0.0.1:{a_output}"]}},
+                    "b": {{"input": "udp://:2073", "outputs": ["udp://127.0.0.1:2074"]}}
+                }}}}"#
+            )
+        };
+        let path =
+            std::env::temp_dir().join(format!("srt-transmit-{}.json", rand::random::<u32>()));
+        fs::write(&path, config(2072)).await?;
+        let (a_output, a_moved, b_output) = (
+            UdpSocket::bind("127.0.0.1:2072").await?,
+            UdpSocket::bind("127.0.0.1:2075").await?,
+            UdpSocket::bind("127.0.0.1:2074").await?,
+        );
+        let mut a = Command::new(find_stransmit_rs())
+            .arg(format!("--config={}", path.display()))
+            .spawn()?;
+
+        relays(2071, &a_output).await?;
+        relays(2073, &b_output).await?;
+
+        // route a moves to another output, b runs on
+        fs::write(&path, config(2075)).await?;
+        let status = Command::new("kill")
+            .args(["-HUP", &a.id().unwrap().to_string()])
+            .status()
+            .await?;
+        assert!(status.success());
+        relays(2071, &a_moved).await?;
+        relays(2073, &b_output).await?;
+
+        // a broken config is rejected, the routes keep running
+        fs::write(&path, "{").await?;
+        Command::new("kill")
+            .args(["-HUP", &a.id().unwrap().to_string()])
+            .status()
+            .await?;
+        sleep(Duration::from_millis(100)).await;
+        relays(2071, &a_moved).await?;
+        assert!(a.try_wait()?.is_none());
+
+        a.kill().await?;
+        fs::remove_file(path).await?;
+        Ok(())
+    }
 }
 
 macro_rules! ui_tests {
@@ -449,6 +1033,10 @@ mod stransmit_rs_ui {
         multiplex_parameter,
         bad_pbkeylen,
         bad_pbkeylen_str,
-        pbkeylen_no_pw
+        pbkeylen_no_pw,
+        bad_mode,
+        streamid_listen,
+        bad_framing,
+        ttl_udp_recv
     );
 }
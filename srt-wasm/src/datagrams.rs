@@ -0,0 +1,113 @@
+use std::io;
+
+use futures::{channel::mpsc, prelude::*};
+use js_sys::{ArrayBuffer, Uint8Array};
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{BinaryType, CloseEvent, Event, MessageEvent, WebSocket};
+
+/// A WebSocket carrying datagrams, one per binary message.
+///
+/// The browser queues what's sent without limit, and received messages are queued until they are
+/// taken, there's no backpressure either way.
+pub struct WebSocketDatagrams {
+    socket: WebSocket,
+    received: mpsc::UnboundedReceiver<io::Result<Vec<u8>>>,
+    // kept alive for as long as the socket may call them
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_close: Closure<dyn FnMut(CloseEvent)>,
+    _on_error: Closure<dyn FnMut(Event)>,
+}
+
+impl WebSocketDatagrams {
+    /// Open a WebSocket to `url`, ready once the WebSocket handshake is done
+    pub async fn open(url: &str) -> io::Result<Self> {
+        let socket = WebSocket::new(url).map_err(js_error)?;
+        socket.set_binary_type(BinaryType::Arraybuffer);
+
+        let (sender, received) = mpsc::unbounded();
+        let on_message = {
+            let sender = sender.clone();
+            Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+                // text messages aren't datagrams, the bridge shouldn't send any
+                if let Ok(buffer) = event.data().dyn_into::<ArrayBuffer>() {
+                    let _ = sender.unbounded_send(Ok(Uint8Array::new(&buffer).to_vec()));
+                }
+            })
+        };
+        let on_close = {
+            let sender = sender.clone();
+            Closure::<dyn FnMut(CloseEvent)>::new(move |event: CloseEvent| {
+                let reason = format!("closed with {} {}", event.code(), event.reason());
+                let _ = sender.unbounded_send(Err(io::Error::new(
+                    io::ErrorKind::ConnectionAborted,
+                    reason,
+                )));
+                sender.close_channel();
+            })
+        };
+        let on_error = Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+            // the browser doesn't tell why, a close event follows with the code
+            let _ = sender.unbounded_send(Err(io::Error::other("WebSocket error")));
+        });
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+        socket.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+
+        let (opened, open) = futures::channel::oneshot::channel();
+        let mut opened = Some(opened);
+        let on_open = Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+            if let Some(opened) = opened.take() {
+                let _ = opened.send(());
+            }
+        });
+        socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+
+        let mut datagrams = WebSocketDatagrams {
+            socket,
+            received,
+            _on_message: on_message,
+            _on_close: on_close,
+            _on_error: on_error,
+        };
+        // a failed handshake ends in an error and a close event instead
+        let result = future::select(open, datagrams.received.next()).await;
+        datagrams.socket.set_onopen(None);
+        match result {
+            future::Either::Left(_) => Ok(datagrams),
+            future::Either::Right((Some(Err(e)), _)) => Err(e),
+            future::Either::Right(_) => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "WebSocket closed while opening",
+            )),
+        }
+    }
+
+    pub fn send(&self, datagram: &[u8]) -> io::Result<()> {
+        self.socket.send_with_u8_array(datagram).map_err(js_error)
+    }
+
+    /// The next datagram, an error once the WebSocket failed or closed
+    pub async fn receive(&mut self) -> io::Result<Vec<u8>> {
+        match self.received.next().await {
+            Some(result) => result,
+            None => Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "WebSocket closed",
+            )),
+        }
+    }
+}
+
+impl Drop for WebSocketDatagrams {
+    fn drop(&mut self) {
+        // the closures are dropped with us, the socket must not call them afterwards
+        self.socket.set_onmessage(None);
+        self.socket.set_onclose(None);
+        self.socket.set_onerror(None);
+        let _ = self.socket.close();
+    }
+}
+
+fn js_error(error: JsValue) -> io::Error {
+    io::Error::other(format!("{error:?}"))
+}
@@ -0,0 +1,32 @@
+//! [SRT](https://www.haivision.com/products/srt-secure-reliable-transport/) in the browser, on
+//! `wasm32-unknown-unknown`.
+//!
+//! A page can't send UDP, so the SRT packets travel as binary WebSocket messages, one packet per
+//! message, to a bridge that forwards each of them as a UDP datagram to the SRT peer and the
+//! peer's back the same way, e.g. [websocat](https://github.com/vi/websocat):
+//!
+//! ```text
+//! websocat --binary ws-l:0.0.0.0:8080 udp:192.0.2.1:2000
+//! ```
+//!
+//! The connection itself is `srt-protocol`'s, driven on the page's event loop, so it works like
+//! an `srt-tokio` caller with the same options.
+//!
+//! ```rust,ignore
+//! use futures::prelude::*;
+//! use srt_protocol::options::CallerOptions;
+//! use srt_wasm::SrtWebSocket;
+//!
+//! let options = CallerOptions::new("192.0.2.1:2000", Some("monitor")).unwrap();
+//! let mut socket = SrtWebSocket::connect("wss://bridge.example.com", options).await?;
+//! while let Some((_, data)) = socket.try_next().await? {
+//!     // e.g. feed a MediaSource
+//! }
+//! ```
+#![cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+
+mod datagrams;
+mod socket;
+
+pub use datagrams::WebSocketDatagrams;
+pub use socket::SrtWebSocket;
@@ -0,0 +1,241 @@
+use std::{
+    io::{self, Cursor},
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::{Bytes, BytesMut};
+use futures::{channel::mpsc, prelude::*, ready, select};
+use gloo_timers::future::TimeoutFuture;
+use log::{debug, error, warn};
+use srt_protocol::{
+    connection::{Action, Connection, ConnectionSettings, DuplexConnection, Input},
+    options::{CallerOptions, SocketHost, Valid},
+    packet::{Packet, ReceivePacketResult},
+    protocol::{
+        pending_connection::{connect::Connect, ConnectionResult},
+        time::Instant,
+    },
+    settings::ConnInitSettings,
+};
+use wasm_bindgen_futures::spawn_local;
+
+use crate::WebSocketDatagrams;
+
+/// An SRT caller whose packets go through a WebSocket bridge, see the [crate](crate) docs.
+///
+/// Like `srt-tokio`'s `SrtSocket` it yields and consumes `(Instant, Bytes)`, on the clock of
+/// [`Instant`], which is the page's in the browser. Closing it (`close()` from `SinkExt`) finishes
+/// sending what was queued before telling the peer.
+#[derive(Debug)]
+pub struct SrtWebSocket {
+    output_data_receiver: mpsc::Receiver<(Instant, Bytes)>,
+    input_data_sender: mpsc::Sender<(Instant, Bytes)>,
+    settings: ConnectionSettings,
+}
+
+impl SrtWebSocket {
+    /// Connect to `options.remote` through the bridge at `url`, which already knows where to
+    /// forward the packets. The remote has to be an IP address, the bridge does the resolving.
+    pub async fn connect(url: &str, options: Valid<CallerOptions>) -> io::Result<Self> {
+        let remote = match options.remote.host {
+            SocketHost::Ipv4(ip) => SocketAddr::new(ip.into(), options.remote.port),
+            SocketHost::Ipv6(ip) => SocketAddr::new(ip.into(), options.remote.port),
+            SocketHost::Domain(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the remote must be an IP address",
+                ))
+            }
+        };
+        let mut datagrams = WebSocketDatagrams::open(url).await?;
+        let connection = handshake(&mut datagrams, remote, &options).await?;
+        let settings = connection.settings.clone();
+
+        let (output_data_sender, output_data_receiver) = mpsc::channel(128);
+        let (input_data_sender, input_data_receiver) = mpsc::channel(128);
+        spawn_local(run(
+            datagrams,
+            DuplexConnection::new(connection),
+            input_data_receiver,
+            output_data_sender,
+        ));
+
+        Ok(SrtWebSocket {
+            output_data_receiver,
+            input_data_sender,
+            settings,
+        })
+    }
+
+    pub fn settings(&self) -> &ConnectionSettings {
+        &self.settings
+    }
+}
+
+impl Stream for SrtWebSocket {
+    type Item = io::Result<(Instant, Bytes)>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Poll::Ready(ready!(Pin::new(&mut self.output_data_receiver).poll_next(cx)).map(Ok))
+    }
+}
+
+impl Sink<(Instant, Bytes)> for SrtWebSocket {
+    type Error = io::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.input_data_sender)
+            .poll_ready(cx)
+            .map_err(not_connected)
+    }
+    fn start_send(mut self: Pin<&mut Self>, item: (Instant, Bytes)) -> Result<(), Self::Error> {
+        Pin::new(&mut self.input_data_sender)
+            .start_send(item)
+            .map_err(not_connected)
+    }
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.input_data_sender)
+            .poll_flush(cx)
+            .map_err(not_connected)
+    }
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.input_data_sender)
+            .poll_close(cx)
+            .map_err(not_connected)
+    }
+}
+
+fn not_connected(error: mpsc::SendError) -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, error)
+}
+
+// the caller side of the handshake, like srt-tokio's
+async fn handshake(
+    datagrams: &mut WebSocketDatagrams,
+    remote: SocketAddr,
+    options: &CallerOptions,
+) -> io::Result<Connection> {
+    let init_settings: ConnInitSettings = options.socket.clone().into();
+    let starting_seqno = init_settings.init_seq_num.unwrap_or_else(rand::random);
+    let mut connect = Connect::new(
+        remote,
+        options.socket.connect.local.ip(),
+        init_settings,
+        options.stream_id.as_ref().map(|s| s.to_string()),
+        starting_seqno,
+    );
+
+    let start_time = Instant::now();
+    // the first tick sends the induction request
+    let mut result = connect.handle_tick(start_time);
+    loop {
+        use ConnectionResult::*;
+        match result {
+            SendPacket((packet, _)) => send(datagrams, &packet)?,
+            NotHandled(e) => warn!("{e:?}"),
+            Reject(packet, reason) => {
+                if let Some((packet, _)) = packet {
+                    send(datagrams, &packet)?;
+                }
+                return Err(io::Error::new(io::ErrorKind::ConnectionRefused, reason));
+            }
+            Connected(packet, connection) => {
+                if let Some((packet, _)) = packet {
+                    send(datagrams, &packet)?;
+                }
+                return Ok(connection);
+            }
+            NoAction | RequestAccess(_) => {}
+            Failure(error) => return Err(error),
+        }
+
+        if start_time.elapsed() > options.socket.connect.timeout {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, ""));
+        }
+        result = select! {
+            _ = TimeoutFuture::new(100).fuse() => connect.handle_tick(Instant::now()),
+            datagram = datagrams.receive().fuse() => {
+                let packet = datagram.map(|datagram| parse(&datagram, remote));
+                match packet {
+                    Ok(packet) => connect.handle_packet(packet, Instant::now()),
+                    Err(e) => return Err(e),
+                }
+            }
+        };
+    }
+}
+
+// drives the connection like srt-tokio's socket task, with what DuplexConnection::handle_input
+// asks for, until it closes
+async fn run(
+    mut datagrams: WebSocketDatagrams,
+    mut connection: DuplexConnection,
+    input_data_receiver: mpsc::Receiver<(Instant, Bytes)>,
+    mut output_data: mpsc::Sender<(Instant, Bytes)>,
+) {
+    let remote = connection.settings().remote;
+    let mut input_data = input_data_receiver.fuse();
+    let mut input_data_closed = false;
+    let mut input = Input::Timer;
+    loop {
+        input = match connection.handle_input(Instant::now(), input) {
+            Action::SendPacket((packet, _)) => {
+                if let Err(e) = send(&datagrams, &packet) {
+                    error!("Error while sending packet: {e}");
+                }
+                Input::PacketSent
+            }
+            Action::ReleaseData(data) => {
+                if !output_data.is_closed() {
+                    let _ = output_data.send(data).await;
+                }
+                Input::DataReleased
+            }
+            Action::UpdateStatistics(_) => Input::StatisticsUpdated,
+            Action::WaitForData(wait) => select! {
+                _ = TimeoutFuture::new(millis(wait)).fuse() => Input::Timer,
+                datagram = datagrams.receive().fuse() => match datagram {
+                    Ok(datagram) => Input::Packet(parse(&datagram, remote)),
+                    // the connection times out like it would on a socket that stopped receiving
+                    Err(e) => {
+                        debug!("{e}");
+                        TimeoutFuture::new(millis(wait)).await;
+                        Input::Timer
+                    }
+                },
+                data = async {
+                    if input_data_closed {
+                        future::pending().await
+                    } else {
+                        input_data.next().await
+                    }
+                }.fuse() => {
+                    input_data_closed = data.is_none();
+                    Input::Data(data)
+                },
+            },
+            Action::Close => break,
+        };
+    }
+    output_data.close_channel();
+}
+
+fn send(datagrams: &WebSocketDatagrams, packet: &Packet) -> io::Result<()> {
+    let mut buffer = BytesMut::with_capacity(packet.wire_size());
+    packet.serialize(&mut buffer);
+    datagrams.send(&buffer)
+}
+
+fn parse(datagram: &[u8], from: SocketAddr) -> ReceivePacketResult {
+    let packet = Packet::parse(&mut Cursor::new(datagram), from.is_ipv6())?;
+    Ok((packet, from))
+}
+
+// the timers only have millisecond resolution, rounded up so they don't wake up early
+fn millis(wait: Duration) -> u32 {
+    let micros = wait.as_micros().saturating_add(999);
+    (micros / 1000).try_into().unwrap_or(u32::MAX)
+}